@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::file_manager::{FileProvider, PrefetchedSources};
+
+/// Time spent in each phase of one bundling run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    /// Running `cargo metadata` and locating the targets
+    pub metadata: Duration,
+    /// Reading and parsing the binary's entry file
+    pub parse: Duration,
+    /// Expanding modules and the library, which parses their files, and the
+    /// transform passes
+    pub transform: Duration,
+    /// Rendering the syntax tree to text
+    pub emit: Duration,
+    /// Running the formatter on the text, zero for minified bundles or without
+    /// a formatter
+    pub format: Duration,
+}
+
+impl PhaseTimings {
+    /// Names of the phases, in the order they run
+    pub const PHASES: [&'static str; 5] = ["metadata", "parse", "transform", "emit", "format"];
+
+    /// Time of each phase, in the order of `PHASES`
+    #[must_use]
+    pub const fn phases(&self) -> [Duration; 5] {
+        [
+            self.metadata,
+            self.parse,
+            self.transform,
+            self.emit,
+            self.format,
+        ]
+    }
+
+    /// Wall time of the whole run
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.phases().iter().sum()
+    }
+}
+
+/// Phase timings of one bundling run and the sources it read, printed by
+/// `--verbose` (see `Bundler::bundle_with_timings`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildSummary {
+    pub timings: PhaseTimings,
+    /// Source files read, each counted once
+    pub files: usize,
+    /// Lines of those files
+    pub lines: usize,
+}
+
+impl BuildSummary {
+    /// One line with the time of each phase and the sources read, e.g.
+    /// `cargo metadata: 41 ms, parse: 1 ms, ... (total 52 ms), 6 files, 812 lines`
+    #[must_use]
+    pub fn to_line(&self) -> String {
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let phases: Vec<String> = PhaseTimings::PHASES
+            .iter()
+            .zip(self.timings.phases())
+            .map(|(name, duration)| {
+                let name = if *name == "metadata" {
+                    "cargo metadata"
+                } else {
+                    name
+                };
+                format!("{name}: {:.0} ms", millis(duration))
+            })
+            .collect();
+        format!(
+            "{} (total {:.0} ms), {} files, {} lines",
+            phases.join(", "),
+            millis(self.timings.total()),
+            self.files,
+            self.lines
+        )
+    }
+}
+
+/// Sources read through another provider, or from files read ahead, counting the
+/// files and their lines
+pub(crate) struct CountingSources<'a> {
+    sources: &'a dyn FileProvider,
+    prefetched: Option<&'a PrefetchedSources>,
+    /// Lines of each file read
+    lines: Mutex<HashMap<PathBuf, usize>>,
+}
+
+impl<'a> CountingSources<'a> {
+    pub(crate) fn new(
+        sources: &'a dyn FileProvider,
+        prefetched: Option<&'a PrefetchedSources>,
+    ) -> Self {
+        Self {
+            sources,
+            prefetched,
+            lines: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Number of files read and their total lines
+    pub(crate) fn counts(&self) -> (usize, usize) {
+        let lines = self.lines.lock().unwrap_or_else(PoisonError::into_inner);
+        (lines.len(), lines.values().sum())
+    }
+}
+
+impl FileProvider for CountingSources<'_> {
+    fn read_file(&self, path: &Path) -> Result<String> {
+        let code = match self.prefetched.and_then(|files| files.get(path)) {
+            Some(code) => code.clone(),
+            None => self.sources.read_file(path)?,
+        };
+        self.lines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path.to_path_buf(), code.lines().count());
+        Ok(code)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.prefetched.is_some_and(|files| files.contains(path)) || self.sources.exists(path)
+    }
+
+    fn read_bytes(&self, path: &Path) -> Result<Vec<u8>> {
+        self.sources.read_bytes(path)
+    }
+
+    fn parse_file(&self, path: &Path, code: &str, keep_comments: bool) -> syn::Result<syn::File> {
+        self.prefetched.map_or_else(
+            || self.sources.parse_file(path, code, keep_comments),
+            |files| files.parse_file(path, code, keep_comments),
+        )
+    }
+}
+
+/// Minimum, average and maximum of a set of durations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    fn of(durations: impl Iterator<Item = Duration> + Clone) -> Self {
+        let count = u32::try_from(durations.clone().count()).unwrap_or(u32::MAX);
+        Self {
+            min: durations.clone().min().unwrap_or_default(),
+            avg: durations.clone().sum::<Duration>() / count.max(1),
+            max: durations.max().unwrap_or_default(),
+        }
+    }
+}
+
+/// Timings of repeated bundling runs of a project (see `Bundler::bench`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BenchReport {
+    /// Phase timings of each run, in order
+    pub runs: Vec<PhaseTimings>,
+    /// Peak resident set size of the process in bytes after the runs, where the
+    /// platform reports it (Linux)
+    pub peak_rss: Option<u64>,
+}
+
+impl BenchReport {
+    /// Statistics of the whole runs
+    #[must_use]
+    pub fn total(&self) -> Stats {
+        Stats::of(self.runs.iter().map(PhaseTimings::total))
+    }
+
+    /// Statistics of each phase, named as in `PhaseTimings::PHASES`
+    #[must_use]
+    pub fn phases(&self) -> Vec<(&'static str, Stats)> {
+        PhaseTimings::PHASES
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                (
+                    *name,
+                    Stats::of(self.runs.iter().map(move |run| run.phases()[index])),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Peak resident set size of the current process in bytes, from the `VmHWM` line of
+/// `/proc/self/status`; `None` where it is not available
+#[must_use]
+pub fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?;
+    let kilobytes: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_statistics() {
+        let run = |ms: [u64; 5]| PhaseTimings {
+            metadata: Duration::from_millis(ms[0]),
+            parse: Duration::from_millis(ms[1]),
+            transform: Duration::from_millis(ms[2]),
+            emit: Duration::from_millis(ms[3]),
+            format: Duration::from_millis(ms[4]),
+        };
+        let report = BenchReport {
+            runs: vec![
+                run([10, 1, 4, 1, 0]),
+                run([20, 2, 5, 1, 0]),
+                run([30, 3, 6, 4, 0]),
+            ],
+            peak_rss: None,
+        };
+
+        let total = report.total();
+        assert_eq!(total.min, Duration::from_millis(16));
+        assert_eq!(total.avg, Duration::from_millis(29));
+        assert_eq!(total.max, Duration::from_millis(43));
+
+        let phases = report.phases();
+        assert_eq!(phases[0].0, "metadata");
+        assert_eq!(phases[0].1.avg, Duration::from_millis(20));
+        assert_eq!(phases[3].1.max, Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_build_summary() {
+        let summary = BuildSummary {
+            timings: PhaseTimings {
+                metadata: Duration::from_millis(41),
+                parse: Duration::from_millis(1),
+                transform: Duration::from_millis(7),
+                emit: Duration::from_millis(2),
+                format: Duration::from_millis(12),
+            },
+            files: 6,
+            lines: 812,
+        };
+        assert_eq!(
+            summary.to_line(),
+            "cargo metadata: 41 ms, parse: 1 ms, transform: 7 ms, emit: 2 ms, format: 12 ms (total 63 ms), 6 files, 812 lines"
+        );
+    }
+
+    #[test]
+    fn test_counting_sources() {
+        let files: HashMap<PathBuf, String> = [
+            (
+                PathBuf::from("main.rs"),
+                "mod a;\nfn main() {}\n".to_string(),
+            ),
+            (PathBuf::from("a.rs"), "pub fn f() {}\n".to_string()),
+        ]
+        .into();
+        let prefetched = PrefetchedSources::from(HashMap::from([(
+            PathBuf::from("main.rs"),
+            "fn main() {}\n".to_string(),
+        )]));
+        let sources = CountingSources::new(&files, Some(&prefetched));
+        assert_eq!(
+            sources.read_file(Path::new("main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        sources.read_file(Path::new("a.rs")).unwrap();
+        sources.read_file(Path::new("a.rs")).unwrap();
+        assert!(sources.read_file(Path::new("b.rs")).is_err());
+        assert_eq!(sources.counts(), (2, 2));
+    }
+}
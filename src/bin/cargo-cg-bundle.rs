@@ -0,0 +1,69 @@
+//! `cargo cg-bundle`: cg-bundler as a Cargo subcommand
+//!
+//! Cargo runs `cargo cg-bundle ARGS...` as `cargo-cg-bundle cg-bundle ARGS...`.
+//! The arguments are those of `cg-bundler`; without a project path, the package
+//! of the current directory is bundled, as with other Cargo commands.
+
+use cg_bundler::cli::{self, Cli};
+use clap::Parser;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Name under which Cargo runs this binary
+const SUBCOMMAND: &str = "cg-bundle";
+
+fn main() {
+    let mut cli = Cli::parse_from(subcommand_args(std::env::args_os()));
+    if cli.project_path.is_none() {
+        cli.project_path = std::env::current_dir()
+            .ok()
+            .and_then(|dir| manifest_dir(&dir));
+    }
+    cli::run(cli);
+}
+
+/// `args` without the subcommand name Cargo inserts, and named `cargo cg-bundle`
+/// in help and error messages
+fn subcommand_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut args = args.into_iter().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == SUBCOMMAND) {
+        args.next();
+    }
+    std::iter::once(OsString::from(format!("cargo {SUBCOMMAND}")))
+        .chain(args)
+        .collect()
+}
+
+/// Directory of the package to bundle: `$CARGO_MANIFEST_DIR` when Cargo sets it
+/// (e.g. under `cargo run`), otherwise the nearest directory from `dir` upwards
+/// with a `Cargo.toml`
+fn manifest_dir(dir: &Path) -> Option<PathBuf> {
+    std::env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            dir.ancestors()
+                .find(|dir| dir.join("Cargo.toml").is_file())
+                .map(Path::to_path_buf)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_subcommand_name_is_skipped() {
+        assert_eq!(
+            subcommand_args(args(&["/bin/cargo-cg-bundle", "cg-bundle", "--m2"])),
+            args(&["cargo cg-bundle", "--m2"])
+        );
+        assert_eq!(
+            subcommand_args(args(&["cargo-cg-bundle", "--m2", "cg-bundle"])),
+            args(&["cargo cg-bundle", "--m2", "cg-bundle"])
+        );
+    }
+}
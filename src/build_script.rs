@@ -0,0 +1,723 @@
+use quote::ToTokens;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use syn::punctuated::Punctuated;
+
+use crate::cargo_project::CargoProject;
+use crate::error::{BundlerError, Result};
+
+/// Marks where an `OUT_DIR` value was substituted into a `format!` string
+const OUT_DIR_MARKER: &str = "\u{0}OUT_DIR\u{0}";
+
+/// `cargo:` directives that do not change how the package compiles
+const HARMLESS_DIRECTIVES: [&str; 3] = ["rerun-if-changed", "rerun-if-env-changed", "warning"];
+
+/// The `OUT_DIR` of a package's build script, as seen by
+/// `include!(concat!(env!("OUT_DIR"), "/file.rs"))` while bundling
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutDir {
+    /// Files the build script would write, reproduced by `analyze` without running it
+    Simulated {
+        /// Stand-in value of `OUT_DIR`; nothing is written there
+        path: PathBuf,
+        /// Generated files keyed by their path below `path`
+        files: BTreeMap<PathBuf, String>,
+    },
+    /// Directory the build script wrote to when run by `run`
+    Built(PathBuf),
+    /// The build script is too complex to analyze, for the given reason
+    Unknown { reason: String },
+}
+
+impl OutDir {
+    /// Value of `env!("OUT_DIR")`, if known
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Simulated { path, .. } | Self::Built(path) => Some(path),
+            Self::Unknown { .. } => None,
+        }
+    }
+
+    /// Value of `env!(name)` set by the build script: `OUT_DIR`, or a variable it
+    /// set with `cargo:rustc-env=NAME=VALUE` when it was run
+    #[must_use]
+    pub fn var(&self, name: &str) -> Option<String> {
+        if name == "OUT_DIR" {
+            return self.path().map(|path| path.to_string_lossy().into_owned());
+        }
+        match self {
+            Self::Built(path) => rustc_env(path).remove(name),
+            Self::Simulated { .. } | Self::Unknown { .. } => None,
+        }
+    }
+
+    /// Content of a simulated file, looked up by its full path
+    #[must_use]
+    pub fn file(&self, path: &Path) -> Option<&str> {
+        match self {
+            Self::Simulated { path: dir, files } => path
+                .strip_prefix(dir)
+                .ok()
+                .and_then(|relative| files.get(relative))
+                .map(String::as_str),
+            Self::Built(_) | Self::Unknown { .. } => None,
+        }
+    }
+}
+
+/// Reproduce the files a simple build script writes to `OUT_DIR`
+///
+/// Recognizes the common pattern of `fn main` reading `OUT_DIR`, joining a file
+/// name to it and writing constant text with `fs::write` or `File::create` plus
+/// `write_all`/`write!`/`writeln!`, next to `println!("cargo:rerun-if-changed=...")`.
+/// Returns the files keyed by their path relative to `OUT_DIR`.
+///
+/// # Errors
+/// Returns the reason when the script does anything else, e.g. loops, calls its
+/// own functions or emits `cargo:rustc-cfg`; run it with `run` instead.
+pub fn analyze(source: &str) -> std::result::Result<BTreeMap<PathBuf, String>, String> {
+    let file = syn::parse_file(source).map_err(|e| format!("cannot parse it: {e}"))?;
+    let mut analyzer = Analyzer::default();
+    for item in &file.items {
+        match item {
+            syn::Item::Const(item) => analyzer.bind(&item.ident, &item.expr),
+            syn::Item::Static(item) => analyzer.bind(&item.ident, &item.expr),
+            _ => {}
+        }
+    }
+
+    let main = file
+        .items
+        .iter()
+        .find_map(|item| match item {
+            syn::Item::Fn(function) if function.sig.ident == "main" => Some(function),
+            _ => None,
+        })
+        .ok_or_else(|| "it has no `fn main`".to_string())?;
+    let stmts = &main.block.stmts;
+    for (index, stmt) in stmts.iter().enumerate() {
+        analyzer.statement(stmt, index + 1 == stmts.len())?;
+    }
+    Ok(analyzer.files)
+}
+
+/// Run the package's build script with `cargo check` and return its `OUT_DIR`
+///
+/// Only the bundled target is checked, so a build script is not failed by other
+/// targets of the package.
+///
+/// # Errors
+/// Returns an error if cargo cannot be run, the build fails or cargo does not
+/// report a build script run for the package
+pub fn run(project: &CargoProject) -> Result<PathBuf> {
+    let package = project.root_package();
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(&cargo)
+        .args([
+            "check",
+            "--quiet",
+            "--message-format=json",
+            "--manifest-path",
+        ])
+        .arg(package.manifest_path.as_std_path())
+        .args(target_args(project.binary_target()))
+        .output()
+        .map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(PathBuf::from(cargo)),
+        })?;
+    if !output.status.success() {
+        return Err(BundlerError::BuildScript {
+            message: format!(
+                "cargo check failed:\n{}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ),
+        });
+    }
+
+    cargo_metadata::Message::parse_stream(output.stdout.as_slice())
+        .filter_map(std::result::Result::ok)
+        .find_map(|message| match message {
+            cargo_metadata::Message::BuildScriptExecuted(script)
+                if script.package_id == package.id =>
+            {
+                Some(script.out_dir.into_std_path_buf())
+            }
+            _ => None,
+        })
+        .ok_or_else(|| BundlerError::BuildScript {
+            message: format!("cargo did not run a build script for '{}'", package.name),
+        })
+}
+
+/// Arguments selecting `target` for `cargo check`
+fn target_args(target: &cargo_metadata::Target) -> Vec<&str> {
+    if CargoProject::target_is(target, "lib") {
+        vec!["--lib"]
+    } else if CargoProject::target_is(target, "example") {
+        vec!["--example", &target.name]
+    } else {
+        vec!["--bin", &target.name]
+    }
+}
+
+/// Variables set with `cargo:rustc-env` by the build script whose `OUT_DIR` is
+/// `out_dir`, read from the `output` file cargo keeps next to it
+fn rustc_env(out_dir: &Path) -> BTreeMap<String, String> {
+    let output = out_dir
+        .parent()
+        .and_then(|build| std::fs::read_to_string(build.join("output")).ok())
+        .unwrap_or_default();
+    output
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("cargo::rustc-env=")
+                .or_else(|| line.strip_prefix("cargo:rustc-env="))
+        })
+        .filter_map(|assignment| assignment.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// `OUT_DIR` of a previous build, from `hint`: either a Cargo target directory,
+/// searched for `<profile>/build/<package>-<hash>/out`, or that directory itself
+///
+/// Of several builds, the most recently modified output directory is used.
+///
+/// # Errors
+/// Returns an error if `hint` does not exist or contains no build script output
+/// of the package
+pub fn find_out_dir(hint: &Path, package: &str) -> Result<PathBuf> {
+    if !hint.is_dir() {
+        return Err(BundlerError::BuildScript {
+            message: format!("OUT_DIR hint '{}' is not a directory", hint.display()),
+        });
+    }
+    let is_target_dir = ["debug", "release"]
+        .iter()
+        .any(|profile| hint.join(profile).is_dir());
+    if !is_target_dir {
+        return Ok(hint.to_path_buf());
+    }
+
+    // Build script output directories are named `<package>-<hash>`
+    let is_build_dir = |dir: &Path| {
+        dir.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(package)?.strip_prefix('-'))
+            .is_some_and(|hash| !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()))
+    };
+
+    let profiles = std::fs::read_dir(hint).map_err(|e| BundlerError::Io {
+        source: e,
+        path: Some(hint.to_path_buf()),
+    })?;
+    profiles
+        .filter_map(std::result::Result::ok)
+        .filter_map(|profile| std::fs::read_dir(profile.path().join("build")).ok())
+        .flatten()
+        .filter_map(std::result::Result::ok)
+        .map(|build| build.path())
+        .filter(|build| is_build_dir(build))
+        .map(|build| build.join("out"))
+        .filter_map(|out| {
+            let modified = out.metadata().and_then(|meta| meta.modified()).ok()?;
+            Some((modified, out))
+        })
+        .max()
+        .map(|(_, out)| out)
+        .ok_or_else(|| BundlerError::BuildScript {
+            message: format!(
+                "No build script output of '{package}' found in '{}'; build the package first",
+                hint.display()
+            ),
+        })
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    /// A path below `OUT_DIR`, relative to it
+    OutPath(PathBuf),
+    /// A file created below `OUT_DIR`
+    File(PathBuf),
+}
+
+#[derive(Default)]
+struct Analyzer {
+    values: HashMap<String, Value>,
+    files: BTreeMap<PathBuf, String>,
+}
+
+impl Analyzer {
+    fn bind(&mut self, name: &syn::Ident, expr: &syn::Expr) {
+        if let Some(value) = self.eval(expr) {
+            self.values.insert(name.to_string(), value);
+        }
+    }
+
+    fn statement(&mut self, stmt: &syn::Stmt, last: bool) -> std::result::Result<(), String> {
+        match stmt {
+            syn::Stmt::Local(local) => {
+                let name = pattern_name(&local.pat).ok_or_else(|| unsupported(stmt))?;
+                let value = local
+                    .init
+                    .as_ref()
+                    .and_then(|init| self.eval(&init.expr))
+                    .ok_or_else(|| format!("the value of `{name}` is not a constant or path"))?;
+                self.values.insert(name, value);
+                Ok(())
+            }
+            syn::Stmt::Item(syn::Item::Use(_)) => Ok(()),
+            syn::Stmt::Item(syn::Item::Const(item)) => {
+                self.bind(&item.ident, &item.expr);
+                Ok(())
+            }
+            syn::Stmt::Macro(stmt) => self.effect_macro(&stmt.mac),
+            syn::Stmt::Expr(expr, None) if last && is_ok_unit(expr) => Ok(()),
+            syn::Stmt::Expr(expr, _) => self.effect(expr).ok_or_else(|| unsupported(stmt)),
+            syn::Stmt::Item(_) => Err(unsupported(stmt)),
+        }
+    }
+
+    /// Apply a statement that writes a file
+    fn effect(&mut self, expr: &syn::Expr) -> Option<()> {
+        match unwrapped(expr) {
+            syn::Expr::Macro(expr) => self.effect_macro(&expr.mac).ok(),
+            syn::Expr::Call(call) if path_ends_with(&call.func, "write") => {
+                let [path, content] = [call.args.first()?, call.args.last()?];
+                let (Value::OutPath(path), Value::Text(content)) =
+                    (self.eval(path)?, self.eval(content)?)
+                else {
+                    return None;
+                };
+                (call.args.len() == 2 && path != Path::new("")).then(|| {
+                    self.files.insert(path, content);
+                })
+            }
+            syn::Expr::MethodCall(call) if call.method == "write_all" => {
+                let (Value::File(path), Some(Value::Text(content))) = (
+                    self.eval(&call.receiver)?,
+                    call.args.first().and_then(|arg| self.eval(arg)),
+                ) else {
+                    return None;
+                };
+                self.files.entry(path).or_default().push_str(&content);
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    fn effect_macro(&mut self, mac: &syn::Macro) -> std::result::Result<(), String> {
+        let name = mac
+            .path
+            .get_ident()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        match name.as_str() {
+            "println" | "print" | "eprintln" | "eprint" => {
+                let text = mac
+                    .parse_body_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated)
+                    .ok()
+                    .and_then(|args| match args.first() {
+                        Some(syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(lit),
+                            ..
+                        })) => Some(lit.value()),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+                let Some(directive) = text
+                    .strip_prefix("cargo::")
+                    .or_else(|| text.strip_prefix("cargo:"))
+                else {
+                    return Ok(());
+                };
+                let key = directive.split('=').next().unwrap_or_default();
+                if HARMLESS_DIRECTIVES.contains(&key) {
+                    Ok(())
+                } else {
+                    Err(format!("it emits `cargo:{key}`"))
+                }
+            }
+            "write" | "writeln" => self
+                .write_macro(mac, name == "writeln")
+                .ok_or_else(|| format!("unsupported `{name}!` call")),
+            _ => Err(format!("unsupported `{name}!` call")),
+        }
+    }
+
+    /// Append the text of `write!(file, "...")` to a created file
+    fn write_macro(&mut self, mac: &syn::Macro, newline: bool) -> Option<()> {
+        let args = mac
+            .parse_body_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated)
+            .ok()?;
+        let mut args = args.iter();
+        let Value::File(path) = self.eval(args.next()?)? else {
+            return None;
+        };
+        let mut text = match args.next() {
+            Some(format) => self.format(format, &args.cloned().collect::<Vec<_>>())?,
+            None => String::new(),
+        };
+        if text.contains(OUT_DIR_MARKER) {
+            return None;
+        }
+        if newline {
+            text.push('\n');
+        }
+        self.files.entry(path).or_default().push_str(&text);
+        Some(())
+    }
+
+    fn eval(&mut self, expr: &syn::Expr) -> Option<Value> {
+        match unwrapped(expr) {
+            syn::Expr::Lit(syn::ExprLit { lit, .. }) => match lit {
+                syn::Lit::Str(lit) => Some(Value::Text(lit.value())),
+                syn::Lit::ByteStr(lit) => String::from_utf8(lit.value()).ok().map(Value::Text),
+                _ => None,
+            },
+            syn::Expr::Path(path) => self
+                .values
+                .get(&path.path.get_ident()?.to_string())
+                .cloned(),
+            syn::Expr::Call(call) => self.eval_call(call),
+            syn::Expr::MethodCall(call) => {
+                let receiver = self.eval(&call.receiver)?;
+                match (call.method.to_string().as_str(), receiver) {
+                    ("join", Value::OutPath(path)) => match self.eval(call.args.first()?)? {
+                        Value::Text(name) => Some(Value::OutPath(path.join(name))),
+                        _ => None,
+                    },
+                    (
+                        "clone" | "to_string" | "to_owned" | "as_str" | "as_path" | "as_ref"
+                        | "into" | "as_bytes" | "as_os_str",
+                        value,
+                    ) => Some(value),
+                    _ => None,
+                }
+            }
+            syn::Expr::Macro(expr) if expr.mac.path.is_ident("concat") => {
+                let parts = expr
+                    .mac
+                    .parse_body_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated)
+                    .ok()?;
+                let mut text = String::new();
+                for part in &parts {
+                    let Value::Text(part) = self.eval(part)? else {
+                        return None;
+                    };
+                    text.push_str(&part);
+                }
+                Some(Value::Text(text))
+            }
+            syn::Expr::Macro(expr) if expr.mac.path.is_ident("format") => {
+                let args = expr
+                    .mac
+                    .parse_body_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated)
+                    .ok()?;
+                let mut args = args.into_iter();
+                let text = self.format(&args.next()?, &args.collect::<Vec<_>>())?;
+                match text.strip_prefix(OUT_DIR_MARKER) {
+                    Some(rest) if !rest.contains(OUT_DIR_MARKER) => {
+                        Some(Value::OutPath(PathBuf::from(rest.trim_start_matches('/'))))
+                    }
+                    Some(_) => None,
+                    None if text.contains(OUT_DIR_MARKER) => None,
+                    None => Some(Value::Text(text)),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn eval_call(&mut self, call: &syn::ExprCall) -> Option<Value> {
+        let syn::Expr::Path(func) = &*call.func else {
+            return None;
+        };
+        let segments: Vec<String> = func
+            .path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect();
+        let arg = call.args.first()?;
+        match segments.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            [.., "var" | "var_os"] => match self.eval(arg)? {
+                Value::Text(name) if name == "OUT_DIR" => Some(Value::OutPath(PathBuf::new())),
+                _ => None,
+            },
+            [.., "Path" | "PathBuf", "new" | "from"] | [.., "String", "from"] => self.eval(arg),
+            [.., "File", "create"] => match self.eval(arg)? {
+                Value::OutPath(path) if path != Path::new("") => {
+                    self.files.insert(path.clone(), String::new());
+                    Some(Value::File(path))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Expand a `format!`-style string whose arguments are known, marking the
+    /// position of `OUT_DIR` paths with `OUT_DIR_MARKER`
+    fn format(&mut self, format: &syn::Expr, args: &[syn::Expr]) -> Option<String> {
+        let syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(format),
+            ..
+        }) = format
+        else {
+            return None;
+        };
+        let format = format.value();
+        let mut text = String::new();
+        let mut next = 0;
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    let value = if name.is_empty() {
+                        next += 1;
+                        self.eval(args.get(next - 1)?)?
+                    } else if let Ok(index) = name.parse::<usize>() {
+                        self.eval(args.get(index)?)?
+                    } else {
+                        self.values.get(&name)?.clone()
+                    };
+                    match value {
+                        Value::Text(value) => text.push_str(&value),
+                        Value::OutPath(path) => {
+                            text.push_str(OUT_DIR_MARKER);
+                            if path != Path::new("") {
+                                text.push('/');
+                                text.push_str(&path.to_string_lossy());
+                            }
+                        }
+                        Value::File(_) => return None,
+                    }
+                }
+                '}' => return None,
+                c => text.push(c),
+            }
+        }
+        Some(text)
+    }
+}
+
+/// `expr` without `&`, parentheses, `?`, `.unwrap()` and `.expect(...)`
+fn unwrapped(mut expr: &syn::Expr) -> &syn::Expr {
+    loop {
+        expr = match expr {
+            syn::Expr::Reference(inner) => &inner.expr,
+            syn::Expr::Paren(inner) => &inner.expr,
+            syn::Expr::Try(inner) => &inner.expr,
+            syn::Expr::MethodCall(call) if call.method == "unwrap" || call.method == "expect" => {
+                &call.receiver
+            }
+            _ => return expr,
+        };
+    }
+}
+
+fn path_ends_with(expr: &syn::Expr, name: &str) -> bool {
+    matches!(expr, syn::Expr::Path(path)
+        if path.path.segments.last().is_some_and(|segment| segment.ident == name))
+}
+
+fn pattern_name(pat: &syn::Pat) -> Option<String> {
+    match pat {
+        syn::Pat::Ident(ident) => Some(ident.ident.to_string()),
+        syn::Pat::Type(typed) => pattern_name(&typed.pat),
+        _ => None,
+    }
+}
+
+/// Whether `expr` is `Ok(())`
+fn is_ok_unit(expr: &syn::Expr) -> bool {
+    matches!(expr, syn::Expr::Call(call)
+        if path_ends_with(&call.func, "Ok")
+            && matches!(call.args.first(), Some(syn::Expr::Tuple(tuple)) if tuple.elems.is_empty()))
+}
+
+fn unsupported(stmt: &syn::Stmt) -> String {
+    let code = stmt.to_token_stream().to_string();
+    let mut shown: String = code.chars().take(60).collect();
+    if shown.len() < code.len() {
+        shown.push('…');
+    }
+    format!("unsupported statement `{shown}`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generated(source: &str) -> Vec<(String, String)> {
+        analyze(source)
+            .unwrap()
+            .into_iter()
+            .map(|(path, content)| (path.to_string_lossy().into_owned(), content))
+            .collect()
+    }
+
+    #[test]
+    fn test_analyze_write_file_patterns() {
+        let files = generated(
+            r#"
+            use std::env;
+            use std::fs::{self, File};
+            use std::io::Write;
+            use std::path::Path;
+
+            const TABLE: &str = "pub const PRIMES: [u32; 3] = [2, 3, 5];";
+
+            fn main() -> std::io::Result<()> {
+                println!("cargo:rerun-if-changed=build.rs");
+                let out_dir = env::var("OUT_DIR").unwrap();
+                let dest = Path::new(&out_dir).join("primes.rs");
+                fs::write(&dest, TABLE).expect("write primes");
+                std::fs::write(format!("{}/n.rs", out_dir), "42")?;
+                let mut file = File::create(Path::new(&out_dir).join("names.rs"))?;
+                writeln!(file, "pub const NAMES: [&str; 1] = [\"{}\"];", "bot")?;
+                Ok(())
+            }
+            "#,
+        );
+        assert_eq!(
+            files,
+            [
+                ("n.rs".to_string(), "42".to_string()),
+                (
+                    "names.rs".to_string(),
+                    "pub const NAMES: [&str; 1] = [\"bot\"];\n".to_string()
+                ),
+                (
+                    "primes.rs".to_string(),
+                    "pub const PRIMES: [u32; 3] = [2, 3, 5];".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_analyze_created_files() {
+        let files = generated(
+            r#"
+            fn main() {
+                let out_dir = std::env::var_os("OUT_DIR").unwrap();
+                let path = std::path::PathBuf::from(out_dir).join("gen/table.rs");
+                let mut file = std::fs::File::create(&path).unwrap();
+                file.write_all(b"pub const A: u8 = 1;\n").unwrap();
+                writeln!(file, "pub const B: &str = \"{{}}\";").unwrap();
+            }
+            "#,
+        );
+        assert_eq!(
+            files,
+            [(
+                "gen/table.rs".to_string(),
+                "pub const A: u8 = 1;\npub const B: &str = \"{}\";\n".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_analyze_rejects_complex_scripts() {
+        let reason = analyze(
+            r#"
+            fn main() {
+                let out_dir = std::env::var("OUT_DIR").unwrap();
+                for i in 0..3 { generate(&out_dir, i); }
+            }
+            "#,
+        )
+        .unwrap_err();
+        assert!(reason.starts_with("unsupported statement `for i in"));
+
+        let reason = analyze(r#"fn main() { println!("cargo:rustc-cfg=fast"); }"#).unwrap_err();
+        assert_eq!(reason, "it emits `cargo:rustc-cfg`");
+
+        let reason = analyze(
+            r#"
+            fn main() {
+                let out_dir = std::env::var("OUT_DIR").unwrap();
+                let mut file = std::fs::File::create(format!("{out_dir}/a.rs")).unwrap();
+                writeln!(file, "const A: u32 = {};", compute()).unwrap();
+            }
+            "#,
+        )
+        .unwrap_err();
+        assert!(reason.contains("writeln"));
+        assert!(analyze("const A: u8 = 1;").is_err());
+    }
+
+    #[test]
+    fn test_find_out_dir_in_target_directory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path();
+        for dir in [
+            "debug/build/solver-1a2b/out",
+            "debug/build/solver-extra-3c4d/out",
+            "release/build/other-5e6f/out",
+        ] {
+            std::fs::create_dir_all(target.join(dir)).unwrap();
+        }
+
+        let out = find_out_dir(target, "solver").unwrap();
+        assert_eq!(out, target.join("debug/build/solver-1a2b/out"));
+        assert_eq!(find_out_dir(&out, "solver").unwrap(), out);
+        assert!(find_out_dir(target, "missing").is_err());
+        assert!(find_out_dir(&target.join("nowhere"), "solver").is_err());
+    }
+
+    #[test]
+    fn test_built_out_dir_variables() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let out = temp_dir.path().join("solver-1a2b/out");
+        std::fs::create_dir_all(&out).unwrap();
+        std::fs::write(
+            out.with_file_name("output"),
+            "cargo:rerun-if-changed=build.rs\ncargo:rustc-env=TABLES=tables/v2.rs\ncargo::rustc-env=SEED=a=b\n",
+        )
+        .unwrap();
+
+        let out_dir = OutDir::Built(out.clone());
+        assert_eq!(
+            out_dir.var("OUT_DIR"),
+            Some(out.to_string_lossy().into_owned())
+        );
+        assert_eq!(out_dir.var("TABLES").as_deref(), Some("tables/v2.rs"));
+        assert_eq!(out_dir.var("SEED").as_deref(), Some("a=b"));
+        assert_eq!(out_dir.var("HOME"), None);
+    }
+
+    #[test]
+    fn test_simulated_out_dir_lookup() {
+        let out_dir = OutDir::Simulated {
+            path: PathBuf::from("/project/target/out"),
+            files: BTreeMap::from([(PathBuf::from("gen.rs"), "1".to_string())]),
+        };
+        assert_eq!(
+            out_dir.file(Path::new("/project/target/out/gen.rs")),
+            Some("1")
+        );
+        assert_eq!(out_dir.file(Path::new("/project/gen.rs")), None);
+        assert!(OutDir::Unknown {
+            reason: String::new()
+        }
+        .path()
+        .is_none());
+    }
+}
@@ -1,13 +1,52 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
 
-use crate::cargo_project::CargoProject;
+use cargo_metadata::DependencyKind;
+
+use crate::bench::{self, BenchReport, BuildSummary, CountingSources, PhaseTimings};
+use crate::build_script::{self, OutDir};
+use crate::cargo_project::{CargoProject, EntryTarget, FeatureSelection};
+use crate::check::{self, CheckReport};
+use crate::config::BundlerConfig;
+use crate::determinism;
+use crate::diagnostics::Span;
+use crate::duplicates::DuplicateReport;
 use crate::error::{BundlerError, Result};
-use crate::file_manager::FileManager;
-use crate::transformer::{CodeTransformer, TransformConfig};
+use crate::file_manager::{FileManager, FileProvider, OsFileProvider, PrefetchedSources};
+use crate::formatter::{self, Formatter};
+use crate::graph::BundleGraph;
+use crate::health::HealthReport;
+use crate::lint::{self, LintProfile, LintReport};
+use crate::plan::BundlePlan;
+use crate::size_report::SizeReport;
+use crate::template::{Template, TemplateValues};
+use crate::transformer::{
+    comments, docs, line_markers, line_wrap, preserve, test_harness, verbatim, CodeTransformer,
+    DemoExample, DocsMode, Minifier, MinifyStage, ParsedFiles, TransformConfig, TransformPass,
+    VendoredCrate,
+};
+use crate::warnings::{BundleReport, BundleWarning};
 
 /// Main bundler that orchestrates the bundling process
 pub struct Bundler {
     config: TransformConfig,
+    passes: Vec<Box<dyn TransformPass>>,
+    target: EntryTarget,
+    run_build_script: bool,
+    out_dir_hint: Option<PathBuf>,
+    vendored_deps: Vec<(String, Option<PathBuf>)>,
+    /// Comment block put above bundles of Cargo packages, and the output style
+    /// substituted for its `{profile}`
+    header: Option<(Template, String)>,
+    /// Threads reading the module files ahead of the transformer
+    jobs: usize,
+    /// Formatter run on bundles that are not minified
+    formatter: Option<Box<dyn Formatter>>,
+    /// Where source files are read from, instead of the file system
+    file_provider: Option<Box<dyn FileProvider>>,
+    /// Edition bundles are formatted and compiled as, instead of the package's
+    edition: Option<String>,
 }
 
 impl Bundler {
@@ -16,13 +55,186 @@ impl Bundler {
     pub fn new() -> Self {
         Self {
             config: TransformConfig::default(),
+            passes: Vec::new(),
+            target: EntryTarget::Default,
+            run_build_script: false,
+            out_dir_hint: None,
+            vendored_deps: Vec::new(),
+            header: None,
+            jobs: 1,
+            formatter: None,
+            file_provider: None,
+            edition: None,
         }
     }
 
     /// Create a new bundler with custom configuration
     #[must_use]
     pub const fn with_config(config: TransformConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            passes: Vec::new(),
+            target: EntryTarget::Default,
+            run_build_script: false,
+            out_dir_hint: None,
+            vendored_deps: Vec::new(),
+            header: None,
+            jobs: 1,
+            formatter: None,
+            file_provider: None,
+            edition: None,
+        }
+    }
+
+    /// Create a new bundler configured from the project's `cg-bundler.toml`
+    /// (or `[package.metadata.cg-bundler]` section)
+    ///
+    /// # Errors
+    /// Returns an error if the configuration exists but is invalid
+    pub fn with_project_config<P: AsRef<Path>>(package_path: P) -> Result<Self> {
+        let config = BundlerConfig::load(package_path)?;
+        Ok(Self::with_config(config.transform_config()))
+    }
+
+    /// Bundle the binary target called `name` of multi-binary packages
+    #[must_use]
+    pub fn with_binary(mut self, name: impl Into<String>) -> Self {
+        self.target = EntryTarget::Binary(name.into());
+        self
+    }
+
+    /// Bundle the example target called `name` (e.g. `examples/NAME.rs`) instead
+    /// of the binary
+    #[must_use]
+    pub fn with_example(mut self, name: impl Into<String>) -> Self {
+        self.target = EntryTarget::Example(name.into());
+        self
+    }
+
+    /// Bundle the integration test target called `name` (e.g. `tests/NAME.rs`)
+    /// instead of the binary, with a generated `fn main` calling its `#[test]`
+    /// functions, e.g. to ship a test harness as a single file
+    #[must_use]
+    pub fn with_test(mut self, name: impl Into<String>) -> Self {
+        self.target = EntryTarget::Test(name.into());
+        self
+    }
+
+    /// Bundle the library target on its own, without a `fn main`, e.g. to ship a
+    /// single-file version of the library
+    ///
+    /// Packages without a binary target are accepted. The library's items keep
+    /// their visibility, and `TransformConfig::strip_unused` leaves them all, as
+    /// there is no `fn main` to start from.
+    #[must_use]
+    pub fn with_library_target(mut self) -> Self {
+        self.target = EntryTarget::Library;
+        self
+    }
+
+    /// Run the package's build script with `cargo check` to resolve
+    /// `include!(concat!(env!("OUT_DIR"), ...))`, instead of reproducing its
+    /// output with `build_script::analyze`
+    ///
+    /// Needed for build scripts that compute what they generate. Variables the
+    /// script sets with `cargo:rustc-env` are resolved in `env!` too. When cargo
+    /// fails, a warning is printed and the output is reproduced as without it.
+    #[must_use]
+    pub const fn with_build_script_run(mut self, run: bool) -> Self {
+        self.run_build_script = run;
+        self
+    }
+
+    /// Resolve `env!("OUT_DIR")` from a previous build instead of the build script
+    ///
+    /// `hint` is a Cargo target directory, in which the package's newest build
+    /// script output is used, or an `OUT_DIR` itself. See `build_script::find_out_dir`.
+    #[must_use]
+    pub fn with_out_dir_hint(mut self, hint: impl Into<PathBuf>) -> Self {
+        self.out_dir_hint = Some(hint.into());
+        self
+    }
+
+    /// Inline the external crate `name` in the bundle as `pub mod name`
+    ///
+    /// `source` is the crate's `lib.rs`, its source directory or the directory of
+    /// its `Cargo.toml`; without it, the dependency's checkout is found through
+    /// `cargo metadata`, e.g. in the local registry. Paths such as
+    /// `use name::Trait;` then point at the module. See `VendoredCrate`.
+    #[must_use]
+    pub fn with_vendored_dep(mut self, name: impl Into<String>, source: Option<PathBuf>) -> Self {
+        self.vendored_deps.push((name.into(), source));
+        self
+    }
+
+    /// Read the module files of a package with `jobs` threads before transforming it
+    ///
+    /// Files are read and their module declarations found concurrently, one level
+    /// of the module tree at a time. When comments are kept, the workers also find
+    /// the comments each file can keep, which takes a parse per dropped comment, so
+    /// that the transformer parses each file once (see `FileManager::prefetch`).
+    /// With one job (the default), files are read as the modules are expanded.
+    #[must_use]
+    pub const fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Format and type-check bundles as Rust of `edition` (e.g. `2018`) instead of
+    /// the edition of the package
+    #[must_use]
+    pub fn with_edition(mut self, edition: impl Into<String>) -> Self {
+        self.edition = Some(edition.into());
+        self
+    }
+
+    /// Put a comment block rendered from `template` at the top of bundles of Cargo
+    /// packages, e.g. `template::DEFAULT_HEADER` with the crate name and version,
+    /// commit hash, build time and bundler version
+    ///
+    /// `profile` is the output style substituted for `{profile}`. Lines of the
+    /// rendered template become `//` comments unless they already are comments.
+    #[must_use]
+    pub fn with_header(mut self, template: Template, profile: impl Into<String>) -> Self {
+        self.header = Some((template, profile.into()));
+        self
+    }
+
+    /// Format bundles with `formatter`, e.g. `formatter::Rustfmt`, for the edition
+    /// of the package (see `formatter::detect_edition`) or the one set with
+    /// `with_edition`
+    ///
+    /// Minified bundles are left as they are. Long lines are wrapped again after
+    /// formatting when `TransformConfig::max_line_len` is set. A formatter error
+    /// fails the bundling.
+    #[must_use]
+    pub fn with_formatter(mut self, formatter: Box<dyn Formatter>) -> Self {
+        self.formatter = Some(formatter);
+        self
+    }
+
+    /// Read the sources of packages through `provider` instead of the file system,
+    /// e.g. from an archive or, in tests, from a `HashMap<PathBuf, String>`
+    ///
+    /// `cargo metadata` still reads the package's `Cargo.toml` from disk, so
+    /// `bundle` needs the manifests there; `bundle_file` needs none.
+    #[must_use]
+    pub fn with_file_provider(mut self, provider: Box<dyn FileProvider>) -> Self {
+        self.file_provider = Some(provider);
+        self
+    }
+
+    /// Edition bundles of `project` are formatted and compiled as
+    fn edition_of<'p>(&'p self, project: &'p CargoProject) -> &'p str {
+        self.edition
+            .as_deref()
+            .unwrap_or_else(|| project.root_package().edition.as_str())
+    }
+
+    /// Where source files are read from: the `with_file_provider` provider, else
+    /// the file system
+    fn sources(&self) -> &dyn FileProvider {
+        self.file_provider.as_deref().unwrap_or(&OsFileProvider)
     }
 
     /// Bundle a Cargo package into a single source file
@@ -30,38 +242,761 @@ impl Bundler {
     /// # Errors
     /// Returns an error if the project cannot be parsed or bundled
     pub fn bundle<P: AsRef<Path>>(&self, package_path: P) -> Result<String> {
-        let project = CargoProject::new(package_path)?;
+        let project = self.load_project(package_path)?;
         self.bundle_project(&project)
     }
 
+    /// Bundle a Cargo package into a single source file, with the warnings found
+    /// on the way: modules that could not be expanded, `use crate::...` naming
+    /// nothing and items removed by `cfg` conditions
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or bundled
+    pub fn bundle_with_report<P: AsRef<Path>>(
+        &self,
+        package_path: P,
+    ) -> Result<(String, BundleReport)> {
+        let project = self.load_project(package_path)?;
+        let (file, _, report) = self.transform_project(&project, None, self.config.clone())?;
+        Ok((self.emit_project(&self.config, &project, &file)?, report))
+    }
+
+    /// Bundle a Cargo package as `bundle_with_report` does, timing each phase and
+    /// counting the source files read and their lines
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or bundled
+    pub fn bundle_with_timings<P: AsRef<Path>>(
+        &self,
+        package_path: P,
+    ) -> Result<(String, BundleReport, BuildSummary)> {
+        let start = Instant::now();
+        let project = self.load_project(package_path)?;
+        let parse_start = Instant::now();
+        // A custom provider reads the files itself
+        let prefetched = (self.jobs > 1 && self.file_provider.is_none())
+            .then(|| prefetch_sources(&project, &self.config, self.jobs));
+        let sources = CountingSources::new(self.sources(), prefetched.as_ref());
+        let file = parse_entry(&sources, &project, &self.config)?;
+        let transform_start = Instant::now();
+        let (file, _, report) =
+            self.transform_sources(&project, file, None, self.config.clone(), &sources, None)?;
+        let emit_start = Instant::now();
+        let code = render(&self.config, &file)?;
+        let format_start = Instant::now();
+        let code = self.format(&self.config, code, self.edition_of(&project))?;
+        let timings = PhaseTimings {
+            metadata: parse_start - start,
+            parse: transform_start - parse_start,
+            transform: emit_start - transform_start,
+            emit: format_start - emit_start,
+            format: format_start.elapsed(),
+        };
+        let (files, lines) = sources.counts();
+        let summary = BuildSummary {
+            timings,
+            files,
+            lines,
+        };
+        Ok((self.with_header_of(&project, code), report, summary))
+    }
+
     /// Bundle a `CargoProject` into a single source file
     ///
     /// # Errors
     /// Returns an error if the project cannot be bundled
     pub fn bundle_project(&self, project: &CargoProject) -> Result<String> {
-        let binary_source_path = project.binary_source_path();
+        self.bundle_with_library(project, None)
+    }
 
-        let code =
-            FileManager::read_file(binary_source_path).map_err(|e| BundlerError::Parsing {
-                message: format!("Failed to read binary target source: {e}"),
-                file_path: Some(binary_source_path.to_path_buf()),
-            })?;
+    /// Bundle in-memory sources without a Cargo project on disk
+    ///
+    /// `entry` is the source of the binary (its `fn main`). `modules` holds the other
+    /// files keyed by their path relative to the entry's directory, e.g. `lib.rs`,
+    /// `geometry.rs` or `geometry/shapes/mod.rs`. `use crate_name::...` or
+    /// `extern crate crate_name` in the entry inlines `lib.rs` as for a real project.
+    ///
+    /// # Errors
+    /// Returns an error if a source cannot be parsed or a required file is missing
+    pub fn bundle_sources(
+        &self,
+        crate_name: &str,
+        entry: &str,
+        modules: &HashMap<PathBuf, String>,
+    ) -> Result<String> {
+        let entry_path = Path::new("main.rs");
+        let mut file = comments::parse_file(entry, self.config.keep_comments).map_err(|e| {
+            BundlerError::SyntaxError {
+                message: format!("Failed to parse entry source: {e}"),
+                file_path: Some(entry_path.to_path_buf()),
+                span: Span::of_syn_error(&e),
+            }
+        })?;
+
+        let root = Path::new("");
+        let mut transformer = CodeTransformer::new(root, crate_name, self.config.clone())
+            .with_source_file(root, entry_path)
+            .with_sources(modules);
+
+        transformer.transform_file_with_passes(&mut file, &self.passes)?;
+
+        let edition = self.edition.as_deref();
+        self.emit(
+            &self.config,
+            &file,
+            edition.unwrap_or(formatter::DEFAULT_EDITION),
+        )
+    }
+
+    /// Bundle a crate root and the module files next to it without a Cargo project
+    ///
+    /// `path` is the crate root (e.g. `main.rs`) or a directory containing
+    /// `main.rs` or `src/main.rs`; `cargo metadata` is not run, so neither a
+    /// `Cargo.toml` nor a Cargo installation is needed. A `lib.rs` next to the
+    /// crate root is inlined as for a real project, for the crate name in
+    /// `TransformConfig::crate_name`, else the name of the crate's directory.
+    ///
+    /// # Errors
+    /// Returns an error if no crate root is found or the sources cannot be bundled
+    pub fn bundle_file<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        let entry = standalone_entry(self.sources(), path.as_ref())?;
+        let code = self.sources().read_file(&entry)?;
+        self.bundle_entry_source(&entry, &code)
+    }
+
+    /// Bundle `code`, the source of the crate root `entry`, without a Cargo project
+    ///
+    /// Like `bundle_file`, for a crate root that is not read from disk, e.g. from
+    /// stdin; its module files are still read next to `entry`.
+    ///
+    /// # Errors
+    /// Returns an error if `code` cannot be parsed or bundled
+    pub fn bundle_entry_source(&self, entry: &Path, code: &str) -> Result<String> {
+        let mut file = comments::parse_file(code, self.config.keep_comments).map_err(|e| {
+            BundlerError::SyntaxError {
+                message: format!("Failed to parse crate root source: {e}"),
+                file_path: Some(entry.to_path_buf()),
+                span: Span::of_syn_error(&e),
+            }
+        })?;
 
-        let mut file = syn::parse_file(&code).map_err(|e| BundlerError::Parsing {
-            message: format!("Failed to parse binary target source: {e}"),
-            file_path: Some(binary_source_path.to_path_buf()),
+        let base_path = entry
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let crate_name = self
+            .config
+            .crate_name_or(&standalone_crate_name(entry))
+            .to_string();
+        let mut transformer = CodeTransformer::new(base_path, &crate_name, self.config.clone())
+            .with_source_file(base_path, entry)
+            .with_file_provider(self.sources());
+        transformer.transform_file_with_passes(&mut file, &self.passes)?;
+
+        let edition = self
+            .edition
+            .clone()
+            .or_else(|| formatter::detect_edition(base_path));
+        self.emit(
+            &self.config,
+            &file,
+            edition.as_deref().unwrap_or(formatter::DEFAULT_EDITION),
+        )
+    }
+
+    /// Transform `code`, an already bundled single file, without a Cargo project
+    ///
+    /// Only the transforms of the configuration that work on one file apply,
+    /// e.g. minifying, stripping docs and tests or mangling, and the custom passes:
+    /// modules are not expanded and no library is inlined, so nothing is read from
+    /// disk.
+    ///
+    /// # Errors
+    /// Returns an error if `code` cannot be parsed or transformed
+    pub fn transform_source(&self, code: &str) -> Result<String> {
+        let mut file = comments::parse_file(code, self.config.keep_comments).map_err(|e| {
+            BundlerError::SyntaxError {
+                message: format!("Failed to parse source: {e}"),
+                file_path: None,
+                span: Span::of_syn_error(&e),
+            }
         })?;
 
-        let mut transformer = CodeTransformer::new(
-            project.base_path(),
-            project.crate_name(),
-            self.config.clone(),
-        );
+        let config = TransformConfig {
+            expand_modules: false,
+            line_markers: false,
+            ..self.config.clone()
+        };
+        // No crate name matches: the library of a bundle is already in it
+        let mut transformer = CodeTransformer::new(Path::new("."), "", config.clone());
+        transformer.transform_file_with_passes(&mut file, &self.passes)?;
+
+        let edition = self.edition.as_deref();
+        self.emit(
+            &config,
+            &file,
+            edition.unwrap_or(formatter::DEFAULT_EDITION),
+        )
+    }
+
+    /// Bundle every binary target of a Cargo package, one file per target
+    ///
+    /// The library is read and parsed once and shared between all targets.
+    /// Returns `(binary name, bundled code)` pairs, one per `[[bin]]` target.
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or any target fails to bundle
+    pub fn bundle_all_bins<P: AsRef<Path>>(
+        &self,
+        package_path: P,
+    ) -> Result<Vec<(String, String)>> {
+        let projects = CargoProject::all_binaries(package_path)?;
+        let Some(first) = projects.first() else {
+            return Ok(Vec::new());
+        };
+
+        let library_root = library_root(first);
+        let library = if self.config.expand_modules && self.sources().exists(&library_root) {
+            let mut loader = CodeTransformer::new(
+                first.binary_base_path(),
+                self.config.crate_name_or(first.crate_name()),
+                self.config.clone(),
+            )
+            .with_source_file(project_root(first), first.binary_source_path())
+            .with_library_root(&library_root)
+            .with_file_provider(self.sources());
+            let out_dir = self.out_dir(first, &mut Vec::new())?;
+            if let Some(out_dir) = &out_dir {
+                loader = loader.with_out_dir(out_dir);
+            }
+            Some(loader.load_library()?)
+        } else {
+            None
+        };
+
+        projects
+            .iter()
+            .map(|project| {
+                let code = self.bundle_with_library(project, library.as_ref())?;
+                Ok((project.binary_target().name.clone(), code))
+            })
+            .collect()
+    }
+
+    /// Bundle a Cargo package once per bundler of `variants`, each with its own
+    /// configuration, passes, header and formatter
+    ///
+    /// The package is analyzed with the target of this bundler, and its sources
+    /// are read and parsed once for all variants, which transform copies of the
+    /// same syntax trees (parsed twice when only some variants keep comments).
+    /// Returns the bundles in the order of `variants`.
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or any variant fails to bundle
+    pub fn bundle_variants<P: AsRef<Path>>(
+        &self,
+        package_path: P,
+        variants: &[Self],
+    ) -> Result<Vec<String>> {
+        let project = self.load_project(package_path)?;
+        let prefetched = self
+            .file_provider
+            .is_none()
+            .then(|| prefetch_sources(&project, &self.config, self.jobs));
+        let sources = prefetched
+            .as_ref()
+            .map_or_else(|| self.sources(), |files| files as &dyn FileProvider);
+
+        // Parsed with and without comments, as the variants need
+        let mut entries: [Option<syn::File>; 2] = [None, None];
+        let parsed = ParsedFiles::default();
+        variants
+            .iter()
+            .map(|variant| {
+                let entry = &mut entries[usize::from(variant.config.keep_comments)];
+                let file = match entry {
+                    Some(file) => file.clone(),
+                    None => entry
+                        .insert(parse_entry(sources, &project, &variant.config)?)
+                        .clone(),
+                };
+                let (file, ..) = variant.transform_sources(
+                    &project,
+                    file,
+                    None,
+                    variant.config.clone(),
+                    prefetched
+                        .as_ref()
+                        .map_or_else(|| variant.sources(), |files| files as &dyn FileProvider),
+                    Some(&parsed),
+                )?;
+                variant.emit_project(&variant.config, &project, &file)
+            })
+            .collect()
+    }
+
+    /// Bundle a Cargo package and turn its demo functions into runnable examples
+    ///
+    /// Demos are parameterless functions named `demo` or `demo_*` gated by
+    /// `#[cfg(test)]`. They are removed from the bundle; each example is the bundle
+    /// with the demo restored and `fn main` calling it. Returns the bundle and
+    /// `(example name, example code)` pairs.
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or bundled
+    pub fn bundle_with_demos<P: AsRef<Path>>(
+        &self,
+        package_path: P,
+    ) -> Result<(String, Vec<(String, String)>)> {
+        let project = self.load_project(package_path)?;
+        let config = TransformConfig {
+            extract_demos: true,
+            ..self.config.clone()
+        };
+
+        let (file, demo_examples, _) = self.transform_project(&project, None, config)?;
+        let examples = demo_examples
+            .into_iter()
+            .map(|example| (example.name, prettyplease::unparse(&example.file)))
+            .collect();
+        Ok((self.emit_project(&self.config, &project, &file)?, examples))
+    }
+
+    fn bundle_with_library(
+        &self,
+        project: &CargoProject,
+        library: Option<&syn::File>,
+    ) -> Result<String> {
+        let (file, ..) = self.transform_project(project, library, self.config.clone())?;
+        self.emit_project(&self.config, project, &file)
+    }
 
-        transformer.transform_file(&mut file)?;
+    fn transform_project(
+        &self,
+        project: &CargoProject,
+        library: Option<&syn::File>,
+        config: TransformConfig,
+    ) -> Result<(syn::File, Vec<DemoExample>, BundleReport)> {
+        let file = parse_entry(self.sources(), project, &config)?;
+        self.transform_entry(project, file, library, config)
+    }
 
-        let bundled_code = prettyplease::unparse(&file);
-        Ok(bundled_code)
+    /// Transform the parsed entry file of `project`
+    fn transform_entry(
+        &self,
+        project: &CargoProject,
+        file: syn::File,
+        library: Option<&syn::File>,
+        config: TransformConfig,
+    ) -> Result<(syn::File, Vec<DemoExample>, BundleReport)> {
+        // A custom provider reads the files itself
+        let prefetched = (self.jobs > 1 && self.file_provider.is_none())
+            .then(|| prefetch_sources(project, &config, self.jobs));
+        let sources = prefetched
+            .as_ref()
+            .map_or_else(|| self.sources(), |files| files as &dyn FileProvider);
+        self.transform_sources(project, file, library, config, sources, None)
+    }
+
+    /// Transform the parsed entry file of `project`, reading source files from
+    /// `sources` and taking those already parsed from `parsed` when given
+    fn transform_sources(
+        &self,
+        project: &CargoProject,
+        mut file: syn::File,
+        library: Option<&syn::File>,
+        config: TransformConfig,
+        sources: &dyn FileProvider,
+        parsed: Option<&ParsedFiles>,
+    ) -> Result<(syn::File, Vec<DemoExample>, BundleReport)> {
+        let binary_source_path = project.binary_source_path();
+        let crate_name = config.crate_name_or(project.crate_name()).to_string();
+        let mut warnings = Vec::new();
+        let out_dir = self.out_dir(project, &mut warnings)?;
+        let vendored = self.vendored_crates(project, &config)?;
+        // Feature-gated modules are enabled over the default features, as with
+        // `cargo build --features`
+        let default_features = FeatureSelection::default();
+        let features = config
+            .features
+            .as_ref()
+            .or_else(|| (!config.enabled_modules.is_empty()).then_some(&default_features))
+            .map(|selection| project.resolve_features(selection))
+            .transpose()?;
+        let library_root = library_root(project);
+        let mut transformer = CodeTransformer::new(project.binary_base_path(), &crate_name, config)
+            .with_source_file(project_root(project), binary_source_path)
+            .with_library_root(&library_root)
+            .with_vendored(&vendored)
+            .with_file_provider(sources);
+        if let Some(parsed) = parsed {
+            transformer = transformer.with_parsed_files(parsed);
+        }
+        if let Some(library) = library {
+            transformer = transformer.with_library(library);
+        }
+        if let Some(out_dir) = &out_dir {
+            transformer = transformer.with_out_dir(out_dir);
+        }
+        if let Some(features) = &features {
+            transformer = transformer.with_features(features);
+        }
+
+        transformer.transform_file_with_passes(&mut file, &self.passes)?;
+
+        warnings.extend(transformer.take_warnings());
+        let report = BundleReport {
+            warnings,
+            included_files: transformer.take_included_files(),
+        };
+        Ok((file, transformer.take_demo_examples(), report))
+    }
+
+    /// Load the crates given with `with_vendored_dep`
+    fn vendored_crates(
+        &self,
+        project: &CargoProject,
+        config: &TransformConfig,
+    ) -> Result<Vec<VendoredCrate>> {
+        self.vendored_deps
+            .iter()
+            .map(|(name, source)| {
+                let dependency = project.dependency_library(name);
+                let lib_path = match source {
+                    Some(source) => VendoredCrate::library_root(source)?,
+                    None => dependency.as_ref().map(|(path, _)| path.clone()).ok_or_else(|| {
+                        BundlerError::ProjectStructure {
+                            message: format!(
+                                "Cannot vendor '{name}': it is not a dependency of {}. Give its source as {name}=PATH",
+                                project.crate_name()
+                            ),
+                        }
+                    })?,
+                };
+                let features = dependency.map_or_else(
+                    || VendoredCrate::default_features(&lib_path),
+                    |(_, features)| features,
+                );
+                VendoredCrate::load(name, &lib_path, &features, config)
+            })
+            .collect()
+    }
+
+    /// Bundle a Cargo package in at most `target` characters, applying the stages of
+    /// `MinifyStage::ALL` the configuration does not already apply, in order, until
+    /// the bundle fits
+    ///
+    /// Returns the bundle and the stages applied to fit it.
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or bundled, or
+    /// `SizeLimitExceeded` if the bundle does not fit with every stage applied
+    pub fn bundle_within<P: AsRef<Path>>(
+        &self,
+        package_path: P,
+        target: usize,
+    ) -> Result<(String, Vec<MinifyStage>)> {
+        let project = self.load_project(package_path)?;
+        let mut config = self.config.clone();
+        let mut applied = Vec::new();
+        let mut pending = MinifyStage::ALL
+            .into_iter()
+            .filter(|stage| !stage.is_applied(&self.config));
+        loop {
+            let (file, ..) = self.transform_project(&project, None, config.clone())?;
+            let code = self.emit_project(&config, &project, &file)?;
+            let characters = code.chars().count();
+            if characters <= target {
+                return Ok((code, applied));
+            }
+            let Some(stage) = pending.next() else {
+                return Err(BundlerError::SizeLimitExceeded {
+                    characters,
+                    limit: target,
+                });
+            };
+            stage.apply(&mut config);
+            applied.push(stage);
+        }
+    }
+
+    /// Bundle a Cargo package and measure how many characters each source file
+    /// contributes to the output
+    ///
+    /// The package is bundled a second time with line markers to attribute the
+    /// output to source files. Returns the bundle (as `bundle` would) and its report.
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or bundled
+    pub fn bundle_with_size_report<P: AsRef<Path>>(
+        &self,
+        package_path: P,
+    ) -> Result<(String, SizeReport)> {
+        let project = self.load_project(package_path)?;
+        let code = self.bundle_project(&project)?;
+
+        let config = TransformConfig {
+            line_markers: true,
+            ..self.config.clone()
+        };
+        let (file, ..) = self.transform_project(&project, None, config.clone())?;
+        let marked = render(&config, &file)?;
+
+        let report = SizeReport::new(&code, &marked);
+        Ok((code, report))
+    }
+
+    /// Bundle a Cargo package and describe the module tree of the bundle: the
+    /// modules, the source files they come from, their items and their sizes
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or bundled
+    pub fn analyze<P: AsRef<Path>>(&self, package_path: P) -> Result<BundleGraph> {
+        let project = self.load_project(package_path)?;
+        let config = TransformConfig {
+            line_markers: true,
+            ..self.config.clone()
+        };
+        let (file, ..) = self.transform_project(&project, None, config)?;
+        Ok(BundleGraph::new(&file))
+    }
+
+    /// Bundle a Cargo package and find the functions of the bundle that are
+    /// copies of each other, or alike but for their names and literals
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or bundled
+    pub fn duplicates<P: AsRef<Path>>(&self, package_path: P) -> Result<DuplicateReport> {
+        let project = self.load_project(package_path)?;
+        let code = self.bundle_project(&project)?;
+
+        let config = TransformConfig {
+            line_markers: true,
+            ..self.config.clone()
+        };
+        let (file, ..) = self.transform_project(&project, None, config)?;
+        Ok(DuplicateReport::new(&file, code.chars().count()))
+    }
+
+    /// Bundle a Cargo package and assess the health of the bundle against a
+    /// character `budget` (see `HealthReport`)
+    ///
+    /// The package is bundled a second time with `strip_unused` to estimate its dead
+    /// code. Dependencies vendored with `with_vendored_dep` do not count against it.
+    ///
+    /// # Errors
+    /// Returns an error if the project cannot be parsed or bundled
+    pub fn health<P: AsRef<Path>>(&self, package_path: P, budget: usize) -> Result<HealthReport> {
+        let project = self.load_project(package_path)?;
+        let code = self.bundle_project(&project)?;
+
+        let config = TransformConfig {
+            strip_unused: true,
+            ..self.config.clone()
+        };
+        let (file, ..) = self.transform_project(&project, None, config.clone())?;
+        let stripped = render(&config, &file)?;
+
+        let vendored = |name: &str| {
+            self.vendored_deps
+                .iter()
+                .any(|(vendored, _)| vendored.replace('-', "_") == name.replace('-', "_"))
+        };
+        let dependencies = project
+            .root_package()
+            .dependencies
+            .iter()
+            .filter(|dep| dep.kind == DependencyKind::Normal && !vendored(&dep.name))
+            .map(|dep| dep.name.clone())
+            .collect();
+        HealthReport::new(&code, &stripped, dependencies, budget)
+    }
+
+    /// Bundle a Cargo package and type-check the bundle with `rustc`
+    ///
+    /// The bundle is rendered with line markers so that compiler errors point at
+    /// the source files they come from. See `check::check_bundle`.
+    ///
+    /// # Errors
+    /// Returns an error if bundling fails or the compiler cannot be run; compiler
+    /// errors in the bundle are returned in the report
+    pub fn check<P: AsRef<Path>>(&self, package_path: P) -> Result<CheckReport> {
+        let project = self.load_project(package_path)?;
+        let config = TransformConfig {
+            line_markers: true,
+            ..self.config.clone()
+        };
+        let (file, ..) = self.transform_project(&project, None, config.clone())?;
+        let code = render(&config, &file)?;
+        check::check_bundle(&code, self.edition_of(&project))
+    }
+
+    /// Bundle a Cargo package and find the uses of APIs `profile` forbids, such as
+    /// `std::fs` on `CodinGame`
+    ///
+    /// The bundle is rendered with line markers so that uses are reported with the
+    /// source files they come from. See `lint::lint_bundle`.
+    ///
+    /// # Errors
+    /// Returns an error if bundling fails; forbidden uses are returned in the report
+    pub fn lint<P: AsRef<Path>>(
+        &self,
+        package_path: P,
+        profile: LintProfile,
+    ) -> Result<LintReport> {
+        let project = self.load_project(package_path)?;
+        let config = TransformConfig {
+            line_markers: true,
+            ..self.config.clone()
+        };
+        let (file, ..) = self.transform_project(&project, None, config.clone())?;
+        lint::lint_bundle(&render(&config, &file)?, profile)
+    }
+
+    /// Bundle a Cargo package `runs` times and fail unless every output is identical
+    ///
+    /// Use this in tests of custom passes to catch output that depends on hash
+    /// iteration order. See `determinism::check_determinism`.
+    ///
+    /// # Errors
+    /// Returns an error if bundling fails or the outputs differ
+    pub fn check_determinism<P: AsRef<Path>>(
+        &self,
+        package_path: P,
+        runs: usize,
+    ) -> Result<String> {
+        let project = self.load_project(package_path)?;
+        // Without the header, whose build time changes between runs
+        determinism::check_determinism(runs, |_| {
+            let (file, ..) = self.transform_project(&project, None, self.config.clone())?;
+            self.emit(&self.config, &file, self.edition_of(&project))
+        })
+    }
+
+    /// Bundle a Cargo package `runs` times, timing each phase of every run
+    ///
+    /// Every run is a `bundle_with_timings` from `cargo metadata` on, as a fresh
+    /// invocation would bundle. The report's peak memory is that of the whole
+    /// process, so it includes whatever ran before.
+    ///
+    /// # Errors
+    /// Returns the first bundling error
+    pub fn bench<P: AsRef<Path>>(&self, package_path: P, runs: usize) -> Result<BenchReport> {
+        let package_path = package_path.as_ref();
+        let runs = (0..runs.max(1))
+            .map(|_| {
+                self.bundle_with_timings(package_path)
+                    .map(|(.., summary)| summary.timings)
+            })
+            .collect::<Result<_>>()?;
+        Ok(BenchReport {
+            runs,
+            peak_rss: bench::peak_rss(),
+        })
+    }
+
+    /// Describe what bundling a Cargo package would do, without parsing its sources
+    ///
+    /// # Errors
+    /// Returns an error if the project metadata or entry sources cannot be read
+    pub fn plan<P: AsRef<Path>>(&self, package_path: P) -> Result<BundlePlan> {
+        let project = self.load_project(package_path)?;
+        BundlePlan::new(&project, &self.config)
+    }
+
+    /// Analyze a Cargo package, selecting the target set with `with_binary` or
+    /// `with_example`
+    fn load_project<P: AsRef<Path>>(&self, package_path: P) -> Result<CargoProject> {
+        CargoProject::with_target(package_path, &self.target)
+    }
+
+    /// The build script output `include!`s may refer to, if the package has a
+    /// build script or an `OUT_DIR` hint was given
+    ///
+    /// A build script that fails to run is reproduced instead, adding a warning
+    /// to `warnings`.
+    fn out_dir(
+        &self,
+        project: &CargoProject,
+        warnings: &mut Vec<BundleWarning>,
+    ) -> Result<Option<OutDir>> {
+        if let Some(hint) = &self.out_dir_hint {
+            let package: &str = &project.root_package().name;
+            return build_script::find_out_dir(hint, package).map(|path| Some(OutDir::Built(path)));
+        }
+        let Some(script) = project.build_script_path() else {
+            return Ok(None);
+        };
+        if self.run_build_script {
+            match build_script::run(project) {
+                Ok(path) => return Ok(Some(OutDir::Built(path))),
+                Err(e) => warnings.push(BundleWarning::BuildScriptFailed {
+                    reason: e.to_string(),
+                }),
+            }
+        }
+
+        let source = self.sources().read_file(script)?;
+        Ok(Some(match build_script::analyze(&source) {
+            Ok(files) => OutDir::Simulated {
+                path: project_root(project).join("target").join("cg-bundler-out"),
+                files,
+            },
+            Err(reason) => OutDir::Unknown { reason },
+        }))
+    }
+
+    /// Render the transformed AST according to the minification settings of
+    /// `config`, then
+    /// format it as Rust of `edition` with the formatter set with `with_formatter`
+    fn emit(&self, config: &TransformConfig, file: &syn::File, edition: &str) -> Result<String> {
+        let code = render(config, file)?;
+        self.format(config, code, edition)
+    }
+
+    /// Run the formatter on `code`, rendered with `config`, unless it is minified
+    fn format(&self, config: &TransformConfig, code: String, edition: &str) -> Result<String> {
+        let formatter = self
+            .formatter
+            .as_ref()
+            .filter(|_| !config.minify && !config.aggressive_minify);
+        let Some(formatter) = formatter else {
+            return Ok(code);
+        };
+        let code = formatter.format(&code, edition)?;
+        // Formatters join the lines the bundler wrapped and leave some long ones
+        Ok(match config.max_line_len {
+            Some(max_len) => line_wrap::wrap_long_lines(&code, max_len),
+            None => code,
+        })
+    }
+
+    /// Render the transformed AST of `project`, below the header set with
+    /// `with_header`
+    fn emit_project(
+        &self,
+        config: &TransformConfig,
+        project: &CargoProject,
+        file: &syn::File,
+    ) -> Result<String> {
+        let code = self.emit(config, file, self.edition_of(project))?;
+        Ok(self.with_header_of(project, code))
+    }
+
+    /// `code`, a bundle of `project`, below the header set with `with_header`
+    fn with_header_of(&self, project: &CargoProject, code: String) -> String {
+        let Some((template, profile)) = &self.header else {
+            return code;
+        };
+        let values = TemplateValues::new(project, profile, &code, SystemTime::now());
+        template.render_comment(&values) + &code
+    }
+
+    /// Register a custom transform pass
+    ///
+    /// Passes run in registration order within their `PassStage`.
+    pub fn add_pass(&mut self, pass: Box<dyn TransformPass>) {
+        self.passes.push(pass);
     }
 
     /// Get the current configuration
@@ -76,6 +1011,147 @@ impl Bundler {
     }
 }
 
+/// Root file of the library of `project`, whether or not it exists
+fn library_root(project: &CargoProject) -> PathBuf {
+    project
+        .library_source_path()
+        .map_or_else(|| project.base_path().join("lib.rs"), Path::to_path_buf)
+}
+
+/// Read the binary and library sources of `project` with up to `jobs` threads,
+/// prepared for parsing with the comments `config` keeps
+fn prefetch_sources(
+    project: &CargoProject,
+    config: &TransformConfig,
+    jobs: usize,
+) -> PrefetchedSources {
+    let library_root = library_root(project);
+    let library_dir = library_root.parent().unwrap_or_else(|| project.base_path());
+    let roots = [
+        (
+            project.binary_source_path().to_path_buf(),
+            project.binary_base_path().to_path_buf(),
+        ),
+        (library_root.clone(), library_dir.to_path_buf()),
+    ];
+    FileManager::prefetch(&roots, jobs, config.keep_comments)
+}
+
+/// Read from `sources` and parse the source of the binary target of `project`
+fn parse_entry(
+    sources: &dyn FileProvider,
+    project: &CargoProject,
+    config: &TransformConfig,
+) -> Result<syn::File> {
+    let binary_source_path = project.binary_source_path();
+    let code = sources.read_file(binary_source_path).map_err(|e| {
+        if sources.exists(binary_source_path) {
+            BundlerError::Parsing {
+                message: format!("Failed to read binary target source: {e}"),
+                file_path: Some(binary_source_path.to_path_buf()),
+            }
+        } else {
+            project.entry_not_found()
+        }
+    })?;
+    let parsed = sources.parse_file(binary_source_path, &code, config.keep_comments);
+    let mut file = parsed.map_err(|e| BundlerError::SyntaxError {
+        message: format!("Failed to parse binary target source: {e}"),
+        file_path: Some(binary_source_path.to_path_buf()),
+        span: Span::of_syn_error(&e),
+    })?;
+    // A test target runs its tests from a generated `fn main`
+    if CargoProject::target_is(project.binary_target(), "test") {
+        test_harness::add_test_main(&mut file.items);
+    }
+    Ok(file)
+}
+
+/// Render a transformed AST according to the minification, docs, line-marker and
+/// line-length settings
+fn render(config: &TransformConfig, file: &syn::File) -> Result<String> {
+    let minifier = if config.aggressive_minify {
+        Some(Minifier::aggressive())
+    } else if config.minify {
+        Some(Minifier::new())
+    } else {
+        None
+    };
+    let code = match minifier {
+        Some(minifier) if config.verify_minify => minifier.minify_file_verified(file)?,
+        Some(minifier) => minifier.minify_file(file),
+        None if config.preserve_formatting => {
+            preserve::unparse_preserving(file, config.keep_comments)
+        }
+        None => prettyplease::unparse(file),
+    };
+    let code = if config.keep_comments {
+        comments::restore(&code)
+    } else {
+        code
+    };
+    let code = if config.effective_docs_mode() == DocsMode::ConvertToPlain {
+        docs::to_plain_comments(&code)
+    } else {
+        code
+    };
+
+    let code = if config.line_markers {
+        line_markers::render(&code, config.minify || config.aggressive_minify)
+    } else {
+        code
+    };
+    let code = verbatim::restore(&code);
+    Ok(match config.max_line_len {
+        Some(max_len) => line_wrap::wrap_long_lines(&code, max_len),
+        None => code,
+    })
+}
+
+/// Crate root of `path` for `Bundler::bundle_file`: the file itself, or the
+/// `main.rs` or `src/main.rs` of a directory
+fn standalone_entry(sources: &dyn FileProvider, path: &Path) -> Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    [path.join("main.rs"), path.join("src").join("main.rs")]
+        .into_iter()
+        .find(|entry| sources.exists(entry))
+        .ok_or_else(|| BundlerError::ProjectStructure {
+            message: format!(
+                "No main.rs or src/main.rs in {} to bundle without a manifest",
+                path.display()
+            ),
+        })
+}
+
+/// Name of the crate rooted at `entry` without a manifest: the name of its
+/// directory, or of the directory above `src`, as a Rust identifier
+fn standalone_crate_name(entry: &Path) -> String {
+    let dir = entry
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+    let dir = FileManager::canonicalize(&dir).unwrap_or(dir);
+    let dir = match dir.file_name() {
+        Some(name) if name == "src" => dir.parent().unwrap_or(&dir),
+        _ => &dir,
+    };
+    dir.file_name().map_or_else(
+        || "main".to_string(),
+        |name| name.to_string_lossy().replace('-', "_"),
+    )
+}
+
+/// Directory containing the package manifest; line-marker paths are relative to it
+fn project_root(project: &CargoProject) -> &Path {
+    project
+        .root_package()
+        .manifest_path
+        .parent()
+        .map_or_else(|| project.base_path(), |root| root.as_std_path())
+}
+
 impl Default for Bundler {
     fn default() -> Self {
         Self::new()
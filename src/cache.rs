@@ -0,0 +1,306 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::cargo_project::CargoProject;
+use crate::digest;
+use crate::error::{BundlerError, Result};
+use crate::file_manager::FileManager;
+use crate::plan::BundlePlan;
+use crate::transformer::TransformConfig;
+use crate::warnings::BundleWarning;
+
+/// What a cached bundle was made from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKey {
+    /// Version of the bundler that made the bundle
+    pub bundler_version: String,
+    /// SHA-256 of every setting the bundle was made with (see `CacheKey::new`)
+    pub config_fingerprint: String,
+    /// SHA-256 of each input file, by path relative to the package directory
+    pub inputs: BTreeMap<String, String>,
+}
+
+impl CacheKey {
+    /// Key of bundling `project` with `config` and `settings`, the text of every
+    /// other setting the output depends on (e.g. the configuration file and the
+    /// formatting flags), so that changing any of them invalidates the bundle
+    ///
+    /// The inputs are the manifest, the source files listed by the project's
+    /// `BundlePlan` and `included`, the files inlined by include macros
+    /// (`BundleReport::included_files`). Included files that cannot be read are
+    /// left out, so that a bundle including them is not reused.
+    ///
+    /// # Errors
+    /// Returns an error if the project plan cannot be built or a source file
+    /// cannot be read
+    pub fn new(
+        project: &CargoProject,
+        config: &TransformConfig,
+        settings: &str,
+        included: &[PathBuf],
+    ) -> Result<Self> {
+        let manifest = Path::new(&project.root_package().manifest_path);
+        let root = manifest.parent().unwrap_or_else(|| Path::new(""));
+        let key_of = |path: &Path, content: &[u8]| {
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            (
+                FileManager::display_path(relative),
+                digest::sha256_hex(content),
+            )
+        };
+        let plan = BundlePlan::new(project, config)?;
+        let mut inputs = std::iter::once(manifest)
+            .chain(plan.input_files())
+            .map(|path| {
+                let content = std::fs::read(path).map_err(|e| BundlerError::Io {
+                    source: e,
+                    path: Some(path.to_path_buf()),
+                })?;
+                Ok(key_of(path, &content))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        inputs.extend(included.iter().filter_map(|path| {
+            let content = std::fs::read(path).ok()?;
+            Some(key_of(path, &content))
+        }));
+        Ok(Self {
+            bundler_version: env!("CARGO_PKG_VERSION").to_string(),
+            config_fingerprint: digest::sha256_hex(
+                format!("{}\n{settings}", config.fingerprint()).as_bytes(),
+            ),
+            inputs,
+        })
+    }
+}
+
+/// A bundle stored in the cache, with the warnings found while bundling it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedBundle {
+    pub key: CacheKey,
+    pub code: String,
+    pub warnings: Vec<BundleWarning>,
+    /// Files the bundle inlined with include macros, which the key of the next
+    /// lookup must hash too
+    #[serde(default)]
+    pub included_files: Vec<PathBuf>,
+}
+
+/// Why the cache had no bundle to reuse for a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheMiss {
+    /// Nothing was cached yet
+    Missing,
+    /// The cache file could not be read or parsed
+    Unreadable(String),
+    /// The bundle was made by another version of the bundler
+    BundlerChanged { cached: String, current: String },
+    /// A setting changed, e.g. in `cg-bundler.toml`, with both fingerprints
+    ConfigChanged { cached: String, current: String },
+    /// Input files added, removed or modified since the bundle was cached
+    InputsChanged(Vec<String>),
+}
+
+impl fmt::Display for CacheMiss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "no cached bundle"),
+            Self::Unreadable(reason) => write!(f, "the cached bundle is unreadable: {reason}"),
+            Self::BundlerChanged { cached, current } => {
+                write!(f, "the bundler changed from {cached} to {current}")
+            }
+            Self::ConfigChanged { cached, current } => write!(
+                f,
+                "the configuration fingerprint changed from {} to {}",
+                short(cached),
+                short(current)
+            ),
+            Self::InputsChanged(files) => write!(f, "changed inputs: {}", files.join(", ")),
+        }
+    }
+}
+
+/// The first 12 characters of a fingerprint, enough to tell two apart
+fn short(fingerprint: &str) -> &str {
+    fingerprint.get(..12).unwrap_or(fingerprint)
+}
+
+/// Bundles of one entry target, kept on disk between runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleCache {
+    path: PathBuf,
+}
+
+impl BundleCache {
+    /// The cache of the entry target of `project`, in `cg-bundler/cache` under the
+    /// Cargo target directory
+    #[must_use]
+    pub fn for_project(project: &CargoProject) -> Self {
+        let target = format!(
+            "{}\n{}",
+            project.root_package().manifest_path,
+            project.binary_source_path().display()
+        );
+        let name = format!("{}.json", &digest::sha256_hex(target.as_bytes())[..16]);
+        Self::at(
+            project
+                .metadata()
+                .target_directory
+                .as_std_path()
+                .join("cg-bundler")
+                .join("cache")
+                .join(name),
+        )
+    }
+
+    /// A cache stored in the file at `path`
+    #[must_use]
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The file the cache is stored in
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Files inlined with include macros by the cached bundle, if there is one,
+    /// to build the key to look it up with
+    #[must_use]
+    pub fn included_files(&self) -> Vec<PathBuf> {
+        std::fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<CachedBundle>(&text).ok())
+            .map(|cached| cached.included_files)
+            .unwrap_or_default()
+    }
+
+    /// The bundle cached for `key`, or why it cannot be reused
+    ///
+    /// # Errors
+    /// Returns why no bundle was cached for `key`
+    pub fn lookup(&self, key: &CacheKey) -> std::result::Result<CachedBundle, CacheMiss> {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(CacheMiss::Missing),
+            Err(e) => return Err(CacheMiss::Unreadable(e.to_string())),
+        };
+        let cached: CachedBundle =
+            serde_json::from_str(&text).map_err(|e| CacheMiss::Unreadable(e.to_string()))?;
+        if cached.key.bundler_version != key.bundler_version {
+            return Err(CacheMiss::BundlerChanged {
+                cached: cached.key.bundler_version,
+                current: key.bundler_version.clone(),
+            });
+        }
+        if cached.key.config_fingerprint != key.config_fingerprint {
+            return Err(CacheMiss::ConfigChanged {
+                cached: cached.key.config_fingerprint,
+                current: key.config_fingerprint.clone(),
+            });
+        }
+        let changed: Vec<String> = cached
+            .key
+            .inputs
+            .keys()
+            .chain(key.inputs.keys())
+            .filter(|path| cached.key.inputs.get(*path) != key.inputs.get(*path))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .cloned()
+            .collect();
+        if !changed.is_empty() {
+            return Err(CacheMiss::InputsChanged(changed));
+        }
+        Ok(cached)
+    }
+
+    /// Store `bundle`, replacing the bundle cached before
+    ///
+    /// # Errors
+    /// Returns an error if the cache file cannot be written
+    pub fn store(&self, bundle: &CachedBundle) -> Result<()> {
+        let io_error = |e| BundlerError::Io {
+            source: e,
+            path: Some(self.path.clone()),
+        };
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        let json = serde_json::to_string(bundle).unwrap_or_default();
+        FileManager::write_if_changed(&self.path, &json)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn key(fingerprint: &str, inputs: &[(&str, &str)]) -> CacheKey {
+        CacheKey {
+            bundler_version: env!("CARGO_PKG_VERSION").to_string(),
+            config_fingerprint: fingerprint.to_string(),
+            inputs: inputs
+                .iter()
+                .map(|(path, hash)| ((*path).to_string(), (*hash).to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_lookup_explains_misses() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = BundleCache::at(temp_dir.path().join("cache/main.json"));
+        let stored = key(
+            "aaaaaaaaaaaaaaaa",
+            &[("src/main.rs", "1"), ("Cargo.toml", "2")],
+        );
+        assert_eq!(cache.lookup(&stored), Err(CacheMiss::Missing));
+
+        let bundle = CachedBundle {
+            key: stored.clone(),
+            code: "fn main() {}".to_string(),
+            warnings: Vec::new(),
+            included_files: vec![PathBuf::from("/bot/src/map.txt")],
+        };
+        cache.store(&bundle).unwrap();
+        assert_eq!(cache.lookup(&stored), Ok(bundle));
+        assert_eq!(cache.included_files(), [PathBuf::from("/bot/src/map.txt")]);
+
+        let miss = cache
+            .lookup(&key(
+                "bbbbbbbbbbbbbbbb",
+                &[("src/main.rs", "1"), ("Cargo.toml", "2")],
+            ))
+            .unwrap_err();
+        assert_eq!(
+            miss.to_string(),
+            "the configuration fingerprint changed from aaaaaaaaaaaa to bbbbbbbbbbbb"
+        );
+
+        let miss = cache
+            .lookup(&key(
+                "aaaaaaaaaaaaaaaa",
+                &[("src/main.rs", "3"), ("src/geo.rs", "4")],
+            ))
+            .unwrap_err();
+        assert_eq!(
+            miss,
+            CacheMiss::InputsChanged(vec![
+                "Cargo.toml".to_string(),
+                "src/geo.rs".to_string(),
+                "src/main.rs".to_string(),
+            ])
+        );
+
+        std::fs::write(cache.path(), "{").unwrap();
+        assert!(matches!(
+            cache.lookup(&stored),
+            Err(CacheMiss::Unreadable(_))
+        ));
+    }
+}
@@ -1,7 +1,81 @@
 use cargo_metadata::{Metadata, Package, Target};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
 
+use crate::diagnostics::LayoutDiagnosis;
 use crate::error::{BundlerError, Result};
+use crate::file_manager::FileManager;
+
+/// Target of a package whose `fn main` is bundled
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EntryTarget {
+    /// The only binary target, or the `default-run` one
+    #[default]
+    Default,
+    /// The `[[bin]]` target with this name
+    Binary(String),
+    /// The `[[example]]` target with this name, e.g. `examples/NAME.rs`
+    Example(String),
+    /// The `[[test]]` target with this name, e.g. `tests/NAME.rs`, bundled with a
+    /// generated `fn main` calling its `#[test]` functions
+    Test(String),
+    /// The `[lib]` target on its own, bundled without a `fn main`
+    Library,
+}
+
+/// Cargo features a package is bundled with, chosen as with `cargo build`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureSelection {
+    /// Features to enable, e.g. `advanced-ai`; `dependency/feature` entries enable
+    /// the optional dependency `dependency`
+    pub features: Vec<String>,
+    /// Leave the `default` feature off
+    pub no_default_features: bool,
+    /// Enable every feature of the package
+    pub all_features: bool,
+}
+
+impl FeatureSelection {
+    /// Features enabled with `table`, the `[features]` of a package, following
+    /// the features each enables
+    ///
+    /// # Errors
+    /// Returns an error if a selected feature is not in `table`
+    pub fn resolve(&self, table: &BTreeMap<String, Vec<String>>) -> Result<BTreeSet<String>> {
+        let mut pending: Vec<&str> = if self.all_features {
+            table.keys().map(String::as_str).collect()
+        } else {
+            self.features.iter().map(String::as_str).collect()
+        };
+        for feature in &pending {
+            let name = feature.split('/').next().unwrap_or(feature);
+            let name = name.strip_suffix('?').unwrap_or(name);
+            if !table.contains_key(name) {
+                return Err(BundlerError::Config {
+                    message: format!("the package has no feature '{name}'"),
+                    path: None,
+                });
+            }
+        }
+        if !self.no_default_features && table.contains_key("default") {
+            pending.push("default");
+        }
+
+        let mut enabled = BTreeSet::new();
+        while let Some(feature) = pending.pop() {
+            // `dep:name` enables a dependency without a feature of that name, and
+            // `name?/feature` leaves an optional dependency off
+            if feature.starts_with("dep:") || feature.contains("?/") {
+                continue;
+            }
+            let name = feature.split('/').next().unwrap_or(feature);
+            if table.contains_key(name) && enabled.insert(name.to_string()) {
+                pending.extend(table[name].iter().map(String::as_str));
+            }
+        }
+        Ok(enabled)
+    }
+}
 
 /// Represents a Cargo project with its metadata
 #[derive(Debug, Clone)]
@@ -19,21 +93,148 @@ impl CargoProject {
     /// # Errors
     /// Returns an error if the Cargo project cannot be analyzed or parsed
     pub fn new<P: AsRef<Path>>(package_path: P) -> Result<Self> {
-        let package_path = package_path.as_ref();
-        let manifest_path = package_path.join("Cargo.toml");
+        let (metadata, root_package) = Self::load_package(package_path.as_ref())?;
+        let (binary_target, library_target) = Self::analyze_targets(&root_package)?;
+        Self::from_targets(metadata, root_package, binary_target, library_target)
+    }
 
-        let metadata = cargo_metadata::MetadataCommand::new()
-            .manifest_path(&manifest_path)
-            .exec()
-            .map_err(|e| BundlerError::CargoMetadata {
-                message: format!("Failed to obtain cargo metadata: {e}"),
-                source: Some(e),
+    /// Create a `CargoProject` for the binary target called `name`
+    ///
+    /// Unlike `new`, packages with several `[[bin]]` targets are accepted.
+    ///
+    /// # Errors
+    /// Returns an error if the Cargo project cannot be analyzed or has no binary
+    /// target called `name`
+    pub fn with_binary_target<P: AsRef<Path>>(package_path: P, name: &str) -> Result<Self> {
+        let (metadata, root_package) = Self::load_package(package_path.as_ref())?;
+        let mut binary_targets = Self::binary_targets(&root_package);
+        let position = binary_targets
+            .iter()
+            .position(|target| target.name == name)
+            .ok_or_else(|| BundlerError::BinaryTargetNotFound {
+                name: name.to_string(),
+                available: binary_targets
+                    .iter()
+                    .map(|target| target.name.clone())
+                    .collect(),
             })?;
+        let binary_target = binary_targets.swap_remove(position);
+        let library_target = Self::find_library_target(&root_package)?;
+        Self::from_targets(metadata, root_package, binary_target, library_target)
+    }
 
-        let root_package = Self::find_root_package(&metadata, &manifest_path)?;
-        let (binary_target, library_target) = Self::analyze_targets(&root_package)?;
-        let base_path = Self::determine_base_path(library_target.as_ref(), &binary_target)?;
+    /// Create a `CargoProject` for the example target called `name`, bundled in
+    /// place of the binary
+    ///
+    /// # Errors
+    /// Returns an error if the Cargo project cannot be analyzed or has no example
+    /// target called `name`
+    pub fn with_example_target<P: AsRef<Path>>(package_path: P, name: &str) -> Result<Self> {
+        let (metadata, root_package) = Self::load_package(package_path.as_ref())?;
+        let mut examples = Self::targets_of_kind(&root_package, "example");
+        let position = examples
+            .iter()
+            .position(|target| target.name == name)
+            .ok_or_else(|| BundlerError::ExampleTargetNotFound {
+                name: name.to_string(),
+                available: examples.iter().map(|target| target.name.clone()).collect(),
+            })?;
+        let example = examples.swap_remove(position);
+        let library_target = Self::find_library_target(&root_package)?;
+        Self::from_targets(metadata, root_package, example, library_target)
+    }
+
+    /// Create a `CargoProject` for the integration test target called `name`,
+    /// bundled in place of the binary
+    ///
+    /// # Errors
+    /// Returns an error if the Cargo project cannot be analyzed or has no test
+    /// target called `name`
+    pub fn with_test_target<P: AsRef<Path>>(package_path: P, name: &str) -> Result<Self> {
+        let (metadata, root_package) = Self::load_package(package_path.as_ref())?;
+        let mut tests = Self::targets_of_kind(&root_package, "test");
+        let position = tests
+            .iter()
+            .position(|target| target.name == name)
+            .ok_or_else(|| BundlerError::TestTargetNotFound {
+                name: name.to_string(),
+                available: tests.iter().map(|target| target.name.clone()).collect(),
+            })?;
+        let test = tests.swap_remove(position);
+        let library_target = Self::find_library_target(&root_package)?;
+        Self::from_targets(metadata, root_package, test, library_target)
+    }
+
+    /// Create a `CargoProject` bundling the library target on its own
+    ///
+    /// The library is then the entry (`binary_target` and `binary_source_path`
+    /// refer to it) as well as the library target; packages without a binary
+    /// target are accepted.
+    ///
+    /// # Errors
+    /// Returns an error if the Cargo project cannot be analyzed or has no library
+    /// target
+    pub fn with_library_target<P: AsRef<Path>>(package_path: P) -> Result<Self> {
+        let (metadata, root_package) = Self::load_package(package_path.as_ref())?;
+        let library_target =
+            Self::find_library_target(&root_package)?.ok_or(BundlerError::NoLibraryTarget)?;
+        Self::from_targets(
+            metadata,
+            root_package,
+            library_target.clone(),
+            Some(library_target),
+        )
+    }
+
+    /// Create a `CargoProject` for `target`, as `new`, `with_binary_target`,
+    /// `with_example_target`, `with_test_target` or `with_library_target` would
+    ///
+    /// # Errors
+    /// Returns an error if the Cargo project cannot be analyzed or lacks the target
+    pub fn with_target<P: AsRef<Path>>(package_path: P, target: &EntryTarget) -> Result<Self> {
+        match target {
+            EntryTarget::Default => Self::new(package_path),
+            EntryTarget::Binary(name) => Self::with_binary_target(package_path, name),
+            EntryTarget::Example(name) => Self::with_example_target(package_path, name),
+            EntryTarget::Test(name) => Self::with_test_target(package_path, name),
+            EntryTarget::Library => Self::with_library_target(package_path),
+        }
+    }
+
+    /// Analyze the given path once and create a `CargoProject` for every binary target
+    ///
+    /// Unlike `new`, packages with several `[[bin]]` targets are accepted.
+    ///
+    /// # Errors
+    /// Returns an error if the Cargo project cannot be analyzed or has no binary target
+    pub fn all_binaries<P: AsRef<Path>>(package_path: P) -> Result<Vec<Self>> {
+        let (metadata, root_package) = Self::load_package(package_path.as_ref())?;
+        let binary_targets = Self::binary_targets(&root_package);
+        if binary_targets.is_empty() {
+            return Err(Self::no_binary_target(&root_package));
+        }
+        let library_target = Self::find_library_target(&root_package)?;
+
+        binary_targets
+            .into_iter()
+            .map(|binary_target| {
+                Self::from_targets(
+                    metadata.clone(),
+                    root_package.clone(),
+                    binary_target,
+                    library_target.clone(),
+                )
+            })
+            .collect()
+    }
 
+    fn from_targets(
+        metadata: Metadata,
+        root_package: Package,
+        binary_target: Target,
+        library_target: Option<Target>,
+    ) -> Result<Self> {
+        let base_path = Self::determine_base_path(library_target.as_ref(), &binary_target)?;
         Ok(Self {
             metadata,
             root_package,
@@ -82,6 +283,15 @@ impl CargoProject {
         Path::new(&self.binary_target.src_path)
     }
 
+    /// Directory the modules of the binary are looked up in, the directory of its
+    /// source file (`src_path`), wherever the manifest puts it
+    #[must_use]
+    pub fn binary_base_path(&self) -> &Path {
+        self.binary_source_path()
+            .parent()
+            .unwrap_or(&self.base_path)
+    }
+
     /// Get the library source path if it exists
     #[must_use]
     pub fn library_source_path(&self) -> Option<&Path> {
@@ -90,16 +300,54 @@ impl CargoProject {
             .map(|lib| Path::new(&lib.src_path))
     }
 
+    /// Test, bench and example targets other than the bundled one, which are never
+    /// part of the bundle
+    pub fn auxiliary_targets(&self) -> impl Iterator<Item = &Target> {
+        self.root_package.targets.iter().filter(|target| {
+            ["test", "bench", "example"]
+                .iter()
+                .any(|kind| Self::target_is(target, kind))
+                && target.src_path != self.binary_target.src_path
+        })
+    }
+
+    /// Whether `path` belongs to an auxiliary target (see `auxiliary_targets`): it
+    /// is the root file of one, or in a directory of such roots (e.g. `tests/`)
+    /// that holds neither the bundled binary nor the library
+    #[must_use]
+    pub fn is_auxiliary_source(&self, path: &Path) -> bool {
+        let bundled: Vec<&Path> = std::iter::once(self.binary_source_path())
+            .chain(self.library_source_path())
+            .collect();
+        self.auxiliary_targets().any(|target| {
+            let root = target.src_path.as_std_path();
+            root == path
+                || root.parent().is_some_and(|dir| {
+                    path.starts_with(dir) && !bundled.iter().any(|file| file.starts_with(dir))
+                })
+        })
+    }
+
+    /// Get the build script (`build.rs`) source path if the package has one
+    #[must_use]
+    pub fn build_script_path(&self) -> Option<&Path> {
+        self.root_package
+            .targets
+            .iter()
+            .find(|t| Self::target_is(t, "custom-build"))
+            .map(|t| t.src_path.as_std_path())
+    }
+
     /// Find the root package in the metadata
     fn find_root_package(metadata: &Metadata, manifest_path: &Path) -> Result<Package> {
-        let canonical_manifest =
-            std::fs::canonicalize(manifest_path).unwrap_or_else(|_| manifest_path.to_path_buf());
+        let canonical_manifest = FileManager::canonicalize(manifest_path)
+            .unwrap_or_else(|_| manifest_path.to_path_buf());
 
         metadata
             .packages
             .iter()
             .find(|pkg| {
-                let pkg_canonical = std::fs::canonicalize(&pkg.manifest_path)
+                let pkg_canonical = FileManager::canonicalize(&pkg.manifest_path)
                     .unwrap_or_else(|_| pkg.manifest_path.clone().into());
                 pkg_canonical == canonical_manifest
             })
@@ -109,30 +357,156 @@ impl CargoProject {
             })
     }
 
-    /// Analyze targets and extract binary and library targets
-    fn analyze_targets(package: &Package) -> Result<(Target, Option<Target>)> {
-        let targets = &package.targets;
+    /// Run `cargo metadata` and find the package whose manifest is in `package_path`
+    fn load_package(package_path: &Path) -> Result<(Metadata, Package)> {
+        let manifest_path = package_path.join("Cargo.toml");
 
-        // Find binary targets
-        let binary_targets: Vec<_> = targets
+        let metadata = cargo_metadata::MetadataCommand::new()
+            .manifest_path(&manifest_path)
+            .exec()
+            .map_err(|e| {
+                // The binary may be where Cargo does not look for it
+                let diagnosis = e
+                    .to_string()
+                    .contains("no targets specified")
+                    .then(|| {
+                        LayoutDiagnosis::missing_binary(
+                            package_path,
+                            Self::manifest_package_name(&manifest_path).as_deref(),
+                        )
+                    })
+                    .flatten();
+                diagnosis.map_or_else(
+                    || BundlerError::CargoMetadata {
+                        message: format!("Failed to obtain cargo metadata: {e}"),
+                        source: Some(e),
+                    },
+                    |diagnosis| BundlerError::EntryNotFound {
+                        diagnosis: Box::new(diagnosis),
+                    },
+                )
+            })?;
+
+        let root_package = Self::find_root_package(&metadata, &manifest_path)?;
+        Ok((metadata, root_package))
+    }
+
+    /// `[package] name` of the manifest at `manifest_path`, read without Cargo
+    fn manifest_package_name(manifest_path: &Path) -> Option<String> {
+        let manifest: toml::Table = std::fs::read_to_string(manifest_path).ok()?.parse().ok()?;
+        Some(manifest.get("package")?.get("name")?.as_str()?.to_string())
+    }
+
+    /// Error for a package without binary target, explaining where its binary
+    /// may be if it has a file defining `fn main`
+    fn no_binary_target(package: &Package) -> BundlerError {
+        package
+            .manifest_path
+            .parent()
+            .and_then(|dir| LayoutDiagnosis::missing_binary(dir.as_std_path(), Some(&package.name)))
+            .map_or(BundlerError::NoBinaryTarget, |diagnosis| {
+                BundlerError::EntryNotFound {
+                    diagnosis: Box::new(diagnosis),
+                }
+            })
+    }
+
+    /// Error for the entry target, whose source file does not exist, with the
+    /// files of the package that may be it and how to fix the manifest
+    #[must_use]
+    pub fn entry_not_found(&self) -> BundlerError {
+        let target = &self.binary_target;
+        let kind = ["bin", "example", "test", "lib"]
+            .into_iter()
+            .find(|kind| Self::target_is(target, kind))
+            .unwrap_or("bin");
+        let sources: Vec<PathBuf> = self
+            .root_package
+            .targets
             .iter()
-            .filter(|t| Self::target_is(t, "bin"))
+            .filter(|other| other.src_path != target.src_path)
+            .map(|other| other.src_path.clone().into_std_path_buf())
+            .collect();
+        let others: Vec<String> = Self::targets_of_kind(&self.root_package, kind)
+            .into_iter()
+            .filter(|other| other.name != target.name && other.src_path.exists())
+            .map(|other| other.name)
             .collect();
+        let diagnosis = LayoutDiagnosis::missing_target(
+            self.package_dir(),
+            kind,
+            &target.name,
+            target.src_path.as_std_path(),
+            &sources,
+            &others,
+        );
+        BundlerError::EntryNotFound {
+            diagnosis: Box::new(diagnosis),
+        }
+    }
+
+    /// Directory of the package manifest
+    fn package_dir(&self) -> &Path {
+        self.root_package
+            .manifest_path
+            .parent()
+            .map_or(&self.base_path, |dir| dir.as_std_path())
+    }
+
+    /// Analyze targets and extract binary and library targets
+    fn analyze_targets(package: &Package) -> Result<(Target, Option<Target>)> {
+        let mut binary_targets = Self::binary_targets(package);
 
         if binary_targets.is_empty() {
-            return Err(BundlerError::NoBinaryTarget);
+            return Err(Self::no_binary_target(package));
         }
 
+        // Like `cargo run`, `default-run` selects among several binaries
+        if let Some(default_run) = &package.default_run {
+            if binary_targets
+                .iter()
+                .any(|target| &target.name == default_run)
+            {
+                binary_targets.retain(|target| &target.name == default_run);
+            }
+        }
         if binary_targets.len() > 1 {
             return Err(BundlerError::MultipleBinaryTargets {
                 target_count: binary_targets.len(),
             });
         }
 
-        let binary_target = binary_targets[0].clone();
+        let binary_target = binary_targets.remove(0);
+        let library_target = Self::find_library_target(package)?;
 
-        // Find library targets
-        let library_targets: Vec<_> = targets
+        Ok((binary_target, library_target))
+    }
+
+    /// All binary targets of a package, sorted by name
+    ///
+    /// Cargo lists targets found in `src/bin` in directory order, which differs
+    /// between file systems; sorting keeps `--all-bins` output and error messages
+    /// the same on every machine.
+    fn binary_targets(package: &Package) -> Vec<Target> {
+        Self::targets_of_kind(package, "bin")
+    }
+
+    /// Targets of a package with the given kind, sorted by name
+    fn targets_of_kind(package: &Package, kind: &str) -> Vec<Target> {
+        let mut targets: Vec<Target> = package
+            .targets
+            .iter()
+            .filter(|t| Self::target_is(t, kind))
+            .cloned()
+            .collect();
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        targets
+    }
+
+    /// The library target of a package, if any
+    fn find_library_target(package: &Package) -> Result<Option<Target>> {
+        let library_targets: Vec<_> = package
+            .targets
             .iter()
             .filter(|t| Self::target_is(t, "lib"))
             .collect();
@@ -143,9 +517,7 @@ impl CargoProject {
             });
         }
 
-        let library_target = library_targets.first().map(|t| (*t).clone());
-
-        Ok((binary_target, library_target))
+        Ok(library_targets.first().map(|t| (*t).clone()))
     }
 
     /// Determine the base path for source files
@@ -163,12 +535,49 @@ impl CargoProject {
             })
     }
 
+    /// Library source file and enabled features of the dependency whose library
+    /// is called `name` in this package's code (as in `use name::...`)
+    ///
+    /// The source is the dependency's checkout, e.g. in the local Cargo registry.
+    /// Returns `None` if the package has no such dependency.
+    #[must_use]
+    pub fn dependency_library(&self, name: &str) -> Option<(PathBuf, BTreeSet<String>)> {
+        let resolve = self.metadata.resolve.as_ref()?;
+        let root = resolve
+            .nodes
+            .iter()
+            .find(|node| node.id == self.root_package.id)?;
+        let dependency = root.deps.iter().find(|dep| dep.name == name)?;
+        let node = resolve
+            .nodes
+            .iter()
+            .find(|node| node.id == dependency.pkg)?;
+        let library = self.metadata[&dependency.pkg]
+            .targets
+            .iter()
+            .find(|target| Self::target_is(target, "lib"))?;
+        let features = node.features.iter().map(ToString::to_string).collect();
+        Some((library.src_path.clone().into_std_path_buf(), features))
+    }
+
+    /// Features of the package enabled with `selection`
+    ///
+    /// # Errors
+    /// Returns an error if a selected feature is not a feature of the package
+    pub fn resolve_features(&self, selection: &FeatureSelection) -> Result<BTreeSet<String>> {
+        selection.resolve(&self.root_package.features)
+    }
+
     /// Check if a target has a specific kind
-    fn target_is(target: &Target, target_kind: &str) -> bool {
+    pub(crate) fn target_is(target: &Target, target_kind: &str) -> bool {
         use cargo_metadata::TargetKind;
         target.kind.iter().any(|kind| match kind {
             TargetKind::Bin if target_kind == "bin" => true,
             TargetKind::Lib if target_kind == "lib" => true,
+            TargetKind::CustomBuild if target_kind == "custom-build" => true,
+            TargetKind::Example if target_kind == "example" => true,
+            TargetKind::Test if target_kind == "test" => true,
+            TargetKind::Bench if target_kind == "bench" => true,
             _ => false,
         })
     }
@@ -226,6 +635,40 @@ path = "src/lib.rs"
         project_path
     }
 
+    #[test]
+    fn test_feature_selection_follows_features() {
+        let table = BTreeMap::from([
+            ("default".to_string(), vec!["greedy".to_string()]),
+            ("greedy".to_string(), Vec::new()),
+            (
+                "advanced-ai".to_string(),
+                vec!["search".to_string(), "dep:rand".to_string()],
+            ),
+            ("search".to_string(), vec!["itertools/use_std".to_string()]),
+            ("itertools".to_string(), vec!["dep:itertools".to_string()]),
+            ("debug-vis".to_string(), vec!["serde?/derive".to_string()]),
+            ("serde".to_string(), vec!["dep:serde".to_string()]),
+        ]);
+        let resolve = |features: &[&str], no_default_features, all_features| {
+            FeatureSelection {
+                features: features.iter().map(ToString::to_string).collect(),
+                no_default_features,
+                all_features,
+            }
+            .resolve(&table)
+            .map(|enabled| enabled.into_iter().collect::<Vec<_>>())
+        };
+
+        assert_eq!(resolve(&[], false, false).unwrap(), ["default", "greedy"]);
+        assert_eq!(
+            resolve(&["advanced-ai"], true, false).unwrap(),
+            ["advanced-ai", "itertools", "search"]
+        );
+        assert_eq!(resolve(&["debug-vis"], true, false).unwrap(), ["debug-vis"]);
+        assert_eq!(resolve(&[], true, true).unwrap().len(), table.len());
+        assert!(resolve(&["nope"], false, false).is_err());
+    }
+
     #[test]
     fn test_project_with_lib() {
         let temp_dir = TempDir::new().unwrap();
@@ -297,6 +740,168 @@ path = "src/bin2.rs"
         }
     }
 
+    #[test]
+    fn test_default_run_selects_binary() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_path = temp_dir.path().join("default_run_project");
+        fs::create_dir_all(project_path.join("src/bin")).unwrap();
+        fs::write(
+            project_path.join("Cargo.toml"),
+            "[package]\nname = \"default_run_project\"\nversion = \"0.1.0\"\nedition = \"2021\"\ndefault-run = \"solver\"\n",
+        )
+        .unwrap();
+        fs::write(project_path.join("src/bin/solver.rs"), "fn main() {}").unwrap();
+        fs::write(project_path.join("src/bin/tool.rs"), "fn main() {}").unwrap();
+
+        let project = CargoProject::new(&project_path).unwrap();
+        assert_eq!(project.binary_target().name, "solver");
+    }
+
+    #[test]
+    fn test_all_binaries_of_multi_bin_project() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_path = temp_dir.path().join("multi_bin_project");
+        fs::create_dir_all(project_path.join("src/bin")).unwrap();
+
+        let cargo_toml = r#"
+[package]
+name = "multi_bin_project"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+name = "shared"
+path = "src/lib.rs"
+
+[[bin]]
+name = "referee"
+path = "src/bin/referee.rs"
+
+[[bin]]
+name = "bot"
+path = "src/bin/bot.rs"
+"#;
+
+        fs::write(project_path.join("Cargo.toml"), cargo_toml).unwrap();
+        fs::write(project_path.join("src/lib.rs"), "pub fn hello() {}").unwrap();
+        fs::write(project_path.join("src/bin/referee.rs"), "fn main() {}").unwrap();
+        fs::write(project_path.join("src/bin/bot.rs"), "fn main() {}").unwrap();
+
+        let projects = CargoProject::all_binaries(&project_path).unwrap();
+        let names: Vec<&str> = projects
+            .iter()
+            .map(|project| project.binary_target().name.as_str())
+            .collect();
+        assert_eq!(names, ["bot", "referee"]);
+        for project in &projects {
+            assert_eq!(project.crate_name(), "shared");
+            assert!(project.base_path().ends_with("src"));
+        }
+    }
+
+    #[test]
+    fn test_with_binary_target_selects_one_binary() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_path = temp_dir.path().join("multi_bin_project");
+        fs::create_dir_all(project_path.join("src/bin")).unwrap();
+
+        let cargo_toml = r#"
+[package]
+name = "multi_bin_project"
+version = "0.1.0"
+edition = "2021"
+
+[[bin]]
+name = "referee"
+path = "src/bin/referee.rs"
+
+[[bin]]
+name = "bot"
+path = "src/bin/bot.rs"
+"#;
+
+        fs::write(project_path.join("Cargo.toml"), cargo_toml).unwrap();
+        fs::write(project_path.join("src/bin/referee.rs"), "fn main() {}").unwrap();
+        fs::write(project_path.join("src/bin/bot.rs"), "fn main() {}").unwrap();
+
+        let project = CargoProject::with_binary_target(&project_path, "bot").unwrap();
+        assert_eq!(project.binary_target().name, "bot");
+        assert!(project.binary_source_path().ends_with("bin/bot.rs"));
+
+        match CargoProject::with_binary_target(&project_path, "player").unwrap_err() {
+            BundlerError::BinaryTargetNotFound { name, available } => {
+                assert_eq!(name, "player");
+                assert_eq!(available, ["bot", "referee"]);
+            }
+            other => panic!("Expected BinaryTargetNotFound error, got {other}"),
+        }
+    }
+
+    #[test]
+    fn test_auxiliary_targets_and_example_selection() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_path = temp_dir.path().join("flat_project");
+        fs::create_dir_all(project_path.join("tests")).unwrap();
+        fs::create_dir_all(project_path.join("examples")).unwrap();
+
+        let cargo_toml = r#"
+[package]
+name = "flat_project"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+path = "lib.rs"
+
+[[bin]]
+name = "bot"
+path = "main.rs"
+"#;
+
+        fs::write(project_path.join("Cargo.toml"), cargo_toml).unwrap();
+        fs::write(project_path.join("lib.rs"), "pub fn hello() {}").unwrap();
+        fs::write(project_path.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(project_path.join("tests/broken.rs"), "fn (").unwrap();
+        fs::write(project_path.join("examples/demo.rs"), "fn main() {}").unwrap();
+
+        let project = CargoProject::new(&project_path).unwrap();
+        let base = project.base_path().to_path_buf();
+        let mut auxiliary: Vec<&str> = project
+            .auxiliary_targets()
+            .map(|target| target.name.as_str())
+            .collect();
+        auxiliary.sort_unstable();
+        assert_eq!(auxiliary, ["broken", "demo"]);
+        assert!(project.is_auxiliary_source(&base.join("tests/broken.rs")));
+        assert!(project.is_auxiliary_source(&base.join("tests/common/mod.rs")));
+        assert!(project.is_auxiliary_source(&base.join("examples/demo.rs")));
+        assert!(!project.is_auxiliary_source(&base.join("lib.rs")));
+        assert!(!project.is_auxiliary_source(&base.join("main.rs")));
+
+        let example =
+            CargoProject::with_target(&project_path, &EntryTarget::Example("demo".into())).unwrap();
+        assert_eq!(example.binary_target().name, "demo");
+        assert!(example.library_target().is_some());
+        assert!(!example.is_auxiliary_source(&base.join("examples/demo.rs")));
+
+        match CargoProject::with_example_target(&project_path, "replay").unwrap_err() {
+            BundlerError::ExampleTargetNotFound { name, available } => {
+                assert_eq!(name, "replay");
+                assert_eq!(available, ["demo"]);
+            }
+            other => panic!("Expected ExampleTargetNotFound error, got {other}"),
+        }
+
+        let test =
+            CargoProject::with_target(&project_path, &EntryTarget::Test("broken".into())).unwrap();
+        assert_eq!(test.binary_source_path(), base.join("tests/broken.rs"));
+        assert!(!test.is_auxiliary_source(&base.join("tests/common/mod.rs")));
+        match CargoProject::with_test_target(&project_path, "demo").unwrap_err() {
+            BundlerError::TestTargetNotFound { available, .. } => assert_eq!(available, ["broken"]),
+            other => panic!("Expected TestTargetNotFound error, got {other}"),
+        }
+    }
+
     #[test]
     fn test_project_with_no_binary_should_fail() {
         let temp_dir = TempDir::new().unwrap();
@@ -326,6 +931,14 @@ path = "src/lib.rs"
             BundlerError::NoBinaryTarget => {}
             _ => panic!("Expected NoBinaryTarget error"),
         }
+
+        let library = CargoProject::with_target(&project_path, &EntryTarget::Library).unwrap();
+        assert!(library.binary_source_path().ends_with("src/lib.rs"));
+        assert_eq!(
+            library.library_source_path(),
+            Some(library.binary_source_path())
+        );
+        assert_eq!(library.crate_name(), "no_bin_project");
     }
 
     #[test]
@@ -0,0 +1,258 @@
+use serde::Deserialize;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{BundlerError, Result};
+use crate::transformer::SourceMap;
+
+/// Compiler run by `check_bundle`, overridable like Cargo's `RUSTC`
+const RUSTC_ENV: &str = "RUSTC";
+
+/// A compiler error in a bundle, mapped back to the source file it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Error code such as `E0425`, if the compiler assigned one
+    pub code: Option<String>,
+    pub message: String,
+    /// Position in the bundle (1-based line and column)
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// Source file the position lies in, when the bundle has line markers there
+    pub file: Option<String>,
+    /// Full compiler output for the error, with code snippets
+    pub rendered: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.code {
+            Some(code) => write!(f, "error[{code}]: {}", self.message)?,
+            None => write!(f, "error: {}", self.message)?,
+        }
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            let file = self.file.as_deref().unwrap_or("<bundle>");
+            write!(f, "\n  --> {file} (bundle line {line}:{column})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors the compiler reported for a bundle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckReport {
+    /// Whether the bundle compiled without errors
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+#[derive(Deserialize)]
+struct CompilerMessage {
+    #[serde(rename = "$message_type")]
+    message_type: Option<String>,
+    message: String,
+    code: Option<CompilerCode>,
+    level: String,
+    spans: Vec<CompilerSpan>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompilerCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct CompilerSpan {
+    line_start: usize,
+    column_start: usize,
+    is_primary: bool,
+}
+
+/// Type-check a bundle with `rustc` (or `$RUSTC`) the way the judge compiles it:
+/// as a single binary crate of the given edition, without dependencies
+///
+/// Warnings are not reported. Positions of errors are mapped to source files
+/// through the bundle's `// file:` line markers, when it has them.
+///
+/// # Errors
+/// Returns an error if the temporary crate cannot be written, the compiler
+/// cannot be run, or it fails without reporting an error in the bundle
+pub fn check_bundle(code: &str, edition: &str) -> Result<CheckReport> {
+    let rustc = std::env::var_os(RUSTC_ENV).unwrap_or_else(|| "rustc".into());
+    check_bundle_with(&rustc, code, edition)
+}
+
+fn check_bundle_with(rustc: &OsStr, code: &str, edition: &str) -> Result<CheckReport> {
+    let dir = TempCrate::new()?;
+    let source = dir.path.join("bundle.rs");
+    std::fs::write(&source, code).map_err(|e| BundlerError::Io {
+        source: e,
+        path: Some(source.clone()),
+    })?;
+
+    let output = Command::new(rustc)
+        .args(["--edition", edition, "--crate-type", "bin", "--crate-name"])
+        .arg("bundle")
+        .args(["--emit=metadata", "--error-format=json", "-A", "warnings"])
+        .arg("--out-dir")
+        .arg(&dir.path)
+        .arg(&source)
+        .output()
+        .map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(PathBuf::from(rustc)),
+        })?;
+
+    let source_map = SourceMap::from_output(code);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let diagnostics: Vec<Diagnostic> = stderr
+        .lines()
+        .filter_map(|line| serde_json::from_str::<CompilerMessage>(line).ok())
+        .filter(|message| {
+            message.message_type.as_deref().unwrap_or("diagnostic") == "diagnostic"
+                && message.level.starts_with("error")
+                && !message.spans.is_empty()
+        })
+        .map(|message| to_diagnostic(message, &source_map))
+        .collect();
+    // E.g. a crashed compiler, or errors outside the bundle such as a bad edition
+    if diagnostics.is_empty() && !output.status.success() {
+        return Err(BundlerError::Io {
+            source: std::io::Error::other(format!(
+                "the compiler failed ({}) without reporting an error in the bundle:\n{}",
+                output.status,
+                render_stderr(&stderr)
+            )),
+            path: Some(PathBuf::from(rustc)),
+        });
+    }
+    Ok(CheckReport { diagnostics })
+}
+
+/// Compiler output as a person reads it: the rendered form of its JSON messages
+fn render_stderr(stderr: &str) -> String {
+    stderr
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<CompilerMessage>(line).map_or_else(
+                |_| line.to_string(),
+                |message| message.rendered.unwrap_or(message.message),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}
+
+fn to_diagnostic(message: CompilerMessage, source_map: &SourceMap) -> Diagnostic {
+    let span = message
+        .spans
+        .iter()
+        .find(|span| span.is_primary)
+        .or_else(|| message.spans.first());
+    Diagnostic {
+        code: message.code.map(|code| code.code),
+        line: span.map(|span| span.line_start),
+        column: span.map(|span| span.column_start),
+        file: span
+            .and_then(|span| source_map.file_at_position(span.line_start, span.column_start))
+            .map(str::to_string),
+        rendered: message.rendered.unwrap_or_else(|| message.message.clone()),
+        message: message.message,
+    }
+}
+
+/// Temporary directory holding the crate to check, removed on drop
+struct TempCrate {
+    path: PathBuf,
+}
+
+impl TempCrate {
+    fn new() -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.subsec_nanos());
+        let path = std::env::temp_dir().join(format!(
+            "cg-bundler-check-{}-{nanos}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(path.clone()),
+        })?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for TempCrate {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_maps_errors_to_source_files() {
+        let code = "// file: src/main.rs\nfn main() {\n    geo::area();\n}\n// file: src/geo.rs\nmod geo {\n    pub fn area() -> u32 {\n        missing\n    }\n}\n";
+        let report = check_bundle(code, "2021").unwrap();
+
+        assert_eq!(report.diagnostics.len(), 1);
+        let diagnostic = &report.diagnostics[0];
+        assert_eq!(diagnostic.code.as_deref(), Some("E0425"));
+        assert_eq!(diagnostic.file.as_deref(), Some("src/geo.rs"));
+        assert_eq!((diagnostic.line, diagnostic.column), (Some(8), Some(9)));
+        assert!(diagnostic
+            .to_string()
+            .ends_with("--> src/geo.rs (bundle line 8:9)"));
+    }
+
+    #[test]
+    fn test_check_accepts_valid_bundle() {
+        let report = check_bundle("fn main() { let unused = 1; }\n", "2021").unwrap();
+        assert!(report.is_ok());
+    }
+
+    #[test]
+    fn test_check_fails_when_the_compiler_fails_silently() {
+        let report = check_bundle("fn main() {}\n", "2000");
+        let error = report.unwrap_err().to_string();
+        assert!(error.contains("without reporting an error in the bundle"));
+        assert!(error.contains("2000"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_reports_compiler_stderr() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempCrate::new().unwrap();
+        let rustc = dir.path.join("rustc");
+        std::fs::write(
+            &rustc,
+            "#!/bin/sh\necho 'rustc: out of memory' >&2\nexit 101\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&rustc, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let error = check_bundle_with(rustc.as_os_str(), "fn main() {}\n", "2021")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("rustc: out of memory"));
+    }
+}
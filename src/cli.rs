@@ -0,0 +1,3445 @@
+//! Command-line interface of the `cg-bundler` and `cargo-cg-bundle` binaries
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+#[cfg(feature = "color")]
+use colored::Colorize;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cache::{BundleCache, CacheKey, CachedBundle};
+use crate::codingame::{PlayMode, PlayResult};
+use crate::crash_report;
+use crate::diagnostics::Diagnostic;
+use crate::diff;
+use crate::file_manager::FileManager;
+use crate::formatter::{self, Formatter, Rustfmt};
+use crate::graph::GraphFormat;
+use crate::hook::{self, HookInstall, HookMode, PreCommitHook};
+use crate::integrity;
+use crate::lint::LintProfile;
+use crate::provenance::Provenance;
+use crate::scaffold::{ProjectTemplate, Scaffold};
+use crate::size_report::CODINGAME_LIMIT;
+use crate::split;
+use crate::template::{Template, TemplateValues, DEFAULT_HEADER};
+use crate::transformer::line_wrap;
+use crate::unbundle;
+use crate::validate::{self, Problem, ProblemCategory};
+use crate::warnings::BundleReport;
+use crate::{
+    Bundler, BundlerConfig, BundlerError, CargoProject, DocsMode, EntryTarget, FeatureSelection,
+    MinifyStage, SourceMap, TransformConfig,
+};
+
+#[cfg(not(feature = "color"))]
+use plain::Colorize;
+
+/// Colorless stand-in for `colored::Colorize` used when the `color` feature is disabled
+#[cfg(not(feature = "color"))]
+mod plain {
+    pub trait Colorize: Sized + Into<String> {
+        fn red(self) -> String {
+            self.into()
+        }
+        fn green(self) -> String {
+            self.into()
+        }
+        fn yellow(self) -> String {
+            self.into()
+        }
+        fn blue(self) -> String {
+            self.into()
+        }
+        fn cyan(self) -> String {
+            self.into()
+        }
+        fn bright_yellow(self) -> String {
+            self.into()
+        }
+        fn bright_blue(self) -> String {
+            self.into()
+        }
+        fn bold(self) -> String {
+            self.into()
+        }
+    }
+
+    impl Colorize for &str {}
+    impl Colorize for String {}
+}
+
+/// How results are printed on stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored text, with the bundle itself on stdout
+    Human,
+    /// A single JSON object, with the bundle as its `code` field
+    Json,
+}
+
+/// Commands run instead of bundling
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Create a Cargo project ready to be bundled: a library with the game logic,
+    /// a binary using it, a `cg-bundler.toml` writing `bundle.rs` and a VS Code
+    /// task running watch mode
+    Init {
+        /// Directory of the project, created if needed (defaults to the current
+        /// directory)
+        #[arg(value_name = "PATH")]
+        path: Option<PathBuf>,
+
+        /// Package name (defaults to the name of the directory)
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+
+        /// Layout of the project: `bot` for multiplayer games and contests, with a
+        /// strategy module, or `puzzle` for a solver reading stdin once
+        #[arg(
+            long,
+            value_name = "TEMPLATE",
+            default_value = "bot",
+            help = "Project layout (bot or puzzle)"
+        )]
+        template: ProjectTemplate,
+    },
+    /// Bundle the project and play it in the `CodinGame` IDE of a puzzle or contest,
+    /// saving the copy and paste; the account is read from the `CODINGAME_TOKEN`
+    /// (the `rememberMe` cookie of a logged-in browser) and `CODINGAME_USER_ID`
+    /// environment variables. Needs the `codingame-api` feature
+    Submit {
+        /// Project to bundle with its configuration file (defaults to the current
+        /// directory)
+        #[arg(value_name = "PATH")]
+        path: Option<PathBuf>,
+
+        /// Puzzle or contest, by the id in its URL, e.g. `spring-challenge-2024`
+        #[arg(long, value_name = "ID")]
+        puzzle: String,
+
+        /// Upload this bundle instead of bundling the project
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+
+        /// Play test case N of a solo puzzle instead of a game against the
+        /// default opponent
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        test: Option<u64>,
+    },
+    /// Manage the git hooks of the repository of a project
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
+    /// Check that an existing bundle still corresponds to the current sources and
+    /// list the source files changed since it was produced (instead of bundling);
+    /// give the project and the flags the bundle was produced with before the
+    /// command, e.g. `cg-bundler --pretty verify-output bundle.rs`
+    VerifyOutput {
+        /// Bundle to check
+        #[arg(value_name = "BUNDLE")]
+        bundle: PathBuf,
+    },
+    /// Bundle the project several times and print the minimum, average and maximum
+    /// time of each phase and the peak memory use (instead of bundling); give the
+    /// project and the bundling flags, e.g. `--jobs 4`, before the command
+    Bench {
+        /// Number of runs
+        #[arg(value_name = "RUNS", default_value_t = 10)]
+        runs: usize,
+    },
+    /// Split a bundle back into a module tree, writing each inline module to its
+    /// own file under `PATH/src` (instead of bundling); existing files are not
+    /// overwritten
+    Unbundle {
+        /// Bundle to split
+        #[arg(value_name = "BUNDLE")]
+        bundle: PathBuf,
+
+        /// Project receiving the module files (defaults to the current directory)
+        #[arg(value_name = "PATH")]
+        path: Option<PathBuf>,
+    },
+}
+
+/// Actions of `cg-bundler hook`
+#[derive(Subcommand, Debug, Clone)]
+pub enum HookAction {
+    /// Write a git pre-commit hook rebundling the project and staging the bundle,
+    /// so that committed bundles are never stale; installing again replaces it
+    Install {
+        /// Project to bundle with its configuration file (defaults to the current
+        /// directory)
+        #[arg(value_name = "PATH")]
+        path: Option<PathBuf>,
+
+        /// Bundle kept up to date, relative to the project (defaults to the output
+        /// of the configuration file, or bundle.rs)
+        #[arg(long, value_name = "FILE", conflicts_with = "profile")]
+        output_path: Option<PathBuf>,
+
+        /// Bundle with this profile of the configuration file, into its output
+        /// (repeatable)
+        #[arg(long, value_name = "NAME")]
+        profile: Vec<String>,
+
+        /// Fail the commit if a bundle is stale instead of updating it
+        #[arg(long)]
+        check: bool,
+
+        /// Add to an existing pre-commit hook not written by cg-bundler
+        #[arg(long)]
+        append: bool,
+    },
+}
+
+/// Parse an `--output-template` value
+fn parse_template(template: &str) -> Result<Template, String> {
+    Template::parse(template).map_err(|e| e.to_string())
+}
+
+/// Output styles, as named by `Cli::output_profile`
+const OUTPUT_PROFILES: [&str; 4] = ["m2", "minify", "pretty", "default"];
+
+/// Parse a `--crate-name` or `--lib-module-name` value, normalizing `-` to `_` like Cargo does
+fn parse_crate_name(name: &str) -> Result<String, String> {
+    let name = name.replace('-', "_");
+    syn::parse_str::<syn::Ident>(&name)
+        .map(|_| name)
+        .map_err(|_| "must be a valid Rust identifier".to_string())
+}
+
+/// Parse a `--vendor-dep NAME[=PATH]` value
+fn parse_vendor_dep(value: &str) -> Result<(String, Option<PathBuf>), String> {
+    let (name, source) = match value.split_once('=') {
+        Some((name, source)) => (name, Some(PathBuf::from(source))),
+        None => (value, None),
+    };
+    Ok((parse_crate_name(name)?, source))
+}
+
+/// Parse an `--enable-module PATH=FEATURE` value
+fn parse_enabled_module(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((path, feature)) if !path.is_empty() && !feature.is_empty() => {
+            Ok((path.to_string(), feature.to_string()))
+        }
+        _ => Err(format!("expected PATH=FEATURE, got '{value}'")),
+    }
+}
+
+/// Parse a `--define NAME=VALUE` value; NAME must be an identifier
+fn parse_define(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value))
+            if syn::parse_str::<syn::Ident>(name.trim()).is_ok() && !value.trim().is_empty() =>
+        {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("expected NAME=VALUE, got '{value}'")),
+    }
+}
+
+/// Parse a `--profile NAME[:SETTINGS]` value, with SETTINGS as
+/// `BundlerConfig::from_settings` reads them
+fn parse_profile(value: &str) -> Result<(String, Option<BundlerConfig>), String> {
+    let (name, settings) = match value.split_once(':') {
+        Some((name, settings)) => (name, Some(settings)),
+        None => (value, None),
+    };
+    if name.is_empty() {
+        return Err(format!("expected NAME or NAME:SETTINGS, got '{value}'"));
+    }
+    let settings = settings
+        .map(BundlerConfig::from_settings)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    Ok((name.to_string(), settings))
+}
+
+/// Parse a `--jobs` value, a number of threads of at least 1
+fn parse_jobs(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("expected at least 1 job".to_string()),
+        Ok(jobs) => Ok(jobs),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse an `--exclude-module`/`--only-module` pattern: `::`-separated module
+/// names, which may contain `*`
+fn parse_module_pattern(value: &str) -> Result<String, String> {
+    let valid = value.split("::").all(|segment| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '*')
+    });
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "expected a module path such as `ai::*`, got '{value}'"
+        ))
+    }
+}
+
+/// Display bug report information to the user, with a link to an issue
+/// pre-filled with `error`
+fn display_bug_report_info(error: &BundlerError) {
+    eprintln!();
+    eprintln!("{}", "━".repeat(60).bright_yellow());
+    eprintln!("{}", "💡 Need help or found a bug?".bright_yellow().bold());
+    eprintln!();
+    eprintln!(
+        "{}",
+        "  Please report issues, request features, or get support at:".yellow()
+    );
+    eprintln!(
+        "{}",
+        format!(
+            "  🔗 {}",
+            crash_report::issue_url(&format!("Error: {error}"))
+        )
+        .blue()
+        .bold()
+    );
+    eprintln!();
+    eprintln!(
+        "{}",
+        "  Your feedback helps improve CG-Bundler for everyone!".yellow()
+    );
+    eprintln!("{}", "━".repeat(60).bright_yellow());
+}
+
+/// A Rust code bundler that combines multiple source files into a single file
+#[derive(Parser, Debug, Clone)]
+#[command(name = "cg-bundler")]
+#[command(about = "Bundle Rust projects into single files")]
+#[command(version = env!("CARGO_PKG_VERSION"))]
+#[command(author = "CG Bundler Contributors")]
+#[command(
+    long_about = "A Rust code bundler that combines multiple source files into a single file.\nBy default, bundles the current directory or the specified project path.\n\n🐛 Found a bug or need help?\n   Report issues: https://github.com/MathieuSoysal/CG-Bundler/issues/new\n\n📖 Documentation:\n   https://docs.rs/cg-bundler"
+)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to the Cargo project directory (defaults to current directory)
+    #[arg(
+        value_name = "PROJECT_PATH",
+        help = "Path to bundle (defaults to current directory)"
+    )]
+    pub project_path: Option<PathBuf>,
+
+    /// Output file path (stdout if not specified)
+    #[arg(short, long, value_name = "FILE", help = "Output file path")]
+    pub output: Option<PathBuf>,
+
+    /// After bundling, type-check the bundle with `rustc` as a standalone crate and
+    /// report compiler errors with the source files they come from
+    #[arg(
+        long,
+        conflicts_with = "all_bins",
+        help = "Compile-check the bundle with rustc"
+    )]
+    pub check: bool,
+
+    /// Edition bundles are formatted with rustfmt and type-checked with rustc as,
+    /// instead of the `edition` of the package
+    #[arg(
+        long,
+        value_name = "EDITION",
+        value_parser = formatter::EDITIONS,
+        help = "Rust edition for rustfmt and rustc (default: the package's)"
+    )]
+    pub edition: Option<String>,
+
+    /// Before writing the bundle, fail if it uses APIs the judge forbids, e.g.
+    /// `std::fs`, `std::net`, `std::process` and threads for `codingame`, listing
+    /// each use with its item and source file
+    #[arg(
+        long,
+        value_name = "PROFILE",
+        conflicts_with = "all_bins",
+        help = "Fail on APIs forbidden by PROFILE (codingame)"
+    )]
+    pub lint: Option<LintProfile>,
+
+    /// Replace only the code below the generated-code sentinel comment of the output
+    /// file, keeping the hand-written header above it
+    #[arg(
+        long,
+        conflicts_with = "all_bins",
+        help = "Keep the header of the output file above the generated-code sentinel"
+    )]
+    pub update_in_place: bool,
+
+    /// Fail when the bundle has more than N characters (`CodinGame` accepts 100000);
+    /// without it, bundles over the `CodinGame` limit only produce a warning
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Fail if the bundle has more than N characters"
+    )]
+    pub max_size: Option<usize>,
+
+    /// Fit the bundle in N characters by applying, until it fits: doc removal,
+    /// comment removal, whitespace minification and identifier shortening; the
+    /// stages applied are reported and the bundle is not formatted with rustfmt
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["extract_demos", "all_bins"],
+        help = "Minify progressively until the bundle has at most N characters"
+    )]
+    pub target_size: Option<usize>,
+
+    /// Split the written bundle into files of at most N bytes for judges limiting
+    /// each file: the modules move to `part1.rs`, `part2.rs`, ... next to the
+    /// output, which includes them with `include!`
+    #[arg(
+        long,
+        value_name = "N_BYTES",
+        conflicts_with_all = ["all_bins", "check_output", "update_in_place"],
+        help = "Split the output into files of at most N_BYTES joined with include!"
+    )]
+    pub split_output: Option<usize>,
+
+    /// Output file name with variables: {crate}, {bin}, {profile} (the `--profile`
+    /// name, else m2, minify, pretty or default), {hash} (git short hash), {date},
+    /// {time}, {timestamp} (UTC), {size} (bytes) and the crate and bundler versions
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = parse_template,
+        conflicts_with_all = ["output", "update_in_place", "all_bins"],
+        help = "Output file name template, e.g. \"{crate}-{profile}-{date}.rs\""
+    )]
+    pub output_template: Option<Template>,
+
+    /// Put a comment block with the crate name and version, commit hash, build
+    /// time and bundler version at the top of the bundle
+    #[arg(long, help = "Start the bundle with a metadata and build info comment")]
+    pub header: bool,
+
+    /// Leave out the header comment block even if the configuration file asks for it
+    #[arg(
+        long,
+        overrides_with = "header",
+        help = "Leave out the header, over the configuration file"
+    )]
+    pub no_header: bool,
+
+    /// Header text replacing the default one of --header, with the variables of
+    /// --output-template; each line becomes a `//` comment
+    #[arg(
+        long,
+        value_name = "TEXT",
+        value_parser = parse_template,
+        help = "Header text with variables, e.g. \"{crate} v{crate_version} ({hash})\""
+    )]
+    pub header_template: Option<Template>,
+
+    /// Text appended to the bundle, with the variables of --output-template
+    #[arg(
+        long,
+        value_name = "TEXT",
+        value_parser = parse_template,
+        conflicts_with = "all_bins",
+        help = "Append a footer to the bundle, e.g. \"// built {date} {time} ({hash})\""
+    )]
+    pub footer: Option<Template>,
+
+    /// File whose content is appended to the bundle, with the variables of --output-template
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["footer", "all_bins"],
+        help = "Append the content of a file to the bundle as a footer"
+    )]
+    pub footer_file: Option<PathBuf>,
+
+    /// Point a symlink (default: `latest.rs` next to the bundle) at the written bundle
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        conflicts_with = "all_bins",
+        help = "Maintain a symlink to the newest bundle"
+    )]
+    pub latest_symlink: Option<Option<PathBuf>>,
+
+    /// Binary target to bundle in packages with several `[[bin]]` targets
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with = "all_bins",
+        help = "Binary target to bundle"
+    )]
+    pub bin: Option<String>,
+
+    /// Example target (`examples/NAME.rs`, `examples/NAME/main.rs` or an
+    /// `[[example]]`) to bundle instead of the binary, as Cargo's `--example` selects
+    #[arg(
+        long,
+        visible_alias = "example",
+        value_name = "NAME",
+        conflicts_with_all = ["bin", "all_bins"],
+        help = "Bundle the example target NAME instead of the binary"
+    )]
+    pub include_examples: Option<String>,
+
+    /// Integration test target (`tests/NAME.rs` or a `[[test]]`) to bundle instead
+    /// of the binary, as Cargo's `--test` selects, with a `fn main` calling its
+    /// `#[test]` functions in order (unless it has one, with `harness = false`)
+    #[arg(
+        long = "test",
+        value_name = "NAME",
+        conflicts_with_all = ["bin", "include_examples", "all_bins"],
+        help = "Bundle the test target NAME, with a main calling its tests"
+    )]
+    pub test_target: Option<String>,
+
+    /// Bundle the library target on its own, without a `fn main`, e.g. to embed a
+    /// single-file version of the library
+    #[arg(
+        long,
+        conflicts_with_all = ["bin", "include_examples", "test_target", "all_bins"],
+        help = "Bundle the library target on its own"
+    )]
+    pub lib: bool,
+
+    /// Bundle `PROJECT_PATH`, a crate root file or a directory with `main.rs` or
+    /// `src/main.rs`, without a `Cargo.toml` or `cargo metadata`; `-` reads the
+    /// crate root from stdin, with its module files in the current directory
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "bin", "include_examples", "test_target", "lib", "vendor_dep", "run_build_script", "out_dir_hint", "check",
+            "lint", "output_template", "header", "header_template", "footer", "footer_file", "attest",
+            "extract_demos", "target_size",
+            "all_bins", "validate", "info", "dry_run", "size_report",
+            "determinism_check", "stats", "tree",
+            "emit_graph", "analyze_duplicates", "watch",
+        ],
+        help = "Bundle a crate root without a Cargo project (- for stdin)"
+    )]
+    pub no_manifest: bool,
+
+    /// Read an already bundled file from stdin and apply the single-file
+    /// transforms (minify, docs, tests, mangle, ...) to it, without a project or
+    /// configuration file
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "project_path", "no_manifest", "profile",
+            "bin", "include_examples", "test_target", "lib", "vendor_dep", "run_build_script", "out_dir_hint", "check",
+            "lint", "output_template", "header", "header_template", "footer", "footer_file", "attest",
+            "extract_demos", "target_size",
+            "all_bins", "validate", "info", "dry_run", "size_report",
+            "determinism_check", "stats", "tree",
+            "emit_graph", "analyze_duplicates", "watch",
+        ],
+        help = "Transform a bundled file read from stdin instead of bundling a project"
+    )]
+    pub filter: bool,
+
+    /// Crate name matched in `extern crate`/`use` statements instead of the Cargo.toml name
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = parse_crate_name,
+        help = "Override the crate name used to detect library imports"
+    )]
+    pub crate_name: Option<String>,
+
+    /// Embed the library in `pub mod NAME` instead of splicing it into the bundle root
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = parse_crate_name,
+        help = "Embed the library in a module with this name"
+    )]
+    pub lib_module_name: Option<String>,
+
+    /// Inline an external crate as `pub mod NAME` and point `NAME::...` paths at it;
+    /// its source is PATH (its `lib.rs` or directory) or the dependency's checkout
+    #[arg(
+        long,
+        value_name = "NAME[=PATH]",
+        value_parser = parse_vendor_dep,
+        help = "Inline an external crate from PATH or the Cargo registry (repeatable)"
+    )]
+    pub vendor_dep: Vec<(String, Option<PathBuf>)>,
+
+    /// Bundle the module at PATH from the bundle root (e.g. `ai::cover_strategy`),
+    /// gated by `#[cfg(feature = "FEATURE")]`; `cfg(feature)` conditions are then
+    /// resolved as if only the features of the enabled modules were enabled
+    #[arg(
+        long,
+        value_name = "PATH=FEATURE",
+        value_parser = parse_enabled_module,
+        help = "Enable a feature-gated module (repeatable)"
+    )]
+    pub enable_module: Vec<(String, String)>,
+
+    /// Cargo features to bundle with, separated by commas or spaces; `cfg(feature)`
+    /// conditions are resolved against the package's `[features]` as `cargo build`
+    /// would
+    #[arg(
+        short = 'F',
+        long,
+        value_name = "FEATURES",
+        conflicts_with_all = ["no_manifest", "filter"],
+        help = "Bundle with these Cargo features enabled (repeatable)"
+    )]
+    pub features: Vec<String>,
+
+    /// Leave the `default` feature off when resolving `cfg(feature)` conditions
+    #[arg(
+        long,
+        conflicts_with_all = ["no_manifest", "filter"],
+        help = "Bundle without the default Cargo features"
+    )]
+    pub no_default_features: bool,
+
+    /// Enable every feature of the package when resolving `cfg(feature)` conditions
+    #[arg(
+        long,
+        conflicts_with_all = ["no_manifest", "filter"],
+        help = "Bundle with all Cargo features enabled"
+    )]
+    pub all_features: bool,
+
+    /// Leave the modules matching PATTERN out of the bundle with their subtrees;
+    /// PATTERN is a module path from the bundle root whose segments may contain `*`
+    /// or be `**` (any number of modules), e.g. `debug`, `bin_helpers::*` or
+    /// `**::fixtures`
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        value_parser = parse_module_pattern,
+        help = "Leave matching modules out of the bundle (repeatable)"
+    )]
+    pub exclude_module: Vec<String>,
+
+    /// Bundle only the modules matching PATTERN (see --exclude-module), with their
+    /// ancestors and descendants
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        value_parser = parse_module_pattern,
+        help = "Bundle only matching modules (repeatable)"
+    )]
+    pub only_module: Vec<String>,
+
+    /// Give the const NAME the value VALUE, a Rust expression, and remove the `if`
+    /// branches that boolean values make unreachable, e.g. `--define DEBUG=false`
+    /// to leave debug logging out of the bundle
+    #[arg(
+        long,
+        value_name = "NAME=VALUE",
+        value_parser = parse_define,
+        help = "Set a const and fold the branches it decides (repeatable)"
+    )]
+    pub define: Vec<(String, String)>,
+
+    /// Run the build script with `cargo check` to inline files it generates in
+    /// `OUT_DIR`; by default simple build scripts are reproduced without running them
+    #[arg(long, help = "Run build.rs to resolve include!s of OUT_DIR files")]
+    pub run_build_script: bool,
+
+    /// Resolve `env!("OUT_DIR")` from a previous build: a target directory (the
+    /// package's newest build script output in it is used) or the `OUT_DIR` itself
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "run_build_script",
+        help = "Target directory or OUT_DIR of a previous build"
+    )]
+    pub out_dir_hint: Option<PathBuf>,
+
+    /// Keep test code in the bundled output
+    #[arg(long, help = "Keep test code in the bundled output")]
+    pub keep_tests: bool,
+
+    /// Remove test code even if the configuration file keeps it
+    #[arg(
+        long,
+        overrides_with = "keep_tests",
+        help = "Remove test code, over the configuration file"
+    )]
+    pub no_keep_tests: bool,
+
+    /// Keep test code, gated behind `#[cfg(NAME)]` instead of `#[cfg(test)]`, so the
+    /// bundle runs its tests with `--cfg NAME` and drops them on submission
+    #[arg(
+        long,
+        value_name = "NAME",
+        num_args = 0..=1,
+        default_missing_value = "local_tests",
+        value_parser = parse_crate_name,
+        help = "Keep tests behind #[cfg(NAME)] (default local_tests)"
+    )]
+    pub tests_under_cfg: Option<String>,
+
+    /// Keep documentation comments in the bundled output
+    #[arg(long, help = "Keep documentation comments")]
+    pub keep_docs: bool,
+
+    /// Remove documentation comments even if the configuration file keeps them
+    #[arg(
+        long,
+        overrides_with = "keep_docs",
+        help = "Remove doc comments, over the configuration file"
+    )]
+    pub no_keep_docs: bool,
+
+    /// Turn documentation comments into plain `//` comments instead of removing them
+    #[arg(
+        long,
+        conflicts_with = "keep_docs",
+        help = "Convert doc comments to plain comments"
+    )]
+    pub plain_docs: bool,
+
+    /// Disable module expansion (keep module declarations)
+    #[arg(long, help = "Disable module expansion")]
+    pub no_expand_modules: bool,
+
+    /// Expand modules even if the configuration file disables it
+    #[arg(
+        long,
+        overrides_with = "no_expand_modules",
+        help = "Expand modules, over the configuration file"
+    )]
+    pub expand_modules: bool,
+
+    /// Pretty print the output (format with rustfmt if available)
+    #[arg(long, help = "Pretty print the output")]
+    pub pretty: bool,
+
+    /// Do not pretty print even if the configuration file asks for it
+    #[arg(
+        long,
+        overrides_with = "pretty",
+        help = "Do not pretty print, over the configuration file"
+    )]
+    pub no_pretty: bool,
+
+    /// Minify the output to a single line
+    #[arg(short, long, help = "Minify the output")]
+    pub minify: bool,
+
+    /// Do not minify, aggressively or not, even if the configuration file asks for it
+    #[arg(
+        long,
+        overrides_with = "minify",
+        help = "Do not minify, over the configuration file"
+    )]
+    pub no_minify: bool,
+
+    /// Aggressive minify emitting only the whitespace Rust requires (implies -m)
+    #[arg(long, help = "Aggressive minify")]
+    pub m2: bool,
+
+    /// Do not minify aggressively even if the configuration file asks for it
+    #[arg(
+        long,
+        overrides_with = "m2",
+        help = "Do not minify aggressively, over the configuration file"
+    )]
+    pub no_m2: bool,
+
+    /// Remove functions, types, impls and modules not reachable from `fn main`
+    #[arg(long, help = "Remove code not reachable from fn main")]
+    pub strip_unused: bool,
+
+    /// Keep unreachable code even if the configuration file strips it
+    #[arg(
+        long,
+        overrides_with = "strip_unused",
+        help = "Keep unreachable code, over the configuration file"
+    )]
+    pub no_strip_unused: bool,
+
+    /// Rename local variables, private free functions and private struct fields to
+    /// the shortest names free in the bundle; names that could also mean something
+    /// else (a method, an import, a field printed by `Debug`) are kept
+    #[arg(
+        long,
+        help = "Rename private functions, fields and locals to short names"
+    )]
+    pub mangle: bool,
+
+    /// Keep names even if the configuration file mangles them
+    #[arg(
+        long,
+        overrides_with = "mangle",
+        help = "Do not rename to short names, over the configuration file"
+    )]
+    pub no_mangle: bool,
+
+    /// Merge the `use` declarations of each module, removing duplicate and
+    /// conflicting imports
+    #[arg(long, help = "Merge and deduplicate use declarations")]
+    pub merge_imports: bool,
+
+    /// Keep `use` declarations as written even if the configuration file merges them
+    #[arg(
+        long,
+        overrides_with = "merge_imports",
+        help = "Do not merge use declarations, over the configuration file"
+    )]
+    pub no_merge_imports: bool,
+
+    /// Rename private items that collide with an item of the same name from another
+    /// file after expansion, instead of failing
+    #[arg(long, help = "Rename colliding private items instead of failing")]
+    pub rename_collisions: bool,
+
+    /// Fail on colliding items even if the configuration file renames them
+    #[arg(
+        long,
+        overrides_with = "rename_collisions",
+        help = "Fail on colliding items, over the configuration file"
+    )]
+    pub no_rename_collisions: bool,
+
+    /// Expand `macro_rules!` invocations that declare modules, e.g.
+    /// `decl_strategies! { alpha, beta }` expanding to `mod alpha; mod beta;`, and
+    /// bundle those modules
+    #[arg(long, help = "Expand macro invocations that declare modules")]
+    pub expand_macros: bool,
+
+    /// Leave module-declaring macros alone even if the configuration file expands them
+    #[arg(
+        long,
+        overrides_with = "expand_macros",
+        help = "Do not expand macros, over the configuration file"
+    )]
+    pub no_expand_macros: bool,
+
+    /// Keep the plain `//` and `/* */` comments of the sources, e.g.
+    /// `// TODO tune this constant`; comments right before a closing brace, an
+    /// `else` or inside a `use` group are still dropped
+    #[arg(long, help = "Keep plain comments in the bundle")]
+    pub keep_comments: bool,
+
+    /// Remove plain comments even if the configuration file keeps them
+    #[arg(
+        long,
+        overrides_with = "keep_comments",
+        help = "Remove plain comments, over the configuration file"
+    )]
+    pub no_keep_comments: bool,
+
+    /// Emit the items no transform changed with the formatting of their source
+    /// files instead of re-printing them; ignored when minifying. Their plain
+    /// comments are kept only with --keep-comments
+    #[arg(long, help = "Keep the source formatting of unchanged items")]
+    pub preserve_formatting: bool,
+
+    /// Re-print every item even if the configuration file preserves formatting
+    #[arg(
+        long,
+        overrides_with = "preserve_formatting",
+        help = "Re-print every item, over the configuration file"
+    )]
+    pub no_preserve_formatting: bool,
+
+    /// Check that minified output re-parses to the same tokens before writing it
+    #[arg(
+        long,
+        help = "Verify that minified output round-trips through the parser"
+    )]
+    pub verify_minify: bool,
+
+    /// Skip the minify round-trip check even if the configuration file asks for it
+    #[arg(
+        long,
+        overrides_with = "verify_minify",
+        help = "Skip the minify check, over the configuration file"
+    )]
+    pub no_verify_minify: bool,
+
+    /// Break lines longer than N characters after `,`, `;` and braces or at spaces,
+    /// for judges limiting line length; literals and comments are never split
+    #[arg(long, value_name = "N", help = "Wrap lines longer than N characters")]
+    pub max_line_len: Option<usize>,
+
+    /// Mark module expansion boundaries with `// file: <path>` comments
+    #[arg(long, help = "Insert // file: comments at module boundaries")]
+    pub line_markers: bool,
+
+    /// Leave out `// file:` comments even if the configuration file asks for them
+    #[arg(
+        long,
+        overrides_with = "line_markers",
+        help = "Leave out // file: comments, over the configuration file"
+    )]
+    pub no_line_markers: bool,
+
+    /// Write a JSON map of output line ranges to source files (implies --line-markers)
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write a JSON source map of the output"
+    )]
+    pub source_map: Option<PathBuf>,
+
+    /// Write an SLSA-style provenance attestation (input and output hashes, bundler
+    /// version, configuration fingerprint, timestamps) of the bundle to FILE
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "all_bins",
+        help = "Write a provenance attestation (JSON) of the bundle"
+    )]
+    pub attest: Option<PathBuf>,
+
+    /// Reuse the bundle of a previous run, kept under the Cargo target directory,
+    /// while the source files and every setting, the configuration file included,
+    /// are unchanged
+    #[arg(
+        long,
+        conflicts_with = "all_bins",
+        help = "Reuse the cached bundle when the sources and settings are unchanged"
+    )]
+    pub cache: bool,
+
+    /// Print why the cached bundle was or was not reused (implies --cache)
+    #[arg(
+        long,
+        conflicts_with = "all_bins",
+        help = "Explain why the cached bundle was or was not reused (implies --cache)"
+    )]
+    pub explain_cache: bool,
+
+    /// Move `#[cfg(test)]` `demo*` functions into runnable example files in DIR
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with = "all_bins",
+        help = "Extract #[cfg(test)] demo functions into example files"
+    )]
+    pub extract_demos: Option<PathBuf>,
+
+    /// Bundle every `[[bin]]` target into its own file in `--out-dir`
+    #[arg(
+        long,
+        conflicts_with_all = ["output", "source_map", "dry_run", "watch"],
+        help = "Bundle every binary target into --out-dir"
+    )]
+    pub all_bins: bool,
+
+    /// Directory receiving one `<bin>.rs` file per binary target (default: `<PROJECT>/bundles`)
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "all_bins",
+        help = "Output directory for --all-bins"
+    )]
+    pub out_dir: Option<PathBuf>,
+
+    /// Bundle once per profile, a named set of settings from the `[profiles]` of
+    /// the configuration file, each into its own output; the sources are read
+    /// once for all of them. `NAME:SETTINGS` defines or extends a profile with
+    /// comma-separated settings, e.g. `submit:m2,output=bundle.submit.rs`
+    #[arg(
+        long,
+        value_name = "NAME[:SETTINGS]",
+        value_parser = parse_profile,
+        conflicts_with_all = [
+            "output", "output_template", "check_output", "latest_symlink", "source_map", "attest",
+            "extract_demos", "target_size", "all_bins", "watch",
+        ],
+        help = "Bundle with each named profile into its own output (repeatable)"
+    )]
+    pub profile: Vec<(String, Option<BundlerConfig>)>,
+
+    /// Read the module files with N threads before bundling, preparing them for
+    /// parsing when comments are kept; every file is still parsed, and the bundle
+    /// transformed, on one thread
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = parse_jobs,
+        help = "Read module files with N threads (default 1)"
+    )]
+    pub jobs: Option<usize>,
+
+    /// Verbose output
+    #[arg(short, long, help = "Verbose output")]
+    pub verbose: bool,
+
+    /// Print results as colored text or as one JSON object on stdout, for editors
+    /// and other tools; supported when bundling, with `--validate` and with `--info`
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Human,
+        conflicts_with_all = [
+            "watch", "all_bins", "dry_run", "size_report", "determinism_check", "check_output",
+        ],
+        help = "Output format of results"
+    )]
+    pub format: OutputFormat,
+
+    /// Validate that the project can be bundled without errors (instead of bundling)
+    #[arg(long, help = "Validate that the project can be bundled without errors")]
+    pub validate: bool,
+
+    /// Show information about the Cargo project structure (instead of bundling)
+    #[arg(long, help = "Show information about the Cargo project structure")]
+    pub info: bool,
+
+    /// Print the planned bundling actions without bundling
+    #[arg(long, help = "Show what would be bundled without bundling")]
+    pub dry_run: bool,
+
+    /// Print the bundle size and the characters each source file contributes
+    /// (instead of bundling); sizes are measured before `--pretty` formatting
+    #[arg(
+        long,
+        conflicts_with = "all_bins",
+        help = "Show the bundle size and a per-file breakdown"
+    )]
+    pub size_report: bool,
+
+    /// Grade the bundle on its size against the budget, estimated dead code,
+    /// dependencies the judge lacks and macro use, with recommendations (instead
+    /// of bundling)
+    #[arg(
+        long,
+        conflicts_with_all = ["all_bins", "watch"],
+        help = "Show a health score of the bundle with recommendations"
+    )]
+    pub stats: bool,
+
+    /// Print the module tree of the bundle with the file, size and items of each
+    /// module (instead of bundling)
+    #[arg(
+        long,
+        conflicts_with_all = ["all_bins", "watch"],
+        help = "Show the module tree of the bundle"
+    )]
+    pub tree: bool,
+
+    /// Print the module graph of the bundle in FORMAT, `dot` for Graphviz or
+    /// `mermaid`: the modules with their file and size, the modules each
+    /// declares and the items it uses from the others (instead of bundling)
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        conflicts_with_all = ["all_bins", "watch", "tree"],
+        help = "Print the module dependency graph of the bundle (dot or mermaid)"
+    )]
+    pub emit_graph: Option<GraphFormat>,
+
+    /// List the functions of the bundle that are copies of each other, or alike
+    /// but for names and literals, with the characters merging them would save
+    /// (instead of bundling)
+    #[arg(
+        long,
+        conflicts_with_all = ["all_bins", "watch"],
+        help = "Report duplicated functions and the characters they cost"
+    )]
+    pub analyze_duplicates: bool,
+
+    /// Bundle the project several times and fail if the outputs are not byte-identical
+    #[arg(
+        long,
+        value_name = "RUNS",
+        num_args = 0..=1,
+        default_missing_value = "5",
+        help = "Bundle RUNS times (default 5) and fail if outputs differ"
+    )]
+    pub determinism_check: Option<usize>,
+
+    /// Bundle in memory and compare the result with FILE, e.g. a committed bundle in
+    /// CI: print a unified diff and fail if they differ; FILE is never written
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "output", "output_template", "latest_symlink", "source_map", "attest", "extract_demos",
+            "all_bins", "watch",
+        ],
+        help = "Fail with a diff if FILE is not the bundle of the current sources"
+    )]
+    pub check_output: Option<PathBuf>,
+
+    /// Watch for file changes and rebuild automatically
+    #[arg(short, long, help = "Watch for file changes and rebuild automatically")]
+    pub watch: bool,
+
+    /// Source directory to watch (default: src)
+    #[arg(long, default_value = "src", help = "Source directory to watch")]
+    pub src_dir: String,
+
+    /// Debounce delay in milliseconds (default: 500)
+    #[arg(long, default_value = "500", help = "Debounce delay in milliseconds")]
+    pub debounce: u64,
+
+    /// Notify clients on localhost:PORT of each successful build in watch mode:
+    /// WebSocket clients (e.g. a browser sync script) get a JSON text message,
+    /// other TCP clients a JSON line, with the output path and size
+    #[arg(
+        long,
+        value_name = "PORT",
+        requires = "watch",
+        help = "Broadcast rebuild events on localhost:PORT (WebSocket or TCP lines)"
+    )]
+    pub notify_port: Option<u16>,
+
+    /// Show watch mode as a terminal dashboard: the last build, the bundle size
+    /// against the limit, build times and file events; `r` rebuilds, `m` toggles
+    /// minification and `q` quits. Needs an output file
+    #[arg(
+        long,
+        requires = "watch",
+        help = "Show watch mode as an interactive terminal dashboard"
+    )]
+    pub tui: bool,
+
+    /// Play each successful rebuild in the `CodinGame` IDE of the puzzle or contest
+    /// ID, with the account of `CODINGAME_TOKEN` and `CODINGAME_USER_ID` (see the
+    /// `submit` command); needs the `codingame-api` feature
+    #[arg(
+        long,
+        value_name = "ID",
+        requires = "watch",
+        conflicts_with = "tui",
+        help = "Play each successful rebuild in the CodinGame IDE of puzzle ID"
+    )]
+    pub submit_on_success: Option<String>,
+
+    /// Configuration file (defaults to cg-bundler.toml or [package.metadata.cg-bundler])
+    #[arg(long, value_name = "FILE", help = "Configuration file to use")]
+    pub config: Option<PathBuf>,
+
+    /// Settings loaded from the project configuration file
+    #[arg(skip)]
+    pub file_config: BundlerConfig,
+
+    /// Name of the `--profile` these flags were set up for (see `with_profile`)
+    #[arg(skip)]
+    pub profile_name: Option<String>,
+}
+
+impl Cli {
+    /// Get the effective project path, using current directory as default
+    #[must_use]
+    pub fn get_project_path(&self) -> PathBuf {
+        self.project_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// Check if verbose mode is enabled
+    #[must_use]
+    pub const fn is_verbose(&self) -> bool {
+        self.verbose
+    }
+
+    /// Check if results are printed as JSON
+    #[must_use]
+    pub const fn is_json(&self) -> bool {
+        matches!(self.format, OutputFormat::Json)
+    }
+
+    /// Load the project configuration file; flags given on the command line take precedence
+    ///
+    /// # Errors
+    /// Returns an error if the configuration file exists but is invalid
+    pub fn load_config_file(&mut self) -> Result<(), BundlerError> {
+        let project_path = self.config_dir();
+        self.file_config = match &self.config {
+            Some(path) => BundlerConfig::from_file(path)?,
+            None => BundlerConfig::load(&project_path)?,
+        };
+        self.apply_file_config(&project_path)
+    }
+
+    /// The configuration file read by `load_config_file`, if it read one
+    fn config_file(&self) -> Option<PathBuf> {
+        self.config.clone().or_else(|| {
+            Some(self.config_dir().join(crate::config::CONFIG_FILE_NAME))
+                .filter(|path| FileManager::file_exists(path))
+        })
+    }
+
+    /// Directory the configuration file is looked up in, and its paths are
+    /// relative to
+    fn config_dir(&self) -> PathBuf {
+        let project_path = self.get_project_path();
+        // A crate root given with `--no-manifest` is configured by its directory
+        if self.no_manifest && !project_path.is_dir() {
+            return project_path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        }
+        project_path
+    }
+
+    /// Take the settings of `file_config` that are not transform settings, for
+    /// those not given on the command line
+    fn apply_file_config(&mut self, project_path: &Path) -> Result<(), BundlerError> {
+        if self.output.is_none() && self.output_template.is_none() {
+            if let Some(template) = &self.file_config.output_template {
+                let template = project_path.join(template);
+                self.output_template = Some(Template::parse(&template.to_string_lossy())?);
+            } else {
+                self.output = self
+                    .file_config
+                    .output
+                    .as_ref()
+                    .map(|output| project_path.join(output));
+            }
+        }
+
+        if let Some(profile) = self
+            .file_config
+            .footers
+            .keys()
+            .find(|profile| !OUTPUT_PROFILES.contains(&profile.as_str()))
+        {
+            return Err(BundlerError::Config {
+                message: format!(
+                    "unknown output style '{profile}' in footers, expected one of {}",
+                    OUTPUT_PROFILES.join(", ")
+                ),
+                path: self.config.clone(),
+            });
+        }
+        if let Some(edition) = self
+            .file_config
+            .edition
+            .as_ref()
+            .filter(|edition| !formatter::EDITIONS.contains(&edition.as_str()))
+        {
+            return Err(BundlerError::Config {
+                message: format!(
+                    "unknown edition '{edition}', expected one of {}",
+                    formatter::EDITIONS.join(", ")
+                ),
+                path: self.config.clone(),
+            });
+        }
+        if self.header_template.is_none() {
+            if let Some(template) = &self.file_config.header_template {
+                self.header_template = Some(Template::parse(template)?);
+            }
+        }
+        self.header |= !self.no_header && self.file_config.header == Some(true);
+        if self.footer.is_none() && self.footer_file.is_none() {
+            let footer = self
+                .file_config
+                .footers
+                .get(self.output_profile())
+                .or(self.file_config.footer.as_ref());
+            if let Some(footer) = footer {
+                self.footer = Some(Template::parse(footer)?);
+            } else {
+                self.footer_file = self
+                    .file_config
+                    .footer_file
+                    .as_ref()
+                    .map(|footer_file| project_path.join(footer_file));
+            }
+        }
+        Ok(())
+    }
+
+    /// The command line `args`, as given before loading the configuration file,
+    /// set up with the configuration of `profile`: the profile of that name,
+    /// extended with the settings given with it
+    ///
+    /// # Errors
+    /// Returns an error if the profile is unknown or has no output file
+    fn with_profile(
+        &self,
+        args: &Self,
+        (name, settings): &(String, Option<BundlerConfig>),
+    ) -> Result<Self, BundlerError> {
+        let mut file_config = if settings.is_some() && !self.file_config.profiles.contains_key(name)
+        {
+            self.file_config.clone()
+        } else {
+            self.file_config.profile(name)?
+        };
+        if let Some(settings) = settings {
+            file_config = file_config.merged_with(settings);
+        }
+
+        let mut cli = args.clone();
+        cli.profile = Vec::new();
+        cli.profile_name = Some(name.clone());
+        cli.file_config = file_config;
+        cli.apply_file_config(&self.config_dir())?;
+        if cli.output.is_none() && cli.output_template.is_none() {
+            return Err(BundlerError::Config {
+                message: format!("profile '{name}' has no output or output-template"),
+                path: self.config.clone(),
+            });
+        }
+        Ok(cli)
+    }
+
+    /// Get transform configuration from the configuration file and the CLI flags
+    #[must_use]
+    pub fn get_transform_config(&self) -> TransformConfig {
+        let mut config = self.file_config.transform_config();
+        // The flags given on the command line take precedence over the file, the
+        // `--no-*` ones turning off what it enables
+        override_flag(
+            &mut config.remove_tests,
+            self.no_keep_tests,
+            self.keep_tests,
+        );
+        if let Some(name) = &self.tests_under_cfg {
+            config.remove_tests = false;
+            config.tests_cfg = Some(name.clone());
+        }
+        if self.keep_docs {
+            config.docs_mode = DocsMode::Keep;
+        }
+        if self.no_keep_docs {
+            config.docs_mode = DocsMode::Remove;
+        }
+        if self.plain_docs {
+            config.docs_mode = DocsMode::ConvertToPlain;
+        }
+        let line_markers = self.line_markers || self.source_map.is_some();
+        for (setting, on, off) in [
+            (
+                &mut config.expand_modules,
+                self.expand_modules,
+                self.no_expand_modules,
+            ),
+            (&mut config.minify, self.minify || self.m2, self.no_minify),
+            (
+                &mut config.aggressive_minify,
+                self.m2,
+                self.no_minify || self.no_m2,
+            ),
+            (
+                &mut config.strip_unused,
+                self.strip_unused,
+                self.no_strip_unused,
+            ),
+            (&mut config.mangle, self.mangle, self.no_mangle),
+            (
+                &mut config.merge_imports,
+                self.merge_imports,
+                self.no_merge_imports,
+            ),
+            (
+                &mut config.rename_collisions,
+                self.rename_collisions,
+                self.no_rename_collisions,
+            ),
+            (
+                &mut config.expand_macros,
+                self.expand_macros,
+                self.no_expand_macros,
+            ),
+            (
+                &mut config.keep_comments,
+                self.keep_comments,
+                self.no_keep_comments,
+            ),
+            (
+                &mut config.preserve_formatting,
+                self.preserve_formatting,
+                self.no_preserve_formatting,
+            ),
+            (&mut config.line_markers, line_markers, self.no_line_markers),
+            (
+                &mut config.verify_minify,
+                self.verify_minify,
+                self.no_verify_minify,
+            ),
+        ] {
+            override_flag(setting, on, off);
+        }
+        // A filtered bundle has its modules already
+        if self.filter {
+            config.expand_modules = false;
+        }
+        if self.extract_demos.is_some() {
+            config.extract_demos = true;
+        }
+        if let Some(max_line_len) = self.max_line_len {
+            config.max_line_len = Some(max_line_len);
+        }
+        if let Some(crate_name) = &self.crate_name {
+            config.crate_name = Some(crate_name.clone());
+        }
+        if let Some(lib_module_name) = &self.lib_module_name {
+            config.lib_module_name = Some(lib_module_name.clone());
+        }
+        config
+            .enabled_modules
+            .extend(self.enable_module.iter().cloned());
+        if !self.features.is_empty() || self.no_default_features || self.all_features {
+            self.select_features(config.features.get_or_insert_with(Default::default));
+        }
+        config
+            .excluded_modules
+            .extend(self.exclude_module.iter().cloned());
+        config.only_modules.extend(self.only_module.iter().cloned());
+        config.defines.extend(self.define.iter().cloned());
+        config
+    }
+
+    /// Add the features of `--features`, `--no-default-features` and
+    /// `--all-features` to `selection`
+    fn select_features(&self, selection: &mut FeatureSelection) {
+        selection.features.extend(
+            self.features
+                .iter()
+                .flat_map(|features| features.split([',', ' ']))
+                .filter(|feature| !feature.is_empty())
+                .map(str::to_string),
+        );
+        selection.no_default_features |= self.no_default_features;
+        selection.all_features |= self.all_features;
+    }
+
+    /// Create a bundler for the transform configuration and the selected binary target
+    #[must_use]
+    pub fn bundler(&self) -> Bundler {
+        let mut bundler = Bundler::with_config(self.get_transform_config())
+            .with_build_script_run(self.run_build_script)
+            .with_jobs(self.jobs());
+        if let Some(hint) = &self.out_dir_hint {
+            bundler = bundler.with_out_dir_hint(hint);
+        }
+        if let Some(edition) = self.edition() {
+            bundler = bundler.with_edition(edition);
+        }
+        for (name, source) in &self.vendor_dep {
+            bundler = bundler.with_vendored_dep(name, source.clone());
+        }
+        let header = match &self.header_template {
+            Some(template) => Some(template.clone()),
+            None if self.header => Template::parse(DEFAULT_HEADER).ok(),
+            None => None,
+        };
+        if let Some(template) = header {
+            bundler = bundler.with_header(template, self.template_profile());
+        }
+        match self.entry_target() {
+            EntryTarget::Default => bundler,
+            EntryTarget::Binary(name) => bundler.with_binary(name),
+            EntryTarget::Example(name) => bundler.with_example(name),
+            EntryTarget::Test(name) => bundler.with_test(name),
+            EntryTarget::Library => bundler.with_library_target(),
+        }
+    }
+
+    /// Target selected with `--bin`, `--include-examples`, `--test` or `--lib`
+    #[must_use]
+    pub fn entry_target(&self) -> EntryTarget {
+        if self.lib {
+            return EntryTarget::Library;
+        }
+        if let Some(name) = &self.test_target {
+            return EntryTarget::Test(name.clone());
+        }
+        match (&self.bin, &self.include_examples) {
+            (Some(name), _) => EntryTarget::Binary(name.clone()),
+            (None, Some(name)) => EntryTarget::Example(name.clone()),
+            (None, None) => EntryTarget::Default,
+        }
+    }
+
+    /// Edition from `--edition` or the configuration file, if set
+    #[must_use]
+    pub fn edition(&self) -> Option<String> {
+        self.edition
+            .clone()
+            .or_else(|| self.file_config.edition.clone())
+    }
+
+    /// Threads reading module files, from `--jobs` or the configuration file
+    #[must_use]
+    pub fn jobs(&self) -> usize {
+        self.jobs.or(self.file_config.jobs).unwrap_or(1).max(1)
+    }
+
+    /// Maximum bundle size from `--max-size` or the configuration file
+    #[must_use]
+    pub const fn max_size(&self) -> Option<usize> {
+        match self.max_size {
+            Some(limit) => Some(limit),
+            None => self.file_config.max_size,
+        }
+    }
+
+    /// Name of the output style (one of `OUTPUT_PROFILES`), selecting the footer
+    /// of the configuration file
+    #[must_use]
+    pub fn output_profile(&self) -> &'static str {
+        if self.is_aggressive_minify() {
+            "m2"
+        } else if self.is_minify() {
+            "minify"
+        } else if self.is_pretty() {
+            "pretty"
+        } else {
+            "default"
+        }
+    }
+
+    /// Substituted for `{profile}` in templates: the name of the `--profile`
+    /// being bundled, else the output style
+    #[must_use]
+    pub fn template_profile(&self) -> &str {
+        self.profile_name
+            .as_deref()
+            .unwrap_or_else(|| self.output_profile())
+    }
+
+    /// Get the output file path
+    #[must_use]
+    pub const fn get_output(&self) -> Option<&PathBuf> {
+        self.output.as_ref()
+    }
+
+    /// Check if pretty formatting is requested
+    #[must_use]
+    pub fn is_pretty(&self) -> bool {
+        self.pretty || (!self.no_pretty && self.file_config.pretty == Some(true))
+    }
+
+    /// Check if minification is requested
+    #[must_use]
+    pub fn is_minify(&self) -> bool {
+        self.get_transform_config().minify
+    }
+
+    /// Check if aggressive minification is requested
+    #[must_use]
+    pub fn is_aggressive_minify(&self) -> bool {
+        self.get_transform_config().aggressive_minify
+    }
+
+    /// The first flag given that selects another operation than a subcommand,
+    /// which clap cannot tell as flags may come before the subcommand
+    fn command_conflict(&self) -> Option<&'static str> {
+        [
+            (self.watch, "--watch"),
+            (self.all_bins, "--all-bins"),
+            (!self.profile.is_empty(), "--profile"),
+            (self.check_output.is_some(), "--check-output"),
+            (self.no_manifest, "--no-manifest"),
+            (self.filter, "--filter"),
+            (self.is_json(), "--format"),
+        ]
+        .into_iter()
+        .find_map(|(given, flag)| given.then_some(flag))
+    }
+}
+
+/// Turn `setting` on if the flag `on` was given, off if its `--no-*` flag `off`
+/// was; clap keeps only the last of the two
+fn override_flag(setting: &mut bool, on: bool, off: bool) {
+    if on {
+        *setting = true;
+    } else if off {
+        *setting = false;
+    }
+}
+
+/// Run the command described by `cli`, exiting with status 1 on error
+///
+/// This is the whole of the `cg-bundler` binary and of the `cargo cg-bundle`
+/// subcommand, which parse their arguments into `cli` first.
+pub fn run(mut cli: Cli) {
+    crash_report::install_panic_hook();
+    // The arguments as given, for the profiles to apply their settings over
+    let args = cli.clone();
+    if let Some(flag) = cli.command.as_ref().and_then(|_| cli.command_conflict()) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!("the argument '{flag}' cannot be used with a subcommand"),
+            )
+            .exit();
+    }
+    let loaded = match cli.command {
+        // `verify-output` and `bench` bundle the project, with its configuration
+        Some(Command::VerifyOutput { .. } | Command::Bench { .. }) => cli.load_config_file(),
+        // `init` writes a configuration file rather than reading one
+        Some(_) => Ok(()),
+        // A filter works on its input alone
+        None if cli.filter => Ok(()),
+        None => cli.load_config_file(),
+    };
+    crash_report::set_context(format!("{cli:#?}"));
+
+    // Handle the different operations based on flags
+    // `--validate` reports an invalid project configuration itself, together
+    // with every other problem it finds
+    let result = loaded
+        .or_else(|e| {
+            if cli.validate && cli.config.is_none() {
+                Ok(())
+            } else {
+                Err(e)
+            }
+        })
+        .and_then(|()| {
+            if let Some(Command::Init {
+                path,
+                name,
+                template,
+            }) = &cli.command
+            {
+                handle_init_command(path.as_deref(), name.as_deref(), *template)
+            } else if let Some(Command::Submit {
+                path,
+                puzzle,
+                file,
+                test,
+            }) = &cli.command
+            {
+                handle_submit_command(path.as_deref(), puzzle, file.as_deref(), *test)
+            } else if let Some(Command::Hook { action }) = &cli.command {
+                handle_hook_command(action)
+            } else if let Some(Command::VerifyOutput { bundle }) = &cli.command {
+                handle_verify_output_command(&cli, bundle)
+            } else if let Some(Command::Bench { runs }) = &cli.command {
+                handle_bench_command(&cli, *runs)
+            } else if let Some(Command::Unbundle { bundle, path }) = &cli.command {
+                let project_path = path.clone().unwrap_or_else(|| cli.get_project_path());
+                handle_unbundle_command(&project_path, bundle)
+            } else if cli.validate {
+                handle_validate_command(&cli)
+            } else if cli.info {
+                handle_info_command(&cli)
+            } else if cli.dry_run {
+                handle_dry_run_command(&cli)
+            } else if cli.size_report {
+                handle_size_report_command(&cli)
+            } else if cli.stats {
+                handle_stats_command(&cli)
+            } else if cli.tree {
+                handle_tree_command(&cli)
+            } else if let Some(format) = cli.emit_graph {
+                handle_emit_graph_command(&cli, format)
+            } else if cli.analyze_duplicates {
+                handle_duplicates_command(&cli)
+            } else if let Some(runs) = cli.determinism_check {
+                handle_determinism_check_command(&cli, runs)
+            } else if !cli.profile.is_empty() {
+                handle_profiles_command(&cli, &args)
+            } else if cli.all_bins {
+                handle_all_bins_command(&cli)
+            } else if cli.watch {
+                handle_watch_command(&cli)
+            } else {
+                // Default behavior: bundle the project
+                handle_bundle_command(&cli)
+            }
+        });
+
+    if let Err(e) = result {
+        // A failed validation already printed its report
+        if cli.is_json() && !matches!(e, BundlerError::ValidationFailed { .. }) {
+            print_json(&ErrorJson {
+                success: false,
+                error: e.to_string(),
+                diagnostic: e.diagnostic(),
+            });
+        }
+        eprintln!("{} {}", "Error:".red().bold(), e);
+        let diagnostic = e.diagnostic();
+        if let Some(location) = diagnostic.location().filter(|_| diagnostic.span.is_some()) {
+            eprintln!("  {} {location}", "-->".bright_blue());
+        }
+        display_bug_report_info(&e);
+        process::exit(1);
+    }
+}
+
+fn handle_init_command(
+    path: Option<&Path>,
+    name: Option<&str>,
+    template: ProjectTemplate,
+) -> Result<(), BundlerError> {
+    let dir = path.unwrap_or_else(|| Path::new("."));
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => std::env::current_dir()
+            .map_err(|e| BundlerError::Io {
+                source: e,
+                path: None,
+            })?
+            .join(dir)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| BundlerError::ProjectStructure {
+                message: format!("cannot name a package after {}, use --name", dir.display()),
+            })?,
+    };
+
+    let files = Scaffold::new(&name, template)?.create(dir)?;
+    println!(
+        "{}",
+        format!(
+            "✓ Created {} project '{name}' in {}",
+            template.name(),
+            dir.display()
+        )
+        .green()
+        .bold()
+    );
+    for file in &files {
+        println!("  {}", file.strip_prefix(dir).unwrap_or(file).display());
+    }
+    println!(
+        "\nRun `cg-bundler` in {} to write bundle.rs, or `cg-bundler --watch` to rebundle on every change",
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Bundle the project at `path` with its configuration file, or read the bundle
+/// `file`, and play it in the `CodinGame` IDE of `puzzle`
+fn handle_submit_command(
+    path: Option<&Path>,
+    puzzle: &str,
+    file: Option<&Path>,
+    test: Option<u64>,
+) -> Result<(), BundlerError> {
+    let code = if let Some(file) = file {
+        fs::read_to_string(file).map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(file.to_path_buf()),
+        })?
+    } else {
+        let mut args = vec![std::ffi::OsString::from("cg-bundler")];
+        args.extend(path.map(|path| path.as_os_str().to_os_string()));
+        let mut cli = Cli::parse_from(args);
+        cli.load_config_file()?;
+        let (code, report) = bundle_project(&cli, &cli.get_project_path())?;
+        let code = finish_output(&cli, code);
+        if let Some(warning) = check_output_size(&cli, code.chars().count())? {
+            print_warning(&warning);
+        }
+        print_bundle_warnings(&cli, &report);
+        code
+    };
+    let mode = test.map_or(PlayMode::Game, |index| {
+        PlayMode::Test(usize::try_from(index).unwrap_or(usize::MAX))
+    });
+    eprintln!("{} {puzzle}...", "Playing in the CodinGame IDE of".yellow());
+    let result = submit_code(puzzle, &code, mode)?;
+    println!(
+        "{}",
+        format!("✓ Played: {}", result.summary()).green().bold()
+    );
+    Ok(())
+}
+
+/// Install the git pre-commit hook `action` describes
+fn handle_hook_command(action: &HookAction) -> Result<(), BundlerError> {
+    let HookAction::Install {
+        path,
+        output_path,
+        profile,
+        check,
+        append,
+    } = action;
+    let project = path.clone().unwrap_or_else(|| PathBuf::from("."));
+    let config = BundlerConfig::load(&project)?;
+    let (root, hooks_dir) = hook::git_paths(&project)?;
+    let relative = FileManager::canonicalize(&project)
+        .ok()
+        .zip(FileManager::canonicalize(&root).ok())
+        .and_then(|(project, root)| project.strip_prefix(root).ok().map(Path::to_path_buf))
+        .ok_or_else(|| BundlerError::ProjectStructure {
+            message: format!(
+                "{} is not in the repository {}",
+                project.display(),
+                root.display()
+            ),
+        })?;
+
+    let hook = if profile.is_empty() {
+        let output = output_path
+            .clone()
+            .or_else(|| config.output.clone())
+            .unwrap_or_else(|| PathBuf::from("bundle.rs"));
+        PreCommitHook::new(&relative, &output)
+    } else {
+        let outputs = profile
+            .iter()
+            .map(|name| {
+                config
+                    .profile(name)?
+                    .output
+                    .ok_or_else(|| BundlerError::Config {
+                        message: format!(
+                            "profile '{name}' has no output file for the hook to keep up to date"
+                        ),
+                        path: None,
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        PreCommitHook::with_profiles(&relative, profile.clone(), outputs)
+    };
+    let mode = if *check {
+        HookMode::Check
+    } else {
+        HookMode::Regenerate
+    };
+    let installed = hook.with_mode(mode).install(&hooks_dir, *append)?;
+    let verb = match installed {
+        HookInstall::Created => "Installed",
+        HookInstall::Updated => "Updated",
+        HookInstall::Appended => "Added cg-bundler to",
+    };
+    println!(
+        "{}",
+        format!(
+            "✓ {verb} the pre-commit hook {}",
+            FileManager::normalize_path(&hooks_dir.join("pre-commit")).display()
+        )
+        .green()
+        .bold()
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "codingame-api"))]
+fn submit_code(_puzzle: &str, _code: &str, _mode: PlayMode) -> Result<PlayResult, BundlerError> {
+    Err(BundlerError::Submission {
+        message: "uploading is not compiled in (enable the `codingame-api` feature)".to_string(),
+    })
+}
+
+/// Upload `code` to the IDE of `puzzle` and play it
+#[cfg(feature = "codingame-api")]
+fn submit_code(puzzle: &str, code: &str, mode: PlayMode) -> Result<PlayResult, BundlerError> {
+    use crate::codingame::{CodinGameClient, Credentials};
+
+    CodinGameClient::new(Credentials::from_env()?).play(puzzle, code, mode)
+}
+
+fn handle_bundle_command(cli: &Cli) -> Result<(), BundlerError> {
+    bundle_and_write(cli)?;
+    Ok(())
+}
+
+/// Bundle as `handle_bundle_command` does, returning the output file (`None` for
+/// stdout) and the bundled code
+fn bundle_and_write(cli: &Cli) -> Result<(Option<PathBuf>, String), BundlerError> {
+    let started_on = SystemTime::now();
+    let timer = Instant::now();
+    let project_path = cli.get_project_path();
+    let transform_config = cli.get_transform_config();
+    let verbose = cli.is_verbose();
+
+    if verbose {
+        print_bundle_configuration(cli, &project_path, &transform_config);
+    }
+
+    let (bundled_code, report) = bundle_with_cache(cli, &project_path)?;
+    let bundled_code = append_footer(cli, bundled_code, started_on)?;
+    let bundled_code = if cli.update_in_place {
+        keep_output_header(cli, &bundled_code)?
+    } else {
+        bundled_code
+    };
+    let mut warnings: Vec<String> = check_output_size(cli, bundled_code.chars().count())?
+        .into_iter()
+        .collect();
+    if !cli.is_json() {
+        for warning in &warnings {
+            print_warning(warning);
+        }
+        print_bundle_warnings(cli, &report);
+    }
+    warnings.extend(report.warnings.iter().map(ToString::to_string));
+    if let Some(profile) = cli.lint {
+        lint_bundle(cli, profile)?;
+    }
+    if let Some(existing_path) = &cli.check_output {
+        check_output(cli, existing_path, &bundled_code)?;
+        return Ok((Some(existing_path.clone()), bundled_code));
+    }
+    let output_file = resolve_output(cli, &bundled_code, started_on)?;
+
+    // The source map is built from the final text so it stays valid after rustfmt
+    if let Some(source_map_path) = &cli.source_map {
+        let source_map = SourceMap::from_output(&bundled_code);
+        if verbose {
+            eprintln!(
+                "{} {}",
+                "Writing source map:".green(),
+                source_map_path.display()
+            );
+        }
+        fs::write(source_map_path, source_map.to_json()).map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(source_map_path.clone()),
+        })?;
+    }
+
+    if let Some(attest_path) = &cli.attest {
+        write_attestation(
+            cli,
+            attest_path,
+            output_file.as_deref(),
+            &transform_config,
+            &report,
+            &bundled_code,
+            started_on,
+        )?;
+    }
+
+    match &output_file {
+        Some(output_path) => write_output(cli, output_path, &bundled_code)?,
+        None if cli.split_output.is_some() => {
+            return Err(BundlerError::Config {
+                message: "--split-output writes files next to the output, set it with --output"
+                    .to_string(),
+                path: None,
+            });
+        }
+        None if cli.is_json() => {}
+        None => print!("{bundled_code}"),
+    }
+
+    if cli.check {
+        check_bundle(cli)?;
+    }
+    if cli.is_json() {
+        print_json(&BundleJson {
+            success: true,
+            code: &bundled_code,
+            output: output_file.as_deref(),
+            warnings,
+            size: SizeJson::new(cli, &bundled_code),
+            timing: TimingJson::since(timer),
+        });
+    }
+    Ok((output_file, bundled_code))
+}
+
+/// Bundle and format `project_path` as the flags say, with the warnings found on
+/// the way, reusing the bundle cached by a previous run with `--cache` when the
+/// sources and settings are unchanged
+fn bundle_with_cache(
+    cli: &Cli,
+    project_path: &Path,
+) -> Result<(String, BundleReport), BundlerError> {
+    let bundle = || {
+        let (bundled_code, report) = bundle_project(cli, project_path)?;
+        // The size reached with `--target-size` is that of the bundle as emitted
+        let bundled_code = if cli.target_size.is_some() {
+            bundled_code
+        } else {
+            finish_output(cli, bundled_code)
+        };
+        Ok((bundled_code, report))
+    };
+    let explain = |explanation: &str| {
+        if cli.explain_cache {
+            eprintln!("{} {explanation}", "Cache:".blue());
+        }
+    };
+    if !cli.cache && !cli.explain_cache {
+        return bundle();
+    }
+    if let Some(reason) = uncacheable_reason(cli) {
+        explain(&format!("not used, {reason}"));
+        return bundle();
+    }
+    let project = load_project(cli)?;
+    if project.build_script_path().is_some() {
+        explain("not used, the output of the build script is not tracked");
+        return bundle();
+    }
+
+    // Every setting the bundle depends on besides the transform settings,
+    // including those of the configuration file not copied into them
+    let settings = format!(
+        "{:?} {} {:?} {:?} {:?}",
+        cli.entry_target(),
+        cli.is_pretty(),
+        cli.edition(),
+        cli.max_line_len,
+        cli.file_config
+    );
+    let transform_config = cli.get_transform_config();
+    let cache = BundleCache::for_project(&project);
+    // The files included last time, until bundling tells which are included now
+    let key = CacheKey::new(
+        &project,
+        &transform_config,
+        &settings,
+        &cache.included_files(),
+    )?;
+    match cache.lookup(&key) {
+        Ok(cached) => {
+            explain(&format!("reused {}", cache.path().display()));
+            return Ok((
+                cached.code,
+                BundleReport {
+                    warnings: cached.warnings,
+                    included_files: cached.included_files,
+                },
+            ));
+        }
+        Err(miss) => explain(&format!("rebuilt, {miss}")),
+    }
+    let (bundled_code, report) = bundle()?;
+    cache.store(&CachedBundle {
+        key: CacheKey::new(
+            &project,
+            &transform_config,
+            &settings,
+            &report.included_files,
+        )?,
+        code: bundled_code.clone(),
+        warnings: report.warnings.clone(),
+        included_files: report.included_files.clone(),
+    })?;
+    Ok((bundled_code, report))
+}
+
+/// Why the bundle the flags ask for cannot be cached, if it cannot
+fn uncacheable_reason(cli: &Cli) -> Option<&'static str> {
+    if cli.filter {
+        Some("the code is read from stdin")
+    } else if cli.no_manifest {
+        Some("the project has no manifest")
+    } else if cli.extract_demos.is_some() {
+        Some("demos are extracted")
+    } else if cli.target_size.is_some() {
+        Some("--target-size minifies in stages")
+    } else if cli.header || cli.header_template.is_some() {
+        Some("the header may hold the date")
+    } else if !cli.vendor_dep.is_empty() || cli.out_dir_hint.is_some() {
+        Some("files outside the package are not tracked")
+    } else {
+        None
+    }
+}
+
+/// Bundle `project_path` as the flags say, before any formatting, with the
+/// warnings found on the way
+fn bundle_project(cli: &Cli, project_path: &Path) -> Result<(String, BundleReport), BundlerError> {
+    let bundler = cli.bundler();
+    if let Some(demo_dir) = &cli.extract_demos {
+        let (bundled_code, examples) = bundler.bundle_with_demos(project_path)?;
+        write_demo_examples(demo_dir, examples, cli.is_verbose())?;
+        return Ok((bundled_code, BundleReport::default()));
+    }
+    if cli.filter {
+        let code = std::io::read_to_string(std::io::stdin()).map_err(|e| BundlerError::Io {
+            source: e,
+            path: None,
+        })?;
+        return Ok((bundler.transform_source(&code)?, BundleReport::default()));
+    }
+    if cli.no_manifest {
+        let bundled_code = bundle_without_manifest(&bundler, project_path)?;
+        return Ok((bundled_code, BundleReport::default()));
+    }
+    let Some(target) = cli.target_size else {
+        if !cli.is_verbose() {
+            return bundler.bundle_with_report(project_path);
+        }
+        let (bundled_code, report, summary) = bundler.bundle_with_timings(project_path)?;
+        eprintln!("{} {}", "Timings:".blue(), summary.to_line());
+        return Ok((bundled_code, report));
+    };
+    let (bundled_code, stages) = bundler.bundle_within(project_path, target)?;
+    if !cli.is_json() {
+        report_minify_stages(&stages, bundled_code.chars().count(), target);
+    }
+    Ok((bundled_code, BundleReport::default()))
+}
+
+/// Print the settings `handle_bundle_command` bundles with
+fn print_bundle_configuration(cli: &Cli, project_path: &Path, transform_config: &TransformConfig) {
+    if cli.filter {
+        eprintln!("{}", "Transforming stdin".green().bold());
+    } else {
+        eprintln!(
+            "{} {}",
+            "Bundling project:".green().bold(),
+            project_path.display()
+        );
+    }
+    eprintln!("Configuration:");
+    eprintln!("  Remove tests: {}", transform_config.remove_tests);
+    eprintln!("  Docs: {:?}", transform_config.effective_docs_mode());
+    eprintln!("  Expand modules: {}", transform_config.expand_modules);
+    eprintln!("  Minify: {}", transform_config.minify);
+    eprintln!(
+        "  Aggressive minify: {}",
+        transform_config.aggressive_minify
+    );
+    eprintln!("  Strip unused: {}", transform_config.strip_unused);
+    eprintln!("  Mangle: {}", transform_config.mangle);
+    eprintln!("  Line markers: {}", transform_config.line_markers);
+    eprintln!("  Verify minify: {}", transform_config.verify_minify);
+    if let Some(crate_name) = &transform_config.crate_name {
+        eprintln!("  Crate name: {crate_name}");
+    }
+    if let Some(lib_module_name) = &transform_config.lib_module_name {
+        eprintln!("  Library module: {lib_module_name}");
+    }
+    if let Some(bin) = &cli.bin {
+        eprintln!("  Binary target: {bin}");
+    }
+    if let Some(example) = &cli.include_examples {
+        eprintln!("  Example target: {example}");
+    }
+    if let Some(test) = &cli.test_target {
+        eprintln!("  Test target: {test}");
+    }
+    if cli.lib {
+        eprintln!("  Library target: on its own");
+    }
+}
+
+/// Bundle the crate root at `path`, or read from stdin for `-`, without a Cargo project
+fn bundle_without_manifest(bundler: &Bundler, path: &Path) -> Result<String, BundlerError> {
+    if path != Path::new("-") {
+        return bundler.bundle_file(path);
+    }
+    let code = std::io::read_to_string(std::io::stdin()).map_err(|e| BundlerError::Io {
+        source: e,
+        path: None,
+    })?;
+    bundler.bundle_entry_source(Path::new("main.rs"), &code)
+}
+
+/// Type-check the bundle with rustc, printing compiler errors by source file
+fn check_bundle(cli: &Cli) -> Result<(), BundlerError> {
+    let verbose = cli.is_verbose();
+    if verbose {
+        eprintln!("{}", "Checking bundle with rustc...".yellow());
+    }
+
+    let report = cli.bundler().check(cli.get_project_path())?;
+    if report.is_ok() {
+        if verbose {
+            eprintln!("{}", "✓ Bundle compiles".green());
+        }
+        return Ok(());
+    }
+
+    for diagnostic in &report.diagnostics {
+        eprintln!("{diagnostic}");
+        if verbose {
+            eprintln!("{}", diagnostic.rendered);
+        }
+    }
+    Err(BundlerError::CompileCheckFailed {
+        error_count: report.diagnostics.len(),
+    })
+}
+
+/// Report the uses of APIs `profile` forbids in the bundle, by item and source file
+fn lint_bundle(cli: &Cli, profile: LintProfile) -> Result<(), BundlerError> {
+    let report = cli.bundler().lint(cli.get_project_path(), profile)?;
+    if report.is_ok() {
+        if cli.is_verbose() {
+            eprintln!("{}", "✓ Bundle uses no forbidden API".green());
+        }
+        return Ok(());
+    }
+
+    for violation in &report.violations {
+        eprintln!("{violation}");
+    }
+    Err(BundlerError::LintFailed {
+        profile: profile.name(),
+        violation_count: report.violations.len(),
+    })
+}
+
+/// Write the bundle to its output file and update the `--latest-symlink`
+fn write_output(
+    cli: &Cli,
+    output_path: &std::path::Path,
+    bundled_code: &str,
+) -> Result<(), BundlerError> {
+    let verbose = cli.is_verbose();
+    if verbose {
+        eprintln!("{} {}", "Writing to file:".green(), output_path.display());
+    }
+    // The parts first, so that the written root never includes a missing one
+    let split = cli
+        .split_output
+        .map(|limit| split::split(bundled_code, limit))
+        .transpose()?;
+    let bundled_code = match &split {
+        Some(split) => {
+            let dir = output_path.parent().unwrap_or_else(|| Path::new(""));
+            for part in &split.parts {
+                let path = dir.join(&part.name);
+                if verbose {
+                    eprintln!("{} {}", "Writing part:".green(), path.display());
+                }
+                FileManager::write_if_changed(&path, &part.code)?;
+            }
+            split.root.as_str()
+        }
+        None => bundled_code,
+    };
+    // Left untouched when unchanged, not to wake up watchers of the file
+    if !FileManager::write_if_changed(output_path, bundled_code)? && verbose {
+        eprintln!("{}", "Output unchanged, not rewritten".yellow());
+    }
+    if let Some(link) = &cli.latest_symlink {
+        let link = link
+            .clone()
+            .unwrap_or_else(|| output_path.with_file_name("latest.rs"));
+        FileManager::link_latest(&link, output_path)?;
+    }
+
+    if verbose {
+        eprintln!("{}", "Bundle complete!".green().bold());
+        eprintln!();
+        eprintln!("{}", "ℹ️  Issues or feedback? Visit:".cyan());
+        eprintln!(
+            "{}",
+            "   🔗 https://github.com/MathieuSoysal/CG-Bundler/issues/new".blue()
+        );
+    }
+    Ok(())
+}
+
+/// Compare the bundle with the existing file at `path`, printing a unified diff
+/// from it when they differ; the file is never written
+fn check_output(cli: &Cli, path: &Path, bundled_code: &str) -> Result<(), BundlerError> {
+    if cli.is_verbose() {
+        eprintln!("{} {}", "Checking output:".green(), path.display());
+    }
+    let existing = FileManager::read_file(path)?;
+    let label = path.display().to_string();
+    let current = format!("{label} (current sources)");
+    let Some(diff) = diff::unified_diff(&existing, bundled_code, &label, &current) else {
+        println!("{}", format!("✓ {label} is up to date").green().bold());
+        return Ok(());
+    };
+    print!("{diff}");
+    Err(BundlerError::OutdatedOutput {
+        path: path.to_path_buf(),
+    })
+}
+
+/// Output file of the bundle: the `--output-template` rendered for `bundled_code`
+/// (creating its directory), else `-o`; `None` writes to stdout
+fn resolve_output(
+    cli: &Cli,
+    bundled_code: &str,
+    finished_on: SystemTime,
+) -> Result<Option<PathBuf>, BundlerError> {
+    let Some(template) = &cli.output_template else {
+        if cli.latest_symlink.is_some() && cli.get_output().is_none() {
+            return Err(BundlerError::Config {
+                message: "--latest-symlink needs an output file (-o or --output-template)"
+                    .to_string(),
+                path: None,
+            });
+        }
+        return Ok(cli.get_output().cloned());
+    };
+
+    let project = load_project(cli)?;
+    let values = TemplateValues::new(&project, cli.template_profile(), bundled_code, finished_on);
+    let output_path = PathBuf::from(template.render(&values));
+    if let Some(dir) = output_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir).map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(dir.to_path_buf()),
+        })?;
+    }
+    Ok(Some(output_path))
+}
+
+/// Append the `--footer` (or `--footer-file`) rendered for `bundled_code`
+fn append_footer(
+    cli: &Cli,
+    mut bundled_code: String,
+    finished_on: SystemTime,
+) -> Result<String, BundlerError> {
+    let footer = match &cli.footer_file {
+        Some(path) => Some(Template::parse(&FileManager::read_file(path)?)?),
+        None => cli.footer.clone(),
+    };
+    let Some(footer) = footer else {
+        return Ok(bundled_code);
+    };
+
+    let project = load_project(cli)?;
+    let values = TemplateValues::new(&project, cli.template_profile(), &bundled_code, finished_on);
+    if !bundled_code.is_empty() && !bundled_code.ends_with('\n') {
+        bundled_code.push('\n');
+    }
+    bundled_code.push_str(&footer.render(&values));
+    if !bundled_code.ends_with('\n') {
+        bundled_code.push('\n');
+    }
+    Ok(bundled_code)
+}
+
+/// Put the hand-written header of the output file above the new bundle
+fn keep_output_header(cli: &Cli, bundled_code: &str) -> Result<String, BundlerError> {
+    // Checked against the whole file, header included
+    let output_path = cli.check_output.as_ref().or_else(|| cli.get_output());
+    let output_path = output_path.ok_or_else(|| BundlerError::Config {
+        message: "--update-in-place needs an output file (-o or `output` in the configuration)"
+            .to_string(),
+        path: None,
+    })?;
+    if cli.is_verbose() {
+        eprintln!("{} {}", "Keeping header of:".green(), output_path.display());
+    }
+    FileManager::replace_generated(output_path, bundled_code)
+}
+
+/// Fail when the bundle is over `--max-size`; without a maximum, return a warning
+/// when it is over the `CodinGame` limit
+fn check_output_size(cli: &Cli, characters: usize) -> Result<Option<String>, BundlerError> {
+    match cli.max_size() {
+        Some(limit) if characters > limit => {
+            Err(BundlerError::SizeLimitExceeded { characters, limit })
+        }
+        Some(_) => Ok(None),
+        None => Ok((characters > CODINGAME_LIMIT).then(|| {
+            format!(
+                "bundle has {characters} characters, over the CodinGame limit of {CODINGAME_LIMIT}"
+            )
+        })),
+    }
+}
+
+fn print_warning(warning: &str) {
+    eprintln!("{} {warning}", "Warning:".yellow());
+}
+
+/// Print the warnings found while bundling with `--verbose`, else their number
+fn print_bundle_warnings(cli: &Cli, report: &BundleReport) {
+    if report.is_clean() {
+        return;
+    }
+    if cli.is_verbose() {
+        for warning in &report.warnings {
+            print_warning(&warning.to_string());
+        }
+    } else {
+        print_warning(&format!(
+            "{} issue(s) found while bundling, run with --verbose to list them",
+            report.warnings.len()
+        ));
+    }
+}
+
+fn handle_size_report_command(cli: &Cli) -> Result<(), BundlerError> {
+    let (_, report) = cli
+        .bundler()
+        .bundle_with_size_report(cli.get_project_path())?;
+    print!("{}", report.to_table());
+    if let Some(warning) = check_output_size(cli, report.characters)? {
+        print_warning(&warning);
+    }
+    Ok(())
+}
+
+fn handle_stats_command(cli: &Cli) -> Result<(), BundlerError> {
+    let budget = cli.max_size().unwrap_or(CODINGAME_LIMIT);
+    let report = cli.bundler().health(cli.get_project_path(), budget)?;
+    if cli.is_json() {
+        print_json(&report);
+    } else {
+        print!("{}", report.to_table());
+    }
+    Ok(())
+}
+
+fn handle_tree_command(cli: &Cli) -> Result<(), BundlerError> {
+    let graph = cli.bundler().analyze(cli.get_project_path())?;
+    if cli.is_json() {
+        print_json(&graph);
+    } else {
+        print!("{}", graph.to_tree());
+    }
+    Ok(())
+}
+
+fn handle_emit_graph_command(cli: &Cli, format: GraphFormat) -> Result<(), BundlerError> {
+    let graph = cli.bundler().analyze(cli.get_project_path())?;
+    print!("{}", graph.to_graph(format));
+    Ok(())
+}
+
+fn handle_duplicates_command(cli: &Cli) -> Result<(), BundlerError> {
+    let report = cli.bundler().duplicates(cli.get_project_path())?;
+    if cli.is_json() {
+        print_json(&report);
+    } else {
+        print!("{}", report.to_table());
+    }
+    Ok(())
+}
+
+fn write_demo_examples(
+    demo_dir: &PathBuf,
+    examples: Vec<(String, String)>,
+    verbose: bool,
+) -> Result<(), BundlerError> {
+    fs::create_dir_all(demo_dir).map_err(|e| BundlerError::Io {
+        source: e,
+        path: Some(demo_dir.clone()),
+    })?;
+
+    for (name, code) in examples {
+        let example_path = demo_dir.join(format!("{name}.rs"));
+        if verbose {
+            eprintln!(
+                "{} {}",
+                "Writing demo example:".green(),
+                example_path.display()
+            );
+        }
+        fs::write(&example_path, code).map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(example_path.clone()),
+        })?;
+    }
+    Ok(())
+}
+
+/// Apply the output formatting requested on the command line to bundled code
+/// Report the stages `--target-size` applied to fit the bundle in `target`
+/// characters
+fn report_minify_stages(stages: &[MinifyStage], characters: usize, target: usize) {
+    let stages = if stages.is_empty() {
+        "none needed".to_string()
+    } else {
+        stages
+            .iter()
+            .map(|stage| stage.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    eprintln!(
+        "{} {stages} ({characters} / {target} characters)",
+        "Minification stages:".green()
+    );
+}
+
+fn finish_output(cli: &Cli, bundled_code: String) -> String {
+    let verbose = cli.is_verbose();
+
+    // Minification happens inside the bundler; format with rustfmt only if not minifying
+    if cli.is_aggressive_minify() {
+        if verbose {
+            eprintln!("{}", "Applied aggressive minification".yellow());
+        }
+        bundled_code
+    } else if cli.is_minify() {
+        if verbose {
+            eprintln!("{}", "Minified output to single line".yellow());
+        }
+        bundled_code
+    } else if cli.is_pretty() {
+        if verbose {
+            eprintln!("{}", "Formatting with rustfmt...".yellow());
+        }
+
+        let edition = cli
+            .edition()
+            .or_else(|| formatter::detect_edition(&cli.get_project_path()));
+        let formatted = Rustfmt::new()
+            .with_errors_shown(verbose)
+            .format(
+                &bundled_code,
+                edition.as_deref().unwrap_or(formatter::DEFAULT_EDITION),
+            )
+            .unwrap_or_else(|e| {
+                if verbose {
+                    eprintln!("{e}");
+                    eprintln!(
+                        "{}",
+                        "Warning: rustfmt formatting failed, using unformatted output".yellow()
+                    );
+                }
+                bundled_code
+            });
+        // rustfmt joins the lines the bundler wrapped and leaves some long ones
+        match cli.max_line_len.or(cli.file_config.max_line_len) {
+            Some(max_len) => line_wrap::wrap_long_lines(&formatted, max_len),
+            None => formatted,
+        }
+    } else {
+        bundled_code
+    }
+}
+
+fn handle_all_bins_command(cli: &Cli) -> Result<(), BundlerError> {
+    let project_path = cli.get_project_path();
+    let verbose = cli.is_verbose();
+    let out_dir = cli
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| project_path.join("bundles"));
+
+    if verbose {
+        eprintln!(
+            "{} {}",
+            "Bundling all binaries of:".green().bold(),
+            project_path.display()
+        );
+    }
+
+    let targets = cli.bundler().bundle_all_bins(&project_path)?;
+
+    fs::create_dir_all(&out_dir).map_err(|e| BundlerError::Io {
+        source: e,
+        path: Some(out_dir.clone()),
+    })?;
+
+    for (name, code) in targets {
+        let output_path = out_dir.join(format!("{name}.rs"));
+        let code = finish_output(cli, code);
+        if let Some(warning) = check_output_size(cli, code.chars().count())? {
+            print_warning(&warning);
+        }
+        if FileManager::write_if_changed(&output_path, &code)? && verbose {
+            eprintln!("{} {}", "Wrote".green(), output_path.display());
+        } else if verbose {
+            eprintln!("{} {}", "Unchanged".yellow(), output_path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Bundle once per `--profile`, each into the output of its profile
+///
+/// `args` is the command line as given, which the settings of each profile are
+/// applied to. Nothing is written when two profiles have the same output file.
+fn handle_profiles_command(cli: &Cli, args: &Cli) -> Result<(), BundlerError> {
+    let started_on = SystemTime::now();
+    let verbose = cli.is_verbose();
+    let profiles = cli
+        .profile
+        .iter()
+        .map(|profile| cli.with_profile(args, profile))
+        .collect::<Result<Vec<_>, _>>()?;
+    let variants: Vec<Bundler> = profiles.iter().map(Cli::bundler).collect();
+    let bundles = cli
+        .bundler()
+        .bundle_variants(cli.get_project_path(), &variants)?;
+
+    let mut outputs: Vec<(&str, PathBuf, String)> = Vec::new();
+    for (((name, _), profile), code) in cli.profile.iter().zip(&profiles).zip(bundles) {
+        let code = finish_output(profile, code);
+        let code = append_footer(profile, code, started_on)?;
+        let code = if profile.update_in_place {
+            keep_output_header(profile, &code)?
+        } else {
+            code
+        };
+        if let Some(warning) = check_output_size(profile, code.chars().count())? {
+            print_warning(&format!("profile '{name}': {warning}"));
+        }
+        let Some(output_path) = resolve_output(profile, &code, started_on)? else {
+            continue;
+        };
+        let normalized = FileManager::normalize_path(&output_path);
+        if let Some((other, ..)) = outputs
+            .iter()
+            .find(|(_, path, _)| FileManager::normalize_path(path) == normalized)
+        {
+            return Err(BundlerError::Config {
+                message: format!(
+                    "profiles '{other}' and '{name}' both write {}",
+                    output_path.display()
+                ),
+                path: cli.config.clone(),
+            });
+        }
+        outputs.push((name, output_path, code));
+    }
+
+    for (name, output_path, code) in outputs {
+        if FileManager::write_if_changed(&output_path, &code)? && verbose {
+            eprintln!("{} {} ({name})", "Wrote".green(), output_path.display());
+        } else if verbose {
+            eprintln!(
+                "{} {} ({name})",
+                "Unchanged".yellow(),
+                output_path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the provenance attestation of the final bundle to `attest_path`
+fn write_attestation(
+    cli: &Cli,
+    attest_path: &std::path::Path,
+    output_file: Option<&std::path::Path>,
+    transform_config: &TransformConfig,
+    report: &BundleReport,
+    bundled_code: &str,
+    started_on: SystemTime,
+) -> Result<(), BundlerError> {
+    let project = load_project(cli)?;
+    let output_name =
+        output_file.map_or_else(|| "stdout".to_string(), |path| path.display().to_string());
+    let other_inputs: Vec<PathBuf> = cli
+        .config_file()
+        .into_iter()
+        .chain(report.included_files.iter().cloned())
+        .map(|path| FileManager::canonicalize(&path).unwrap_or(path))
+        .collect();
+    let provenance = Provenance::new(
+        &project,
+        transform_config,
+        &other_inputs,
+        &output_name,
+        bundled_code,
+        started_on,
+    )?;
+
+    if cli.is_verbose() {
+        eprintln!(
+            "{} {}",
+            "Writing provenance attestation:".green(),
+            attest_path.display()
+        );
+    }
+    fs::write(attest_path, provenance.to_json()).map_err(|e| BundlerError::Io {
+        source: e,
+        path: Some(attest_path.to_path_buf()),
+    })
+}
+
+/// Analyze the project, selecting the target given with `--bin`, `--include-examples`
+/// or `--test`
+fn load_project(cli: &Cli) -> Result<CargoProject, BundlerError> {
+    CargoProject::with_target(cli.get_project_path(), &cli.entry_target())
+}
+
+fn handle_validate_command(cli: &Cli) -> Result<(), BundlerError> {
+    let timer = Instant::now();
+    let project_path = cli.get_project_path();
+    let verbose = cli.is_verbose();
+    if verbose {
+        eprintln!(
+            "{} {}",
+            "Validating project:".green().bold(),
+            project_path.display()
+        );
+    }
+
+    let report = validate::validate_project(
+        &project_path,
+        &cli.entry_target(),
+        &cli.get_transform_config(),
+    );
+
+    if verbose {
+        if let Some(project) = &report.project {
+            eprintln!("{}", "✓ Project structure is valid".green());
+            eprintln!("  Crate name: {}", project.crate_name());
+            eprintln!("  Binary target: {}", project.binary_target().name);
+            if let Some(lib) = project.library_target() {
+                eprintln!("  Library target: {}", lib.name);
+            }
+        }
+    }
+
+    if cli.is_json() {
+        print_json(&ValidateJson {
+            success: report.is_ok(),
+            problems: &report.problems,
+            skipped: ProblemCategory::ALL
+                .into_iter()
+                .filter(|category| !report.checked.contains(category))
+                .collect(),
+            timing: TimingJson::since(timer),
+        });
+    }
+
+    if !report.is_ok() {
+        if cli.is_json() {
+            return Err(BundlerError::ValidationFailed {
+                problem_count: report.problems.len(),
+            });
+        }
+        for problem in &report.problems {
+            eprintln!("{} [{}] {problem}", "✗".red(), problem.category.name());
+        }
+        eprintln!();
+        eprint!("{}", report.summary_table());
+        return Err(BundlerError::ValidationFailed {
+            problem_count: report.problems.len(),
+        });
+    }
+
+    if verbose {
+        eprintln!("{}", "✓ Project can be bundled successfully".green());
+        eprintln!("{}", "✓ Generated code is syntactically valid".green());
+    }
+
+    if !cli.is_json() {
+        println!("{}", "✓ Project validation successful".green().bold());
+    }
+
+    if verbose {
+        eprintln!();
+        eprintln!("{}", "ℹ️  Need help or want to report an issue?".cyan());
+        eprintln!(
+            "{}",
+            "   Visit: https://github.com/MathieuSoysal/CG-Bundler/issues/new".blue()
+        );
+    }
+
+    Ok(())
+}
+
+fn handle_dry_run_command(cli: &Cli) -> Result<(), BundlerError> {
+    let project_path = cli.get_project_path();
+    let plan = cli.bundler().plan(&project_path)?;
+
+    println!("{}", "Bundle Plan (dry run)".blue().bold());
+    println!("{}", "=".repeat(21));
+    println!("{}: {}", "Project".bold(), project_path.display());
+    println!("{}: {}", "Entry".bold(), plan.binary_source.display());
+    match &plan.library_source {
+        Some(library) => println!("{}: {}", "Library".bold(), library.display()),
+        None => println!("{}: not inlined", "Library".bold()),
+    }
+
+    println!();
+    println!("{}", "Modules".blue().bold());
+    println!("{}", "-".repeat(10));
+    if plan.modules.is_empty() {
+        println!("No module files to expand");
+    }
+    for module in &plan.modules {
+        match &module.file {
+            Some(file) => println!("  {} ({})", module.path, file.display()),
+            None => println!("  {} {}", module.path, "(file not found)".yellow()),
+        }
+    }
+
+    println!();
+    println!("{}", "Transforms".blue().bold());
+    println!("{}", "-".repeat(10));
+    for transform in &plan.transforms {
+        println!("  {transform}");
+    }
+    if cli.is_pretty() && !cli.is_minify() {
+        println!("  rustfmt");
+    }
+
+    println!();
+    match cli.get_output() {
+        Some(output) => println!("{}: {}", "Output".bold(), output.display()),
+        None => println!("{}: stdout", "Output".bold()),
+    }
+
+    Ok(())
+}
+
+fn handle_determinism_check_command(cli: &Cli, runs: usize) -> Result<(), BundlerError> {
+    let project_path = cli.get_project_path();
+    let runs = runs.max(2);
+
+    if cli.is_verbose() {
+        eprintln!(
+            "{} {} ({runs} runs)",
+            "Checking bundle determinism:".green().bold(),
+            project_path.display()
+        );
+    }
+
+    cli.bundler().check_determinism(&project_path, runs)?;
+
+    println!(
+        "{}",
+        format!("✓ Bundled output is identical across {runs} runs")
+            .green()
+            .bold()
+    );
+    Ok(())
+}
+
+fn handle_bench_command(cli: &Cli, runs: usize) -> Result<(), BundlerError> {
+    let project_path = cli.get_project_path();
+    if cli.is_verbose() {
+        eprintln!(
+            "{} {} ({runs} runs)",
+            "Benchmarking:".green().bold(),
+            project_path.display()
+        );
+    }
+
+    let report = cli.bundler().bench(&project_path, runs)?;
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    println!(
+        "{}",
+        format!("Benchmark ({} runs)", report.runs.len()).bold()
+    );
+    let total = ("total", report.total());
+    for (name, stats) in std::iter::once(total).chain(report.phases()) {
+        println!(
+            "  {name:<10} min {:>9.3} ms  avg {:>9.3} ms  max {:>9.3} ms",
+            millis(stats.min),
+            millis(stats.avg),
+            millis(stats.max)
+        );
+    }
+    match report.peak_rss {
+        #[allow(clippy::cast_precision_loss)]
+        Some(bytes) => println!("  {:<10} {:.1} MiB", "peak RSS", bytes as f64 / 1_048_576.0),
+        None => println!("  {:<10} unavailable", "peak RSS"),
+    }
+    Ok(())
+}
+
+fn handle_verify_output_command(cli: &Cli, bundle_path: &Path) -> Result<(), BundlerError> {
+    let project_path = cli.get_project_path();
+    if cli.is_verbose() {
+        eprintln!(
+            "{} {} against {}",
+            "Verifying bundle:".green().bold(),
+            bundle_path.display(),
+            project_path.display()
+        );
+    }
+
+    let bundle = FileManager::read_file(bundle_path)?;
+    // Bundled again as the original was, with line markers to attribute changes
+    let mut bundler = cli.bundler();
+    bundler.set_config(TransformConfig {
+        line_markers: true,
+        ..cli.get_transform_config()
+    });
+    let marked = finish_output(cli, bundler.bundle(&project_path)?);
+    let report = integrity::compare(&bundle, &marked)?;
+    if report.is_current() {
+        println!(
+            "{}",
+            format!("✓ {} matches the current sources", bundle_path.display())
+                .green()
+                .bold()
+        );
+        return Ok(());
+    }
+
+    if !report.changed_files.is_empty() {
+        eprintln!(
+            "{}",
+            "Source files changed since the bundle was produced:".yellow()
+        );
+        for file in &report.changed_files {
+            eprintln!("  {} {file}", "✗".red());
+        }
+    }
+    if !report.stale_items.is_empty() {
+        eprintln!(
+            "{}",
+            "Items of the bundle no longer in the sources:".yellow()
+        );
+        for item in &report.stale_items {
+            eprintln!("  {} {item}", "✗".red());
+        }
+    }
+    Err(BundlerError::OutdatedOutput {
+        path: bundle_path.to_path_buf(),
+    })
+}
+
+fn handle_unbundle_command(project_path: &Path, bundle_path: &Path) -> Result<(), BundlerError> {
+    let src_dir = project_path.join("src");
+    let files = unbundle::unbundle(&FileManager::read_file(bundle_path)?)?;
+
+    // Nothing is written if any file would be overwritten
+    if let Some(existing) = files
+        .iter()
+        .map(|file| src_dir.join(&file.path))
+        .find(|path| path.exists())
+    {
+        return Err(BundlerError::Io {
+            source: std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "Refusing to overwrite an existing file",
+            ),
+            path: Some(existing),
+        });
+    }
+
+    for file in files {
+        let path = src_dir.join(&file.path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| BundlerError::Io {
+                source: e,
+                path: Some(dir.to_path_buf()),
+            })?;
+        }
+        fs::write(&path, file.code).map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(path.clone()),
+        })?;
+        eprintln!("{} {}", "Wrote".green(), path.display());
+    }
+    Ok(())
+}
+
+fn handle_info_command(cli: &Cli) -> Result<(), BundlerError> {
+    let timer = Instant::now();
+    let project = load_project(cli)?;
+    if cli.is_json() {
+        print_json(&InfoJson::new(&project, timer));
+        return Ok(());
+    }
+
+    println!("{}", "Project Information".blue().bold());
+    println!("{}", "=".repeat(20));
+
+    let package = project.root_package();
+    println!("{}: {}", "Name".bold(), package.name);
+    println!("{}: {}", "Version".bold(), package.version);
+
+    if let Some(description) = &package.description {
+        println!("{}: {}", "Description".bold(), description);
+    }
+
+    println!("{}: {}", "Manifest Path".bold(), package.manifest_path);
+    println!(
+        "{}: {}",
+        "Source Base Path".bold(),
+        project.base_path().display()
+    );
+
+    println!();
+    println!("{}", "Targets".blue().bold());
+    println!("{}", "-".repeat(10));
+
+    let binary = project.binary_target();
+    println!("{}: {} ({})", "Binary".bold(), binary.name, binary.src_path);
+
+    if let Some(library) = project.library_target() {
+        println!(
+            "{}: {} ({})",
+            "Library".bold(),
+            library.name,
+            library.src_path
+        );
+    }
+
+    println!();
+    println!("{}", "Dependencies".blue().bold());
+    println!("{}", "-".repeat(15));
+
+    if package.dependencies.is_empty() {
+        println!("No dependencies");
+    } else {
+        for dep in &package.dependencies {
+            println!("  {} {}", dep.name, dep.req);
+        }
+    }
+
+    println!();
+    println!("{}", "━".repeat(50).bright_blue());
+    println!(
+        "{}",
+        "ℹ️  Need help or want to report an issue?".cyan().bold()
+    );
+    println!(
+        "{}",
+        "   🔗 https://github.com/MathieuSoysal/CG-Bundler/issues/new".blue()
+    );
+    println!("{}", "━".repeat(50).bright_blue());
+
+    Ok(())
+}
+
+/// Print `value` as pretty-printed JSON on stdout, for `--format json`
+fn print_json(value: &impl Serialize) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).unwrap_or_default()
+    );
+}
+
+/// `--format json` result of a failed command
+#[derive(Serialize)]
+struct ErrorJson {
+    success: bool,
+    error: String,
+    diagnostic: Diagnostic,
+}
+
+/// `--format json` result of bundling
+#[derive(Serialize)]
+struct BundleJson<'a> {
+    success: bool,
+    code: &'a str,
+    /// File the bundle was written to, `None` when it is only in `code`
+    output: Option<&'a Path>,
+    warnings: Vec<String>,
+    size: SizeJson,
+    timing: TimingJson,
+}
+
+/// Size of a bundle and the limit it is checked against
+#[derive(Serialize)]
+struct SizeJson {
+    bytes: usize,
+    characters: usize,
+    lines: usize,
+    /// `--max-size`, else the `CodinGame` limit
+    limit: usize,
+}
+
+impl SizeJson {
+    fn new(cli: &Cli, code: &str) -> Self {
+        Self {
+            bytes: code.len(),
+            characters: code.chars().count(),
+            lines: code.lines().count(),
+            limit: cli.max_size().unwrap_or(CODINGAME_LIMIT),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TimingJson {
+    duration_ms: u128,
+}
+
+impl TimingJson {
+    fn since(start: Instant) -> Self {
+        Self {
+            duration_ms: start.elapsed().as_millis(),
+        }
+    }
+}
+
+/// `--format json` result of `--validate`
+#[derive(Serialize)]
+struct ValidateJson<'a> {
+    success: bool,
+    problems: &'a [Problem],
+    /// Stages that did not run because an earlier one failed
+    skipped: Vec<ProblemCategory>,
+    timing: TimingJson,
+}
+
+/// `--format json` result of `--info`
+#[derive(Serialize)]
+struct InfoJson {
+    name: String,
+    version: String,
+    description: Option<String>,
+    manifest_path: String,
+    source_base_path: PathBuf,
+    binary: TargetJson,
+    library: Option<TargetJson>,
+    dependencies: Vec<DependencyJson>,
+    timing: TimingJson,
+}
+
+#[derive(Serialize)]
+struct TargetJson {
+    name: String,
+    path: String,
+}
+
+#[derive(Serialize)]
+struct DependencyJson {
+    name: String,
+    req: String,
+}
+
+impl InfoJson {
+    fn new(project: &CargoProject, timer: Instant) -> Self {
+        let package = project.root_package();
+        let target = |target: &cargo_metadata::Target| TargetJson {
+            name: target.name.clone(),
+            path: target.src_path.to_string(),
+        };
+        Self {
+            name: package.name.as_str().to_owned(),
+            version: package.version.to_string(),
+            description: package.description.clone(),
+            manifest_path: package.manifest_path.to_string(),
+            source_base_path: project.base_path().to_path_buf(),
+            binary: target(project.binary_target()),
+            library: project.library_target().map(target),
+            dependencies: package
+                .dependencies
+                .iter()
+                .map(|dep| DependencyJson {
+                    name: dep.name.clone(),
+                    req: dep.req.to_string(),
+                })
+                .collect(),
+            timing: TimingJson::since(timer),
+        }
+    }
+}
+
+#[cfg(not(feature = "watch"))]
+fn handle_watch_command(_cli: &Cli) -> Result<(), BundlerError> {
+    Err(BundlerError::Io {
+        source: std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "watch mode is not compiled in (enable the `watch` feature)",
+        ),
+        path: None,
+    })
+}
+
+/// A channel receiving `()` when Ctrl+C is pressed, for a graceful shutdown
+#[cfg(all(feature = "watch", feature = "daemon"))]
+fn shutdown_signal() -> Result<std::sync::mpsc::Receiver<()>, BundlerError> {
+    let (shutdown_tx, shutdown_rx) = std::sync::mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = shutdown_tx.send(());
+    })
+    .map_err(|e| BundlerError::Io {
+        source: std::io::Error::other(e.to_string()),
+        path: None,
+    })?;
+    Ok(shutdown_rx)
+}
+
+/// Without the `daemon` feature Ctrl+C ends the process directly, so the
+/// channel never receives anything
+#[cfg(all(feature = "watch", not(feature = "daemon")))]
+#[allow(clippy::unnecessary_wraps)]
+fn shutdown_signal() -> Result<std::sync::mpsc::Receiver<()>, BundlerError> {
+    let (_, shutdown_rx) = std::sync::mpsc::channel();
+    Ok(shutdown_rx)
+}
+
+#[cfg(feature = "watch")]
+fn handle_watch_command(cli: &Cli) -> Result<(), BundlerError> {
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    if cli.tui {
+        return run_dashboard(cli);
+    }
+
+    println!("{} Starting watch mode...", "🔍".green());
+    println!("{} Watching directory: {}", "📁".blue(), cli.src_dir);
+    if let Some(output) = &cli.output {
+        println!("{} Output file: {}", "📄".blue(), output.display());
+    } else {
+        println!("{} Output: stdout", "📄".blue());
+    }
+    println!("{} Debounce delay: {}ms", "⏱️".blue(), cli.debounce);
+    println!("{} Press Ctrl+C to stop\n", "ℹ️".yellow());
+
+    let (_watcher, rx) = start_watching(cli)?;
+
+    let shutdown_rx = shutdown_signal()?;
+
+    let notify_server = start_notify_server(cli)?;
+    let build = |cli: &Cli| {
+        let code = build_and_notify(cli, notify_server.as_ref())?;
+        if let Some(puzzle) = &cli.submit_on_success {
+            // A failed upload is reported without failing the build
+            match submit_code(puzzle, &code, PlayMode::Game) {
+                Ok(result) => println!("{} Played in the IDE: {}", "🎮".green(), result.summary()),
+                Err(e) => eprintln!("{} {}", "⚠️".yellow(), e),
+            }
+        }
+        Ok::<(), BundlerError>(())
+    };
+
+    // Initial build
+    if let Err(e) = build(cli) {
+        eprintln!("{} Initial build failed: {}", "❌".red(), e);
+    } else {
+        println!("{} Initial build successful!\n", "✅".green());
+    }
+
+    let mut last_event_time = Instant::now();
+    let debounce_duration = Duration::from_millis(cli.debounce);
+
+    loop {
+        // Check for shutdown signal
+        if shutdown_rx.try_recv() == Ok(()) {
+            println!("\n{} Received shutdown signal", "🛑".yellow());
+            break;
+        }
+
+        // Check for file system events
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(Ok(event)) => {
+                if should_rebuild(&event) {
+                    let now = Instant::now();
+                    if now.duration_since(last_event_time) > debounce_duration {
+                        last_event_time = now;
+
+                        match event.paths.first().and_then(|path| path.file_name()) {
+                            Some(file_name) => {
+                                println!("{} File change detected: {file_name:?}", "🔄".yellow());
+                            }
+                            None => println!("{} File change detected", "🔄".yellow()),
+                        }
+
+                        match build(cli) {
+                            Ok(()) => println!("{} Rebuild successful!\n", "✅".green()),
+                            Err(e) => eprintln!("{} Rebuild failed: {}\n", "❌".red(), e),
+                        }
+                    }
+                }
+            }
+            Ok(Err(e)) => eprintln!("{} Watch error: {}", "⚠️".yellow(), e),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // Continue loop
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    println!("{} Watch mode stopped.", "🛑".red());
+    Ok(())
+}
+
+/// Watch the sources, returning the watcher and its events
+#[cfg(feature = "watch")]
+fn start_watching(cli: &Cli) -> Result<WatchedProject, BundlerError> {
+    use notify::{RecursiveMode, Watcher};
+
+    let watch_path = cli.get_project_path().join(&cli.src_dir);
+    if !watch_path.exists() {
+        return Err(BundlerError::Io {
+            source: std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Source directory '{}' does not exist", cli.src_dir),
+            ),
+            path: Some(watch_path),
+        });
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(|e| BundlerError::Io {
+        source: std::io::Error::other(e.to_string()),
+        path: None,
+    })?;
+    watcher
+        .watch(&watch_path, RecursiveMode::Recursive)
+        .map_err(|e| BundlerError::Io {
+            source: std::io::Error::other(e.to_string()),
+            path: Some(watch_path),
+        })?;
+    Ok((watcher, rx))
+}
+
+#[cfg(feature = "watch")]
+type WatchedProject = (
+    notify::RecommendedWatcher,
+    std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+);
+
+/// Bundle and write the output, then notify the `--notify-port` clients
+#[cfg(feature = "watch")]
+fn build_and_notify(
+    cli: &Cli,
+    notify_server: Option<&crate::live_reload::NotifyServer>,
+) -> Result<String, BundlerError> {
+    let (output, code) = bundle_and_write(cli)?;
+    if let Some(server) = notify_server {
+        let event = crate::live_reload::RebuildEvent::new(output.as_deref(), &code);
+        server.broadcast(&event);
+    }
+    Ok(code)
+}
+
+#[cfg(all(feature = "watch", not(feature = "tui")))]
+fn run_dashboard(_cli: &Cli) -> Result<(), BundlerError> {
+    Err(BundlerError::Io {
+        source: std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the watch dashboard is not compiled in (enable the `tui` feature)",
+        ),
+        path: None,
+    })
+}
+
+/// Watch mode with the `--tui` dashboard, rebuilding on changes and on the keys
+/// the dashboard binds
+#[cfg(feature = "tui")]
+fn run_dashboard(cli: &Cli) -> Result<(), BundlerError> {
+    use crate::dashboard::{Action, Build, Dashboard};
+    use ratatui::crossterm::event::{self, Event};
+
+    if cli.output.is_none() && cli.output_template.is_none() {
+        return Err(BundlerError::Config {
+            message: "--tui needs an output file (-o FILE), the dashboard takes the terminal"
+                .to_string(),
+            path: None,
+        });
+    }
+    let notify_server = start_notify_server(cli)?;
+    let (_watcher, rx) = start_watching(cli)?;
+    let project_dir = FileManager::canonicalize(cli.get_project_path())
+        .unwrap_or_else(|_| cli.get_project_path());
+    let mut cli = cli.clone();
+    let mut dashboard = Dashboard::new(cli.max_size().unwrap_or(CODINGAME_LIMIT), cli.is_minify());
+    let debounce = Duration::from_millis(cli.debounce);
+    let mut last_build: Option<Instant> = None;
+    let mut rebuild = true;
+    let io_error = |source| BundlerError::Io { source, path: None };
+
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if rebuild {
+            rebuild = false;
+            last_build = Some(Instant::now());
+            let start = Instant::now();
+            let result = build_and_notify(&cli, notify_server.as_ref())
+                .map(|code| code.chars().count())
+                .map_err(|e| e.to_string());
+            dashboard.record_build(Build {
+                result,
+                duration: start.elapsed(),
+            });
+            // Warnings printed while bundling are drawn over by a full redraw
+            if let Err(e) = terminal.clear() {
+                break Err(io_error(e));
+            }
+        }
+        if let Err(e) = terminal.draw(|frame| dashboard.render(frame)) {
+            break Err(io_error(e));
+        }
+
+        let key = match event::poll(Duration::from_millis(100)) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) => Action::for_key(key),
+                Ok(_) => None,
+                Err(e) => break Err(io_error(e)),
+            },
+            Ok(false) => None,
+            Err(e) => break Err(io_error(e)),
+        };
+        match key {
+            Some(Action::Quit) => break Ok(()),
+            Some(Action::Rebuild) => rebuild = true,
+            Some(Action::ToggleMinify) => {
+                toggle_minify(&mut cli);
+                dashboard.set_minify(cli.is_minify());
+                rebuild = true;
+            }
+            None => {}
+        }
+
+        while let Ok(Ok(event)) = rx.try_recv() {
+            if should_rebuild(&event) {
+                dashboard.record_event(describe_event(&event, &project_dir));
+                rebuild |= last_build.map_or(true, |time| time.elapsed() > debounce);
+            }
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+/// Switch minification of the bundle on or off, over the configuration file
+#[cfg(feature = "tui")]
+fn toggle_minify(cli: &mut Cli) {
+    let minify = !cli.is_minify();
+    cli.minify = minify;
+    cli.no_minify = !minify;
+    cli.m2 = false;
+    cli.file_config.minify = Some(minify);
+    cli.file_config.m2 = Some(false);
+}
+
+/// A file event as listed on the dashboard, e.g. `changed src/ai.rs`
+#[cfg(feature = "tui")]
+fn describe_event(event: &notify::Event, project_dir: &Path) -> String {
+    use notify::EventKind;
+
+    let kind = match event.kind {
+        EventKind::Create(_) => "created",
+        EventKind::Remove(_) => "removed",
+        _ => "changed",
+    };
+    let paths: Vec<String> = event
+        .paths
+        .iter()
+        .map(|path| {
+            path.strip_prefix(project_dir)
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        })
+        .collect();
+    format!("{kind} {}", paths.join(", "))
+}
+
+/// Start the server of `--notify-port`, if given
+#[cfg(feature = "watch")]
+fn start_notify_server(
+    cli: &Cli,
+) -> Result<Option<crate::live_reload::NotifyServer>, BundlerError> {
+    let Some(port) = cli.notify_port else {
+        return Ok(None);
+    };
+    let server = crate::live_reload::NotifyServer::bind(port)?;
+    println!(
+        "{} Notifying rebuilds on localhost:{}",
+        "📡".blue(),
+        server.port()
+    );
+    Ok(Some(server))
+}
+
+#[cfg(feature = "watch")]
+fn should_rebuild(event: &notify::Event) -> bool {
+    // Only rebuild for Rust files
+    is_change(event)
+        && event.paths.iter().any(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ext == "rs")
+        })
+}
+
+#[cfg(feature = "watch")]
+const fn is_change(event: &notify::Event) -> bool {
+    use notify::EventKind;
+
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
@@ -0,0 +1,314 @@
+#[cfg(feature = "codingame-api")]
+use serde_json::json;
+use serde_json::Value;
+use std::fmt::Write;
+#[cfg(feature = "codingame-api")]
+use std::time::Duration;
+
+use crate::error::{BundlerError, Result};
+
+/// Services of the `CodinGame` web IDE
+pub const DEFAULT_BASE_URL: &str = "https://www.codingame.com/services";
+
+/// Environment variable holding the value of the `rememberMe` cookie of a
+/// logged-in `CodinGame` session
+pub const TOKEN_VAR: &str = "CODINGAME_TOKEN";
+
+/// Environment variable holding the numeric `CodinGame` user id
+pub const USER_ID_VAR: &str = "CODINGAME_USER_ID";
+
+/// Language of the uploaded code, as named by `CodinGame`
+#[cfg(feature = "codingame-api")]
+const LANGUAGE: &str = "Rust";
+
+/// Account the code is uploaded with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    /// Value of the `rememberMe` cookie
+    pub token: String,
+    pub user_id: u64,
+}
+
+impl Credentials {
+    /// Read the credentials from `CODINGAME_TOKEN` and `CODINGAME_USER_ID`
+    ///
+    /// # Errors
+    /// Returns an error if a variable is unset or the user id is not a number
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| BundlerError::Submission {
+                message: format!("set {name} to upload to CodinGame"),
+            })
+        };
+        let user_id = var(USER_ID_VAR)?;
+        let user_id = user_id
+            .trim()
+            .parse()
+            .map_err(|_| BundlerError::Submission {
+                message: format!("{USER_ID_VAR} is not a user id: '{user_id}'"),
+            })?;
+        Ok(Self {
+            token: var(TOKEN_VAR)?,
+            user_id,
+        })
+    }
+}
+
+/// What the IDE plays with the uploaded code
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlayMode {
+    /// A game of a multiplayer puzzle or contest against the default opponent
+    #[default]
+    Game,
+    /// The test case with this index, from 1, of a solo puzzle
+    Test(usize),
+}
+
+/// Outcome of a play, as far as the IDE reports it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlayResult {
+    /// Rank of each player of a game, 0 for the winner, the bundle being the first
+    pub ranks: Vec<u64>,
+    pub scores: Vec<f64>,
+    /// Whether a test case printed the expected output
+    pub passed: Option<bool>,
+}
+
+impl PlayResult {
+    /// Outcome in the response of `TestSession/play`
+    #[must_use]
+    pub fn from_response(response: &Value) -> Self {
+        let numbers = |key: &str| -> Vec<Value> {
+            response
+                .get(key)
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default()
+        };
+        Self {
+            ranks: numbers("ranks").iter().filter_map(Value::as_u64).collect(),
+            scores: numbers("scores").iter().filter_map(Value::as_f64).collect(),
+            passed: response
+                .pointer("/comparison/success")
+                .and_then(Value::as_bool),
+        }
+    }
+
+    /// One line describing the outcome, e.g. `rank 1 of 2, scores 12 and 7`
+    #[must_use]
+    pub fn summary(&self) -> String {
+        if let Some(passed) = self.passed {
+            return if passed { "test passed" } else { "test failed" }.to_string();
+        }
+        let Some(rank) = self.ranks.first() else {
+            return "played".to_string();
+        };
+        let mut summary = format!("rank {} of {}", rank + 1, self.ranks.len());
+        if !self.scores.is_empty() {
+            let scores: Vec<String> = self.scores.iter().map(ToString::to_string).collect();
+            let _ = write!(summary, ", scores {}", scores.join(" and "));
+        }
+        summary
+    }
+}
+
+/// Client of the web API the `CodinGame` IDE uses, which is not a documented public
+/// API and may change
+#[cfg(feature = "codingame-api")]
+#[derive(Debug)]
+pub struct CodinGameClient {
+    base_url: String,
+    credentials: Credentials,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "codingame-api")]
+impl CodinGameClient {
+    #[must_use]
+    pub fn new(credentials: Credentials) -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            credentials,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(120))
+                .build(),
+        }
+    }
+
+    /// Send the requests to `base_url` instead of `DEFAULT_BASE_URL`
+    #[must_use]
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Upload `code` to the IDE of `puzzle`, its id as in the puzzle's URL (e.g.
+    /// `spring-challenge-2024`), and play it as `mode` says
+    ///
+    /// The code stays in the IDE, as if pasted there and played.
+    ///
+    /// # Errors
+    /// Returns an error if a request fails or `CodinGame` rejects it
+    pub fn play(&self, puzzle: &str, code: &str, mode: PlayMode) -> Result<PlayResult> {
+        let session = self.call(
+            "Puzzle/generateSessionFromPuzzlePrettyId",
+            &json!([self.credentials.user_id, puzzle, false]),
+        )?;
+        let handle = session
+            .get("handle")
+            .and_then(Value::as_str)
+            .ok_or_else(|| BundlerError::Submission {
+                message: format!("no IDE session for puzzle '{puzzle}'"),
+            })?;
+
+        let mut play = json!({ "code": code, "programmingLanguageId": LANGUAGE });
+        match mode {
+            PlayMode::Game => {
+                play["multi"] = json!({ "agentsIds": [-1, -2], "gameOptions": null });
+            }
+            PlayMode::Test(index) => play["testIndex"] = json!(index),
+        }
+        let response = self.call("TestSession/play", &json!([handle, play]))?;
+        Ok(PlayResult::from_response(&response))
+    }
+
+    /// POST `body` to the service `path` and return the JSON response
+    fn call(&self, path: &str, body: &Value) -> Result<Value> {
+        let url = format!("{}/{path}", self.base_url);
+        let response = self
+            .agent
+            .post(&url)
+            .set("Cookie", &format!("rememberMe={}", self.credentials.token))
+            .send_json(body)
+            .map_err(|e| BundlerError::Submission {
+                message: match e {
+                    ureq::Error::Status(401 | 403, _) => {
+                        format!("{path}: not logged in, check {TOKEN_VAR} and {USER_ID_VAR}")
+                    }
+                    ureq::Error::Status(status, response) => format!(
+                        "{path}: HTTP {status} {}",
+                        response.into_string().unwrap_or_default()
+                    ),
+                    ureq::Error::Transport(transport) => format!("{path}: {transport}"),
+                },
+            })?;
+        response.into_json().map_err(|e| BundlerError::Submission {
+            message: format!("{path}: unexpected response: {e}"),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "codingame-api"))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Answer each request to a local server with the next of `responses`,
+    /// returning its URL and the requests received, as `path body` lines
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/services", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split(' ').nth(1).unwrap().to_string();
+                let mut length = 0;
+                let mut cookie = String::new();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let header = line.trim_end().to_ascii_lowercase();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.strip_prefix("content-length: ") {
+                        length = value.parse().unwrap();
+                    }
+                    if let Some(value) = header.strip_prefix("cookie: ") {
+                        cookie = value.to_string();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                requests.push(format!(
+                    "{path} {cookie} {}",
+                    String::from_utf8(body).unwrap()
+                ));
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn client(url: &str) -> CodinGameClient {
+        CodinGameClient::new(Credentials {
+            token: "secret".to_string(),
+            user_id: 1_234_567,
+        })
+        .with_base_url(url)
+    }
+
+    #[test]
+    fn test_play_game() {
+        let (url, server) = serve(vec![
+            r#"{"handle":"abc"}"#,
+            r#"{"frames":[],"ranks":[0,1],"scores":[12,7]}"#,
+        ]);
+        let result = client(&url)
+            .play("spring-challenge-2024", "fn main() {}", PlayMode::Game)
+            .unwrap();
+        assert_eq!(result.ranks, [0, 1]);
+        assert_eq!(result.summary(), "rank 1 of 2, scores 12 and 7");
+
+        let requests = server.join().unwrap();
+        assert_eq!(
+            requests[0],
+            r#"/services/Puzzle/generateSessionFromPuzzlePrettyId rememberme=secret [1234567,"spring-challenge-2024",false]"#
+        );
+        let (path, body) = requests[1].split_once(" rememberme=secret ").unwrap();
+        assert_eq!(path, "/services/TestSession/play");
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body[0], "abc");
+        assert_eq!(body[1]["code"], "fn main() {}");
+        assert_eq!(body[1]["programmingLanguageId"], "Rust");
+        assert_eq!(body[1]["multi"]["agentsIds"], json!([-1, -2]));
+    }
+
+    #[test]
+    fn test_play_test_case() {
+        let (url, server) = serve(vec![
+            r#"{"handle":"abc"}"#,
+            r#"{"frames":[],"comparison":{"success":false}}"#,
+        ]);
+        let result = client(&url)
+            .play("onboarding", "fn main() {}", PlayMode::Test(3))
+            .unwrap();
+        assert_eq!(result.summary(), "test failed");
+        let requests = server.join().unwrap();
+        assert!(
+            requests[1].ends_with(r#""testIndex":3}]"#),
+            "{}",
+            requests[1]
+        );
+
+        let (url, server) = serve(vec!["null"]);
+        let error = client(&url)
+            .play("missing", "fn main() {}", PlayMode::Game)
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("no IDE session for puzzle 'missing'"));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,578 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{BundlerError, Result};
+use crate::file_manager::FileManager;
+use crate::transformer::{AttributeRetention, DocsMode, TransformConfig};
+
+/// Name of the per-project configuration file
+pub const CONFIG_FILE_NAME: &str = "cg-bundler.toml";
+
+/// Per-project settings loaded from `cg-bundler.toml` or from the
+/// `[package.metadata.cg-bundler]` section of `Cargo.toml`
+///
+/// Every field is optional; unset fields fall back to the command-line flags or
+/// the built-in defaults. Keys use the same spelling as the CLI flags.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BundlerConfig {
+    /// Output file, relative to the project directory
+    pub output: Option<PathBuf>,
+    /// Output file name template (see `template::Template`), relative to the
+    /// project directory; takes precedence over `output`
+    pub output_template: Option<String>,
+    /// Start the bundle with a comment block of crate and build information
+    pub header: Option<bool>,
+    /// Text of that comment block, with the variables of `output_template` plus
+    /// `{crate_version}`, `{timestamp}` and `{bundler_version}`; implies `header`
+    pub header_template: Option<String>,
+    /// Text appended to the bundle, with the variables of `output_template`
+    pub footer: Option<String>,
+    /// File holding the footer, relative to the project directory; `footer` takes
+    /// precedence
+    pub footer_file: Option<PathBuf>,
+    /// Footer per output style (`m2`, `minify`, `pretty` or `default`), taking
+    /// precedence over `footer` and `footer_file`
+    #[serde(default)]
+    pub footers: BTreeMap<String, String>,
+    /// Crate name used for `extern crate`/`use` matching instead of the Cargo.toml name
+    pub crate_name: Option<String>,
+    /// Module the library is embedded in instead of the bundle root
+    pub lib_module_name: Option<String>,
+    pub keep_tests: Option<bool>,
+    /// Keep tests behind `#[cfg(NAME)]` instead of `#[cfg(test)]`
+    pub tests_under_cfg: Option<String>,
+    pub keep_docs: Option<bool>,
+    /// `remove`, `keep` or `convert-to-plain`; takes precedence over `keep-docs`
+    pub docs_mode: Option<DocsMode>,
+    pub no_expand_modules: Option<bool>,
+    pub pretty: Option<bool>,
+    pub minify: Option<bool>,
+    pub m2: Option<bool>,
+    pub strip_unused: Option<bool>,
+    pub mangle: Option<bool>,
+    pub merge_imports: Option<bool>,
+    pub rename_collisions: Option<bool>,
+    pub expand_macros: Option<bool>,
+    pub keep_comments: Option<bool>,
+    /// Emit the items no transform changed with their source formatting
+    pub preserve_formatting: Option<bool>,
+    pub line_markers: Option<bool>,
+    pub verify_minify: Option<bool>,
+    /// Maximum number of characters of the bundle
+    pub max_size: Option<usize>,
+    /// Lines longer than this many characters are broken
+    pub max_line_len: Option<usize>,
+    /// Threads reading the module files ahead of bundling
+    pub jobs: Option<usize>,
+    /// Edition the bundle is formatted and type-checked as, instead of the package's
+    pub edition: Option<String>,
+    /// Per-attribute retention overrides, e.g. `deprecated = "keep"`
+    #[serde(default)]
+    pub attributes: BTreeMap<String, AttributeRetention>,
+    /// Feature-gated modules to bundle, e.g. `"ai::cover_strategy" = "cover"`
+    #[serde(default)]
+    pub enable_modules: BTreeMap<String, String>,
+    /// Cargo features to bundle with, e.g. `["advanced-ai"]`
+    #[serde(default)]
+    pub features: Vec<String>,
+    pub no_default_features: Option<bool>,
+    pub all_features: Option<bool>,
+    /// Patterns of modules left out of the bundle, e.g. `["debug", "bin_helpers::*"]`
+    #[serde(default)]
+    pub exclude_modules: Vec<String>,
+    /// Patterns of the only modules bundled
+    #[serde(default)]
+    pub only_modules: Vec<String>,
+    /// Values of consts, e.g. `DEBUG = false` or `DEPTH = "3 * 2"`
+    #[serde(default)]
+    pub defines: BTreeMap<String, DefineValue>,
+    /// Named output configurations, e.g. `[profiles.submit]` with `m2 = true` and
+    /// `output = "bundle.submit.rs"`, each set on top of the other settings
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Self>,
+}
+
+/// Value of a `defines` entry: a boolean, an integer or the text of a Rust
+/// expression
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum DefineValue {
+    Bool(bool),
+    Integer(i64),
+    Expression(String),
+}
+
+impl DefineValue {
+    /// The value as the text of a Rust expression
+    #[must_use]
+    pub fn to_expression(&self) -> String {
+        match self {
+            Self::Bool(value) => value.to_string(),
+            Self::Integer(value) => value.to_string(),
+            Self::Expression(expression) => expression.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CargoManifest {
+    package: Option<ManifestPackage>,
+}
+
+#[derive(Deserialize)]
+struct ManifestPackage {
+    metadata: Option<ManifestMetadata>,
+}
+
+#[derive(Deserialize)]
+struct ManifestMetadata {
+    #[serde(rename = "cg-bundler")]
+    cg_bundler: Option<BundlerConfig>,
+}
+
+impl BundlerConfig {
+    /// Load the configuration of a project
+    ///
+    /// `cg-bundler.toml` in the project directory takes precedence over the
+    /// `[package.metadata.cg-bundler]` section of `Cargo.toml`. Returns the default
+    /// (empty) configuration when neither exists.
+    ///
+    /// # Errors
+    /// Returns an error if a configuration file exists but cannot be parsed
+    pub fn load<P: AsRef<Path>>(project_path: P) -> Result<Self> {
+        let project_path = project_path.as_ref();
+
+        let config_path = project_path.join(CONFIG_FILE_NAME);
+        if FileManager::file_exists(&config_path) {
+            return Self::from_file(&config_path);
+        }
+
+        let manifest_path = project_path.join("Cargo.toml");
+        FileManager::try_read_file(&manifest_path).map_or_else(
+            || Ok(Self::default()),
+            |manifest| Self::from_manifest(&manifest, &manifest_path),
+        )
+    }
+
+    /// Load a configuration from an explicit `cg-bundler.toml`-style file
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or parsed
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = FileManager::read_file(path)?;
+        toml::from_str(&content).map_err(|e| BundlerError::Config {
+            message: e.to_string(),
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    fn from_manifest(manifest: &str, manifest_path: &Path) -> Result<Self> {
+        let manifest: CargoManifest =
+            toml::from_str(manifest).map_err(|e| BundlerError::Config {
+                message: format!("Invalid [package.metadata.cg-bundler] section: {e}"),
+                path: Some(manifest_path.to_path_buf()),
+            })?;
+
+        Ok(manifest
+            .package
+            .and_then(|package| package.metadata)
+            .and_then(|metadata| metadata.cg_bundler)
+            .unwrap_or_default())
+    }
+
+    /// Load a configuration from comma-separated settings, as given on the command
+    /// line: `key` sets a flag and `key=value` any setting, e.g.
+    /// `m2,output=bundle.submit.rs` or `defines.DEBUG=false`
+    ///
+    /// Values are read as TOML, or as strings when they are not valid TOML.
+    ///
+    /// # Errors
+    /// Returns an error if a key is unknown or a value has the wrong type
+    pub fn from_settings(settings: &str) -> Result<Self> {
+        let mut table = String::new();
+        for setting in settings.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting.split_once('=').unwrap_or((setting, "true"));
+            let (key, value) = (key.trim(), value.trim());
+            let line = format!("{key} = {value}\n");
+            if line.parse::<toml::Table>().is_ok() {
+                table.push_str(&line);
+            } else {
+                let _ = writeln!(table, "{key} = {}", toml::Value::from(value));
+            }
+        }
+        toml::from_str(&table).map_err(|e| BundlerError::Config {
+            message: format!("Invalid settings '{settings}': {}", e.message()),
+            path: None,
+        })
+    }
+
+    /// This configuration with the settings of the profile `name` on top
+    ///
+    /// # Errors
+    /// Returns an error if there is no such profile, or it defines profiles itself
+    pub fn profile(&self, name: &str) -> Result<Self> {
+        let Some(profile) = self.profiles.get(name) else {
+            let defined: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(BundlerError::Config {
+                message: if defined.is_empty() {
+                    format!("unknown profile '{name}', no profiles are defined")
+                } else {
+                    format!(
+                        "unknown profile '{name}', expected one of {}",
+                        defined.join(", ")
+                    )
+                },
+                path: None,
+            });
+        };
+        if !profile.profiles.is_empty() {
+            return Err(BundlerError::Config {
+                message: format!("profile '{name}' cannot define profiles"),
+                path: None,
+            });
+        }
+        Ok(self.merged_with(profile))
+    }
+
+    /// This configuration with the settings of `other` on top: values `other`
+    /// sets replace these, and its tables and lists are added to these
+    ///
+    /// An `output` of `other` replaces an `output-template` of this
+    /// configuration, and a `footer` replaces a `footer-file`.
+    #[must_use]
+    pub fn merged_with(&self, other: &Self) -> Self {
+        fn join<K: Ord + Clone, V: Clone>(
+            base: &BTreeMap<K, V>,
+            other: &BTreeMap<K, V>,
+        ) -> BTreeMap<K, V> {
+            let mut joined = base.clone();
+            joined.extend(other.iter().map(|(k, v)| (k.clone(), v.clone())));
+            joined
+        }
+        let output_template = if other.output.is_some() {
+            other.output_template.clone()
+        } else {
+            other
+                .output_template
+                .clone()
+                .or_else(|| self.output_template.clone())
+        };
+        let footer_file = if other.footer.is_some() {
+            other.footer_file.clone()
+        } else {
+            other
+                .footer_file
+                .clone()
+                .or_else(|| self.footer_file.clone())
+        };
+        Self {
+            output: other.output.clone().or_else(|| self.output.clone()),
+            output_template,
+            header: other.header.or(self.header),
+            header_template: other
+                .header_template
+                .clone()
+                .or_else(|| self.header_template.clone()),
+            footer: other.footer.clone().or_else(|| self.footer.clone()),
+            footer_file,
+            footers: join(&self.footers, &other.footers),
+            crate_name: other.crate_name.clone().or_else(|| self.crate_name.clone()),
+            lib_module_name: other
+                .lib_module_name
+                .clone()
+                .or_else(|| self.lib_module_name.clone()),
+            keep_tests: other.keep_tests.or(self.keep_tests),
+            tests_under_cfg: other
+                .tests_under_cfg
+                .clone()
+                .or_else(|| self.tests_under_cfg.clone()),
+            keep_docs: other.keep_docs.or(self.keep_docs),
+            docs_mode: other.docs_mode.or(self.docs_mode),
+            no_expand_modules: other.no_expand_modules.or(self.no_expand_modules),
+            pretty: other.pretty.or(self.pretty),
+            minify: other.minify.or(self.minify),
+            m2: other.m2.or(self.m2),
+            strip_unused: other.strip_unused.or(self.strip_unused),
+            mangle: other.mangle.or(self.mangle),
+            merge_imports: other.merge_imports.or(self.merge_imports),
+            rename_collisions: other.rename_collisions.or(self.rename_collisions),
+            expand_macros: other.expand_macros.or(self.expand_macros),
+            keep_comments: other.keep_comments.or(self.keep_comments),
+            preserve_formatting: other.preserve_formatting.or(self.preserve_formatting),
+            line_markers: other.line_markers.or(self.line_markers),
+            verify_minify: other.verify_minify.or(self.verify_minify),
+            max_size: other.max_size.or(self.max_size),
+            max_line_len: other.max_line_len.or(self.max_line_len),
+            jobs: other.jobs.or(self.jobs),
+            edition: other.edition.clone().or_else(|| self.edition.clone()),
+            attributes: join(&self.attributes, &other.attributes),
+            enable_modules: join(&self.enable_modules, &other.enable_modules),
+            features: [self.features.as_slice(), &other.features].concat(),
+            no_default_features: other.no_default_features.or(self.no_default_features),
+            all_features: other.all_features.or(self.all_features),
+            exclude_modules: [self.exclude_modules.as_slice(), &other.exclude_modules].concat(),
+            only_modules: [self.only_modules.as_slice(), &other.only_modules].concat(),
+            defines: join(&self.defines, &other.defines),
+            profiles: join(&self.profiles, &other.profiles),
+        }
+    }
+
+    /// Apply the configured values on top of a transform configuration
+    pub fn apply(&self, config: &mut TransformConfig) {
+        if let Some(keep_tests) = self.keep_tests {
+            config.remove_tests = !keep_tests;
+        }
+        if let Some(name) = &self.tests_under_cfg {
+            config.remove_tests = false;
+            config.tests_cfg = Some(name.clone());
+        }
+        if let Some(keep_docs) = self.keep_docs {
+            config.docs_mode = if keep_docs {
+                DocsMode::Keep
+            } else {
+                DocsMode::Remove
+            };
+        }
+        if let Some(docs_mode) = self.docs_mode {
+            config.docs_mode = docs_mode;
+        }
+        if let Some(no_expand_modules) = self.no_expand_modules {
+            config.expand_modules = !no_expand_modules;
+        }
+        if let Some(minify) = self.minify {
+            config.minify = minify;
+        }
+        if let Some(m2) = self.m2 {
+            config.aggressive_minify = m2;
+            config.minify |= m2;
+        }
+        if let Some(strip_unused) = self.strip_unused {
+            config.strip_unused = strip_unused;
+        }
+        if let Some(mangle) = self.mangle {
+            config.mangle = mangle;
+        }
+        if let Some(merge_imports) = self.merge_imports {
+            config.merge_imports = merge_imports;
+        }
+        if let Some(rename_collisions) = self.rename_collisions {
+            config.rename_collisions = rename_collisions;
+        }
+        if let Some(expand_macros) = self.expand_macros {
+            config.expand_macros = expand_macros;
+        }
+        if let Some(keep_comments) = self.keep_comments {
+            config.keep_comments = keep_comments;
+        }
+        if let Some(preserve_formatting) = self.preserve_formatting {
+            config.preserve_formatting = preserve_formatting;
+        }
+        if let Some(line_markers) = self.line_markers {
+            config.line_markers = line_markers;
+        }
+        if let Some(verify_minify) = self.verify_minify {
+            config.verify_minify = verify_minify;
+        }
+        if let Some(max_line_len) = self.max_line_len {
+            config.max_line_len = Some(max_line_len);
+        }
+        if let Some(crate_name) = &self.crate_name {
+            config.crate_name = Some(crate_name.replace('-', "_"));
+        }
+        if let Some(lib_module_name) = &self.lib_module_name {
+            config.lib_module_name = Some(lib_module_name.replace('-', "_"));
+        }
+        for (name, retention) in &self.attributes {
+            config
+                .attribute_policy
+                .overrides
+                .insert(name.clone(), *retention);
+        }
+        config.enabled_modules.extend(self.enable_modules.clone());
+        if !self.features.is_empty()
+            || self.no_default_features.is_some()
+            || self.all_features.is_some()
+        {
+            let selection = config.features.get_or_insert_with(Default::default);
+            selection.features.extend(self.features.iter().cloned());
+            if let Some(no_default_features) = self.no_default_features {
+                selection.no_default_features = no_default_features;
+            }
+            if let Some(all_features) = self.all_features {
+                selection.all_features = all_features;
+            }
+        }
+        config
+            .excluded_modules
+            .extend(self.exclude_modules.iter().cloned());
+        config
+            .only_modules
+            .extend(self.only_modules.iter().cloned());
+        for (name, value) in &self.defines {
+            config.defines.insert(name.clone(), value.to_expression());
+        }
+    }
+
+    /// Build a transform configuration from the defaults and this file
+    #[must_use]
+    pub fn transform_config(&self) -> TransformConfig {
+        let mut config = TransformConfig::default();
+        self.apply(&mut config);
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cargo_project::FeatureSelection;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_without_config_is_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = BundlerConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config, BundlerConfig::default());
+    }
+
+    #[test]
+    fn test_load_config_file() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join(CONFIG_FILE_NAME),
+            "keep-docs = true\nm2 = true\noutput = \"bundle.rs\"\n\n[attributes]\ndeprecated = \"keep\"\n",
+        )
+        .unwrap();
+
+        let config = BundlerConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.output, Some(PathBuf::from("bundle.rs")));
+
+        let transform = config.transform_config();
+        assert_eq!(transform.docs_mode, DocsMode::Keep);
+        assert!(transform.minify);
+        assert!(transform.aggressive_minify);
+        assert_eq!(
+            transform.attribute_policy.overrides.get("deprecated"),
+            Some(&AttributeRetention::Keep)
+        );
+    }
+
+    #[test]
+    fn test_load_cargo_metadata_section() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"bot\"\nversion = \"0.1.0\"\n\n[package.metadata.cg-bundler]\nkeep-tests = true\n",
+        )
+        .unwrap();
+
+        let config = BundlerConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.keep_tests, Some(true));
+    }
+
+    #[test]
+    fn test_config_file_takes_precedence_over_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"bot\"\n\n[package.metadata.cg-bundler]\nminify = true\n",
+        )
+        .unwrap();
+        fs::write(
+            temp_dir.path().join(CONFIG_FILE_NAME),
+            "pretty = true\ncrate-name = \"my-bot\"\n",
+        )
+        .unwrap();
+
+        let config = BundlerConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.pretty, Some(true));
+        assert_eq!(config.minify, None);
+        assert_eq!(
+            config.transform_config().crate_name.as_deref(),
+            Some("my_bot")
+        );
+    }
+
+    #[test]
+    fn test_unknown_key_is_an_error() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join(CONFIG_FILE_NAME), "keep-doc = true\n").unwrap();
+
+        let result = BundlerConfig::load(temp_dir.path());
+        assert!(matches!(result, Err(BundlerError::Config { .. })));
+    }
+
+    #[test]
+    fn test_profiles_on_top_of_the_configuration() {
+        let config: BundlerConfig = toml::from_str(
+            "keep-docs = true\noutput-template = \"out/{crate}.rs\"\nexclude-modules = [\"debug\"]\n\n\
+             [profiles.debug]\npretty = true\n\n\
+             [profiles.submit]\nm2 = true\nkeep-docs = false\noutput = \"bundle.submit.rs\"\nexclude-modules = [\"log\"]\n",
+        )
+        .unwrap();
+
+        let debug = config.profile("debug").unwrap();
+        assert_eq!(debug.pretty, Some(true));
+        assert_eq!(debug.output_template.as_deref(), Some("out/{crate}.rs"));
+        assert_eq!(debug.transform_config().docs_mode, DocsMode::Keep);
+
+        let submit = config.profile("submit").unwrap();
+        assert_eq!(submit.output, Some(PathBuf::from("bundle.submit.rs")));
+        assert_eq!(submit.output_template, None);
+        assert_eq!(submit.exclude_modules, ["debug", "log"]);
+        let transform = submit.transform_config();
+        assert!(transform.aggressive_minify);
+        assert_eq!(transform.docs_mode, DocsMode::Remove);
+
+        assert!(matches!(
+            config.profile("release"),
+            Err(BundlerError::Config { message, .. }) if message.ends_with("one of debug, submit")
+        ));
+    }
+
+    #[test]
+    fn test_feature_selection() {
+        let config: BundlerConfig = toml::from_str(
+            "features = [\"advanced-ai\"]\n\n[profiles.debug]\nfeatures = [\"debug-vis\"]\nno-default-features = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.transform_config().features.unwrap().features,
+            ["advanced-ai"]
+        );
+
+        let debug = config.profile("debug").unwrap().transform_config();
+        assert_eq!(
+            debug.features,
+            Some(FeatureSelection {
+                features: vec!["advanced-ai".to_string(), "debug-vis".to_string()],
+                no_default_features: true,
+                all_features: false,
+            })
+        );
+        assert_eq!(BundlerConfig::default().transform_config().features, None);
+    }
+
+    #[test]
+    fn test_settings_from_command_line() {
+        let settings = BundlerConfig::from_settings(
+            "m2, output=out/bundle.rs,max-size=90000,defines.DEBUG=false",
+        )
+        .unwrap();
+        assert_eq!(settings.m2, Some(true));
+        assert_eq!(settings.output, Some(PathBuf::from("out/bundle.rs")));
+        assert_eq!(settings.max_size, Some(90_000));
+        assert_eq!(
+            settings.defines.get("DEBUG"),
+            Some(&DefineValue::Bool(false))
+        );
+
+        assert!(BundlerConfig::from_settings("m3").is_err());
+        assert!(BundlerConfig::from_settings("max-size=big").is_err());
+    }
+}
@@ -0,0 +1,267 @@
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{BundlerError, Result};
+
+/// Page for opening a new issue on the project's tracker
+pub const ISSUES_URL: &str = "https://github.com/MathieuSoysal/CG-Bundler/issues/new";
+
+/// Backtrace lines kept in a crash report
+const MAX_BACKTRACE_LINES: usize = 60;
+
+/// Lines of the offending source file kept in a crash report
+const MAX_FILE_LINES: usize = 200;
+
+/// Characters of the panic message kept in a pre-filled issue title
+const MAX_TITLE_CHARS: usize = 80;
+
+/// Description of the run, such as the parsed arguments and loaded configuration
+static CONTEXT: Mutex<Option<String>> = Mutex::new(None);
+
+thread_local! {
+    /// Source file most recently read by this thread
+    static CURRENT_FILE: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Describe the run for crash reports, replacing any earlier description
+pub fn set_context(context: String) {
+    if let Ok(mut slot) = CONTEXT.lock() {
+        *slot = Some(context);
+    }
+}
+
+/// Remember `path` as the file being processed, reported if this thread panics
+pub fn note_file(path: &Path) {
+    CURRENT_FILE.with(|file| *file.borrow_mut() = Some(path.to_path_buf()));
+}
+
+/// Replace the default panic output with a crash report
+///
+/// On panic, a `CrashReport` is written to the temporary directory and a short
+/// message points to it and to a pre-filled issue on the project's tracker,
+/// instead of a raw backtrace.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| (*message).to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let location = info
+            .location()
+            .map(|location| format!("{}:{}", location.file(), location.line()));
+
+        let report = CrashReport::capture(message, location);
+        eprintln!("cg-bundler crashed: {}", report.summary());
+        eprintln!("This is a bug in cg-bundler, not in your project.");
+        match report.write(&std::env::temp_dir()) {
+            Ok(path) => {
+                eprintln!("A diagnostic report was written to:");
+                eprintln!("  {}", path.display());
+                eprintln!("Please open an issue and attach the report:");
+            }
+            Err(e) => {
+                eprintln!("The diagnostic report could not be written: {e}");
+                eprintln!("Please open an issue describing the command you ran:");
+            }
+        }
+        eprintln!("  {}", report.issue_url());
+    }));
+}
+
+/// Link to a new issue with `title` filled in
+#[must_use]
+pub fn issue_url(title: &str) -> String {
+    format!("{ISSUES_URL}?title={}", percent_encode(title))
+}
+
+/// Everything known about a panic, written to a file users can attach to an issue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashReport {
+    pub message: String,
+    /// Source location of the panic in cg-bundler, as `file:line`
+    pub location: Option<String>,
+    /// Command line arguments
+    pub args: Vec<String>,
+    /// Description set with `set_context`
+    pub context: Option<String>,
+    /// Project file being processed when the panic happened
+    pub file: Option<PathBuf>,
+    /// Backtrace, truncated to its first lines
+    pub backtrace: String,
+}
+
+impl CrashReport {
+    /// Gather a report for the panic with `message` on the current thread
+    #[must_use]
+    pub fn capture(message: String, location: Option<String>) -> Self {
+        Self {
+            message,
+            location,
+            args: std::env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            context: CONTEXT.lock().ok().and_then(|context| context.clone()),
+            file: CURRENT_FILE.with(|file| file.borrow().clone()),
+            backtrace: truncate_lines(&Backtrace::force_capture().to_string(), MAX_BACKTRACE_LINES),
+        }
+    }
+
+    /// Panic message followed by its location
+    #[must_use]
+    pub fn summary(&self) -> String {
+        self.location.as_ref().map_or_else(
+            || self.message.clone(),
+            |location| format!("{} ({location})", self.message),
+        )
+    }
+
+    /// Issue title naming the panic
+    #[must_use]
+    pub fn title(&self) -> String {
+        let message = self.message.lines().next().unwrap_or_default();
+        let mut title: String = message.chars().take(MAX_TITLE_CHARS).collect();
+        if title.len() < message.len() {
+            title.push('…');
+        }
+        format!("Panic: {title}")
+    }
+
+    /// Link to a new issue pre-filled with `title`
+    #[must_use]
+    pub fn issue_url(&self) -> String {
+        issue_url(&self.title())
+    }
+
+    /// The report as plain text
+    #[must_use]
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "cg-bundler {} crash report\n\nPanic: {}\n",
+            env!("CARGO_PKG_VERSION"),
+            self.summary()
+        );
+        let _ = writeln!(
+            text,
+            "Platform: {}-{}",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        let _ = writeln!(text, "Arguments: {:?}", self.args);
+        if let Some(file) = &self.file {
+            let _ = writeln!(text, "File being processed: {}", file.display());
+        }
+        if let Some(context) = &self.context {
+            let _ = write!(text, "\n## Configuration\n\n{context}\n");
+        }
+        let _ = write!(text, "\n## Backtrace\n\n{}\n", self.backtrace.trim_end());
+        if let Some(source) = self
+            .file
+            .as_ref()
+            .and_then(|file| std::fs::read_to_string(file).ok())
+        {
+            let _ = write!(
+                text,
+                "\n## Source of the file being processed\n\n{}\n",
+                truncate_lines(&source, MAX_FILE_LINES).trim_end()
+            );
+        }
+        text
+    }
+
+    /// Write the report to a new file in `dir` and return its path
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be written
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let path = dir.join(format!(
+            "cg-bundler-crash-{seconds}-{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&path, self.to_text()).map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(path.clone()),
+        })?;
+        Ok(path)
+    }
+}
+
+/// The first `max` lines of `text`, noting how many were left out
+fn truncate_lines(text: &str, max: usize) -> String {
+    let total = text.lines().count();
+    let mut kept: String = text
+        .lines()
+        .take(max)
+        .fold(String::new(), |mut kept, line| {
+            kept.push_str(line);
+            kept.push('\n');
+            kept
+        });
+    if total > max {
+        let _ = writeln!(kept, "... {} more lines", total - max);
+    }
+    kept
+}
+
+/// Percent-encode `text` for a URL query value
+fn percent_encode(text: &str) -> String {
+    text.bytes().fold(String::new(), |mut encoded, byte| {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+        encoded
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn report() -> CrashReport {
+        CrashReport {
+            message: "index out of bounds: the len is 0 but the index is 0".to_string(),
+            location: Some("src/transformer.rs:42".to_string()),
+            args: vec!["cg-bundler".to_string(), "--m2".to_string()],
+            context: Some("minify: true".to_string()),
+            file: None,
+            backtrace: truncate_lines("0: main\n1: start\n2: _start\n", 2),
+        }
+    }
+
+    #[test]
+    fn test_issue_url_encodes_title() {
+        assert_eq!(
+            report().issue_url(),
+            format!("{ISSUES_URL}?title=Panic%3A%20index%20out%20of%20bounds%3A%20the%20len%20is%200%20but%20the%20index%20is%200")
+        );
+        assert_eq!(issue_url("é&"), format!("{ISSUES_URL}?title=%C3%A9%26"));
+    }
+
+    #[test]
+    fn test_report_is_written_with_details() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("main.rs");
+        std::fs::write(&source, "fn main() {}\n").unwrap();
+        let mut report = report();
+        report.file = Some(source);
+
+        let path = report.write(temp_dir.path()).unwrap();
+        let text = std::fs::read_to_string(path).unwrap();
+        assert!(text.contains("(src/transformer.rs:42)"));
+        assert!(text.contains("Arguments: [\"cg-bundler\", \"--m2\"]"));
+        assert!(text.contains("minify: true"));
+        assert!(text.contains("0: main\n1: start\n... 1 more lines"));
+        assert!(text.ends_with("fn main() {}\n"));
+    }
+}
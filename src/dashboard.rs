@@ -0,0 +1,251 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, Paragraph, Sparkline};
+use ratatui::Frame;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Builds kept for the build time history
+const BUILD_HISTORY: usize = 200;
+
+/// File events kept, the most recent first
+const EVENT_HISTORY: usize = 50;
+
+/// What a key pressed on the dashboard asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Rebuild,
+    ToggleMinify,
+    Quit,
+}
+
+impl Action {
+    /// Action bound to `key`: `r` rebuilds, `m` toggles minification, `q`, `Esc`
+    /// and `Ctrl+C` quit
+    #[must_use]
+    pub fn for_key(key: KeyEvent) -> Option<Self> {
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(Self::Quit),
+            KeyCode::Char('r') => Some(Self::Rebuild),
+            KeyCode::Char('m') => Some(Self::ToggleMinify),
+            KeyCode::Char('q') | KeyCode::Esc => Some(Self::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of a build in watch mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Build {
+    /// Characters of the bundle, or the error that failed the build
+    pub result: Result<usize, String>,
+    pub duration: Duration,
+}
+
+/// The `--tui` dashboard of watch mode: the last build, the bundle size against
+/// the limit, a history of build times and recent file events
+///
+/// Only holds what it shows; the watch loop records builds and events and draws
+/// it with `render`.
+#[derive(Debug, Clone)]
+pub struct Dashboard {
+    limit: usize,
+    minify: bool,
+    builds: VecDeque<Build>,
+    events: VecDeque<String>,
+}
+
+impl Dashboard {
+    /// Dashboard of bundles limited to `limit` characters
+    #[must_use]
+    pub const fn new(limit: usize, minify: bool) -> Self {
+        Self {
+            limit,
+            minify,
+            builds: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Whether the bundles are minified
+    #[must_use]
+    pub const fn minify(&self) -> bool {
+        self.minify
+    }
+
+    pub fn set_minify(&mut self, minify: bool) {
+        self.minify = minify;
+    }
+
+    pub fn record_build(&mut self, build: Build) {
+        if self.builds.len() == BUILD_HISTORY {
+            self.builds.pop_front();
+        }
+        self.builds.push_back(build);
+    }
+
+    /// Add a file event, e.g. `changed src/ai.rs`
+    pub fn record_event(&mut self, event: impl Into<String>) {
+        self.events.truncate(EVENT_HISTORY - 1);
+        self.events.push_front(event.into());
+    }
+
+    /// Draw the dashboard over the whole frame
+    pub fn render(&self, frame: &mut Frame) {
+        let [status, size, history, events, help] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Length(3),
+            Constraint::Length(6),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(
+            Paragraph::new(self.status()).block(Block::bordered().title(" Last build ")),
+            status,
+        );
+        frame.render_widget(self.size_gauge(), size);
+
+        // The most recent builds that fit, oldest on the left
+        let durations: Vec<u64> = self
+            .builds
+            .iter()
+            .map(|build| u64::try_from(build.duration.as_millis()).unwrap_or(u64::MAX))
+            .collect();
+        let shown = durations
+            .len()
+            .saturating_sub(usize::from(history.width.saturating_sub(2)));
+        frame.render_widget(
+            Sparkline::default()
+                .block(Block::bordered().title(" Build time (ms) "))
+                .data(&durations[shown..])
+                .style(Style::new().fg(Color::Cyan)),
+            history,
+        );
+
+        frame.render_widget(
+            List::new(self.events.iter().map(String::as_str))
+                .block(Block::bordered().title(" File events ")),
+            events,
+        );
+        frame.render_widget(
+            Line::from(" r rebuild · m toggle minify · q quit").dim(),
+            help,
+        );
+    }
+
+    fn status(&self) -> Vec<Line<'_>> {
+        let minify = format!("Minify: {}", if self.minify { "on" } else { "off" });
+        let Some(build) = self.builds.back() else {
+            return vec![
+                Line::from("Waiting for the first build"),
+                Line::from(minify),
+            ];
+        };
+        let millis = build.duration.as_millis();
+        match &build.result {
+            Ok(_) => vec![
+                Line::from(format!("✓ Built in {millis} ms")).green(),
+                Line::from(minify),
+            ],
+            Err(error) => vec![
+                Line::from(format!("✗ Failed after {millis} ms")).red(),
+                Line::from(error.lines().next().unwrap_or_default().to_string()),
+            ],
+        }
+    }
+
+    /// Size of the last successful bundle against the limit
+    fn size_gauge(&self) -> Gauge<'_> {
+        let block = Block::bordered().title(" Bundle size ");
+        let Some(&size) = self
+            .builds
+            .iter()
+            .rev()
+            .find_map(|build| build.result.as_ref().ok())
+        else {
+            return Gauge::default().block(block).label("no bundle yet");
+        };
+        let percent = size.saturating_mul(100) / self.limit.max(1);
+        let color = match percent {
+            0..=89 => Color::Green,
+            90..=100 => Color::Yellow,
+            _ => Color::Red,
+        };
+        Gauge::default()
+            .block(block)
+            .gauge_style(Style::new().fg(color))
+            .percent(u16::try_from(percent.min(100)).unwrap_or(100))
+            .label(format!("{size} / {} characters ({percent}%)", self.limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn screen(dashboard: &Dashboard) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(usize::from(buffer.area.width))
+            .map(|row| {
+                row.iter()
+                    .map(ratatui::buffer::Cell::symbol)
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_dashboard_shows_builds_and_events() {
+        let mut dashboard = Dashboard::new(1000, false);
+        assert!(screen(&dashboard).contains("Waiting for the first build"));
+
+        dashboard.record_build(Build {
+            result: Ok(950),
+            duration: Duration::from_millis(120),
+        });
+        dashboard.record_event("changed src/ai.rs");
+        dashboard.record_build(Build {
+            result: Err("Parsing error in 'src/ai.rs'\nmore".to_string()),
+            duration: Duration::from_millis(80),
+        });
+        dashboard.set_minify(true);
+
+        let screen = screen(&dashboard);
+        assert!(screen.contains("✗ Failed after 80 ms"));
+        assert!(screen.contains("Parsing error in 'src/ai.rs'"));
+        assert!(screen.contains("950 / 1000 characters (95%)"));
+        assert!(screen.contains("changed src/ai.rs"));
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let key = |code, modifiers| Action::for_key(KeyEvent::new(code, modifiers));
+        assert_eq!(
+            key(KeyCode::Char('r'), KeyModifiers::NONE),
+            Some(Action::Rebuild)
+        );
+        assert_eq!(
+            key(KeyCode::Char('m'), KeyModifiers::NONE),
+            Some(Action::ToggleMinify)
+        );
+        assert_eq!(
+            key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            Some(Action::Quit)
+        );
+        assert_eq!(key(KeyCode::Char('x'), KeyModifiers::NONE), None);
+    }
+}
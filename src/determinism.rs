@@ -0,0 +1,102 @@
+use crate::error::{BundlerError, Result};
+
+/// Run `bundle` several times and fail if any output differs from the first
+///
+/// Transforms must not depend on `HashMap`/`HashSet` iteration order. The standard
+/// library gives every new map keys differing from those of the maps created before
+/// it, so the maps a run builds iterate in another order than in the previous runs,
+/// and a pass that emits items in hash order will almost always produce a different
+/// output within a few runs. `bundle` receives the run index (starting at 0). At
+/// least two runs are always performed.
+///
+/// Returns the (identical) output of the runs.
+///
+/// # Errors
+/// Returns the first bundling error, or `BundlerError::Nondeterministic` describing
+/// the first line at which a run diverged from the first one
+pub fn check_determinism<F>(runs: usize, mut bundle: F) -> Result<String>
+where
+    F: FnMut(usize) -> Result<String>,
+{
+    let expected = bundle(0)?;
+
+    for run in 1..runs.max(2) {
+        let actual = bundle(run)?;
+        if let Some((line, expected_line, actual_line)) = first_difference(&expected, &actual) {
+            return Err(BundlerError::Nondeterministic {
+                run: run + 1,
+                line,
+                expected: expected_line,
+                actual: actual_line,
+            });
+        }
+    }
+
+    Ok(expected)
+}
+
+/// First differing line (1-based) and its two versions, or `None` if equal
+fn first_difference(expected: &str, actual: &str) -> Option<(usize, String, String)> {
+    if expected == actual {
+        return None;
+    }
+
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(a), Some(b)) if a == b => line += 1,
+            (a, b) => {
+                return Some((
+                    line,
+                    a.unwrap_or_default().to_string(),
+                    b.unwrap_or_default().to_string(),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_identical_runs_pass() {
+        let mut calls = 0;
+        let output = check_determinism(3, |_| {
+            calls += 1;
+            Ok("fn main() {}\n".to_string())
+        })
+        .unwrap();
+        assert_eq!(output, "fn main() {}\n");
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_hash_order_is_detected() {
+        let result = check_determinism(20, |_| {
+            let names: HashSet<String> = (0..32).map(|i| format!("fn f{i}() {{}}")).collect();
+            Ok(names.into_iter().collect::<Vec<_>>().join("\n"))
+        });
+        assert!(matches!(
+            result,
+            Err(BundlerError::Nondeterministic { line: 1.., .. })
+        ));
+    }
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(first_difference("a\nb\nc", "a\nb\nc"), None);
+        assert_eq!(
+            first_difference("a\nb\nc", "a\nx\nc"),
+            Some((2, "b".to_string(), "x".to_string()))
+        );
+        assert_eq!(
+            first_difference("a\nb", "a"),
+            Some((2, "b".to_string(), String::new()))
+        );
+    }
+}
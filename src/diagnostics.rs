@@ -0,0 +1,488 @@
+use serde::Serialize;
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+        })
+    }
+}
+
+/// A range of source text; lines and columns are 1-based, columns count
+/// characters and the end is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    /// Span of the tokens a `syn` parse error points at
+    #[must_use]
+    pub fn of_syn_error(error: &syn::Error) -> Self {
+        let span = error.span();
+        let (start, end) = (span.start(), span.end());
+        Self {
+            line: start.line,
+            column: start.column + 1,
+            end_line: end.line,
+            end_column: end.column + 1,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Longest line shown whole in a code frame; longer ones, as in minified code,
+/// are cut around the span
+const MAX_FRAME_WIDTH: usize = 100;
+
+/// Lines of `code` at `span`, numbered and with the start of the span underlined,
+/// like the snippets of compiler messages:
+///
+/// ```text
+///   |
+/// 7 |     pub fn area() -> u32 {
+/// 8 |         missing x
+///   |                 ^
+/// ```
+///
+/// The line before the span is shown for context, and at most three lines of the
+/// span itself.
+#[must_use]
+pub fn code_frame(code: &str, span: Span) -> String {
+    let lines: Vec<&str> = code.lines().collect();
+    let line = span.line.clamp(1, lines.len().max(1));
+    let first = line.saturating_sub(1).max(1);
+    let last = span.end_line.clamp(line, line + 2).min(lines.len().max(1));
+    let width = last.to_string().len();
+
+    let text = |number: usize| lines.get(number - 1).copied().unwrap_or("");
+    // Characters cut from the start of every line, so that the span shows
+    let length = text(line).chars().count();
+    let skipped = if length > MAX_FRAME_WIDTH {
+        span.column
+            .saturating_sub(MAX_FRAME_WIDTH / 2)
+            .min(length - MAX_FRAME_WIDTH)
+    } else {
+        0
+    };
+    let cut = |text: &str| {
+        let shown: String = text.chars().skip(skipped).take(MAX_FRAME_WIDTH).collect();
+        let before = if skipped > 0 { "..." } else { "" };
+        let after = if text.chars().count() > skipped + MAX_FRAME_WIDTH {
+            "..."
+        } else {
+            ""
+        };
+        format!("{before}{shown}{after}")
+    };
+
+    let mut frame = format!("{:width$} |\n", "");
+    for number in first..=last {
+        let _ = writeln!(frame, "{number:>width$} | {}", cut(text(number)).trim_end());
+        if number == line {
+            let start = span.column.saturating_sub(1).saturating_sub(skipped);
+            let end = if span.end_line == span.line {
+                span.end_column.saturating_sub(1).saturating_sub(skipped)
+            } else {
+                length.saturating_sub(skipped)
+            };
+            let indent = start.min(MAX_FRAME_WIDTH) + if skipped > 0 { 3 } else { 0 };
+            let carets = end.saturating_sub(start).clamp(1, MAX_FRAME_WIDTH);
+            let _ = writeln!(
+                frame,
+                "{:width$} | {:indent$}{}",
+                "",
+                "",
+                "^".repeat(carets)
+            );
+        }
+    }
+    frame
+}
+
+/// An error or warning with its code, location and related notes, as reported to
+/// editors with `--format json`
+///
+/// Built from errors with `BundlerError::diagnostic`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// Stable code of the kind of problem, e.g. `CG0003` for a syntax error
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// File the problem is in, when known
+    pub file: Option<PathBuf>,
+    /// Position of the problem in `file`
+    pub span: Option<Span>,
+    /// Related information, such as other locations involved
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// `file:line:column` of the problem, or just the file when its span is unknown
+    #[must_use]
+    pub fn location(&self) -> Option<String> {
+        let file = self.file.as_ref()?.display();
+        Some(self.span.map_or_else(
+            || file.to_string(),
+            |span| format!("{file}:{}:{}", span.line, span.column),
+        ))
+    }
+}
+
+/// Rendered like compiler messages:
+///
+/// ```text
+/// error[CG0003]: expected `;`
+///   --> src/ai.rs:3:5
+///   = note: ...
+/// ```
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        if let Some(location) = self.location() {
+            write!(f, "\n  --> {location}")?;
+        }
+        self.notes
+            .iter()
+            .try_for_each(|note| write!(f, "\n  = note: {note}"))
+    }
+}
+
+/// Directories below the package searched for files that look like a missing
+/// target source
+const SEARCH_DEPTH: usize = 3;
+
+/// At most this many such files are listed
+const MAX_FOUND: usize = 5;
+
+/// Why the source file of the target to bundle was not found, and how the
+/// package or the command line could be fixed
+///
+/// Paths are relative to the package directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayoutDiagnosis {
+    /// What was looked for, e.g. `binary target 'bot'`
+    pub target: String,
+    /// Paths looked for, in order
+    pub tried: Vec<PathBuf>,
+    /// Files of the package that look like the missing source
+    pub found: Vec<PathBuf>,
+    /// Options to pass or manifest changes that would fix the problem
+    pub suggestions: Vec<String>,
+}
+
+impl LayoutDiagnosis {
+    /// Diagnose the target `name` of `kind` (`bin`, `example`, `test` or `lib`),
+    /// whose source `path` in the package at `package_dir` does not exist
+    ///
+    /// `targets` are the sources of the other targets of the package, which are
+    /// not suggested, and `others` the names of the other targets of `kind`.
+    #[must_use]
+    pub fn missing_target(
+        package_dir: &Path,
+        kind: &str,
+        name: &str,
+        path: &Path,
+        targets: &[PathBuf],
+        others: &[String],
+    ) -> Self {
+        let target = format!("{} '{name}'", kind_name(kind));
+        let relative = |path: &Path| path.strip_prefix(package_dir).unwrap_or(path).to_path_buf();
+        let found: Vec<PathBuf> = entry_candidates(package_dir, kind)
+            .into_iter()
+            .filter(|candidate| !targets.iter().any(|target| relative(target) == *candidate))
+            .collect();
+        let section = match kind {
+            "lib" => "[lib]".to_string(),
+            kind => format!("the [[{kind}]] section named '{name}'"),
+        };
+
+        let mut suggestions = Vec::new();
+        match found.first() {
+            Some(candidate) => {
+                suggestions.push(format!(
+                    "set `path = \"{}\"` in {section} of Cargo.toml",
+                    slashed(candidate)
+                ));
+                suggestions.extend(watch_hint(candidate));
+            }
+            None => suggestions.push(format!(
+                "create {}, or set `path` in {section} of Cargo.toml to the target's source",
+                slashed(&relative(path))
+            )),
+        }
+        if let (Some(flag), false) = (selection_flag(kind), others.is_empty()) {
+            suggestions.push(format!(
+                "or bundle another {} with {flag} NAME: {}",
+                kind_name(kind),
+                others.join(", ")
+            ));
+        }
+        Self {
+            target,
+            tried: vec![relative(path)],
+            found,
+            suggestions,
+        }
+    }
+
+    /// Diagnose a package at `package_dir` without a binary target: its manifest
+    /// declares none and Cargo found none where it looks, `src/main.rs` and
+    /// `src/bin`
+    ///
+    /// Returns `None` if the package has no file defining `fn main` either.
+    #[must_use]
+    pub fn missing_binary(package_dir: &Path, package: Option<&str>) -> Option<Self> {
+        let found = entry_candidates(package_dir, "bin");
+        let candidate = found.first()?;
+        let name = package.map_or_else(String::new, |name| format!("name = \"{name}\" and "));
+        let mut suggestions = vec![format!(
+            "add a [[bin]] section with {name}`path = \"{}\"` to Cargo.toml, or move the file to src/main.rs",
+            slashed(candidate)
+        )];
+        suggestions.extend(watch_hint(candidate));
+        Some(Self {
+            target: "a binary target".to_string(),
+            tried: vec![PathBuf::from("src/main.rs"), PathBuf::from("src/bin")],
+            found,
+            suggestions,
+        })
+    }
+
+    /// The paths tried, the files found and the suggestions, one per line
+    #[must_use]
+    pub fn notes(&self) -> Vec<String> {
+        let tried = self
+            .tried
+            .iter()
+            .map(|path| slashed(path))
+            .collect::<Vec<_>>();
+        let mut notes = vec![format!("looked for: {}", tried.join(", "))];
+        if !self.found.is_empty() {
+            let found = self
+                .found
+                .iter()
+                .map(|path| slashed(path))
+                .collect::<Vec<_>>();
+            notes.push(format!("found: {}", found.join(", ")));
+        }
+        notes.extend(self.suggestions.iter().map(|help| format!("help: {help}")));
+        notes
+    }
+}
+
+/// The first line, then the notes indented below it:
+///
+/// ```text
+/// No source file found for binary target 'bot'
+///   looked for: bots/main.rs
+///   found: bot/main.rs
+///   help: set `path = "bot/main.rs"` in the [[bin]] section named 'bot' of Cargo.toml
+/// ```
+impl fmt::Display for LayoutDiagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No source file found for {}", self.target)?;
+        self.notes()
+            .iter()
+            .try_for_each(|note| write!(f, "\n  {note}"))
+    }
+}
+
+fn kind_name(kind: &str) -> &'static str {
+    match kind {
+        "example" => "example target",
+        "test" => "test target",
+        "lib" => "library target",
+        _ => "binary target",
+    }
+}
+
+/// Option selecting another target of `kind`
+fn selection_flag(kind: &str) -> Option<&'static str> {
+    match kind {
+        "bin" => Some("--bin"),
+        "example" => Some("--include-examples"),
+        "test" => Some("--test"),
+        _ => None,
+    }
+}
+
+/// `--src-dir`, for watch mode, when `candidate` is outside `src`
+fn watch_hint(candidate: &Path) -> Option<String> {
+    let dir = candidate.parent().filter(|dir| !dir.starts_with("src"))?;
+    let dir = if dir.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        slashed(dir)
+    };
+    Some(format!("watch its directory with --watch --src-dir {dir}"))
+}
+
+/// `path` with `/` separators, as written in Cargo.toml
+fn slashed(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Files below `package_dir`, relative to it, that look like the source of a
+/// target of `kind`: `lib.rs` files for libraries, `.rs` files defining
+/// `fn main` otherwise
+///
+/// Hidden directories and `target` are skipped.
+fn entry_candidates(package_dir: &Path, kind: &str) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut dirs = vec![(PathBuf::new(), 0)];
+    while let Some((dir, depth)) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(package_dir.join(&dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let path = dir.join(&*name);
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if depth < SEARCH_DEPTH && !name.starts_with('.') && name != "target" {
+                    dirs.push((path, depth + 1));
+                }
+            } else if kind == "lib" {
+                if name == "lib.rs" {
+                    found.push(path);
+                }
+            } else if Path::new(&*name).extension().is_some_and(|ext| ext == "rs")
+                && fs::read_to_string(entry.path()).is_ok_and(|code| code.contains("fn main("))
+            {
+                found.push(path);
+            }
+        }
+    }
+    // Shallowest first, as the most likely to be the target's
+    found.sort_by_key(|path| (path.components().count(), path.clone()));
+    found.truncate(MAX_FOUND);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::BundlerError;
+
+    #[test]
+    fn test_parse_error_diagnostic() {
+        let error = syn::parse_file("fn main() {\n    let x = 1\n}\n").unwrap_err();
+        let error = BundlerError::SyntaxError {
+            message: error.to_string(),
+            file_path: Some(PathBuf::from("src/main.rs")),
+            span: Span::of_syn_error(&error),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Parsing error in 'src/main.rs' at line 3, column 1: expected `;`"
+        );
+
+        let diagnostic = error.diagnostic();
+        assert_eq!(diagnostic.code, "CG0003");
+        assert_eq!(diagnostic.location().as_deref(), Some("src/main.rs:3:1"));
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[CG0003]: expected `;`\n  --> src/main.rs:3:1"
+        );
+        let json = serde_json::to_value(&diagnostic).unwrap();
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["span"]["line"], 3);
+    }
+
+    #[test]
+    fn test_collision_notes() {
+        let diagnostic = BundlerError::NameCollision {
+            collisions: vec!["`helper` at src/lib.rs:3 and src/main.rs:7".to_string()],
+        }
+        .diagnostic();
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[CG0009]: Items collide after expansion (see --rename-collisions)\n  = note: `helper` at src/lib.rs:3 and src/main.rs:7"
+        );
+    }
+
+    #[test]
+    fn test_missing_target() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for (path, code) in [
+            ("src/lib.rs", "pub fn f() {}"),
+            ("bot/main.rs", "fn main() {}"),
+            ("tools/gen.rs", "fn main() {}"),
+            ("target/debug/build.rs", "fn main() {}"),
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, code).unwrap();
+        }
+
+        let diagnosis = LayoutDiagnosis::missing_target(
+            dir.path(),
+            "bin",
+            "bot",
+            &dir.path().join("src/main.rs"),
+            &[dir.path().join("tools/gen.rs")],
+            &["gen".to_string()],
+        );
+        assert_eq!(
+            diagnosis.to_string(),
+            "No source file found for binary target 'bot'\n  looked for: src/main.rs\n  found: bot/main.rs\n  help: set `path = \"bot/main.rs\"` in the [[bin]] section named 'bot' of Cargo.toml\n  help: watch its directory with --watch --src-dir bot\n  help: or bundle another binary target with --bin NAME: gen"
+        );
+
+        let diagnosis = LayoutDiagnosis::missing_binary(dir.path(), Some("bot")).unwrap();
+        assert_eq!(
+            diagnosis.found,
+            [PathBuf::from("bot/main.rs"), PathBuf::from("tools/gen.rs")]
+        );
+        fs::remove_dir_all(dir.path().join("bot")).unwrap();
+        fs::remove_dir_all(dir.path().join("tools")).unwrap();
+        assert!(LayoutDiagnosis::missing_binary(dir.path(), None).is_none());
+    }
+
+    #[test]
+    fn test_code_frame() {
+        let code = "fn main() {\n    let x = 1\n    x\n}\n";
+        let error = syn::parse_file(code).unwrap_err();
+        assert_eq!(
+            code_frame(code, Span::of_syn_error(&error)),
+            "  |\n2 |     let x = 1\n3 |     x\n  |     ^\n"
+        );
+
+        let minified = format!("fn main() {{ {} let x = 1 x }}", "let _ = 0; ".repeat(20));
+        let error = syn::parse_file(&minified).unwrap_err();
+        let frame = code_frame(&minified, Span::of_syn_error(&error));
+        let lines: Vec<&str> = frame.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("1 | ...") && lines[1].ends_with("let x = 1 x }"));
+        assert_eq!(
+            lines[2].find('^'),
+            lines[1].rfind(" x }").map(|space| space + 1)
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt::Write;
+
+/// Unchanged lines shown around each change
+const CONTEXT: usize = 3;
+
+/// Most lines compared pairwise between the first and last change; beyond it the
+/// lines in between are reported as replaced as a whole
+const MAX_COMPARED: usize = 4_000_000;
+
+/// An edit turning the old text into the new one, with the index of its line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Keep(usize),
+    Remove(usize),
+    Add(usize),
+}
+
+/// Unified diff from `old` to `new`, as `diff -u` prints it, with `old_label` and
+/// `new_label` on the `---`/`+++` lines, or `None` if the texts are identical
+///
+/// Lines are compared with their line endings, so a missing final newline shows
+/// up as a change.
+#[must_use]
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = edits(&old, &new);
+
+    let mut diff = format!("--- {old_label}\n+++ {new_label}\n");
+    // Line of each text before each edit
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_line, mut new_line) = (0, 0);
+    for edit in &edits {
+        positions.push((old_line, new_line));
+        match edit {
+            Edit::Keep(_) => (old_line, new_line) = (old_line + 1, new_line + 1),
+            Edit::Remove(_) => old_line += 1,
+            Edit::Add(_) => new_line += 1,
+        }
+    }
+    positions.push((old_line, new_line));
+
+    for (start, end) in hunks(&edits) {
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        let _ = writeln!(
+            diff,
+            "@@ -{} +{} @@",
+            range(old_start, old_end - old_start),
+            range(new_start, new_end - new_start)
+        );
+        for edit in &edits[start..end] {
+            let (sign, line) = match *edit {
+                Edit::Keep(index) => (' ', old[index]),
+                Edit::Remove(index) => ('-', old[index]),
+                Edit::Add(index) => ('+', new[index]),
+            };
+            diff.push(sign);
+            diff.push_str(line);
+            if !line.ends_with('\n') {
+                diff.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    Some(diff)
+}
+
+/// A hunk range: its first line (1-based, or the line before for an empty range)
+/// and number of lines
+fn range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{start},0")
+    } else {
+        format!("{},{count}", start + 1)
+    }
+}
+
+/// Edits of a shortest edit script from `old` to `new`
+fn edits(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+
+    let mut edits: Vec<Edit> = (0..prefix).map(Edit::Keep).collect();
+    let (rows, columns) = (old_end - prefix, new_end - prefix);
+    if rows.saturating_mul(columns) > MAX_COMPARED {
+        edits.extend((prefix..old_end).map(Edit::Remove));
+        edits.extend((prefix..new_end).map(Edit::Add));
+    } else {
+        let (old_lines, new_lines) = (&old[prefix..old_end], &new[prefix..new_end]);
+        // Length of the longest common subsequence of the lines after `i` and `j`
+        let mut common = vec![vec![0_u32; columns + 1]; rows + 1];
+        for i in (0..rows).rev() {
+            for j in (0..columns).rev() {
+                common[i][j] = if old_lines[i] == new_lines[j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < rows || j < columns {
+            if old_lines
+                .get(i)
+                .is_some_and(|line| new_lines.get(j) == Some(line))
+            {
+                edits.push(Edit::Keep(prefix + i));
+                (i, j) = (i + 1, j + 1);
+            } else if j == columns || (i < rows && common[i + 1][j] >= common[i][j + 1]) {
+                edits.push(Edit::Remove(prefix + i));
+                i += 1;
+            } else {
+                edits.push(Edit::Add(prefix + j));
+                j += 1;
+            }
+        }
+    }
+    edits.extend((old_end..old.len()).map(Edit::Keep));
+    edits
+}
+
+/// Ranges of `edits` shown as hunks: the changes with `CONTEXT` kept lines around
+/// them, merged when their contexts touch
+fn hunks(edits: &[Edit]) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (index, edit) in edits.iter().enumerate() {
+        if matches!(edit, Edit::Keep(_)) {
+            continue;
+        }
+        let start = index.saturating_sub(CONTEXT);
+        let end = (index + 1 + CONTEXT).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if last.1 >= start => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+        assert_eq!(unified_diff(old, old, "old", "new"), None);
+        assert_eq!(
+            unified_diff(old, new, "bundle.rs", "bundle.rs (current sources)").unwrap(),
+            "--- bundle.rs\n+++ bundle.rs (current sources)\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -10,3 +10,4 @@\n j\n k\n l\n+m\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_final_newline_and_empty_text() {
+        assert_eq!(
+            unified_diff("fn main() {}\n", "fn main() {}", "a", "b").unwrap(),
+            "--- a\n+++ b\n@@ -1,1 +1,1 @@\n-fn main() {}\n+fn main() {}\n\\ No newline at end of file\n"
+        );
+        assert_eq!(
+            unified_diff("", "x\n", "a", "b").unwrap(),
+            "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+x\n"
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::transformer::line_markers::{self, Marker};
+
+/// Functions smaller than this many characters are not reported: short helpers
+/// such as getters are alike without being worth merging
+const MIN_CHARACTERS: usize = 60;
+
+/// Whether the functions of a group are copies or alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateKind {
+    /// Same parameters, return type and body
+    Identical,
+    /// Same code but for the names of variables, functions or types and the
+    /// values of literals, e.g. a strategy copied and tweaked
+    Similar,
+}
+
+/// A function of the bundle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunctionLocation {
+    /// Path from the bundle root, e.g. `ai::Greedy::score`
+    pub function: String,
+    /// Source file, relative to the project root, if line markers attribute it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+/// Functions of the bundle with the same code
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// In bundle order
+    pub functions: Vec<FunctionLocation>,
+    /// Characters of the smallest of them, as tokens separated by single spaces
+    pub characters: usize,
+    /// Characters saved by keeping a single copy
+    pub recoverable: usize,
+}
+
+/// Duplicated functions of a bundle and how many characters merging them would
+/// save
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateReport {
+    /// Characters of the bundle
+    pub characters: usize,
+    /// Most recoverable first
+    pub groups: Vec<DuplicateGroup>,
+    /// Sum of the characters recoverable from each group
+    pub recoverable: usize,
+}
+
+impl DuplicateReport {
+    /// Find the duplicated functions of `file`, a bundle transformed with line
+    /// markers, rendered in `characters` characters
+    #[must_use]
+    pub fn new(file: &syn::File, characters: usize) -> Self {
+        let mut functions = Vec::new();
+        collect(&file.items, &mut Vec::new(), None, &mut functions);
+
+        let mut by_shape: HashMap<&str, Vec<&Function>> = HashMap::new();
+        for function in functions
+            .iter()
+            .filter(|function| function.characters >= MIN_CHARACTERS)
+        {
+            by_shape.entry(&function.shape).or_default().push(function);
+        }
+        let mut groups: Vec<DuplicateGroup> = by_shape
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|group| {
+                let identical = group.iter().all(|function| function.code == group[0].code);
+                let characters = group.iter().map(|function| function.characters).min();
+                let characters = characters.unwrap_or_default();
+                DuplicateGroup {
+                    kind: if identical {
+                        DuplicateKind::Identical
+                    } else {
+                        DuplicateKind::Similar
+                    },
+                    recoverable: characters * (group.len() - 1),
+                    characters,
+                    functions: group
+                        .into_iter()
+                        .map(|function| function.location.clone())
+                        .collect(),
+                }
+            })
+            .collect();
+        groups.sort_by(|a, b| {
+            b.recoverable
+                .cmp(&a.recoverable)
+                .then_with(|| a.functions[0].function.cmp(&b.functions[0].function))
+        });
+
+        Self {
+            characters,
+            recoverable: groups.iter().map(|group| group.recoverable).sum(),
+            groups,
+        }
+    }
+
+    /// Human-readable report: one block per group, with its functions
+    #[must_use]
+    pub fn to_table(&self) -> String {
+        if self.groups.is_empty() {
+            return "No duplicated functions found\n".to_string();
+        }
+        let tenths = (self.recoverable * 1000)
+            .checked_div(self.characters)
+            .unwrap_or(0);
+        let mut table = format!(
+            "{} group(s) of duplicated functions, about {} of {} characters recoverable ({}.{}%)\n",
+            self.groups.len(),
+            self.recoverable,
+            self.characters,
+            tenths / 10,
+            tenths % 10
+        );
+        for group in &self.groups {
+            let kind = match group.kind {
+                DuplicateKind::Identical => "Identical",
+                DuplicateKind::Similar => "Similar, but for names and literals",
+            };
+            let _ = writeln!(
+                table,
+                "\n{kind}: {} x {} characters, {} recoverable",
+                group.functions.len(),
+                group.characters,
+                group.recoverable
+            );
+            for location in &group.functions {
+                match &location.file {
+                    Some(file) => {
+                        let _ = writeln!(table, "  {} ({file})", location.function);
+                    }
+                    None => {
+                        let _ = writeln!(table, "  {}", location.function);
+                    }
+                }
+            }
+        }
+        table
+    }
+}
+
+/// A function and its code, as written and with names and literals abstracted
+struct Function {
+    location: FunctionLocation,
+    /// Parameters, return type and body
+    code: String,
+    /// `code` with each distinct identifier numbered in order of appearance and
+    /// every literal replaced
+    shape: String,
+    characters: usize,
+}
+
+impl Function {
+    fn new(function: String, file: Option<&str>, sig: &syn::Signature, body: &syn::Block) -> Self {
+        let mut tokens = TokenStream::new();
+        sig.inputs.to_tokens(&mut tokens);
+        sig.output.to_tokens(&mut tokens);
+        body.to_tokens(&mut tokens);
+
+        let code = tokens.to_string();
+        let mut shape = String::new();
+        abstract_tokens(tokens, &mut HashMap::new(), &mut shape);
+        Self {
+            location: FunctionLocation {
+                function,
+                file: file.map(str::to_string),
+            },
+            characters: code.chars().count(),
+            code,
+            shape,
+        }
+    }
+}
+
+/// Add the functions of `items`, in the module at `module`, to `functions`
+fn collect(
+    items: &[syn::Item],
+    module: &mut Vec<String>,
+    mut file: Option<String>,
+    functions: &mut Vec<Function>,
+) {
+    let path = |module: &[String], name: String| {
+        module
+            .iter()
+            .cloned()
+            .chain(std::iter::once(name))
+            .collect::<Vec<_>>()
+            .join("::")
+    };
+    for item in items {
+        match item {
+            syn::Item::Fn(item_fn) => functions.push(Function::new(
+                path(module, item_fn.sig.ident.to_string()),
+                file.as_deref(),
+                &item_fn.sig,
+                &item_fn.block,
+            )),
+            syn::Item::Impl(item_impl) => {
+                let self_ty = item_impl.self_ty.to_token_stream().to_string();
+                let self_ty = self_ty.replace(' ', "");
+                for impl_item in &item_impl.items {
+                    if let syn::ImplItem::Fn(method) = impl_item {
+                        functions.push(Function::new(
+                            path(module, format!("{self_ty}::{}", method.sig.ident)),
+                            file.as_deref(),
+                            &method.sig,
+                            &method.block,
+                        ));
+                    }
+                }
+            }
+            syn::Item::Trait(item_trait) => {
+                for trait_item in &item_trait.items {
+                    if let syn::TraitItem::Fn(syn::TraitItemFn {
+                        sig,
+                        default: Some(body),
+                        ..
+                    }) = trait_item
+                    {
+                        functions.push(Function::new(
+                            path(module, format!("{}::{}", item_trait.ident, sig.ident)),
+                            file.as_deref(),
+                            sig,
+                            body,
+                        ));
+                    }
+                }
+            }
+            syn::Item::Mod(syn::ItemMod {
+                ident,
+                content: Some((_, content)),
+                ..
+            }) => {
+                module.push(ident.to_string());
+                collect(content, module, file.clone(), functions);
+                module.pop();
+            }
+            _ => {
+                if let Some(Marker::Open(path)) = line_markers::parse_marker(item) {
+                    file = Some(path);
+                }
+            }
+        }
+    }
+}
+
+/// Append `tokens` to `shape`, numbering identifiers with `names` and replacing
+/// literals, so that code differing only in those has the same shape
+fn abstract_tokens(tokens: TokenStream, names: &mut HashMap<String, usize>, shape: &mut String) {
+    for tree in tokens {
+        match tree {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    proc_macro2::Delimiter::Parenthesis => ("(", ")"),
+                    proc_macro2::Delimiter::Brace => ("{", "}"),
+                    proc_macro2::Delimiter::Bracket => ("[", "]"),
+                    proc_macro2::Delimiter::None => ("", ""),
+                };
+                shape.push_str(open);
+                abstract_tokens(group.stream(), names, shape);
+                shape.push_str(close);
+            }
+            TokenTree::Ident(ident) => {
+                let name = ident.to_string();
+                if is_keyword(&name) {
+                    let _ = write!(shape, " {name} ");
+                } else {
+                    let next = names.len();
+                    let _ = write!(shape, " _{} ", names.entry(name).or_insert(next));
+                }
+            }
+            TokenTree::Punct(punct) => shape.push(punct.as_char()),
+            TokenTree::Literal(_) => shape.push_str(" 0 "),
+        }
+    }
+}
+
+/// Whether `name` is a keyword, which is part of the shape of code
+fn is_keyword(name: &str) -> bool {
+    const KEYWORDS: [&str; 36] = [
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "while",
+    ];
+    KEYWORDS.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicates_found() {
+        let file = syn::parse_file(
+            r#"
+__cg_bundler_file!("src/ai/greedy.rs");
+mod greedy {
+    pub fn score(cells: &[u32]) -> u32 {
+        let mut total = 0;
+        for cell in cells { if *cell > 3 { total += cell * 2; } }
+        total
+    }
+}
+__cg_bundler_file!("src/ai/beam.rs");
+mod beam {
+    pub struct Beam;
+    impl Beam {
+        pub fn evaluate(values: &[u32]) -> u32 {
+            let mut sum = 0;
+            for value in values { if *value > 5 { sum += value * 2; } }
+            sum
+        }
+    }
+    pub fn score(cells: &[u32]) -> u32 {
+        let mut total = 0;
+        for cell in cells { if *cell > 3 { total += cell * 2; } }
+        total
+    }
+    fn small() -> u32 { 1 }
+    fn other() -> u32 { 1 }
+}
+fn main() {}
+"#,
+        )
+        .unwrap();
+        let report = DuplicateReport::new(&file, 1000);
+
+        assert_eq!(report.groups.len(), 1, "{report:#?}");
+        let group = &report.groups[0];
+        assert_eq!(group.kind, DuplicateKind::Similar);
+        assert_eq!(
+            group
+                .functions
+                .iter()
+                .map(|location| location.function.as_str())
+                .collect::<Vec<_>>(),
+            ["greedy::score", "beam::Beam::evaluate", "beam::score"]
+        );
+        assert_eq!(group.functions[1].file.as_deref(), Some("src/ai/beam.rs"));
+        assert_eq!(group.recoverable, 2 * group.characters);
+        assert_eq!(report.recoverable, group.recoverable);
+
+        let table = report.to_table();
+        assert!(
+            table.starts_with("1 group(s) of duplicated functions"),
+            "{table}"
+        );
+        assert!(
+            table.contains("  greedy::score (src/ai/greedy.rs)\n"),
+            "{table}"
+        );
+    }
+
+    #[test]
+    fn test_identical_functions() {
+        let file = syn::parse_file(
+            "mod a { pub fn f(x: u64) -> u64 { (x * 31 + 7) % 1_000_000_007 + x.pow(2) - x / 3 } }
+             mod b { pub fn g(x: u64) -> u64 { (x * 31 + 7) % 1_000_000_007 + x.pow(2) - x / 3 } }",
+        )
+        .unwrap();
+        let report = DuplicateReport::new(&file, 200);
+        assert_eq!(report.groups[0].kind, DuplicateKind::Identical);
+        assert_eq!(report.groups[0].functions[1].file, None);
+
+        let file = syn::parse_file("fn main() {}").unwrap();
+        assert_eq!(
+            DuplicateReport::new(&file, 12).to_table(),
+            "No duplicated functions found\n"
+        );
+    }
+}
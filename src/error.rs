@@ -2,6 +2,8 @@ use std::fmt;
 use std::io;
 use std::path::PathBuf;
 
+use crate::diagnostics::{Diagnostic, LayoutDiagnosis, Severity, Span};
+
 /// Custom error types for the cg-bundler application
 #[derive(Debug)]
 pub enum BundlerError {
@@ -20,17 +22,97 @@ pub enum BundlerError {
         message: String,
         file_path: Option<PathBuf>,
     },
+    /// Syntax errors at a known position, that of the offending tokens
+    SyntaxError {
+        message: String,
+        file_path: Option<PathBuf>,
+        span: Span,
+    },
     /// Project structure related errors
     ProjectStructure { message: String },
-    /// Multiple binary targets found (not supported)
+    /// Multiple binary targets found and none was selected
     MultipleBinaryTargets { target_count: usize },
     /// No binary target found
     NoBinaryTarget,
+    /// The library target was selected but the package has none
+    NoLibraryTarget,
+    /// The selected binary target does not exist
+    BinaryTargetNotFound {
+        name: String,
+        available: Vec<String>,
+    },
+    /// The selected example target does not exist
+    ExampleTargetNotFound {
+        name: String,
+        available: Vec<String>,
+    },
+    /// The selected test target does not exist
+    TestTargetNotFound {
+        name: String,
+        available: Vec<String>,
+    },
+    /// Items of a module bind the same name after expansion
+    NameCollision { collisions: Vec<String> },
     /// Multiple library targets found (not supported)
     MultipleLibraryTargets { target_count: usize },
+    /// Invalid configuration file or section
+    Config {
+        message: String,
+        path: Option<PathBuf>,
+    },
+    /// A custom transform pass failed
+    TransformPass { pass: String, message: String },
+    /// Minified output does not re-parse to the same tokens
+    MinifyVerification { message: String },
+    /// Repeated bundling runs produced different output
+    Nondeterministic {
+        run: usize,
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+    /// `--validate` found problems; they have already been reported
+    ValidationFailed { problem_count: usize },
+    /// A file to update in place has no generated-code sentinel line
+    MissingGeneratedSentinel { path: PathBuf },
+    /// The bundle does not compile; the compiler errors have already been reported
+    CompileCheckFailed { error_count: usize },
+    /// An existing bundle does not match the current sources; the differences have
+    /// already been reported
+    OutdatedOutput { path: PathBuf },
+    /// The bundle has more characters than the configured maximum
+    SizeLimitExceeded { characters: usize, limit: usize },
+    /// Running the package's build script failed
+    BuildScript { message: String },
+    /// The binary and the inlined library both define a top-level `fn main`,
+    /// each given as `file:line`
+    DuplicateMain { binary: String, library: String },
+    /// The bundle uses APIs forbidden by a `--lint` profile; the uses have already
+    /// been reported
+    LintFailed {
+        profile: &'static str,
+        violation_count: usize,
+    },
+    /// Uploading the bundle to `CodinGame` failed
+    Submission { message: String },
+    /// A bundle does not match its stored snapshot
+    SnapshotMismatch { path: PathBuf, diff: String },
+    /// A module has both a `name.rs` and a `name/mod.rs` file
+    AmbiguousModule { module: String, paths: Vec<PathBuf> },
+    /// The source file of the target to bundle is missing, or the package has
+    /// no target where Cargo looks for them
+    EntryNotFound { diagnosis: Box<LayoutDiagnosis> },
+    /// The bundle cannot be split into files of `limit` bytes: `what` is larger
+    /// on its own
+    SplitTooLarge {
+        what: String,
+        bytes: usize,
+        limit: usize,
+    },
 }
 
 impl fmt::Display for BundlerError {
+    #[allow(clippy::too_many_lines)]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Io { source, path } => {
@@ -43,12 +125,21 @@ impl fmt::Display for BundlerError {
             Self::CargoMetadata { message, .. } => {
                 write!(f, "Cargo metadata error: {message}")
             }
-            Self::Parsing { message, file_path } => {
-                if let Some(path) = file_path {
-                    write!(f, "Parsing error in '{}': {message}", path.display())
-                } else {
-                    write!(f, "Parsing error: {message}")
-                }
+            Self::Parsing {
+                message,
+                file_path: Some(path),
+            } => write!(f, "Parsing error in '{}': {message}", path.display()),
+            Self::SyntaxError {
+                message,
+                file_path: Some(path),
+                span,
+            } => write!(
+                f,
+                "Parsing error in '{}' at {span}: {message}",
+                path.display()
+            ),
+            Self::Parsing { message, .. } | Self::SyntaxError { message, .. } => {
+                write!(f, "Parsing error: {message}")
             }
             Self::ProjectStructure { message } => {
                 write!(f, "Project structure error: {message}")
@@ -56,19 +147,257 @@ impl fmt::Display for BundlerError {
             Self::MultipleBinaryTargets { target_count } => {
                 write!(
                     f,
-                    "Multiple binary targets found ({target_count}). Only single binary target is supported."
+                    "Multiple binary targets found ({target_count}). Select one with --bin NAME."
                 )
             }
             Self::NoBinaryTarget => {
                 write!(f, "No binary target found in the project")
             }
+            Self::NoLibraryTarget => write!(f, "No library target found in the project"),
+            Self::BinaryTargetNotFound { name, available } => {
+                write!(
+                    f,
+                    "No binary target named '{name}'. Available binaries: {}",
+                    available.join(", ")
+                )
+            }
+            Self::ExampleTargetNotFound { name, available } => write!(
+                f,
+                "No example target named '{name}'. Available examples: {}",
+                available.join(", ")
+            ),
+            Self::TestTargetNotFound { name, available } => write!(
+                f,
+                "No test target named '{name}'. Available tests: {}",
+                available.join(", ")
+            ),
+            Self::NameCollision { collisions } => {
+                write!(f, "Items collide after expansion (see --rename-collisions):")?;
+                collisions.iter().try_for_each(|collision| write!(f, "\n  {collision}"))
+            }
             Self::MultipleLibraryTargets { target_count } => {
                 write!(
                     f,
                     "Multiple library targets found ({target_count}). Only single library target is supported."
                 )
             }
+            Self::Config { message, path } => {
+                if let Some(path) = path {
+                    write!(f, "Configuration error in '{}': {message}", path.display())
+                } else {
+                    write!(f, "Configuration error: {message}")
+                }
+            }
+            Self::TransformPass { pass, message } => {
+                write!(f, "Transform pass '{pass}' failed: {message}")
+            }
+            Self::MinifyVerification { message } => {
+                write!(f, "Minified output does not round-trip: {message}")
+            }
+            Self::Nondeterministic {
+                run,
+                line,
+                expected,
+                actual,
+            } => {
+                write!(
+                    f,
+                    "Bundling is not deterministic: run {run} differs from run 1 at line {line}\n  run 1:   {expected}\n  run {run}: {actual}"
+                )
+            }
+            Self::ValidationFailed { problem_count } => {
+                write!(f, "Validation found {problem_count} problem(s)")
+            }
+            Self::MissingGeneratedSentinel { path } => {
+                write!(
+                    f,
+                    "'{}' has no generated-code sentinel line; add the line \"{}\" below the header to keep, or remove the file",
+                    path.display(),
+                    crate::file_manager::GENERATED_SENTINEL
+                )
+            }
+            Self::CompileCheckFailed { error_count } => {
+                write!(f, "Bundle does not compile: {error_count} error(s)")
+            }
+            Self::OutdatedOutput { path } => {
+                write!(f, "'{}' does not match the current sources", path.display())
+            }
+            Self::SizeLimitExceeded { characters, limit } => {
+                write!(
+                    f,
+                    "Bundle has {characters} characters, over the maximum of {limit}"
+                )
+            }
+            Self::BuildScript { message } => {
+                write!(f, "Build script error: {message}")
+            }
+            Self::DuplicateMain { binary, library } => {
+                write!(
+                    f,
+                    "Duplicate `fn main`: defined by the binary at {binary} and by the inlined library at {library}. \
+                     Move the library's `fn main` into a module or `src/bin/`, embed the library in a module with --lib-module-name NAME, \
+                     or select the intended binary with --bin NAME."
+                )
+            }
+            Self::LintFailed {
+                profile,
+                violation_count,
+            } => write!(
+                f,
+                "Bundle uses {violation_count} API(s) forbidden on {profile}"
+            ),
+            Self::Submission { message } => write!(f, "CodinGame submission failed: {message}"),
+            Self::SnapshotMismatch { path, diff } => write!(
+                f,
+                "Bundle does not match the snapshot '{}' (set {} to update it):\n{diff}",
+                path.display(),
+                crate::testing::UPDATE_VAR
+            ),
+            Self::AmbiguousModule { module, paths } => {
+                let paths: Vec<String> = paths
+                    .iter()
+                    .map(|path| format!("'{}'", path.display()))
+                    .collect();
+                write!(
+                    f,
+                    "File for module '{module}' found at both {}; delete or rename one of them",
+                    paths.join(" and ")
+                )
+            }
+            Self::EntryNotFound { diagnosis } => write!(f, "{diagnosis}"),
+            Self::SplitTooLarge { what, bytes, limit } => write!(
+                f,
+                "Cannot split the bundle into files of at most {limit} bytes: {what} is {bytes} bytes"
+            ),
+        }
+    }
+}
+
+impl BundlerError {
+    /// Stable code identifying the kind of error in diagnostics
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::Io { .. } => "CG0001",
+            Self::CargoMetadata { .. } => "CG0002",
+            Self::Parsing { .. } | Self::SyntaxError { .. } => "CG0003",
+            Self::ProjectStructure { .. } => "CG0004",
+            Self::MultipleBinaryTargets { .. } => "CG0005",
+            Self::NoBinaryTarget => "CG0006",
+            Self::BinaryTargetNotFound { .. } => "CG0007",
+            Self::ExampleTargetNotFound { .. } => "CG0008",
+            Self::NameCollision { .. } => "CG0009",
+            Self::MultipleLibraryTargets { .. } => "CG0010",
+            Self::Config { .. } => "CG0011",
+            Self::TransformPass { .. } => "CG0012",
+            Self::MinifyVerification { .. } => "CG0013",
+            Self::Nondeterministic { .. } => "CG0014",
+            Self::ValidationFailed { .. } => "CG0015",
+            Self::MissingGeneratedSentinel { .. } => "CG0016",
+            Self::CompileCheckFailed { .. } => "CG0017",
+            Self::OutdatedOutput { .. } => "CG0018",
+            Self::SizeLimitExceeded { .. } => "CG0019",
+            Self::BuildScript { .. } => "CG0020",
+            Self::DuplicateMain { .. } => "CG0021",
+            Self::NoLibraryTarget => "CG0022",
+            Self::LintFailed { .. } => "CG0023",
+            Self::Submission { .. } => "CG0024",
+            Self::SnapshotMismatch { .. } => "CG0025",
+            Self::TestTargetNotFound { .. } => "CG0026",
+            Self::AmbiguousModule { .. } => "CG0027",
+            Self::EntryNotFound { .. } => "CG0028",
+            Self::SplitTooLarge { .. } => "CG0029",
+        }
+    }
+
+    /// The error as a diagnostic, with its file and span split out of the message
+    #[must_use]
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic {
+            code: self.code(),
+            severity: Severity::Error,
+            message: self.to_string(),
+            file: None,
+            span: None,
+            notes: Vec::new(),
+        };
+        match self {
+            Self::Io {
+                source,
+                path: Some(path),
+            } => {
+                diagnostic.message = format!("IO error: {source}");
+                diagnostic.file = Some(path.clone());
+            }
+            Self::Parsing { message, file_path } => {
+                diagnostic.message.clone_from(message);
+                diagnostic.file.clone_from(file_path);
+            }
+            Self::SyntaxError {
+                message,
+                file_path,
+                span,
+            } => {
+                diagnostic.message.clone_from(message);
+                diagnostic.file.clone_from(file_path);
+                diagnostic.span = Some(*span);
+            }
+            Self::Config {
+                message,
+                path: Some(path),
+            } => {
+                diagnostic.message = format!("Configuration error: {message}");
+                diagnostic.file = Some(path.clone());
+            }
+            Self::MissingGeneratedSentinel { path }
+            | Self::OutdatedOutput { path }
+            | Self::SnapshotMismatch { path, .. } => {
+                diagnostic.file = Some(path.clone());
+            }
+            Self::NameCollision { collisions } => {
+                "Items collide after expansion (see --rename-collisions)"
+                    .clone_into(&mut diagnostic.message);
+                diagnostic.notes.clone_from(collisions);
+            }
+            Self::BinaryTargetNotFound { name, available } => {
+                diagnostic.message = format!("No binary target named '{name}'");
+                diagnostic.notes = vec![format!("available binaries: {}", available.join(", "))];
+            }
+            Self::ExampleTargetNotFound { name, available } => {
+                diagnostic.message = format!("No example target named '{name}'");
+                diagnostic.notes = vec![format!("available examples: {}", available.join(", "))];
+            }
+            Self::AmbiguousModule { module, paths } => {
+                diagnostic.message = format!("File for module '{module}' found at both candidates");
+                diagnostic.file = paths.first().cloned();
+                diagnostic.notes = paths
+                    .iter()
+                    .map(|path| format!("candidate: {}", path.display()))
+                    .chain(std::iter::once("delete or rename one of them".to_string()))
+                    .collect();
+            }
+            Self::TestTargetNotFound { name, available } => {
+                diagnostic.message = format!("No test target named '{name}'");
+                diagnostic.notes = vec![format!("available tests: {}", available.join(", "))];
+            }
+            Self::EntryNotFound { diagnosis } => {
+                diagnostic.message = format!("No source file found for {}", diagnosis.target);
+                diagnostic.notes = diagnosis.notes();
+            }
+            Self::DuplicateMain { binary, library } => {
+                "Duplicate `fn main` in the binary and the inlined library"
+                    .clone_into(&mut diagnostic.message);
+                diagnostic.notes = vec![
+                    format!("defined by the binary at {binary}"),
+                    format!("defined by the inlined library at {library}"),
+                    "move the library's `fn main` into a module or `src/bin/`, embed the \
+                     library with --lib-module-name NAME, or select the binary with --bin NAME"
+                        .to_string(),
+                ];
+            }
+            _ => {}
         }
+        diagnostic
     }
 }
 
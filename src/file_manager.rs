@@ -1,8 +1,19 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
-use std::path::{Path, PathBuf};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf, Prefix};
 
+use crate::crash_report;
 use crate::error::{BundlerError, Result};
+use crate::plan;
+use crate::transformer::comments;
+
+/// Comment line separating a hand-written header from the generated bundle in
+/// files written with `--update-in-place`
+pub const GENERATED_SENTINEL: &str =
+    "// ---- cg-bundler: generated code below, edits are overwritten ----";
 
 /// Utility struct for file operations
 pub struct FileManager;
@@ -14,6 +25,7 @@ impl FileManager {
     /// Returns an error if the file cannot be read
     pub fn read_file<P: AsRef<Path>>(path: P) -> Result<String> {
         let path = path.as_ref();
+        crash_report::note_file(path);
         let mut buf = String::new();
 
         let mut file = File::open(path).map_err(|e| BundlerError::Io {
@@ -40,38 +52,544 @@ impl FileManager {
         Self::read_file(path).ok()
     }
 
+    /// `path` made absolute with its symlinks resolved, as `fs::canonicalize` does,
+    /// without the verbatim prefix (`\\?\`) Windows gives it
+    ///
+    /// Paths of the file system, such as those in file watcher events, compare
+    /// equal to the result. See `normalize_path` for the prefix.
+    ///
+    /// # Errors
+    /// Returns an error if `path` does not exist
+    pub fn canonicalize<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+        std::fs::canonicalize(path).map(|path| strip_verbatim_prefix(&path))
+    }
+
+    /// `path` without `.` components, with each `..` removing the component before
+    /// it, with the separators of the platform and without a verbatim prefix:
+    /// `\\?\C:\bot` becomes `C:\bot` and `\\?\UNC\wsl$\Ubuntu\bot` becomes
+    /// `\\wsl$\Ubuntu\bot`
+    ///
+    /// The file system is not read, so a `..` after a symlink removes the link:
+    /// use this for paths shown to the user, not for paths to read.
+    #[must_use]
+    pub fn normalize_path(path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::new();
+        for component in strip_verbatim_prefix(path).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => match normalized.components().next_back() {
+                    Some(Component::Normal(_)) => {
+                        normalized.pop();
+                    }
+                    Some(Component::Prefix(_) | Component::RootDir) => {}
+                    _ => normalized.push(".."),
+                },
+                _ => normalized.push(component),
+            }
+        }
+        normalized
+    }
+
+    /// `relative`, a path written in the sources such as that of a `#[path]`
+    /// attribute or an `include_str!`, joined to `base`
+    ///
+    /// `/` separates the components of `relative` on every platform, as for
+    /// rustc, which Windows does not do after the verbatim prefix of a
+    /// canonicalized `base`; the prefix is removed.
+    #[must_use]
+    pub fn join_relative(base: &Path, relative: &str) -> PathBuf {
+        let mut path = strip_verbatim_prefix(base);
+        if Path::new(relative).has_root() {
+            return path.join(relative);
+        }
+        path.extend(
+            relative
+                .split('/')
+                .filter(|part| !part.is_empty() && *part != "."),
+        );
+        path
+    }
+
+    /// `path` as written in bundles and reports, with `/` separators on every
+    /// platform
+    #[must_use]
+    pub fn display_path(path: &Path) -> String {
+        path.to_string_lossy().replace('\\', "/")
+    }
+
+    /// Contents of `path` with everything below its `GENERATED_SENTINEL` line
+    /// replaced by `bundle`
+    ///
+    /// The header up to and including the sentinel line is kept byte for byte. A
+    /// missing file yields the sentinel followed by the bundle.
+    ///
+    /// # Errors
+    /// Returns an error if the file exists but cannot be read or has no sentinel line
+    pub fn replace_generated<P: AsRef<Path>>(path: P, bundle: &str) -> Result<String> {
+        let path = path.as_ref();
+        if !Self::file_exists(path) {
+            return Ok(format!("{GENERATED_SENTINEL}\n{bundle}"));
+        }
+
+        let existing = Self::read_file(path)?;
+        let mut header_len = None;
+        let mut offset = 0;
+        for line in existing.split_inclusive('\n') {
+            offset += line.len();
+            if line.trim_end() == GENERATED_SENTINEL {
+                header_len = Some(offset);
+                break;
+            }
+        }
+
+        let header_len = header_len.ok_or_else(|| BundlerError::MissingGeneratedSentinel {
+            path: path.to_path_buf(),
+        })?;
+        let mut content = existing[..header_len].to_string();
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(bundle);
+        Ok(content)
+    }
+
+    /// Write `contents` to `path` unless it already holds exactly them, so that an
+    /// unchanged output keeps its modification time; returns whether it was written
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be written
+    pub fn write_if_changed<P: AsRef<Path>>(path: P, contents: &str) -> Result<bool> {
+        let path = path.as_ref();
+        if std::fs::read(path).is_ok_and(|existing| existing == contents.as_bytes()) {
+            return Ok(false);
+        }
+        std::fs::write(path, contents).map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(path.to_path_buf()),
+        })?;
+        Ok(true)
+    }
+
+    /// Point `link` at `target`, replacing a link left by a previous run
+    ///
+    /// On Unix `link` is a symbolic link, relative when both paths share a
+    /// directory. Elsewhere, where creating symlinks may need privileges, `target`
+    /// is copied to `link`.
+    ///
+    /// # Errors
+    /// Returns an error if `link` exists and is not a symlink (on Unix), or the link
+    /// cannot be created
+    pub fn link_latest(link: &Path, target: &Path) -> Result<()> {
+        let io_error = |e| BundlerError::Io {
+            source: e,
+            path: Some(link.to_path_buf()),
+        };
+
+        #[cfg(unix)]
+        {
+            if let Ok(metadata) = std::fs::symlink_metadata(link) {
+                if !metadata.file_type().is_symlink() {
+                    return Err(io_error(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        "not replacing a file that is not a symlink",
+                    )));
+                }
+                std::fs::remove_file(link).map_err(io_error)?;
+            }
+            let destination = if link.parent() == target.parent() {
+                target.file_name().map_or(target, Path::new)
+            } else {
+                target
+            };
+            std::os::unix::fs::symlink(destination, link).map_err(io_error)
+        }
+
+        #[cfg(not(unix))]
+        {
+            std::fs::copy(target, link).map(|_| ()).map_err(io_error)
+        }
+    }
+
     /// Find a module file by trying different possible locations
     /// Returns (`base_path_for_submodules`, `file_content`)
     ///
     /// # Errors
     /// Returns an error if the module file cannot be found or read
     pub fn find_module_file(base_path: &Path, module_name: &str) -> Result<(PathBuf, String)> {
-        let possible_locations = vec![
+        OsFileProvider.find_module_file(base_path, module_name)
+    }
+
+    /// Locate a module file without reading it
+    /// Returns (`module_file_path`, `base_path_for_submodules`), or `None` if
+    /// neither candidate file exists or both do
+    #[must_use]
+    pub fn locate_module_file(base_path: &Path, module_name: &str) -> Option<(PathBuf, PathBuf)> {
+        OsFileProvider.locate_module_file(base_path, module_name)
+    }
+
+    /// Read the module files reachable from `roots`, (`crate_root_file`,
+    /// `base_path_for_submodules`) pairs, with up to `jobs` threads
+    ///
+    /// Each level of the module tree is read concurrently, finding the `mod name;`
+    /// declarations of a file with `plan::scan_mod_declarations` and the one file
+    /// each module is expanded from as the transformer does. With `keep_comments`,
+    /// the workers also prepare each file for parsing (see `comments::prepare`),
+    /// parsing it once more for each comment that cannot be kept; the final parse
+    /// is left to the bundling thread, as the spans of a syntax tree belong to the
+    /// thread that parsed it. Files that cannot be read or parsed are left out, to
+    /// be reported when the transformer reads them.
+    #[must_use]
+    pub fn prefetch(
+        roots: &[(PathBuf, PathBuf)],
+        jobs: usize,
+        keep_comments: bool,
+    ) -> PrefetchedSources {
+        let mut prefetched = PrefetchedSources {
+            keep_comments,
+            ..PrefetchedSources::default()
+        };
+        let mut seen: HashSet<PathBuf> = roots.iter().map(|(path, _)| path.clone()).collect();
+        let mut level = roots.to_vec();
+        while !level.is_empty() {
+            let chunk_size = level.len().div_ceil(jobs.max(1));
+            let read: Vec<ReadAhead> = std::thread::scope(|scope| {
+                // Every worker is spawned before the first is joined
+                #[allow(clippy::needless_collect)]
+                let workers: Vec<_> = level
+                    .chunks(chunk_size)
+                    .map(|chunk| {
+                        scope.spawn(move || {
+                            chunk
+                                .iter()
+                                .filter_map(|(path, base_path)| {
+                                    ReadAhead::new(path, base_path, keep_comments)
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().unwrap_or_default())
+                    .collect()
+            });
+
+            level = Vec::new();
+            for file in read {
+                let modules = file
+                    .modules
+                    .iter()
+                    .filter_map(|name| Self::locate_module_file(&file.base_path, name));
+                for (module_file, submodule_base) in modules {
+                    if seen.insert(module_file.clone()) {
+                        level.push((module_file, submodule_base));
+                    }
+                }
+                if let Some(prepared) = file.prepared {
+                    prefetched.prepared.insert(file.path.clone(), prepared);
+                }
+                prefetched.files.insert(file.path, file.code);
+            }
+        }
+        prefetched
+    }
+
+    /// Possible (`module_file_path`, `base_path_for_submodules`) pairs, in lookup order
+    fn module_file_candidates(base_path: &Path, module_name: &str) -> [(PathBuf, PathBuf); 2] {
+        [
             // Look for module_name.rs in base_path, submodules will be in base_path/module_name/
             (
-                base_path.to_path_buf(),
-                format!("{module_name}.rs"),
+                base_path.join(format!("{module_name}.rs")),
                 base_path.join(module_name),
             ),
             // Look for mod.rs in base_path/module_name/, submodules will be in base_path/module_name/
             (
-                base_path.join(module_name),
-                "mod.rs".to_string(),
+                base_path.join(module_name).join("mod.rs"),
                 base_path.join(module_name),
             ),
-        ];
+        ]
+    }
+}
+
+/// `path` with its verbatim prefix replaced by the prefix it stands for
+fn strip_verbatim_prefix(path: &Path) -> PathBuf {
+    let mut components = path.components();
+    if let Some(Component::Prefix(prefix)) = components.next() {
+        if let Some(simplified) = simplify_prefix(prefix.kind()) {
+            let mut path = PathBuf::from(simplified);
+            path.extend(components);
+            return path;
+        }
+    }
+    path.to_path_buf()
+}
+
+/// The prefix a verbatim `prefix` stands for, `None` if it is not verbatim or has
+/// no shorter form
+fn simplify_prefix(prefix: Prefix<'_>) -> Option<OsString> {
+    match prefix {
+        Prefix::VerbatimDisk(drive) => Some(format!("{}:", char::from(drive)).into()),
+        Prefix::VerbatimUNC(server, share) => {
+            let mut unc = OsString::from(r"\\");
+            unc.push(server);
+            unc.push(r"\");
+            unc.push(share);
+            Some(unc)
+        }
+        _ => None,
+    }
+}
+
+/// The first module file candidate of `module_name` that `read` reads
+/// Returns (`base_path_for_submodules`, `file_content`)
+fn find_module_file_with(
+    base_path: &Path,
+    module_name: &str,
+    read: impl Fn(&Path) -> Result<String>,
+) -> Result<(PathBuf, String)> {
+    let mut found = FileManager::module_file_candidates(base_path, module_name)
+        .into_iter()
+        .filter_map(|(full_path, submodule_base)| {
+            read(&full_path)
+                .ok()
+                .map(|content| (full_path, submodule_base, content))
+        });
+    match (found.next(), found.next()) {
+        (Some((_, submodule_base, content)), None) => Ok((submodule_base, content)),
+        (Some((first, ..)), Some((second, ..))) => {
+            Err(ambiguous_module(module_name, first, second))
+        }
+        (None, _) => Err(module_not_found(module_name)),
+    }
+}
+
+fn module_not_found(module_name: &str) -> BundlerError {
+    BundlerError::ProjectStructure {
+        message: format!("Module '{module_name}' not found in expected locations"),
+    }
+}
+
+fn ambiguous_module(module_name: &str, first: PathBuf, second: PathBuf) -> BundlerError {
+    BundlerError::AmbiguousModule {
+        module: module_name.to_string(),
+        paths: vec![first, second],
+    }
+}
+
+/// A file read by a worker of `FileManager::prefetch`
+struct ReadAhead {
+    path: PathBuf,
+    /// Directory of the modules the file declares
+    base_path: PathBuf,
+    code: String,
+    /// Names of the modules declared with `mod name;`
+    modules: Vec<String>,
+    /// The text to parse, from `comments::prepare`
+    prepared: Option<String>,
+}
+
+impl ReadAhead {
+    fn new(path: &Path, base_path: &Path, keep_comments: bool) -> Option<Self> {
+        let code = std::fs::read_to_string(path).ok()?;
+        let prepared = keep_comments
+            .then(|| comments::prepare(&code, true).ok())
+            .flatten();
+        Some(Self {
+            path: path.to_path_buf(),
+            base_path: base_path.to_path_buf(),
+            modules: plan::scan_mod_declarations(&code, false),
+            prepared,
+            code,
+        })
+    }
+}
+
+/// Source files read ahead by `FileManager::prefetch`, with the text each parses
+/// from when comments are kept
+#[derive(Debug, Clone, Default)]
+pub struct PrefetchedSources {
+    files: HashMap<PathBuf, String>,
+    /// Text from `comments::prepare`, by path
+    prepared: HashMap<PathBuf, String>,
+    /// Whether `prepared` keeps comments
+    keep_comments: bool,
+}
+
+impl PrefetchedSources {
+    /// Contents of the file at `path`, if it was read ahead
+    #[must_use]
+    pub fn get(&self, path: &Path) -> Option<&String> {
+        self.files.get(path)
+    }
+
+    /// Whether the file at `path` was read ahead
+    #[must_use]
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+}
 
-        for (file_base, file_name, submodule_base) in possible_locations {
-            let full_path = file_base.join(&file_name);
-            if let Ok(content) = Self::read_file(&full_path) {
-                return Ok((submodule_base, content));
+/// Files read ahead without preparing them for parsing
+impl From<HashMap<PathBuf, String>> for PrefetchedSources {
+    fn from(files: HashMap<PathBuf, String>) -> Self {
+        Self {
+            files,
+            ..Self::default()
+        }
+    }
+}
+
+/// Files read ahead, then the real file system
+impl FileProvider for PrefetchedSources {
+    fn read_file(&self, path: &Path) -> Result<String> {
+        self.get(path).map_or_else(
+            || FileManager::read_file(path),
+            |code| {
+                crash_report::note_file(path);
+                Ok(code.clone())
+            },
+        )
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.contains(path) || OsFileProvider.exists(path)
+    }
+
+    fn read_bytes(&self, path: &Path) -> Result<Vec<u8>> {
+        OsFileProvider.read_bytes(path)
+    }
+
+    fn parse_file(&self, path: &Path, code: &str, keep_comments: bool) -> syn::Result<syn::File> {
+        match self.prepared.get(path) {
+            Some(prepared)
+                if keep_comments == self.keep_comments
+                    && self.get(path).is_some_and(|read| read == code) =>
+            {
+                syn::parse_file(prepared)
             }
+            _ => comments::parse_file(code, keep_comments),
         }
+    }
+}
+
+/// Source of the files a bundle is made of, to bundle sources that are not on
+/// disk (an archive, a git object store, memory in tests)
+///
+/// Paths are those the bundler computes from the project layout, e.g.
+/// `/project/src/geo/mod.rs`. Only `read_file` and `exists` are required; the
+/// `Cargo.toml` of a package and vendored crates are still read from disk.
+pub trait FileProvider: Send + Sync {
+    /// Read a source file
+    ///
+    /// # Errors
+    /// Returns an error if the file does not exist or cannot be read
+    fn read_file(&self, path: &Path) -> Result<String>;
+
+    /// Whether there is a source file at `path`
+    fn exists(&self, path: &Path) -> bool;
 
-        Err(BundlerError::ProjectStructure {
-            message: format!("Module '{module_name}' not found in expected locations"),
+    /// Read a file as raw bytes, for `include_bytes!`
+    ///
+    /// # Errors
+    /// Returns an error if the file does not exist or cannot be read
+    fn read_bytes(&self, path: &Path) -> Result<Vec<u8>> {
+        self.read_file(path).map(String::into_bytes)
+    }
+
+    /// Parse `code`, read from `path`, as `comments::parse_file` does; providers
+    /// holding text prepared ahead with `comments::prepare` parse that instead
+    ///
+    /// # Errors
+    /// Returns the parse error of `code`
+    fn parse_file(&self, _path: &Path, code: &str, keep_comments: bool) -> syn::Result<syn::File> {
+        comments::parse_file(code, keep_comments)
+    }
+
+    /// Find and read the file of module `module_name` declared in `base_path`,
+    /// `module_name.rs` or `module_name/mod.rs`
+    /// Returns (`base_path_for_submodules`, `file_content`)
+    ///
+    /// # Errors
+    /// Returns an error if the module file cannot be found or read
+    fn find_module_file(&self, base_path: &Path, module_name: &str) -> Result<(PathBuf, String)> {
+        find_module_file_with(base_path, module_name, |path| self.read_file(path))
+    }
+
+    /// Locate the file of module `module_name` declared in `base_path` without
+    /// reading it, `module_name.rs` or `module_name/mod.rs` as rustc does, which
+    /// rejects modules with both
+    /// Returns (`module_file_path`, `base_path_for_submodules`)
+    ///
+    /// # Errors
+    /// Returns an error if neither file exists, or `AmbiguousModule` if both do
+    fn resolve_module_file(
+        &self,
+        base_path: &Path,
+        module_name: &str,
+    ) -> Result<(PathBuf, PathBuf)> {
+        let mut found = FileManager::module_file_candidates(base_path, module_name)
+            .into_iter()
+            .filter(|(full_path, _)| self.exists(full_path));
+        match (found.next(), found.next()) {
+            (Some(module), None) => Ok(module),
+            (Some((first, _)), Some((second, _))) => {
+                Err(ambiguous_module(module_name, first, second))
+            }
+            (None, _) => Err(module_not_found(module_name)),
+        }
+    }
+
+    /// Locate a module file without reading it, as `resolve_module_file` does
+    /// Returns (`module_file_path`, `base_path_for_submodules`), or `None` if
+    /// neither candidate file exists or both do
+    fn locate_module_file(
+        &self,
+        base_path: &Path,
+        module_name: &str,
+    ) -> Option<(PathBuf, PathBuf)> {
+        self.resolve_module_file(base_path, module_name).ok()
+    }
+}
+
+impl fmt::Debug for dyn FileProvider + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FileProvider")
+    }
+}
+
+/// The real file system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsFileProvider;
+
+impl FileProvider for OsFileProvider {
+    fn read_file(&self, path: &Path) -> Result<String> {
+        FileManager::read_file(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn read_bytes(&self, path: &Path) -> Result<Vec<u8>> {
+        std::fs::read(path).map_err(|e| BundlerError::Io {
+            source: e,
+            path: Some(path.to_path_buf()),
+        })
+    }
+}
+
+/// In-memory sources keyed by path
+impl<S: std::hash::BuildHasher + Send + Sync> FileProvider for HashMap<PathBuf, String, S> {
+    fn read_file(&self, path: &Path) -> Result<String> {
+        self.get(path).cloned().ok_or_else(|| BundlerError::Io {
+            source: io::Error::new(io::ErrorKind::NotFound, "no such in-memory source"),
+            path: Some(path.to_path_buf()),
         })
     }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.contains_key(path)
+    }
 }
 
 #[cfg(test)]
@@ -80,6 +598,82 @@ mod tests {
     use std::fs;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_prefetch_reads_module_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path();
+        fs::create_dir_all(src.join("ai/search")).unwrap();
+        fs::write(
+            src.join("main.rs"),
+            "// mod ignored;\nmod ai;\nmod missing;\nfn main() {}\n",
+        )
+        .unwrap();
+        fs::write(
+            src.join("ai/mod.rs"),
+            "pub mod search; const S: &str = \"mod no;\";",
+        )
+        .unwrap();
+        fs::write(src.join("ai/search.rs"), "pub mod beam;").unwrap();
+        fs::write(src.join("ai/search/beam.rs"), "pub fn width() {}").unwrap();
+        fs::write(src.join("unused.rs"), "").unwrap();
+
+        let files = FileManager::prefetch(&[(src.join("main.rs"), src.to_path_buf())], 4, false);
+        let mut paths: Vec<_> = files
+            .files
+            .keys()
+            .map(|path| path.strip_prefix(src).unwrap())
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                Path::new("ai/mod.rs"),
+                Path::new("ai/search/beam.rs"),
+                Path::new("ai/search.rs"),
+                Path::new("main.rs"),
+            ]
+        );
+        assert_eq!(
+            files.files[&src.join("ai/search/beam.rs")],
+            "pub fn width() {}"
+        );
+    }
+
+    #[test]
+    fn test_prefetch_resolves_modules_as_the_transformer() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path();
+        fs::create_dir_all(src.join("geo")).unwrap();
+        fs::create_dir_all(src.join("ai")).unwrap();
+        fs::write(src.join("main.rs"), "mod geo;\nmod ai;\nfn main() {}\n").unwrap();
+        // Both candidates of `geo` exist, which the transformer rejects
+        fs::write(src.join("geo.rs"), "").unwrap();
+        fs::write(src.join("geo/mod.rs"), "").unwrap();
+        fs::write(
+            src.join("ai.rs"),
+            "pub fn decide() {\n    // the best move\n}\n",
+        )
+        .unwrap();
+
+        let files = FileManager::prefetch(&[(src.join("main.rs"), src.to_path_buf())], 2, true);
+        assert!(files.contains(&src.join("ai.rs")));
+        assert!(!files.contains(&src.join("geo.rs")));
+        assert!(!files.contains(&src.join("geo/mod.rs")));
+
+        let path = src.join("ai.rs");
+        let code = files.get(&path).unwrap().clone();
+        assert!(files.prepared.contains_key(&path));
+        let parsed = |file: syn::File| quote::ToTokens::to_token_stream(&file).to_string();
+        assert_eq!(
+            parsed(files.parse_file(&path, &code, true).unwrap()),
+            parsed(comments::parse_file(&code, true).unwrap())
+        );
+        assert_eq!(
+            parsed(files.parse_file(&path, &code, false).unwrap()),
+            parsed(comments::parse_file(&code, false).unwrap())
+        );
+    }
+
     #[test]
     fn test_read_existing_file() {
         let temp_dir = TempDir::new().unwrap();
@@ -125,4 +719,173 @@ mod tests {
             Some(content.to_string())
         );
     }
+
+    #[test]
+    fn test_replace_generated_keeps_header() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("bundle.rs");
+
+        let created = FileManager::replace_generated(&file_path, "fn main() {}\n").unwrap();
+        assert_eq!(created, format!("{GENERATED_SENTINEL}\nfn main() {{}}\n"));
+
+        let header = format!("// Strategy: rush the center\r\n{GENERATED_SENTINEL}\r\n");
+        fs::write(&file_path, format!("{header}fn old() {{}}\n")).unwrap();
+        let updated = FileManager::replace_generated(&file_path, "fn main() {}\n").unwrap();
+        assert_eq!(updated, format!("{header}fn main() {{}}\n"));
+
+        fs::write(&file_path, "fn old() {}\n").unwrap();
+        assert!(matches!(
+            FileManager::replace_generated(&file_path, "fn main() {}\n"),
+            Err(BundlerError::MissingGeneratedSentinel { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_latest_replaces_previous_link() {
+        let temp_dir = TempDir::new().unwrap();
+        let link = temp_dir.path().join("latest.rs");
+        for name in ["old.rs", "new.rs"] {
+            fs::write(temp_dir.path().join(name), name).unwrap();
+            FileManager::link_latest(&link, &temp_dir.path().join(name)).unwrap();
+        }
+        assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("new.rs"));
+        assert_eq!(fs::read_to_string(&link).unwrap(), "new.rs");
+
+        let file = temp_dir.path().join("notes.rs");
+        fs::write(&file, "keep").unwrap();
+        assert!(FileManager::link_latest(&file, &temp_dir.path().join("new.rs")).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "keep");
+    }
+
+    #[test]
+    fn test_write_if_changed() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("bundle.rs");
+        assert!(FileManager::write_if_changed(&path, "fn main() {}\n").unwrap());
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert!(!FileManager::write_if_changed(&path, "fn main() {}\n").unwrap());
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        assert!(FileManager::write_if_changed(&path, "fn main() { run() }\n").unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() { run() }\n");
+    }
+
+    #[test]
+    fn test_memory_module_lookup() {
+        let files = HashMap::from([
+            (PathBuf::from("geo.rs"), "pub mod shapes;".to_string()),
+            (
+                PathBuf::from("geo/shapes/mod.rs"),
+                "pub struct Circle;".to_string(),
+            ),
+        ]);
+        let sources: &dyn FileProvider = &files;
+
+        let (base, content) = sources.find_module_file(Path::new(""), "geo").unwrap();
+        assert_eq!(base, PathBuf::from("geo"));
+        assert_eq!(content, "pub mod shapes;");
+
+        let (file, _) = sources.locate_module_file(&base, "shapes").unwrap();
+        assert_eq!(file, PathBuf::from("geo/shapes/mod.rs"));
+        assert!(sources.find_module_file(&base, "missing").is_err());
+    }
+
+    #[test]
+    fn test_ambiguous_module_files() {
+        let files = HashMap::from([
+            (PathBuf::from("ai.rs"), "pub mod search;".to_string()),
+            (PathBuf::from("ai/mod.rs"), "pub mod search;".to_string()),
+            (PathBuf::from("ai/search.rs"), "pub fn run() {}".to_string()),
+        ]);
+        let sources: &dyn FileProvider = &files;
+
+        match sources
+            .resolve_module_file(Path::new(""), "ai")
+            .unwrap_err()
+        {
+            BundlerError::AmbiguousModule { module, paths } => {
+                assert_eq!(module, "ai");
+                assert_eq!(
+                    paths,
+                    [PathBuf::from("ai.rs"), Path::new("ai").join("mod.rs")]
+                );
+            }
+            other => panic!("Expected AmbiguousModule error, got {other}"),
+        }
+        assert!(sources.locate_module_file(Path::new(""), "ai").is_none());
+        let error = sources.find_module_file(Path::new(""), "ai").unwrap_err();
+        assert!(
+            error.to_string().contains("found at both 'ai.rs' and"),
+            "{error}"
+        );
+        assert!(sources
+            .resolve_module_file(Path::new("ai"), "search")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        let normalize = |path: &str| FileManager::normalize_path(Path::new(path));
+        assert_eq!(
+            normalize("src/./ai/../geo.rs"),
+            Path::new("src").join("geo.rs")
+        );
+        assert_eq!(
+            normalize("src/../../shared"),
+            Path::new("..").join("shared")
+        );
+        assert_eq!(normalize("/../src"), Path::new("/src"));
+        assert_eq!(normalize(""), PathBuf::new());
+        // Joined paths keep `..` for the file system to resolve, across symlinks
+        assert_eq!(
+            FileManager::join_relative(Path::new("src/ai"), "../data/./map.txt"),
+            Path::new("src/ai/../data/map.txt")
+        );
+        assert_eq!(
+            FileManager::join_relative(Path::new("src"), "/out/gen.rs"),
+            Path::new("/out/gen.rs")
+        );
+        assert_eq!(
+            FileManager::display_path(&Path::new("src").join("ai").join("mod.rs")),
+            "src/ai/mod.rs"
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_prefixes() {
+        let normalize = |path: &str| FileManager::normalize_path(Path::new(path));
+        assert_eq!(
+            normalize(r"\\?\C:\bot\src\..\Cargo.toml"),
+            Path::new(r"C:\bot\Cargo.toml")
+        );
+        assert_eq!(
+            normalize(r"\\?\UNC\wsl$\Ubuntu\bot\src\main.rs"),
+            Path::new(r"\\wsl$\Ubuntu\bot\src\main.rs")
+        );
+        assert_eq!(
+            normalize(r"C:\bot\src/ai/mod.rs"),
+            Path::new(r"C:\bot\src\ai\mod.rs")
+        );
+        assert_eq!(
+            FileManager::join_relative(Path::new(r"\\?\UNC\wsl$\Ubuntu\bot\src"), "ai/geo.rs"),
+            Path::new(r"\\wsl$\Ubuntu\bot\src\ai\geo.rs")
+        );
+        assert_eq!(
+            FileManager::display_path(Path::new(r"\\wsl$\Ubuntu\bot\src\geo.rs")),
+            "//wsl$/Ubuntu/bot/src/geo.rs"
+        );
+
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}").unwrap();
+        let canonical = FileManager::canonicalize(dir.path()).unwrap();
+        assert!(
+            !canonical.to_string_lossy().starts_with(r"\\?\"),
+            "{canonical:?}"
+        );
+        assert!(canonical.join("main.rs").is_file());
+    }
 }
@@ -0,0 +1,216 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::diagnostics::Span;
+use crate::error::{BundlerError, Result};
+use crate::file_manager::FileManager;
+use crate::transformer::{comments, verbatim};
+
+/// Edition bundles are formatted for when their package's cannot be found
+pub const DEFAULT_EDITION: &str = "2021";
+
+/// Editions rustfmt and rustc accept
+pub const EDITIONS: [&str; 4] = ["2015", "2018", "2021", "2024"];
+
+/// Formats bundled code, registered with `Bundler::with_formatter`
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use cg_bundler::formatter::Rustfmt;
+/// use cg_bundler::Bundler;
+///
+/// let bundler = Bundler::new().with_formatter(Box::new(Rustfmt::new()));
+/// let bundled = bundler.bundle("./my_project").unwrap();
+/// ```
+pub trait Formatter {
+    /// Format `code`, a whole bundle, as Rust of `edition` (e.g. `2021`)
+    ///
+    /// # Errors
+    /// Returns an error if the code cannot be formatted
+    fn format(&self, code: &str, edition: &str) -> Result<String>;
+}
+
+/// Formats with the `rustfmt` found in `PATH`, honouring the `rustfmt.toml` of
+/// the working directory; verbatim regions are left as written
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rustfmt {
+    show_errors: bool,
+}
+
+impl Rustfmt {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { show_errors: false }
+    }
+
+    /// Pass the error output of `rustfmt` through to stderr instead of
+    /// discarding it
+    #[must_use]
+    pub const fn with_errors_shown(mut self, show_errors: bool) -> Self {
+        self.show_errors = show_errors;
+        self
+    }
+}
+
+impl Formatter for Rustfmt {
+    #[cfg(feature = "format")]
+    fn format(&self, code: &str, edition: &str) -> Result<String> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let rustfmt_error = |source| BundlerError::Io {
+            source,
+            path: Some("rustfmt".into()),
+        };
+        // Verbatim regions are hidden from rustfmt behind placeholders
+        let code = verbatim::mark(code).map_err(|e| BundlerError::Parsing {
+            message: format!("Failed to parse the bundle for formatting: {e}"),
+            file_path: None,
+        })?;
+        let mut child = Command::new("rustfmt")
+            .args(["--emit", "stdout", "--edition", edition])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(if self.show_errors {
+                Stdio::inherit()
+            } else {
+                Stdio::null()
+            })
+            .spawn()
+            .map_err(rustfmt_error)?;
+
+        if let Some(stdin) = child.stdin.as_mut() {
+            stdin.write_all(code.as_bytes()).map_err(rustfmt_error)?;
+        }
+
+        let output = child.wait_with_output().map_err(rustfmt_error)?;
+        if !output.status.success() {
+            return Err(rustfmt_error(io::Error::other(format!(
+                "rustfmt failed ({})",
+                output.status
+            ))));
+        }
+        String::from_utf8(output.stdout)
+            .map(|code| verbatim::restore(&code))
+            .map_err(|e| rustfmt_error(io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+
+    #[cfg(not(feature = "format"))]
+    fn format(&self, _code: &str, _edition: &str) -> Result<String> {
+        Err(BundlerError::Io {
+            source: io::Error::new(
+                io::ErrorKind::Unsupported,
+                "rustfmt support is not compiled in (enable the `format` feature)",
+            ),
+            path: None,
+        })
+    }
+}
+
+/// Formats with `prettyplease`, which needs no toolchain
+///
+/// Plain comments are kept, except where `comments::parse_file` drops them, and
+/// verbatim regions are left as written; the edition is ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrettyPlease;
+
+impl Formatter for PrettyPlease {
+    fn format(&self, code: &str, _edition: &str) -> Result<String> {
+        let file = comments::parse_file(code, true).map_err(|e| BundlerError::SyntaxError {
+            message: format!("Failed to parse the bundle for formatting: {e}"),
+            file_path: None,
+            span: Span::of_syn_error(&e),
+        })?;
+        let code = comments::restore(&prettyplease::unparse(&file));
+        Ok(verbatim::restore(&code))
+    }
+}
+
+/// Leaves the code as rendered by the bundler
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoFormatting;
+
+impl Formatter for NoFormatting {
+    fn format(&self, code: &str, _edition: &str) -> Result<String> {
+        Ok(code.to_string())
+    }
+}
+
+/// Edition of the package whose sources include `path`, from the nearest
+/// `Cargo.toml` at or above it
+///
+/// `edition.workspace = true` is resolved from `[workspace.package]` of a
+/// manifest further up, and a package without an edition is `2015`, as for
+/// Cargo. Returns `None` when no manifest is found or it cannot be read.
+#[must_use]
+pub fn detect_edition(path: &Path) -> Option<String> {
+    let path = FileManager::canonicalize(path).ok()?;
+    let mut inherited = false;
+    for dir in path.ancestors().filter(|dir| dir.is_dir()) {
+        let Ok(manifest) = fs::read_to_string(dir.join("Cargo.toml")) else {
+            continue;
+        };
+        let manifest = manifest.parse::<toml::Table>().ok()?;
+        if inherited {
+            let workspace_edition = manifest
+                .get("workspace")
+                .and_then(|workspace| workspace.get("package"))
+                .and_then(|package| package.get("edition"))
+                .and_then(toml::Value::as_str);
+            if let Some(edition) = workspace_edition {
+                return Some(edition.to_string());
+            }
+            continue;
+        }
+        match manifest
+            .get("package")
+            .map(|package| package.get("edition"))
+        {
+            Some(Some(toml::Value::String(edition))) => return Some(edition.clone()),
+            Some(Some(_)) => inherited = true,
+            Some(None) => return Some("2015".to_string()),
+            None => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detect_edition() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let member = root.join("bot/src");
+        fs::create_dir_all(&member).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"bot\"]\n\n[workspace.package]\nedition = \"2024\"\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("bot/Cargo.toml"),
+            "[package]\nname = \"bot\"\nedition.workspace = true\n",
+        )
+        .unwrap();
+        assert_eq!(detect_edition(&member).as_deref(), Some("2024"));
+
+        fs::write(root.join("bot/Cargo.toml"), "[package]\nname = \"bot\"\n").unwrap();
+        assert_eq!(detect_edition(&member).as_deref(), Some("2015"));
+    }
+
+    #[test]
+    fn test_prettyplease_keeps_comments() {
+        let code = "fn main(){// answer\nlet x=42;println!(\"{x}\");}";
+        assert_eq!(
+            PrettyPlease.format(code, DEFAULT_EDITION).unwrap(),
+            "fn main() {\n    // answer\n    let x = 42;\n    println!(\"{x}\");\n}\n"
+        );
+        assert_eq!(NoFormatting.format(code, "2015").unwrap(), code);
+    }
+}
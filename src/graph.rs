@@ -0,0 +1,434 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::transformer::line_markers::{self, Marker};
+use crate::transformer::SymbolGraph;
+
+/// Names of a use shown on an edge of an exported graph before the count of the
+/// others
+const EDGE_NAMES: usize = 3;
+
+/// Language of an exported module graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz, e.g. `cg-bundler --emit-graph dot | dot -Tsvg > modules.svg`
+    Dot,
+    /// Mermaid flowchart, rendered by GitHub in Markdown code blocks
+    Mermaid,
+}
+
+impl GraphFormat {
+    pub const ALL: [Self; 2] = [Self::Dot, Self::Mermaid];
+
+    /// Name of the format on the command line
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Dot => "dot",
+            Self::Mermaid => "mermaid",
+        }
+    }
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| format!("unknown graph format '{name}' (expected dot or mermaid)"))
+    }
+}
+
+/// A module of a bundle, with its items and submodules
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModuleNode {
+    /// `crate` for the bundle root
+    pub name: String,
+    /// Path of the module from the bundle root, e.g. `ai::search`, empty at the root
+    pub path: String,
+    /// Source file the module starts in, relative to the project root; inline
+    /// modules are in the file of their parent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Items of the module other than submodules, by kind, e.g. `fn` or `impl`
+    pub items: BTreeMap<String, usize>,
+    /// Characters of the module, submodules included, formatted with prettyplease
+    pub characters: usize,
+    /// Not expanded: a `mod name;` whose file was not bundled
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub external: bool,
+    pub modules: Vec<Self>,
+}
+
+/// Items of a module used by another, found by the reference analysis of dead
+/// code elimination
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModuleUse {
+    /// Path of the using module, empty at the root
+    pub from: String,
+    /// Path of the module defining the items
+    pub to: String,
+    /// Names of the items used
+    pub names: Vec<String>,
+}
+
+/// The module tree of a bundle and the source files it was built from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BundleGraph {
+    pub root: ModuleNode,
+    /// Source files of the bundle, relative to the project root, in bundle order
+    pub files: Vec<String>,
+    /// Uses of the items of a module by another, by using and used module path
+    pub uses: Vec<ModuleUse>,
+}
+
+impl BundleGraph {
+    /// Build the graph of `file`, a bundle transformed with line markers
+    #[must_use]
+    pub fn new(file: &syn::File) -> Self {
+        let mut files = Vec::new();
+        let root = ModuleNode::new("crate", String::new(), &file.items, None, &mut files);
+        let uses = SymbolGraph::build(file)
+            .module_uses()
+            .into_iter()
+            .map(|((from, to), names)| ModuleUse {
+                from,
+                to,
+                names: names.into_iter().collect(),
+            })
+            .collect();
+        Self { root, files, uses }
+    }
+
+    /// The modules as a graph in `format`: a node per module with its file and
+    /// size, a solid edge from each module to the modules it declares and a
+    /// dashed one to the modules whose items it uses, labeled with their names
+    #[must_use]
+    pub fn to_graph(&self, format: GraphFormat) -> String {
+        let mut modules = Vec::new();
+        collect_modules(&self.root, &mut modules);
+        let id = |path: &str| {
+            let index = modules.iter().position(|module| module.path == path);
+            format!("m{}", index.unwrap_or_default())
+        };
+
+        let mut graph = String::new();
+        match format {
+            GraphFormat::Dot => graph.push_str("digraph modules {\n    node [shape=box];\n"),
+            GraphFormat::Mermaid => graph.push_str("flowchart TD\n"),
+        }
+        for module in &modules {
+            let mut lines = vec![if module.path.is_empty() {
+                module.name.clone()
+            } else {
+                module.path.clone()
+            }];
+            lines.extend(module.file.clone());
+            lines.push(format!("{} characters", module.characters));
+            let id = id(&module.path);
+            let _ = match format {
+                GraphFormat::Dot => {
+                    writeln!(
+                        graph,
+                        "    {id} [label={}];",
+                        dot_string(&lines.join("\\n"))
+                    )
+                }
+                GraphFormat::Mermaid => writeln!(
+                    graph,
+                    "    {id}[\"{}\"]",
+                    mermaid_text(&lines.join("<br/>"))
+                ),
+            };
+        }
+        for module in &modules {
+            for child in &module.modules {
+                let (from, to) = (id(&module.path), id(&child.path));
+                let _ = match format {
+                    GraphFormat::Dot => writeln!(graph, "    {from} -> {to};"),
+                    GraphFormat::Mermaid => writeln!(graph, "    {from} --> {to}"),
+                };
+            }
+        }
+        for module_use in &self.uses {
+            let (from, to) = (id(&module_use.from), id(&module_use.to));
+            let label = edge_label(&module_use.names);
+            let _ = match format {
+                GraphFormat::Dot => writeln!(
+                    graph,
+                    "    {from} -> {to} [style=dashed, label={}];",
+                    dot_string(&label)
+                ),
+                GraphFormat::Mermaid => {
+                    writeln!(graph, "    {from} -.->|\"{}\"| {to}", mermaid_text(&label))
+                }
+            };
+        }
+        if format == GraphFormat::Dot {
+            graph.push_str("}\n");
+        }
+        graph
+    }
+
+    /// Human-readable tree, one line per module with its file, size and items
+    #[must_use]
+    pub fn to_tree(&self) -> String {
+        let mut tree = String::new();
+        let _ = writeln!(tree, "{}", self.root.label());
+        write_children(&mut tree, &self.root, "");
+        let _ = writeln!(tree, "\n{} source file(s)", self.files.len());
+        tree
+    }
+
+    /// Serialize the graph as pretty-printed JSON
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+impl ModuleNode {
+    /// Node of the module `name` at `path` made of `items`, in `parent_file` unless
+    /// a marker opens its own; files met along the way are added to `files`
+    fn new(
+        name: &str,
+        path: String,
+        items: &[syn::Item],
+        parent_file: Option<&str>,
+        files: &mut Vec<String>,
+    ) -> Self {
+        let own_file = match items.first().and_then(line_markers::parse_marker) {
+            Some(Marker::Open(file)) => Some(file),
+            _ => None,
+        };
+        let file = own_file.or_else(|| parent_file.map(str::to_string));
+
+        let mut node = Self {
+            name: name.to_string(),
+            path,
+            file,
+            items: BTreeMap::new(),
+            characters: characters(items),
+            external: false,
+            modules: Vec::new(),
+        };
+        for item in items {
+            if let Some(Marker::Open(file)) = line_markers::parse_marker(item) {
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+                continue;
+            }
+            if line_markers::is_marker(item) {
+                continue;
+            }
+            let syn::Item::Mod(item_mod) = item else {
+                *node.items.entry(kind(item).to_string()).or_default() += 1;
+                continue;
+            };
+            let name = item_mod.ident.to_string();
+            let path = if node.path.is_empty() {
+                name.clone()
+            } else {
+                format!("{}::{name}", node.path)
+            };
+            let module = if let Some((_, content)) = &item_mod.content {
+                Self::new(&name, path, content, node.file.as_deref(), files)
+            } else {
+                Self {
+                    name,
+                    path,
+                    file: None,
+                    items: BTreeMap::new(),
+                    characters: characters(std::slice::from_ref(item)),
+                    external: true,
+                    modules: Vec::new(),
+                }
+            };
+            node.modules.push(module);
+        }
+        node
+    }
+
+    /// `name (file, N characters: 2 fn, 1 struct)`
+    fn label(&self) -> String {
+        let mut details = Vec::new();
+        if let Some(file) = &self.file {
+            details.push(file.clone());
+        }
+        if self.external {
+            details.push("not expanded".to_string());
+        }
+        let items: Vec<String> = self
+            .items
+            .iter()
+            .map(|(kind, count)| format!("{count} {kind}"))
+            .collect();
+        let mut size = format!("{} characters", self.characters);
+        if !items.is_empty() {
+            let _ = write!(size, ": {}", items.join(", "));
+        }
+        details.push(size);
+        format!("{} ({})", self.name, details.join(", "))
+    }
+}
+
+/// Write the submodules of `node` below it, with `prefix` before their branches
+fn write_children(tree: &mut String, node: &ModuleNode, prefix: &str) {
+    for (index, module) in node.modules.iter().enumerate() {
+        let last = index + 1 == node.modules.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let _ = writeln!(tree, "{prefix}{branch}{}", module.label());
+        write_children(tree, module, &format!("{prefix}{indent}"));
+    }
+}
+
+/// `node` and its submodules, depth first
+fn collect_modules<'a>(node: &'a ModuleNode, modules: &mut Vec<&'a ModuleNode>) {
+    modules.push(node);
+    for module in &node.modules {
+        collect_modules(module, modules);
+    }
+}
+
+/// `names` of a use, the first few followed by how many others there are
+fn edge_label(names: &[String]) -> String {
+    let mut label = names
+        .iter()
+        .take(EDGE_NAMES)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > EDGE_NAMES {
+        let _ = write!(label, " +{}", names.len() - EDGE_NAMES);
+    }
+    label
+}
+
+/// `text` as a quoted Graphviz string, with `\n` line breaks kept
+fn dot_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\\\""))
+}
+
+/// `text` for a quoted Mermaid label, where `"` ends the label
+fn mermaid_text(text: &str) -> String {
+    text.replace('"', "#quot;")
+}
+
+/// Characters of `items` without their markers, formatted with prettyplease
+fn characters(items: &[syn::Item]) -> usize {
+    let mut items = items.to_vec();
+    line_markers::strip(&mut items);
+    let file = syn::File {
+        shebang: None,
+        attrs: Vec::new(),
+        items,
+    };
+    prettyplease::unparse(&file).chars().count()
+}
+
+/// Kind of an item as written in Rust, e.g. `fn` or `extern crate`
+const fn kind(item: &syn::Item) -> &'static str {
+    match item {
+        syn::Item::Const(_) => "const",
+        syn::Item::Enum(_) => "enum",
+        syn::Item::ExternCrate(_) => "extern crate",
+        syn::Item::Fn(_) => "fn",
+        syn::Item::ForeignMod(_) => "extern block",
+        syn::Item::Impl(_) => "impl",
+        syn::Item::Macro(_) => "macro",
+        syn::Item::Mod(_) => "mod",
+        syn::Item::Static(_) => "static",
+        syn::Item::Struct(_) => "struct",
+        syn::Item::Trait(_) | syn::Item::TraitAlias(_) => "trait",
+        syn::Item::Type(_) => "type",
+        syn::Item::Union(_) => "union",
+        syn::Item::Use(_) => "use",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph_of_marked_bundle() {
+        let file = syn::parse_file(
+            r#"
+__cg_bundler_file!("src/main.rs");
+use geo::Point;
+mod geo {
+    __cg_bundler_file!("src/geo.rs");
+    pub struct Point;
+    impl Point {}
+    pub mod shapes { pub struct Circle; }
+}
+__cg_bundler_file!("src/main.rs");
+mod missing;
+fn main() { let _ = Point; }
+"#,
+        )
+        .unwrap();
+        let graph = BundleGraph::new(&file);
+
+        assert_eq!(graph.files, ["src/main.rs", "src/geo.rs"]);
+        assert_eq!(graph.root.file.as_deref(), Some("src/main.rs"));
+        assert_eq!(
+            graph.root.items,
+            BTreeMap::from([("fn".to_string(), 1), ("use".to_string(), 1)])
+        );
+        let geo = &graph.root.modules[0];
+        assert_eq!(geo.path, "geo");
+        assert_eq!(geo.file.as_deref(), Some("src/geo.rs"));
+        assert_eq!(geo.modules[0].path, "geo::shapes");
+        assert_eq!(geo.modules[0].file.as_deref(), Some("src/geo.rs"));
+        assert!(graph.root.modules[1].external);
+
+        let tree = graph.to_tree();
+        assert!(tree.starts_with("crate (src/main.rs, "), "{tree}");
+        assert!(tree.contains("├── geo (src/geo.rs, "), "{tree}");
+        assert!(tree.contains("│   └── shapes (src/geo.rs, "), "{tree}");
+        assert!(tree.contains("└── missing (not expanded, "), "{tree}");
+        assert!(!tree.contains(line_markers::MARKER_MACRO));
+
+        assert_eq!(
+            graph.uses,
+            [ModuleUse {
+                from: String::new(),
+                to: "geo".to_string(),
+                names: vec!["Point".to_string()],
+            }]
+        );
+        let dot = graph.to_graph(GraphFormat::Dot);
+        assert!(dot.starts_with("digraph modules {\n"), "{dot}");
+        assert!(dot.contains("    m1 [label=\"geo\\nsrc/geo.rs\\n"), "{dot}");
+        assert!(dot.contains("    m1 -> m2;\n"), "{dot}");
+        assert!(
+            dot.contains("    m0 -> m1 [style=dashed, label=\"Point\"];\n"),
+            "{dot}"
+        );
+        let mermaid = graph.to_graph(GraphFormat::Mermaid);
+        assert!(
+            mermaid.contains("    m2[\"geo::shapes<br/>src/geo.rs<br/>"),
+            "{mermaid}"
+        );
+        assert!(mermaid.contains("    m0 -.->|\"Point\"| m1\n"), "{mermaid}");
+        assert_eq!("mermaid".parse(), Ok(GraphFormat::Mermaid));
+    }
+
+    #[test]
+    fn test_edge_label() {
+        let names: Vec<String> = ["a", "b", "c", "d", "e"].map(String::from).to_vec();
+        assert_eq!(edge_label(&names[..2]), "a, b");
+        assert_eq!(edge_label(&names), "a, b, c +2");
+    }
+}
@@ -0,0 +1,269 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::diagnostics::Span;
+use crate::error::{BundlerError, Result};
+
+/// Health of a bundle: how close it is to the size budget, how much of it is dead
+/// code, which dependencies the judge lacks and how heavily it relies on macros,
+/// summed up in a score and a letter grade
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+    pub characters: usize,
+    /// Character budget of a submission
+    pub budget: usize,
+    /// Characters of the items `--strip-unused` would remove, an estimate of the
+    /// code unreachable from `fn main`
+    pub dead_characters: usize,
+    /// Dependencies that are neither vendored nor part of the standard library,
+    /// which the judge cannot compile
+    pub dependencies: Vec<String>,
+    /// `macro_rules!` definitions in the bundle
+    pub macro_definitions: usize,
+    /// Rules of those definitions
+    pub macro_rules: usize,
+    /// Invocations of those macros
+    pub macro_invocations: usize,
+    /// From 0 to 100
+    pub score: u32,
+    /// `A` (score of 90 or more) to `F` (below 60)
+    pub grade: char,
+    /// What to clean up, most urgent first
+    pub recommendations: Vec<String>,
+}
+
+impl HealthReport {
+    /// Assess `code`, a bundle, given `stripped`, the same bundle with unused
+    /// items removed, the package's unvendored `dependencies` and the size `budget`
+    ///
+    /// Each area costs points: size over half the budget (40 over the budget),
+    /// dead code, 10 per dependency and macro rules beyond the first ten.
+    ///
+    /// # Errors
+    /// Returns an error if `code` is not valid Rust
+    pub fn new(
+        code: &str,
+        stripped: &str,
+        dependencies: Vec<String>,
+        budget: usize,
+    ) -> Result<Self> {
+        let file = syn::parse_file(code).map_err(|e| BundlerError::SyntaxError {
+            message: format!("Failed to parse the bundle: {e}"),
+            file_path: None,
+            span: Span::of_syn_error(&e),
+        })?;
+        let macros = MacroCounter::new(file.to_token_stream());
+
+        let characters = code.chars().count();
+        let dead_characters = characters.saturating_sub(stripped.chars().count());
+        let size_percent = percent(characters, budget);
+        let dead_percent = percent(dead_characters, characters);
+
+        let mut penalties: Vec<(usize, String)> = Vec::new();
+        let size_penalty = if characters > budget {
+            40
+        } else {
+            size_percent.saturating_sub(50) * 3 / 5
+        };
+        if size_penalty > 0 {
+            penalties.push((
+                size_penalty,
+                format!(
+                    "The bundle uses {size_percent}% of the {budget}-character budget: minify it (--minify, --m2) or remove unused code"
+                ),
+            ));
+        }
+        let dead_penalty = (dead_percent / 2).min(25);
+        if dead_penalty > 0 {
+            penalties.push((
+                dead_penalty,
+                format!(
+                    "About {dead_percent}% of the bundle is not reachable from fn main: delete it or bundle with --strip-unused"
+                ),
+            ));
+        }
+        let dependency_penalty = (dependencies.len() * 10).min(30);
+        if dependency_penalty > 0 {
+            penalties.push((
+                dependency_penalty,
+                format!(
+                    "The judge has no access to {}: vendor them with --vendor-dep or replace them with std",
+                    dependencies.join(", ")
+                ),
+            ));
+        }
+        let macro_penalty = macros.rules.saturating_sub(10).min(15);
+        if macro_penalty > 0 {
+            penalties.push((
+                macro_penalty,
+                format!(
+                    "{} macro_rules! rules make the bundle hard to debug: replace rarely used macros with functions",
+                    macros.rules
+                ),
+            ));
+        }
+        // Stable sort: equal penalties keep the order of the areas above
+        penalties.sort_by_key(|(penalty, _)| std::cmp::Reverse(*penalty));
+
+        let lost: usize = penalties.iter().map(|(penalty, _)| penalty).sum();
+        let score = u32::try_from(100usize.saturating_sub(lost)).unwrap_or(0);
+        Ok(Self {
+            characters,
+            budget,
+            dead_characters,
+            dependencies,
+            macro_definitions: macros.definitions.len(),
+            macro_rules: macros.rules,
+            macro_invocations: macros.invocations,
+            score,
+            grade: grade(score),
+            recommendations: penalties.into_iter().map(|(_, text)| text).collect(),
+        })
+    }
+
+    /// Human-readable report: the grade, each area, then the recommendations
+    #[must_use]
+    pub fn to_table(&self) -> String {
+        let mut table = format!("Health: {} ({}/100)\n\n", self.grade, self.score);
+        let _ = writeln!(
+            table,
+            "  Size          {} of {} characters ({}%)",
+            self.characters,
+            self.budget,
+            percent(self.characters, self.budget)
+        );
+        let _ = writeln!(
+            table,
+            "  Dead code     ~{} characters ({}%)",
+            self.dead_characters,
+            percent(self.dead_characters, self.characters)
+        );
+        let _ = writeln!(
+            table,
+            "  Dependencies  {}",
+            if self.dependencies.is_empty() {
+                "std only".to_string()
+            } else {
+                self.dependencies.join(", ")
+            }
+        );
+        let _ = writeln!(
+            table,
+            "  Macros        {} definitions, {} rules, {} invocations",
+            self.macro_definitions, self.macro_rules, self.macro_invocations
+        );
+        if !self.recommendations.is_empty() {
+            table.push_str("\nRecommendations:\n");
+            for (index, recommendation) in self.recommendations.iter().enumerate() {
+                let _ = writeln!(table, "  {}. {recommendation}", index + 1);
+            }
+        }
+        table
+    }
+}
+
+/// `part / whole` as a whole percentage, rounded down
+fn percent(part: usize, whole: usize) -> usize {
+    (part * 100).checked_div(whole).unwrap_or(0)
+}
+
+const fn grade(score: u32) -> char {
+    match score {
+        90.. => 'A',
+        80..=89 => 'B',
+        70..=79 => 'C',
+        60..=69 => 'D',
+        _ => 'F',
+    }
+}
+
+/// Counts of `macro_rules!` definitions, their rules and invocations of them
+#[derive(Default)]
+struct MacroCounter {
+    definitions: Vec<String>,
+    rules: usize,
+    invocations: usize,
+}
+
+impl MacroCounter {
+    /// Count the macros of `tokens`, including those defined or invoked inside
+    /// function bodies and other macros
+    fn new(tokens: TokenStream) -> Self {
+        let mut counter = Self::default();
+        counter.collect_definitions(tokens.clone());
+        counter.count_invocations(tokens);
+        counter
+    }
+
+    fn collect_definitions(&mut self, tokens: TokenStream) {
+        let trees: Vec<TokenTree> = tokens.into_iter().collect();
+        for (index, tree) in trees.iter().enumerate() {
+            match (tree, trees.get(index + 1..index + 4)) {
+                (
+                    TokenTree::Ident(keyword),
+                    Some([TokenTree::Punct(bang), TokenTree::Ident(name), TokenTree::Group(body)]),
+                ) if keyword == "macro_rules" && bang.as_char() == '!' => {
+                    self.definitions.push(name.to_string());
+                    // Rules are `(matcher) => {transcriber}`, separated by `;`
+                    self.rules += body
+                        .stream()
+                        .into_iter()
+                        .filter(|tree| matches!(tree, TokenTree::Punct(punct) if punct.as_char() == '>'))
+                        .count();
+                }
+                (TokenTree::Group(group), _) => self.collect_definitions(group.stream()),
+                _ => {}
+            }
+        }
+    }
+
+    fn count_invocations(&mut self, tokens: TokenStream) {
+        let trees: Vec<TokenTree> = tokens.into_iter().collect();
+        for (index, tree) in trees.iter().enumerate() {
+            match (tree, trees.get(index + 1)) {
+                (TokenTree::Ident(name), Some(TokenTree::Punct(bang)))
+                    if bang.as_char() == '!'
+                        && self.definitions.iter().any(|defined| name == defined) =>
+                {
+                    self.invocations += 1;
+                }
+                (TokenTree::Group(group), _) => self.count_invocations(group.stream()),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_grades_and_recommendations() {
+        let code = "macro_rules! sq { ($x:expr) => { $x * $x }; () => { 0 }; }\nfn unused() {}\nfn main() { println!(\"{}\", sq!(2) + sq!()); }\n";
+        let stripped = "macro_rules! sq { ($x:expr) => { $x * $x }; () => { 0 }; }\nfn main() { println!(\"{}\", sq!(2) + sq!()); }\n";
+
+        let healthy = HealthReport::new(code, code, Vec::new(), 100_000).unwrap();
+        assert_eq!((healthy.score, healthy.grade), (100, 'A'));
+        assert!(healthy.recommendations.is_empty());
+        assert_eq!(
+            (
+                healthy.macro_definitions,
+                healthy.macro_rules,
+                healthy.macro_invocations
+            ),
+            (1, 2, 2)
+        );
+
+        let report =
+            HealthReport::new(code, stripped, vec!["rand".to_string()], code.len()).unwrap();
+        assert_eq!(report.dead_characters, 15);
+        assert_eq!(report.score, 100 - 30 - 10 - 6);
+        assert_eq!(report.grade, 'F');
+        assert!(report.recommendations[0].contains("100% of the"));
+        assert!(report.recommendations[1].contains("rand"));
+        assert!(report.recommendations[2].contains("not reachable"));
+    }
+}
@@ -0,0 +1,294 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{BundlerError, Result};
+use crate::file_manager::FileManager;
+
+/// First line of the part of a pre-commit hook written by cg-bundler
+const BEGIN: &str = "# >>> cg-bundler >>>";
+/// Last line of that part, which is replaced when the hook is installed again
+const END: &str = "# <<< cg-bundler <<<";
+
+/// What the pre-commit hook does with a stale bundle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HookMode {
+    /// Rebundle and stage the bundles, so that the commit includes them
+    #[default]
+    Regenerate,
+    /// Fail the commit with a diff, leaving the bundles to be updated by hand
+    Check,
+}
+
+/// What installing a hook did to the existing one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookInstall {
+    /// There was no pre-commit hook
+    Created,
+    /// The part written by a previous install was replaced
+    Updated,
+    /// The commands were added at the end of a hook not written by cg-bundler
+    Appended,
+}
+
+/// A git pre-commit hook bundling a project of the repository, so that the
+/// committed bundles are never stale
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreCommitHook {
+    /// Project directory, relative to the root of the repository
+    project: PathBuf,
+    /// Bundles the hook keeps up to date, relative to the project directory
+    outputs: Vec<PathBuf>,
+    /// Profiles of the configuration file writing `outputs`, if not the default
+    /// settings writing the single output
+    profiles: Vec<String>,
+    mode: HookMode,
+}
+
+impl PreCommitHook {
+    /// Hook keeping `output` the bundle of `project`, a directory relative to the
+    /// root of the repository
+    #[must_use]
+    pub fn new(project: &Path, output: &Path) -> Self {
+        Self {
+            project: project.to_path_buf(),
+            outputs: vec![output.to_path_buf()],
+            profiles: Vec::new(),
+            mode: HookMode::default(),
+        }
+    }
+
+    /// Hook bundling `project` with each of `profiles`, which write `outputs`
+    #[must_use]
+    pub fn with_profiles(project: &Path, profiles: Vec<String>, outputs: Vec<PathBuf>) -> Self {
+        Self {
+            project: project.to_path_buf(),
+            outputs,
+            profiles,
+            mode: HookMode::default(),
+        }
+    }
+
+    #[must_use]
+    pub const fn with_mode(mut self, mode: HookMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// The commands of the hook, between the lines marking them as written by
+    /// cg-bundler
+    #[must_use]
+    pub fn script(&self) -> String {
+        let project = FileManager::display_path(&self.project);
+        let outputs: Vec<String> = self
+            .outputs
+            .iter()
+            .map(|output| shell_quote(&FileManager::display_path(output)))
+            .collect();
+        let outputs = outputs.join(" ");
+        let bundle = if self.profiles.is_empty() {
+            let flag = match self.mode {
+                HookMode::Regenerate => "--output",
+                HookMode::Check => "--check-output",
+            };
+            format!("cg-bundler {flag} {outputs}")
+        } else {
+            let profiles: Vec<String> = self
+                .profiles
+                .iter()
+                .map(|profile| format!("--profile {}", shell_quote(profile)))
+                .collect();
+            format!("cg-bundler {}", profiles.join(" "))
+        };
+        let after = match (self.mode, self.profiles.is_empty()) {
+            (HookMode::Regenerate, _) => format!("git add -- {outputs} || exit 1\n"),
+            (HookMode::Check, true) => String::new(),
+            // The profiles rewrite their outputs, which then differ from the
+            // staged ones if those are stale
+            (HookMode::Check, false) => format!(
+                "git diff --exit-code -- {outputs} || {{ echo 'cg-bundler: stale bundle, stage it to commit' >&2; exit 1; }}\n"
+            ),
+        };
+        let cd = if project.is_empty() || project == "." {
+            "cd \"$(git rev-parse --show-toplevel)\" || exit 1".to_string()
+        } else {
+            format!(
+                "cd \"$(git rev-parse --show-toplevel)\"/{} || exit 1",
+                shell_quote(&project)
+            )
+        };
+        format!(
+            "{BEGIN}\n# Written by `cg-bundler hook install`: keeps the committed bundles up to date\n(\n{cd}\n{bundle} || exit 1\n{after}) || exit 1\n{END}\n"
+        )
+    }
+
+    /// Install the hook in `hooks_dir`, replacing the part written by a previous
+    /// install; a pre-commit hook not written by cg-bundler is only extended if
+    /// `append` is set
+    ///
+    /// # Errors
+    /// Returns an error if there is another pre-commit hook and `append` is not
+    /// set, or if the hook cannot be read or written
+    pub fn install(&self, hooks_dir: &Path, append: bool) -> Result<HookInstall> {
+        let path = hooks_dir.join("pre-commit");
+        let io_error = |e| BundlerError::Io {
+            source: e,
+            path: Some(path.clone()),
+        };
+        let (content, install) = match fs::read_to_string(&path) {
+            Ok(existing) => {
+                if let (Some(begin), Some(end)) = (existing.find(BEGIN), existing.find(END)) {
+                    let end = end + END.len();
+                    let end = end + usize::from(existing[end..].starts_with('\n'));
+                    let content = format!(
+                        "{}{}{}",
+                        &existing[..begin],
+                        self.script(),
+                        &existing[end..]
+                    );
+                    (content, HookInstall::Updated)
+                } else if append {
+                    let separator = if existing.ends_with('\n') {
+                        "\n"
+                    } else {
+                        "\n\n"
+                    };
+                    (
+                        format!("{existing}{separator}{}", self.script()),
+                        HookInstall::Appended,
+                    )
+                } else {
+                    return Err(BundlerError::ProjectStructure {
+                        message: format!(
+                            "{} was not written by cg-bundler, use --append to add to it",
+                            path.display()
+                        ),
+                    });
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                fs::create_dir_all(hooks_dir).map_err(|e| BundlerError::Io {
+                    source: e,
+                    path: Some(hooks_dir.to_path_buf()),
+                })?;
+                (
+                    format!("#!/bin/sh\n{}", self.script()),
+                    HookInstall::Created,
+                )
+            }
+            Err(e) => return Err(io_error(e)),
+        };
+        fs::write(&path, content).map_err(io_error)?;
+        make_executable(&path).map_err(io_error)?;
+        Ok(install)
+    }
+}
+
+/// Root of the git repository containing `dir` and the directory of its hooks,
+/// which `core.hooksPath` may have moved
+///
+/// # Errors
+/// Returns an error if `dir` is not in a git repository or git cannot be run
+pub fn git_paths(dir: &Path) -> Result<(PathBuf, PathBuf)> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--show-toplevel", "--git-path", "hooks"])
+        .output()
+        .map_err(|e| BundlerError::ProjectStructure {
+            message: format!("cannot run git: {e}"),
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    match (output.status.success(), lines.next(), lines.next()) {
+        (true, Some(root), Some(hooks)) => Ok((PathBuf::from(root), dir.join(hooks))),
+        _ => Err(BundlerError::ProjectStructure {
+            message: format!("{} is not in a git repository", dir.display()),
+        }),
+    }
+}
+
+/// `path` quoted for a POSIX shell if it has characters the shell would
+/// interpret
+fn shell_quote(path: &str) -> String {
+    let plain = !path.is_empty()
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:+,=@".contains(c));
+    if plain {
+        path.to_string()
+    } else {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    fs::set_permissions(path, permissions)
+}
+
+/// Git for Windows runs hooks through its own shell, whatever the permissions
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+const fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let script = PreCommitHook::new(Path::new("bots/my bot"), Path::new("bundle.rs")).script();
+        assert!(script.starts_with(BEGIN) && script.ends_with(&format!("{END}\n")));
+        assert!(
+            script.contains("cd \"$(git rev-parse --show-toplevel)\"/'bots/my bot' || exit 1\n"),
+            "{script}"
+        );
+        assert!(script.contains("cg-bundler --output bundle.rs || exit 1\ngit add -- bundle.rs"));
+
+        let script = PreCommitHook::new(Path::new(""), Path::new("bundle.rs"))
+            .with_mode(HookMode::Check)
+            .script();
+        assert!(script.contains("cg-bundler --check-output bundle.rs || exit 1\n)"));
+        assert!(!script.contains("git add"));
+
+        let script = PreCommitHook::with_profiles(
+            Path::new("."),
+            vec!["submit".to_string(), "debug".to_string()],
+            vec![PathBuf::from("bundle.rs"), PathBuf::from("bundle.debug.rs")],
+        )
+        .with_mode(HookMode::Check)
+        .script();
+        assert!(script.contains("cg-bundler --profile submit --profile debug || exit 1\n"));
+        assert!(script.contains("git diff --exit-code -- bundle.rs bundle.debug.rs ||"));
+    }
+
+    #[test]
+    fn test_install_keeps_other_hooks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let hooks = dir.path().join("hooks");
+        let hook = PreCommitHook::new(Path::new("."), Path::new("bundle.rs"));
+        assert_eq!(hook.install(&hooks, false).unwrap(), HookInstall::Created);
+        let hook = hook.with_mode(HookMode::Check);
+        assert_eq!(hook.install(&hooks, false).unwrap(), HookInstall::Updated);
+        let content = fs::read_to_string(hooks.join("pre-commit")).unwrap();
+        assert_eq!(content, format!("#!/bin/sh\n{}", hook.script()));
+
+        fs::write(hooks.join("pre-commit"), "#!/bin/sh\ncargo fmt --check\n").unwrap();
+        let error = hook.install(&hooks, false).unwrap_err().to_string();
+        assert!(error.contains("use --append"), "{error}");
+        assert_eq!(hook.install(&hooks, true).unwrap(), HookInstall::Appended);
+        assert_eq!(hook.install(&hooks, false).unwrap(), HookInstall::Updated);
+        let content = fs::read_to_string(hooks.join("pre-commit")).unwrap();
+        assert_eq!(
+            content,
+            format!("#!/bin/sh\ncargo fmt --check\n\n{}", hook.script())
+        );
+    }
+}
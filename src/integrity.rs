@@ -0,0 +1,187 @@
+use quote::ToTokens;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use syn::spanned::Spanned;
+
+use crate::diagnostics::Span;
+use crate::error::{BundlerError, Result};
+use crate::transformer::SourceMap;
+
+/// Differences between an existing bundle and a fresh bundle of the current sources
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IntegrityReport {
+    /// Source files with items that are not in the existing bundle as they are now,
+    /// in order of appearance
+    pub changed_files: Vec<String>,
+    /// Items of the existing bundle that the current sources no longer produce,
+    /// e.g. `geo::fn area`
+    pub stale_items: Vec<String>,
+}
+
+impl IntegrityReport {
+    /// Whether the existing bundle corresponds to the current sources
+    #[must_use]
+    pub fn is_current(&self) -> bool {
+        self.changed_files.is_empty() && self.stale_items.is_empty()
+    }
+}
+
+/// An item of a bundle, outside of inline modules
+struct BundleItem {
+    /// Path of the enclosing module from the bundle root, empty at the root
+    module: String,
+    /// The item without comments or formatting
+    tokens: String,
+    description: String,
+    line: usize,
+    column: usize,
+}
+
+/// Compare the items of `bundle`, an earlier output, with those of `marked`, a
+/// fresh bundle of the current sources rendered with line markers
+///
+/// Items are compared by their tokens, so comments and formatting do not matter,
+/// but `marked` must be produced with the settings and formatting `bundle` was
+/// produced with (e.g. `--m2` renames items, rustfmt sorts imports). Fresh items
+/// missing from `bundle` are attributed to their source file with the line markers.
+///
+/// # Errors
+/// Returns an error if `bundle` or `marked` is not valid Rust
+pub fn compare(bundle: &str, marked: &str) -> Result<IntegrityReport> {
+    let parse = |code: &str, what: &str| {
+        syn::parse_file(code).map_err(|e| BundlerError::SyntaxError {
+            message: format!("Failed to parse {what}: {e}"),
+            file_path: None,
+            span: Span::of_syn_error(&e),
+        })
+    };
+    let mut existing: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for item in items(&parse(bundle, "the existing bundle")?) {
+        existing
+            .entry((item.module, item.tokens))
+            .or_default()
+            .push(item.description);
+    }
+
+    let source_map = SourceMap::from_output(marked);
+    let mut changed_files: Vec<String> = Vec::new();
+    for item in items(&parse(marked, "the fresh bundle")?) {
+        let matched = existing
+            .get_mut(&(item.module, item.tokens))
+            .and_then(Vec::pop)
+            .is_some();
+        if matched {
+            continue;
+        }
+        let file = source_map
+            .file_at_position(item.line, item.column)
+            .unwrap_or("(generated)")
+            .to_string();
+        if !changed_files.contains(&file) {
+            changed_files.push(file);
+        }
+    }
+
+    Ok(IntegrityReport {
+        changed_files,
+        stale_items: existing.into_values().flatten().collect(),
+    })
+}
+
+/// The items of `file`, with those of inline modules in place of the modules
+fn items(file: &syn::File) -> Vec<BundleItem> {
+    let mut items = Vec::new();
+    flatten(&file.items, "", &mut items);
+    items
+}
+
+fn flatten(items: &[syn::Item], module: &str, flat: &mut Vec<BundleItem>) {
+    for item in items {
+        if let syn::Item::Mod(syn::ItemMod {
+            ident,
+            content: Some((_, content)),
+            ..
+        }) = item
+        {
+            let path = if module.is_empty() {
+                ident.to_string()
+            } else {
+                format!("{module}::{ident}")
+            };
+            flatten(content, &path, flat);
+            continue;
+        }
+
+        let start = item.span().start();
+        let description = describe(item);
+        flat.push(BundleItem {
+            module: module.to_string(),
+            tokens: item.to_token_stream().to_string(),
+            description: if module.is_empty() {
+                description
+            } else {
+                format!("{module}::{description}")
+            },
+            line: start.line,
+            column: start.column + 1,
+        });
+    }
+}
+
+/// Short description of an item, e.g. `fn area` or `impl Point`
+pub(crate) fn describe(item: &syn::Item) -> String {
+    let (kind, name) = match item {
+        syn::Item::Const(item) => ("const", item.ident.to_string()),
+        syn::Item::Enum(item) => ("enum", item.ident.to_string()),
+        syn::Item::Fn(item) => ("fn", item.sig.ident.to_string()),
+        syn::Item::Impl(item) => {
+            let name = item.trait_.as_ref().map_or_else(
+                || item.self_ty.to_token_stream().to_string(),
+                |(_, path, _)| {
+                    format!(
+                        "{} for {}",
+                        path.to_token_stream(),
+                        item.self_ty.to_token_stream()
+                    )
+                },
+            );
+            ("impl", name)
+        }
+        syn::Item::Macro(item) => (
+            "macro",
+            item.ident.as_ref().map_or_else(
+                || item.mac.path.to_token_stream().to_string(),
+                ToString::to_string,
+            ),
+        ),
+        syn::Item::Mod(item) => ("mod", item.ident.to_string()),
+        syn::Item::Static(item) => ("static", item.ident.to_string()),
+        syn::Item::Struct(item) => ("struct", item.ident.to_string()),
+        syn::Item::Trait(item) => ("trait", item.ident.to_string()),
+        syn::Item::Type(item) => ("type", item.ident.to_string()),
+        syn::Item::Union(item) => ("union", item.ident.to_string()),
+        syn::Item::Use(item) => ("use", item.tree.to_token_stream().to_string()),
+        other => ("item", other.to_token_stream().to_string()),
+    };
+    format!("{kind} {name}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_and_stale_items_reported() {
+        let bundle = "// Bundled for league 3\nmod geo {\n    pub fn area() -> u32 { 4 }\n    pub fn old() {}\n}\nfn main() {\n    println!(\"{}\", geo::area());\n}\n";
+        let marked = "// file: src/main.rs\nmod geo {\n    // file: src/geo.rs\n    pub fn area() -> u32 { 5 }\n}\n// file: src/main.rs\nfn main() { println!(\"{}\", geo::area()); }\n";
+
+        let report = compare(bundle, marked).unwrap();
+        assert_eq!(report.changed_files, ["src/geo.rs"]);
+        assert_eq!(report.stale_items, ["geo::fn area", "geo::fn old"]);
+        assert!(!report.is_current());
+
+        let current =
+            "mod geo { pub fn area() -> u32 { 5 } }\nfn main() { println!(\"{}\", geo::area()); }";
+        assert!(compare(current, marked).unwrap().is_current());
+    }
+}
@@ -3,17 +3,58 @@
 //! This library provides functionality to bundle Rust projects into single source files,
 //! combining multiple modules and dependencies into a single, self-contained file.
 
+pub mod bench;
+pub mod build_script;
 pub mod bundler;
+pub mod cache;
 pub mod cargo_project;
+pub mod check;
+pub mod cli;
+pub mod codingame;
+pub mod config;
+pub mod crash_report;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+pub mod determinism;
+pub mod diagnostics;
+pub mod diff;
+pub mod digest;
+pub mod duplicates;
 pub mod error;
 pub mod file_manager;
+pub mod formatter;
+pub mod graph;
+pub mod health;
+pub mod hook;
+pub mod integrity;
+pub mod lint;
+#[cfg(feature = "watch")]
+pub mod live_reload;
+pub mod plan;
+#[cfg(feature = "playground")]
+pub mod playground;
+pub mod provenance;
+pub mod scaffold;
+pub mod size_report;
+pub mod split;
+pub mod template;
+pub mod testing;
 pub mod transformer;
+pub mod unbundle;
+pub mod validate;
+pub mod warnings;
 
 // Re-export main types for convenience
 pub use bundler::Bundler;
-pub use cargo_project::CargoProject;
+pub use cargo_project::{CargoProject, EntryTarget, FeatureSelection};
+pub use config::BundlerConfig;
 pub use error::{BundlerError, Result};
-pub use transformer::{CodeTransformer, TransformConfig};
+pub use file_manager::{FileProvider, OsFileProvider};
+pub use plan::BundlePlan;
+pub use transformer::{
+    AttributePolicy, AttributeRetention, CodeTransformer, DocsMode, Minifier, MinifyStage,
+    SourceMap, TransformConfig,
+};
 
 use std::path::Path;
 
@@ -0,0 +1,406 @@
+use proc_macro2::{Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::diagnostics::Span;
+use crate::error::{BundlerError, Result};
+use crate::integrity::describe;
+use crate::transformer::SourceMap;
+
+/// APIs a bundle must not use, selected with `--lint`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintProfile {
+    /// `CodinGame` runs bundles without file system, network, processes or threads
+    CodinGame,
+}
+
+impl LintProfile {
+    /// Name of the profile on the command line
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::CodinGame => "codingame",
+        }
+    }
+
+    const fn rules(self) -> &'static [Rule] {
+        match self {
+            Self::CodinGame => &CODINGAME_RULES,
+        }
+    }
+}
+
+impl FromStr for LintProfile {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        match name {
+            "codingame" => Ok(Self::CodinGame),
+            _ => Err(format!(
+                "unknown lint profile '{name}' (expected codingame)"
+            )),
+        }
+    }
+}
+
+/// A forbidden module of `std`
+struct Rule {
+    module: &'static str,
+    reason: &'static str,
+    /// Items of the module that may still be used
+    allowed: &'static [&'static str],
+}
+
+const CODINGAME_RULES: [Rule; 5] = [
+    Rule {
+        module: "fs",
+        reason: "file system access",
+        allowed: &[],
+    },
+    Rule {
+        module: "net",
+        reason: "network access",
+        allowed: &[],
+    },
+    Rule {
+        module: "process",
+        reason: "running processes",
+        allowed: &["exit", "abort", "ExitCode", "Termination"],
+    },
+    Rule {
+        module: "thread",
+        reason: "threads",
+        allowed: &[],
+    },
+    Rule {
+        module: "os",
+        reason: "platform-specific OS access",
+        allowed: &[],
+    },
+];
+
+/// A use of a forbidden API in a bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The path used, with imported modules spelled out, e.g. `std::fs::read`
+    pub path: String,
+    /// What the API gives access to, e.g. `file system access`
+    pub reason: &'static str,
+    /// Item using it, e.g. `debug::fn dump`
+    pub item: String,
+    /// Source file of the use, when the bundle has line markers there
+    pub file: Option<String>,
+    /// Position of the path in the bundle
+    pub span: Span,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "forbidden: `{}` ({}) in {}",
+            self.path, self.reason, self.item
+        )?;
+        let file = self.file.as_deref().unwrap_or("<bundle>");
+        write!(
+            f,
+            "\n  --> {file} (bundle line {}:{})",
+            self.span.line, self.span.column
+        )
+    }
+}
+
+/// Forbidden APIs a bundle uses
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintReport {
+    pub violations: Vec<Violation>,
+}
+
+impl LintReport {
+    /// Whether the bundle uses no forbidden API
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Find the uses of the modules of `std` that `profile` forbids in a bundle
+///
+/// Paths are read from the tokens of every item, macro arguments included, so
+/// `std::fs::read` is found in a `println!` too. Importing a forbidden module is
+/// not a use: `use std::process;` is allowed, `process::Command` is not. Names
+/// are not resolved beyond that, so a path reaching `std::fs` through a re-export
+/// goes unnoticed. Uses are attributed to source files through the bundle's
+/// `// file:` line markers, when it has them.
+///
+/// # Errors
+/// Returns an error if `code` is not valid Rust
+pub fn lint_bundle(code: &str, profile: LintProfile) -> Result<LintReport> {
+    let file = syn::parse_file(code).map_err(|e| BundlerError::SyntaxError {
+        message: format!("Failed to parse the bundle: {e}"),
+        file_path: None,
+        span: Span::of_syn_error(&e),
+    })?;
+    let mut items = Vec::new();
+    flatten(&file.items, "", &mut items);
+
+    // Forbidden modules imported under a name, e.g. `thread` for `use std::thread;`
+    let mut modules: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for occurrence in items.iter().flat_map(|(_, occurrences)| occurrences) {
+        let Some(path) = absolute(&occurrence.segments, &modules) else {
+            continue;
+        };
+        if path.len() == 2 && rule(profile, &path).is_some() {
+            let name = occurrence.alias.as_ref().unwrap_or(&path[1]);
+            modules.insert(name.clone(), path);
+        }
+    }
+
+    let source_map = SourceMap::from_output(code);
+    let mut violations = Vec::new();
+    for (item, occurrences) in &items {
+        for occurrence in occurrences {
+            let Some(path) = absolute(&occurrence.segments, &modules) else {
+                continue;
+            };
+            let Some(rule) = rule(profile, &path) else {
+                continue;
+            };
+            if path.len() == 2 || rule.allowed.contains(&path[2].as_str()) {
+                continue;
+            }
+            violations.push(Violation {
+                path: path.join("::"),
+                reason: rule.reason,
+                item: item.clone(),
+                file: source_map
+                    .file_at_position(occurrence.span.line, occurrence.span.column)
+                    .map(ToString::to_string),
+                span: occurrence.span,
+            });
+        }
+    }
+    Ok(LintReport { violations })
+}
+
+/// The rule of `profile` forbidding the module `path` is in
+fn rule(profile: LintProfile, path: &[String]) -> Option<&'static Rule> {
+    let module = path.get(1)?;
+    profile
+        .rules()
+        .iter()
+        .find(|rule| path[0] == "std" && rule.module == module)
+}
+
+/// `segments` starting from `std`, with a leading imported module spelled out
+/// and a trailing `self` removed
+fn absolute(segments: &[String], modules: &BTreeMap<String, Vec<String>>) -> Option<Vec<String>> {
+    let (first, rest) = segments.split_first()?;
+    let mut path = if first == "std" {
+        vec![first.clone()]
+    } else if rest.is_empty() {
+        return None;
+    } else {
+        modules.get(first)?.clone()
+    };
+    path.extend(rest.iter().cloned());
+    if path.last().is_some_and(|segment| segment == "self") {
+        path.pop();
+    }
+    Some(path)
+}
+
+/// A path written in a bundle
+struct Occurrence {
+    /// Segments, with those of the enclosing `use` groups first
+    segments: Vec<String>,
+    /// Name given with `as`
+    alias: Option<String>,
+    span: Span,
+}
+
+/// The items of `items` and of their inline modules, described, with the paths
+/// each one uses
+fn flatten(items: &[syn::Item], module: &str, flat: &mut Vec<(String, Vec<Occurrence>)>) {
+    for item in items {
+        if let syn::Item::Mod(syn::ItemMod {
+            ident,
+            content: Some((_, content)),
+            ..
+        }) = item
+        {
+            let path = if module.is_empty() {
+                ident.to_string()
+            } else {
+                format!("{module}::{ident}")
+            };
+            flatten(content, &path, flat);
+            continue;
+        }
+
+        let description = describe(item);
+        let description = if module.is_empty() {
+            description
+        } else {
+            format!("{module}::{description}")
+        };
+        let mut occurrences = Vec::new();
+        collect_paths(item.to_token_stream(), &[], &mut occurrences);
+        flat.push((description, occurrences));
+    }
+}
+
+/// Add the paths written in `tokens` to `occurrences`, prefixed with `prefix`,
+/// the segments of the `use` group they are in
+fn collect_paths(tokens: TokenStream, prefix: &[String], occurrences: &mut Vec<Occurrence>) {
+    let mut segments: Vec<String> = Vec::new();
+    let mut span = None;
+    // Whether the previous tokens were `::`, expecting a segment or a group
+    let mut after_colons = false;
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some(tree) = tokens.next() {
+        match tree {
+            TokenTree::Ident(ident) if segments.is_empty() || after_colons => {
+                if span.is_none() {
+                    span = Some(span_of(&ident.span()));
+                }
+                segments.push(ident.to_string());
+                after_colons = false;
+            }
+            TokenTree::Punct(punct)
+                if punct.as_char() == ':'
+                    && punct.spacing() == Spacing::Joint
+                    && matches!(tokens.peek(), Some(TokenTree::Punct(next)) if next.as_char() == ':') =>
+            {
+                tokens.next();
+                after_colons = true;
+            }
+            TokenTree::Group(group) if after_colons => {
+                let mut nested = prefix.to_vec();
+                nested.append(&mut segments);
+                collect_paths(group.stream(), &nested, occurrences);
+                span = None;
+                after_colons = false;
+            }
+            tree => {
+                after_colons = false;
+                let alias = match &tree {
+                    TokenTree::Ident(ident) if ident == "as" => match tokens.peek() {
+                        Some(TokenTree::Ident(alias)) => Some(alias.to_string()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                finish(prefix, &mut segments, span.take(), alias, occurrences);
+                match tree {
+                    TokenTree::Ident(ident) => {
+                        span = Some(span_of(&ident.span()));
+                        segments.push(ident.to_string());
+                    }
+                    TokenTree::Group(group) => collect_paths(group.stream(), &[], occurrences),
+                    TokenTree::Punct(_) | TokenTree::Literal(_) => {}
+                }
+            }
+        }
+    }
+    finish(prefix, &mut segments, span, None, occurrences);
+}
+
+/// Add the path of `segments` starting at `span`, if any, to `occurrences`
+fn finish(
+    prefix: &[String],
+    segments: &mut Vec<String>,
+    span: Option<Span>,
+    alias: Option<String>,
+    occurrences: &mut Vec<Occurrence>,
+) {
+    if let Some(span) = span {
+        let mut path = prefix.to_vec();
+        path.append(segments);
+        occurrences.push(Occurrence {
+            segments: path,
+            alias,
+            span,
+        });
+    }
+    segments.clear();
+}
+
+fn span_of(span: &proc_macro2::Span) -> Span {
+    let (start, end) = (span.start(), span.end());
+    Span {
+        line: start.line,
+        column: start.column + 1,
+        end_line: end.line,
+        end_column: end.column + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violations(code: &str) -> Vec<String> {
+        lint_bundle(code, LintProfile::CodinGame)
+            .unwrap()
+            .violations
+            .iter()
+            .map(|violation| format!("{} in {}", violation.path, violation.item))
+            .collect()
+    }
+
+    #[test]
+    fn test_forbidden_paths_found() {
+        let code = r#"
+// file: src/main.rs
+use std::io::{self, BufRead};
+use std::{process, thread as worker};
+mod debug {
+    // file: src/debug.rs
+    use std::fs::File;
+    pub fn dump(state: &str) {
+        eprintln!("{:?}", ::std::fs::read_to_string("state.txt"));
+        let _ = state;
+    }
+}
+// file: src/main.rs
+fn main() {
+    let handle = worker::spawn(|| 42);
+    if handle.join().is_err() {
+        process::exit(1);
+    }
+    let _ = std::net::TcpStream::connect("localhost:80");
+}
+"#;
+        assert_eq!(
+            violations(code),
+            [
+                "std::fs::File in debug::use std :: fs :: File",
+                "std::fs::read_to_string in debug::fn dump",
+                "std::thread::spawn in fn main",
+                "std::net::TcpStream::connect in fn main",
+            ]
+        );
+
+        let report = lint_bundle(code, LintProfile::CodinGame).unwrap();
+        let violation = &report.violations[1];
+        assert_eq!(violation.file.as_deref(), Some("src/debug.rs"));
+        assert_eq!(
+            violation.to_string(),
+            "forbidden: `std::fs::read_to_string` (file system access) in debug::fn dump\n  --> src/debug.rs (bundle line 9:29)"
+        );
+    }
+
+    #[test]
+    fn test_allowed_items_and_other_modules() {
+        assert!(violations(
+            "use std::process::{self, exit};\nmod fs { pub fn read() {} }\nfn main() { fs::read(); exit(0); }"
+        )
+        .is_empty());
+        assert_eq!("codingame".parse(), Ok(LintProfile::CodinGame));
+        assert!("judge".parse::<LintProfile>().is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use serde::Serialize;
+use std::io::Write;
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::{Message, WebSocket};
+
+use crate::error::{BundlerError, Result};
+
+/// How long a new client has to send a WebSocket upgrade request before it is
+/// treated as a plain TCP client
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Event sent to clients after a successful rebuild
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RebuildEvent<'a> {
+    /// Always `rebuilt`
+    pub event: &'static str,
+    /// Output file, or `None` when the bundle went to stdout
+    pub output: Option<&'a Path>,
+    pub bytes: usize,
+    pub characters: usize,
+}
+
+impl<'a> RebuildEvent<'a> {
+    #[must_use]
+    pub fn new(output: Option<&'a Path>, code: &str) -> Self {
+        Self {
+            event: "rebuilt",
+            output,
+            bytes: code.len(),
+            characters: code.chars().count(),
+        }
+    }
+}
+
+enum Client {
+    /// A browser script, sent each event as a text message
+    WebSocket(Box<WebSocket<TcpStream>>),
+    /// Any other client, sent each event as a line
+    Lines(TcpStream),
+}
+
+/// Server notifying editors and browser scripts of rebuilds in watch mode
+///
+/// Listens on localhost. Browsers connect with a WebSocket and receive each event
+/// as a JSON text message; pages of other sites than localhost and codingame.com
+/// are refused. Other clients (e.g. `nc localhost PORT`) receive one JSON object
+/// per line. Clients that disconnect are dropped on the next event.
+pub struct NotifyServer {
+    port: u16,
+    clients: Arc<Mutex<Vec<Client>>>,
+}
+
+impl NotifyServer {
+    /// Listen on `127.0.0.1:port` (`0` picks a free port), accepting clients in a
+    /// background thread
+    ///
+    /// # Errors
+    /// Returns an error if the port cannot be bound
+    pub fn bind(port: u16) -> Result<Self> {
+        let listener =
+            TcpListener::bind((Ipv4Addr::LOCALHOST, port)).map_err(|e| BundlerError::Io {
+                source: std::io::Error::new(e.kind(), format!("Cannot listen on port {port}: {e}")),
+                path: None,
+            })?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| BundlerError::Io {
+                source: e,
+                path: None,
+            })?
+            .port();
+
+        let clients: Arc<Mutex<Vec<Client>>> = Arc::default();
+        let accepted = Arc::clone(&clients);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Some(client) = accept(stream) {
+                    accepted
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push(client);
+                }
+            }
+        });
+        Ok(Self { port, clients })
+    }
+
+    /// Port the server listens on
+    #[must_use]
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Send `event` to every connected client, returning how many received it
+    pub fn broadcast(&self, event: &RebuildEvent) -> usize {
+        let message = serde_json::to_string(event).unwrap_or_default();
+        let mut clients = self.clients.lock().unwrap_or_else(PoisonError::into_inner);
+        clients.retain_mut(|client| match client {
+            Client::WebSocket(socket) => socket.send(Message::text(message.as_str())).is_ok(),
+            Client::Lines(stream) => writeln!(stream, "{message}")
+                .and_then(|()| stream.flush())
+                .is_ok(),
+        });
+        clients.len()
+    }
+}
+
+/// Complete the WebSocket handshake if `stream` asks for one
+fn accept(stream: TcpStream) -> Option<Client> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).ok()?;
+    // A client that stops reading must not block the rebuilds
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT)).ok()?;
+    let mut start = [0; 4];
+    // A client that sends nothing in time is a line-protocol client
+    if !matches!(stream.peek(&mut start), Ok(4) if &start == b"GET ") {
+        return Some(Client::Lines(stream));
+    }
+    tungstenite::accept_hdr(stream, check_origin)
+        .ok()
+        .map(|socket| Client::WebSocket(Box::new(socket)))
+}
+
+/// Refuse the WebSocket handshake of pages whose origin `is_allowed_origin` rejects,
+/// which browsers otherwise let any site open to localhost
+// The signature of tungstenite's handshake callbacks
+#[allow(clippy::result_large_err)]
+fn check_origin(
+    request: &Request,
+    response: Response,
+) -> std::result::Result<Response, ErrorResponse> {
+    let origin = request.headers().get("origin").map(|value| value.to_str());
+    match origin {
+        // Not opened by a web page
+        None => Ok(response),
+        Some(Ok(origin)) if is_allowed_origin(origin) => Ok(response),
+        Some(_) => {
+            let mut refusal = ErrorResponse::new(Some("origin not allowed".to_string()));
+            *refusal.status_mut() = StatusCode::FORBIDDEN;
+            Err(refusal)
+        }
+    }
+}
+
+/// Whether pages served from `origin` may receive rebuild events: local pages
+/// and codingame.com, where browser sync scripts run
+fn is_allowed_origin(origin: &str) -> bool {
+    let Some(authority) = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+    else {
+        return false;
+    };
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if port.bytes().all(|byte| byte.is_ascii_digit()) => host,
+        _ => authority,
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]" | "codingame.com")
+        || host.ends_with(".codingame.com")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use tungstenite::client::IntoClientRequest;
+    use tungstenite::http::HeaderValue;
+
+    #[allow(clippy::result_large_err)]
+    fn connect_websocket(port: u16, origin: &str) -> tungstenite::Result<WebSocket<TcpStream>> {
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+        let mut request = format!("ws://localhost:{port}/").into_client_request()?;
+        request
+            .headers_mut()
+            .insert("origin", HeaderValue::from_str(origin).unwrap());
+        tungstenite::client(request, stream)
+            .map(|(socket, _)| socket)
+            .map_err(|e| match e {
+                tungstenite::HandshakeError::Failure(e) => e,
+                tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::ConnectionClosed,
+            })
+    }
+
+    #[test]
+    fn test_allowed_origins() {
+        assert!(is_allowed_origin("https://www.codingame.com"));
+        assert!(is_allowed_origin("http://localhost:8080"));
+        assert!(is_allowed_origin("http://[::1]:3000"));
+        assert!(!is_allowed_origin("https://codingame.com.example.org"));
+        assert!(!is_allowed_origin("https://evilcodingame.com"));
+        assert!(!is_allowed_origin("null"));
+    }
+
+    #[test]
+    fn test_clients_receive_rebuild_events() {
+        let server = NotifyServer::bind(0).unwrap();
+        let mut line_client = TcpStream::connect((Ipv4Addr::LOCALHOST, server.port())).unwrap();
+        let mut websocket = connect_websocket(server.port(), "https://www.codingame.com").unwrap();
+
+        // Wait for the line client to pass the handshake timeout
+        let event = RebuildEvent::new(Some(Path::new("bundle.rs")), "fn main() {}");
+        let mut reached = 0;
+        for _ in 0..50 {
+            reached = server.broadcast(&event);
+            if reached == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(reached, 2);
+
+        let expected = r#"{"event":"rebuilt","output":"bundle.rs","bytes":12,"characters":12}"#;
+        let mut line = String::new();
+        BufReader::new(&mut line_client)
+            .read_line(&mut line)
+            .unwrap();
+        assert_eq!(line.trim_end(), expected);
+        assert_eq!(websocket.read().unwrap(), Message::text(expected));
+    }
+
+    #[test]
+    fn test_websockets_of_other_sites_are_refused() {
+        let server = NotifyServer::bind(0).unwrap();
+        match connect_websocket(server.port(), "https://example.org") {
+            Err(tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            }
+            other => panic!("expected a refused handshake, got {other:?}"),
+        }
+    }
+}
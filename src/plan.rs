@@ -0,0 +1,359 @@
+use std::path::{Path, PathBuf};
+
+use crate::cargo_project::CargoProject;
+use crate::error::Result;
+use crate::file_manager::FileManager;
+use crate::transformer::{DocsMode, TransformConfig};
+
+/// A module file that bundling would inline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedModule {
+    /// Module path relative to the bundle root, e.g. `geometry::shapes`
+    pub path: String,
+    /// Resolved source file, or `None` when no candidate file exists or both
+    /// `name.rs` and `name/mod.rs` do
+    pub file: Option<PathBuf>,
+}
+
+/// The actions a bundling run would perform, computed without parsing any source
+///
+/// Module files are discovered by scanning source text for `mod name;`
+/// declarations, so a plan is cheap to build even for very large projects.
+/// Declarations produced by macros or `#[path]` attributes are not followed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundlePlan {
+    /// Source file of the binary target (the bundle entry point)
+    pub binary_source: PathBuf,
+    /// Library root that would be inlined because the binary imports the crate
+    pub library_source: Option<PathBuf>,
+    /// Module files that would be expanded, in discovery order
+    pub modules: Vec<PlannedModule>,
+    /// Human-readable names of the transforms that would run
+    pub transforms: Vec<String>,
+}
+
+impl BundlePlan {
+    /// Build the plan for bundling a project with the given configuration
+    ///
+    /// # Errors
+    /// Returns an error if the binary or library source cannot be read
+    pub fn new(project: &CargoProject, config: &TransformConfig) -> Result<Self> {
+        let binary_source = project.binary_source_path().to_path_buf();
+        let binary_code = FileManager::read_file(&binary_source)?;
+        let skip_tests = config.remove_tests;
+
+        let mut modules = Vec::new();
+        let lib_path = project
+            .library_source_path()
+            .map_or_else(|| project.base_path().join("lib.rs"), Path::to_path_buf);
+        let library_dir = lib_path.parent().unwrap_or_else(|| project.base_path());
+        // The library bundled on its own is not inlined in itself
+        let library_code = if config.expand_modules && binary_source != lib_path {
+            if imports_crate(&binary_code, config.crate_name_or(project.crate_name())) {
+                Some(FileManager::read_file(&lib_path)?)
+            } else {
+                FileManager::try_read_file(&lib_path)
+                    .filter(|lib_code| imports_library_modules(&binary_code, lib_code))
+            }
+        } else {
+            None
+        };
+        let library_source = library_code.map(|lib_code| {
+            collect_modules(&lib_code, library_dir, "", skip_tests, &mut modules);
+            lib_path.clone()
+        });
+        collect_modules(
+            &binary_code,
+            project.binary_base_path(),
+            "",
+            skip_tests,
+            &mut modules,
+        );
+
+        Ok(Self {
+            binary_source,
+            library_source,
+            modules,
+            transforms: describe_transforms(config),
+        })
+    }
+
+    /// Source files bundling would read: the entry, the library root and every
+    /// resolved module file, in discovery order
+    #[must_use]
+    pub fn input_files(&self) -> Vec<&Path> {
+        std::iter::once(self.binary_source.as_path())
+            .chain(self.library_source.as_deref())
+            .chain(
+                self.modules
+                    .iter()
+                    .filter_map(|module| module.file.as_deref()),
+            )
+            .collect()
+    }
+
+    /// Modules whose declaration could not be resolved to a file
+    pub fn missing_modules(&self) -> impl Iterator<Item = &PlannedModule> {
+        self.modules.iter().filter(|module| module.file.is_none())
+    }
+}
+
+/// Names of the transforms enabled by a configuration, in the order they run
+#[must_use]
+pub fn describe_transforms(config: &TransformConfig) -> Vec<String> {
+    let mut transforms = Vec::new();
+    if config.expand_modules {
+        transforms.push("expand library crate".to_string());
+    }
+    if config.expand_macros {
+        transforms.push("expand macros declaring modules".to_string());
+    }
+    transforms.push(match (config.remove_tests, &config.tests_cfg) {
+        (true, _) => "remove tests".to_string(),
+        (false, Some(name)) => format!("keep tests under cfg({name})"),
+        (false, None) => "keep tests (test harness)".to_string(),
+    });
+    if config.extract_demos {
+        transforms.push("extract demos into examples".to_string());
+    }
+    for pattern in &config.excluded_modules {
+        transforms.push(format!("exclude modules {pattern}"));
+    }
+    if !config.only_modules.is_empty() {
+        transforms.push(format!("only modules {}", config.only_modules.join(", ")));
+    }
+    if let Some(selection) = &config.features {
+        let mut features = selection.features.clone();
+        if selection.all_features {
+            features = vec!["all features".to_string()];
+        } else if !selection.no_default_features {
+            features.push("default features".to_string());
+        }
+        let features = if features.is_empty() {
+            "no features".to_string()
+        } else {
+            features.join(", ")
+        };
+        transforms.push(format!("resolve cfg(feature) with {features}"));
+    }
+    for (path, feature) in &config.enabled_modules {
+        transforms.push(format!("enable module {path} (feature \"{feature}\")"));
+    }
+    for (name, value) in &config.defines {
+        transforms.push(format!("define {name} = {value}"));
+    }
+    if config.merge_imports {
+        transforms.push("merge and deduplicate imports".to_string());
+    }
+    if config.rename_collisions {
+        transforms.push("rename colliding private items".to_string());
+    }
+    match config.effective_docs_mode() {
+        DocsMode::Remove => transforms.push("remove docs".to_string()),
+        DocsMode::ConvertToPlain => transforms.push("convert docs to plain comments".to_string()),
+        DocsMode::Keep => {}
+    }
+    if config.keep_comments {
+        transforms.push("keep comments".to_string());
+    }
+    if config.strip_unused {
+        transforms.push("strip unused items".to_string());
+    }
+    for (name, retention) in &config.attribute_policy.overrides {
+        transforms.push(format!("attribute #[{name}]: {retention:?}"));
+    }
+    if config.aggressive_minify {
+        transforms.push("aggressive minify".to_string());
+    } else if config.minify {
+        transforms.push("minify".to_string());
+    } else if config.preserve_formatting {
+        transforms.push("keep source formatting of unchanged items".to_string());
+    }
+    if config.verify_minify && (config.minify || config.aggressive_minify) {
+        transforms.push("verify minified output".to_string());
+    }
+    transforms
+}
+
+/// Whether the binary source imports the library crate (`use name::...` or `extern crate name`)
+fn imports_crate(source: &str, crate_name: &str) -> bool {
+    source.lines().map(str::trim).any(|line| {
+        let line = strip_visibility(line);
+        let rest = line
+            .strip_prefix("use ")
+            .or_else(|| line.strip_prefix("extern crate "))
+            .map(str::trim_start);
+        rest.and_then(|rest| rest.strip_prefix(crate_name))
+            .is_some_and(|after| {
+                after.starts_with("::") || after.starts_with(';') || after.starts_with(" as ")
+            })
+    })
+}
+
+/// Whether the binary reaches library modules through `use crate::module...`
+fn imports_library_modules(binary_source: &str, library_source: &str) -> bool {
+    let library_modules = scan_mod_declarations(library_source, false);
+    let binary_modules = scan_mod_declarations(binary_source, false);
+    binary_source.lines().map(str::trim).any(|line| {
+        strip_visibility(line)
+            .strip_prefix("use crate::")
+            .map(|rest| {
+                let end = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                &rest[..end]
+            })
+            .is_some_and(|module| {
+                library_modules.iter().any(|name| name == module)
+                    && !binary_modules.iter().any(|name| name == module)
+            })
+    })
+}
+
+fn collect_modules(
+    source: &str,
+    base_path: &Path,
+    parent: &str,
+    skip_tests: bool,
+    modules: &mut Vec<PlannedModule>,
+) {
+    for name in scan_mod_declarations(source, skip_tests) {
+        let path = if parent.is_empty() {
+            name.clone()
+        } else {
+            format!("{parent}::{name}")
+        };
+
+        match FileManager::locate_module_file(base_path, &name) {
+            Some((file, submodule_base)) => {
+                let nested = FileManager::try_read_file(&file).unwrap_or_default();
+                modules.push(PlannedModule {
+                    path: path.clone(),
+                    file: Some(file),
+                });
+                collect_modules(&nested, &submodule_base, &path, skip_tests, modules);
+            }
+            None => modules.push(PlannedModule { path, file: None }),
+        }
+    }
+}
+
+/// Find out-of-line `mod name;` declarations by scanning source lines
+///
+/// When `skip_tests` is set, declarations gated by a `#[cfg(test)]` attribute
+/// (on the same line or on the preceding attribute lines) are ignored.
+#[must_use]
+pub fn scan_mod_declarations(source: &str, skip_tests: bool) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut test_gated = false;
+
+    for line in source.lines() {
+        let mut line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        while let Some(attr) = line.strip_prefix("#[") {
+            let Some(end) = attr.find(']') else {
+                break;
+            };
+            test_gated |= is_cfg_test(&attr[..end]);
+            line = attr[end + 1..].trim_start();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        if !(skip_tests && test_gated) {
+            if let Some(name) = parse_mod_declaration(line) {
+                names.push(name.to_string());
+            }
+        }
+        test_gated = false;
+    }
+    names
+}
+
+fn is_cfg_test(attr: &str) -> bool {
+    let compact: String = attr.chars().filter(|c| !c.is_whitespace()).collect();
+    compact == "cfg(test)" || compact == "test"
+}
+
+fn strip_visibility(line: &str) -> &str {
+    let Some(rest) = line.strip_prefix("pub") else {
+        return line;
+    };
+    if let Some(restricted) = rest.strip_prefix('(') {
+        return restricted
+            .find(')')
+            .map_or(line, |end| restricted[end + 1..].trim_start());
+    }
+    if rest.starts_with(char::is_whitespace) {
+        rest.trim_start()
+    } else {
+        line
+    }
+}
+
+fn parse_mod_declaration(line: &str) -> Option<&str> {
+    let rest = strip_visibility(line).strip_prefix("mod ")?.trim_start();
+    let rest = rest.strip_prefix("r#").unwrap_or(rest);
+    let end = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    let (name, tail) = rest.split_at(end);
+    (!name.is_empty() && tail.trim_start().starts_with(';')).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_mod_declarations() {
+        let source = "mod a;\npub mod b ;\npub(crate) mod c;\nmod inline { }\n// mod commented;\n#[cfg(test)]\nmod tests;\n#[cfg(test)] mod more_tests;\nfn main() {}\n";
+        assert_eq!(scan_mod_declarations(source, true), ["a", "b", "c"]);
+        assert_eq!(
+            scan_mod_declarations(source, false),
+            ["a", "b", "c", "tests", "more_tests"]
+        );
+    }
+
+    #[test]
+    fn test_imports_crate() {
+        assert!(imports_crate("use my_lib::*;\nfn main() {}", "my_lib"));
+        assert!(imports_crate("extern crate my_lib;", "my_lib"));
+        assert!(!imports_crate("use my_library::*;", "my_lib"));
+        assert!(!imports_crate("fn main() {}", "my_lib"));
+    }
+
+    #[test]
+    fn test_imports_library_modules() {
+        let lib = "pub mod geo;\npub mod util;\n";
+        assert!(imports_library_modules("use crate::geo::area;", lib));
+        assert!(!imports_library_modules(
+            "mod geo;\nuse crate::geo::area;",
+            lib
+        ));
+        assert!(!imports_library_modules("use crate::other::x;", lib));
+    }
+
+    #[test]
+    fn test_collect_nested_modules() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path();
+        fs::create_dir_all(src.join("geo")).unwrap();
+        fs::write(src.join("geo.rs"), "pub mod shapes;\n").unwrap();
+        fs::write(src.join("geo/shapes.rs"), "pub struct Circle;\n").unwrap();
+
+        let mut modules = Vec::new();
+        collect_modules("mod geo;\nmod missing;\n", src, "", true, &mut modules);
+
+        let paths: Vec<&str> = modules.iter().map(|m| m.path.as_str()).collect();
+        assert_eq!(paths, ["geo", "geo::shapes", "missing"]);
+        assert_eq!(modules[1].file, Some(src.join("geo/shapes.rs")));
+        assert_eq!(modules[2].file, None);
+    }
+}
@@ -0,0 +1,244 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::bundler::Bundler;
+use crate::error::Result;
+use crate::transformer::lexer::{block_comment_len, literal_token, LiteralKind};
+use crate::transformer::TransformConfig;
+
+/// Rust keywords, including `Self`, `self`, `super` and `crate`
+const KEYWORDS: [&str; 40] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "yield", "try",
+];
+
+/// Sources to bundle, as typed in a web playground
+#[derive(Debug, Clone, Default)]
+pub struct PlaygroundRequest {
+    /// Crate name matched by `use crate_name::...` in `main`
+    pub crate_name: String,
+    /// Source of the binary (`main.rs`)
+    pub main: String,
+    /// Other files, keyed by their path relative to `main.rs` (see `Bundler::bundle_sources`)
+    pub modules: HashMap<PathBuf, String>,
+    pub config: TransformConfig,
+}
+
+/// Kind of token, for choosing its color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenClass {
+    Keyword,
+    Identifier,
+    /// Name of a macro invocation or definition (`println` in `println!`)
+    Macro,
+    Lifetime,
+    /// String literal of any kind: byte, C or raw
+    String,
+    /// Character or byte literal
+    Char,
+    Number,
+    Comment,
+    Punctuation,
+}
+
+/// Class of the bytes `start..end` of the bundle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct HighlightSpan {
+    pub start: usize,
+    pub end: usize,
+    pub class: TokenClass,
+}
+
+/// A bundle with the spans to highlight it; whitespace is not covered by any span
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HighlightedBundle {
+    pub code: String,
+    /// Non-overlapping spans in source order
+    pub spans: Vec<HighlightSpan>,
+}
+
+/// Bundle in-memory sources and classify the tokens of the result
+///
+/// Everything happens in memory, so a web demo (e.g. compiled to WebAssembly) can
+/// show highlighted bundles without a Cargo project or a separate highlighter.
+///
+/// # Errors
+/// Returns an error if the sources cannot be bundled
+pub fn bundle_and_highlight(request: &PlaygroundRequest) -> Result<HighlightedBundle> {
+    let code = Bundler::with_config(request.config.clone()).bundle_sources(
+        &request.crate_name,
+        &request.main,
+        &request.modules,
+    )?;
+    let spans = highlight(&code);
+    Ok(HighlightedBundle { code, spans })
+}
+
+/// Classify the tokens of Rust source code
+///
+/// A lexer only, so code that does not parse still gets highlighted; unterminated
+/// literals and comments extend to the end of the code.
+#[must_use]
+pub fn highlight(code: &str) -> Vec<HighlightSpan> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while let Some(c) = code[start..].chars().next() {
+        let rest = &code[start..];
+        if c.is_whitespace() {
+            start += c.len_utf8();
+            continue;
+        }
+
+        let (len, class) = if rest.starts_with("//") {
+            (rest.find('\n').unwrap_or(rest.len()), TokenClass::Comment)
+        } else if rest.starts_with("/*") {
+            (block_comment_len(rest), TokenClass::Comment)
+        } else if c.is_ascii_digit() {
+            (number_len(rest), TokenClass::Number)
+        } else {
+            match literal_token(rest) {
+                Some((len, LiteralKind::Str)) => (len, TokenClass::String),
+                Some((len, LiteralKind::Char)) => (len, TokenClass::Char),
+                Some((_, LiteralKind::LifetimeQuote)) => {
+                    (1 + ident_len(&rest[1..]), TokenClass::Lifetime)
+                }
+                Some((_, LiteralKind::Ident)) => word(rest),
+                None => (c.len_utf8(), TokenClass::Punctuation),
+            }
+        };
+        spans.push(HighlightSpan {
+            start,
+            end: start + len,
+            class,
+        });
+        start += len;
+    }
+    spans
+}
+
+fn is_ident_start(c: char) -> bool {
+    c == '_' || c.is_alphabetic()
+}
+
+fn ident_len(text: &str) -> usize {
+    text.find(|c: char| c != '_' && !c.is_alphanumeric())
+        .unwrap_or(text.len())
+}
+
+/// Length of an identifier, keyword or raw identifier at the start of `rest`
+fn word(rest: &str) -> (usize, TokenClass) {
+    if let Some(raw) = rest
+        .strip_prefix("r#")
+        .filter(|raw| raw.starts_with(is_ident_start))
+    {
+        return (2 + ident_len(raw), TokenClass::Identifier);
+    }
+
+    let len = ident_len(rest);
+    let after = rest[len..].trim_start();
+    let class = if KEYWORDS.contains(&&rest[..len]) {
+        TokenClass::Keyword
+    } else if after.starts_with('!') && !after.starts_with("!=") {
+        TokenClass::Macro
+    } else {
+        TokenClass::Identifier
+    };
+    (len, class)
+}
+
+/// Length of a number literal, with its suffix, at the start of `rest`
+fn number_len(rest: &str) -> usize {
+    let mut len = ident_len(rest);
+    // A fraction, but not a range (`0..n`) or a method call (`1.max(2)`)
+    if rest[len..].starts_with('.') && rest[len + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+        len += 1 + ident_len(&rest[len + 1..]);
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classes(code: &str) -> Vec<(&str, TokenClass)> {
+        highlight(code)
+            .into_iter()
+            .map(|span| (&code[span.start..span.end], span.class))
+            .collect()
+    }
+
+    #[test]
+    fn test_highlight_classifies_tokens() {
+        use TokenClass::{
+            Char, Comment, Identifier, Keyword, Lifetime, Macro, Number, Punctuation, String,
+        };
+
+        let code = "// file: main.rs\nfn f<'a>(s: &'a str) { println!(r#\"\"x\"\"#, b'\\n', 'é', 1.5e3, 0..2); /* a /* b */ */ r#type }";
+        assert_eq!(
+            classes(code),
+            [
+                ("// file: main.rs", Comment),
+                ("fn", Keyword),
+                ("f", Identifier),
+                ("<", Punctuation),
+                ("'a", Lifetime),
+                (">", Punctuation),
+                ("(", Punctuation),
+                ("s", Identifier),
+                (":", Punctuation),
+                ("&", Punctuation),
+                ("'a", Lifetime),
+                ("str", Identifier),
+                (")", Punctuation),
+                ("{", Punctuation),
+                ("println", Macro),
+                ("!", Punctuation),
+                ("(", Punctuation),
+                ("r#\"\"x\"\"#", String),
+                (",", Punctuation),
+                ("b'\\n'", Char),
+                (",", Punctuation),
+                ("'é'", Char),
+                (",", Punctuation),
+                ("1.5e3", Number),
+                (",", Punctuation),
+                ("0", Number),
+                (".", Punctuation),
+                (".", Punctuation),
+                ("2", Number),
+                (")", Punctuation),
+                (";", Punctuation),
+                ("/* a /* b */ */", Comment),
+                ("r#type", Identifier),
+                ("}", Punctuation),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bundle_and_highlight_covers_bundle() {
+        let request = PlaygroundRequest {
+            crate_name: "solver".to_string(),
+            main: "use solver::double;\nfn main() { println!(\"{}\", double(21)); }\n".to_string(),
+            modules: HashMap::from([(
+                PathBuf::from("lib.rs"),
+                "pub fn double(x: i32) -> i32 { x * 2 }\n".to_string(),
+            )]),
+            config: TransformConfig::default(),
+        };
+
+        let bundle = bundle_and_highlight(&request).unwrap();
+        assert!(bundle.code.contains("pub fn double"));
+        let covered: usize = bundle.spans.iter().map(|span| span.end - span.start).sum();
+        let non_whitespace = bundle.code.chars().filter(|c| !c.is_whitespace()).count();
+        assert!(covered >= non_whitespace);
+        assert!(bundle
+            .spans
+            .windows(2)
+            .all(|pair| pair[0].end <= pair[1].start));
+    }
+}
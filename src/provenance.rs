@@ -0,0 +1,227 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cargo_project::CargoProject;
+use crate::digest;
+use crate::error::{BundlerError, Result};
+use crate::file_manager::FileManager;
+use crate::plan::BundlePlan;
+use crate::transformer::TransformConfig;
+
+const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+const BUILD_TYPE: &str = "https://github.com/MathieuSoysal/cg-bundler/bundle@v1";
+
+/// A file identified by its SHA-256 digest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResourceDescriptor {
+    /// Name of a produced artifact
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Location of an input, relative to the project directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    /// Digests by algorithm; always contains `sha256`
+    pub digest: BTreeMap<String, String>,
+}
+
+impl ResourceDescriptor {
+    fn sha256(data: &[u8]) -> BTreeMap<String, String> {
+        BTreeMap::from([("sha256".to_string(), digest::sha256_hex(data))])
+    }
+}
+
+/// Parameters of the bundling run that determine its output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalParameters {
+    pub package: String,
+    pub binary: String,
+    /// `TransformConfig::fingerprint` of the effective configuration
+    pub config_fingerprint: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildDefinition {
+    pub build_type: String,
+    pub external_parameters: ExternalParameters,
+    /// Every source file read to produce the bundle
+    pub resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Builder {
+    pub id: String,
+    pub version: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildMetadata {
+    /// RFC 3339 UTC timestamps
+    pub started_on: String,
+    pub finished_on: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunDetails {
+    pub builder: Builder,
+    pub metadata: BuildMetadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Predicate {
+    pub build_definition: BuildDefinition,
+    pub run_details: RunDetails,
+}
+
+/// SLSA-style provenance attestation of a bundle: an in-toto statement naming the
+/// bundle by digest, with the hashed inputs, bundler version and configuration
+/// fingerprint that produced it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    pub subject: Vec<ResourceDescriptor>,
+    pub predicate_type: String,
+    pub predicate: Predicate,
+}
+
+impl Provenance {
+    /// Attest that `output` (written as `output_name`) was bundled from `project`
+    /// with `config`, in a run that started at `started_on` and ends now
+    ///
+    /// The inputs are the manifest, the source files listed by the project's
+    /// `BundlePlan` and `other_inputs`, the other files bundling read, e.g. the
+    /// configuration file and the files inlined by include macros
+    /// (`BundleReport::included_files`).
+    ///
+    /// # Errors
+    /// Returns an error if the project plan cannot be built or an input cannot be read
+    pub fn new(
+        project: &CargoProject,
+        config: &TransformConfig,
+        other_inputs: &[PathBuf],
+        output_name: &str,
+        output: &str,
+        started_on: SystemTime,
+    ) -> Result<Self> {
+        let manifest = Path::new(&project.root_package().manifest_path);
+        let root = manifest.parent().unwrap_or_else(|| Path::new(""));
+        let plan = BundlePlan::new(project, config)?;
+        let package: &str = &project.root_package().name;
+
+        let resolved_dependencies = std::iter::once(manifest)
+            .chain(plan.input_files())
+            .chain(other_inputs.iter().map(PathBuf::as_path))
+            .map(|path| {
+                let content = std::fs::read(path).map_err(|e| BundlerError::Io {
+                    source: e,
+                    path: Some(path.to_path_buf()),
+                })?;
+                let relative = path.strip_prefix(root).unwrap_or(path);
+                Ok(ResourceDescriptor {
+                    name: None,
+                    uri: Some(FileManager::display_path(relative)),
+                    digest: ResourceDescriptor::sha256(&content),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            statement_type: STATEMENT_TYPE.to_string(),
+            subject: vec![ResourceDescriptor {
+                name: Some(output_name.to_string()),
+                uri: None,
+                digest: ResourceDescriptor::sha256(output.as_bytes()),
+            }],
+            predicate_type: PREDICATE_TYPE.to_string(),
+            predicate: Predicate {
+                build_definition: BuildDefinition {
+                    build_type: BUILD_TYPE.to_string(),
+                    external_parameters: ExternalParameters {
+                        package: package.to_string(),
+                        binary: project.binary_target().name.clone(),
+                        config_fingerprint: config.fingerprint(),
+                    },
+                    resolved_dependencies,
+                },
+                run_details: RunDetails {
+                    builder: Builder {
+                        id: env!("CARGO_PKG_NAME").to_string(),
+                        version: BTreeMap::from([(
+                            env!("CARGO_PKG_NAME").to_string(),
+                            env!("CARGO_PKG_VERSION").to_string(),
+                        )]),
+                    },
+                    metadata: BuildMetadata {
+                        started_on: rfc3339(started_on),
+                        finished_on: rfc3339(SystemTime::now()),
+                    },
+                },
+            },
+        })
+    }
+
+    /// Serialize the attestation as pretty-printed JSON
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// Format a time as an RFC 3339 UTC timestamp with second precision
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(951_825_599)),
+            "2000-02-29T11:59:59Z"
+        );
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(1_798_761_600)),
+            "2027-01-01T00:00:00Z"
+        );
+    }
+}
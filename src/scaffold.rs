@@ -0,0 +1,302 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::config::CONFIG_FILE_NAME;
+use crate::error::{BundlerError, Result};
+
+/// Layout of a project created by `cg-bundler init`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProjectTemplate {
+    /// Multiplayer game or contest bot: a turn loop in `main.rs`, the game state in
+    /// `game.rs` and the decisions in `strategy.rs`
+    #[default]
+    Bot,
+    /// Puzzle read from stdin once, solved in `solver.rs`
+    Puzzle,
+}
+
+impl ProjectTemplate {
+    pub const ALL: [Self; 2] = [Self::Bot, Self::Puzzle];
+
+    /// Name of the template on the command line
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Bot => "bot",
+            Self::Puzzle => "puzzle",
+        }
+    }
+}
+
+impl FromStr for ProjectTemplate {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|template| template.name() == name)
+            .ok_or_else(|| format!("unknown project template '{name}' (expected bot or puzzle)"))
+    }
+}
+
+/// A new Cargo project ready to be bundled: a library with the game logic, a
+/// binary using it, a `cg-bundler.toml` writing `bundle.rs` and an editor task
+/// running watch mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scaffold {
+    name: String,
+    template: ProjectTemplate,
+}
+
+impl Scaffold {
+    /// Scaffold of the package `name` with the layout of `template`
+    ///
+    /// # Errors
+    /// Returns an error if `name` is not a valid package name whose crate name is
+    /// a Rust identifier
+    pub fn new(name: &str, template: ProjectTemplate) -> Result<Self> {
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && syn::parse_str::<syn::Ident>(&name.replace('-', "_")).is_ok();
+        if !valid {
+            return Err(BundlerError::ProjectStructure {
+                message: format!(
+                    "'{name}' is not a valid package name, use letters, digits, '-' and '_'"
+                ),
+            });
+        }
+        Ok(Self {
+            name: name.to_string(),
+            template,
+        })
+    }
+
+    /// The files of the project, relative to its directory, with their contents
+    #[must_use]
+    pub fn files(&self) -> Vec<(PathBuf, String)> {
+        let crate_name = self.name.replace('-', "_");
+        let mut files = vec![
+            (
+                "Cargo.toml",
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n",
+                    self.name
+                ),
+            ),
+            (CONFIG_FILE_NAME, CONFIG.to_string()),
+            (".gitignore", "/target\n".to_string()),
+            (".vscode/tasks.json", TASKS.to_string()),
+        ];
+        match self.template {
+            ProjectTemplate::Bot => files.extend([
+                ("src/main.rs", BOT_MAIN.replace("{crate}", &crate_name)),
+                ("src/lib.rs", BOT_LIB.to_string()),
+                ("src/game.rs", BOT_GAME.to_string()),
+                ("src/strategy.rs", BOT_STRATEGY.to_string()),
+            ]),
+            ProjectTemplate::Puzzle => files.extend([
+                ("src/main.rs", PUZZLE_MAIN.replace("{crate}", &crate_name)),
+                ("src/lib.rs", PUZZLE_LIB.to_string()),
+                ("src/solver.rs", PUZZLE_SOLVER.to_string()),
+            ]),
+        }
+        files
+            .into_iter()
+            .map(|(path, content)| (PathBuf::from(path), content))
+            .collect()
+    }
+
+    /// Write the project in `dir`, creating it if needed, and return the paths of
+    /// the files written
+    ///
+    /// # Errors
+    /// Returns an error if one of the files already exists, in which case nothing
+    /// is written, or if a file cannot be written
+    pub fn create(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let files = self.files();
+        let existing: Vec<String> = files
+            .iter()
+            .filter(|(path, _)| dir.join(path).exists())
+            .map(|(path, _)| path.display().to_string())
+            .collect();
+        if !existing.is_empty() {
+            return Err(BundlerError::ProjectStructure {
+                message: format!(
+                    "{} already has {}, not overwriting it",
+                    dir.display(),
+                    existing.join(", ")
+                ),
+            });
+        }
+
+        let mut written = Vec::with_capacity(files.len());
+        for (path, content) in files {
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| BundlerError::Io {
+                    source: e,
+                    path: Some(parent.to_path_buf()),
+                })?;
+            }
+            fs::write(&path, content).map_err(|e| BundlerError::Io {
+                source: e,
+                path: Some(path.clone()),
+            })?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+const CONFIG: &str = r#"# Settings of cg-bundler, with the names of its command-line flags
+output = "bundle.rs"
+max-size = 100000
+
+# `cg-bundler --profile debug` writes a bundle pointing at the source files
+[profiles.debug]
+output = "bundle.debug.rs"
+line-markers = true
+"#;
+
+const TASKS: &str = r#"{
+    "version": "2.0.0",
+    "tasks": [
+        {
+            "label": "cg-bundler: watch",
+            "type": "shell",
+            "command": "cg-bundler --watch",
+            "isBackground": true,
+            "problemMatcher": []
+        }
+    ]
+}
+"#;
+
+const BOT_MAIN: &str = r#"use std::io::{self, BufRead};
+
+use {crate}::game::State;
+use {crate}::strategy;
+
+fn main() {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines().map_while(Result::ok);
+    while let Some(state) = State::read(&mut lines) {
+        println!("{}", strategy::decide(&state));
+    }
+}
+"#;
+
+const BOT_LIB: &str = r"//! Game logic, bundled with the binary into `bundle.rs` by cg-bundler
+
+pub mod game;
+pub mod strategy;
+";
+
+const BOT_GAME: &str = r"/// What the referee sends for a turn
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    /// Input lines of the turn, to parse into the entities of the game
+    pub lines: Vec<String>,
+}
+
+impl State {
+    /// Read a turn: a line with the number of lines that follow, then those lines
+    pub fn read(input: &mut impl Iterator<Item = String>) -> Option<Self> {
+        let count: usize = input.next()?.trim().parse().ok()?;
+        Some(Self {
+            lines: input.take(count).collect(),
+        })
+    }
+}
+";
+
+const BOT_STRATEGY: &str = r#"use crate::game::State;
+
+/// Action for the turn described by `state`
+pub fn decide(state: &State) -> String {
+    eprintln!("turn with {} input lines", state.lines.len());
+    "WAIT".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decide() {
+        assert_eq!(decide(&State::default()), "WAIT");
+    }
+}
+"#;
+
+const PUZZLE_MAIN: &str = r#"use std::io::{self, Read};
+
+use {crate}::solver;
+
+fn main() {
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .expect("failed to read stdin");
+    println!("{}", solver::solve(&input));
+}
+"#;
+
+const PUZZLE_LIB: &str = r"//! Puzzle solver, bundled with the binary into `bundle.rs` by cg-bundler
+
+pub mod solver;
+";
+
+const PUZZLE_SOLVER: &str = r#"/// Answer to the puzzle described by `input`
+pub fn solve(input: &str) -> String {
+    input.lines().count().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve() {
+        assert_eq!(solve("1\n2\n"), "2");
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_names() {
+        for template in ProjectTemplate::ALL {
+            assert_eq!(template.name().parse(), Ok(template));
+        }
+        assert!("summer".parse::<ProjectTemplate>().is_err());
+    }
+
+    #[test]
+    fn test_package_names() {
+        assert!(Scaffold::new("my-bot_2", ProjectTemplate::Bot).is_ok());
+        for name in ["", "2bot", "my bot", "fn", "bot.rs"] {
+            assert!(Scaffold::new(name, ProjectTemplate::Bot).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_create_refuses_to_overwrite() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let scaffold = Scaffold::new("my-bot", ProjectTemplate::Bot).unwrap();
+        let written = scaffold.create(dir.path()).unwrap();
+        assert_eq!(written.len(), scaffold.files().len());
+        assert!(fs::read_to_string(dir.path().join("src/main.rs"))
+            .unwrap()
+            .contains("use my_bot::game::State;"));
+
+        let error = scaffold.create(dir.path()).unwrap_err().to_string();
+        assert!(error.contains("Cargo.toml, cg-bundler.toml"), "{error}");
+    }
+}
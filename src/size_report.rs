@@ -0,0 +1,139 @@
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::transformer::line_markers;
+
+/// Maximum number of characters of a `CodinGame` submission
+pub const CODINGAME_LIMIT: usize = 100_000;
+
+/// Label for output that no line marker attributes to a source file
+const UNATTRIBUTED: &str = "(generated)";
+
+/// Characters of the bundle that came from one source file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileSize {
+    /// Source file, relative to the project root
+    pub file: String,
+    pub characters: usize,
+}
+
+/// Size of a bundle and how much each source file contributes to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SizeReport {
+    pub bytes: usize,
+    pub characters: usize,
+    /// Contribution of each source file, largest first
+    pub files: Vec<FileSize>,
+}
+
+impl SizeReport {
+    /// Measure `code`, attributing its characters to source files with `marked`,
+    /// the same bundle rendered with line markers
+    ///
+    /// The breakdown is read from `marked` without its marker comments, so it
+    /// adds up to the size of `code` unless markers changed the layout.
+    #[must_use]
+    pub fn new(code: &str, marked: &str) -> Self {
+        let mut files: Vec<FileSize> = line_markers::characters_by_file(marked)
+            .into_iter()
+            .map(|(file, characters)| FileSize {
+                file: if file.is_empty() {
+                    UNATTRIBUTED.to_string()
+                } else {
+                    file
+                },
+                characters,
+            })
+            .collect();
+        files.sort_by_key(|file| std::cmp::Reverse(file.characters));
+
+        Self {
+            bytes: code.len(),
+            characters: code.chars().count(),
+            files,
+        }
+    }
+
+    /// Whether the bundle has more than `limit` characters
+    #[must_use]
+    pub const fn exceeds(&self, limit: usize) -> bool {
+        self.characters > limit
+    }
+
+    /// Human-readable report: totals, then one line per source file with its
+    /// share of the bundle
+    #[must_use]
+    pub fn to_table(&self) -> String {
+        let mut table = format!(
+            "Bundle size: {} bytes, {} characters ({} of the CodinGame limit of {CODINGAME_LIMIT})\n\n",
+            self.bytes,
+            self.characters,
+            percent(self.characters, CODINGAME_LIMIT)
+        );
+        let _ = writeln!(table, "{:>10}  {:>6}  File", "Characters", "Share");
+        let attributed: usize = self.files.iter().map(|file| file.characters).sum();
+        for file in &self.files {
+            let _ = writeln!(
+                table,
+                "{:>10}  {:>6}  {}",
+                file.characters,
+                percent(file.characters, attributed),
+                file.file
+            );
+        }
+        table
+    }
+
+    /// Serialize the report as pretty-printed JSON
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// `part / whole` as a percentage with one decimal, e.g. `12.5%`
+fn percent(part: usize, whole: usize) -> String {
+    let tenths = (part * 1000).checked_div(whole).unwrap_or(0);
+    format!("{}.{}%", tenths / 10, tenths % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_attributes_characters_to_files() {
+        let code = "use x;\nfn a() {}\nfn main() {}\n";
+        let marked = "// file: src/main.rs\nuse x;\n// file: src/lib.rs\nfn a() {}\n// file: src/main.rs\nfn main() {}\n";
+        let report = SizeReport::new(code, marked);
+
+        assert_eq!(report.characters, 30);
+        assert_eq!(
+            report.files,
+            [
+                FileSize {
+                    file: "src/main.rs".to_string(),
+                    characters: 20
+                },
+                FileSize {
+                    file: "src/lib.rs".to_string(),
+                    characters: 10
+                }
+            ]
+        );
+        assert!(report.exceeds(29));
+        assert!(!report.exceeds(CODINGAME_LIMIT));
+
+        let table = report.to_table();
+        assert!(table.starts_with("Bundle size: 30 bytes, 30 characters (0.0% of"));
+        assert!(table.contains("        20   66.6%  src/main.rs\n"));
+    }
+
+    #[test]
+    fn test_characters_differ_from_bytes() {
+        let report = SizeReport::new("const S: &str = \"é\";", "");
+        assert_eq!(report.bytes, 21);
+        assert_eq!(report.characters, 20);
+        assert!(report.files.is_empty());
+    }
+}
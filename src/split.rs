@@ -0,0 +1,287 @@
+use proc_macro2::LineColumn;
+use quote::ToTokens;
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::diagnostics::Span;
+use crate::error::{BundlerError, Result};
+
+/// A file included by the root file of a split bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    /// File name, e.g. `part1.rs`, next to the root file
+    pub name: String,
+    pub code: String,
+}
+
+/// A bundle split into files of limited size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitBundle {
+    /// The crate root, including the parts
+    pub root: String,
+    pub parts: Vec<Part>,
+}
+
+/// Split a bundle into a root file and parts of at most `limit` bytes each, for
+/// judges limiting the size of each submitted file
+///
+/// The inline modules of the bundle root move to `part1.rs`, `part2.rs`, ...,
+/// consecutive modules sharing a part while it fits, and the root includes each
+/// part with `include!("partN.rs");` where its modules were. A module larger than
+/// `limit` stays as `mod name { ... }` around the `include!`s of its own items,
+/// split the same way. The text of the bundle is kept as is, comments and
+/// formatting included. A bundle within `limit` is not split.
+///
+/// # Errors
+/// Returns an error if `code` is not valid Rust, or if an item that is not a
+/// module, or the root file without the modules, is larger than `limit`
+pub fn split(code: &str, limit: usize) -> Result<SplitBundle> {
+    if code.len() <= limit {
+        return Ok(SplitBundle {
+            root: code.to_string(),
+            parts: Vec::new(),
+        });
+    }
+    let file = syn::parse_file(code).map_err(|e| BundlerError::SyntaxError {
+        message: format!("Failed to parse the bundle: {e}"),
+        file_path: None,
+        span: Span::of_syn_error(&e),
+    })?;
+
+    let mut splitter = Splitter {
+        code,
+        line_starts: std::iter::once(0)
+            .chain(code.match_indices('\n').map(|(index, _)| index + 1))
+            .collect(),
+        limit,
+        parts: Vec::new(),
+    };
+    let root = splitter.split_items(0..code.len(), &file.items, true)?;
+    if root.len() > limit {
+        return Err(BundlerError::SplitTooLarge {
+            what: "the root file, without its modules,".to_string(),
+            bytes: root.len(),
+            limit,
+        });
+    }
+    Ok(SplitBundle {
+        root,
+        parts: splitter.parts,
+    })
+}
+
+struct Splitter<'a> {
+    code: &'a str,
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+    limit: usize,
+    parts: Vec<Part>,
+}
+
+impl Splitter<'_> {
+    /// Byte offset of a span position
+    fn offset(&self, position: LineColumn) -> usize {
+        let start = self.line_starts[position.line - 1];
+        self.code[start..]
+            .char_indices()
+            .nth(position.column)
+            .map_or(self.code.len(), |(index, _)| start + index)
+    }
+
+    /// Start and end offsets of `item`, outer attributes and doc comments included
+    fn item_range(&self, item: &syn::Item) -> Range<usize> {
+        let tokens: Vec<_> = item.to_token_stream().into_iter().collect();
+        let start = tokens.first().map(|token| token.span().start());
+        let end = tokens.last().map(|token| token.span().end());
+        match (start, end) {
+            (Some(start), Some(end)) => self.offset(start)..self.offset(end),
+            _ => 0..0,
+        }
+    }
+
+    /// `offset` moved back to the start of its line if only spaces precede it
+    fn line_start(&self, offset: usize) -> usize {
+        let start = self.code[..offset].rfind('\n').map_or(0, |index| index + 1);
+        if self.code[start..offset].trim().is_empty() {
+            start
+        } else {
+            offset
+        }
+    }
+
+    /// `offset` moved past the end of its line if only spaces follow it
+    fn line_end(&self, offset: usize) -> usize {
+        let end = self.code[offset..]
+            .find('\n')
+            .map_or(self.code.len(), |index| offset + index + 1);
+        if self.code[offset..end].trim().is_empty() {
+            end
+        } else {
+            offset
+        }
+    }
+
+    /// The text of `range`, which holds `items`, with its movable items in parts:
+    /// the inline modules at the bundle root, every item below
+    fn split_items(
+        &mut self,
+        range: Range<usize>,
+        items: &[syn::Item],
+        root: bool,
+    ) -> Result<String> {
+        let ranges: Vec<Range<usize>> = items.iter().map(|item| self.item_range(item)).collect();
+        let Some(first) = ranges.first() else {
+            return Ok(self.code[range].to_string());
+        };
+        // Each item takes the comments and blank lines above it
+        let mut bounds = vec![self.line_start(first.start).max(range.start)];
+        bounds.extend(
+            ranges
+                .iter()
+                .map(|item| self.line_end(item.end).min(range.end)),
+        );
+
+        let mut code = self.code[range.start..bounds[0]].to_string();
+        // Part the previous item went to, which the next one joins if it fits
+        let mut open_part: Option<usize> = None;
+        for (index, item) in items.iter().enumerate() {
+            let segment = &self.code[bounds[index]..bounds[index + 1]];
+            let module = match item {
+                syn::Item::Mod(item_mod) => item_mod.content.as_ref().map(|(brace, content)| {
+                    (
+                        brace.span.open().start(),
+                        brace.span.close().start(),
+                        content,
+                    )
+                }),
+                _ => None,
+            };
+            if root && module.is_none() {
+                open_part = None;
+                code.push_str(segment);
+                continue;
+            }
+            let segment = if segment.len() <= self.limit {
+                segment.to_string()
+            } else if let Some((open, close, content)) = module {
+                let (open, close) = (self.offset(open) + 1, self.offset(close));
+                let inner = self.split_items(open..close, content, false)?;
+                format!(
+                    "{}{inner}{}",
+                    &self.code[bounds[index]..open],
+                    &self.code[close..bounds[index + 1]]
+                )
+            } else {
+                return Err(BundlerError::SplitTooLarge {
+                    what: describe(item),
+                    bytes: segment.len(),
+                    limit: self.limit,
+                });
+            };
+            if segment.len() > self.limit {
+                open_part = None;
+                code.push_str(&segment);
+                continue;
+            }
+
+            let part = match open_part {
+                Some(part) if self.parts[part].code.len() + segment.len() <= self.limit => part,
+                _ => {
+                    let part = self.parts.len();
+                    let name = format!("part{}.rs", part + 1);
+                    let start = ranges[index].start;
+                    let indent = &self.code[self.line_start(start)..start];
+                    let line_break = if segment.ends_with('\n') { "\n" } else { "" };
+                    let _ = write!(code, "{indent}include!(\"{name}\");{line_break}");
+                    self.parts.push(Part {
+                        name,
+                        code: String::new(),
+                    });
+                    open_part = Some(part);
+                    part
+                }
+            };
+            self.parts[part].code.push_str(&segment);
+        }
+        code.push_str(&self.code[bounds[items.len()]..range.end]);
+        Ok(code)
+    }
+}
+
+/// An item named as in an error message, e.g. `` `const TABLE` ``
+fn describe(item: &syn::Item) -> String {
+    let name = match item {
+        syn::Item::Const(item) => format!("const {}", item.ident),
+        syn::Item::Enum(item) => format!("enum {}", item.ident),
+        syn::Item::Fn(item) => format!("fn {}", item.sig.ident),
+        syn::Item::Static(item) => format!("static {}", item.ident),
+        syn::Item::Struct(item) => format!("struct {}", item.ident),
+        syn::Item::Trait(item) => format!("trait {}", item.ident),
+        syn::Item::Impl(item) => format!("impl {}", item.self_ty.to_token_stream()),
+        _ => return "an item".to_string(),
+    };
+    format!("`{name}`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = "\
+// Bundled bot
+mod geo {
+    pub struct Point;
+}
+mod ai {
+    pub fn decide() -> u32 {
+        1
+    }
+
+    // Search depth
+    pub const DEPTH: u32 = 4;
+}
+fn main() {
+    ai::decide();
+}
+";
+
+    #[test]
+    fn test_modules_move_to_parts() {
+        let split = split(BUNDLE, 150).unwrap();
+        assert_eq!(
+            split.root,
+            "// Bundled bot\ninclude!(\"part1.rs\");\nfn main() {\n    ai::decide();\n}\n"
+        );
+        assert_eq!(split.parts.len(), 1);
+        assert_eq!(split.parts[0].name, "part1.rs");
+        assert!(split.parts[0].code.starts_with("mod geo {"));
+        assert!(split.parts[0]
+            .code
+            .ends_with("pub const DEPTH: u32 = 4;\n}\n"));
+
+        assert_eq!(super::split(BUNDLE, BUNDLE.len()).unwrap().parts, []);
+    }
+
+    #[test]
+    fn test_large_module_split_inside() {
+        let split = split(BUNDLE, 100).unwrap();
+        assert_eq!(
+            split.root,
+            "// Bundled bot\ninclude!(\"part1.rs\");\nfn main() {\n    ai::decide();\n}\n"
+        );
+        assert_eq!(
+            split.parts[0].code,
+            "mod geo {\n    pub struct Point;\n}\nmod ai {\n    include!(\"part2.rs\");\n}\n"
+        );
+        assert_eq!(
+            split.parts[1].code,
+            "    pub fn decide() -> u32 {\n        1\n    }\n\n    // Search depth\n    pub const DEPTH: u32 = 4;\n"
+        );
+
+        let error = super::split(BUNDLE, 50).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot split the bundle into files of at most 50 bytes: `const DEPTH` is 51 bytes"
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use std::path::Path;
+use std::process::Command;
+use std::time::SystemTime;
+
+use crate::cargo_project::CargoProject;
+use crate::error::{BundlerError, Result};
+use crate::provenance::rfc3339;
+
+/// Variables a `Template` may reference as `{name}`
+pub const TEMPLATE_VARIABLES: [&str; 10] = [
+    "crate",
+    "crate_version",
+    "bin",
+    "profile",
+    "hash",
+    "date",
+    "time",
+    "timestamp",
+    "size",
+    "bundler_version",
+];
+
+/// Header of `--header` without `--header-template`
+pub const DEFAULT_HEADER: &str =
+    "{crate} {crate_version} ({hash})\nBuilt {timestamp} with cg-bundler {bundler_version}";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable(String),
+}
+
+/// Text with `{variable}` placeholders, such as the output file name
+/// `bundles/{crate}-{date}.rs`
+///
+/// See `TEMPLATE_VARIABLES` for the variables and `TemplateValues` for their values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parse a template
+    ///
+    /// # Errors
+    /// Returns an error for unknown variables and unbalanced braces
+    pub fn parse(template: &str) -> Result<Self> {
+        let error = |message: String| BundlerError::Config {
+            message: format!("Invalid template '{template}': {message}"),
+            path: None,
+        };
+
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find(['{', '}']) {
+            if rest[open..].starts_with('}') {
+                return Err(error("unmatched '}'".to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| error("unclosed '{'".to_string()))?;
+            let name = &rest[open + 1..open + close];
+            if !TEMPLATE_VARIABLES.contains(&name) {
+                return Err(error(format!(
+                    "unknown variable '{{{name}}}', expected one of {}",
+                    TEMPLATE_VARIABLES
+                        .map(|name| format!("{{{name}}}"))
+                        .join(", ")
+                )));
+            }
+            if open > 0 {
+                segments.push(Segment::Literal(rest[..open].to_string()));
+            }
+            segments.push(Segment::Variable(name.to_string()));
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Substitute the variables
+    #[must_use]
+    pub fn render(&self, values: &TemplateValues) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Variable(name) => values.get(name),
+            })
+            .collect()
+    }
+
+    /// Substitute the variables and turn each line that is not already a comment
+    /// into a `//` comment, for the header at the top of a bundle
+    #[must_use]
+    pub fn render_comment(&self, values: &TemplateValues) -> String {
+        self.render(values)
+            .lines()
+            .map(|line| match line.trim() {
+                "" => "//\n".to_string(),
+                trimmed if trimmed.starts_with("//") => format!("{line}\n"),
+                _ => format!("// {line}\n"),
+            })
+            .collect()
+    }
+}
+
+/// Values of the template variables for one bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateValues {
+    /// `{crate}`: crate name
+    pub crate_name: String,
+    /// `{crate_version}`: package version from `Cargo.toml`
+    pub crate_version: String,
+    /// `{bin}`: binary target name
+    pub bin: String,
+    /// `{profile}`: the profile bundled with `--profile`, else the output style,
+    /// e.g. `m2` or `pretty`
+    pub profile: String,
+    /// `{hash}`: short commit hash of the project's git repository, `unknown` outside one
+    pub hash: String,
+    /// `{date}` and `{time}`: UTC, as `2025-06-30` and `142501`
+    pub date: String,
+    pub time: String,
+    /// `{timestamp}`: UTC, as `2025-06-30T14:25:01Z`
+    pub timestamp: String,
+    /// `{size}`: bundle size in bytes
+    pub size: usize,
+}
+
+impl TemplateValues {
+    /// Values for `code`, the bundle of `project` in style `profile`, finished at `now`
+    #[must_use]
+    pub fn new(project: &CargoProject, profile: &str, code: &str, now: SystemTime) -> Self {
+        let timestamp = rfc3339(now);
+        let root = project
+            .root_package()
+            .manifest_path
+            .parent()
+            .map_or_else(|| project.base_path(), |root| root.as_std_path());
+
+        Self {
+            crate_name: project.crate_name().to_string(),
+            crate_version: project.root_package().version.to_string(),
+            bin: project.binary_target().name.clone(),
+            profile: profile.to_string(),
+            hash: git_short_hash(root).unwrap_or_else(|| "unknown".to_string()),
+            date: timestamp[..10].to_string(),
+            time: timestamp[11..19].replace(':', ""),
+            timestamp,
+            size: code.len(),
+        }
+    }
+
+    fn get(&self, name: &str) -> String {
+        match name {
+            "crate" => self.crate_name.clone(),
+            "crate_version" => self.crate_version.clone(),
+            "bin" => self.bin.clone(),
+            "profile" => self.profile.clone(),
+            "hash" => self.hash.clone(),
+            "date" => self.date.clone(),
+            "time" => self.time.clone(),
+            "timestamp" => self.timestamp.clone(),
+            "size" => self.size.to_string(),
+            "bundler_version" => env!("CARGO_PKG_VERSION").to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Short hash of the commit checked out in `dir`, if it is in a git repository
+fn git_short_hash(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    let hash = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !hash.is_empty()).then_some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> TemplateValues {
+        TemplateValues {
+            crate_name: "bot".to_string(),
+            crate_version: "0.3.1".to_string(),
+            bin: "main".to_string(),
+            profile: "m2".to_string(),
+            hash: "1a2b3c4".to_string(),
+            date: "2025-06-30".to_string(),
+            time: "142501".to_string(),
+            timestamp: "2025-06-30T14:25:01Z".to_string(),
+            size: 4096,
+        }
+    }
+
+    #[test]
+    fn test_render_substitutes_variables() {
+        let template =
+            Template::parse("out/{crate}-{profile}-{date}T{time}-{hash}.{size}.rs").unwrap();
+        assert_eq!(
+            template.render(&values()),
+            "out/bot-m2-2025-06-30T142501-1a2b3c4.4096.rs"
+        );
+        assert_eq!(
+            Template::parse("bundle.rs").unwrap().render(&values()),
+            "bundle.rs"
+        );
+    }
+
+    #[test]
+    fn test_render_comment_header() {
+        let header = Template::parse(DEFAULT_HEADER).unwrap();
+        assert_eq!(
+            header.render_comment(&values()),
+            format!(
+                "// bot 0.3.1 (1a2b3c4)\n// Built 2025-06-30T14:25:01Z with cg-bundler {}\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        let custom = Template::parse("// {crate}\n\n{bin} v{crate_version}").unwrap();
+        assert_eq!(
+            custom.render_comment(&values()),
+            "// bot\n//\n// main v0.3.1\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_templates() {
+        let unknown = Template::parse("{crate}-{version}.rs").unwrap_err();
+        assert!(unknown.to_string().contains("unknown variable '{version}'"));
+        assert!(Template::parse("{crate.rs").is_err());
+        assert!(Template::parse("crate}.rs").is_err());
+    }
+}
@@ -0,0 +1,377 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bundler::Bundler;
+use crate::diff;
+use crate::error::{BundlerError, Result};
+
+/// Environment variable that makes `Snapshots` rewrite the snapshots that differ
+/// instead of failing, e.g. `CG_BUNDLER_UPDATE_SNAPSHOTS=1 cargo test`
+pub const UPDATE_VAR: &str = "CG_BUNDLER_UPDATE_SNAPSHOTS";
+
+/// Extension of the snapshot files, so that Cargo and editors do not take them
+/// for sources of the project
+pub const SNAPSHOT_EXTENSION: &str = "snap";
+
+/// Rewrites of the parts of a bundle that change between runs or machines, so
+/// that snapshots only change with the code
+///
+/// Line endings are always normalized to `\n`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Normalizer {
+    hashes: bool,
+    timestamps: bool,
+    replacements: Vec<(String, String)>,
+}
+
+impl Normalizer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace lowercase hexadecimal words of at least 7 characters with both
+    /// digits and letters, such as commit hashes and SHA-256 digests, with `[hash]`
+    ///
+    /// Identifiers of the bundle that look like hashes, e.g. `abc1234`, are
+    /// replaced too.
+    #[must_use]
+    pub const fn strip_hashes(mut self) -> Self {
+        self.hashes = true;
+        self
+    }
+
+    /// Replace RFC 3339 timestamps (`2025-06-30T14:25:01Z`) with `[timestamp]`,
+    /// dates (`2025-06-30`) with `[date]` and times (`14:25:01`) with `[time]`
+    #[must_use]
+    pub const fn strip_timestamps(mut self) -> Self {
+        self.timestamps = true;
+        self
+    }
+
+    /// Replace every occurrence of `from` with `to`, e.g. a temporary directory with
+    /// `[fixture]`, before the other rewrites
+    #[must_use]
+    pub fn replace(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        let from = from.into();
+        if !from.is_empty() {
+            self.replacements.push((from, to.into()));
+        }
+        self
+    }
+
+    /// `code` with the rewrites applied
+    #[must_use]
+    pub fn normalize(&self, code: &str) -> String {
+        let mut code = code.replace("\r\n", "\n");
+        for (from, to) in &self.replacements {
+            code = code.replace(from.as_str(), to);
+        }
+        if self.timestamps {
+            code = replace_words(&code, timestamp_len);
+        }
+        if self.hashes {
+            code = replace_words(&code, hash_len);
+        }
+        code
+    }
+}
+
+/// What checking a snapshot did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotStatus {
+    /// The snapshot matched
+    Matched,
+    /// There was no snapshot, it was written
+    Created,
+    /// The snapshot differed and was rewritten, as `UPDATE_VAR` asked
+    Updated,
+}
+
+/// Snapshot tests of bundles: bundle fixture projects and compare the normalized
+/// bundles with the files `NAME.snap` stored in a directory
+///
+/// A missing snapshot is written, to be reviewed and committed. A snapshot that
+/// differs fails the check with a unified diff, unless updating is enabled with
+/// `with_update` or by setting `UPDATE_VAR`.
+///
+/// ```rust,no_run
+/// use cg_bundler::testing::{Normalizer, Snapshots};
+///
+/// Snapshots::new("tests/snapshots")
+///     .with_normalizer(Normalizer::new().strip_hashes().strip_timestamps())
+///     .assert_fixture("bot", "tests/fixtures/bot");
+/// ```
+pub struct Snapshots {
+    dir: PathBuf,
+    bundler: Bundler,
+    normalizer: Normalizer,
+    update: bool,
+}
+
+impl Snapshots {
+    /// Snapshots stored in `dir`, of bundles made by a default `Bundler`
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            bundler: Bundler::new(),
+            normalizer: Normalizer::new(),
+            update: std::env::var_os(UPDATE_VAR).is_some_and(|value| value != "0"),
+        }
+    }
+
+    /// Bundle the fixtures with `bundler`, e.g. one with a header or minification
+    #[must_use]
+    pub fn with_bundler(mut self, bundler: Bundler) -> Self {
+        self.bundler = bundler;
+        self
+    }
+
+    /// Normalize the bundles with `normalizer` before comparing them
+    #[must_use]
+    pub fn with_normalizer(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Rewrite the snapshots that differ instead of failing, whatever `UPDATE_VAR`
+    /// says
+    #[must_use]
+    pub const fn with_update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Path of the snapshot `name`
+    #[must_use]
+    pub fn snapshot_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.{SNAPSHOT_EXTENSION}"))
+    }
+
+    /// Normalized bundle of the Cargo package at `fixture`, in which the path of
+    /// the fixture is replaced with `[fixture]`
+    ///
+    /// # Errors
+    /// Returns an error if the fixture cannot be bundled
+    pub fn bundle(&self, fixture: impl AsRef<Path>) -> Result<String> {
+        let fixture = fixture.as_ref();
+        let code = self.bundler.bundle(fixture)?;
+        let mut normalizer = self.normalizer.clone();
+        if let Ok(canonical) = fixture.canonicalize() {
+            normalizer = normalizer.replace(canonical.display().to_string(), "[fixture]");
+        }
+        let normalizer = normalizer.replace(fixture.display().to_string(), "[fixture]");
+        Ok(normalizer.normalize(&code))
+    }
+
+    /// Compare `code`, once normalized, with the snapshot `name`
+    ///
+    /// # Errors
+    /// Returns `BundlerError::SnapshotMismatch` with the differences if the
+    /// snapshot differs and updating is disabled, or an error if the snapshot
+    /// cannot be read or written
+    pub fn check(&self, name: &str, code: &str) -> Result<SnapshotStatus> {
+        let code = self.normalizer.normalize(code);
+        let path = self.snapshot_path(name);
+        let status = match fs::read_to_string(&path) {
+            Ok(snapshot) => {
+                let snapshot = snapshot.replace("\r\n", "\n");
+                let label = path.display().to_string();
+                match diff::unified_diff(&snapshot, &code, &label, "bundle") {
+                    None => return Ok(SnapshotStatus::Matched),
+                    Some(diff) if !self.update => {
+                        return Err(BundlerError::SnapshotMismatch { path, diff });
+                    }
+                    Some(_) => SnapshotStatus::Updated,
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SnapshotStatus::Created,
+            Err(e) => {
+                return Err(BundlerError::Io {
+                    source: e,
+                    path: Some(path),
+                })
+            }
+        };
+
+        let io_error = |source, path: &Path| BundlerError::Io {
+            source,
+            path: Some(path.to_path_buf()),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| io_error(e, parent))?;
+        }
+        fs::write(&path, code).map_err(|e| io_error(e, &path))?;
+        Ok(status)
+    }
+
+    /// Bundle the Cargo package at `fixture` and compare it with the snapshot `name`
+    ///
+    /// # Errors
+    /// Returns an error if the fixture cannot be bundled or `check` fails
+    pub fn check_fixture(&self, name: &str, fixture: impl AsRef<Path>) -> Result<SnapshotStatus> {
+        let code = self.bundle(fixture)?;
+        self.check(name, &code)
+    }
+
+    /// `check_fixture`, for tests
+    ///
+    /// # Panics
+    /// Panics with the error of `check_fixture`, including the diff of a snapshot
+    /// that differs
+    #[track_caller]
+    pub fn assert_fixture(&self, name: &str, fixture: impl AsRef<Path>) {
+        if let Err(e) = self.check_fixture(name, fixture) {
+            panic!("{e}");
+        }
+    }
+}
+
+/// `code` with each word `word_len` recognizes replaced with the placeholder it
+/// returns; words start after a character that is not alphanumeric or `_`
+fn replace_words(code: &str, word_len: fn(&str) -> Option<(usize, &'static str)>) -> String {
+    let mut normalized = String::with_capacity(code.len());
+    let mut rest = code;
+    let mut previous: Option<char> = None;
+    while let Some(c) = rest.chars().next() {
+        let at_boundary = !previous.is_some_and(|p| p.is_alphanumeric() || p == '_');
+        if let Some((len, placeholder)) = at_boundary.then(|| word_len(rest)).flatten() {
+            let after = rest[len..].chars().next();
+            if !after.is_some_and(|a| a.is_alphanumeric() || a == '_') {
+                normalized.push_str(placeholder);
+                previous = rest[..len].chars().next_back();
+                rest = &rest[len..];
+                continue;
+            }
+        }
+        normalized.push(c);
+        previous = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    normalized
+}
+
+/// Length of the hash `text` starts with
+fn hash_len(text: &str) -> Option<(usize, &'static str)> {
+    let len = text
+        .bytes()
+        .take_while(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(b))
+        .count();
+    let word = &text[..len];
+    (len >= 7
+        && word.bytes().any(|b| b.is_ascii_digit())
+        && word.bytes().any(|b| b.is_ascii_alphabetic()))
+    .then_some((len, "[hash]"))
+}
+
+/// Length of the timestamp, date or time `text` starts with
+fn timestamp_len(text: &str) -> Option<(usize, &'static str)> {
+    let bytes = text.as_bytes();
+    if matches_shape(bytes, b"dddd-dd-ddTdd:dd:dd") {
+        let mut len = 19;
+        if bytes.get(len) == Some(&b'.') {
+            let digits = bytes[len + 1..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            if digits > 0 {
+                len += 1 + digits;
+            }
+        }
+        if bytes.get(len) == Some(&b'Z') {
+            len += 1;
+        } else if matches_shape(&bytes[len..], b"+dd:dd") || matches_shape(&bytes[len..], b"-dd:dd")
+        {
+            len += 6;
+        }
+        return Some((len, "[timestamp]"));
+    }
+    if matches_shape(bytes, b"dddd-dd-dd") {
+        return Some((10, "[date]"));
+    }
+    matches_shape(bytes, b"dd:dd:dd").then_some((8, "[time]"))
+}
+
+/// Whether `bytes` starts with `shape`, in which `d` stands for a digit
+fn matches_shape(bytes: &[u8], shape: &[u8]) -> bool {
+    bytes.len() >= shape.len()
+        && shape.iter().zip(bytes).all(|(&expected, &actual)| {
+            if expected == b'd' {
+                actual.is_ascii_digit()
+            } else {
+                actual == expected
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalizer() {
+        let header = "// bot 0.3.1 (1a2b3c4)\r\n// Built 2025-06-30T14:25:01Z, on 2025-06-30 at 14:25:01\r\n";
+        let normalizer = Normalizer::new().strip_hashes().strip_timestamps();
+        assert_eq!(
+            normalizer.normalize(header),
+            "// bot 0.3.1 ([hash])\n// Built [timestamp], on [date] at [time]\n"
+        );
+
+        // Words that only look like a hash or a date in part are kept
+        let code = "let x = 0x5be0cd19 + 1_000_000_007; let y = abcdef0g; // 2025-06-301\n";
+        assert_eq!(normalizer.normalize(code), code);
+        assert_eq!(
+            normalizer.normalize("sha256: e3b0c44298fc1c149afbf4c8996fb924"),
+            "sha256: [hash]"
+        );
+        assert_eq!(
+            Normalizer::new()
+                .replace("/tmp/.tmpA1", "[fixture]")
+                .normalize("include_str!(\"/tmp/.tmpA1/src/map.txt\")"),
+            "include_str!(\"[fixture]/src/map.txt\")"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_lifecycle() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let snapshots = Snapshots::new(dir.path().join("snapshots"))
+            .with_normalizer(Normalizer::new().strip_timestamps())
+            .with_update(false);
+
+        let code = "// 2025-06-30\nfn main() {}\n";
+        assert_eq!(
+            snapshots.check("bot", code).unwrap(),
+            SnapshotStatus::Created
+        );
+        assert_eq!(
+            fs::read_to_string(snapshots.snapshot_path("bot")).unwrap(),
+            "// [date]\nfn main() {}\n"
+        );
+        let code = "// 2025-07-01\nfn main() {}\n";
+        assert_eq!(
+            snapshots.check("bot", code).unwrap(),
+            SnapshotStatus::Matched
+        );
+
+        let changed = "// 2025-07-01\nfn main() { println!(\"1\"); }\n";
+        let error = snapshots.check("bot", changed).unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("does not match the snapshot"), "{message}");
+        assert!(
+            message.contains("+fn main() { println!(\"1\"); }"),
+            "{message}"
+        );
+
+        let snapshots = snapshots.with_update(true);
+        assert_eq!(
+            snapshots.check("bot", changed).unwrap(),
+            SnapshotStatus::Updated
+        );
+        assert_eq!(
+            snapshots.check("bot", changed).unwrap(),
+            SnapshotStatus::Matched
+        );
+    }
+}
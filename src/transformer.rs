@@ -1,50 +1,380 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use syn::punctuated::Punctuated;
 use syn::visit_mut::VisitMut;
 
+use crate::build_script::OutDir;
+use crate::cargo_project::FeatureSelection;
+use crate::diagnostics::Span;
 use crate::error::{BundlerError, Result};
-use crate::file_manager::FileManager;
+use crate::file_manager::{FileManager, FileProvider, OsFileProvider, PrefetchedSources};
+use crate::warnings::BundleWarning;
+
+pub mod attributes;
+pub mod collisions;
+pub mod comments;
+pub mod dead_code;
+mod defines;
+pub mod demos;
+pub mod docs;
+mod extern_crates;
+mod features;
+mod imports;
+mod includes;
+pub(crate) mod lexer;
+mod lib_module;
+pub mod line_markers;
+pub mod line_wrap;
+mod macros;
+mod mangle;
+pub mod minify;
+mod module_filter;
+pub mod pass;
+pub mod preserve;
+mod reexports;
+mod shorten;
+pub mod test_harness;
+mod unresolved_uses;
+pub mod vendor;
+pub mod verbatim;
+mod visibility;
+
+pub use attributes::{AttributeClass, AttributePolicy, AttributeRetention};
+pub use collisions::Collision;
+pub use dead_code::SymbolGraph;
+pub use demos::DemoExample;
+pub use docs::DocsMode;
+pub use line_markers::SourceMap;
+pub use minify::{Minifier, MinifyStage};
+pub use pass::{FnPass, PassStage, TransformPass};
+pub use vendor::VendoredCrate;
 
 /// Configuration for code transformation
 #[derive(Debug, Clone)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct TransformConfig {
     pub remove_tests: bool,
+    /// `false` keeps documentation comments when `docs_mode` is `Remove`
+    #[deprecated(note = "use `docs_mode` instead")]
     pub remove_docs: bool,
+    /// What happens to documentation comments
+    pub docs_mode: DocsMode,
     pub expand_modules: bool,
     pub minify: bool,
     pub aggressive_minify: bool,
+    /// Which non-doc, non-test attributes survive bundling
+    pub attribute_policy: AttributePolicy,
+    /// Remove items that are not reachable from `fn main`
+    pub strip_unused: bool,
+    /// Mark module expansion boundaries with `// file: <path>` comments
+    pub line_markers: bool,
+    /// Move `#[cfg(test)]` `demo*` functions into generated examples instead of
+    /// deleting them
+    pub extract_demos: bool,
+    /// Check that minified output re-parses to the same tokens as the syntax tree
+    pub verify_minify: bool,
+    /// Crate name matched in `extern crate`/`use` statements, overriding the name
+    /// from `Cargo.toml`
+    pub crate_name: Option<String>,
+    /// Embed the library in `pub mod <name>` instead of splicing its items into the
+    /// bundle root; `use crate_name::...` statements are pointed at the module
+    pub lib_module_name: Option<String>,
+    /// Emit items that no transform changed with their original source text
+    /// instead of re-printing them (ignored when minifying)
+    pub preserve_formatting: bool,
+    /// Break lines longer than this many characters (see `line_wrap::wrap_long_lines`)
+    pub max_line_len: Option<usize>,
+    /// Feature-gated modules to bundle, by path from the bundle root (e.g.
+    /// `ai::cover_strategy`), mapped to the Cargo feature gating each; when set,
+    /// `cfg(feature)` conditions are resolved as if only these features were enabled
+    pub enabled_modules: BTreeMap<String, String>,
+    /// Cargo features to bundle with; when set, `cfg(feature)` conditions are
+    /// resolved as if the package were built with them (see
+    /// `FeatureSelection::resolve`)
+    pub features: Option<FeatureSelection>,
+    /// Merge the `use` declarations of each module and remove duplicate imports
+    /// (see `imports::merge_imports`)
+    pub merge_imports: bool,
+    /// Rename private items colliding with an item of the same name from another
+    /// file instead of failing (see `collisions::resolve`)
+    pub rename_collisions: bool,
+    /// Gate kept test code (`remove_tests` off) behind `#[cfg(NAME)]` instead of
+    /// `#[cfg(test)]` (see `test_harness::gate_tests`)
+    pub tests_cfg: Option<String>,
+    /// Expand `macro_rules!` invocations that declare modules before bundling the
+    /// modules (see `macros::expand`)
+    pub expand_macros: bool,
+    /// Keep plain `//` and `/* */` comments of the sources in the bundle (see
+    /// `comments::parse_file`)
+    pub keep_comments: bool,
+    /// Patterns of module paths from the bundle root (`debug`, `bin_helpers::*`)
+    /// whose modules are left out of the bundle (see `module_filter::prune`)
+    pub excluded_modules: Vec<String>,
+    /// When not empty, patterns of the only modules bundled, with their ancestors
+    /// and descendants
+    pub only_modules: Vec<String>,
+    /// Values given to the consts of the bundle by name, as Rust expressions, with
+    /// the branches made unreachable by boolean ones removed (see `defines::apply`)
+    pub defines: BTreeMap<String, String>,
+    /// Rename the local variables of functions to the shortest free names (see
+    /// `shorten::shorten_locals`)
+    pub shorten_identifiers: bool,
+    /// Also rename private free functions and private struct fields to short names
+    /// (see `mangle::mangle_private_items`); implies `shorten_identifiers`
+    pub mangle: bool,
 }
 
+#[allow(deprecated)]
 impl Default for TransformConfig {
     fn default() -> Self {
         Self {
             remove_tests: true,
             remove_docs: true,
+            docs_mode: DocsMode::Remove,
             expand_modules: true,
             minify: false,
             aggressive_minify: false,
+            attribute_policy: AttributePolicy::default(),
+            strip_unused: false,
+            line_markers: false,
+            extract_demos: false,
+            verify_minify: false,
+            crate_name: None,
+            lib_module_name: None,
+            preserve_formatting: false,
+            max_line_len: None,
+            enabled_modules: BTreeMap::new(),
+            features: None,
+            merge_imports: false,
+            rename_collisions: false,
+            tests_cfg: None,
+            expand_macros: false,
+            keep_comments: false,
+            excluded_modules: Vec::new(),
+            only_modules: Vec::new(),
+            defines: BTreeMap::new(),
+            shorten_identifiers: false,
+            mangle: false,
         }
     }
 }
 
+impl TransformConfig {
+    /// The crate name override, or `default` (usually `CargoProject::crate_name`)
+    #[must_use]
+    pub fn crate_name_or<'a>(&'a self, default: &'a str) -> &'a str {
+        self.crate_name.as_deref().unwrap_or(default)
+    }
+
+    /// How docs are actually bundled: `ConvertToPlain` means `Remove` when
+    /// minifying, and `Remove` means `Keep` when the deprecated `remove_docs` is
+    /// `false`
+    #[must_use]
+    #[allow(deprecated)]
+    pub const fn effective_docs_mode(&self) -> DocsMode {
+        match self.docs_mode {
+            DocsMode::ConvertToPlain if self.minify || self.aggressive_minify => DocsMode::Remove,
+            DocsMode::Remove if !self.remove_docs => DocsMode::Keep,
+            mode => mode,
+        }
+    }
+
+    /// SHA-256 of every setting, identifying configurations that produce the same
+    /// output with this version of the bundler
+    #[must_use]
+    pub fn fingerprint(&self) -> String {
+        crate::digest::sha256_hex(format!("{self:?}").as_bytes())
+    }
+}
+
+/// Source files parsed for several transformers, e.g. the variants of
+/// `Bundler::bundle_variants`, by path and whether their comments were kept
+pub type ParsedFiles = RefCell<HashMap<(PathBuf, bool), syn::File>>;
+
 /// Handles code transformation and expansion
 pub struct CodeTransformer<'a> {
     base_path: &'a Path,
+    /// Crate root of the library, when it is not `lib.rs` in the base path
+    library_root: Option<&'a Path>,
     crate_name: &'a str,
     config: TransformConfig,
+    source_root: Option<&'a Path>,
+    entry_file: Option<&'a Path>,
+    /// Directory of the module file being expanded, for `#[path]` attributes
+    module_dir: Option<&'a Path>,
+    library: Option<&'a syn::File>,
+    sources: &'a dyn FileProvider,
+    /// Module files and libraries parsed by earlier transformers
+    parsed_files: Option<&'a ParsedFiles>,
+    out_dir: Option<&'a OutDir>,
+    /// Cargo features the package is bundled with, resolved from `config.features`
+    features: Option<&'a BTreeSet<String>>,
+    /// Inner attributes of the inlined library, hoisted to the bundle root
+    library_attrs: RefCell<Vec<syn::Attribute>>,
+    /// Line of the top-level `fn main` of the inlined library, if it has one
+    library_main: Cell<Option<usize>>,
+    vendored: &'a [VendoredCrate],
+    demo_examples: Vec<DemoExample>,
+    /// `macro_rules!` definitions visible to the modules of the file being expanded
+    macros: Vec<macros::MacroRules>,
+    /// Path from the bundle root of the module being expanded
+    module_path: Vec<String>,
+    /// Number of blocks (function bodies, ...) around the item being visited
+    block_depth: usize,
+    /// Whether the library was inlined at the bundle root
+    library_inlined: bool,
+    /// Set, across the modules of a bundle, when a path names the library
+    library_referenced: Rc<Cell<bool>>,
+    /// Found across the modules of a bundle
+    warnings: Rc<RefCell<Vec<BundleWarning>>>,
+    /// Read by include macros across the modules of a bundle
+    included_files: Rc<RefCell<Vec<PathBuf>>>,
+    /// First error found across the modules of a bundle by the visitor, which
+    /// cannot return it
+    failure: Rc<RefCell<Option<BundlerError>>>,
 }
 
 impl<'a> CodeTransformer<'a> {
     /// Create a new code transformer
     #[must_use]
-    pub const fn new(base_path: &'a Path, crate_name: &'a str, config: TransformConfig) -> Self {
+    pub fn new(base_path: &'a Path, crate_name: &'a str, config: TransformConfig) -> Self {
         Self {
             base_path,
+            library_root: None,
             crate_name,
             config,
+            source_root: None,
+            entry_file: None,
+            module_dir: None,
+            library: None,
+            sources: &OsFileProvider,
+            parsed_files: None,
+            out_dir: None,
+            features: None,
+            library_attrs: RefCell::new(Vec::new()),
+            library_main: Cell::new(None),
+            vendored: &[],
+            demo_examples: Vec::new(),
+            macros: Vec::new(),
+            module_path: Vec::new(),
+            block_depth: 0,
+            library_inlined: false,
+            library_referenced: Rc::new(Cell::new(false)),
+            warnings: Rc::new(RefCell::new(Vec::new())),
+            included_files: Rc::new(RefCell::new(Vec::new())),
+            failure: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Set the project root (line-marker paths are shown relative to it) and the
+    /// file being transformed
+    #[must_use]
+    pub const fn with_source_file(mut self, source_root: &'a Path, entry_file: &'a Path) -> Self {
+        self.source_root = Some(source_root);
+        self.entry_file = Some(entry_file);
+        self
+    }
+
+    /// Read the library from `library_root`, e.g. the `src_path` of the library
+    /// target, instead of `lib.rs` in the base path; its modules are looked up next
+    /// to it
+    #[must_use]
+    pub const fn with_library_root(mut self, library_root: &'a Path) -> Self {
+        self.library_root = Some(library_root);
+        self
+    }
+
+    /// Use an already loaded library (see `load_library`) instead of reading `lib.rs`
+    #[must_use]
+    pub const fn with_library(mut self, library: &'a syn::File) -> Self {
+        self.library = Some(library);
+        self
+    }
+
+    /// Read source files from the given in-memory map instead of the file system
+    ///
+    /// Paths are looked up relative to the base path, e.g. `base_path.join("lib.rs")`.
+    #[must_use]
+    pub const fn with_sources(mut self, files: &'a HashMap<PathBuf, String>) -> Self {
+        self.sources = files;
+        self
+    }
+
+    /// Read source files from those read ahead by `FileManager::prefetch`, then
+    /// from the file system, parsing them from the text prepared ahead
+    #[must_use]
+    pub const fn with_prefetched_sources(mut self, files: &'a PrefetchedSources) -> Self {
+        self.sources = files;
+        self
+    }
+
+    /// Read source files through `provider` instead of the file system
+    #[must_use]
+    pub const fn with_file_provider(mut self, provider: &'a dyn FileProvider) -> Self {
+        self.sources = provider;
+        self
+    }
+
+    /// Take the module files and library parsed by other transformers from
+    /// `parsed`, adding those parsed here, instead of parsing them again
+    #[must_use]
+    pub const fn with_parsed_files(mut self, parsed: &'a ParsedFiles) -> Self {
+        self.parsed_files = Some(parsed);
+        self
+    }
+
+    /// Resolve `env!("OUT_DIR")` in `include!` paths to the package's build script
+    /// output instead of the environment
+    #[must_use]
+    pub const fn with_out_dir(mut self, out_dir: &'a OutDir) -> Self {
+        self.out_dir = Some(out_dir);
+        self
+    }
+
+    /// Resolve `cfg(feature)` conditions as if exactly `features` were enabled
+    #[must_use]
+    pub const fn with_features(mut self, features: &'a BTreeSet<String>) -> Self {
+        self.features = Some(features);
+        self
+    }
+
+    /// Embed external crates in the bundle as modules (see `vendor::embed`)
+    #[must_use]
+    pub const fn with_vendored(mut self, crates: &'a [VendoredCrate]) -> Self {
+        self.vendored = crates;
+        self
+    }
+
+    /// Parse `lib.rs` and expand its module files
+    ///
+    /// The result can be shared between transformers of several binaries through
+    /// `with_library`, so the library sources are read and parsed only once.
+    ///
+    /// # Errors
+    /// Returns an error if `lib.rs` cannot be read or parsed
+    pub fn load_library(&mut self) -> Result<syn::File> {
+        let mut lib = self.read_library("sharing between binaries")?;
+        self.base_path = self.library_dir();
+        self.prune_modules(&mut lib.items);
+        if self.config.expand_macros {
+            self.macros = macros::definitions(&lib.items);
+        }
+        for item in &mut lib.items {
+            if self.config.remove_tests && Self::has_test_attribute(item) {
+                continue;
+            }
+            if let syn::Item::Mod(item_mod) = item {
+                self.visit_item_mod_mut(item_mod);
+            }
+        }
+        self.expand_macro_modules(&mut lib.items)?;
+        if let Some(e) = self.failure.borrow_mut().take() {
+            return Err(e);
         }
+        Ok(lib)
     }
 
     /// Transform a file's AST according to configuration
@@ -52,25 +382,173 @@ impl<'a> CodeTransformer<'a> {
     /// # Errors
     /// Returns an error if module expansion fails
     pub fn transform_file(&mut self, file: &mut syn::File) -> Result<()> {
-        if self.config.remove_docs {
+        self.transform_file_with_passes(file, &[])
+    }
+
+    /// Transform a file's AST, running custom passes around the built-in ones
+    ///
+    /// # Errors
+    /// Returns an error if module expansion or a custom pass fails
+    pub fn transform_file_with_passes(
+        &mut self,
+        file: &mut syn::File,
+        passes: &[Box<dyn TransformPass + '_>],
+    ) -> Result<()> {
+        if self.removes_docs() {
             self.remove_file_level_docs(file);
         }
 
-        self.expand_items(&mut file.items)?;
-
+        includes::inline(
+            &mut file.items,
+            self.file_dir(),
+            self.sources,
+            self.out_dir,
+            &self.included_files,
+            &self.warnings,
+        );
+
+        // Line markers tell which file each item came from when resolving collisions
+        let line_markers = mem::replace(&mut self.config.line_markers, true);
+        let expanded = self.expand_items(&mut file.items);
+        // The modules of the inlined library are next to the library, which may not
+        // be in the directory of the entry file (e.g. `[[bin]] path = "bot/main.rs"`)
+        let library = self.marker_path(&self.library_path());
+        let (entry_dir, library_dir) = (self.base_path, self.library_dir());
+        let module_dir = self.module_dir;
+        let mut regions: Vec<String> = Vec::new();
         for item in &mut file.items {
+            match line_markers::parse_marker(item) {
+                Some(line_markers::Marker::Open(path)) => regions.push(path),
+                Some(line_markers::Marker::Close) => {
+                    regions.pop();
+                }
+                None => {}
+            }
+            (self.base_path, self.module_dir) = if regions.first() == Some(&library) {
+                (library_dir, Some(library_dir))
+            } else {
+                (entry_dir, module_dir)
+            };
             self.visit_item_mut(item);
         }
+        (self.base_path, self.module_dir) = (entry_dir, module_dir);
+        let expanded = expanded
+            .and_then(|()| self.expand_macro_modules(&mut file.items))
+            .and_then(|()| self.inline_referenced_library(&mut file.items));
+        self.config.line_markers = line_markers;
+        expanded?;
+        if let Some(e) = self.failure.borrow_mut().take() {
+            return Err(e);
+        }
+        if self.config.lib_module_name.is_none() {
+            reexports::flatten(&mut file.items, &library);
+        }
+
+        self.apply_features_and_defines(file)?;
+        attributes::hoist_crate_attributes(file, self.library_attrs.take());
+        vendor::embed(file, self.vendored);
+        extern_crates::merge(file);
+
+        if !self.config.remove_tests {
+            test_harness::disambiguate_test_modules(&mut file.items);
+        }
+
+        self.resolve_collisions(file)?;
+        visibility::fix_restricted(&mut file.items);
+        self.warnings
+            .borrow_mut()
+            .extend(unresolved_uses::find(file));
+        if !self.config.line_markers {
+            line_markers::strip(&mut file.items);
+        }
 
+        if self.config.merge_imports {
+            imports::merge_imports(&mut file.items);
+        }
+
+        if self.config.extract_demos {
+            self.demo_examples = demos::extract(file);
+        }
+
+        pass::run_passes(passes, PassStage::BeforeBuiltin, file)?;
+
+        if self.config.strip_unused {
+            dead_code::strip_unused(file);
+        }
+
+        if self.config.shorten_identifiers || self.config.mangle {
+            shorten::shorten_locals(file);
+        }
+        if self.config.mangle {
+            mangle::mangle_private_items(file);
+        }
+
+        self.config
+            .attribute_policy
+            .apply(file, self.config.minify || self.config.aggressive_minify);
+
+        if let (false, Some(name)) = (self.config.remove_tests, &self.config.tests_cfg) {
+            let name = syn::parse_str::<syn::Ident>(name).map_err(|_| BundlerError::Config {
+                message: format!("'{name}' is not a valid cfg name to gate tests behind"),
+                path: None,
+            })?;
+            test_harness::gate_tests(&mut file.items, &name);
+        }
+
+        if self.config.line_markers {
+            let entry = self.entry_file.map(|entry| self.marker_path(entry));
+            if let Some(entry) = &entry {
+                file.items.insert(0, line_markers::marker(Some(entry)));
+            }
+            line_markers::finalize(&mut file.items, None);
+        }
+
+        pass::run_passes(passes, PassStage::AfterBuiltin, file)
+    }
+
+    /// Remove the items of disabled features and fold the consts of
+    /// `TransformConfig::defines`, warning about what they leave out
+    fn apply_features_and_defines(&self, file: &mut syn::File) -> Result<()> {
+        if self.features.is_some() || !self.config.enabled_modules.is_empty() {
+            let stripped = features::enable_modules(
+                &mut file.items,
+                self.features.cloned().unwrap_or_default(),
+                &self.config.enabled_modules,
+            )?;
+            self.warnings.borrow_mut().extend(stripped);
+        }
+        let undefined = defines::apply(file, &self.config.defines)?;
+        self.warnings.borrow_mut().extend(undefined);
         Ok(())
     }
 
+    /// Examples built from demo functions by the last `transform_file` call
+    /// (requires `TransformConfig::extract_demos`)
+    pub fn take_demo_examples(&mut self) -> Vec<DemoExample> {
+        mem::take(&mut self.demo_examples)
+    }
+
+    /// What was left out of the bundle or could not be resolved since the last
+    /// call (see `BundleWarning`)
+    pub fn take_warnings(&mut self) -> Vec<BundleWarning> {
+        self.warnings.take()
+    }
+
+    /// Files inlined by `include!`, `include_str!` and `include_bytes!` since the
+    /// last call, in the order they were first read
+    pub fn take_included_files(&mut self) -> Vec<PathBuf> {
+        self.included_files.take()
+    }
+
     /// Expand items (extern crate, use paths, etc.)
     ///
     /// # Errors
     /// Returns an error if module expansion or file parsing fails
     pub fn expand_items(&mut self, items: &mut Vec<syn::Item>) -> Result<()> {
-        if self.config.expand_modules {
+        if self.config.expand_modules && !self.module_path.is_empty() {
+            self.unlink_library(items);
+        } else if self.config.expand_modules {
+            let entry_main = Self::main_line(items);
             // Check if we have both extern crate and use statements for the same crate
             let has_extern_crate = items
                 .iter()
@@ -80,46 +558,344 @@ impl<'a> CodeTransformer<'a> {
                 .any(|item| Self::is_use_path(item, self.crate_name));
 
             if has_extern_crate {
-                // If extern crate exists, expand it and remove use statements
+                // If extern crate exists, expand it and rewrite use statements
                 self.expand_extern_crate(items)?;
-                self.remove_use_paths(items);
-            } else if has_use_statement {
+                self.rewrite_use_paths(items);
+                self.library_inlined = true;
+            } else if has_use_statement || self.uses_library_through_crate(items) {
                 // If only use statements exist, expand the library from use statements
                 self.expand_use_path(items)?;
+                self.rewrite_use_paths(items);
+                self.library_inlined = true;
             }
+            self.check_single_main(entry_main)?;
+        }
+
+        if self.config.extract_demos {
+            demos::mark(items);
         }
 
-        if self.config.remove_tests || self.config.remove_docs {
+        if self.config.remove_tests || self.removes_docs() {
             self.filter_tests_and_docs(items);
         }
 
+        if !self.config.remove_tests {
+            // Kept tests must resolve library paths inside the single bundled crate
+            test_harness::rewrite_crate_paths(
+                items,
+                self.crate_name,
+                self.config.lib_module_name.as_deref(),
+            );
+        }
+
+        self.prune_modules(items);
+        if self.config.expand_macros {
+            self.macros.extend(macros::definitions(items));
+        }
+
         Ok(())
     }
 
-    /// Remove file-level documentation
-    fn remove_file_level_docs(&self, file: &mut syn::File) {
-        if self.config.remove_docs {
-            file.attrs.retain(|attr| !Self::is_doc_attribute(attr));
+    /// Fail if both the entry file, whose `fn main` is at line `entry_main`, and
+    /// the inlined library define `fn main`
+    fn check_single_main(&self, entry_main: Option<usize>) -> Result<()> {
+        if let (Some(binary), Some(library)) = (entry_main, self.library_main.take()) {
+            let entry = self
+                .entry_file
+                .map_or_else(|| "main.rs".to_string(), |entry| self.marker_path(entry));
+            return Err(BundlerError::DuplicateMain {
+                binary: format!("{entry}:{binary}"),
+                library: format!("{}:{library}", self.marker_path(&self.library_path())),
+            });
         }
+        Ok(())
     }
 
-    /// Filter out tests and documentation
-    fn filter_tests_and_docs(&self, items: &mut Vec<syn::Item>) {
+    /// Drop the `extern crate` declarations of the library from the items of a
+    /// module, whose library imports and paths are pointed at the bundle root
+    ///
+    /// In a module of the binary, `crate::` keeps meaning the binary's own root;
+    /// only paths starting with the crate name reach the library, which is
+    /// inlined once at the bundle root (see `inline_referenced_library`).
+    fn unlink_library(&self, items: &mut Vec<syn::Item>) {
         items.retain(|item| {
+            let is_extern_crate = Self::is_extern_crate(item, self.crate_name);
+            if is_extern_crate {
+                self.library_referenced.set(true);
+            }
+            !is_extern_crate
+        });
+    }
+
+    /// Inline the library at the top of the bundle root when only its modules, or
+    /// paths outside `use` statements, refer to it
+    fn inline_referenced_library(&mut self, items: &mut Vec<syn::Item>) -> Result<()> {
+        if !self.config.expand_modules
+            || self.library_inlined
+            || !self.library_referenced.get()
+            || !self.module_path.is_empty()
+            || !self.has_library()
+        {
+            return Ok(());
+        }
+        let entry_main = Self::main_line(items);
+        let count = items.len();
+        self.expand_use_path(items)?;
+        self.library_inlined = true;
+        // Inserted at the top, after the other items were visited
+        let inserted = items.len() - count;
+        for item in &mut items[..inserted] {
+            self.visit_item_mut(item);
+        }
+        self.check_single_main(entry_main)
+    }
+
+    /// Path of the inlined library from anywhere in the bundle
+    ///
+    /// At the bundle root this is a placeholder for `crate`, which
+    /// `reexports::flatten` resolves once the library's re-exports are known.
+    fn library_root(&self) -> syn::Path {
+        self.config
+            .lib_module_name
+            .as_deref()
+            .map_or_else(reexports::library_root, lib_module::path)
+    }
+
+    /// Point a `use crate_name...` tree below the bundle root at the inlined library
+    fn rewrite_nested_use(&self, tree: &mut syn::UseTree) {
+        let root = self.library_root();
+        *tree = match mem::replace(tree, syn::parse_quote!(self)) {
+            syn::UseTree::Path(path) => {
+                let subtree = path.tree;
+                syn::parse_quote!(#root::#subtree)
+            }
+            syn::UseTree::Name(name) => {
+                let alias = name.ident;
+                syn::parse_quote!(#root as #alias)
+            }
+            syn::UseTree::Rename(rename) => {
+                let alias = rename.rename;
+                syn::parse_quote!(#root as #alias)
+            }
+            tree => tree,
+        };
+    }
+
+    /// Remove the modules among `items` excluded by `excluded_modules` or
+    /// `only_modules`, before their files are read
+    fn prune_modules(&self, items: &mut Vec<syn::Item>) {
+        module_filter::prune(
+            items,
+            &self.module_path,
+            &self.config.excluded_modules,
+            &self.config.only_modules,
+            &mut self.warnings.borrow_mut(),
+        );
+    }
+
+    /// Expand the macro invocations among `items` that declare modules, with
+    /// `expand_macros`, and bundle those modules
+    ///
+    /// Runs once the modules of `items` are expanded, so macros defined in
+    /// `#[macro_use]` modules are known.
+    fn expand_macro_modules(&mut self, items: &mut Vec<syn::Item>) -> Result<()> {
+        if !self.config.expand_macros {
+            return Ok(());
+        }
+        let expanded = macros::expand(items, &self.macros)?;
+        // The expansions have not been through `expand_items`
+        let mut dropped = Vec::new();
+        for index in expanded {
+            let item = &mut items[index];
             if self.config.remove_tests && Self::has_test_attribute(item) {
-                return false;
+                dropped.push(index);
+                continue;
             }
-            true
+            if let syn::Item::Mod(item_mod) = item {
+                let path = [self.module_path.as_slice(), &[item_mod.ident.to_string()]].concat();
+                if !module_filter::keep(
+                    &path,
+                    &self.config.excluded_modules,
+                    &self.config.only_modules,
+                    &mut self.warnings.borrow_mut(),
+                ) {
+                    dropped.push(index);
+                    continue;
+                }
+            }
+            self.tests_and_docs_filter().visit_item_mut(item);
+            self.visit_item_mut(item);
+        }
+        let mut index = 0;
+        items.retain(|_| {
+            index += 1;
+            !dropped.contains(&(index - 1))
         });
+        Ok(())
+    }
+
+    /// Fail on items of a module binding the same name, unless all of them could be
+    /// renamed with `TransformConfig::rename_collisions`
+    fn resolve_collisions(&self, file: &mut syn::File) -> Result<()> {
+        let entry = self.entry_file.map(|entry| self.marker_path(entry));
+        let (renamed, unresolved): (Vec<Collision>, Vec<Collision>) = collisions::resolve(
+            &mut file.items,
+            entry.as_deref(),
+            self.config.rename_collisions,
+        )
+        .into_iter()
+        .partition(Collision::is_resolved);
+
+        self.warnings
+            .borrow_mut()
+            .extend(
+                renamed
+                    .iter()
+                    .map(|collision| BundleWarning::RenamedCollision {
+                        collision: collision.to_string(),
+                    }),
+            );
+        if unresolved.is_empty() {
+            return Ok(());
+        }
+        Err(BundlerError::NameCollision {
+            collisions: unresolved.iter().map(ToString::to_string).collect(),
+        })
+    }
+
+    /// Crate root of the library
+    fn library_path(&self) -> PathBuf {
+        self.library_root
+            .map_or_else(|| self.base_path.join("lib.rs"), Path::to_path_buf)
+    }
+
+    /// Directory the modules of the library are looked up in
+    fn library_dir(&self) -> &'a Path {
+        self.library_root
+            .and_then(Path::parent)
+            .unwrap_or(self.base_path)
+    }
+
+    /// Path of a source file as shown in line markers
+    fn marker_path(&self, file: &Path) -> String {
+        let file = FileManager::normalize_path(file);
+        let relative = self
+            .source_root
+            .map(FileManager::normalize_path)
+            .and_then(|root| file.strip_prefix(root).ok().map(Path::to_path_buf))
+            .unwrap_or(file);
+        FileManager::display_path(&relative)
+    }
+
+    /// Parse `code`, read from `path`, unless `with_parsed_files` files have it
+    fn parse_source(&self, path: &Path, code: &str) -> syn::Result<syn::File> {
+        let keep_comments = self.config.keep_comments;
+        let Some(parsed_files) = self.parsed_files else {
+            return self.sources.parse_file(path, code, keep_comments);
+        };
+        let key = (path.to_path_buf(), keep_comments);
+        if let Some(file) = parsed_files.borrow().get(&key) {
+            return Ok(file.clone());
+        }
+        let file = self.sources.parse_file(path, code, keep_comments)?;
+        parsed_files.borrow_mut().insert(key, file.clone());
+        Ok(file)
+    }
+
+    /// The shared library if one was provided, otherwise `lib.rs` read from disk
+    fn read_library(&self, purpose: &str) -> Result<syn::File> {
+        if let Some(library) = self.library {
+            return Ok(library.clone());
+        }
+
+        let lib_path = self.library_path();
+        let code =
+            self.sources
+                .read_file(&lib_path)
+                .map_err(|_| BundlerError::ProjectStructure {
+                    message: format!("Failed to read lib.rs for {purpose}"),
+                })?;
+
+        let parsed = self.parse_source(&lib_path, &code);
+        let mut lib = parsed.map_err(|e| BundlerError::SyntaxError {
+            message: format!("Failed to parse lib.rs: {e}"),
+            file_path: Some(lib_path),
+            span: Span::of_syn_error(&e),
+        })?;
+        includes::inline(
+            &mut lib.items,
+            self.library_dir(),
+            self.sources,
+            self.out_dir,
+            &self.included_files,
+            &self.warnings,
+        );
+        Ok(lib)
+    }
 
-        if self.config.remove_docs {
-            for item in items.iter_mut() {
-                Self::remove_doc_attributes(item);
-                self.remove_doc_from_children(item);
+    /// Splice the library items into `items`, between line markers when enabled
+    ///
+    /// An embedded library's module files are expanded here, as the modules of
+    /// the bundle root are only expanded when declared with `mod name;` there.
+    fn push_library_items(&self, items: &mut Vec<syn::Item>, mut lib: syn::File) -> Result<()> {
+        if self.config.line_markers {
+            let lib_path = self.library_path();
+            items.push(line_markers::marker(Some(&self.marker_path(&lib_path))));
+        }
+        self.library_attrs.borrow_mut().extend(lib.attrs);
+        if self.config.lib_module_name.is_none() {
+            self.library_main.set(Self::main_line(&lib.items));
+        }
+        match &self.config.lib_module_name {
+            Some(name) => {
+                for item in &mut lib.items {
+                    if self.config.remove_tests && Self::has_test_attribute(item) {
+                        continue;
+                    }
+                    if let syn::Item::Mod(item_mod) = item {
+                        self.expand_mods(item_mod, self.library_dir())?;
+                    }
+                }
+                items.push(lib_module::wrap(lib.items, name));
             }
+            None => items.extend(lib.items),
+        }
+        if self.config.line_markers {
+            items.push(line_markers::marker(None));
+        }
+        Ok(())
+    }
+
+    /// Remove file-level documentation
+    fn remove_file_level_docs(&self, file: &mut syn::File) {
+        if self.removes_docs() {
+            file.attrs.retain(|attr| !Self::is_doc_attribute(attr));
+        }
+    }
+
+    /// Filter out tests and documentation, at any depth: in inline modules, impl
+    /// blocks and the bodies of functions
+    fn filter_tests_and_docs(&self, items: &mut Vec<syn::Item>) {
+        if self.config.remove_tests {
+            items.retain(|item| !Self::has_test_attribute(item));
+        }
+        let mut filter = self.tests_and_docs_filter();
+        for item in items {
+            filter.visit_item_mut(item);
+        }
+    }
+
+    fn tests_and_docs_filter(&self) -> TestsAndDocsFilter {
+        TestsAndDocsFilter {
+            tests: self.config.remove_tests,
+            docs: self.removes_docs(),
         }
     }
 
+    fn removes_docs(&self) -> bool {
+        self.config.effective_docs_mode() == DocsMode::Remove
+    }
+
     /// Check if an attribute is a documentation attribute
     fn is_doc_attribute(attr: &syn::Attribute) -> bool {
         if attr.path().is_ident("doc") {
@@ -133,8 +909,7 @@ impl<'a> CodeTransformer<'a> {
     }
 
     /// Remove documentation from child elements
-    #[allow(clippy::only_used_in_recursion)]
-    fn remove_doc_from_children(&self, item: &mut syn::Item) {
+    fn remove_doc_from_children(item: &mut syn::Item) {
         match item {
             syn::Item::Struct(item_struct) => {
                 Self::remove_docs_from_fields(&mut item_struct.fields);
@@ -158,14 +933,6 @@ impl<'a> CodeTransformer<'a> {
                     Self::remove_docs_from_trait_item(trait_item);
                 }
             }
-            syn::Item::Mod(item_mod) => {
-                if let Some((_, ref mut mod_items)) = item_mod.content {
-                    for mod_item in mod_items {
-                        Self::remove_doc_attributes(mod_item);
-                        self.remove_doc_from_children(mod_item);
-                    }
-                }
-            }
             _ => {}
         }
     }
@@ -247,18 +1014,20 @@ impl<'a> CodeTransformer<'a> {
             _ => return false,
         };
 
-        attrs.iter().any(|attr| {
-            if attr.path().is_ident("test") {
-                return true;
-            }
+        attrs.iter().any(Self::is_test_attribute)
+    }
 
-            if attr.path().is_ident("cfg") {
-                let attr_str = quote::quote!(#attr).to_string();
-                return attr_str.contains("test");
-            }
+    /// Check if an attribute marks test code: `#[test]`, or a `cfg` that only holds
+    /// in tests
+    fn is_test_attribute(attr: &syn::Attribute) -> bool {
+        if attr.path().is_ident("test") {
+            return true;
+        }
 
-            false
-        })
+        attr.path().is_ident("cfg")
+            && attr
+                .parse_args::<syn::Meta>()
+                .is_ok_and(|predicate| features::is_test_only(&predicate))
     }
 
     /// Remove documentation attributes from an item
@@ -286,24 +1055,8 @@ impl<'a> CodeTransformer<'a> {
         let mut new_items = vec![];
         for item in items.drain(..) {
             if Self::is_extern_crate(&item, self.crate_name) {
-                eprintln!(
-                    "Expanding crate {} in {}",
-                    self.crate_name,
-                    self.base_path.display()
-                );
-                let lib_path = self.base_path.join("lib.rs");
-                let code = FileManager::read_file(&lib_path).map_err(|_| {
-                    BundlerError::ProjectStructure {
-                        message: "Failed to read lib.rs for extern crate expansion".to_string(),
-                    }
-                })?;
-
-                let lib = syn::parse_file(&code).map_err(|e| BundlerError::Parsing {
-                    message: format!("Failed to parse lib.rs: {e}"),
-                    file_path: Some(lib_path),
-                })?;
-
-                new_items.extend(lib.items);
+                let lib = self.read_library("extern crate expansion")?;
+                self.push_library_items(&mut new_items, lib)?;
             } else {
                 new_items.push(item);
             }
@@ -312,71 +1065,372 @@ impl<'a> CodeTransformer<'a> {
         Ok(())
     }
 
-    /// Remove use paths without expanding library (used when extern crate is present)
-    fn remove_use_paths(&self, items: &mut Vec<syn::Item>) {
+    /// Point `use crate_name::...` statements at the inlined library
+    ///
+    /// The library now lives at the crate root, so `use crate_name::a::b;` becomes
+    /// `use crate::a::b;`. Imports that would only re-bind a name already defined
+    /// at the root (globs of the root, `self`, or items the library itself defines
+    /// or re-exports there) are dropped, as they would otherwise clash.
+    ///
+    /// When the library is embedded in a module, `use crate_name::a::b;` becomes
+    /// `use crate::module::a::b;`, and `use crate::a::b;` too when `a` is defined by
+    /// the library rather than by the binary.
+    fn rewrite_use_paths(&self, items: &mut Vec<syn::Item>) {
+        let root_names = Self::item_names(
+            items
+                .iter()
+                .filter(|item| !Self::is_use_path(item, self.crate_name)),
+        );
+        // A `use crate::name;` does not make `name` the binary's
+        let binary_names = Self::item_names(
+            items
+                .iter()
+                .filter(|item| !Self::is_use_path(item, self.crate_name))
+                .filter(|item| !Self::is_use_path(item, "crate")),
+        );
+        let module = self
+            .config
+            .lib_module_name
+            .as_deref()
+            .map(|name| syn::Ident::new(name, proc_macro2::Span::call_site()));
+        let library = module.as_ref().and_then(|module| {
+            items.iter().find_map(|item| match item {
+                syn::Item::Mod(item_mod) if item_mod.ident == *module => {
+                    item_mod.content.as_ref().map(|(_, items)| items.as_slice())
+                }
+                _ => None,
+            })
+        });
+        // Names re-exported with a glob are the library's too
+        let library_names = library.map_or_else(BTreeSet::new, |items| {
+            let mut names = Self::item_names(items.iter());
+            names.extend(reexports::glob_names(items));
+            names
+        });
+        let exported_macros = library.map_or_else(BTreeSet::new, lib_module::exported_macros);
+
         let mut new_items = vec![];
         for item in items.drain(..) {
-            if !Self::is_use_path(&item, self.crate_name) {
-                new_items.push(item);
+            match item {
+                syn::Item::Use(mut item_use)
+                    if Self::use_tree_references_crate(&item_use.tree, self.crate_name) =>
+                {
+                    let tree = match &module {
+                        Some(module) => {
+                            Self::rewrite_module_use(item_use.tree, module, &exported_macros)
+                        }
+                        None => Self::rewrite_library_use(item_use.tree, &root_names),
+                    };
+                    if let Some(tree) = tree {
+                        item_use.tree = tree;
+                        new_items.push(syn::Item::Use(item_use));
+                    }
+                }
+                syn::Item::Use(mut item_use) if module.is_some() => {
+                    if let (syn::UseTree::Path(path), Some(module)) = (&mut item_use.tree, &module)
+                    {
+                        if path.ident == "crate" {
+                            let subtree = mem::replace(&mut *path.tree, syn::parse_quote!(self));
+                            *path.tree =
+                                Self::into_module(subtree, module, &library_names, &binary_names);
+                        }
+                    }
+                    new_items.push(syn::Item::Use(item_use));
+                }
+                item => new_items.push(item),
             }
         }
         *items = new_items;
     }
 
-    /// Expand use paths
-    fn expand_use_path(&self, items: &mut Vec<syn::Item>) -> Result<()> {
-        let mut new_items = vec![];
-        let mut library_expanded = false;
+    /// Rewrite the tree of a `use crate_name...` statement, or `None` to drop it
+    fn rewrite_library_use(
+        tree: syn::UseTree,
+        root_names: &BTreeSet<String>,
+    ) -> Option<syn::UseTree> {
+        match tree {
+            syn::UseTree::Path(path) => {
+                let root = reexports::library_root();
+                let subtree = Self::prune_root_use(*path.tree, None, root_names)?;
+                Some(syn::parse_quote!(#root::#subtree))
+            }
+            syn::UseTree::Rename(rename) => {
+                let (root, alias) = (reexports::library_root(), rename.rename);
+                Some(syn::parse_quote!(#root as #alias))
+            }
+            _ => None,
+        }
+    }
 
-        for item in items.drain(..) {
-            if Self::is_use_path(&item, self.crate_name) {
-                // If this is a use statement for the current crate, expand the library
-                if !library_expanded {
-                    eprintln!(
-                        "Expanding crate {} in {} (from use statement)",
-                        self.crate_name,
-                        self.base_path.display()
-                    );
-                    let lib_path = self.base_path.join("lib.rs");
-                    let code = FileManager::read_file(&lib_path).map_err(|_| {
-                        BundlerError::ProjectStructure {
-                            message: "Failed to read lib.rs for use path expansion".to_string(),
-                        }
-                    })?;
+    /// Rewrite the tree of a `use crate_name...` statement for a library embedded
+    /// in `module`, or `None` to drop it
+    ///
+    /// Only the module itself and the library's exported macros are bound at the
+    /// root, so imports of those are dropped.
+    fn rewrite_module_use(
+        tree: syn::UseTree,
+        module: &syn::Ident,
+        exported_macros: &BTreeSet<String>,
+    ) -> Option<syn::UseTree> {
+        match tree {
+            syn::UseTree::Path(path) => {
+                let mut root_names = exported_macros.clone();
+                root_names.insert(module.to_string());
+                let subtree = Self::prune_root_use(*path.tree, Some(module), &root_names)?;
+                Some(syn::parse_quote!(crate::#module::#subtree))
+            }
+            syn::UseTree::Rename(rename) => {
+                let alias = rename.rename;
+                Some(syn::parse_quote!(crate::#module as #alias))
+            }
+            _ => None,
+        }
+    }
+
+    /// Prefix the parts of a `use crate::...` tree that name library items with
+    /// the library module, unless the binary defines them as well
+    fn into_module(
+        tree: syn::UseTree,
+        module: &syn::Ident,
+        library_names: &BTreeSet<String>,
+        binary_names: &BTreeSet<String>,
+    ) -> syn::UseTree {
+        let defined_by_library = |ident: &syn::Ident| {
+            let name = ident.to_string();
+            library_names.contains(&name) && !binary_names.contains(&name)
+        };
+        match tree {
+            syn::UseTree::Group(mut group) => {
+                group.items = mem::take(&mut group.items)
+                    .into_iter()
+                    .map(|tree| Self::into_module(tree, module, library_names, binary_names))
+                    .collect();
+                syn::UseTree::Group(group)
+            }
+            syn::UseTree::Path(syn::UsePath { ref ident, .. })
+            | syn::UseTree::Name(syn::UseName { ref ident })
+            | syn::UseTree::Rename(syn::UseRename { ref ident, .. })
+                if defined_by_library(ident) =>
+            {
+                syn::parse_quote!(#module::#tree)
+            }
+            tree => tree,
+        }
+    }
+
+    /// Drop the parts of a use tree below the crate root that are redundant there
+    ///
+    /// `parent` is the last path segment above `tree`, or `None` at the root itself.
+    fn prune_root_use(
+        tree: syn::UseTree,
+        parent: Option<&syn::Ident>,
+        root_names: &BTreeSet<String>,
+    ) -> Option<syn::UseTree> {
+        let binds_root_name = |name: &syn::Ident| root_names.contains(&name.to_string());
+        match tree {
+            syn::UseTree::Path(mut path) => {
+                let subtree = Self::prune_root_use(*path.tree, Some(&path.ident), root_names)?;
+                path.tree = Box::new(subtree);
+                Some(syn::UseTree::Path(path))
+            }
+            syn::UseTree::Name(name) => {
+                let bound = if name.ident == "self" {
+                    parent?
+                } else {
+                    &name.ident
+                };
+                (!binds_root_name(bound)).then_some(syn::UseTree::Name(name))
+            }
+            syn::UseTree::Rename(rename) => {
+                let keep = !binds_root_name(&rename.rename)
+                    && (rename.ident != "self" || parent.is_some());
+                keep.then_some(syn::UseTree::Rename(rename))
+            }
+            syn::UseTree::Glob(glob) => parent.map(|_| syn::UseTree::Glob(glob)),
+            syn::UseTree::Group(mut group) => {
+                group.items = mem::take(&mut group.items)
+                    .into_iter()
+                    .filter_map(|tree| Self::prune_root_use(tree, parent, root_names))
+                    .collect();
+                (!group.items.is_empty()).then_some(syn::UseTree::Group(group))
+            }
+        }
+    }
+
+    /// Whether the entry file imports library modules through `use crate::...`
+    ///
+    /// Only applies to the entry file of a project with a library, and only to
+    /// paths whose first segment is not defined by the entry file itself.
+    fn uses_library_through_crate(&self, items: &[syn::Item]) -> bool {
+        fn names_other(tree: &syn::UseTree, local_names: &BTreeSet<String>) -> bool {
+            match tree {
+                syn::UseTree::Path(syn::UsePath { ident, .. })
+                | syn::UseTree::Name(syn::UseName { ident })
+                | syn::UseTree::Rename(syn::UseRename { ident, .. }) => {
+                    ident != "self" && !local_names.contains(&ident.to_string())
+                }
+                syn::UseTree::Group(group) => group
+                    .items
+                    .iter()
+                    .any(|tree| names_other(tree, local_names)),
+                syn::UseTree::Glob(_) => false,
+            }
+        }
 
-                    let lib = syn::parse_file(&code).map_err(|e| BundlerError::Parsing {
-                        message: format!("Failed to parse lib.rs: {e}"),
-                        file_path: Some(lib_path),
-                    })?;
+        if self.entry_file.is_none() || !self.has_library() {
+            return false;
+        }
 
-                    new_items.extend(lib.items);
-                    library_expanded = true;
+        // The names `use crate::...` imports are not defined by the entry file
+        let local_names = Self::item_names(
+            items
+                .iter()
+                .filter(|item| !Self::is_use_path(item, "crate")),
+        );
+        items.iter().any(|item| match item {
+            syn::Item::Use(item_use) => match &item_use.tree {
+                syn::UseTree::Path(path) if path.ident == "crate" => {
+                    names_other(&path.tree, &local_names)
                 }
-                // Don't add the use statement itself
-            } else {
-                new_items.push(item);
+                _ => false,
+            },
+            _ => false,
+        })
+    }
+
+    /// Whether there is a library to inline, shared or in `lib.rs`, other than the
+    /// file being bundled
+    fn has_library(&self) -> bool {
+        let lib_path = self.library_path();
+        self.entry_file != Some(lib_path.as_path())
+            && (self.library.is_some() || self.sources.exists(&lib_path))
+    }
+
+    /// Names bound at the level of `items` by definitions and non-glob imports
+    fn item_names<'i>(items: impl Iterator<Item = &'i syn::Item>) -> BTreeSet<String> {
+        fn use_names(tree: &syn::UseTree, names: &mut BTreeSet<String>) {
+            match tree {
+                syn::UseTree::Path(path) => use_names(&path.tree, names),
+                syn::UseTree::Name(name) if name.ident != "self" => {
+                    names.insert(name.ident.to_string());
+                }
+                syn::UseTree::Rename(rename) => {
+                    names.insert(rename.rename.to_string());
+                }
+                syn::UseTree::Group(group) => {
+                    for tree in &group.items {
+                        use_names(tree, names);
+                    }
+                }
+                _ => {}
             }
         }
-        *items = new_items;
+
+        let mut names = BTreeSet::new();
+        for item in items {
+            let ident = match item {
+                syn::Item::Const(item) => &item.ident,
+                syn::Item::Enum(item) => &item.ident,
+                syn::Item::Fn(item) => &item.sig.ident,
+                syn::Item::Mod(item) => &item.ident,
+                syn::Item::Static(item) => &item.ident,
+                syn::Item::Struct(item) => &item.ident,
+                syn::Item::Trait(item) => &item.ident,
+                syn::Item::TraitAlias(item) => &item.ident,
+                syn::Item::Type(item) => &item.ident,
+                syn::Item::Union(item) => &item.ident,
+                syn::Item::Use(item) => {
+                    use_names(&item.tree, &mut names);
+                    continue;
+                }
+                _ => continue,
+            };
+            names.insert(ident.to_string());
+        }
+        names
+    }
+
+    /// Expand use paths
+    ///
+    /// The library is inserted where it is first imported (or at the top when it is
+    /// only reached through `use crate::...`); the imports themselves are kept for
+    /// `rewrite_use_paths`.
+    fn expand_use_path(&self, items: &mut Vec<syn::Item>) -> Result<()> {
+        let lib = self.read_library("use path expansion")?;
+        let position = items
+            .iter()
+            .position(|item| Self::is_use_path(item, self.crate_name))
+            .unwrap_or(0);
+
+        let mut library_items = vec![];
+        self.push_library_items(&mut library_items, lib)?;
+        items.splice(position..position, library_items);
         Ok(())
     }
 
-    /// Expand module declarations
-    fn expand_mods(&self, item: &mut syn::ItemMod) -> Result<()> {
+    /// Expand a module declaration with the contents of its file
+    ///
+    /// Modules are expanded in the order they are declared in the parent file, from
+    /// `name.rs` or `name/mod.rs`, a module with both being an error as with rustc;
+    /// the output never depends on the order in which the file system lists
+    /// directory entries.
+    ///
+    /// A `#[path = "..."]` attribute names the file relative to the directory of the
+    /// file declaring the module, which its own modules are then looked up in, as
+    /// rustc does; the attribute is dropped once the module is inline. Modules
+    /// declared in function bodies and other blocks are expanded too, without line
+    /// markers, which only take effect between the items of modules.
+    ///
+    /// Modules without `#[path]` are looked up in `dir`.
+    fn expand_mods(&self, item: &mut syn::ItemMod, dir: &Path) -> Result<()> {
         if item.content.is_some() {
             return Ok(());
         }
 
         let name = item.ident.to_string();
-        let (base_path, code) = FileManager::find_module_file(self.base_path, &name)?;
+        let (module_file, base_path) = match Self::path_attribute(&item.attrs) {
+            Some(path) => {
+                let module_file = FileManager::join_relative(self.file_dir(), &path);
+                let base_path = module_file
+                    .parent()
+                    .map_or_else(PathBuf::new, Path::to_path_buf);
+                (module_file, base_path)
+            }
+            None => self.sources.resolve_module_file(dir, &name)?,
+        };
+        let code = self.sources.read_file(&module_file)?;
 
-        let mut file = syn::parse_file(&code).map_err(|e| BundlerError::Parsing {
+        let parsed = self.parse_source(&module_file, &code);
+        let mut file = parsed.map_err(|e| BundlerError::SyntaxError {
             message: format!("Failed to parse module file: {e}"),
-            file_path: Some(base_path.join(format!("{name}.rs"))),
+            file_path: Some(module_file.clone()),
+            span: Span::of_syn_error(&e),
         })?;
+        if let Some(module_dir) = module_file.parent() {
+            includes::inline(
+                &mut file.items,
+                module_dir,
+                self.sources,
+                self.out_dir,
+                &self.included_files,
+                &self.warnings,
+            );
+        }
 
         // Use the original config for expansion to ensure consistent behavior
-        let mut expander = CodeTransformer::new(&base_path, self.crate_name, self.config.clone());
+        let mut config = self.config.clone();
+        config.line_markers &= self.block_depth == 0;
+        let mut expander = CodeTransformer::new(&base_path, self.crate_name, config);
+        expander.source_root = self.source_root;
+        expander.module_dir = module_file.parent();
+        expander.sources = self.sources;
+        expander.parsed_files = self.parsed_files;
+        expander.macros.clone_from(&self.macros);
+        expander.module_path = [self.module_path.as_slice(), &[name]].concat();
+        expander.library_referenced = Rc::clone(&self.library_referenced);
+        expander.warnings = Rc::clone(&self.warnings);
+        expander.included_files = Rc::clone(&self.included_files);
+        expander.failure = Rc::clone(&self.failure);
 
         // Apply full transformation to the module content
         expander.expand_items(&mut file.items)?;
@@ -385,22 +1439,73 @@ impl<'a> CodeTransformer<'a> {
         for item in &mut file.items {
             expander.visit_item_mut(item);
         }
+        expander.expand_macro_modules(&mut file.items)?;
 
+        if expander.config.line_markers {
+            let path = self.marker_path(&module_file);
+            file.items.insert(0, line_markers::marker(Some(&path)));
+        }
+
+        // The file's inner attributes (`#![allow(...)]`, `//!` docs) stay inside
+        // the module; crate-level ones are hoisted by `hoist_crate_attributes`
+        if self.removes_docs() {
+            file.attrs.retain(|attr| !Self::is_doc_attribute(attr));
+        }
+        item.attrs.retain(|attr| !attr.path().is_ident("path"));
+        item.attrs.extend(file.attrs);
         item.content = Some((syn::token::Brace::default(), file.items));
         Ok(())
     }
 
+    /// Directory of the file being transformed, which `#[path]` attributes and
+    /// `include!` are relative to
+    fn file_dir(&self) -> &Path {
+        self.module_dir
+            .or_else(|| self.entry_file.and_then(Path::parent))
+            .unwrap_or(self.base_path)
+    }
+
+    /// File named by the `#[path = "..."]` attribute of a module
+    fn path_attribute(attrs: &[syn::Attribute]) -> Option<String> {
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("path"))
+            .find_map(|attr| match &attr.meta {
+                syn::Meta::NameValue(syn::MetaNameValue {
+                    value:
+                        syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(path),
+                            ..
+                        }),
+                    ..
+                }) => Some(path.value()),
+                _ => None,
+            })
+    }
+
     /// Expand crate paths
     fn expand_crate_path(&self, path: &mut syn::Path) {
-        if Self::path_starts_with(path, self.crate_name) {
-            let new_segments = mem::replace(&mut path.segments, Punctuated::new())
-                .into_pairs()
-                .skip(1)
-                .collect();
-            path.segments = new_segments;
+        // A lone segment is a local binding that happens to share the crate's name
+        if path.segments.len() > 1 && Self::path_starts_with(path, self.crate_name) {
+            self.library_referenced.set(true);
+            let rest = mem::replace(&mut path.segments, Punctuated::new()).into_pairs();
+            // From the bundle root, as the path may be in a module
+            let mut segments = self.library_root().segments;
+            segments.extend(rest.skip(1).map(syn::punctuated::Pair::into_value));
+            path.segments = segments;
         }
     }
 
+    /// Line of the top-level `fn main` among `items`
+    fn main_line(items: &[syn::Item]) -> Option<usize> {
+        items.iter().find_map(|item| match item {
+            syn::Item::Fn(item_fn) if item_fn.sig.ident == "main" => {
+                Some(item_fn.sig.ident.span().start().line)
+            }
+            _ => None,
+        })
+    }
+
     /// Check if item is an extern crate declaration
     fn is_extern_crate(item: &syn::Item, crate_name: &str) -> bool {
         if let syn::Item::ExternCrate(ref item) = *item {
@@ -454,7 +1559,7 @@ impl<'a> CodeTransformer<'a> {
 
 impl VisitMut for CodeTransformer<'_> {
     fn visit_file_mut(&mut self, file: &mut syn::File) {
-        if self.config.remove_docs {
+        if self.removes_docs() {
             file.attrs.retain(|attr| !Self::is_doc_attribute(attr));
         }
 
@@ -463,7 +1568,11 @@ impl VisitMut for CodeTransformer<'_> {
         }
 
         if let Err(e) = self.expand_items(&mut file.items) {
-            eprintln!("Warning: Failed to expand items: {e}");
+            self.warnings
+                .borrow_mut()
+                .push(BundleWarning::ExpansionFailed {
+                    reason: e.to_string(),
+                });
         }
 
         for item in &mut file.items {
@@ -478,8 +1587,25 @@ impl VisitMut for CodeTransformer<'_> {
         self.visit_visibility_mut(&mut item.vis);
         self.visit_ident_mut(&mut item.ident);
 
-        if let Err(e) = self.expand_mods(item) {
-            eprintln!("Warning: Failed to expand module {}: {}", item.ident, e);
+        match self.expand_mods(item, self.base_path) {
+            Ok(()) => {}
+            // rustc rejects the crate too, so skipping the module would hide the
+            // problem until the bundle is submitted
+            Err(e @ BundlerError::AmbiguousModule { .. }) => {
+                self.failure.borrow_mut().get_or_insert(e);
+            }
+            Err(e) => {
+                let module = [self.module_path.as_slice(), &[item.ident.to_string()]].concat();
+                self.warnings
+                    .borrow_mut()
+                    .push(BundleWarning::SkippedModule {
+                        module: module.join("::"),
+                        reason: e.to_string(),
+                    });
+            }
+        }
+        if self.config.expand_macros {
+            self.macros.extend(macros::exported_definitions(item));
         }
 
         // Note: We don't recursively visit the expanded content here because
@@ -487,6 +1613,34 @@ impl VisitMut for CodeTransformer<'_> {
         // with the correct base path context
     }
 
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        // Modules declared in blocks are expanded like those of the file
+        self.block_depth += 1;
+        syn::visit_mut::visit_block_mut(self, block);
+        self.block_depth -= 1;
+    }
+
+    fn visit_item_use_mut(&mut self, item: &mut syn::ItemUse) {
+        // Imports of the bundle root were rewritten by `expand_items`
+        if Self::use_tree_references_crate(&item.tree, self.crate_name) {
+            self.library_referenced.set(true);
+            self.rewrite_nested_use(&mut item.tree);
+        }
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        // Macro input is not parsed, so its paths are found among its tokens
+        if let Some(tokens) = test_harness::rewrite_macro_paths(
+            &mac.tokens,
+            self.crate_name,
+            self.config.lib_module_name.as_deref(),
+        ) {
+            self.library_referenced.set(true);
+            mac.tokens = tokens;
+        }
+        syn::visit_mut::visit_macro_mut(self, mac);
+    }
+
     fn visit_path_mut(&mut self, path: &mut syn::Path) {
         self.expand_crate_path(path);
         for mut el in Punctuated::pairs_mut(&mut path.segments) {
@@ -496,6 +1650,55 @@ impl VisitMut for CodeTransformer<'_> {
     }
 }
 
+/// Removes tests and documentation below an item, wherever items nest: inline
+/// modules, impl and trait blocks, and the blocks of function bodies
+struct TestsAndDocsFilter {
+    tests: bool,
+    docs: bool,
+}
+
+impl VisitMut for TestsAndDocsFilter {
+    fn visit_item_mut(&mut self, item: &mut syn::Item) {
+        if self.docs {
+            CodeTransformer::remove_doc_attributes(item);
+            CodeTransformer::remove_doc_from_children(item);
+        }
+        syn::visit_mut::visit_item_mut(self, item);
+    }
+
+    fn visit_item_mod_mut(&mut self, item: &mut syn::ItemMod) {
+        if let (true, Some((_, items))) = (self.tests, &mut item.content) {
+            items.retain(|item| !CodeTransformer::has_test_attribute(item));
+        }
+        syn::visit_mut::visit_item_mod_mut(self, item);
+    }
+
+    fn visit_item_impl_mut(&mut self, item: &mut syn::ItemImpl) {
+        if self.tests {
+            item.items.retain(|impl_item| {
+                let attrs = match impl_item {
+                    syn::ImplItem::Fn(method) => &method.attrs,
+                    syn::ImplItem::Const(item_const) => &item_const.attrs,
+                    syn::ImplItem::Type(item_type) => &item_type.attrs,
+                    _ => return true,
+                };
+                !attrs.iter().any(CodeTransformer::is_test_attribute)
+            });
+        }
+        syn::visit_mut::visit_item_impl_mut(self, item);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        if self.tests {
+            block.stmts.retain(|stmt| match stmt {
+                syn::Stmt::Item(item) => !CodeTransformer::has_test_attribute(item),
+                _ => true,
+            });
+        }
+        syn::visit_mut::visit_block_mut(self, block);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -505,7 +1708,7 @@ mod tests {
     fn test_transform_config_default() {
         let config = TransformConfig::default();
         assert!(config.remove_tests);
-        assert!(config.remove_docs);
+        assert_eq!(config.docs_mode, DocsMode::Remove);
         assert!(config.expand_modules);
         assert!(!config.minify);
         assert!(!config.aggressive_minify);
@@ -573,4 +1776,271 @@ mod tests {
         };
         assert!(!CodeTransformer::is_extern_crate(&fn_item, "test_crate"));
     }
+
+    #[test]
+    fn test_rewrite_library_use() {
+        let root_names = BTreeSet::from(["clamp".to_string(), "geo".to_string()]);
+        let rewrite = |tree: syn::UseTree| {
+            CodeTransformer::rewrite_library_use(tree, &root_names).map(|tree| {
+                let mut items = vec![syn::parse_quote!(use #tree;)];
+                reexports::flatten(&mut items, "src/lib.rs");
+                let syn::Item::Use(item_use) = &items[0] else {
+                    unreachable!()
+                };
+                let tree = &item_use.tree;
+                quote::quote!(#tree).to_string().replace(' ', "")
+            })
+        };
+
+        assert_eq!(
+            rewrite(syn::parse_quote!(my_lib::geo::area)),
+            Some("crate::geo::area".to_string())
+        );
+        assert_eq!(rewrite(syn::parse_quote!(my_lib::*)), None);
+        assert_eq!(rewrite(syn::parse_quote!(my_lib::clamp)), None);
+        assert_eq!(
+            rewrite(syn::parse_quote!(my_lib::{self, clamp, geo::{self, area}})),
+            Some("crate::{geo::{area}}".to_string())
+        );
+        assert_eq!(
+            rewrite(syn::parse_quote!(my_lib as lib)),
+            Some("crateaslib".to_string())
+        );
+    }
+
+    #[test]
+    fn test_use_crate_path_expands_library() {
+        let files = HashMap::from([(PathBuf::from("lib.rs"), "pub mod geo;".to_string())]);
+        let root = Path::new("");
+        let mut transformer = CodeTransformer::new(root, "my_lib", TransformConfig::default())
+            .with_source_file(root, Path::new("main.rs"))
+            .with_sources(&files);
+
+        let mut items: Vec<syn::Item> = vec![
+            syn::parse_quote!(
+                use crate::geo::area;
+            ),
+            syn::parse_quote!(
+                fn main() {}
+            ),
+        ];
+        transformer.expand_items(&mut items).unwrap();
+        assert!(matches!(&items[0], syn::Item::Mod(item) if item.ident == "geo"));
+
+        let mut local: Vec<syn::Item> = vec![
+            syn::parse_quote!(
+                mod geo {}
+            ),
+            syn::parse_quote!(
+                use crate::geo::area;
+            ),
+        ];
+        transformer.expand_items(&mut local).unwrap();
+        assert_eq!(local.len(), 2);
+    }
+
+    #[test]
+    fn test_binary_modules_refer_to_library() {
+        let files = HashMap::from([
+            (PathBuf::from("lib.rs"), "pub fn area() -> u32 { 1 }".to_string()),
+            (
+                PathBuf::from("helpers.rs"),
+                "use my_lib::area; pub fn x() -> u32 { area() + my_lib::area() + crate::helpers::y() } pub fn y() -> u32 { 0 }".to_string(),
+            ),
+        ]);
+        let root = Path::new("");
+        let bundle = |lib_module_name: Option<&str>| {
+            let config = TransformConfig {
+                lib_module_name: lib_module_name.map(ToString::to_string),
+                ..TransformConfig::default()
+            };
+            let mut file: syn::File = syn::parse_quote! {
+                mod helpers;
+                use crate::helpers::x;
+                fn main() { x(); }
+            };
+            CodeTransformer::new(root, "my_lib", config)
+                .with_source_file(root, Path::new("main.rs"))
+                .with_sources(&files)
+                .transform_file(&mut file)
+                .unwrap();
+            quote::quote!(#file).to_string()
+        };
+
+        assert_eq!(
+            bundle(None),
+            "pub fn area () -> u32 { 1 } mod helpers { use crate :: area ; pub fn x () -> u32 { area () + crate :: area () + crate :: helpers :: y () } pub fn y () -> u32 { 0 } } use crate :: helpers :: x ; fn main () { x () ; }"
+        );
+        let embedded = bundle(Some("lib"));
+        assert!(embedded.starts_with("pub mod lib { pub fn area () -> u32 { 1 } }"));
+        assert!(embedded.contains("use crate :: lib :: area ;"));
+        assert!(embedded.contains("crate :: lib :: area () + crate :: helpers :: y ()"));
+    }
+
+    #[test]
+    fn test_parsed_files_shared_between_transformers() {
+        let root = Path::new("");
+        let parsed = ParsedFiles::default();
+        let bundle = |files: &HashMap<PathBuf, String>, config: TransformConfig| {
+            let mut file: syn::File = syn::parse_quote! {
+                mod geo;
+                fn main() {}
+            };
+            CodeTransformer::new(root, "bot", config)
+                .with_source_file(root, Path::new("main.rs"))
+                .with_sources(files)
+                .with_parsed_files(&parsed)
+                .transform_file(&mut file)
+                .unwrap();
+            quote::quote!(#file).to_string()
+        };
+
+        let first = HashMap::from([(PathBuf::from("geo.rs"), "pub fn area() {}".to_string())]);
+        assert_eq!(
+            bundle(&first, TransformConfig::default()),
+            "mod geo { pub fn area () { } } fn main () { }"
+        );
+        // Parsed by the first transformer, whatever the file now says
+        let second =
+            HashMap::from([(PathBuf::from("geo.rs"), "pub fn perimeter() {}".to_string())]);
+        let minified = TransformConfig {
+            minify: true,
+            ..TransformConfig::default()
+        };
+        assert_eq!(
+            bundle(&second, minified),
+            "mod geo { pub fn area () { } } fn main () { }"
+        );
+        // Files parsed with comments are kept apart
+        let with_comments = TransformConfig {
+            keep_comments: true,
+            ..TransformConfig::default()
+        };
+        assert!(bundle(&second, with_comments).contains("perimeter"));
+        assert_eq!(parsed.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_modules_declared_in_blocks() {
+        let files = HashMap::from([
+            (
+                PathBuf::from("ai.rs"),
+                "pub fn search() -> u32 { #[path = \"tuning.rs\"] mod tuning; tuning::DEPTH }"
+                    .to_string(),
+            ),
+            (
+                PathBuf::from("tuning.rs"),
+                "pub const DEPTH: u32 = 3;".to_string(),
+            ),
+            (PathBuf::from("scoped.rs"), "pub fn run() {}".to_string()),
+        ]);
+        let root = Path::new("");
+        let config = TransformConfig {
+            line_markers: true,
+            ..TransformConfig::default()
+        };
+        let mut file: syn::File = syn::parse_quote! {
+            mod ai;
+            fn main() {
+                { mod scoped; scoped::run(); }
+                ai::search();
+            }
+        };
+        CodeTransformer::new(root, "bot", config)
+            .with_sources(&files)
+            .transform_file(&mut file)
+            .unwrap();
+        assert_eq!(
+            quote::quote!(#file).to_string(),
+            "mod ai { __cg_bundler_file ! (\"ai.rs\") ; pub fn search () -> u32 { mod tuning { pub const DEPTH : u32 = 3 ; } tuning :: DEPTH } } fn main () { { mod scoped { pub fn run () { } } scoped :: run () ; } ai :: search () ; }"
+        );
+    }
+
+    #[test]
+    fn test_path_attribute_markers_are_normalized() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/ai")).unwrap();
+        std::fs::create_dir_all(root.join("src/shared")).unwrap();
+        std::fs::write(
+            root.join("src/ai/mod.rs"),
+            "#[path = \"./../shared/tuning.rs\"] pub mod tuning;",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("src/shared/tuning.rs"),
+            "pub const DEPTH: u32 = 3;",
+        )
+        .unwrap();
+        let config = TransformConfig {
+            line_markers: true,
+            ..TransformConfig::default()
+        };
+        let mut file: syn::File = syn::parse_quote! {
+            mod ai;
+            fn main() {}
+        };
+        let (src, entry) = (root.join("src"), root.join("src/main.rs"));
+        CodeTransformer::new(&src, "bot", config)
+            .with_source_file(&root.join("."), &entry)
+            .transform_file(&mut file)
+            .unwrap();
+        let bundle = quote::quote!(#file).to_string();
+        assert!(
+            bundle.contains("__cg_bundler_file ! (\"src/shared/tuning.rs\")"),
+            "{bundle}"
+        );
+        assert!(
+            bundle.contains("__cg_bundler_file ! (\"src/ai/mod.rs\")"),
+            "{bundle}"
+        );
+    }
+
+    #[test]
+    fn test_tests_and_docs_removed_at_any_depth() {
+        let mut file: syn::File = syn::parse_quote! {
+            /// Helpers
+            mod helpers {
+                //! Inner docs
+                pub mod deeper {
+                    /// Doc of b
+                    pub fn b() -> u32 { 2 }
+                    pub mod deepest {
+                        pub struct Grid;
+                        impl Grid {
+                            /// Doc of cell
+                            pub fn cell(&self) {}
+                            #[cfg(test)]
+                            fn fixture() -> Self { Grid }
+                            #[cfg(not(test))]
+                            fn release(&self) {}
+                        }
+                        #[cfg(all(test, unix))]
+                        mod tests {}
+                        #[test]
+                        fn stray() {}
+                    }
+                }
+            }
+            fn main() {
+                /// Local
+                mod local {
+                    /// Doc of l
+                    pub fn l() {}
+                    #[cfg(test)]
+                    mod tests {}
+                }
+                #[test]
+                fn inner_test() {}
+                local::l();
+            }
+        };
+        CodeTransformer::new(Path::new("."), "bot", TransformConfig::default())
+            .transform_file(&mut file)
+            .unwrap();
+        assert_eq!(
+            quote::quote!(#file).to_string(),
+            "mod helpers { pub mod deeper { pub fn b () -> u32 { 2 } pub mod deepest { pub struct Grid ; impl Grid { pub fn cell (& self) { } # [cfg (not (test))] fn release (& self) { } } } } } fn main () { mod local { pub fn l () { } } local :: l () ; }"
+        );
+    }
 }
@@ -0,0 +1,424 @@
+use quote::ToTokens;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+
+/// Broad category of an attribute, used to decide whether it survives bundling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeClass {
+    /// `#[doc]` attributes and doc comments
+    Doc,
+    /// `#[test]` and `#[cfg(test)]`
+    Test,
+    /// Lint levels such as `#[allow]`, `#[warn]`, `#[deny]`, `#[forbid]`, `#[expect]`
+    Lint,
+    /// `#[deprecated]`
+    Deprecated,
+    /// `#[must_use]`
+    MustUse,
+    /// Attributes that change how the code compiles (`derive`, `cfg`, `repr`, ...)
+    Semantic,
+    /// Anything not listed in the classification table
+    Other,
+}
+
+/// What to do with an attribute of a given class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AttributeRetention {
+    /// Emit the attribute unchanged
+    Keep,
+    /// Keep the attribute but drop its arguments (`#[must_use = "..."]` becomes `#[must_use]`)
+    StripArguments,
+    /// Remove the attribute entirely
+    Strip,
+}
+
+/// Classification table mapping attribute names to their class
+const CLASSIFICATION: &[(&str, AttributeClass)] = &[
+    ("doc", AttributeClass::Doc),
+    ("test", AttributeClass::Test),
+    ("allow", AttributeClass::Lint),
+    ("warn", AttributeClass::Lint),
+    ("deny", AttributeClass::Lint),
+    ("forbid", AttributeClass::Lint),
+    ("expect", AttributeClass::Lint),
+    ("deprecated", AttributeClass::Deprecated),
+    ("must_use", AttributeClass::MustUse),
+    ("derive", AttributeClass::Semantic),
+    ("cfg", AttributeClass::Semantic),
+    ("cfg_attr", AttributeClass::Semantic),
+    ("repr", AttributeClass::Semantic),
+    ("inline", AttributeClass::Semantic),
+    ("cold", AttributeClass::Semantic),
+    ("path", AttributeClass::Semantic),
+    ("macro_export", AttributeClass::Semantic),
+    ("macro_use", AttributeClass::Semantic),
+    ("non_exhaustive", AttributeClass::Semantic),
+    ("target_feature", AttributeClass::Semantic),
+    ("track_caller", AttributeClass::Semantic),
+    ("no_mangle", AttributeClass::Semantic),
+    ("export_name", AttributeClass::Semantic),
+    ("link_section", AttributeClass::Semantic),
+    ("automatically_derived", AttributeClass::Semantic),
+];
+
+/// Inner attributes only allowed at the crate root
+const CRATE_LEVEL: &[&str] = &[
+    "feature",
+    "no_std",
+    "no_main",
+    "recursion_limit",
+    "type_length_limit",
+    "crate_type",
+    "crate_name",
+    "windows_subsystem",
+    "no_builtins",
+];
+
+/// Library inner attributes that describe the library crate rather than the
+/// bundle, or that would turn lints caused by bundling (e.g. removed docs for
+/// `missing_docs`) into errors
+const LIBRARY_ONLY: &[&str] = &[
+    "no_std",
+    "no_main",
+    "crate_type",
+    "crate_name",
+    "deny",
+    "forbid",
+];
+
+/// Classify an attribute according to the classification table
+#[must_use]
+pub fn classify(attr: &syn::Attribute) -> AttributeClass {
+    let path = attr.path();
+    if path.is_ident("cfg") && is_cfg_test(attr) {
+        return AttributeClass::Test;
+    }
+
+    CLASSIFICATION
+        .iter()
+        .find(|(name, _)| path.is_ident(name))
+        .map_or(AttributeClass::Other, |(_, class)| *class)
+}
+
+/// Name of an attribute as used for policy overrides (`"must_use"`, `"clippy::all"`...)
+#[must_use]
+pub fn attribute_name(attr: &syn::Attribute) -> String {
+    attr.path()
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Paths of the attributes `attr` applies: those inside `cfg_attr(predicate, ...)`,
+/// or `attr` itself
+fn applied_paths(attr: &syn::Attribute) -> Vec<syn::Path> {
+    if !attr.path().is_ident("cfg_attr") {
+        return vec![attr.path().clone()];
+    }
+    attr.parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+        .map(|metas| {
+            metas
+                .into_iter()
+                .skip(1)
+                .map(|meta| meta.path().clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn applies_any(attr: &syn::Attribute, names: &[&str]) -> bool {
+    applied_paths(attr)
+        .iter()
+        .any(|path| names.iter().any(|name| path.is_ident(name)))
+}
+
+/// Whether an inner attribute is only allowed at the crate root, such as
+/// `#![feature(...)]` or `#![cfg_attr(nightly, feature(...))]`
+#[must_use]
+pub fn is_crate_level(attr: &syn::Attribute) -> bool {
+    applies_any(attr, CRATE_LEVEL)
+}
+
+/// Gather crate-level inner attributes at the top of the bundle
+///
+/// `library` holds the inner attributes of the inlined `lib.rs`, whose items now
+/// live at the bundle root: they are added to the file's own, except doc comments
+/// and the attributes in `LIBRARY_ONLY`. Crate-level attributes of expanded module
+/// files, illegal inside a module, are moved to the root as well. Duplicates are
+/// removed, keeping the first occurrence.
+pub fn hoist_crate_attributes(file: &mut syn::File, library: Vec<syn::Attribute>) {
+    let mut attrs = mem::take(&mut file.attrs);
+    attrs.extend(library.into_iter().filter(|attr| {
+        matches!(attr.style, syn::AttrStyle::Inner(_))
+            && classify(attr) != AttributeClass::Doc
+            && !applies_any(attr, LIBRARY_ONLY)
+    }));
+    collect_crate_level(&mut file.items, &mut attrs);
+
+    let mut seen = BTreeSet::new();
+    attrs.retain(|attr| seen.insert(attr.to_token_stream().to_string()));
+    file.attrs = attrs;
+}
+
+fn collect_crate_level(items: &mut [syn::Item], hoisted: &mut Vec<syn::Attribute>) {
+    for item in items {
+        if let syn::Item::Mod(item_mod) = item {
+            let (crate_level, kept) =
+                mem::take(&mut item_mod.attrs)
+                    .into_iter()
+                    .partition(|attr| {
+                        matches!(attr.style, syn::AttrStyle::Inner(_)) && is_crate_level(attr)
+                    });
+            item_mod.attrs = kept;
+            hoisted.extend(crate_level);
+            if let Some((_, content)) = &mut item_mod.content {
+                collect_crate_level(content, hoisted);
+            }
+        }
+    }
+}
+
+fn is_cfg_test(attr: &syn::Attribute) -> bool {
+    let mut is_test = false;
+    let _ = attr.parse_nested_meta(|meta| {
+        is_test |= meta.path.is_ident("test");
+        Ok(())
+    });
+    is_test
+}
+
+/// Configurable attribute-retention policy
+///
+/// The defaults keep every attribute in regular output. In size-optimized output
+/// (any minification level) `#[deprecated]` is dropped and `#[must_use]` loses its
+/// message. Per-attribute overrides, keyed by attribute name, always win.
+/// Doc and test attributes are governed by `docs_mode` and `remove_tests`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttributePolicy {
+    pub overrides: BTreeMap<String, AttributeRetention>,
+}
+
+impl AttributePolicy {
+    /// Override the retention of a single attribute by name
+    #[must_use]
+    pub fn with_override(mut self, name: &str, retention: AttributeRetention) -> Self {
+        self.overrides.insert(name.to_string(), retention);
+        self
+    }
+
+    /// Resolve the retention for an attribute
+    #[must_use]
+    pub fn retention(&self, attr: &syn::Attribute, size_optimized: bool) -> AttributeRetention {
+        if let Some(retention) = self.overrides.get(&attribute_name(attr)) {
+            return *retention;
+        }
+
+        match classify(attr) {
+            AttributeClass::Deprecated if size_optimized => AttributeRetention::Strip,
+            AttributeClass::MustUse if size_optimized => AttributeRetention::StripArguments,
+            _ => AttributeRetention::Keep,
+        }
+    }
+
+    /// Apply the policy to every attribute list reachable from the file's items
+    pub fn apply(&self, file: &mut syn::File, size_optimized: bool) {
+        let mut visitor = RetentionVisitor {
+            policy: self,
+            size_optimized,
+        };
+        for item in &mut file.items {
+            visitor.visit_item_mut(item);
+        }
+    }
+
+    fn filter(&self, attrs: &mut Vec<syn::Attribute>, size_optimized: bool) {
+        attrs.retain_mut(|attr| match self.retention(attr, size_optimized) {
+            AttributeRetention::Keep => true,
+            AttributeRetention::Strip => false,
+            AttributeRetention::StripArguments => {
+                attr.meta = syn::Meta::Path(attr.path().clone());
+                true
+            }
+        });
+    }
+}
+
+struct RetentionVisitor<'p> {
+    policy: &'p AttributePolicy,
+    size_optimized: bool,
+}
+
+impl RetentionVisitor<'_> {
+    fn filter(&self, attrs: &mut Vec<syn::Attribute>) {
+        self.policy.filter(attrs, self.size_optimized);
+    }
+}
+
+impl VisitMut for RetentionVisitor<'_> {
+    fn visit_item_mut(&mut self, item: &mut syn::Item) {
+        if let Some(attrs) = item_attrs_mut(item) {
+            self.filter(attrs);
+        }
+        visit_mut::visit_item_mut(self, item);
+    }
+
+    fn visit_impl_item_mut(&mut self, item: &mut syn::ImplItem) {
+        match item {
+            syn::ImplItem::Fn(item) => self.filter(&mut item.attrs),
+            syn::ImplItem::Const(item) => self.filter(&mut item.attrs),
+            syn::ImplItem::Type(item) => self.filter(&mut item.attrs),
+            _ => {}
+        }
+        visit_mut::visit_impl_item_mut(self, item);
+    }
+
+    fn visit_trait_item_mut(&mut self, item: &mut syn::TraitItem) {
+        match item {
+            syn::TraitItem::Fn(item) => self.filter(&mut item.attrs),
+            syn::TraitItem::Const(item) => self.filter(&mut item.attrs),
+            syn::TraitItem::Type(item) => self.filter(&mut item.attrs),
+            _ => {}
+        }
+        visit_mut::visit_trait_item_mut(self, item);
+    }
+
+    fn visit_field_mut(&mut self, field: &mut syn::Field) {
+        self.filter(&mut field.attrs);
+        visit_mut::visit_field_mut(self, field);
+    }
+
+    fn visit_variant_mut(&mut self, variant: &mut syn::Variant) {
+        self.filter(&mut variant.attrs);
+        visit_mut::visit_variant_mut(self, variant);
+    }
+}
+
+/// Mutable access to the outer attributes of an item
+pub(crate) fn item_attrs_mut(item: &mut syn::Item) -> Option<&mut Vec<syn::Attribute>> {
+    let attrs = match item {
+        syn::Item::Const(item) => &mut item.attrs,
+        syn::Item::Enum(item) => &mut item.attrs,
+        syn::Item::ExternCrate(item) => &mut item.attrs,
+        syn::Item::Fn(item) => &mut item.attrs,
+        syn::Item::ForeignMod(item) => &mut item.attrs,
+        syn::Item::Impl(item) => &mut item.attrs,
+        syn::Item::Macro(item) => &mut item.attrs,
+        syn::Item::Mod(item) => &mut item.attrs,
+        syn::Item::Static(item) => &mut item.attrs,
+        syn::Item::Struct(item) => &mut item.attrs,
+        syn::Item::Trait(item) => &mut item.attrs,
+        syn::Item::TraitAlias(item) => &mut item.attrs,
+        syn::Item::Type(item) => &mut item.attrs,
+        syn::Item::Union(item) => &mut item.attrs,
+        syn::Item::Use(item) => &mut item.attrs,
+        _ => return None,
+    };
+    Some(attrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(policy: &AttributePolicy, source: &str, size_optimized: bool) -> String {
+        let mut file = syn::parse_file(source).unwrap();
+        policy.apply(&mut file, size_optimized);
+        file.to_token_stream().to_string()
+    }
+
+    #[test]
+    fn test_classification_table() {
+        let cases: [(syn::Attribute, AttributeClass); 6] = [
+            (syn::parse_quote!(#[doc = "x"]), AttributeClass::Doc),
+            (syn::parse_quote!(#[cfg(test)]), AttributeClass::Test),
+            (
+                syn::parse_quote!(#[cfg(feature = "x")]),
+                AttributeClass::Semantic,
+            ),
+            (syn::parse_quote!(#[allow(dead_code)]), AttributeClass::Lint),
+            (syn::parse_quote!(#[deprecated]), AttributeClass::Deprecated),
+            (
+                syn::parse_quote!(#[serde(rename = "x")]),
+                AttributeClass::Other,
+            ),
+        ];
+        for (attr, class) in cases {
+            assert_eq!(classify(&attr), class);
+        }
+    }
+
+    #[test]
+    fn test_default_policy_keeps_everything_when_not_size_optimized() {
+        let source = "#[deprecated] #[must_use = \"msg\"] #[allow(dead_code)] fn f() -> i32 { 1 }";
+        let output = apply(&AttributePolicy::default(), source, false);
+        assert!(output.contains("deprecated"));
+        assert!(output.contains("\"msg\""));
+    }
+
+    #[test]
+    fn test_default_policy_strips_when_size_optimized() {
+        let source = "struct S; impl S { #[deprecated(note = \"old\")] #[must_use = \"msg\"] #[allow(dead_code)] fn f() -> i32 { 1 } }";
+        let output = apply(&AttributePolicy::default(), source, true);
+        assert!(!output.contains("deprecated"));
+        assert!(!output.contains("\"msg\""));
+        assert!(output.contains("must_use"));
+        assert!(output.contains("allow"));
+    }
+
+    #[test]
+    fn test_overrides_take_precedence() {
+        let policy = AttributePolicy::default()
+            .with_override("deprecated", AttributeRetention::Keep)
+            .with_override("allow", AttributeRetention::Strip);
+        let output = apply(&policy, "#[deprecated] #[allow(dead_code)] fn f() {}", true);
+        assert!(output.contains("deprecated"));
+        assert!(!output.contains("allow"));
+    }
+
+    #[test]
+    fn test_hoist_crate_attributes() {
+        let mut file: syn::File = syn::parse_quote! {
+            #![allow(clippy::all)]
+            mod geo {
+                #![allow(dead_code)]
+                #![feature(let_chains)]
+                mod inner {
+                    #![cfg_attr(nightly, feature(test))]
+                }
+            }
+            fn main() {}
+        };
+        let library: syn::File = syn::parse_quote! {
+            //! Library docs
+            #![allow(clippy::all)]
+            #![feature(let_chains)]
+            #![deny(missing_docs)]
+            #![no_std]
+        };
+
+        hoist_crate_attributes(&mut file, library.attrs);
+        let attrs: Vec<String> = file
+            .attrs
+            .iter()
+            .map(|attr| attr.to_token_stream().to_string())
+            .collect();
+        assert_eq!(
+            attrs,
+            [
+                "# ! [allow (clippy :: all)]",
+                "# ! [feature (let_chains)]",
+                "# ! [cfg_attr (nightly , feature (test))]",
+            ]
+        );
+        let output = file.items[0].to_token_stream().to_string();
+        assert!(output.contains("# ! [allow (dead_code)]"));
+        assert!(!output.contains("feature"));
+    }
+}
@@ -0,0 +1,663 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use proc_macro2::{Group, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::visit_mut::{self, VisitMut};
+
+use super::line_markers::{self, Marker};
+
+/// A definition of a colliding name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    /// `file:line` of the definition in the original sources
+    pub location: String,
+    /// Name the definition was given to resolve the collision, if it was renamed
+    pub renamed_to: Option<String>,
+}
+
+/// Items of one module of the bundle that bind the same name, which rustc rejects
+/// with E0428 unless all but one of them are renamed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    /// Path of the module, e.g. `crate::ai`
+    pub module: String,
+    pub name: String,
+    /// Definitions in bundle order, the kept one first
+    pub definitions: Vec<Definition>,
+}
+
+impl Collision {
+    /// Whether renaming left a single definition with the name
+    #[must_use]
+    pub fn is_resolved(&self) -> bool {
+        self.definitions
+            .iter()
+            .filter(|definition| definition.renamed_to.is_none())
+            .count()
+            <= 1
+    }
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` in {}: ", self.name, self.module)?;
+        for (index, definition) in self.definitions.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", definition.location)?;
+            if let Some(renamed_to) = &definition.renamed_to {
+                write!(f, " (renamed to `{renamed_to}`)")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find items of the same module binding the same name in the same namespace,
+/// renaming private ones when `rename` is set
+///
+/// `items` is the bundle before line markers are finalized: the marker items
+/// tell which file each item came from, `entry` being the file of the items
+/// before the first marker. Items gated by different `#[cfg]` attributes are
+/// assumed not to be compiled together, and `macro_rules!` definitions, which may
+/// shadow each other, are ignored.
+///
+/// The first public definition, or else the first one, keeps its name. Private
+/// definitions from another file than the kept one are renamed `name_2`, `name_3`,
+/// ... together with the paths that refer to them in the items of their file:
+/// plain and `self::` paths in the module, `super::` paths in its child modules,
+/// and `crate::` paths. Returns every collision, renamed or not.
+pub fn resolve(items: &mut [syn::Item], entry: Option<&str>, rename: bool) -> Vec<Collision> {
+    let mut collisions = Vec::new();
+    resolve_module(items, &mut Vec::new(), entry, rename, &mut collisions);
+    collisions
+}
+
+fn resolve_module(
+    items: &mut [syn::Item],
+    module: &mut Vec<String>,
+    file: Option<&str>,
+    rename: bool,
+    collisions: &mut Vec<Collision>,
+) {
+    let files = item_files(items, file);
+    let mut taken: BTreeSet<String> = items
+        .iter()
+        .filter_map(|item| binding(item).map(|(name, _)| name))
+        .collect();
+
+    let mut seen = BTreeSet::new();
+    for index in 0..items.len() {
+        if let Some((name, namespaces)) = binding(&items[index]) {
+            if seen.contains(&(name.clone(), index)) {
+                continue;
+            }
+            let definitions: Vec<usize> = (index..items.len())
+                .filter(|&other| {
+                    binding(&items[other]).is_some_and(|(other_name, other_namespaces)| {
+                        other_name == name && namespaces.intersects(other_namespaces)
+                    })
+                })
+                .collect();
+            seen.extend(
+                definitions
+                    .iter()
+                    .map(|&definition| (name.clone(), definition)),
+            );
+            if definitions.len() < 2 {
+                continue;
+            }
+            let kept = definitions
+                .iter()
+                .copied()
+                .find(|&definition| {
+                    matches!(
+                        visibility(&items[definition]),
+                        Some(syn::Visibility::Public(_))
+                    )
+                })
+                .unwrap_or(definitions[0]);
+            let clashing: Vec<usize> = definitions
+                .into_iter()
+                .filter(|&definition| {
+                    definition == kept || cfg_overlaps(&items[kept], &items[definition])
+                })
+                .collect();
+            if clashing.len() < 2 {
+                continue;
+            }
+
+            let mut collision = Collision {
+                module: std::iter::once("crate")
+                    .chain(module.iter().map(String::as_str))
+                    .collect::<Vec<_>>()
+                    .join("::"),
+                name: name.clone(),
+                definitions: Vec::new(),
+            };
+            for definition in
+                std::iter::once(kept).chain(clashing.into_iter().filter(|&d| d != kept))
+            {
+                let location = location(&items[definition], files[definition].as_deref());
+                let renamable = rename
+                    && definition != kept
+                    && files[definition] != files[kept]
+                    && matches!(
+                        visibility(&items[definition]),
+                        Some(syn::Visibility::Inherited)
+                    );
+                let renamed_to = renamable.then(|| {
+                    let new_name = fresh_name(&name, &taken);
+                    taken.insert(new_name.clone());
+                    rename_in_file(items, &files, definition, module, &name, &new_name);
+                    new_name
+                });
+                collision.definitions.push(Definition {
+                    location,
+                    renamed_to,
+                });
+            }
+            collisions.push(collision);
+        }
+    }
+
+    for (item, file) in items.iter_mut().zip(files) {
+        if let syn::Item::Mod(syn::ItemMod {
+            ident,
+            content: Some((_, content)),
+            ..
+        }) = item
+        {
+            module.push(ident.to_string());
+            resolve_module(content, module, file.as_deref(), rename, collisions);
+            module.pop();
+        }
+    }
+}
+
+/// File each of `items` came from, following the line markers among them
+pub(super) fn item_files(items: &[syn::Item], file: Option<&str>) -> Vec<Option<String>> {
+    let mut current = file.map(str::to_string);
+    let mut enclosing = Vec::new();
+    items
+        .iter()
+        .map(|item| {
+            match line_markers::parse_marker(item) {
+                Some(Marker::Open(path)) => enclosing.push(current.replace(path)),
+                Some(Marker::Close) => {
+                    if let Some(outer) = enclosing.pop() {
+                        current = outer;
+                    }
+                }
+                None => {}
+            }
+            current.clone()
+        })
+        .collect()
+}
+
+/// Namespaces a name can be bound in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Namespaces {
+    types: bool,
+    values: bool,
+}
+
+impl Namespaces {
+    pub(super) const TYPE: Self = Self {
+        types: true,
+        values: false,
+    };
+    pub(super) const VALUE: Self = Self {
+        types: false,
+        values: true,
+    };
+    pub(super) const ALL: Self = Self {
+        types: true,
+        values: true,
+    };
+
+    pub(super) const fn intersects(self, other: Self) -> bool {
+        (self.types && other.types) || (self.values && other.values)
+    }
+}
+
+/// Name an item binds in its module, with its namespaces
+pub(super) fn binding(item: &syn::Item) -> Option<(String, Namespaces)> {
+    let namespaces = match item {
+        syn::Item::Fn(_) | syn::Item::Const(_) | syn::Item::Static(_) => Namespaces::VALUE,
+        // Tuple and unit structs also define a constructor
+        syn::Item::Struct(item) => Namespaces {
+            types: true,
+            values: !matches!(item.fields, syn::Fields::Named(_)),
+        },
+        _ => Namespaces::TYPE,
+    };
+    ident(item)
+        .filter(|ident| *ident != "_")
+        .map(|ident| (ident.to_string(), namespaces))
+}
+
+/// Name of the items that can collide: `macro_rules!` definitions may shadow each
+/// other and `use` declarations are handled by `imports::merge_imports`
+const fn ident(item: &syn::Item) -> Option<&syn::Ident> {
+    match item {
+        syn::Item::Fn(item) => Some(&item.sig.ident),
+        syn::Item::Const(item) => Some(&item.ident),
+        syn::Item::Static(item) => Some(&item.ident),
+        syn::Item::Struct(item) => Some(&item.ident),
+        syn::Item::Enum(item) => Some(&item.ident),
+        syn::Item::Union(item) => Some(&item.ident),
+        syn::Item::Trait(item) => Some(&item.ident),
+        syn::Item::TraitAlias(item) => Some(&item.ident),
+        syn::Item::Type(item) => Some(&item.ident),
+        syn::Item::Mod(item) => Some(&item.ident),
+        _ => None,
+    }
+}
+
+fn ident_mut(item: &mut syn::Item) -> Option<&mut syn::Ident> {
+    match item {
+        syn::Item::Fn(item) => Some(&mut item.sig.ident),
+        syn::Item::Const(item) => Some(&mut item.ident),
+        syn::Item::Static(item) => Some(&mut item.ident),
+        syn::Item::Struct(item) => Some(&mut item.ident),
+        syn::Item::Enum(item) => Some(&mut item.ident),
+        syn::Item::Union(item) => Some(&mut item.ident),
+        syn::Item::Trait(item) => Some(&mut item.ident),
+        syn::Item::TraitAlias(item) => Some(&mut item.ident),
+        syn::Item::Type(item) => Some(&mut item.ident),
+        syn::Item::Mod(item) => Some(&mut item.ident),
+        _ => None,
+    }
+}
+
+const fn visibility(item: &syn::Item) -> Option<&syn::Visibility> {
+    match item {
+        syn::Item::Fn(item) => Some(&item.vis),
+        syn::Item::Const(item) => Some(&item.vis),
+        syn::Item::Static(item) => Some(&item.vis),
+        syn::Item::Struct(item) => Some(&item.vis),
+        syn::Item::Enum(item) => Some(&item.vis),
+        syn::Item::Union(item) => Some(&item.vis),
+        syn::Item::Trait(item) => Some(&item.vis),
+        syn::Item::TraitAlias(item) => Some(&item.vis),
+        syn::Item::Type(item) => Some(&item.vis),
+        syn::Item::Mod(item) => Some(&item.vis),
+        _ => None,
+    }
+}
+
+fn attrs(item: &syn::Item) -> &[syn::Attribute] {
+    match item {
+        syn::Item::Fn(item) => &item.attrs,
+        syn::Item::Const(item) => &item.attrs,
+        syn::Item::Static(item) => &item.attrs,
+        syn::Item::Struct(item) => &item.attrs,
+        syn::Item::Enum(item) => &item.attrs,
+        syn::Item::Union(item) => &item.attrs,
+        syn::Item::Trait(item) => &item.attrs,
+        syn::Item::TraitAlias(item) => &item.attrs,
+        syn::Item::Type(item) => &item.attrs,
+        syn::Item::Mod(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+/// Whether both items can be compiled together, i.e. they have the same `#[cfg]`
+/// attributes or one of them has none
+fn cfg_overlaps(first: &syn::Item, second: &syn::Item) -> bool {
+    let cfgs = |item| -> Vec<String> {
+        attrs(item)
+            .iter()
+            .filter(|attr| attr.path().is_ident("cfg"))
+            .map(|attr| attr.to_token_stream().to_string())
+            .collect()
+    };
+    let (first, second) = (cfgs(first), cfgs(second));
+    first.is_empty() || second.is_empty() || first == second
+}
+
+fn location(item: &syn::Item, file: Option<&str>) -> String {
+    let line = ident(item).map_or(0, |ident| ident.span().start().line);
+    format!("{}:{line}", file.unwrap_or("<bundle>"))
+}
+
+/// `name_2`, `name_3`, ... whichever is not bound in the module yet
+fn fresh_name(name: &str, taken: &BTreeSet<String>) -> String {
+    let mut suffix = 2;
+    while taken.contains(&format!("{name}_{suffix}")) {
+        suffix += 1;
+    }
+    format!("{name}_{suffix}")
+}
+
+/// Rename the item at `index` and the paths referring to it in the items of the
+/// module that came from the same file
+fn rename_in_file(
+    items: &mut [syn::Item],
+    files: &[Option<String>],
+    index: usize,
+    module: &[String],
+    name: &str,
+    new_name: &str,
+) {
+    if let Some(ident) = ident_mut(&mut items[index]) {
+        *ident = syn::Ident::new(new_name, ident.span());
+    }
+    let mut renamer = Renamer {
+        name,
+        new_name,
+        module,
+        depth: 0,
+        visible: true,
+        shadowed: false,
+    };
+    for (item, file) in items.iter_mut().zip(files) {
+        if *file == files[index] {
+            renamer.visit_item_mut(item);
+        }
+    }
+}
+
+/// Rewrites the paths to a renamed item of `module`
+struct Renamer<'a> {
+    name: &'a str,
+    new_name: &'a str,
+    /// Path of the module from the crate root
+    module: &'a [String],
+    /// How many modules below `module` the visited code is
+    depth: usize,
+    /// Whether the item is in scope under its plain name, i.e. in `module` or in
+    /// child modules importing it with `use super::*`
+    visible: bool,
+    /// Whether a local binding hides the item in the visited block
+    shadowed: bool,
+}
+
+impl Renamer<'_> {
+    /// Index of the segment of `segments` (a path without leading `::`) that
+    /// names the renamed item; `local` tells whether a lone segment may name a
+    /// local variable instead
+    fn target(&self, segments: &[String], local: bool) -> Option<usize> {
+        let is_name = |index: usize| segments.get(index).is_some_and(|s| s == self.name);
+        if self.visible && is_name(0) && (segments.len() > 1 || !(local && self.shadowed)) {
+            return Some(0);
+        }
+        if self.depth == 0 && segments.first().is_some_and(|s| s == "self") && is_name(1) {
+            return Some(1);
+        }
+        if self.depth > 0
+            && segments.len() > self.depth
+            && segments[..self.depth].iter().all(|s| s == "super")
+            && is_name(self.depth)
+        {
+            return Some(self.depth);
+        }
+        let crate_len = self.module.len() + 1;
+        (segments.first().is_some_and(|s| s == "crate")
+            && segments.get(1..crate_len) == Some(self.module)
+            && is_name(crate_len))
+        .then_some(crate_len)
+    }
+
+    fn new_ident(&self, span: proc_macro2::Span) -> syn::Ident {
+        syn::Ident::new(self.new_name, span)
+    }
+
+    /// Rename `tree`, whose path so far is `prefix`
+    fn rename_use(&self, tree: &mut syn::UseTree, prefix: &mut Vec<String>) {
+        match tree {
+            syn::UseTree::Path(path) => {
+                prefix.push(path.ident.to_string());
+                if self.target(prefix, false) == Some(prefix.len() - 1) {
+                    path.ident = self.new_ident(path.ident.span());
+                }
+                self.rename_use(&mut path.tree, prefix);
+                prefix.pop();
+            }
+            syn::UseTree::Name(use_name) => {
+                prefix.push(use_name.ident.to_string());
+                if self.target(prefix, false) == Some(prefix.len() - 1) {
+                    // Keep the name the importing module uses
+                    *tree = syn::UseTree::Rename(syn::UseRename {
+                        ident: self.new_ident(use_name.ident.span()),
+                        as_token: syn::Token![as](use_name.ident.span()),
+                        rename: use_name.ident.clone(),
+                    });
+                }
+                prefix.pop();
+            }
+            syn::UseTree::Rename(rename) => {
+                prefix.push(rename.ident.to_string());
+                if self.target(prefix, false) == Some(prefix.len() - 1) {
+                    rename.ident = self.new_ident(rename.ident.span());
+                }
+                prefix.pop();
+            }
+            syn::UseTree::Group(group) => {
+                for tree in &mut group.items {
+                    self.rename_use(tree, prefix);
+                }
+            }
+            syn::UseTree::Glob(_) => {}
+        }
+    }
+
+    /// Rename plain and `self::` uses of the item in macro input, where paths are not
+    /// parsed; plain uses are left alone where a local variable may hide the item
+    fn rename_tokens(&self, tokens: TokenStream) -> TokenStream {
+        let trees: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut renamed = Vec::with_capacity(trees.len());
+        for (index, tree) in trees.iter().enumerate() {
+            // Fields, methods, metavariables and paths of other items
+            let follows_punct = index > 0
+                && matches!(&trees[index - 1], TokenTree::Punct(punct)
+                    if matches!(punct.as_char(), '.' | ':' | '$'));
+            let follows_self = self.depth == 0
+                && index >= 3
+                && matches!(&trees[index - 3], TokenTree::Ident(ident) if ident == "self")
+                && trees[index - 2..index]
+                    .iter()
+                    .all(|tree| matches!(tree, TokenTree::Punct(punct) if punct.as_char() == ':'));
+            renamed.push(match tree {
+                TokenTree::Ident(ident)
+                    if ident == self.name
+                        && (follows_self || !(follows_punct || self.shadowed)) =>
+                {
+                    TokenTree::Ident(self.new_ident(ident.span()))
+                }
+                TokenTree::Group(group) => {
+                    let mut new_group =
+                        Group::new(group.delimiter(), self.rename_tokens(group.stream()));
+                    new_group.set_span(group.span());
+                    TokenTree::Group(new_group)
+                }
+                tree => tree.clone(),
+            });
+        }
+        renamed.into_iter().collect()
+    }
+
+    /// Whether `pat` binds a local variable with the item's name
+    fn binds(&self, pat: &syn::Pat) -> bool {
+        match pat {
+            syn::Pat::Ident(pat) => pat.ident == self.name,
+            syn::Pat::Tuple(pat) => pat.elems.iter().any(|pat| self.binds(pat)),
+            syn::Pat::TupleStruct(pat) => pat.elems.iter().any(|pat| self.binds(pat)),
+            syn::Pat::Slice(pat) => pat.elems.iter().any(|pat| self.binds(pat)),
+            syn::Pat::Or(pat) => pat.cases.iter().any(|pat| self.binds(pat)),
+            syn::Pat::Struct(pat) => pat.fields.iter().any(|field| self.binds(&field.pat)),
+            syn::Pat::Reference(pat) => self.binds(&pat.pat),
+            syn::Pat::Type(pat) => self.binds(&pat.pat),
+            syn::Pat::Paren(pat) => self.binds(&pat.pat),
+            _ => false,
+        }
+    }
+
+    /// Whether a parameter of `sig` binds a local variable with the item's name
+    fn binds_param(&self, sig: &syn::Signature) -> bool {
+        sig.inputs.iter().any(|input| match input {
+            syn::FnArg::Typed(pat_type) => self.binds(&pat_type.pat),
+            syn::FnArg::Receiver(_) => false,
+        })
+    }
+
+    /// Visit a function or closure body, hidden by its parameters if `hidden`
+    fn visit_body(&mut self, hidden: bool, visit: impl FnOnce(&mut Self)) {
+        let shadowed = self.shadowed;
+        self.shadowed |= hidden;
+        visit(self);
+        self.shadowed = shadowed;
+    }
+}
+
+impl VisitMut for Renamer<'_> {
+    fn visit_path_mut(&mut self, path: &mut syn::Path) {
+        if path.leading_colon.is_none() {
+            let segments: Vec<String> = path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect();
+            if let Some(index) = self.target(&segments, true) {
+                let ident = &mut path.segments[index].ident;
+                *ident = self.new_ident(ident.span());
+            }
+        }
+        visit_mut::visit_path_mut(self, path);
+    }
+
+    fn visit_item_use_mut(&mut self, item: &mut syn::ItemUse) {
+        if item.leading_colon.is_none() {
+            self.rename_use(&mut item.tree, &mut Vec::new());
+        }
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        visit_mut::visit_path_mut(self, &mut mac.path);
+        if self.visible {
+            mac.tokens = self.rename_tokens(std::mem::take(&mut mac.tokens));
+        }
+    }
+
+    fn visit_item_mod_mut(&mut self, item: &mut syn::ItemMod) {
+        let Some((_, content)) = &mut item.content else {
+            return;
+        };
+        let (visible, shadowed) = (self.visible, self.shadowed);
+        let imports_parent = content.iter().any(|item| match item {
+            syn::Item::Use(syn::ItemUse {
+                leading_colon: None,
+                tree: syn::UseTree::Path(path),
+                ..
+            }) => path.ident == "super" && matches!(*path.tree, syn::UseTree::Glob(_)),
+            _ => false,
+        });
+        let defines_name = content
+            .iter()
+            .any(|item| binding(item).is_some_and(|(name, _)| name == self.name));
+        self.visible = visible && imports_parent && !defines_name;
+        self.shadowed = false;
+        self.depth += 1;
+        for item in content {
+            self.visit_item_mut(item);
+        }
+        self.depth -= 1;
+        (self.visible, self.shadowed) = (visible, shadowed);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        let shadowed = self.shadowed;
+        for stmt in &mut block.stmts {
+            self.visit_stmt_mut(stmt);
+            if let syn::Stmt::Local(local) = stmt {
+                self.shadowed |= self.binds(&local.pat);
+            }
+        }
+        self.shadowed = shadowed;
+    }
+
+    fn visit_item_fn_mut(&mut self, item: &mut syn::ItemFn) {
+        let hidden = self.binds_param(&item.sig);
+        self.visit_body(hidden, |renamer| {
+            visit_mut::visit_item_fn_mut(renamer, item);
+        });
+    }
+
+    fn visit_impl_item_fn_mut(&mut self, item: &mut syn::ImplItemFn) {
+        let hidden = self.binds_param(&item.sig);
+        self.visit_body(hidden, |renamer| {
+            visit_mut::visit_impl_item_fn_mut(renamer, item);
+        });
+    }
+
+    fn visit_expr_closure_mut(&mut self, closure: &mut syn::ExprClosure) {
+        let hidden = closure.inputs.iter().any(|pat| self.binds(pat));
+        self.visit_body(hidden, |renamer| {
+            visit_mut::visit_expr_closure_mut(renamer, closure);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collisions_reported_and_renamed() {
+        let code = r#"use flat::answer;
+__cg_bundler_file!("src/lib.rs");
+pub fn answer() -> u32 { helper() }
+fn helper() -> u32 { 42 }
+struct Point(u32);
+mod geo { pub fn area() -> u32 { crate::helper() } }
+__cg_bundler_file!();
+fn helper() -> u32 { 1 }
+#[derive(Debug)]
+struct Point { x: u32 }
+#[cfg(test)]
+fn only_in_tests() {}
+#[cfg(not(test))]
+fn only_in_tests() {}
+mod ai {
+    use super::*;
+    pub fn play(helper: u32) -> u32 { helper + super::helper() }
+    pub fn twice() -> u32 { helper() * 2 }
+}
+fn main() {
+    let p = Point { x: helper() };
+    println!("{} {:?} {} {}", answer(), p, helper(), self::helper());
+}
+"#;
+        let mut file = syn::parse_file(code).unwrap();
+        let collisions = resolve(&mut file.items, Some("src/main.rs"), true);
+        let reports: Vec<String> = collisions.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            reports,
+            [
+                "`helper` in crate: src/lib.rs:4, src/main.rs:8 (renamed to `helper_2`)",
+                "`Point` in crate: src/lib.rs:5, src/main.rs:10 (renamed to `Point_2`)",
+            ]
+        );
+        assert!(collisions.iter().all(Collision::is_resolved));
+
+        line_markers::strip(&mut file.items);
+        let bundle = prettyplease::unparse(&file);
+        assert!(bundle.contains("pub fn answer() -> u32 {\n    helper()\n}"));
+        assert!(bundle.contains("crate::helper()"));
+        assert!(bundle.contains("fn helper_2() -> u32 {\n    1\n}"));
+        assert!(bundle.contains("struct Point_2 {"));
+        assert!(bundle.contains("helper + super::helper_2()"));
+        assert!(bundle.contains("helper_2() * 2"));
+        assert!(bundle.contains("let p = Point_2 { x: helper_2() };"));
+        assert!(bundle.contains("answer(), p, helper_2(), self::helper_2()"));
+
+        let mut file = syn::parse_file(code).unwrap();
+        let collisions = resolve(&mut file.items, Some("src/main.rs"), false);
+        assert_eq!(collisions.len(), 2);
+        assert!(!collisions[0].is_resolved());
+    }
+}
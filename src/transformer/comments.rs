@@ -0,0 +1,285 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use super::lexer::{block_comment_len, literal_len};
+use super::verbatim;
+
+/// Attribute standing in for a plain comment while the bundle is a syntax tree
+const COMMENT_ATTRIBUTE: &str = "__cg_bundler_comment";
+
+/// Parse `code`, keeping its plain `//` and `/* */` comments when `keep_comments`
+/// is set
+///
+/// `syn` drops plain comments, so each one is turned into an attribute on the
+/// node that follows it, which the transforms carry along with the node and
+/// `restore` turns back into a comment. A comment where no attribute can go
+/// (before a closing brace, an `else` or inside a `use` group) is dropped. Doc
+/// comments are attributes already and are left alone. Verbatim regions are
+/// replaced by placeholders first (see `verbatim::mark`).
+///
+/// # Errors
+/// Returns the parse error of `code`, or an error if its verbatim regions are
+/// unmatched or do not enclose whole items or statements
+pub fn parse_file(code: &str, keep_comments: bool) -> syn::Result<syn::File> {
+    let marked = verbatim::mark(code)?;
+    parse_checked(code, &marked, keep_comments).map(|(_, file)| file)
+}
+
+/// The text `parse_file` parses `code` from, which `syn::parse_file` turns into
+/// the tree `parse_file` returns
+///
+/// Its verbatim regions are replaced by placeholders and, when `keep_comments` is
+/// set, the comments that can be kept are turned into attributes. Finding the
+/// comments that can be kept parses `code` once more for each that cannot, which
+/// `FileManager::prefetch` does on its worker threads.
+///
+/// # Errors
+/// Returns the errors of `parse_file`
+pub fn prepare(code: &str, keep_comments: bool) -> syn::Result<String> {
+    let marked = verbatim::mark(code)?;
+    let (attached, _) = parse_checked(code, &marked, keep_comments)?;
+    Ok(attached.unwrap_or_else(|| marked.into_owned()))
+}
+
+/// Parse `marked`, `code` with its verbatim regions marked, with the text the
+/// comments were attached to when it is not `marked` itself
+fn parse_checked(
+    code: &str,
+    marked: &str,
+    keep_comments: bool,
+) -> syn::Result<(Option<String>, syn::File)> {
+    match parse_marked(marked, keep_comments) {
+        Err(error) if marked != code && syn::parse_file(code).is_ok() => {
+            Err(verbatim::misplaced(&error))
+        }
+        parsed => parsed,
+    }
+}
+
+fn parse_marked(code: &str, keep_comments: bool) -> syn::Result<(Option<String>, syn::File)> {
+    let unattached = || syn::parse_file(code).map(|file| (None, file));
+    if !keep_comments {
+        return unattached();
+    }
+    let comments = plain_comments(code);
+    let mut attached = vec![true; comments.len()];
+    loop {
+        let (text, starts) = attach(code, &comments, &attached);
+        let error = match syn::parse_file(&text) {
+            Ok(file) => return Ok((Some(text), file)),
+            Err(error) => error,
+        };
+        // Drop the last comment before the error, unless the code itself is invalid
+        let offset = byte_offset(&text, error.span().start());
+        match (0..comments.len())
+            .rev()
+            .find(|&index| attached[index] && starts[index] <= offset)
+        {
+            Some(index) => attached[index] = false,
+            None => return unattached(),
+        }
+        if attached.iter().all(|attached| !attached) {
+            return unattached();
+        }
+    }
+}
+
+/// Turn the comment attributes of rendered code back into comments
+///
+/// A line comment followed by code on the same line, as with attributes on
+/// expressions or in minified output, ends the line, the code continuing on the
+/// next line with the same indentation.
+#[must_use]
+pub fn restore(code: &str) -> String {
+    let mut restored = String::with_capacity(code.len());
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        if let Some((len, comment)) = comment_attribute(rest) {
+            restored.push_str(&comment);
+            let after = &rest[len..];
+            let line_end = after.find('\n').unwrap_or(after.len());
+            if comment.starts_with("//") && !after[..line_end].trim().is_empty() {
+                let line_start = restored.rfind('\n').map_or(0, |index| index + 1);
+                let line = &restored[line_start..];
+                let indent = line[..line.len() - line.trim_start().len()].to_string();
+                restored.push('\n');
+                restored.push_str(&indent);
+                rest = after.trim_start_matches([' ', '\t']);
+            } else {
+                rest = after;
+            }
+            continue;
+        }
+
+        let len = if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            block_comment_len(rest)
+        } else {
+            literal_len(rest).unwrap_or_else(|| c.len_utf8())
+        };
+        restored.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    restored
+}
+
+/// Byte ranges of the plain (non-doc) comments of `code`
+pub(super) fn plain_comments(code: &str) -> Vec<Range<usize>> {
+    let mut comments = Vec::new();
+    let mut offset = 0;
+    while let Some(c) = code[offset..].chars().next() {
+        let rest = &code[offset..];
+        let (len, is_plain) = if rest.starts_with("//") {
+            let is_doc =
+                (rest.starts_with("///") && !rest.starts_with("////")) || rest.starts_with("//!");
+            (rest.find('\n').unwrap_or(rest.len()), !is_doc)
+        } else if rest.starts_with("/*") {
+            let is_doc =
+                (rest.starts_with("/**") && !rest.starts_with("/***") && !rest.starts_with("/**/"))
+                    || rest.starts_with("/*!");
+            (block_comment_len(rest), !is_doc)
+        } else {
+            (literal_len(rest).unwrap_or_else(|| c.len_utf8()), false)
+        };
+        if is_plain {
+            comments.push(offset..offset + len);
+        }
+        offset += len;
+    }
+    comments
+}
+
+/// `code` with the `attached` comments replaced by attributes, and the offset of
+/// each comment in the result
+///
+/// Attributes of block comments spanning several lines are followed by as many
+/// line breaks, so the lines of the code after them keep their numbers.
+fn attach(code: &str, comments: &[Range<usize>], attached: &[bool]) -> (String, Vec<usize>) {
+    let mut text = String::with_capacity(code.len());
+    let mut starts = Vec::with_capacity(comments.len());
+    let mut cursor = 0;
+    for (range, &attached) in comments.iter().zip(attached) {
+        text.push_str(&code[cursor..range.start]);
+        starts.push(text.len());
+        let comment = &code[range.clone()];
+        if attached {
+            let _ = write!(text, "#[{COMMENT_ATTRIBUTE} = {comment:?}]");
+            text.push_str(&"\n".repeat(comment.matches('\n').count()));
+        } else {
+            text.push_str(comment);
+        }
+        cursor = range.end;
+    }
+    text.push_str(&code[cursor..]);
+    (text, starts)
+}
+
+fn byte_offset(text: &str, position: proc_macro2::LineColumn) -> usize {
+    let line_start = text
+        .split_inclusive('\n')
+        .take(position.line.saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>();
+    text[line_start..]
+        .char_indices()
+        .nth(position.column)
+        .map_or(text.len(), |(index, _)| line_start + index)
+}
+
+/// Length and comment of the comment attribute at the start of `rest`, printed
+/// as `#[name = "..."]` with any spacing
+fn comment_attribute(rest: &str) -> Option<(usize, String)> {
+    let after_hash = rest.strip_prefix('#')?;
+    let after_bracket = after_hash.trim_start().strip_prefix('[')?;
+    let after_name = after_bracket.trim_start().strip_prefix(COMMENT_ATTRIBUTE)?;
+    let literal = after_name.trim_start().strip_prefix('=')?.trim_start();
+    let literal_len = literal_len(literal).filter(|_| literal.starts_with('"'))?;
+    let after_literal = literal[literal_len..].trim_start().strip_prefix(']')?;
+    let comment = syn::parse_str::<syn::LitStr>(&literal[..literal_len])
+        .ok()?
+        .value();
+    Some((rest.len() - after_literal.len(), comment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(code: &str) -> String {
+        restore(&prettyplease::unparse(&parse_file(code, true).unwrap()))
+    }
+
+    #[test]
+    fn test_comments_survive_reprinting() {
+        let code = "\
+// Tuning knobs
+const DEPTH: u32 = 3; /* in plies */
+
+/// Entry point
+fn main() {
+    // TODO tune this constant
+    let x = DEPTH; // trailing
+    let s = \"// not a comment\";
+    if x > 2 {
+        println!(\"{s}\");
+        // dropped: nothing follows
+    } // dropped: before else
+    else {
+    }
+}
+";
+        assert_eq!(
+            roundtrip(code),
+            "\
+// Tuning knobs
+const DEPTH: u32 = 3;
+/* in plies */
+/// Entry point
+fn main() {
+    // TODO tune this constant
+    let x = DEPTH;
+    // trailing
+    let s = \"// not a comment\";
+    if x > 2 {
+        println!(\"{s}\");
+    } else {}
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_restore_breaks_lines_after_line_comments() {
+        let minified = "fn f(){#[__cg_bundler_comment=\"// note\"] let x=1;#[__cg_bundler_comment = \"/* b */\"]x;}";
+        assert_eq!(restore(minified), "fn f(){// note\nlet x=1;/* b */x;}");
+        assert_eq!(
+            restore("    #[__cg_bundler_comment = \"// a \\\"quote\\\"\"] call();"),
+            "    // a \"quote\"\n    call();"
+        );
+        assert!(parse_file("fn f( {", true).is_err());
+    }
+
+    #[test]
+    fn test_prepared_text_parses_to_the_same_tree() {
+        use quote::ToTokens;
+
+        let code =
+            "fn main() {\n    let x = 1; // kept\n    x;\n    // dropped: nothing follows\n}\n";
+        for keep_comments in [false, true] {
+            let prepared = prepare(code, keep_comments).unwrap();
+            assert_eq!(
+                syn::parse_file(&prepared)
+                    .unwrap()
+                    .to_token_stream()
+                    .to_string(),
+                parse_file(code, keep_comments)
+                    .unwrap()
+                    .to_token_stream()
+                    .to_string()
+            );
+        }
+        assert!(prepare(code, true).unwrap().contains(COMMENT_ATTRIBUTE));
+        assert!(prepare("fn f( {", true).is_err());
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::punctuated::Punctuated;
+
+use super::line_markers;
+
+/// Name-based symbol reference graph of a bundled file
+///
+/// Every named item contributes an edge set containing all identifiers that appear
+/// in its tokens. Items sharing a name are merged, which keeps the analysis
+/// conservative: a reference to a name keeps every definition of that name.
+#[derive(Debug, Default)]
+pub struct SymbolGraph {
+    defined: BTreeSet<String>,
+    modules: BTreeSet<String>,
+    edges: BTreeMap<String, BTreeSet<String>>,
+    /// Paths of the modules defining each name, empty for the crate root
+    owners: BTreeMap<String, BTreeSet<String>>,
+    impls: Vec<ImplNode>,
+    roots: BTreeSet<String>,
+}
+
+#[derive(Debug)]
+struct ImplNode {
+    module: String,
+    self_ty: Option<String>,
+    trait_name: Option<String>,
+    refs: BTreeSet<String>,
+    methods: Vec<(String, BTreeSet<String>)>,
+}
+
+impl ImplNode {
+    /// Node carrying only the implemented type and trait names
+    fn header(item_impl: &syn::ItemImpl) -> Self {
+        Self {
+            module: String::new(),
+            self_ty: type_name(&item_impl.self_ty),
+            trait_name: item_impl
+                .trait_
+                .as_ref()
+                .and_then(|(_, path, _)| path.segments.last())
+                .map(|segment| segment.ident.to_string()),
+            refs: BTreeSet::new(),
+            methods: Vec::new(),
+        }
+    }
+}
+
+impl SymbolGraph {
+    /// Build the reference graph of a file, rooted at `fn main` and test functions
+    #[must_use]
+    pub fn build(file: &syn::File) -> Self {
+        let mut graph = Self::default();
+        graph.collect_items(&file.items, "");
+        graph
+    }
+
+    /// Whether the file has an entry point the analysis can start from
+    #[must_use]
+    pub fn has_entry_point(&self) -> bool {
+        self.roots.contains("main")
+    }
+
+    /// Names defined in the crate (items, enum variants and trait members)
+    #[must_use]
+    pub const fn defined(&self) -> &BTreeSet<String> {
+        &self.defined
+    }
+
+    /// Module names declared in the crate
+    #[must_use]
+    pub const fn modules(&self) -> &BTreeSet<String> {
+        &self.modules
+    }
+
+    /// Identifiers directly referenced by the definitions named `name`
+    #[must_use]
+    pub fn references(&self, name: &str) -> Option<&BTreeSet<String>> {
+        self.edges.get(name)
+    }
+
+    /// Names each module uses from other modules, by pair of module paths (empty
+    /// for the crate root), e.g. `("", "ai")` if `fn main` calls `ai::decide`
+    ///
+    /// Only the names of `defined` are matched, so a method call counts through
+    /// the type it is called on. A name defined in several modules is used from
+    /// each of them.
+    #[must_use]
+    pub fn module_uses(&self) -> BTreeMap<(String, String), BTreeSet<String>> {
+        let mut uses: BTreeMap<(String, String), BTreeSet<String>> = BTreeMap::new();
+        let mut add = |module: &str, refs: &BTreeSet<String>| {
+            for name in refs {
+                for owner in self.owners.get(name).into_iter().flatten() {
+                    if owner != module {
+                        uses.entry((module.to_string(), owner.clone()))
+                            .or_default()
+                            .insert(name.clone());
+                    }
+                }
+            }
+        };
+        for (name, modules) in &self.owners {
+            for module in modules {
+                add(module, &self.edges[name]);
+            }
+        }
+        for node in &self.impls {
+            add(&node.module, &node.refs);
+            for (_, refs) in &node.methods {
+                add(&node.module, refs);
+            }
+        }
+        uses
+    }
+
+    /// Compute every name reachable from the roots
+    #[must_use]
+    pub fn reachable(&self) -> BTreeSet<String> {
+        let mut reached = BTreeSet::new();
+        let mut queue: Vec<String> = self.roots.iter().cloned().collect();
+
+        loop {
+            while let Some(name) = queue.pop() {
+                if let Some(refs) = self.edges.get(&name) {
+                    queue.extend(refs.iter().filter(|r| !reached.contains(*r)).cloned());
+                }
+                reached.insert(name);
+            }
+
+            for node in self
+                .impls
+                .iter()
+                .filter(|node| self.is_live(node, &reached))
+            {
+                queue.extend(node.refs.iter().cloned());
+                for (method, refs) in &node.methods {
+                    if reached.contains(method) {
+                        queue.extend(refs.iter().cloned());
+                    }
+                }
+            }
+            queue.retain(|name| !reached.contains(name));
+
+            if queue.is_empty() {
+                return reached;
+            }
+        }
+    }
+
+    fn is_live(&self, node: &ImplNode, reached: &BTreeSet<String>) -> bool {
+        let live = |name: &Option<String>| {
+            name.as_ref().map_or(true, |name| {
+                !self.defined.contains(name) || reached.contains(name)
+            })
+        };
+        live(&node.self_ty) && live(&node.trait_name)
+    }
+
+    fn define(&mut self, module: &str, name: String, refs: BTreeSet<String>) {
+        self.defined.insert(name.clone());
+        self.owners
+            .entry(name.clone())
+            .or_default()
+            .insert(module.to_string());
+        self.edges.entry(name).or_default().extend(refs);
+    }
+
+    fn collect_items(&mut self, items: &[syn::Item], module: &str) {
+        for item in items {
+            self.collect_item(item, module);
+        }
+    }
+
+    fn collect_item(&mut self, item: &syn::Item, module: &str) {
+        if let Some(name) = item_name(item) {
+            if is_root(item, &name, module.is_empty()) {
+                self.roots.insert(name.clone());
+            }
+            self.define(module, name, idents(item.to_token_stream()));
+        }
+
+        match item {
+            syn::Item::Enum(item_enum) => {
+                for variant in &item_enum.variants {
+                    self.define(
+                        module,
+                        variant.ident.to_string(),
+                        BTreeSet::from([item_enum.ident.to_string()]),
+                    );
+                }
+            }
+            syn::Item::Trait(item_trait) => {
+                for trait_item in &item_trait.items {
+                    if let Some(member) = trait_item_name(trait_item) {
+                        self.define(
+                            module,
+                            member,
+                            BTreeSet::from([item_trait.ident.to_string()]),
+                        );
+                    }
+                }
+            }
+            syn::Item::Mod(item_mod) => {
+                self.modules.insert(item_mod.ident.to_string());
+                if let Some((_, content)) = &item_mod.content {
+                    let path = if module.is_empty() {
+                        item_mod.ident.to_string()
+                    } else {
+                        format!("{module}::{}", item_mod.ident)
+                    };
+                    self.collect_items(content, &path);
+                }
+            }
+            syn::Item::Impl(item_impl) => self.collect_impl(item_impl, module),
+            syn::Item::Use(item_use) => self.collect_use_renames(&item_use.tree),
+            syn::Item::Macro(item_macro) if item_macro.ident.is_none() => {
+                self.roots.extend(idents(item.to_token_stream()));
+            }
+            syn::Item::ForeignMod(_) | syn::Item::Verbatim(_) => {
+                self.roots.extend(idents(item.to_token_stream()));
+            }
+            _ => {}
+        }
+    }
+
+    fn collect_impl(&mut self, item_impl: &syn::ItemImpl, module: &str) {
+        let mut refs = idents(item_impl.generics.to_token_stream());
+        refs.extend(idents(item_impl.self_ty.to_token_stream()));
+        if let Some((_, path, _)) = &item_impl.trait_ {
+            refs.extend(idents(path.to_token_stream()));
+        }
+
+        let mut methods = Vec::new();
+        for impl_item in &item_impl.items {
+            match impl_item {
+                syn::ImplItem::Fn(method) if item_impl.trait_.is_none() => {
+                    methods.push((
+                        method.sig.ident.to_string(),
+                        idents(method.to_token_stream()),
+                    ));
+                }
+                _ => refs.extend(idents(impl_item.to_token_stream())),
+            }
+        }
+
+        self.impls.push(ImplNode {
+            module: module.to_string(),
+            refs,
+            methods,
+            ..ImplNode::header(item_impl)
+        });
+    }
+
+    fn collect_use_renames(&mut self, tree: &syn::UseTree) {
+        match tree {
+            syn::UseTree::Path(path) => self.collect_use_renames(&path.tree),
+            syn::UseTree::Rename(rename) => {
+                self.edges
+                    .entry(rename.rename.to_string())
+                    .or_default()
+                    .insert(rename.ident.to_string());
+            }
+            syn::UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.collect_use_renames(tree);
+                }
+            }
+            syn::UseTree::Name(_) | syn::UseTree::Glob(_) => {}
+        }
+    }
+}
+
+/// Remove functions, types, impls and modules that cannot be reached from `fn main`
+///
+/// Returns the number of removed items. Files without a top-level `fn main` are
+/// left untouched.
+pub fn strip_unused(file: &mut syn::File) -> usize {
+    let graph = SymbolGraph::build(file);
+    if !graph.has_entry_point() {
+        return 0;
+    }
+
+    let reached = graph.reachable();
+    let mut pruner = Pruner {
+        graph: &graph,
+        reached: &reached,
+        kept_modules: BTreeSet::new(),
+        removed_modules: BTreeSet::new(),
+        removed: 0,
+    };
+    pruner.prune_items(&mut file.items, true);
+
+    let mut unresolved: BTreeSet<String> = graph
+        .defined
+        .iter()
+        .filter(|name| !reached.contains(*name))
+        .cloned()
+        .collect();
+    unresolved.extend(
+        pruner
+            .removed_modules
+            .difference(&pruner.kept_modules)
+            .cloned(),
+    );
+    prune_imports(&mut file.items, &unresolved);
+
+    pruner.removed
+}
+
+struct Pruner<'g> {
+    graph: &'g SymbolGraph,
+    reached: &'g BTreeSet<String>,
+    kept_modules: BTreeSet<String>,
+    removed_modules: BTreeSet<String>,
+    removed: usize,
+}
+
+impl Pruner<'_> {
+    fn prune_items(&mut self, items: &mut Vec<syn::Item>, top_level: bool) {
+        let before = items.len();
+        items.retain_mut(|item| self.keep(item, top_level));
+        self.removed += before - items.len();
+    }
+
+    fn keep(&mut self, item: &mut syn::Item, top_level: bool) -> bool {
+        match item {
+            syn::Item::Mod(item_mod) => {
+                let name = item_mod.ident.to_string();
+                let Some((_, content)) = &mut item_mod.content else {
+                    return true;
+                };
+                self.prune_items(content, false);
+                let keep = content.iter().any(|item| !line_markers::is_marker(item))
+                    || self.reached.contains(&name);
+                if keep {
+                    self.kept_modules.insert(name);
+                } else {
+                    self.removed_modules.insert(name);
+                }
+                keep
+            }
+            syn::Item::Impl(item_impl) => {
+                if !self
+                    .graph
+                    .is_live(&ImplNode::header(item_impl), self.reached)
+                {
+                    return false;
+                }
+                if item_impl.trait_.is_none() {
+                    let before = item_impl.items.len();
+                    item_impl.items.retain(|impl_item| match impl_item {
+                        syn::ImplItem::Fn(method) => {
+                            self.reached.contains(&method.sig.ident.to_string())
+                        }
+                        _ => true,
+                    });
+                    self.removed += before - item_impl.items.len();
+                    return !item_impl.items.is_empty();
+                }
+                true
+            }
+            _ => item_name(item).map_or(true, |name| {
+                self.reached.contains(&name) || is_root(item, &name, top_level)
+            }),
+        }
+    }
+}
+
+/// Drop import leaves that refer to removed definitions
+fn prune_imports(items: &mut Vec<syn::Item>, removed: &BTreeSet<String>) {
+    items.retain_mut(|item| match item {
+        syn::Item::Use(item_use) => prune_use_tree(&mut item_use.tree, removed),
+        syn::Item::Mod(item_mod) => {
+            if let Some((_, content)) = &mut item_mod.content {
+                prune_imports(content, removed);
+            }
+            true
+        }
+        _ => true,
+    });
+}
+
+fn prune_use_tree(tree: &mut syn::UseTree, removed: &BTreeSet<String>) -> bool {
+    match tree {
+        syn::UseTree::Path(path) => {
+            !removed.contains(&path.ident.to_string()) && prune_use_tree(&mut path.tree, removed)
+        }
+        syn::UseTree::Name(name) => !removed.contains(&name.ident.to_string()),
+        syn::UseTree::Rename(rename) => !removed.contains(&rename.ident.to_string()),
+        syn::UseTree::Glob(_) => true,
+        syn::UseTree::Group(group) => {
+            let items = std::mem::take(&mut group.items);
+            group.items = items
+                .into_iter()
+                .filter_map(|mut tree| prune_use_tree(&mut tree, removed).then_some(tree))
+                .collect::<Punctuated<_, syn::Token![,]>>();
+            !group.items.is_empty()
+        }
+    }
+}
+
+/// Name of a prunable item
+fn item_name(item: &syn::Item) -> Option<String> {
+    let ident = match item {
+        syn::Item::Fn(item) => &item.sig.ident,
+        syn::Item::Struct(item) => &item.ident,
+        syn::Item::Enum(item) => &item.ident,
+        syn::Item::Union(item) => &item.ident,
+        syn::Item::Trait(item) => &item.ident,
+        syn::Item::TraitAlias(item) => &item.ident,
+        syn::Item::Type(item) => &item.ident,
+        syn::Item::Const(item) => &item.ident,
+        syn::Item::Static(item) => &item.ident,
+        syn::Item::Macro(item) => item.ident.as_ref()?,
+        _ => return None,
+    };
+    Some(ident.to_string())
+}
+
+fn trait_item_name(item: &syn::TraitItem) -> Option<String> {
+    match item {
+        syn::TraitItem::Fn(item) => Some(item.sig.ident.to_string()),
+        syn::TraitItem::Const(item) => Some(item.ident.to_string()),
+        syn::TraitItem::Type(item) => Some(item.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// Items that must be kept regardless of references
+fn is_root(item: &syn::Item, name: &str, top_level: bool) -> bool {
+    let attrs = match item {
+        syn::Item::Fn(item) => &item.attrs,
+        syn::Item::Static(item) => &item.attrs,
+        _ => return false,
+    };
+    (top_level && name == "main")
+        || attrs.iter().any(|attr| {
+            ["test", "no_mangle", "export_name", "used"]
+                .iter()
+                .any(|root| attr.path().is_ident(root))
+        })
+}
+
+/// Last path segment of the implemented type (`Foo` for `impl<T> a::Foo<T>`)
+fn type_name(ty: &syn::Type) -> Option<String> {
+    match ty {
+        syn::Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        syn::Type::Reference(reference) => type_name(&reference.elem),
+        syn::Type::Paren(paren) => type_name(&paren.elem),
+        syn::Type::Group(group) => type_name(&group.elem),
+        _ => None,
+    }
+}
+
+/// All identifiers in a token stream, including names captured by format strings
+fn idents(tokens: TokenStream) -> BTreeSet<String> {
+    let mut out = BTreeSet::new();
+    collect_idents(tokens, &mut out);
+    out
+}
+
+fn collect_idents(tokens: TokenStream, out: &mut BTreeSet<String>) {
+    for tree in tokens {
+        match tree {
+            TokenTree::Group(group) => collect_idents(group.stream(), out),
+            TokenTree::Ident(ident) => {
+                out.insert(ident.to_string());
+            }
+            TokenTree::Literal(literal) => collect_format_idents(&literal.to_string(), out),
+            TokenTree::Punct(_) => {}
+        }
+    }
+}
+
+/// Identifiers used as inline format arguments (`"{CONST}"`, `"{value:?}"`)
+fn collect_format_idents(literal: &str, out: &mut BTreeSet<String>) {
+    if !literal.starts_with('"') {
+        return;
+    }
+    let mut rest = literal;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        if rest.starts_with('{') {
+            rest = &rest[1..];
+            continue;
+        }
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        if name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            out.insert(name.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(source: &str) -> String {
+        let mut file = syn::parse_file(source).unwrap();
+        strip_unused(&mut file);
+        prettyplease::unparse(&file)
+    }
+
+    #[test]
+    fn test_unreferenced_items_are_removed() {
+        let output = strip(
+            r"
+            mod ai {
+                pub struct Used;
+                pub struct Unused;
+                impl Used { pub fn go(&self) {} pub fn never(&self) {} }
+                impl Unused { pub fn go(&self) {} }
+            }
+            mod debug { pub fn dump() {} }
+            use ai::{Unused, Used};
+            use debug::dump;
+            fn main() { Used.go(); }
+            ",
+        );
+        assert!(output.contains("struct Used"));
+        assert!(!output.contains("Unused"));
+        assert!(!output.contains("never"));
+        assert!(!output.contains("mod debug"));
+        assert!(!output.contains("dump"));
+        syn::parse_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_traits_kept_through_method_calls_and_impls() {
+        let output = strip(
+            r"
+            trait Action { fn run(&self); }
+            struct Move;
+            impl Action for Move { fn run(&self) {} }
+            fn main() { Move.run(); }
+            ",
+        );
+        assert!(output.contains("trait Action"));
+        assert!(output.contains("impl Action for Move"));
+    }
+
+    #[test]
+    fn test_enum_variants_and_format_args_keep_definitions() {
+        let output = strip(
+            r#"
+            enum Dir { North, South }
+            use Dir::*;
+            const LIMIT: u32 = 3;
+            fn main() { let _d = North; println!("{LIMIT}"); }
+            "#,
+        );
+        assert!(output.contains("enum Dir"));
+        assert!(output.contains("const LIMIT"));
+    }
+
+    #[test]
+    fn test_module_uses() {
+        let file = syn::parse_file(
+            r"
+            mod geo {
+                pub struct Point;
+                impl Point { pub fn norm(&self) -> f64 { super::math::sqrt(1.0) } }
+            }
+            mod math { pub fn sqrt(x: f64) -> f64 { x } }
+            fn main() { geo::Point.norm(); }
+            ",
+        )
+        .unwrap();
+        let uses = SymbolGraph::build(&file).module_uses();
+        let pairs: Vec<(&str, &str, Vec<&String>)> = uses
+            .iter()
+            .map(|((from, to), names)| (from.as_str(), to.as_str(), names.iter().collect()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("", "geo", vec![&"Point".to_string()]),
+                ("geo", "math", vec![&"sqrt".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_without_main_is_untouched() {
+        let output = strip("pub fn unused() {}");
+        assert!(output.contains("fn unused"));
+    }
+}
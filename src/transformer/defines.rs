@@ -0,0 +1,223 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use syn::visit_mut::{self, VisitMut};
+
+use crate::error::{BundlerError, Result};
+use crate::warnings::BundleWarning;
+
+/// Give the consts named in `defines` the value of their entry, a Rust expression
+/// such as `false` or `3`, and remove the code that boolean values make unreachable
+///
+/// Every `const NAME` of the bundle, in any module or function body, is rewritten.
+/// Then `if` conditions built from the boolean ones with `!`, `&&` and `||` are
+/// evaluated: a statically true `if` is replaced by its block, a false one by its
+/// `else` branch, or removed. Conditions are matched by the last segment of the
+/// paths they use, so `DEBUG` and `config::DEBUG` are both the define `DEBUG`.
+/// The defines matching no const are returned as warnings.
+///
+/// # Errors
+/// Returns an error if a value is not a Rust expression
+pub(super) fn apply(
+    file: &mut syn::File,
+    defines: &BTreeMap<String, String>,
+) -> Result<Vec<BundleWarning>> {
+    if defines.is_empty() {
+        return Ok(Vec::new());
+    }
+    let values = defines
+        .iter()
+        .map(|(name, value)| {
+            let expr = syn::parse_str::<syn::Expr>(value).map_err(|e| BundlerError::Config {
+                message: format!("Value '{value}' of define {name} is not an expression: {e}"),
+                path: None,
+            })?;
+            Ok((name.clone(), expr))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+    let mut folder = ConstFolder {
+        booleans: values
+            .iter()
+            .filter_map(|(name, expr)| Some((name.clone(), literal_bool(expr)?)))
+            .collect(),
+        values,
+        defined: BTreeSet::new(),
+    };
+    folder.visit_file_mut(file);
+
+    Ok(defines
+        .keys()
+        .filter(|name| !folder.defined.contains(*name))
+        .map(|name| BundleWarning::UnknownDefine { name: name.clone() })
+        .collect())
+}
+
+struct ConstFolder {
+    values: BTreeMap<String, syn::Expr>,
+    /// Defines whose value is `true` or `false`
+    booleans: BTreeMap<String, bool>,
+    /// Defines a const was found for
+    defined: BTreeSet<String>,
+}
+
+impl ConstFolder {
+    /// Value of `expr` when it only depends on boolean defines
+    fn evaluate(&self, expr: &syn::Expr) -> Option<bool> {
+        match expr {
+            syn::Expr::Lit(_) => literal_bool(expr),
+            syn::Expr::Path(path) if path.qself.is_none() => {
+                let name = path.path.segments.last()?.ident.to_string();
+                self.booleans.get(&name).copied()
+            }
+            syn::Expr::Paren(paren) => self.evaluate(&paren.expr),
+            syn::Expr::Group(group) => self.evaluate(&group.expr),
+            syn::Expr::Unary(syn::ExprUnary {
+                op: syn::UnOp::Not(_),
+                expr,
+                ..
+            }) => self.evaluate(expr).map(|value| !value),
+            // The right operand is only known to be skipped when the left one decides
+            syn::Expr::Binary(binary) => match (binary.op, self.evaluate(&binary.left)) {
+                (syn::BinOp::And(_), Some(false)) => Some(false),
+                (syn::BinOp::Or(_), Some(true)) => Some(true),
+                (syn::BinOp::And(_) | syn::BinOp::Or(_), Some(_)) => self.evaluate(&binary.right),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// What `expr_if` becomes when its condition is statically known: the block
+    /// or `else` branch taken, or `{}`
+    fn fold_if(&self, expr_if: &syn::ExprIf) -> Option<syn::Expr> {
+        let taken = self.evaluate(&expr_if.cond)?;
+        Some(match (taken, &expr_if.else_branch) {
+            (true, _) => syn::Expr::Block(syn::ExprBlock {
+                attrs: expr_if.attrs.clone(),
+                label: None,
+                block: expr_if.then_branch.clone(),
+            }),
+            (false, Some((_, else_branch))) => (**else_branch).clone(),
+            (false, None) => syn::parse_quote!({}),
+        })
+    }
+}
+
+impl VisitMut for ConstFolder {
+    fn visit_item_const_mut(&mut self, item: &mut syn::ItemConst) {
+        let name = item.ident.to_string();
+        if let Some(value) = self.values.get(&name) {
+            *item.expr = value.clone();
+            self.defined.insert(name);
+        }
+        visit_mut::visit_item_const_mut(self, item);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        block.stmts.retain_mut(|stmt| {
+            while let syn::Stmt::Expr(syn::Expr::If(expr_if), semi) = stmt {
+                match self.fold_if(expr_if) {
+                    // A block without statements has type `()`, as had the `if`, so it
+                    // can go wherever it is
+                    Some(syn::Expr::Block(block)) if block.block.stmts.is_empty() => {
+                        return false;
+                    }
+                    Some(expr) => *stmt = syn::Stmt::Expr(expr, *semi),
+                    None => break,
+                }
+            }
+            true
+        });
+        visit_mut::visit_block_mut(self, block);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+        if let syn::Expr::If(expr_if) = expr {
+            if let Some(folded) = self.fold_if(expr_if) {
+                *expr = folded;
+            }
+        }
+        visit_mut::visit_expr_mut(self, expr);
+    }
+}
+
+const fn literal_bool(expr: &syn::Expr) -> Option<bool> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Bool(value),
+            ..
+        }) => Some(value.value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+
+    fn fold(file: &mut syn::File, defines: &[(&str, &str)]) {
+        let defines = defines
+            .iter()
+            .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+            .collect();
+        apply(file, &defines).unwrap();
+    }
+
+    #[test]
+    fn test_debug_branches_removed() {
+        let mut file: syn::File = syn::parse_quote! {
+            const DEBUG: bool = true;
+            const DEPTH: u32 = 3;
+            mod log {
+                pub fn trace(turn: u32) {
+                    if crate::DEBUG && turn > 0 {
+                        eprintln!("turn {turn}");
+                    }
+                }
+            }
+            fn main() {
+                if DEBUG {
+                    eprintln!("start");
+                }
+                let depth = if !DEBUG { DEPTH } else { 1 };
+                if DEBUG || depth > 2 {
+                    eprintln!("deep");
+                } else if depth > 1 {
+                    log::trace(depth);
+                }
+            }
+        };
+        fold(&mut file, &[("DEBUG", "false"), ("DEPTH", "5")]);
+        assert_eq!(
+            file.to_token_stream().to_string(),
+            "const DEBUG : bool = false ; const DEPTH : u32 = 5 ; mod log { pub fn trace (turn : u32) { } } fn main () { let depth = { DEPTH } ; if DEBUG || depth > 2 { eprintln ! (\"deep\") ; } else if depth > 1 { log :: trace (depth) ; } }"
+        );
+    }
+
+    #[test]
+    fn test_invalid_define() {
+        let mut file: syn::File = syn::parse_quote!(
+            const DEBUG: bool = true;
+        );
+        let defines = BTreeMap::from([("DEBUG".to_string(), "fal se".to_string())]);
+        assert!(apply(&mut file, &defines).is_err());
+    }
+
+    #[test]
+    fn test_unknown_define_reported() {
+        let mut file: syn::File = syn::parse_quote!(
+            const DEBUG: bool = true;
+        );
+        let defines = BTreeMap::from([
+            ("DEBUG".to_string(), "false".to_string()),
+            ("VERBOSE".to_string(), "true".to_string()),
+        ]);
+        assert_eq!(
+            apply(&mut file, &defines).unwrap(),
+            [BundleWarning::UnknownDefine {
+                name: "VERBOSE".to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use proc_macro2::Span;
+
+use super::line_markers;
+
+/// Attribute temporarily replacing `#[cfg(test)]` on demo functions so that test
+/// filtering keeps them until they are extracted
+const DEMO_MARKER: &str = "__cg_bundler_demo";
+
+/// A runnable example generated from a `#[cfg(test)]` demo function
+#[derive(Debug, Clone)]
+pub struct DemoExample {
+    /// File stem of the example, e.g. `geometry_demo`
+    pub name: String,
+    /// The bundle with the demo restored and `fn main` calling it
+    pub file: syn::File,
+}
+
+fn is_cfg_test(attr: &syn::Attribute) -> bool {
+    attr.path().is_ident("cfg")
+        && attr
+            .parse_args::<syn::Ident>()
+            .is_ok_and(|arg| arg == "test")
+}
+
+/// Whether a function is a `main`-like demo: gated by exactly `#[cfg(test)]`, not a
+/// `#[test]`, without parameters or generics, and named `demo` or `demo_*`
+fn is_demo(item_fn: &syn::ItemFn) -> bool {
+    let name = item_fn.sig.ident.to_string();
+    (name == "demo" || name.starts_with("demo_"))
+        && item_fn.sig.inputs.is_empty()
+        && item_fn.sig.generics.params.is_empty()
+        && item_fn.attrs.iter().any(is_cfg_test)
+        && !item_fn
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("test"))
+}
+
+/// Replace the `#[cfg(test)]` of demo functions among `items` with a marker
+pub(crate) fn mark(items: &mut [syn::Item]) {
+    let marker = syn::Ident::new(DEMO_MARKER, Span::call_site());
+    for item in items {
+        if let syn::Item::Fn(item_fn) = item {
+            if is_demo(item_fn) {
+                for attr in &mut item_fn.attrs {
+                    if is_cfg_test(attr) {
+                        *attr = syn::parse_quote!(#[#marker]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Remove the marked demo functions from the bundle and build one example per demo
+///
+/// Each example is the bundle (before dead-code elimination) with the demo put
+/// back in its module and `fn main` replaced by a call to it.
+pub(crate) fn extract(file: &mut syn::File) -> Vec<DemoExample> {
+    let mut demos = Vec::new();
+    take_demos(&mut file.items, &mut Vec::new(), &mut demos);
+    if demos.is_empty() {
+        return Vec::new();
+    }
+
+    let mut base = file.clone();
+    strip_markers(&mut base.items);
+
+    demos
+        .into_iter()
+        .map(|(module_path, mut demo)| {
+            let mut name = module_path.join("_");
+            if !name.is_empty() {
+                name.push('_');
+            }
+            name.push_str(&demo.sig.ident.to_string());
+
+            let mut example = base.clone();
+            let call = call_path(&module_path, &demo.sig.ident);
+            if matches!(demo.vis, syn::Visibility::Inherited) {
+                demo.vis = syn::parse_quote!(pub(crate));
+            }
+            if let Some(items) = module_items(&mut example.items, &module_path) {
+                items.push(syn::Item::Fn(demo));
+            }
+            replace_main(&mut example.items, &call);
+
+            DemoExample {
+                name,
+                file: example,
+            }
+        })
+        .collect()
+}
+
+fn take_demos(
+    items: &mut Vec<syn::Item>,
+    module_path: &mut Vec<String>,
+    demos: &mut Vec<(Vec<String>, syn::ItemFn)>,
+) {
+    let mut kept = Vec::with_capacity(items.len());
+    for item in items.drain(..) {
+        match item {
+            syn::Item::Fn(mut item_fn)
+                if item_fn
+                    .attrs
+                    .iter()
+                    .any(|attr| attr.path().is_ident(DEMO_MARKER)) =>
+            {
+                item_fn
+                    .attrs
+                    .retain(|attr| !attr.path().is_ident(DEMO_MARKER));
+                demos.push((module_path.clone(), item_fn));
+            }
+            mut item => {
+                if let syn::Item::Mod(item_mod) = &mut item {
+                    if let Some((_, content)) = &mut item_mod.content {
+                        module_path.push(item_mod.ident.to_string());
+                        take_demos(content, module_path, demos);
+                        module_path.pop();
+                    }
+                }
+                kept.push(item);
+            }
+        }
+    }
+    *items = kept;
+}
+
+fn strip_markers(items: &mut Vec<syn::Item>) {
+    items.retain(|item| !line_markers::is_marker(item));
+    for item in items {
+        if let syn::Item::Mod(item_mod) = item {
+            if let Some((_, content)) = &mut item_mod.content {
+                strip_markers(content);
+            }
+        }
+    }
+}
+
+fn module_items<'a>(
+    items: &'a mut Vec<syn::Item>,
+    module_path: &[String],
+) -> Option<&'a mut Vec<syn::Item>> {
+    let Some((first, rest)) = module_path.split_first() else {
+        return Some(items);
+    };
+    items.iter_mut().find_map(|item| match item {
+        syn::Item::Mod(item_mod) if item_mod.ident == first => item_mod
+            .content
+            .as_mut()
+            .and_then(|(_, content)| module_items(content, rest)),
+        _ => None,
+    })
+}
+
+fn call_path(module_path: &[String], name: &syn::Ident) -> syn::Path {
+    let mut path: syn::Path = syn::parse_quote!(crate);
+    for module in module_path {
+        path.segments
+            .push(syn::Ident::new(module, Span::call_site()).into());
+    }
+    path.segments.push(name.clone().into());
+    path
+}
+
+/// Replace the body of `fn main` with a call to the demo, adding `main` if missing
+fn replace_main(items: &mut Vec<syn::Item>, call: &syn::Path) {
+    let body: syn::Block = syn::parse_quote!({
+        #call();
+    });
+    let main = items.iter_mut().find_map(|item| match item {
+        syn::Item::Fn(item_fn) if item_fn.sig.ident == "main" => Some(item_fn),
+        _ => None,
+    });
+    match main {
+        Some(main) => {
+            main.sig.output = syn::ReturnType::Default;
+            *main.block = body;
+        }
+        None => items.push(syn::parse_quote!(
+            fn main() #body
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_demo() {
+        let demo: syn::ItemFn = syn::parse_quote!(
+            #[cfg(test)]
+            fn demo_paths() {}
+        );
+        let with_args: syn::ItemFn = syn::parse_quote!(
+            #[cfg(test)]
+            fn demo(n: u32) {}
+        );
+        let test_fn: syn::ItemFn = syn::parse_quote!(
+            #[cfg(test)]
+            #[test]
+            fn demo() {}
+        );
+        let not_test: syn::ItemFn = syn::parse_quote!(
+            #[cfg(not(test))]
+            fn demo() {}
+        );
+        assert!(is_demo(&demo));
+        assert!(!is_demo(&with_args));
+        assert!(!is_demo(&test_fn));
+        assert!(!is_demo(&not_test));
+    }
+
+    #[test]
+    fn test_extract_builds_example() {
+        let mut file: syn::File = syn::parse_quote! {
+            mod geo {
+                pub fn area() -> u32 { 4 }
+                #[cfg(test)]
+                fn demo() { println!("{}", area()); }
+            }
+            fn main() { println!("{}", geo::area()); }
+        };
+        let syn::Item::Mod(geo) = &mut file.items[0] else {
+            unreachable!()
+        };
+        mark(&mut geo.content.as_mut().unwrap().1);
+
+        let examples = extract(&mut file);
+        assert_eq!(examples.len(), 1);
+        assert_eq!(examples[0].name, "geo_demo");
+
+        let bundle = prettyplease::unparse(&file);
+        assert!(!bundle.contains("fn demo"));
+
+        let example = prettyplease::unparse(&examples[0].file);
+        assert!(example.contains("pub(crate) fn demo()"));
+        assert!(!example.contains("cfg(test)"));
+        assert!(example.contains("crate::geo::demo();"));
+        assert!(!example.contains("geo::area()) }"));
+    }
+}
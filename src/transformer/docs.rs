@@ -0,0 +1,85 @@
+use serde::Deserialize;
+
+use super::lexer::{block_comment_len, literal_len};
+
+/// What happens to documentation comments (`///`, `//!`, `#[doc = "..."]`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DocsMode {
+    /// Remove them from the bundle
+    #[default]
+    Remove,
+    /// Emit them unchanged
+    Keep,
+    /// Emit them as plain `//` and `/* */` comments, which the compiler does not
+    /// turn into `#[doc]` attributes; removed as with `Remove` when minifying, where
+    /// every comment is dropped
+    ConvertToPlain,
+}
+
+/// Turn the doc comments of rendered code into plain comments: `///` and `//!`
+/// become `//`, `/**` and `/*!` become `/*`
+///
+/// String and character literals are skipped, so text that only looks like a doc
+/// comment is left alone.
+#[must_use]
+pub fn to_plain_comments(code: &str) -> String {
+    let mut plain = String::with_capacity(code.len());
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let (len, is_doc) = if rest.starts_with("//") {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            let is_doc =
+                (rest.starts_with("///") && !rest.starts_with("////")) || rest.starts_with("//!");
+            (len, is_doc)
+        } else if rest.starts_with("/*") {
+            let is_doc =
+                (rest.starts_with("/**") && !rest.starts_with("/***") && !rest.starts_with("/**/"))
+                    || rest.starts_with("/*!");
+            (block_comment_len(rest), is_doc)
+        } else {
+            (literal_len(rest).unwrap_or_else(|| c.len_utf8()), false)
+        };
+
+        if is_doc {
+            plain.push_str(&rest[..2]);
+            plain.push_str(&rest[3..len]);
+        } else {
+            plain.push_str(&rest[..len]);
+        }
+        rest = &rest[len..];
+    }
+    plain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doc_comments_become_plain_comments() {
+        let code = r##"//! Crate docs
+/// Adds one.
+/** Block docs */
+fn add_one<'a>(x: &'a i32) -> i32 {
+    //// not a doc comment
+    /**/ /*** neither */
+    let _ = ("/// in a string", r#"//! raw"#, b"/**", '/', b'"');
+    x + 1 // trailing
+}
+"##;
+        assert_eq!(
+            to_plain_comments(code),
+            r##"// Crate docs
+// Adds one.
+/* Block docs */
+fn add_one<'a>(x: &'a i32) -> i32 {
+    //// not a doc comment
+    /**/ /*** neither */
+    let _ = ("/// in a string", r#"//! raw"#, b"/**", '/', b'"');
+    x + 1 // trailing
+}
+"##
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+
+use quote::ToTokens;
+
+/// Make the `extern crate` declarations of external crates valid in a single file
+///
+/// The binary and the inlined library may both declare the same crate, which
+/// would define its name twice at the bundle root: duplicates are merged into the
+/// first declaration, keeping the `#[macro_use]` of any of them. A `#[macro_use]
+/// extern crate` of an expanded module file must be at the crate root, so a copy
+/// importing the macros is declared there, and the module keeps a plain
+/// declaration for its paths. `extern crate self` is left as it is.
+pub(super) fn merge(file: &mut syn::File) {
+    let mut hoisted = Vec::new();
+    for item in &mut file.items {
+        if let syn::Item::Mod(syn::ItemMod {
+            content: Some((_, items)),
+            ..
+        }) = item
+        {
+            take_macro_use(items, &mut hoisted);
+        }
+    }
+
+    // Index in `file.items` of the first declaration of each crate
+    let mut first: BTreeMap<String, usize> = BTreeMap::new();
+    let mut items = Vec::with_capacity(file.items.len() + hoisted.len());
+    for item in hoisted
+        .into_iter()
+        .map(syn::Item::ExternCrate)
+        .chain(file.items.drain(..))
+    {
+        let syn::Item::ExternCrate(extern_crate) = item else {
+            items.push(item);
+            continue;
+        };
+        if extern_crate.ident == "self" {
+            items.push(syn::Item::ExternCrate(extern_crate));
+            continue;
+        }
+        if let Some(&index) = first.get(&key(&extern_crate)) {
+            if let syn::Item::ExternCrate(kept) = &mut items[index] {
+                add_macro_use(&mut kept.attrs, &extern_crate.attrs);
+            }
+        } else {
+            first.insert(key(&extern_crate), items.len());
+            items.push(syn::Item::ExternCrate(extern_crate));
+        }
+    }
+    file.items = items;
+}
+
+/// Copies of the `#[macro_use] extern crate` declarations of `items` and of their
+/// modules, whose own declarations lose the attribute
+fn take_macro_use(items: &mut [syn::Item], hoisted: &mut Vec<syn::ItemExternCrate>) {
+    for item in items {
+        match item {
+            syn::Item::ExternCrate(extern_crate)
+                if extern_crate.ident != "self" && extern_crate.attrs.iter().any(is_macro_use) =>
+            {
+                let mut copy = extern_crate.clone();
+                copy.vis = syn::Visibility::Inherited;
+                hoisted.push(copy);
+                extern_crate.attrs.retain(|attr| !is_macro_use(attr));
+            }
+            syn::Item::Mod(syn::ItemMod {
+                content: Some((_, items)),
+                ..
+            }) => take_macro_use(items, hoisted),
+            _ => {}
+        }
+    }
+}
+
+/// The crate, name and attributes other than `#[macro_use]` of a declaration:
+/// declarations with the same key are the same
+fn key(extern_crate: &syn::ItemExternCrate) -> String {
+    let mut key = extern_crate.ident.to_string();
+    if let Some((_, rename)) = &extern_crate.rename {
+        key = format!("{key} as {rename}");
+    }
+    for attr in extern_crate.attrs.iter().filter(|attr| !is_macro_use(attr)) {
+        key.push(' ');
+        key.push_str(&attr.to_token_stream().to_string());
+    }
+    key
+}
+
+/// Add the `#[macro_use]` attributes of `other` to `attrs`; a bare `#[macro_use]`
+/// imports every macro and replaces those listing some
+fn add_macro_use(attrs: &mut Vec<syn::Attribute>, other: &[syn::Attribute]) {
+    let is_bare =
+        |attr: &syn::Attribute| is_macro_use(attr) && matches!(attr.meta, syn::Meta::Path(_));
+    if attrs.iter().any(is_bare) {
+        return;
+    }
+    if let Some(bare) = other.iter().find(|attr| is_bare(attr)) {
+        attrs.retain(|attr| !is_macro_use(attr));
+        attrs.push(bare.clone());
+        return;
+    }
+    for attr in other.iter().filter(|attr| is_macro_use(attr)) {
+        let tokens = attr.to_token_stream().to_string();
+        if !attrs
+            .iter()
+            .any(|kept| kept.to_token_stream().to_string() == tokens)
+        {
+            attrs.push(attr.clone());
+        }
+    }
+}
+
+fn is_macro_use(attr: &syn::Attribute) -> bool {
+    attr.path().is_ident("macro_use")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(source: &str) -> String {
+        let mut file = syn::parse_file(source).unwrap();
+        merge(&mut file);
+        file.into_token_stream().to_string()
+    }
+
+    fn tokens(source: &str) -> String {
+        syn::parse_file(source)
+            .unwrap()
+            .into_token_stream()
+            .to_string()
+    }
+
+    #[test]
+    fn test_duplicates_merged_at_root() {
+        assert_eq!(
+            merged(
+                "extern crate rand; #[macro_use] extern crate lazy_static; \
+                 fn f() {} #[macro_use] extern crate rand; extern crate lazy_static; \
+                 extern crate rand as r; extern crate self as me;"
+            ),
+            tokens(
+                "#[macro_use] extern crate rand; #[macro_use] extern crate lazy_static; \
+                 fn f() {} extern crate rand as r; extern crate self as me;"
+            )
+        );
+    }
+
+    #[test]
+    fn test_macro_use_hoisted_from_modules() {
+        assert_eq!(
+            merged(
+                "mod a { mod b { #[macro_use(lazy_static)] pub extern crate lazy_static; } } \
+                 fn main() {}"
+            ),
+            tokens(
+                "#[macro_use(lazy_static)] extern crate lazy_static; \
+                 mod a { mod b { pub extern crate lazy_static; } } fn main() {}"
+            )
+        );
+        assert_eq!(
+            merged("#[macro_use] extern crate itertools; mod lib { #[macro_use] extern crate itertools; }"),
+            tokens("#[macro_use] extern crate itertools; mod lib { extern crate itertools; }")
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+
+use quote::ToTokens;
+
+use super::attributes::item_attrs_mut;
+use crate::error::{BundlerError, Result};
+use crate::integrity::describe;
+use crate::warnings::BundleWarning;
+
+/// Resolve the `cfg` and `cfg_attr` conditions of `items` that only depend on Cargo
+/// features, as if exactly `features` were enabled
+///
+/// Items behind disabled features are removed, and returned as warnings.
+/// Conditions that also depend on anything else, such as `test` or `unix`, are
+/// left to the compiler.
+pub(super) fn resolve(
+    items: &mut Vec<syn::Item>,
+    features: &BTreeSet<String>,
+) -> Vec<BundleWarning> {
+    let mut resolver = FeatureResolver {
+        features,
+        module_path: Vec::new(),
+        stripped: Vec::new(),
+    };
+    items.retain_mut(|item| resolver.resolve_item(item));
+    for item in items {
+        resolver.visit_item_mut(item);
+    }
+    resolver.stripped
+}
+
+/// Enable `features` and the feature-gated modules, given as module paths from the
+/// bundle root mapped to the feature gating each (see
+/// `TransformConfig::enabled_modules`)
+///
+/// # Errors
+/// Returns an error if a module is not in the bundle once its feature is enabled
+pub(super) fn enable_modules(
+    items: &mut Vec<syn::Item>,
+    mut features: BTreeSet<String>,
+    modules: &BTreeMap<String, String>,
+) -> Result<Vec<BundleWarning>> {
+    features.extend(modules.values().cloned());
+    let stripped = resolve(items, &features);
+
+    for (path, feature) in modules {
+        if !has_module(items, path) {
+            return Err(BundlerError::ProjectStructure {
+                message: format!(
+                    "Module '{path}' is not in the bundle with feature '{feature}' enabled"
+                ),
+            });
+        }
+    }
+    Ok(stripped)
+}
+
+/// Whether code under the `cfg` predicate `predicate` is only compiled in tests,
+/// e.g. `test` or `all(test, feature = "x")`, unlike `not(test)` or
+/// `any(test, debug_assertions)`
+pub(super) fn is_test_only(predicate: &syn::Meta) -> bool {
+    outside_tests(predicate) == Some(false)
+}
+
+/// Value of a `cfg` predicate outside of tests, or `None` if it depends on more
+/// than `test`
+fn outside_tests(predicate: &syn::Meta) -> Option<bool> {
+    match predicate {
+        syn::Meta::Path(path) if path.is_ident("test") => Some(false),
+        syn::Meta::List(list) => {
+            let values: Vec<Option<bool>> = list
+                .parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+                .ok()?
+                .iter()
+                .map(outside_tests)
+                .collect();
+            if list.path.is_ident("all") {
+                combine(&values, false)
+            } else if list.path.is_ident("any") {
+                combine(&values, true)
+            } else if list.path.is_ident("not") {
+                match values[..] {
+                    [value] => value.map(|value| !value),
+                    _ => None,
+                }
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Whether `items` declare the module `path` (`a::b`, optionally `crate::a::b`)
+fn has_module(items: &[syn::Item], path: &str) -> bool {
+    let path = path.strip_prefix("crate::").unwrap_or(path);
+    let mut items = items;
+    for name in path.split("::") {
+        let Some(content) = items.iter().find_map(|item| match item {
+            syn::Item::Mod(item_mod) if item_mod.ident == name => Some(
+                item_mod
+                    .content
+                    .as_ref()
+                    .map_or(&[][..], |(_, items)| items),
+            ),
+            _ => None,
+        }) else {
+            return false;
+        };
+        items = content;
+    }
+    true
+}
+
+/// Resolves `cfg` and `cfg_attr` conditions that only depend on Cargo features
+struct FeatureResolver<'a> {
+    features: &'a BTreeSet<String>,
+    /// Path from the root of the module being resolved
+    module_path: Vec<String>,
+    /// Items removed, with the condition removing them
+    stripped: Vec<BundleWarning>,
+}
+
+impl FeatureResolver<'_> {
+    /// Value of a `cfg` predicate, or `None` if it depends on more than features
+    fn eval(&self, predicate: &syn::Meta) -> Option<bool> {
+        match predicate {
+            syn::Meta::NameValue(name_value) if name_value.path.is_ident("feature") => {
+                match &name_value.value {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(feature),
+                        ..
+                    }) => Some(self.features.contains(&feature.value())),
+                    _ => None,
+                }
+            }
+            syn::Meta::List(list) => {
+                let values: Vec<Option<bool>> = list
+                    .parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+                    .ok()?
+                    .iter()
+                    .map(|predicate| self.eval(predicate))
+                    .collect();
+                if list.path.is_ident("all") {
+                    combine(&values, false)
+                } else if list.path.is_ident("any") {
+                    combine(&values, true)
+                } else if list.path.is_ident("not") {
+                    match values[..] {
+                        [value] => value.map(|value| !value),
+                        _ => None,
+                    }
+                } else {
+                    None
+                }
+            }
+            syn::Meta::Path(_) | syn::Meta::NameValue(_) => None,
+        }
+    }
+
+    /// Drop resolved `cfg`s from `attrs` and apply resolved `cfg_attr`s; `false` if
+    /// the item they belong to is compiled out
+    fn resolve(&self, attrs: &mut Vec<syn::Attribute>) -> bool {
+        self.disabling_condition(attrs).is_none()
+    }
+
+    /// Like `resolve`, returning the first false `cfg` condition of `attrs`, if any
+    fn disabling_condition(&self, attrs: &mut Vec<syn::Attribute>) -> Option<syn::Meta> {
+        let mut disabled_by = None;
+        for attr in mem::take(attrs) {
+            if attr.path().is_ident("cfg") {
+                let Ok(predicate) = attr.parse_args::<syn::Meta>() else {
+                    attrs.push(attr);
+                    continue;
+                };
+                match self.eval(&predicate) {
+                    Some(true) => {}
+                    Some(false) => {
+                        disabled_by.get_or_insert(predicate);
+                    }
+                    None => attrs.push(attr),
+                }
+            } else if attr.path().is_ident("cfg_attr") {
+                let Ok(args) =
+                    attr.parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+                else {
+                    attrs.push(attr);
+                    continue;
+                };
+                let mut args = args.into_iter();
+                match args.next().and_then(|predicate| self.eval(&predicate)) {
+                    Some(true) => attrs.extend(args.map(|meta| syn::Attribute {
+                        meta,
+                        ..attr.clone()
+                    })),
+                    Some(false) => {}
+                    None => attrs.push(attr),
+                }
+            } else {
+                attrs.push(attr);
+            }
+        }
+        disabled_by
+    }
+
+    fn resolve_item(&mut self, item: &mut syn::Item) -> bool {
+        let description = describe(item);
+        let Some(condition) =
+            item_attrs_mut(item).and_then(|attrs| self.disabling_condition(attrs))
+        else {
+            return true;
+        };
+        self.module_path.push(description);
+        self.stripped.push(BundleWarning::CfgStripped {
+            item: self.module_path.join("::"),
+            condition: condition.to_token_stream().to_string(),
+        });
+        self.module_path.pop();
+        false
+    }
+}
+
+/// Attributes of an expression statement, e.g. `#[cfg(feature = "x")] run();`
+fn expr_attrs_mut(expr: &mut syn::Expr) -> Option<&mut Vec<syn::Attribute>> {
+    Some(match expr {
+        syn::Expr::Assign(expr) => &mut expr.attrs,
+        syn::Expr::Binary(expr) => &mut expr.attrs,
+        syn::Expr::Block(expr) => &mut expr.attrs,
+        syn::Expr::Break(expr) => &mut expr.attrs,
+        syn::Expr::Call(expr) => &mut expr.attrs,
+        syn::Expr::Continue(expr) => &mut expr.attrs,
+        syn::Expr::ForLoop(expr) => &mut expr.attrs,
+        syn::Expr::If(expr) => &mut expr.attrs,
+        syn::Expr::Loop(expr) => &mut expr.attrs,
+        syn::Expr::Macro(expr) => &mut expr.attrs,
+        syn::Expr::Match(expr) => &mut expr.attrs,
+        syn::Expr::MethodCall(expr) => &mut expr.attrs,
+        syn::Expr::Path(expr) => &mut expr.attrs,
+        syn::Expr::Return(expr) => &mut expr.attrs,
+        syn::Expr::Unary(expr) => &mut expr.attrs,
+        syn::Expr::Unsafe(expr) => &mut expr.attrs,
+        syn::Expr::While(expr) => &mut expr.attrs,
+        _ => return None,
+    })
+}
+
+/// `all` (`short_circuit` is `false`) or `any` (`true`) of partially known values
+fn combine(values: &[Option<bool>], short_circuit: bool) -> Option<bool> {
+    if values.contains(&Some(short_circuit)) {
+        Some(short_circuit)
+    } else if values.iter().all(Option::is_some) {
+        Some(!short_circuit)
+    } else {
+        None
+    }
+}
+
+impl VisitMut for FeatureResolver<'_> {
+    fn visit_item_mod_mut(&mut self, item: &mut syn::ItemMod) {
+        self.module_path.push(item.ident.to_string());
+        if let Some((_, items)) = &mut item.content {
+            items.retain_mut(|item| self.resolve_item(item));
+        }
+        visit_mut::visit_item_mod_mut(self, item);
+        self.module_path.pop();
+    }
+
+    fn visit_item_impl_mut(&mut self, item: &mut syn::ItemImpl) {
+        item.items.retain_mut(|item| match item {
+            syn::ImplItem::Const(item) => self.resolve(&mut item.attrs),
+            syn::ImplItem::Fn(item) => self.resolve(&mut item.attrs),
+            syn::ImplItem::Type(item) => self.resolve(&mut item.attrs),
+            syn::ImplItem::Macro(item) => self.resolve(&mut item.attrs),
+            _ => true,
+        });
+        visit_mut::visit_item_impl_mut(self, item);
+    }
+
+    fn visit_item_trait_mut(&mut self, item: &mut syn::ItemTrait) {
+        item.items.retain_mut(|item| match item {
+            syn::TraitItem::Const(item) => self.resolve(&mut item.attrs),
+            syn::TraitItem::Fn(item) => self.resolve(&mut item.attrs),
+            syn::TraitItem::Type(item) => self.resolve(&mut item.attrs),
+            syn::TraitItem::Macro(item) => self.resolve(&mut item.attrs),
+            _ => true,
+        });
+        visit_mut::visit_item_trait_mut(self, item);
+    }
+
+    fn visit_item_enum_mut(&mut self, item: &mut syn::ItemEnum) {
+        item.variants = mem::take(&mut item.variants)
+            .into_iter()
+            .filter_map(|mut variant| self.resolve(&mut variant.attrs).then_some(variant))
+            .collect();
+        visit_mut::visit_item_enum_mut(self, item);
+    }
+
+    fn visit_fields_named_mut(&mut self, fields: &mut syn::FieldsNamed) {
+        fields.named = mem::take(&mut fields.named)
+            .into_iter()
+            .filter_map(|mut field| self.resolve(&mut field.attrs).then_some(field))
+            .collect();
+        visit_mut::visit_fields_named_mut(self, fields);
+    }
+
+    fn visit_fields_unnamed_mut(&mut self, fields: &mut syn::FieldsUnnamed) {
+        fields.unnamed = mem::take(&mut fields.unnamed)
+            .into_iter()
+            .filter_map(|mut field| self.resolve(&mut field.attrs).then_some(field))
+            .collect();
+        visit_mut::visit_fields_unnamed_mut(self, fields);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        block.stmts.retain_mut(|stmt| match stmt {
+            syn::Stmt::Local(local) => self.resolve(&mut local.attrs),
+            syn::Stmt::Item(item) => self.resolve_item(item),
+            syn::Stmt::Macro(mac) => self.resolve(&mut mac.attrs),
+            syn::Stmt::Expr(expr, _) => {
+                expr_attrs_mut(expr).map_or(true, |attrs| self.resolve(attrs))
+            }
+        });
+        visit_mut::visit_block_mut(self, block);
+    }
+
+    fn visit_expr_match_mut(&mut self, expr: &mut syn::ExprMatch) {
+        expr.arms.retain_mut(|arm| self.resolve(&mut arm.attrs));
+        visit_mut::visit_expr_match_mut(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enabled_module_swaps_strategy() {
+        let mut file = syn::parse_file(
+            r#"pub mod ai {
+    #[cfg(feature = "splash")]
+    pub mod splash_bomb_strategy { pub struct SplashBombStrategy; }
+    #[cfg(feature = "cover")]
+    pub mod cover_strategy { pub struct CoverStrategy; }
+}
+#[cfg(feature = "cover")]
+type Strategy = ai::cover_strategy::CoverStrategy;
+#[cfg(not(feature = "cover"))]
+type Strategy = ai::splash_bomb_strategy::SplashBombStrategy;
+#[cfg(test)]
+mod tests {}
+"#,
+        )
+        .unwrap();
+        let modules =
+            BTreeMap::from([("crate::ai::cover_strategy".to_string(), "cover".to_string())]);
+        let stripped = enable_modules(&mut file.items, BTreeSet::new(), &modules).unwrap();
+        assert_eq!(
+            stripped.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "type Strategy removed by #[cfg(not (feature = \"cover\"))]",
+                "ai::mod splash_bomb_strategy removed by #[cfg(feature = \"splash\")]",
+            ]
+        );
+
+        let output = prettyplease::unparse(&file);
+        assert!(output.contains("pub mod cover_strategy"));
+        assert!(output.contains("type Strategy = ai::cover_strategy::CoverStrategy;"));
+        assert!(!output.contains("SplashBomb"));
+        assert!(!output.contains("feature"));
+        assert!(output.contains("#[cfg(test)]"));
+
+        let modules = BTreeMap::from([("ai::cover".to_string(), "cover".to_string())]);
+        assert!(enable_modules(&mut file.items, BTreeSet::new(), &modules).is_err());
+    }
+
+    #[test]
+    fn test_statement_conditions() {
+        let mut file = syn::parse_file(
+            r#"fn main() {
+    #[cfg(feature = "search")]
+    search::run();
+    #[cfg(not(feature = "search"))]
+    greedy::run();
+    #[cfg(all(feature = "search", unix))]
+    log();
+}"#,
+        )
+        .unwrap();
+        resolve(&mut file.items, &BTreeSet::from(["search".to_string()]));
+        assert_eq!(
+            prettyplease::unparse(&file),
+            "fn main() {\n    search::run();\n    #[cfg(all(feature = \"search\", unix))] log();\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_test_only_conditions() {
+        let test_only = |predicate: &str| is_test_only(&syn::parse_str(predicate).unwrap());
+        assert!(test_only("test"));
+        assert!(test_only("all(test, feature = \"x\")"));
+        assert!(test_only("any(test, not(not(test)))"));
+        assert!(!test_only("not(test)"));
+        assert!(!test_only("any(test, debug_assertions)"));
+        assert!(!test_only("feature = \"testing\""));
+    }
+}
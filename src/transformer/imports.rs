@@ -0,0 +1,383 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use quote::ToTokens;
+use syn::punctuated::Punctuated;
+
+/// What a flattened `use` path ends with
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Leaf {
+    Name(syn::Ident),
+    Rename(syn::Ident, syn::Ident),
+    Glob,
+}
+
+impl Leaf {
+    /// Name the import binds in its module, if any
+    fn binding(&self) -> Option<&syn::Ident> {
+        match self {
+            Self::Name(name) => Some(name),
+            Self::Rename(_, alias) if alias != "_" => Some(alias),
+            Self::Rename(..) | Self::Glob => None,
+        }
+    }
+}
+
+/// A single import of a `use` declaration, e.g. `std::io::Write` of
+/// `use std::{fmt, io::Write};`
+struct Import {
+    attrs: Vec<syn::Attribute>,
+    vis: syn::Visibility,
+    leading_colon: bool,
+    path: Vec<syn::Ident>,
+    leaf: Leaf,
+}
+
+impl Import {
+    /// Attributes as text, telling apart imports that are compiled in different cases
+    fn attrs_key(&self) -> String {
+        self.attrs
+            .iter()
+            .map(|attr| attr.to_token_stream().to_string())
+            .collect()
+    }
+
+    /// `#[cfg]` attributes as text; other attributes, such as the comments kept by
+    /// `keep_comments`, do not change when an import is compiled
+    fn cfg_key(&self) -> Vec<String> {
+        self.attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("cfg"))
+            .map(|attr| attr.to_token_stream().to_string())
+            .collect()
+    }
+
+    /// Whether both imports can be compiled together, i.e. they have the same
+    /// `#[cfg]` attributes or one of them has none
+    fn overlaps(&self, other: &Self) -> bool {
+        let (cfgs, other_cfgs) = (self.cfg_key(), other.cfg_key());
+        cfgs.is_empty() || other_cfgs.is_empty() || cfgs == other_cfgs
+    }
+
+    fn same_target(&self, other: &Self) -> bool {
+        self.leading_colon == other.leading_colon
+            && self.path == other.path
+            && self.leaf == other.leaf
+    }
+}
+
+/// Merge the `use` declarations of each module of `items` into one per path root
+/// and visibility, removing duplicates
+///
+/// Duplicate imports, which the bundle gets when the library and the binary both
+/// import a name at the crate root, are kept once with the widest visibility;
+/// `a::b::{self}` counts as `a::b`. When two imports bind the same name to different
+/// paths, the first one wins and the later ones become `as _` imports, which still
+/// bring traits into scope for their methods. The merged declarations take the
+/// place of the first `use` of the module.
+pub fn merge_imports(items: &mut Vec<syn::Item>) {
+    merge_module(items);
+    for item in items {
+        if let syn::Item::Mod(syn::ItemMod {
+            content: Some((_, content)),
+            ..
+        }) = item
+        {
+            merge_imports(content);
+        }
+    }
+}
+
+fn merge_module(items: &mut Vec<syn::Item>) {
+    let Some(first) = items
+        .iter()
+        .position(|item| matches!(item, syn::Item::Use(_)))
+    else {
+        return;
+    };
+
+    let mut imports: Vec<Import> = Vec::new();
+    let mut rest = Vec::with_capacity(items.len());
+    for item in items.drain(..) {
+        let syn::Item::Use(item_use) = item else {
+            rest.push(item);
+            continue;
+        };
+        let mut leaves = Vec::new();
+        flatten(&item_use.tree, &mut Vec::new(), &mut leaves);
+        for (path, leaf) in leaves {
+            let import = Import {
+                attrs: item_use.attrs.clone(),
+                vis: item_use.vis.clone(),
+                leading_colon: item_use.leading_colon.is_some(),
+                path,
+                leaf,
+            };
+            add_import(&mut imports, import);
+        }
+    }
+    resolve_conflicts(&mut imports);
+
+    *items = rest;
+    items.splice(first..first, build_uses(imports));
+}
+
+/// Split a use tree into `(path, leaf)` imports, `prefix` being the path above it
+fn flatten(
+    tree: &syn::UseTree,
+    prefix: &mut Vec<syn::Ident>,
+    leaves: &mut Vec<(Vec<syn::Ident>, Leaf)>,
+) {
+    match tree {
+        syn::UseTree::Path(path) => {
+            prefix.push(path.ident.clone());
+            flatten(&path.tree, prefix, leaves);
+            prefix.pop();
+        }
+        syn::UseTree::Name(name) => match prefix.split_last() {
+            Some((parent, path)) if name.ident == "self" => {
+                leaves.push((path.to_vec(), Leaf::Name(parent.clone())));
+            }
+            _ => leaves.push((prefix.clone(), Leaf::Name(name.ident.clone()))),
+        },
+        syn::UseTree::Rename(rename) => match prefix.split_last() {
+            Some((parent, path)) if rename.ident == "self" => {
+                let leaf = Leaf::Rename(parent.clone(), rename.rename.clone());
+                leaves.push((path.to_vec(), leaf));
+            }
+            _ => {
+                let leaf = Leaf::Rename(rename.ident.clone(), rename.rename.clone());
+                leaves.push((prefix.clone(), leaf));
+            }
+        },
+        syn::UseTree::Glob(_) => leaves.push((prefix.clone(), Leaf::Glob)),
+        syn::UseTree::Group(group) => {
+            for tree in &group.items {
+                flatten(tree, prefix, leaves);
+            }
+        }
+    }
+}
+
+/// Add `import` unless an import of the same path compiled in the same cases exists,
+/// widening the visibility of that one instead; an unconditional import absorbs
+/// `#[cfg]`-gated copies
+fn add_import(imports: &mut Vec<Import>, import: Import) {
+    let existing = imports
+        .iter_mut()
+        .find(|existing| existing.overlaps(&import) && existing.same_target(&import));
+    match existing {
+        Some(existing) => {
+            if import.cfg_key().is_empty() {
+                existing.attrs.retain(|attr| !attr.path().is_ident("cfg"));
+            }
+            if visibility_rank(&import.vis) > visibility_rank(&existing.vis) {
+                existing.vis = import.vis;
+            }
+        }
+        None => imports.push(import),
+    }
+}
+
+const fn visibility_rank(vis: &syn::Visibility) -> u8 {
+    match vis {
+        syn::Visibility::Inherited => 0,
+        syn::Visibility::Restricted(_) => 1,
+        syn::Visibility::Public(_) => 2,
+    }
+}
+
+/// Turn imports binding a name already bound by an earlier import into `as _` imports
+fn resolve_conflicts(imports: &mut [Import]) {
+    for index in 1..imports.len() {
+        let (earlier, rest) = imports.split_at_mut(index);
+        let import = &mut rest[0];
+        let Some(name) = import.leaf.binding() else {
+            continue;
+        };
+        let conflicts = earlier
+            .iter()
+            .any(|earlier| earlier.leaf.binding() == Some(name) && earlier.overlaps(import));
+        if let (true, Leaf::Name(ident) | Leaf::Rename(ident, _)) = (conflicts, &import.leaf) {
+            let ident = ident.clone();
+            import.leaf = Leaf::Rename(ident, syn::Ident::new("_", proc_macro2::Span::call_site()));
+        }
+    }
+}
+
+/// Imports sharing a path prefix, as a tree
+#[derive(Default)]
+struct Node {
+    leaves: BTreeSet<Leaf>,
+    children: BTreeMap<syn::Ident, Self>,
+}
+
+impl Node {
+    fn insert(&mut self, path: &[syn::Ident], leaf: Leaf) {
+        match path.split_first() {
+            Some((first, rest)) => self
+                .children
+                .entry(first.clone())
+                .or_default()
+                .insert(rest, leaf),
+            None => {
+                self.leaves.insert(leaf);
+            }
+        }
+    }
+
+    /// The use trees of this node's imports, relative to its path
+    fn trees(&self) -> Vec<syn::UseTree> {
+        let leaves = self.leaves.iter().map(|leaf| match leaf {
+            Leaf::Name(ident) => syn::UseTree::Name(syn::UseName {
+                ident: ident.clone(),
+            }),
+            Leaf::Rename(ident, rename) => syn::UseTree::Rename(syn::UseRename {
+                ident: ident.clone(),
+                as_token: syn::Token![as](proc_macro2::Span::call_site()),
+                rename: rename.clone(),
+            }),
+            Leaf::Glob => syn::UseTree::Glob(syn::UseGlob {
+                star_token: syn::Token![*](proc_macro2::Span::call_site()),
+            }),
+        });
+        let children = self.children.iter().map(|(ident, child)| {
+            let mut trees = child.trees();
+            let tree = if trees.len() == 1 {
+                trees.remove(0)
+            } else {
+                syn::UseTree::Group(syn::UseGroup {
+                    brace_token: syn::token::Brace::default(),
+                    items: trees.into_iter().collect::<Punctuated<_, _>>(),
+                })
+            };
+            syn::UseTree::Path(syn::UsePath {
+                ident: ident.clone(),
+                colon2_token: syn::Token![::](proc_macro2::Span::call_site()),
+                tree: Box::new(tree),
+            })
+        });
+        leaves.chain(children).collect()
+    }
+}
+
+/// One `use` declaration per attributes, visibility and path root, in the order
+/// their first import appeared
+fn build_uses(imports: Vec<Import>) -> Vec<syn::Item> {
+    let mut groups: Vec<(Import, Node)> = Vec::new();
+    for import in imports {
+        let key = (
+            import.attrs_key(),
+            import.vis.to_token_stream().to_string(),
+            import.leading_colon,
+        );
+        let index = groups
+            .iter()
+            .position(|(first, _)| {
+                (
+                    first.attrs_key(),
+                    first.vis.to_token_stream().to_string(),
+                    first.leading_colon,
+                ) == key
+            })
+            .unwrap_or_else(|| {
+                groups.push((
+                    Import {
+                        attrs: import.attrs.clone(),
+                        vis: import.vis.clone(),
+                        leading_colon: import.leading_colon,
+                        path: Vec::new(),
+                        leaf: Leaf::Glob,
+                    },
+                    Node::default(),
+                ));
+                groups.len() - 1
+            });
+        groups[index].1.insert(&import.path, import.leaf);
+    }
+
+    groups
+        .into_iter()
+        .flat_map(|(first, node)| {
+            node.trees().into_iter().map(move |tree| {
+                syn::Item::Use(syn::ItemUse {
+                    attrs: first.attrs.clone(),
+                    vis: first.vis.clone(),
+                    use_token: syn::Token![use](proc_macro2::Span::call_site()),
+                    leading_colon: first
+                        .leading_colon
+                        .then(|| syn::Token![::](proc_macro2::Span::call_site())),
+                    tree,
+                    semi_token: syn::Token![;](proc_macro2::Span::call_site()),
+                })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_imports_merged_and_deduplicated() {
+        let mut file = syn::parse_file(
+            r"use std::collections::HashMap;
+use std::fmt::Write;
+fn helper() {}
+use std::collections::{HashMap, BTreeSet};
+pub use crate::geo::Point;
+use crate::geo::{self, Point};
+use std::io::Write;
+use std::io::{self as stdio};
+#[cfg(test)]
+use std::collections::HashMap;
+mod nested {
+    use super::*;
+    use super::*;
+}
+",
+        )
+        .unwrap();
+        merge_imports(&mut file.items);
+
+        assert_eq!(
+            prettyplease::unparse(&file),
+            r"use crate::geo;
+use std::{
+    io as stdio, collections::{BTreeSet, HashMap},
+    fmt::Write, io::Write as _,
+};
+pub use crate::geo::Point;
+fn helper() {}
+mod nested {
+    use super::*;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_imports_with_kept_comments_deduplicated() {
+        let mut file = syn::parse_file(
+            r#"#[__cg_bundler_comment = "// from the library"]
+use std::io::Write;
+use std::io::Write;
+#[__cg_bundler_comment = "// from the binary"]
+#[cfg(test)]
+use std::fmt::Write;
+"#,
+        )
+        .unwrap();
+        merge_imports(&mut file.items);
+
+        assert_eq!(
+            prettyplease::unparse(&file),
+            r#"#[__cg_bundler_comment = "// from the library"]
+use std::io::Write;
+#[__cg_bundler_comment = "// from the binary"]
+#[cfg(test)]
+use std::fmt::Write as _;
+"#
+        );
+    }
+}
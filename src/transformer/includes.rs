@@ -0,0 +1,557 @@
+use proc_macro2::{Group, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+
+use crate::build_script::OutDir;
+use crate::error::Result;
+use crate::file_manager::{FileManager, FileProvider};
+use crate::warnings::BundleWarning;
+
+/// Limit on `include!` files that include further files, guarding against cycles
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IncludeKind {
+    /// `include!`: Rust source parsed in place of the invocation
+    Source,
+    /// `include_str!`: a string literal
+    Str,
+    /// `include_bytes!`: a byte string literal
+    Bytes,
+}
+
+fn include_kind(mac: &syn::Macro) -> Option<IncludeKind> {
+    let segments = &mac.path.segments;
+    let qualified = match segments.len() {
+        1 => true,
+        2 => ["std", "core"]
+            .iter()
+            .any(|krate| segments[0].ident == krate),
+        _ => false,
+    };
+    if !qualified {
+        return None;
+    }
+    match segments.last()?.ident.to_string().as_str() {
+        "include" => Some(IncludeKind::Source),
+        "include_str" => Some(IncludeKind::Str),
+        "include_bytes" => Some(IncludeKind::Bytes),
+        _ => None,
+    }
+}
+
+/// Evaluate the path argument of an include macro
+///
+/// Supports string literals and `concat!` of literals and `env!` variables that are
+/// set while bundling. `OUT_DIR` and the variables set with `cargo:rustc-env` come
+/// from `out_dir`, the package's build script output, when known, and otherwise
+/// from the environment.
+fn eval_path(expr: &syn::Expr, out_dir: Option<&OutDir>) -> Option<String> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(lit),
+            ..
+        }) => Some(lit.value()),
+        syn::Expr::Macro(expr_macro) if expr_macro.mac.path.is_ident("env") => {
+            let name = expr_macro.mac.parse_body::<syn::LitStr>().ok()?.value();
+            out_dir
+                .and_then(|out_dir| out_dir.var(&name))
+                .or_else(|| std::env::var(name).ok())
+        }
+        syn::Expr::Macro(expr_macro) if expr_macro.mac.path.is_ident("concat") => expr_macro
+            .mac
+            .parse_body_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated)
+            .ok()?
+            .iter()
+            .map(|expr| eval_path(expr, out_dir))
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Whether an included expression must be parenthesized to keep its meaning when
+/// it replaces an `include!` operand, e.g. `include!("n.rs") * 2` with `1 + 2`
+const fn needs_parens(expr: &syn::Expr) -> bool {
+    !matches!(
+        expr,
+        syn::Expr::Array(_)
+            | syn::Expr::Block(_)
+            | syn::Expr::Call(_)
+            | syn::Expr::Field(_)
+            | syn::Expr::Index(_)
+            | syn::Expr::Lit(_)
+            | syn::Expr::Macro(_)
+            | syn::Expr::MethodCall(_)
+            | syn::Expr::Paren(_)
+            | syn::Expr::Path(_)
+            | syn::Expr::Repeat(_)
+            | syn::Expr::Struct(_)
+            | syn::Expr::Tuple(_)
+    )
+}
+
+/// Inline `include!`, `include_str!` and `include_bytes!` invocations
+///
+/// Relative paths are resolved against `dir`, the directory of the file containing
+/// the invocations, as rustc does. Invocations whose path cannot be evaluated or
+/// read are left in place, with a warning added to `warnings`. The source files
+/// read are added to `included`, once each.
+pub(super) fn inline(
+    items: &mut Vec<syn::Item>,
+    dir: &Path,
+    sources: &dyn FileProvider,
+    out_dir: Option<&OutDir>,
+    included: &RefCell<Vec<PathBuf>>,
+    warnings: &RefCell<Vec<BundleWarning>>,
+) {
+    Inliner {
+        dir: dir.to_path_buf(),
+        sources,
+        out_dir,
+        included,
+        warnings,
+        depth: 0,
+    }
+    .inline_items(items);
+}
+
+struct Inliner<'a> {
+    dir: PathBuf,
+    sources: &'a dyn FileProvider,
+    out_dir: Option<&'a OutDir>,
+    included: &'a RefCell<Vec<PathBuf>>,
+    warnings: &'a RefCell<Vec<BundleWarning>>,
+    depth: usize,
+}
+
+impl Inliner<'_> {
+    /// Read an included file, which may be generated by the build script
+    fn read_file(&self, path: &Path) -> Result<String> {
+        self.out_dir
+            .and_then(|out_dir| out_dir.file(path))
+            .map_or_else(
+                || {
+                    let content = self.sources.read_file(path)?;
+                    self.record(path);
+                    Ok(content)
+                },
+                |content| Ok(content.to_string()),
+            )
+    }
+
+    fn read_bytes(&self, path: &Path) -> Result<Vec<u8>> {
+        self.out_dir
+            .and_then(|out_dir| out_dir.file(path))
+            .map_or_else(
+                || {
+                    let content = self.sources.read_bytes(path)?;
+                    self.record(path);
+                    Ok(content)
+                },
+                |content| Ok(content.as_bytes().to_vec()),
+            )
+    }
+
+    /// Warn that `mac` is left unchanged
+    fn warn(&self, mac: &syn::Macro, reason: String) {
+        self.warnings
+            .borrow_mut()
+            .push(BundleWarning::UnresolvedInclude {
+                invocation: format!("{}!", mac.path.to_token_stream()),
+                reason,
+            });
+    }
+
+    /// Note that the source file `path` was included
+    fn record(&self, path: &Path) {
+        let mut included = self.included.borrow_mut();
+        if !included.iter().any(|file| file == path) {
+            included.push(path.to_path_buf());
+        }
+    }
+
+    fn inline_items(&mut self, items: &mut Vec<syn::Item>) {
+        let mut inlined = Vec::with_capacity(items.len());
+        for mut item in items.drain(..) {
+            if let syn::Item::Macro(item_macro) = &item {
+                if item_macro.ident.is_none() {
+                    if let Some(included) = self.include_source(
+                        &item_macro.mac,
+                        |code| syn::parse_file(code).map(|file| file.items),
+                        Self::inline_items,
+                    ) {
+                        inlined.extend(included);
+                        continue;
+                    }
+                }
+            }
+            self.visit_item_mut(&mut item);
+            inlined.push(item);
+        }
+        *items = inlined;
+    }
+
+    fn inline_stmts(&mut self, stmts: &mut Vec<syn::Stmt>) {
+        let mut block = syn::Block {
+            brace_token: syn::token::Brace::default(),
+            stmts: std::mem::take(stmts),
+        };
+        self.visit_block_mut(&mut block);
+        *stmts = block.stmts;
+    }
+
+    /// Resolve the file named by an include macro, or `None` if `mac` is not one
+    fn target(&self, mac: &syn::Macro, warn: bool) -> Option<(IncludeKind, PathBuf)> {
+        let kind = include_kind(mac)?;
+        let path = mac
+            .parse_body::<syn::Expr>()
+            .ok()
+            .and_then(|expr| eval_path(&expr, self.out_dir));
+        let Some(path) = path else {
+            if warn {
+                let reason = match self.out_dir {
+                    Some(OutDir::Unknown { reason })
+                        if mac.tokens.to_string().contains("OUT_DIR") =>
+                    {
+                        format!(
+                            "cannot resolve its path, as the build script cannot be reproduced without running it ({reason}); use --run-build-script"
+                        )
+                    }
+                    _ => "cannot resolve its path".to_string(),
+                };
+                self.warn(mac, reason);
+            }
+            return None;
+        };
+        Some((kind, FileManager::join_relative(&self.dir, &path)))
+    }
+
+    /// Read, parse and recursively inline an `include!`d source file
+    fn include_source<T>(
+        &self,
+        mac: &syn::Macro,
+        parse: fn(&str) -> syn::Result<T>,
+        visit: fn(&mut Self, &mut T),
+    ) -> Option<T> {
+        let (IncludeKind::Source, path) = self.target(mac, true)? else {
+            return None;
+        };
+        if self.depth >= MAX_DEPTH {
+            self.warn(
+                mac,
+                format!("too many nested include! files at {}", path.display()),
+            );
+            return None;
+        }
+
+        let code = self
+            .read_file(&path)
+            .map_err(|e| self.warn(mac, e.to_string()))
+            .ok()?;
+        let mut parsed = parse(&code)
+            .map_err(|e| self.warn(mac, format!("cannot parse {}: {e}", path.display())))
+            .ok()?;
+
+        let mut nested = Inliner {
+            dir: path.parent().map_or_else(PathBuf::new, Path::to_path_buf),
+            sources: self.sources,
+            out_dir: self.out_dir,
+            included: self.included,
+            warnings: self.warnings,
+            depth: self.depth + 1,
+        };
+        visit(&mut nested, &mut parsed);
+        Some(parsed)
+    }
+
+    /// Literal replacing an `include_str!` or `include_bytes!` invocation
+    fn include_literal(&self, mac: &syn::Macro, warn: bool) -> Option<syn::Lit> {
+        let (kind, path) = self.target(mac, warn)?;
+        let span = mac.path.segments.last()?.ident.span();
+        let literal = match kind {
+            IncludeKind::Source => return None,
+            IncludeKind::Str => self
+                .read_file(&path)
+                .map(|content| syn::Lit::Str(syn::LitStr::new(&content, span))),
+            IncludeKind::Bytes => self
+                .read_bytes(&path)
+                .map(|content| syn::Lit::ByteStr(syn::LitByteStr::new(&content, span))),
+        };
+        literal.map_err(|e| self.warn(mac, e.to_string())).ok()
+    }
+
+    /// Replace `include_str!(...)`/`include_bytes!(...)` inside unparsed macro
+    /// arguments, e.g. `println!("{}", include_str!("map.txt"))`
+    fn inline_tokens(&self, tokens: TokenStream) -> TokenStream {
+        let trees: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut out = TokenStream::new();
+        let mut index = 0;
+        while index < trees.len() {
+            if let Some(literal) = trees
+                .get(index..index + 3)
+                .and_then(|window| self.token_literal(window))
+            {
+                out.extend(literal);
+                index += 3;
+                continue;
+            }
+            match &trees[index] {
+                TokenTree::Group(group) => {
+                    let mut inlined =
+                        Group::new(group.delimiter(), self.inline_tokens(group.stream()));
+                    inlined.set_span(group.span());
+                    out.extend([TokenTree::Group(inlined)]);
+                }
+                tree => out.extend([tree.clone()]),
+            }
+            index += 1;
+        }
+        out
+    }
+
+    fn token_literal(&self, window: &[TokenTree]) -> Option<TokenStream> {
+        let [TokenTree::Ident(name), TokenTree::Punct(bang), TokenTree::Group(_)] = window else {
+            return None;
+        };
+        if bang.as_char() != '!' || (name != "include_str" && name != "include_bytes") {
+            return None;
+        }
+        let mac: syn::Macro = syn::parse2(window.iter().cloned().collect()).ok()?;
+        // Arguments of macro_rules! bodies such as `$path` are expected not to resolve
+        self.include_literal(&mac, false)
+            .map(ToTokens::into_token_stream)
+    }
+}
+
+impl VisitMut for Inliner<'_> {
+    fn visit_item_mod_mut(&mut self, item: &mut syn::ItemMod) {
+        if let Some((_, content)) = &mut item.content {
+            self.inline_items(content);
+        }
+    }
+
+    fn visit_item_macro_mut(&mut self, item: &mut syn::ItemMacro) {
+        // Leave `macro_rules!` definitions alone
+        if item.ident.is_none() {
+            visit_mut::visit_item_macro_mut(self, item);
+        }
+    }
+
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        let mut stmts = Vec::with_capacity(block.stmts.len());
+        for mut stmt in block.stmts.drain(..) {
+            if let syn::Stmt::Macro(stmt_macro) = &stmt {
+                if let Some(included) = self.include_source(
+                    &stmt_macro.mac,
+                    |code| syn::Block::parse_within.parse_str(code),
+                    Self::inline_stmts,
+                ) {
+                    stmts.extend(included);
+                    continue;
+                }
+            }
+            self.visit_stmt_mut(&mut stmt);
+            stmts.push(stmt);
+        }
+        block.stmts = stmts;
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+        if let syn::Expr::Macro(expr_macro) = expr {
+            match include_kind(&expr_macro.mac) {
+                Some(IncludeKind::Source) => {
+                    if let Some(included) = self.include_source(
+                        &expr_macro.mac,
+                        syn::parse_str::<syn::Expr>,
+                        Self::visit_expr_mut,
+                    ) {
+                        *expr = if needs_parens(&included) {
+                            syn::parse_quote!((#included))
+                        } else {
+                            included
+                        };
+                    }
+                    return;
+                }
+                Some(_) => {
+                    if let Some(literal) = self.include_literal(&expr_macro.mac, true) {
+                        *expr = syn::Expr::Lit(syn::ExprLit {
+                            attrs: Vec::new(),
+                            lit: literal,
+                        });
+                    }
+                    return;
+                }
+                None => {}
+            }
+        }
+        visit_mut::visit_expr_mut(self, expr);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        if include_kind(mac).is_none() {
+            mac.tokens = self.inline_tokens(std::mem::take(&mut mac.tokens));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn inline_with(code: &str, files: &[(&str, &str)]) -> String {
+        inline_with_out_dir(code, files, None)
+    }
+
+    fn inline_with_out_dir(code: &str, files: &[(&str, &str)], out_dir: Option<&OutDir>) -> String {
+        inline_recording(code, files, out_dir).0
+    }
+
+    /// The inlined code, the files included and the warnings
+    fn inline_recording(
+        code: &str,
+        files: &[(&str, &str)],
+        out_dir: Option<&OutDir>,
+    ) -> (String, Vec<PathBuf>, Vec<BundleWarning>) {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, content)| (PathBuf::from(path), (*content).to_string()))
+            .collect();
+        let mut file = syn::parse_file(code).unwrap();
+        let included = RefCell::new(Vec::new());
+        let warnings = RefCell::new(Vec::new());
+        inline(
+            &mut file.items,
+            Path::new("src"),
+            &files,
+            out_dir,
+            &included,
+            &warnings,
+        );
+        (
+            prettyplease::unparse(&file),
+            included.into_inner(),
+            warnings.into_inner(),
+        )
+    }
+
+    #[test]
+    fn test_inline_items_and_literals() {
+        let bundled = inline_with(
+            r#"
+            include!("gen/tables.rs");
+            const MAP: &str = include_str!("../data/map.txt");
+            static RAW: &[u8] = include_bytes!("../data/map.txt");
+            fn main() {
+                println!("{}", include_str!("../data/map.txt"));
+                let n = include!("gen/n.rs") * 2;
+            }
+            "#,
+            &[
+                (
+                    "src/gen/tables.rs",
+                    "pub const SIZE: usize = include!(\"size.rs\");",
+                ),
+                ("src/gen/size.rs", "4"),
+                ("src/gen/n.rs", "1 + 2"),
+                ("src/../data/map.txt", "x.x\n"),
+            ],
+        );
+        assert!(bundled.contains("pub const SIZE: usize = 4;"));
+        assert!(bundled.contains(r#"const MAP: &str = "x.x\n";"#));
+        assert!(bundled.contains(r#"static RAW: &[u8] = b"x.x\n";"#));
+        assert!(bundled.contains(r#"println!("{}", "x.x\n");"#));
+        assert!(bundled.contains("let n = (1 + 2) * 2;"));
+        assert!(!bundled.contains("include"));
+    }
+
+    #[test]
+    fn test_included_files_are_recorded_once() {
+        let (_, included, _) = inline_recording(
+            r#"
+            const A: &str = include_str!("a.txt");
+            const B: &[u8] = include_bytes!("a.txt");
+            include!("gen.rs");
+            "#,
+            &[("src/a.txt", "a"), ("src/gen.rs", "const C: u8 = 1;")],
+            None,
+        );
+        assert_eq!(
+            included,
+            [PathBuf::from("src/a.txt"), PathBuf::from("src/gen.rs")]
+        );
+    }
+
+    #[test]
+    fn test_unresolved_includes_are_kept() {
+        let (bundled, _, warnings) = inline_recording(
+            r#"
+            include!(concat!(env!("CG_BUNDLER_UNSET_VAR"), "/gen.rs"));
+            const A: &str = include_str!("missing.txt");
+            macro_rules! load { ($p:expr) => { include_str!($p) }; }
+            "#,
+            &[],
+            None,
+        );
+        assert!(bundled.contains("include!(concat!(env!(\"CG_BUNDLER_UNSET_VAR\")"));
+        assert!(bundled.contains("include_str!(\"missing.txt\")"));
+        assert!(bundled.contains("include_str!($p)"));
+        let invocations: Vec<&str> = warnings
+            .iter()
+            .filter_map(|warning| match warning {
+                BundleWarning::UnresolvedInclude { invocation, .. } => Some(invocation.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(invocations, ["include!", "include_str!"]);
+    }
+
+    #[test]
+    fn test_inline_build_script_output() {
+        let out_dir = OutDir::Simulated {
+            path: PathBuf::from("/project/target/out"),
+            files: std::iter::once((
+                PathBuf::from("primes.rs"),
+                "const P: [u8; 2] = [2, 3];".to_string(),
+            ))
+            .collect(),
+        };
+        let bundled = inline_with_out_dir(
+            r#"include!(concat!(env!("OUT_DIR"), "/primes.rs"));"#,
+            &[],
+            Some(&out_dir),
+        );
+        assert_eq!(bundled.trim(), "const P: [u8; 2] = [2, 3];");
+    }
+
+    #[test]
+    fn test_inline_built_output_named_by_rustc_env() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let out = temp_dir.path().join("bot-1a2b/out");
+        std::fs::create_dir_all(&out).unwrap();
+        std::fs::write(
+            out.with_file_name("output"),
+            "cargo:rustc-env=TABLES=tables.rs\n",
+        )
+        .unwrap();
+
+        let tables = out.join("tables.rs");
+        let bundled = inline_with_out_dir(
+            r#"include!(concat!(env!("OUT_DIR"), "/", env!("TABLES")));"#,
+            &[(tables.to_str().unwrap(), "const T: [u8; 1] = [7];")],
+            Some(&OutDir::Built(out)),
+        );
+        assert_eq!(bundled.trim(), "const T: [u8; 1] = [7];");
+    }
+
+    #[test]
+    fn test_eval_path_with_concat() {
+        let expr: syn::Expr = syn::parse_quote!(concat!("a", "/", "b.rs"));
+        assert_eq!(eval_path(&expr, None).as_deref(), Some("a/b.rs"));
+    }
+}
@@ -0,0 +1,144 @@
+/// Length of a (possibly nested) block comment at the start of `rest`
+pub fn block_comment_len(rest: &str) -> usize {
+    let mut depth = 0;
+    let mut index = 0;
+    while index < rest.len() {
+        if rest[index..].starts_with("/*") {
+            depth += 1;
+            index += 2;
+        } else if rest[index..].starts_with("*/") {
+            depth -= 1;
+            index += 2;
+            if depth == 0 {
+                return index;
+            }
+        } else {
+            index += rest[index..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    rest.len()
+}
+
+/// What `literal_token` found at the start of some text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiteralKind {
+    /// An identifier or keyword; `r` alone for a raw identifier such as `r#type`
+    Ident,
+    /// A string literal of any kind: byte, C or raw
+    Str,
+    /// A character or byte literal
+    Char,
+    /// The quote of a lifetime, without its name
+    LifetimeQuote,
+}
+
+/// Length of the identifier, string literal or character literal at the start of
+/// `rest`, or `None` if there is none
+pub fn literal_len(rest: &str) -> Option<usize> {
+    literal_token(rest).map(|(len, _)| len)
+}
+
+/// Length and kind of the identifier, string literal or character literal at the
+/// start of `rest`, or `None` if there is none
+pub fn literal_token(rest: &str) -> Option<(usize, LiteralKind)> {
+    if let Some(body) = rest.strip_prefix('"') {
+        return Some((1 + escaped_len(body, '"'), LiteralKind::Str));
+    }
+    if let Some(body) = rest.strip_prefix('\'') {
+        let is_char = body.starts_with('\\')
+            || body
+                .chars()
+                .next()
+                .is_some_and(|c| body[c.len_utf8()..].starts_with('\''));
+        // The quote of a lifetime is copied alone, then its name as an identifier
+        return Some(if is_char {
+            (1 + escaped_len(body, '\''), LiteralKind::Char)
+        } else {
+            (1, LiteralKind::LifetimeQuote)
+        });
+    }
+
+    let ident = rest
+        .find(|c: char| c != '_' && !c.is_alphanumeric())
+        .unwrap_or(rest.len());
+    if ident == 0 {
+        return None;
+    }
+    let after = &rest[ident..];
+    let token = match &rest[..ident] {
+        "r" | "br" | "cr" => {
+            let hashes = after.len() - after.trim_start_matches('#').len();
+            if after[hashes..].starts_with('"') {
+                let terminator = format!("\"{}", "#".repeat(hashes));
+                let body = &after[hashes + 1..];
+                let len = body.find(&terminator).map_or(rest.len(), |end| {
+                    ident + hashes + 1 + end + terminator.len()
+                });
+                (len, LiteralKind::Str)
+            } else {
+                (ident, LiteralKind::Ident)
+            }
+        }
+        "b" | "c" if after.starts_with('"') => {
+            (ident + 1 + escaped_len(&after[1..], '"'), LiteralKind::Str)
+        }
+        "b" if after.starts_with('\'') => (
+            ident + 1 + escaped_len(&after[1..], '\''),
+            LiteralKind::Char,
+        ),
+        _ => (ident, LiteralKind::Ident),
+    };
+    Some(token)
+}
+
+/// Length of the body of a literal up to and including the unescaped `quote`
+fn escaped_len(body: &str, quote: char) -> usize {
+    let mut chars = body.char_indices();
+    while let Some((index, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c == quote {
+            return index + 1;
+        }
+    }
+    body.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The literal at the start of `text`
+    fn literal(text: &str) -> &str {
+        &text[..literal_len(text).unwrap()]
+    }
+
+    #[test]
+    fn test_raw_strings_with_any_hash_count() {
+        assert_eq!(literal(r#"r"a \" b"#), r#"r"a \""#);
+        assert_eq!(literal(r##"r#"say "hi" {"# // x"##), r##"r#"say "hi" {"#"##);
+        assert_eq!(
+            literal(r####"r###"a "## b"### rest"####),
+            r####"r###"a "## b"###"####
+        );
+        assert_eq!(literal("br#\"raw \"bytes\"\"# x"), "br#\"raw \"bytes\"\"#");
+        assert_eq!(literal("cr#\"c \"str\"\"#;"), "cr#\"c \"str\"\"#");
+        // A raw identifier is not a string
+        assert_eq!(literal("r#type"), "r");
+    }
+
+    #[test]
+    fn test_char_and_byte_literals() {
+        assert_eq!(literal("'{' }"), "'{'");
+        assert_eq!(literal(r#"'"' x"#), r#"'"'"#);
+        assert_eq!(literal(r"'\'' x"), r"'\''");
+        assert_eq!(literal("'é' x"), "'é'");
+        assert_eq!(literal(r"b'\'' x"), r"b'\''");
+        assert_eq!(literal(r#"b"a \" // b" x"#), r#"b"a \" // b""#);
+        assert_eq!(literal(r#"c"c str" x"#), r#"c"c str""#);
+        // The quote of a lifetime alone
+        assert_eq!(literal("'a str"), "'");
+        assert_eq!(literal("'static {"), "'");
+        assert_eq!(literal_len("{ x"), None);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeSet;
+
+use proc_macro2::{Group, Punct, Spacing, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::visit_mut::{self, VisitMut};
+
+/// Embed the library items in `pub mod name` (see `TransformConfig::lib_module_name`)
+///
+/// The library's own `crate::...` paths, including `$crate::...` in its macros, are
+/// pointed at the module. The module gets `#[macro_use]` when the library defines
+/// `macro_rules!` macros, so they stay usable after it as at the bundle root.
+pub(super) fn wrap(mut items: Vec<syn::Item>, name: &str) -> syn::Item {
+    let module = syn::Ident::new(name, Span::call_site());
+    let mut rewriter = CrateRootRewriter { module: &module };
+    for item in &mut items {
+        rewriter.visit_item_mut(item);
+    }
+
+    let defines_macros = items
+        .iter()
+        .any(|item| item.to_token_stream().to_string().contains("macro_rules"));
+    let mut item_mod: syn::ItemMod = syn::parse_quote!(pub mod #module {});
+    if defines_macros {
+        item_mod.attrs.push(syn::parse_quote!(#[macro_use]));
+    }
+    item_mod.content = Some((syn::token::Brace::default(), items));
+    syn::Item::Mod(item_mod)
+}
+
+/// Names of the `#[macro_export]` macros in `items`, which live at the bundle root
+/// wherever they are defined
+pub(super) fn exported_macros(items: &[syn::Item]) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for item in items {
+        match item {
+            syn::Item::Macro(item_macro)
+                if item_macro
+                    .attrs
+                    .iter()
+                    .any(|attr| attr.path().is_ident("macro_export")) =>
+            {
+                names.extend(item_macro.ident.as_ref().map(ToString::to_string));
+            }
+            syn::Item::Mod(syn::ItemMod {
+                content: Some((_, items)),
+                ..
+            }) => names.extend(exported_macros(items)),
+            _ => {}
+        }
+    }
+    names
+}
+
+/// `crate::name`, the path of the embedded library
+pub(super) fn path(name: &str) -> syn::Path {
+    let module = syn::Ident::new(name, Span::call_site());
+    syn::parse_quote!(crate::#module)
+}
+
+/// `crate :: name` tokens, replacing a crate name or following `crate` in macro bodies
+pub(super) fn path_tokens(name: &str, span: Span) -> [TokenTree; 4] {
+    [
+        TokenTree::Ident(syn::Ident::new("crate", span)),
+        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
+        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
+        TokenTree::Ident(syn::Ident::new(name, span)),
+    ]
+}
+
+/// Whether the tokens at `index` are `::`
+pub(super) fn is_path_separator(trees: &[TokenTree], index: usize) -> bool {
+    matches!(
+        (trees.get(index), trees.get(index + 1)),
+        (Some(TokenTree::Punct(first)), Some(TokenTree::Punct(second)))
+            if first.as_char() == ':' && second.as_char() == ':'
+    )
+}
+
+/// Inserts the library module after the leading `crate` of paths
+struct CrateRootRewriter<'a> {
+    module: &'a syn::Ident,
+}
+
+impl CrateRootRewriter<'_> {
+    fn rewrite_tokens(&self, tokens: TokenStream) -> TokenStream {
+        let trees: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut rewritten = Vec::with_capacity(trees.len());
+        for (index, tree) in trees.iter().enumerate() {
+            match tree {
+                TokenTree::Group(group) => {
+                    let mut new_group =
+                        Group::new(group.delimiter(), self.rewrite_tokens(group.stream()));
+                    new_group.set_span(group.span());
+                    rewritten.push(TokenTree::Group(new_group));
+                }
+                TokenTree::Ident(ident)
+                    if ident == "crate" && is_path_separator(&trees, index + 1) =>
+                {
+                    rewritten.extend(path_tokens(&self.module.to_string(), ident.span()));
+                }
+                other => rewritten.push(other.clone()),
+            }
+        }
+        rewritten.into_iter().collect()
+    }
+}
+
+impl VisitMut for CrateRootRewriter<'_> {
+    fn visit_path_mut(&mut self, path: &mut syn::Path) {
+        // A lone `crate` is `pub(crate)`, which keeps meaning the whole bundle
+        if path.leading_colon.is_none()
+            && path.segments.len() > 1
+            && path.segments[0].ident == "crate"
+        {
+            path.segments.insert(1, self.module.clone().into());
+        }
+        visit_mut::visit_path_mut(self, path);
+    }
+
+    fn visit_use_path_mut(&mut self, use_path: &mut syn::UsePath) {
+        if use_path.ident == "crate" {
+            let module = self.module;
+            let tree = std::mem::replace(&mut *use_path.tree, syn::parse_quote!(self));
+            *use_path.tree = syn::parse_quote!(#module::#tree);
+            return;
+        }
+        visit_mut::visit_use_path_mut(self, use_path);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        mac.tokens = self.rewrite_tokens(std::mem::take(&mut mac.tokens));
+        visit_mut::visit_macro_mut(self, mac);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_points_crate_paths_at_module() {
+        let file: syn::File = syn::parse_quote! {
+            use crate::geo::{Point, Vec2};
+            pub mod geo {
+                pub struct Point;
+                pub struct Vec2;
+                pub(crate) fn origin() -> crate::geo::Point { Point }
+            }
+            #[macro_export]
+            macro_rules! point { () => { $crate::geo::origin() }; }
+            pub fn make() -> Point { crate::geo::origin() }
+        };
+
+        let output = wrap(file.items, "solver").to_token_stream().to_string();
+        assert!(output.starts_with("# [macro_use] pub mod solver {"));
+        assert!(output.contains("use crate :: solver :: geo :: { Point , Vec2 }"));
+        assert!(output.contains("pub (crate) fn origin () -> crate :: solver :: geo :: Point"));
+        assert!(output.contains("$ crate :: solver :: geo :: origin ()"));
+        assert!(output.contains("{ crate :: solver :: geo :: origin () }"));
+    }
+}
@@ -0,0 +1,505 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use serde::Serialize;
+
+use super::lexer;
+
+/// Name of the placeholder macro that carries a file marker through the pipeline
+///
+/// Comments do not survive a syntax tree, so expansion boundaries are recorded as
+/// `__cg_bundler_file!("src/ai/strategy.rs");` items and turned into comments once
+/// the bundle has been rendered.
+pub const MARKER_MACRO: &str = "__cg_bundler_file";
+
+const LINE_COMMENT: &str = "// file: ";
+const BLOCK_COMMENT: &str = "/* file: ";
+
+/// Marker item opening a region of code from `path`, or closing the current region
+/// when `path` is `None`
+pub(crate) fn marker(path: Option<&str>) -> syn::Item {
+    let name = syn::Ident::new(MARKER_MACRO, Span::call_site());
+    let args: TokenStream = path.map_or_else(TokenStream::new, |path| quote!(#path));
+    syn::parse_quote!(#name!(#args);)
+}
+
+pub(crate) enum Marker {
+    /// Start of a region of code from the given file
+    Open(String),
+    /// End of the current region; the enclosing file resumes
+    Close,
+}
+
+pub(crate) fn parse_marker(item: &syn::Item) -> Option<Marker> {
+    let syn::Item::Macro(item_macro) = item else {
+        return None;
+    };
+    if item_macro.ident.is_some() || !item_macro.mac.path.is_ident(MARKER_MACRO) {
+        return None;
+    }
+    if item_macro.mac.tokens.is_empty() {
+        return Some(Marker::Close);
+    }
+    item_macro
+        .mac
+        .parse_body::<syn::LitStr>()
+        .ok()
+        .map(|lit| Marker::Open(lit.value()))
+}
+
+/// Whether an item is a line marker placeholder
+#[must_use]
+pub fn is_marker(item: &syn::Item) -> bool {
+    parse_marker(item).is_some()
+}
+
+/// Remove every marker item, including those of expanded modules
+pub(crate) fn strip(items: &mut Vec<syn::Item>) {
+    items.retain(|item| !is_marker(item));
+    for item in items {
+        if let syn::Item::Mod(syn::ItemMod {
+            content: Some((_, content)),
+            ..
+        }) = item
+        {
+            strip(content);
+        }
+    }
+}
+
+/// Resolve closing markers and drop redundant ones
+///
+/// Closing markers become opening markers for the file being resumed, a marker is
+/// added after every module expanded from its own file, and markers that are
+/// immediately followed by another marker (or by nothing) are removed.
+pub(crate) fn finalize(items: &mut Vec<syn::Item>, current: Option<&str>) {
+    let mut current = current.map(str::to_string);
+    let mut enclosing: Vec<Option<String>> = Vec::new();
+    let mut resolved = Vec::with_capacity(items.len());
+
+    for mut item in items.drain(..) {
+        match parse_marker(&item) {
+            Some(Marker::Open(path)) => {
+                enclosing.push(current.replace(path));
+                resolved.push(item);
+            }
+            Some(Marker::Close) => {
+                if let Some(outer) = enclosing.pop() {
+                    current = outer;
+                }
+                if let Some(path) = &current {
+                    resolved.push(marker(Some(path)));
+                }
+            }
+            None => {
+                let mut from_file = false;
+                if let syn::Item::Mod(item_mod) = &mut item {
+                    if let Some((_, content)) = &mut item_mod.content {
+                        from_file = content.first().is_some_and(|first| {
+                            matches!(parse_marker(first), Some(Marker::Open(_)))
+                        });
+                        finalize(content, current.as_deref());
+                    }
+                }
+                resolved.push(item);
+                if from_file {
+                    if let Some(path) = &current {
+                        resolved.push(marker(Some(path)));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut kept: Vec<syn::Item> = Vec::with_capacity(resolved.len());
+    for item in resolved {
+        if kept.last().is_some_and(is_marker) && is_marker(&item) {
+            kept.pop();
+        }
+        kept.push(item);
+    }
+    if kept.last().is_some_and(is_marker) {
+        kept.pop();
+    }
+    *items = kept;
+}
+
+/// Replace rendered marker items with `// file: ...` comments
+///
+/// Only lines holding nothing but a marker item are replaced, so the marker name
+/// in a string literal or a comment is kept. Single-line (minified) output uses
+/// `/* file: ... */` so the comment does not swallow the rest of the line; there
+/// the markers are found between tokens, outside literals and comments.
+#[must_use]
+pub fn render(code: &str, block_comments: bool) -> String {
+    if block_comments {
+        return render_inline(code);
+    }
+    let mut out = String::with_capacity(code.len());
+    for line in code.split_inclusive('\n') {
+        let trimmed = line.trim();
+        match parse_rendered_marker(trimmed) {
+            Some((path, len)) if len == trimmed.len() => {
+                out.push_str(&line[..line.len() - line.trim_start().len()]);
+                out.push_str(LINE_COMMENT);
+                out.push_str(&path);
+                out.push_str(&line[line.trim_end().len()..]);
+            }
+            _ => out.push_str(line),
+        }
+    }
+    out
+}
+
+/// Replace the marker items of minified output with `/* file: ... */` comments
+fn render_inline(code: &str) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut index = 0;
+    while index < code.len() {
+        let rest = &code[index..];
+        let len = if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            lexer::block_comment_len(rest)
+        } else if let Some(len) = lexer::literal_len(rest) {
+            if let Some((path, marker_len)) = (&rest[..len] == MARKER_MACRO)
+                .then(|| parse_rendered_marker(rest))
+                .flatten()
+            {
+                out.push_str(BLOCK_COMMENT);
+                out.push_str(&path);
+                out.push_str(" */");
+                index += marker_len;
+                continue;
+            }
+            len
+        } else {
+            rest.chars().next().map_or(1, char::len_utf8)
+        };
+        out.push_str(&rest[..len]);
+        index += len;
+    }
+    out
+}
+
+/// Parse `__cg_bundler_file!("path");` (with any spacing) at the start of `text`,
+/// returning the path and the length of the marker text
+fn parse_rendered_marker(text: &str) -> Option<(String, usize)> {
+    let open = text.find('"')?;
+    let between = text.get(..open)?.strip_prefix(MARKER_MACRO)?;
+    if !between
+        .chars()
+        .all(|c| c.is_whitespace() || c == '!' || c == '(')
+    {
+        return None;
+    }
+
+    let mut escaped = false;
+    let close = text[open + 1..].char_indices().find_map(|(index, c)| {
+        let found = c == '"' && !escaped;
+        escaped = c == '\\' && !escaped;
+        found.then_some(open + 1 + index)
+    })?;
+    let path = syn::parse_str::<syn::LitStr>(&text[open..=close])
+        .ok()?
+        .value();
+
+    let semicolon = close + 1 + text[close + 1..].find(';')?;
+    if !text[close + 1..semicolon]
+        .chars()
+        .all(|c| c.is_whitespace() || c == ')')
+    {
+        return None;
+    }
+    Some((path, semicolon + 1))
+}
+
+/// A run of bundled output that came from one source file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceSegment {
+    /// Source file, relative to the project root
+    pub file: String,
+    /// First output line of the segment (1-based)
+    pub start_line: usize,
+    /// Column of the marker comment on the first line (1-based, in characters)
+    pub start_column: usize,
+    /// Last output line of the segment (1-based, inclusive)
+    pub end_line: usize,
+}
+
+/// Mapping from bundled output lines back to the original source files
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SourceMap {
+    pub segments: Vec<SourceSegment>,
+}
+
+impl SourceMap {
+    /// Rebuild the map from output containing `// file:` or `/* file: */` markers
+    ///
+    /// Working from the final text keeps the map valid after external formatting.
+    #[must_use]
+    pub fn from_output(code: &str) -> Self {
+        let mut segments: Vec<SourceSegment> = Vec::new();
+        let mut line_count = 0;
+
+        for (index, line) in code.lines().enumerate() {
+            let line_number = index + 1;
+            line_count = line_number;
+            let mut offset = 0;
+
+            while let Some((position, file, len)) = find_comment(&line[offset..]) {
+                if let Some(previous) = segments.last_mut() {
+                    previous.end_line = line_number.saturating_sub(1).max(previous.start_line);
+                }
+                segments.push(SourceSegment {
+                    file,
+                    start_line: line_number,
+                    start_column: line[..offset + position].chars().count() + 1,
+                    end_line: line_number,
+                });
+                offset += position + len;
+            }
+        }
+
+        if let Some(last) = segments.last_mut() {
+            last.end_line = line_count.max(last.start_line);
+        }
+        Self { segments }
+    }
+
+    /// Source file of an output line, if it lies within a marked segment
+    #[must_use]
+    pub fn file_at(&self, line: usize) -> Option<&str> {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start_line <= line && line <= segment.end_line)
+            .map(|segment| segment.file.as_str())
+    }
+
+    /// Source file of an output position (1-based line and column, in characters)
+    ///
+    /// Unlike `file_at`, this tells apart the segments of a single-line
+    /// (minified) bundle.
+    #[must_use]
+    pub fn file_at_position(&self, line: usize, column: usize) -> Option<&str> {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| {
+                segment.start_line <= line
+                    && line <= segment.end_line
+                    && (segment.start_line < line || segment.start_column <= column)
+            })
+            .map(|segment| segment.file.as_str())
+    }
+
+    /// Serialize the map as pretty-printed JSON
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// Count the characters of output containing marker comments per source file
+///
+/// The marker comments are not counted, nor are lines holding nothing but
+/// markers. Characters before the first marker are counted under an empty file
+/// name. Files are listed in order of first appearance.
+#[must_use]
+pub fn characters_by_file(code: &str) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    let mut current = String::new();
+
+    for line in code.split_inclusive('\n') {
+        let body = line.strip_suffix('\n').unwrap_or(line);
+        let mut pieces: Vec<(String, usize)> = Vec::new();
+        let mut only_markers = true;
+        let mut offset = 0;
+
+        while let Some((position, file, len)) = find_comment(&body[offset..]) {
+            let text = &body[offset..offset + position];
+            only_markers &= text.trim().is_empty();
+            pieces.push((std::mem::replace(&mut current, file), text.chars().count()));
+            offset += position + len;
+        }
+        let tail = &body[offset..];
+        if pieces.is_empty() || !tail.trim().is_empty() {
+            only_markers = false;
+        }
+        pieces.push((
+            current.clone(),
+            tail.chars().count() + line.len() - body.len(),
+        ));
+
+        if only_markers {
+            continue;
+        }
+        for (file, count) in pieces {
+            match counts.iter_mut().find(|(known, _)| *known == file) {
+                Some((_, total)) => *total += count,
+                None => counts.push((file, count)),
+            }
+        }
+    }
+    counts.retain(|(_, count)| *count > 0);
+    counts
+}
+
+/// Find the next marker comment, returning (position, file, length)
+///
+/// Line markers are only recognized at the start of a line and block markers only
+/// around a path without quotes, so marker-like text in string literals is skipped.
+fn find_comment(text: &str) -> Option<(usize, String, usize)> {
+    let trimmed = text.trim_start();
+    if let Some(file) = trimmed.strip_prefix(LINE_COMMENT) {
+        return Some((
+            text.len() - trimmed.len(),
+            file.trim().to_string(),
+            trimmed.len(),
+        ));
+    }
+
+    let mut from = 0;
+    while let Some(start) = text[from..].find(BLOCK_COMMENT) {
+        let block = from + start;
+        let body = &text[block + BLOCK_COMMENT.len()..];
+        if let Some(end) = body.find("*/").filter(|&end| !body[..end].contains('"')) {
+            let file = body[..end].trim().to_string();
+            return Some((block, file, BLOCK_COMMENT.len() + end + 2));
+        }
+        from = block + BLOCK_COMMENT.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_of(items: Vec<syn::Item>) -> syn::File {
+        syn::File {
+            shebang: None,
+            attrs: Vec::new(),
+            items,
+        }
+    }
+
+    #[test]
+    fn test_finalize_resumes_parent_file() {
+        let mut module: syn::ItemMod = syn::parse_quote!(
+            mod geo {}
+        );
+        module.content = Some((
+            syn::token::Brace::default(),
+            vec![
+                marker(Some("src/geo.rs")),
+                syn::parse_quote!(
+                    fn dist() {}
+                ),
+            ],
+        ));
+        let mut items = vec![
+            marker(Some("src/main.rs")),
+            marker(Some("src/lib.rs")),
+            syn::Item::Mod(module),
+            marker(None),
+            syn::parse_quote!(
+                fn main() {}
+            ),
+        ];
+        finalize(&mut items, None);
+
+        let rendered = render(&prettyplease::unparse(&file_of(items)), false);
+        let markers: Vec<&str> = rendered
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("//"))
+            .collect();
+        assert_eq!(
+            markers,
+            [
+                "// file: src/lib.rs",
+                "// file: src/geo.rs",
+                "// file: src/main.rs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_minified_uses_block_comments() {
+        let file = file_of(vec![
+            marker(Some("src/a.rs")),
+            syn::parse_quote!(
+                fn a() {}
+            ),
+        ]);
+        let minified = crate::transformer::Minifier::new().minify_file(&file);
+        let rendered = render(&minified, true);
+        assert_eq!(rendered, "/* file: src/a.rs */ fn a () {}");
+    }
+
+    #[test]
+    fn test_render_keeps_marker_text_in_code() {
+        let code = "__cg_bundler_file!(\"src/a.rs\");\nconst NAME: &str = \"__cg_bundler_file!(\\\"x\\\");\";\n    __cg_bundler_file!(\"src/b.rs\");\n";
+        assert_eq!(
+            render(code, false),
+            "// file: src/a.rs\nconst NAME: &str = \"__cg_bundler_file!(\\\"x\\\");\";\n    // file: src/b.rs\n"
+        );
+
+        let minified = "__cg_bundler_file!(\"src/a.rs\");const N:&str=\"__cg_bundler_file!(\\\"x\\\");\";/* __cg_bundler_file!(\"y\"); */__cg_bundler_file ! (\"src/b.rs\") ;fn b(){}";
+        assert_eq!(
+            render(minified, true),
+            "/* file: src/a.rs */const N:&str=\"__cg_bundler_file!(\\\"x\\\");\";/* __cg_bundler_file!(\"y\"); *//* file: src/b.rs */fn b(){}"
+        );
+    }
+
+    #[test]
+    fn test_source_map_from_output() {
+        let code = "// file: src/main.rs\nuse x;\n// file: src/lib.rs\nfn a() {}\nfn b() {}\n// file: src/main.rs\nfn main() {}\n";
+        let map = SourceMap::from_output(code);
+        let ranges: Vec<(&str, usize, usize)> = map
+            .segments
+            .iter()
+            .map(|s| (s.file.as_str(), s.start_line, s.end_line))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                ("src/main.rs", 1, 2),
+                ("src/lib.rs", 3, 5),
+                ("src/main.rs", 6, 7)
+            ]
+        );
+        assert_eq!(map.file_at(4), Some("src/lib.rs"));
+        assert!(map.to_json().contains("\"start_line\": 3"));
+    }
+
+    #[test]
+    fn test_characters_by_file_skips_markers() {
+        let code = "// file: src/main.rs\nuse x;\n// file: src/lib.rs\nfn a() {}\n// file: src/main.rs\nfn main() {}\n";
+        assert_eq!(
+            characters_by_file(code),
+            [
+                ("src/main.rs".to_string(), 20),
+                ("src/lib.rs".to_string(), 10)
+            ]
+        );
+
+        let minified = "/* file: src/a.rs */ fn a(){}/* file: src/b.rs */ fn b(){}";
+        assert_eq!(
+            characters_by_file(minified),
+            [("src/a.rs".to_string(), 9), ("src/b.rs".to_string(), 9)]
+        );
+    }
+
+    #[test]
+    fn test_source_map_single_line() {
+        let map =
+            SourceMap::from_output("/* file: src/a.rs */ fn a(){}/* file: src/b.rs */ fn b(){}");
+        assert_eq!(map.segments.len(), 2);
+        assert_eq!(map.segments[1].start_column, 30);
+        assert_eq!(map.segments[1].end_line, 1);
+        assert_eq!(map.file_at_position(1, 25), Some("src/a.rs"));
+        assert_eq!(map.file_at_position(1, 55), Some("src/b.rs"));
+    }
+}
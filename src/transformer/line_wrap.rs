@@ -0,0 +1,133 @@
+use super::lexer::{block_comment_len, literal_len};
+use super::verbatim;
+
+/// Indentation added to the continuation lines of a wrapped line
+const CONTINUATION_INDENT: &str = "    ";
+
+/// Break lines longer than `max_len` characters, e.g. long merged `use` groups,
+/// function signatures or minified code
+///
+/// Lines are broken after `,`, `;`, `{` and `}`, or else at spaces, never inside string
+/// or character literals or comments, so the code keeps its meaning. Continuation
+/// lines are indented one level deeper than the line they come from. A line with no
+/// such place to break before the limit is broken at the first one after it. The
+/// lines of verbatim regions are left as they are.
+#[must_use]
+pub fn wrap_long_lines(code: &str, max_len: usize) -> String {
+    let points = break_points(code);
+    let regions = verbatim::regions(code);
+    let mut wrapped = String::with_capacity(code.len());
+    let mut line_start = 0;
+    for line in code.split_inclusive('\n') {
+        if regions.iter().any(|region| region.contains(&line_start)) {
+            wrapped.push_str(line);
+            line_start += line.len();
+            continue;
+        }
+        let content = line.trim_end();
+        wrap_line(
+            &mut wrapped,
+            code,
+            line_start..line_start + content.len(),
+            &points,
+            max_len,
+        );
+        wrapped.push_str(&line[content.len()..]);
+        line_start += line.len();
+    }
+    wrapped
+}
+
+/// Byte offsets of `code` at which a line break can be inserted, and whether they
+/// follow punctuation (preferred to spaces)
+fn break_points(code: &str) -> Vec<(usize, bool)> {
+    let mut points = Vec::new();
+    let mut index = 0;
+    while let Some(c) = code[index..].chars().next() {
+        let rest = &code[index..];
+        let len = if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            block_comment_len(rest)
+        } else {
+            match c {
+                ' ' => points.push((index, false)),
+                ',' | ';' | '{' | '}' => points.push((index + 1, true)),
+                _ => {}
+            }
+            literal_len(rest).unwrap_or_else(|| c.len_utf8())
+        };
+        index += len;
+    }
+    points
+}
+
+/// Append the line `code[line]` (without trailing whitespace) to `wrapped`, broken
+/// at `points` where it is too long
+fn wrap_line(
+    wrapped: &mut String,
+    code: &str,
+    line: std::ops::Range<usize>,
+    points: &[(usize, bool)],
+    max_len: usize,
+) {
+    let text = &code[line.clone()];
+    let indent = &text[..text.len() - text.trim_start().len()];
+    let continuation = format!("{indent}{CONTINUATION_INDENT}");
+    let first = points.partition_point(|&(point, _)| point <= line.start + indent.len());
+    let last = points.partition_point(|&(point, _)| point < line.end);
+    let candidates = &points[first..last];
+
+    let mut start = line.start;
+    let mut width = 0;
+    while width + code[start..line.end].chars().count() > max_len {
+        let fits = |&(point, _): &(usize, bool)| {
+            width + code[start..point].trim_end().chars().count() <= max_len
+        };
+        let after = candidates.iter().filter(|&&(point, _)| point > start);
+        let fitting = after.clone().take_while(|candidate| fits(candidate));
+        let Some(&(split, _)) = fitting
+            .clone()
+            .filter(|&&(_, punctuation)| punctuation)
+            .last()
+            .or_else(|| fitting.last())
+            .or_else(|| after.clone().next())
+        else {
+            break;
+        };
+
+        wrapped.push_str(code[start..split].trim_end());
+        wrapped.push('\n');
+        wrapped.push_str(&continuation);
+        width = continuation.chars().count();
+        let rest = &code[split..line.end];
+        start = split + rest.len() - rest.trim_start().len();
+    }
+    wrapped.push_str(&code[start..line.end]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_lines_wrapped_outside_literals() {
+        let code = "use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};\nfn main() {\n    let s = \"a, b, c, d, e, f, g\"; let t = 1; // x, y, z\n}\n";
+        assert_eq!(
+            wrap_long_lines(code, 45),
+            "use std::collections::{BTreeMap, BTreeSet,\n    HashMap, HashSet, VecDeque};\nfn main() {\n    let s = \"a, b, c, d, e, f, g\"; let t = 1;\n        // x, y, z\n}\n"
+        );
+        assert_eq!(wrap_long_lines(code, 200), code);
+        assert!(syn::parse_file(&wrap_long_lines(code, 10)).is_ok());
+    }
+
+    #[test]
+    fn test_verbatim_regions_not_wrapped() {
+        let region = "// cg-bundler: verbatim-start\nconst GRID: [u8; 6] = [1, 2, 3, 4, 5, 6];\n// cg-bundler: verbatim-end\n";
+        let code = format!("{region}const ROW: [u8; 6] = [1, 2, 3, 4, 5, 6];\n");
+        assert_eq!(
+            wrap_long_lines(&code, 30),
+            format!("{region}const ROW: [u8; 6] = [1, 2, 3,\n    4, 5, 6];\n")
+        );
+    }
+}
@@ -0,0 +1,613 @@
+use proc_macro2::{Delimiter, Group, Ident, TokenStream, TokenTree};
+use std::collections::HashMap;
+use syn::parse::{Parse, ParseStream, Parser};
+
+use crate::error::{BundlerError, Result};
+
+/// Limit on invocations expanding to further invocations (rustc's default
+/// `recursion_limit`)
+const MAX_DEPTH: usize = 128;
+
+/// A `macro_rules!` definition
+#[derive(Debug, Clone)]
+pub struct MacroRules {
+    name: String,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    matcher: Vec<Matcher>,
+    transcriber: TokenStream,
+}
+
+/// Part of the left-hand side of a rule
+#[derive(Debug, Clone)]
+enum Matcher {
+    /// Punctuation, identifier or literal matched as is
+    Token(TokenTree),
+    Group(Delimiter, Vec<Self>),
+    /// `$name:kind`
+    Fragment {
+        name: String,
+        kind: String,
+    },
+    /// `$( ... ) separator? operator`
+    Repeat {
+        body: Vec<Self>,
+        separator: Option<TokenTree>,
+        operator: char,
+    },
+}
+
+/// Tokens matched by a fragment, or by each repetition of the fragment
+#[derive(Debug, Clone)]
+enum Binding {
+    Fragment(TokenStream),
+    Repeated(Vec<Self>),
+}
+
+type Bindings = HashMap<String, Binding>;
+
+impl MacroRules {
+    /// Definition made by `item`, if it is a `macro_rules!` whose rules this expander
+    /// understands
+    fn parse(item: &syn::ItemMacro) -> Option<Self> {
+        let name = item.ident.as_ref()?;
+        if !item.mac.path.is_ident("macro_rules") {
+            return None;
+        }
+        let trees: Vec<TokenTree> = item.mac.tokens.clone().into_iter().collect();
+        let mut rules = Vec::new();
+        for rule in trees.split(|tree| is_punct(tree, ';')) {
+            match rule {
+                [] => {}
+                [TokenTree::Group(matcher), TokenTree::Punct(eq), TokenTree::Punct(gt), TokenTree::Group(transcriber)]
+                    if eq.as_char() == '=' && gt.as_char() == '>' =>
+                {
+                    rules.push(Rule {
+                        matcher: parse_matcher(matcher.stream())?,
+                        transcriber: transcriber.stream(),
+                    });
+                }
+                _ => return None,
+            }
+        }
+        Some(Self {
+            name: name.to_string(),
+            rules,
+        })
+    }
+
+    /// Expansion of an invocation with `input` by the first rule matching it
+    fn expand(&self, input: TokenStream) -> Option<TokenStream> {
+        let input: Vec<TokenTree> = input.into_iter().collect();
+        self.rules.iter().find_map(|rule| {
+            let mut bindings = Bindings::new();
+            match_all(&rule.matcher, &input, &mut bindings)?;
+            transcribe(rule.transcriber.clone(), &bindings)
+        })
+    }
+}
+
+/// Replace the invocations of `macro_rules!` macros among `items` whose expansion
+/// declares modules by that expansion, so the modules are bundled like those of
+/// literal `mod` declarations; returns the indices of the items they expanded to
+///
+/// A macro is visible when it is defined in `inherited` (the scope of the parent
+/// module) or before the invocation, either directly or in a `#[macro_use]`
+/// module. Expansions are expanded again until no invocation is left that
+/// declares modules. Other invocations, and those of macros that are not visible
+/// or do not match, are left to the compiler.
+///
+/// # Errors
+/// Returns an error if expansions nest deeper than rustc's default recursion limit
+pub fn expand(items: &mut Vec<syn::Item>, inherited: &[MacroRules]) -> Result<Vec<usize>> {
+    expand_in_scope(items, &mut inherited.to_vec(), 0)
+}
+
+/// `macro_rules!` definitions among `items`, including those of `#[macro_use]`
+/// modules, in the order they are defined
+#[must_use]
+pub fn definitions(items: &[syn::Item]) -> Vec<MacroRules> {
+    let mut definitions = Vec::new();
+    for item in items {
+        match item {
+            syn::Item::Macro(item) => definitions.extend(MacroRules::parse(item)),
+            syn::Item::Mod(module) => definitions.extend(exported_definitions(module)),
+            _ => {}
+        }
+    }
+    definitions
+}
+
+/// Definitions of an expanded module that stay visible after it, which requires
+/// `#[macro_use]`
+#[must_use]
+pub fn exported_definitions(module: &syn::ItemMod) -> Vec<MacroRules> {
+    match &module.content {
+        Some((_, content)) if has_macro_use(&module.attrs) => definitions(content),
+        _ => Vec::new(),
+    }
+}
+
+fn expand_in_scope(
+    items: &mut Vec<syn::Item>,
+    scope: &mut Vec<MacroRules>,
+    depth: usize,
+) -> Result<Vec<usize>> {
+    let mut expanded = Vec::new();
+    let mut index = 0;
+    while index < items.len() {
+        match &mut items[index] {
+            syn::Item::Macro(item) => {
+                if let Some(rules) = MacroRules::parse(item) {
+                    scope.push(rules);
+                } else if let Some(expansion) = expand_invocation(item, scope, depth)? {
+                    scope.extend(definitions(&expansion));
+                    let count = expansion.len();
+                    items.splice(index..=index, expansion);
+                    expanded.extend(index..index + count);
+                    index += count;
+                    continue;
+                }
+            }
+            syn::Item::Mod(module) => {
+                if let Some((_, content)) = &mut module.content {
+                    // Macros of a module are visible after it only with `#[macro_use]`
+                    let mut inner = scope.clone();
+                    expand_in_scope(content, &mut inner, depth)?;
+                    if has_macro_use(&module.attrs) {
+                        *scope = inner;
+                    }
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    Ok(expanded)
+}
+
+/// Items `item` expands to, if they declare modules
+fn expand_invocation(
+    item: &syn::ItemMacro,
+    scope: &[MacroRules],
+    depth: usize,
+) -> Result<Option<Vec<syn::Item>>> {
+    let segments = &item.mac.path.segments;
+    let name = match segments.len() {
+        1 => &segments[0].ident,
+        2 if segments[0].ident == "crate" => &segments[1].ident,
+        _ => return Ok(None),
+    };
+    let Some(rules) = scope.iter().rev().find(|rules| name == &rules.name) else {
+        return Ok(None);
+    };
+    if depth == MAX_DEPTH {
+        return Err(BundlerError::Parsing {
+            message: format!("Recursion limit reached while expanding `{name}!`"),
+            file_path: None,
+        });
+    }
+    let Some(mut items) = rules
+        .expand(item.mac.tokens.clone())
+        .and_then(|tokens| syn::parse2::<syn::File>(tokens).ok())
+        .map(|file| file.items)
+    else {
+        return Ok(None);
+    };
+
+    expand_in_scope(&mut items, &mut scope.to_vec(), depth + 1)?;
+    if !items.iter().any(|item| matches!(item, syn::Item::Mod(_))) {
+        return Ok(None);
+    }
+    // Attributes of the invocation, e.g. `#[cfg(...)]`, apply to what it expands to
+    for expanded in &mut items {
+        if let Some(attrs) = super::attributes::item_attrs_mut(expanded) {
+            attrs.splice(0..0, item.attrs.iter().cloned());
+        }
+    }
+    Ok(Some(items))
+}
+
+fn has_macro_use(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| attr.path().is_ident("macro_use"))
+}
+
+fn is_punct(tree: &TokenTree, ch: char) -> bool {
+    matches!(tree, TokenTree::Punct(punct) if punct.as_char() == ch)
+}
+
+fn same_token(a: &TokenTree, b: &TokenTree) -> bool {
+    match (a, b) {
+        (TokenTree::Punct(a), TokenTree::Punct(b)) => a.as_char() == b.as_char(),
+        (TokenTree::Ident(a), TokenTree::Ident(b)) => a == b,
+        (TokenTree::Literal(a), TokenTree::Literal(b)) => a.to_string() == b.to_string(),
+        _ => false,
+    }
+}
+
+fn parse_matcher(tokens: TokenStream) -> Option<Vec<Matcher>> {
+    let trees: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut matchers = Vec::new();
+    let mut index = 0;
+    while let Some(tree) = trees.get(index) {
+        index += 1;
+        match (tree, trees.get(index)) {
+            (TokenTree::Punct(dollar), Some(TokenTree::Ident(name))) if dollar.as_char() == '$' => {
+                match trees.get(index + 1..index + 3) {
+                    Some([TokenTree::Punct(colon), TokenTree::Ident(kind)])
+                        if colon.as_char() == ':' =>
+                    {
+                        matchers.push(Matcher::Fragment {
+                            name: name.to_string(),
+                            kind: kind.to_string(),
+                        });
+                        index += 3;
+                    }
+                    _ => return None,
+                }
+            }
+            (TokenTree::Punct(dollar), Some(TokenTree::Group(body)))
+                if dollar.as_char() == '$' && body.delimiter() == Delimiter::Parenthesis =>
+            {
+                index += 1;
+                let (separator, operator) = repetition_operator(&trees, &mut index)?;
+                matchers.push(Matcher::Repeat {
+                    body: parse_matcher(body.stream())?,
+                    separator,
+                    operator,
+                });
+            }
+            (TokenTree::Group(group), _) => {
+                matchers.push(Matcher::Group(
+                    group.delimiter(),
+                    parse_matcher(group.stream())?,
+                ));
+            }
+            (tree, _) => matchers.push(Matcher::Token(tree.clone())),
+        }
+    }
+    Some(matchers)
+}
+
+/// Read the optional separator and the `*`, `+` or `?` operator following `$(...)`
+fn repetition_operator(
+    trees: &[TokenTree],
+    index: &mut usize,
+) -> Option<(Option<TokenTree>, char)> {
+    let operator = |tree: Option<&TokenTree>| match tree {
+        Some(TokenTree::Punct(punct)) if matches!(punct.as_char(), '*' | '+' | '?') => {
+            Some(punct.as_char())
+        }
+        _ => None,
+    };
+    if let Some(operator) = operator(trees.get(*index)) {
+        *index += 1;
+        return Some((None, operator));
+    }
+    let separator = trees.get(*index)?.clone();
+    let operator = operator(trees.get(*index + 1))?;
+    *index += 2;
+    Some((Some(separator), operator))
+}
+
+/// Names of the fragments of `matchers`, at any depth
+fn fragment_names(matchers: &[Matcher], names: &mut Vec<String>) {
+    for matcher in matchers {
+        match matcher {
+            Matcher::Fragment { name, .. } => names.push(name.clone()),
+            Matcher::Group(_, body) | Matcher::Repeat { body, .. } => fragment_names(body, names),
+            Matcher::Token(_) => {}
+        }
+    }
+}
+
+fn match_all(matchers: &[Matcher], input: &[TokenTree], bindings: &mut Bindings) -> Option<()> {
+    match_prefix(matchers, input, bindings)?
+        .is_empty()
+        .then_some(())
+}
+
+/// Match `matchers` against the start of `input`, returning the tokens left over
+fn match_prefix<'t>(
+    matchers: &[Matcher],
+    mut input: &'t [TokenTree],
+    bindings: &mut Bindings,
+) -> Option<&'t [TokenTree]> {
+    for matcher in matchers {
+        input = match matcher {
+            Matcher::Token(expected) => {
+                let (first, rest) = input.split_first()?;
+                if !same_token(first, expected) {
+                    return None;
+                }
+                rest
+            }
+            Matcher::Group(delimiter, body) => match input.split_first()? {
+                (TokenTree::Group(group), rest) if group.delimiter() == *delimiter => {
+                    let trees: Vec<TokenTree> = group.stream().into_iter().collect();
+                    match_all(body, &trees, bindings)?;
+                    rest
+                }
+                _ => return None,
+            },
+            Matcher::Fragment { name, kind } => {
+                let (fragment, rest) = parse_fragment(kind, input)?;
+                bindings.insert(name.clone(), Binding::Fragment(fragment));
+                rest
+            }
+            Matcher::Repeat {
+                body,
+                separator,
+                operator,
+            } => {
+                let mut iterations: Vec<Bindings> = Vec::new();
+                let mut rest = input;
+                loop {
+                    let next = match (iterations.is_empty(), separator) {
+                        (false, Some(separator)) => match rest.split_first() {
+                            Some((first, after)) if same_token(first, separator) => after,
+                            _ => break,
+                        },
+                        _ => rest,
+                    };
+                    let mut iteration = Bindings::new();
+                    match match_prefix(body, next, &mut iteration) {
+                        // Stop on empty matches, which would repeat forever
+                        Some(after) if after.len() < rest.len() => {
+                            iterations.push(iteration);
+                            rest = after;
+                        }
+                        _ => break,
+                    }
+                    if *operator == '?' {
+                        break;
+                    }
+                }
+                if *operator == '+' && iterations.is_empty() {
+                    return None;
+                }
+                let mut names = Vec::new();
+                fragment_names(body, &mut names);
+                for name in names {
+                    let values = iterations
+                        .iter_mut()
+                        .map(|iteration| {
+                            iteration
+                                .remove(&name)
+                                .unwrap_or(Binding::Repeated(Vec::new()))
+                        })
+                        .collect();
+                    bindings.insert(name, Binding::Repeated(values));
+                }
+                rest
+            }
+        };
+    }
+    Some(input)
+}
+
+/// Match a fragment of the given kind (`ident`, `expr`, ...) at the start of
+/// `input`, returning its tokens and the tokens left over
+fn parse_fragment<'t>(
+    kind: &str,
+    input: &'t [TokenTree],
+) -> Option<(TokenStream, &'t [TokenTree])> {
+    let take = |count: usize| Some((input[..count].iter().cloned().collect(), &input[count..]));
+    match (kind, input) {
+        ("tt" | "ident", [TokenTree::Ident(_), ..])
+        | ("tt", [_, ..])
+        | ("literal", [TokenTree::Literal(_), ..]) => take(1),
+        ("literal", [TokenTree::Punct(minus), TokenTree::Literal(_), ..])
+            if minus.as_char() == '-' =>
+        {
+            take(2)
+        }
+        ("literal", [TokenTree::Ident(boolean), ..]) if boolean == "true" || boolean == "false" => {
+            take(1)
+        }
+        ("lifetime", [TokenTree::Punct(quote), TokenTree::Ident(_), ..])
+            if quote.as_char() == '\'' =>
+        {
+            take(2)
+        }
+        ("expr", _) => {
+            let (tokens, rest, expr) = parse_prefix(input, syn::Expr::parse)?;
+            Some((parenthesize(tokens, &expr), rest))
+        }
+        ("ty", _) => parse_prefix(input, syn::Type::parse).map(fragment),
+        ("path", _) => parse_prefix(input, syn::Path::parse).map(fragment),
+        ("pat", _) => parse_prefix(input, syn::Pat::parse_multi_with_leading_vert).map(fragment),
+        ("pat_param", _) => parse_prefix(input, syn::Pat::parse_single).map(fragment),
+        ("stmt", _) => parse_prefix(input, syn::Stmt::parse).map(fragment),
+        ("block", _) => parse_prefix(input, syn::Block::parse).map(fragment),
+        ("item", _) => parse_prefix(input, syn::Item::parse).map(fragment),
+        ("meta", _) => parse_prefix(input, syn::Meta::parse).map(fragment),
+        ("vis", _) => parse_prefix(input, syn::Visibility::parse).map(fragment),
+        _ => None,
+    }
+}
+
+fn fragment<T>((tokens, rest, _): (TokenStream, &[TokenTree], T)) -> (TokenStream, &[TokenTree]) {
+    (tokens, rest)
+}
+
+/// Parse a syntax node from the start of `input`, returning its tokens, the tokens
+/// left over and the node
+fn parse_prefix<T>(
+    input: &[TokenTree],
+    parse: fn(ParseStream) -> syn::Result<T>,
+) -> Option<(TokenStream, &[TokenTree], T)> {
+    let parser = |stream: ParseStream| Ok((parse(stream)?, stream.parse::<TokenStream>()?));
+    let (node, rest) = parser.parse2(input.iter().cloned().collect()).ok()?;
+    let consumed = input.len() - rest.into_iter().count();
+    Some((
+        input[..consumed].iter().cloned().collect(),
+        &input[consumed..],
+        node,
+    ))
+}
+
+/// Wrap an `expr` fragment in parentheses unless it binds tighter than any
+/// operator, as the compiler keeps it a single expression when substituting it
+fn parenthesize(tokens: TokenStream, expr: &syn::Expr) -> TokenStream {
+    let atomic = matches!(
+        expr,
+        syn::Expr::Array(_)
+            | syn::Expr::Block(_)
+            | syn::Expr::Call(_)
+            | syn::Expr::Field(_)
+            | syn::Expr::Index(_)
+            | syn::Expr::Lit(_)
+            | syn::Expr::Macro(_)
+            | syn::Expr::MethodCall(_)
+            | syn::Expr::Paren(_)
+            | syn::Expr::Path(_)
+            | syn::Expr::Repeat(_)
+            | syn::Expr::Struct(_)
+            | syn::Expr::Try(_)
+            | syn::Expr::Tuple(_)
+    );
+    if atomic {
+        tokens
+    } else {
+        TokenStream::from(TokenTree::Group(Group::new(Delimiter::Parenthesis, tokens)))
+    }
+}
+
+/// Names following `$` in `tokens`, at any depth
+fn variables(tokens: TokenStream, names: &mut Vec<String>) {
+    let trees: Vec<TokenTree> = tokens.into_iter().collect();
+    for (index, tree) in trees.iter().enumerate() {
+        match (tree, trees.get(index + 1)) {
+            (TokenTree::Punct(dollar), Some(TokenTree::Ident(name))) if dollar.as_char() == '$' => {
+                names.push(name.to_string());
+            }
+            (TokenTree::Group(group), _) => variables(group.stream(), names),
+            _ => {}
+        }
+    }
+}
+
+/// Substitute the fragments of `bindings` into `transcriber`
+///
+/// Unbound `$name`s and repetitions without repeated fragments, e.g. those of a
+/// nested `macro_rules!`, are copied as is.
+fn transcribe(transcriber: TokenStream, bindings: &Bindings) -> Option<TokenStream> {
+    let trees: Vec<TokenTree> = transcriber.into_iter().collect();
+    let mut output = TokenStream::new();
+    let mut index = 0;
+    while let Some(tree) = trees.get(index) {
+        index += 1;
+        match (tree, trees.get(index)) {
+            (TokenTree::Punct(dollar), Some(TokenTree::Ident(name))) if dollar.as_char() == '$' => {
+                match bindings.get(&name.to_string()) {
+                    _ if name == "crate" => {
+                        output.extend([TokenTree::Ident(Ident::new("crate", name.span()))]);
+                    }
+                    Some(Binding::Fragment(fragment)) => output.extend(fragment.clone()),
+                    // Repeated fragment outside of its repetition
+                    Some(Binding::Repeated(_)) => return None,
+                    None => output.extend([tree.clone(), TokenTree::Ident(name.clone())]),
+                }
+                index += 1;
+            }
+            (TokenTree::Punct(dollar), Some(TokenTree::Group(body)))
+                if dollar.as_char() == '$'
+                    && body.delimiter() == Delimiter::Parenthesis
+                    && repeats(body, bindings) =>
+            {
+                index += 1;
+                let (separator, _) = repetition_operator(&trees, &mut index)?;
+                let mut names = Vec::new();
+                variables(body.stream(), &mut names);
+                let repeated: Vec<(&String, &Vec<Binding>)> = names
+                    .iter()
+                    .filter_map(|name| match bindings.get(name) {
+                        Some(Binding::Repeated(values)) => Some((name, values)),
+                        _ => None,
+                    })
+                    .collect();
+                let count = repeated[0].1.len();
+                if repeated.iter().any(|(_, values)| values.len() != count) {
+                    return None;
+                }
+                for iteration in 0..count {
+                    if iteration > 0 {
+                        output.extend(separator.clone());
+                    }
+                    let mut scope = bindings.clone();
+                    for (name, values) in &repeated {
+                        scope.insert((*name).clone(), values[iteration].clone());
+                    }
+                    output.extend(transcribe(body.stream(), &scope)?);
+                }
+            }
+            (TokenTree::Group(group), _) => {
+                let mut substituted =
+                    Group::new(group.delimiter(), transcribe(group.stream(), bindings)?);
+                substituted.set_span(group.span());
+                output.extend([TokenTree::Group(substituted)]);
+            }
+            _ => output.extend([tree.clone()]),
+        }
+    }
+    Some(output)
+}
+
+/// Whether the repetition `$(body)` uses a repeated fragment
+fn repeats(body: &Group, bindings: &Bindings) -> bool {
+    let mut names = Vec::new();
+    variables(body.stream(), &mut names);
+    names
+        .iter()
+        .any(|name| matches!(bindings.get(name), Some(Binding::Repeated(_))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+
+    #[test]
+    fn test_expands_invocations_declaring_modules() {
+        let mut file: syn::File = syn::parse_quote! {
+            macro_rules! decl_strategies {
+                ($($name:ident),* $(,)?) => { $(pub mod $name;)* };
+            }
+            macro_rules! nested {
+                ($first:ident $(, $rest:ident)*) => {
+                    decl_strategies!($first);
+                    pub const COUNT: usize = 1 + [$(stringify!($rest)),*].len();
+                };
+            }
+            macro_rules! constant {
+                ($name:ident = $value:expr) => { const $name: u32 = $value * 2; };
+            }
+            decl_strategies! { alpha, beta, }
+            #[cfg(feature = "gamma")]
+            nested!(gamma, delta);
+            constant!(LIMIT = 1 + 2);
+            fn main() {}
+        };
+        let expanded = expand(&mut file.items, &[]).unwrap();
+        assert_eq!(expanded, [3, 4, 5, 6]);
+
+        let code = file.to_token_stream().to_string();
+        assert!(code.contains("pub mod alpha ; pub mod beta ;"));
+        assert!(code.contains(
+            "# [cfg (feature = \"gamma\")] pub mod gamma ; # [cfg (feature = \"gamma\")] pub const COUNT"
+        ));
+        // Invocations that declare no module are left to the compiler
+        assert!(code.contains("constant ! (LIMIT = 1 + 2) ;"));
+
+        let definitions = definitions(&file.items);
+        assert_eq!(definitions.len(), 3);
+        let constant = definitions[2]
+            .expand(syn::parse_quote!(LIMIT = 1 + 2))
+            .unwrap();
+        assert_eq!(constant.to_string(), "const LIMIT : u32 = (1 + 2) * 2 ;");
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use proc_macro2::{Group, Ident, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::visit_mut::{self, VisitMut};
+
+use super::shorten::{collect_idents, short_names};
+use super::verbatim;
+
+/// Names never renamed: `fn main`, and the fields of `std::ops::Range` that a
+/// field access could not be told apart from
+const RESERVED: [&str; 3] = ["main", "start", "end"];
+
+/// Derives that do not depend on the names of the fields
+const NAME_AGNOSTIC_DERIVES: [&str; 8] = [
+    "Clone",
+    "Copy",
+    "PartialEq",
+    "Eq",
+    "PartialOrd",
+    "Ord",
+    "Hash",
+    "Default",
+];
+
+/// Rename the private free functions and private struct fields of a file to the
+/// shortest names that are not used anywhere in it
+///
+/// Names are renamed everywhere at once, so a name is only renamed when nothing
+/// else than private functions, private fields and local variables is called by
+/// it: no item, method, import, macro, public field or field of a struct deriving
+/// `Debug` or another trait that prints or reads field names, and it must not be
+/// used where none of its definitions is in scope (e.g. a function of the
+/// prelude) or as a path segment outside of the bundle (`Vec::new`). Names in
+/// attributes, in `{name}` format arguments and after `$` in macros are kept.
+pub(super) fn mangle_private_items(file: &mut syn::File) {
+    let mut analysis = Analysis::default();
+    analysis.visit_file_mut(file);
+    let renamable = analysis.renamable();
+    if renamable.is_empty() {
+        return;
+    }
+
+    let mut counts = BTreeMap::new();
+    count_idents(file.to_token_stream(), &mut counts);
+    let mut candidates: Vec<(usize, String)> = renamable
+        .into_iter()
+        .map(|name| (counts.get(&name).copied().unwrap_or_default(), name))
+        .collect();
+    candidates.sort_by(|(a_uses, a), (b_uses, b)| b_uses.cmp(a_uses).then_with(|| a.cmp(b)));
+
+    let mut taken = BTreeSet::new();
+    collect_idents(file.to_token_stream(), &mut taken);
+    let mut names = short_names().filter(|name| !taken.contains(name));
+    let renames: BTreeMap<String, String> = candidates
+        .into_iter()
+        .map_while(|(_, name)| Some((name, names.next()?)))
+        .filter(|(name, short)| short.len() < name.len())
+        .collect();
+    Renamer { renames: &renames }.visit_file_mut(file);
+}
+
+/// Where the names of a file are defined and used
+#[derive(Default)]
+struct Analysis {
+    /// Path of the module being visited
+    module: Vec<String>,
+    /// Modules of the file, whose names may prefix paths to private functions
+    modules: BTreeSet<String>,
+    /// Private free functions, with the modules defining them
+    functions: BTreeMap<String, Vec<Vec<String>>>,
+    /// Private fields of structs without name-dependent derives
+    fields: BTreeSet<String>,
+    /// Local variables, with the modules binding them
+    locals: BTreeSet<(String, Vec<String>)>,
+    /// Single-segment value paths, with the modules using them
+    uses: Vec<(String, Vec<String>)>,
+    /// Fields accessed or initialized, of any type
+    accessed: BTreeSet<String>,
+    /// Names with another meaning somewhere
+    foreign: BTreeSet<String>,
+}
+
+impl Analysis {
+    /// Names only meaning private functions, private fields and local variables
+    fn renamable(&self) -> BTreeSet<String> {
+        let in_scope = |name: &String, module: &Vec<String>| {
+            self.locals.contains(&(name.clone(), module.clone()))
+                || self.functions.get(name).is_some_and(|modules| {
+                    modules.iter().any(|defining| module.starts_with(defining))
+                })
+        };
+        let mut names: BTreeSet<String> = self
+            .functions
+            .keys()
+            .chain(&self.fields)
+            .filter(|name| !self.foreign.contains(*name) && !RESERVED.contains(&name.as_str()))
+            .filter(|name| !self.accessed.contains(*name) || self.fields.contains(*name))
+            .cloned()
+            .collect();
+        for (name, module) in &self.uses {
+            if !in_scope(name, module) {
+                names.remove(name);
+            }
+        }
+        names
+    }
+
+    fn foreign(&mut self, ident: &syn::Ident) {
+        self.foreign.insert(ident.to_string());
+    }
+
+    fn visit_tokens(&mut self, tokens: TokenStream) {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        let punct = |index: Option<usize>| match index.and_then(|index| tokens.get(index)) {
+            Some(TokenTree::Punct(punct)) => Some((punct.as_char(), punct.spacing())),
+            _ => None,
+        };
+        for (index, token) in tokens.iter().enumerate() {
+            match token {
+                TokenTree::Ident(ident) => {
+                    let name = ident.to_string();
+                    let before = punct(index.checked_sub(1));
+                    let after = punct(Some(index + 1));
+                    let call = matches!(tokens.get(index + 1), Some(TokenTree::Group(group)) if group.delimiter() == proc_macro2::Delimiter::Parenthesis);
+                    match (before, after) {
+                        (Some(('$' | ':' | '\'', _)), _)
+                        | (_, Some((':', Spacing::Joint) | ('!', _))) => {
+                            self.foreign.insert(name);
+                        }
+                        (Some(('.', _)), _) if call => {
+                            self.foreign.insert(name);
+                        }
+                        (Some(('.', _)), _) => {
+                            self.accessed.insert(name);
+                        }
+                        _ => self.uses.push((name, self.module.clone())),
+                    }
+                }
+                // Implicit format arguments, e.g. `{x}` or `{x:>4}`
+                TokenTree::Literal(literal) => {
+                    let text = literal.to_string();
+                    let mut rest = text.as_str();
+                    while let Some(start) = rest.find('{') {
+                        rest = &rest[start + 1..];
+                        let end = rest
+                            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                            .unwrap_or(rest.len());
+                        self.foreign.insert(rest[..end].to_string());
+                    }
+                }
+                TokenTree::Group(group) => self.visit_tokens(group.stream()),
+                TokenTree::Punct(_) => {}
+            }
+        }
+    }
+}
+
+impl VisitMut for Analysis {
+    fn visit_item_mut(&mut self, item: &mut syn::Item) {
+        match item {
+            syn::Item::Fn(function)
+                if matches!(function.vis, syn::Visibility::Inherited)
+                    && !function.attrs.iter().any(|attr| {
+                        attr.path().is_ident("no_mangle") || attr.path().is_ident("export_name")
+                    }) =>
+            {
+                self.functions
+                    .entry(function.sig.ident.to_string())
+                    .or_default()
+                    .push(self.module.clone());
+            }
+            syn::Item::Struct(item) => {
+                self.foreign(&item.ident);
+                let private = name_agnostic(&item.attrs);
+                for field in &item.fields {
+                    if let Some(ident) = &field.ident {
+                        if private && matches!(field.vis, syn::Visibility::Inherited) {
+                            self.fields.insert(ident.to_string());
+                        } else {
+                            self.foreign(ident);
+                        }
+                    }
+                }
+            }
+            syn::Item::Mod(module) => {
+                self.foreign(&module.ident);
+                self.modules.insert(module.ident.to_string());
+                self.module.push(module.ident.to_string());
+                visit_mut::visit_item_mut(self, item);
+                self.module.pop();
+                return;
+            }
+            syn::Item::Union(item) => {
+                self.foreign(&item.ident);
+                for field in &item.fields.named {
+                    self.foreign
+                        .extend(field.ident.as_ref().map(ToString::to_string));
+                }
+            }
+            syn::Item::Fn(syn::ItemFn {
+                sig: syn::Signature { ident, .. },
+                ..
+            })
+            | syn::Item::Const(syn::ItemConst { ident, .. })
+            | syn::Item::Enum(syn::ItemEnum { ident, .. })
+            | syn::Item::ExternCrate(syn::ItemExternCrate { ident, .. })
+            | syn::Item::Static(syn::ItemStatic { ident, .. })
+            | syn::Item::Trait(syn::ItemTrait { ident, .. })
+            | syn::Item::TraitAlias(syn::ItemTraitAlias { ident, .. })
+            | syn::Item::Type(syn::ItemType { ident, .. })
+            | syn::Item::Macro(syn::ItemMacro {
+                ident: Some(ident), ..
+            }) => {
+                self.foreign.insert(ident.to_string());
+            }
+            _ => {}
+        }
+        visit_mut::visit_item_mut(self, item);
+    }
+
+    fn visit_variant_mut(&mut self, variant: &mut syn::Variant) {
+        self.foreign(&variant.ident);
+        for field in &variant.fields {
+            self.foreign
+                .extend(field.ident.as_ref().map(ToString::to_string));
+        }
+        visit_mut::visit_variant_mut(self, variant);
+    }
+
+    fn visit_impl_item_mut(&mut self, item: &mut syn::ImplItem) {
+        match item {
+            syn::ImplItem::Fn(item) => self.foreign(&item.sig.ident),
+            syn::ImplItem::Const(item) => self.foreign(&item.ident),
+            syn::ImplItem::Type(item) => self.foreign(&item.ident),
+            _ => {}
+        }
+        visit_mut::visit_impl_item_mut(self, item);
+    }
+
+    fn visit_trait_item_mut(&mut self, item: &mut syn::TraitItem) {
+        match item {
+            syn::TraitItem::Fn(item) => self.foreign(&item.sig.ident),
+            syn::TraitItem::Const(item) => self.foreign(&item.ident),
+            syn::TraitItem::Type(item) => self.foreign(&item.ident),
+            _ => {}
+        }
+        visit_mut::visit_trait_item_mut(self, item);
+    }
+
+    fn visit_foreign_item_mut(&mut self, item: &mut syn::ForeignItem) {
+        collect_idents(item.to_token_stream(), &mut self.foreign);
+    }
+
+    fn visit_generic_param_mut(&mut self, param: &mut syn::GenericParam) {
+        match param {
+            syn::GenericParam::Type(param) => self.foreign(&param.ident),
+            syn::GenericParam::Const(param) => self.foreign(&param.ident),
+            syn::GenericParam::Lifetime(_) => {}
+        }
+        visit_mut::visit_generic_param_mut(self, param);
+    }
+
+    fn visit_use_tree_mut(&mut self, tree: &mut syn::UseTree) {
+        collect_idents(tree.to_token_stream(), &mut self.foreign);
+    }
+
+    fn visit_attribute_mut(&mut self, attr: &mut syn::Attribute) {
+        collect_idents(attr.to_token_stream(), &mut self.foreign);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        self.visit_path_mut(&mut mac.path);
+        if let Some(segment) = mac.path.segments.last() {
+            self.foreign(&segment.ident);
+        }
+        if mac.path.is_ident("stringify") || verbatim::is_placeholder(mac) {
+            collect_idents(mac.tokens.clone(), &mut self.foreign);
+        } else {
+            self.visit_tokens(mac.tokens.clone());
+        }
+    }
+
+    fn visit_path_mut(&mut self, path: &mut syn::Path) {
+        if path.segments.len() > 1 || path.leading_colon.is_some() {
+            // Only `self::f`, `super::f` or `crate::module::f` may reach a private function
+            let mut local = path.leading_colon.is_none();
+            for (index, segment) in path.segments.iter().enumerate() {
+                let name = segment.ident.to_string();
+                if index == 0 || !local {
+                    self.foreign.insert(name.clone());
+                }
+                local &= matches!(name.as_str(), "crate" | "self" | "super")
+                    || self.modules.contains(&name);
+            }
+        }
+        visit_mut::visit_path_mut(self, path);
+    }
+
+    fn visit_type_path_mut(&mut self, path: &mut syn::TypePath) {
+        for segment in &path.path.segments {
+            self.foreign(&segment.ident);
+        }
+        visit_mut::visit_type_path_mut(self, path);
+    }
+
+    fn visit_expr_path_mut(&mut self, path: &mut syn::ExprPath) {
+        // Generic arguments included, e.g. `parse::<u32>()`
+        if path.qself.is_none()
+            && path.path.leading_colon.is_none()
+            && path.path.segments.len() == 1
+        {
+            let name = path.path.segments[0].ident.to_string();
+            self.uses.push((name, self.module.clone()));
+        }
+        visit_mut::visit_expr_path_mut(self, path);
+    }
+
+    fn visit_pat_ident_mut(&mut self, pat: &mut syn::PatIdent) {
+        self.locals
+            .insert((pat.ident.to_string(), self.module.clone()));
+        visit_mut::visit_pat_ident_mut(self, pat);
+    }
+
+    fn visit_expr_method_call_mut(&mut self, call: &mut syn::ExprMethodCall) {
+        self.foreign(&call.method);
+        visit_mut::visit_expr_method_call_mut(self, call);
+    }
+
+    fn visit_member_mut(&mut self, member: &mut syn::Member) {
+        if let syn::Member::Named(ident) = member {
+            self.accessed.insert(ident.to_string());
+        }
+    }
+}
+
+/// Whether the derives of a struct do not depend on the names of its fields
+fn name_agnostic(attrs: &[syn::Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .all(|attr| {
+            let mut agnostic = true;
+            let _ = attr.parse_nested_meta(|meta| {
+                agnostic &= meta.path.get_ident().is_some_and(|ident| {
+                    NAME_AGNOSTIC_DERIVES.contains(&ident.to_string().as_str())
+                });
+                Ok(())
+            });
+            agnostic
+        })
+}
+
+struct Renamer<'a> {
+    renames: &'a BTreeMap<String, String>,
+}
+
+impl Renamer<'_> {
+    fn rename_tokens(&self, tokens: TokenStream) -> TokenStream {
+        tokens
+            .into_iter()
+            .map(|token| match token {
+                TokenTree::Ident(ident) => self
+                    .renames
+                    .get(&ident.to_string())
+                    .map_or(TokenTree::Ident(ident.clone()), |short| {
+                        TokenTree::Ident(Ident::new(short, ident.span()))
+                    }),
+                TokenTree::Group(group) => {
+                    let mut renamed =
+                        Group::new(group.delimiter(), self.rename_tokens(group.stream()));
+                    renamed.set_span(group.span());
+                    TokenTree::Group(renamed)
+                }
+                token => token,
+            })
+            .collect()
+    }
+}
+
+impl VisitMut for Renamer<'_> {
+    fn visit_ident_mut(&mut self, ident: &mut syn::Ident) {
+        if let Some(short) = self.renames.get(&ident.to_string()) {
+            *ident = syn::Ident::new(short, ident.span());
+        }
+    }
+
+    fn visit_attribute_mut(&mut self, _attr: &mut syn::Attribute) {}
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        mac.tokens = self.rename_tokens(mac.tokens.clone());
+    }
+}
+
+fn count_idents(tokens: TokenStream, counts: &mut BTreeMap<String, usize>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => *counts.entry(ident.to_string()).or_default() += 1,
+            TokenTree::Group(group) => count_idents(group.stream(), counts),
+            TokenTree::Punct(_) | TokenTree::Literal(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mangled(mut file: syn::File) -> String {
+        mangle_private_items(&mut file);
+        prettyplease::unparse(&file)
+    }
+
+    #[test]
+    fn test_private_functions_and_fields_renamed() {
+        let file: syn::File = syn::parse_quote! {
+            #[derive(Clone, Copy)]
+            struct Unit { health: i32, shield: i32 }
+            mod combat {
+                fn damage_of(unit: &super::Unit) -> i32 {
+                    unit.health + unit.shield
+                }
+                pub fn strongest(units: &[super::Unit]) -> i32 {
+                    units.iter().map(damage_of).max().unwrap_or(0)
+                }
+            }
+            fn main() {
+                let health = 3;
+                let unit = Unit { health, shield: 2 };
+                println!("{}", combat::strongest(&[unit]));
+            }
+        };
+        assert_eq!(
+            mangled(file),
+            "#[derive(Clone, Copy)]\nstruct Unit {\n    a: i32,\n    b: i32,\n}\nmod combat {\n    fn c(unit: &super::Unit) -> i32 {\n        unit.a + unit.b\n    }\n    pub fn strongest(units: &[super::Unit]) -> i32 {\n        units.iter().map(c).max().unwrap_or(0)\n    }\n}\nfn main() {\n    let a = 3;\n    let unit = Unit { a, b: 2 };\n    println!(\"{}\", combat::strongest(& [unit]));\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_names_with_other_meanings_kept() {
+        let file: syn::File = syn::parse_quote! {
+            #[derive(Debug)]
+            struct Report { score: u32 }
+            struct Span { start: usize, width: usize }
+            fn max(a: u32, b: u32) -> u32 { if a > b { a } else { b } }
+            mod ai {
+                use std::cmp::max;
+                pub fn best(a: u32) -> u32 { max(a, 1) }
+            }
+            fn main() {
+                let width = 2;
+                let span = Span { start: 0, width };
+                let report = Report { score: max(ai::best(1), 2) };
+                println!("{report:?} {width} {}", span.start + span.width);
+            }
+        };
+        let code = mangled(file);
+        assert!(code.contains("score: u32"));
+        assert!(code.contains("start: usize"));
+        assert!(code.contains("fn max("));
+        assert!(code.contains("width: usize"));
+    }
+}
@@ -0,0 +1,433 @@
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+
+use super::{DocsMode, TransformConfig};
+use crate::error::{BundlerError, Result};
+
+/// Two-character punctuation prefixes that the Rust lexer would glue together
+/// (or read as the start of a comment) if emitted without separating whitespace.
+///
+/// `>` `>` is missing on purpose: two separate `>` only occur as closing angle
+/// brackets (`Vec<Vec<i32>>`), where the parser splits `>>` again. `<` `<` is not,
+/// since `a < <T>::MAX` would become a shift.
+const GLUING_PAIRS: &[(char, char)] = &[
+    ('&', '&'),
+    ('&', '='),
+    ('|', '|'),
+    ('|', '='),
+    ('<', '<'),
+    ('<', '='),
+    ('<', '-'),
+    ('>', '='),
+    ('+', '='),
+    ('-', '='),
+    ('-', '>'),
+    ('*', '='),
+    ('/', '='),
+    ('/', '/'),
+    ('/', '*'),
+    ('%', '='),
+    ('^', '='),
+    ('=', '='),
+    ('=', '>'),
+    ('!', '='),
+    (':', ':'),
+    ('.', '.'),
+    // Half-open range patterns: `0.. =>` must not become `0..=>`
+    ('.', '='),
+];
+
+/// Token-based minifier that re-emits a syntax tree with minimal whitespace
+///
+/// Unlike textual replacements, the minifier works on the token stream of the
+/// parsed file, so string, char and byte literals as well as lifetimes are
+/// always emitted verbatim and the output re-parses to the same tokens.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Minifier {
+    aggressive: bool,
+}
+
+/// Classification of the last emitted token, used to decide whether a
+/// separating space is required before the next one
+#[derive(Debug, Clone, Copy)]
+enum Last {
+    Start,
+    Word,
+    Literal { ends_with_dot: bool },
+    Punct { ch: char, joint: bool },
+    Open,
+    Close,
+}
+
+impl Minifier {
+    /// Create a minifier emitting every token on a single line, separated by spaces
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { aggressive: false }
+    }
+
+    /// Create a minifier that only emits whitespace where the lexer requires it
+    #[must_use]
+    pub const fn aggressive() -> Self {
+        Self { aggressive: true }
+    }
+
+    /// Minify a parsed file
+    #[must_use]
+    pub fn minify_file(self, file: &syn::File) -> String {
+        self.minify_tokens(file.to_token_stream())
+    }
+
+    /// Minify a parsed file and check that the output re-parses to the same tokens
+    ///
+    /// # Errors
+    /// Returns `BundlerError::MinifyVerification` if the output does not round-trip
+    pub fn minify_file_verified(self, file: &syn::File) -> Result<String> {
+        let minified = self.minify_file(file);
+        Self::verify(file, &minified)?;
+        Ok(minified)
+    }
+
+    /// Check that `minified` parses to a file with the same token stream as `file`
+    ///
+    /// Catches whitespace-gluing bugs, where two tokens emitted without a space lex
+    /// as a different token (e.g. `< -` becoming `<-`).
+    ///
+    /// # Errors
+    /// Returns `BundlerError::MinifyVerification` describing the first difference
+    pub fn verify(file: &syn::File, minified: &str) -> Result<()> {
+        let reparsed = syn::parse_file(minified).map_err(|e| BundlerError::MinifyVerification {
+            message: format!("output does not parse: {e}"),
+        })?;
+
+        let expected = flatten(file.to_token_stream());
+        let actual = flatten(reparsed.to_token_stream());
+        let Some(index) = (0..expected.len().max(actual.len()))
+            .find(|&index| expected.get(index) != actual.get(index))
+        else {
+            return Ok(());
+        };
+
+        let context = |tokens: &[String]| {
+            let start = index.saturating_sub(4);
+            let end = (index + 4).min(tokens.len());
+            tokens
+                .get(start..end)
+                .map_or_else(String::new, |window| window.join(" "))
+        };
+        Err(BundlerError::MinifyVerification {
+            message: format!(
+                "token {} differs: expected `{}`, found `{}`",
+                index + 1,
+                context(&expected),
+                context(&actual)
+            ),
+        })
+    }
+
+    /// Minify an arbitrary token stream
+    #[must_use]
+    pub fn minify_tokens(self, tokens: TokenStream) -> String {
+        let mut out = String::new();
+        let mut last = Last::Start;
+        self.emit_stream(tokens, &mut out, &mut last);
+        out
+    }
+
+    fn emit_stream(self, tokens: TokenStream, out: &mut String, last: &mut Last) {
+        for tree in tokens {
+            match tree {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => {
+                            self.emit_stream(group.stream(), out, last);
+                            continue;
+                        }
+                    };
+                    self.separate(out, *last, Last::Open);
+                    out.push_str(open);
+                    *last = Last::Open;
+                    self.emit_stream(group.stream(), out, last);
+                    self.separate(out, *last, Last::Close);
+                    out.push_str(close);
+                    *last = Last::Close;
+                }
+                TokenTree::Ident(ident) => {
+                    self.separate(out, *last, Last::Word);
+                    out.push_str(&ident.to_string());
+                    *last = Last::Word;
+                }
+                TokenTree::Literal(literal) => {
+                    let text = literal.to_string();
+                    let next = Last::Literal {
+                        ends_with_dot: text.ends_with('.'),
+                    };
+                    // Negative literals built by passes (e.g. `quote!` of a `-1i32`)
+                    // start with a minus sign, which `<` must not be glued to
+                    let first = if text.starts_with('-') {
+                        Last::Punct {
+                            ch: '-',
+                            joint: true,
+                        }
+                    } else {
+                        next
+                    };
+                    self.separate(out, *last, first);
+                    out.push_str(&text);
+                    *last = next;
+                }
+                TokenTree::Punct(punct) => {
+                    let next = Last::Punct {
+                        ch: punct.as_char(),
+                        joint: punct.spacing() == Spacing::Joint,
+                    };
+                    self.separate(out, *last, next);
+                    out.push(punct.as_char());
+                    *last = next;
+                }
+            }
+        }
+    }
+
+    fn separate(self, out: &mut String, prev: Last, next: Last) {
+        let space = if self.aggressive {
+            Self::needs_space(prev, next)
+        } else {
+            !matches!(
+                (prev, next),
+                (
+                    Last::Start | Last::Open | Last::Punct { joint: true, .. },
+                    _
+                ) | (_, Last::Close)
+            )
+        };
+        if space {
+            out.push(' ');
+        }
+    }
+
+    /// Whether two adjacent tokens would lex differently without a space between them
+    fn needs_space(prev: Last, next: Last) -> bool {
+        match (prev, next) {
+            (Last::Word | Last::Literal { .. }, Last::Word | Last::Literal { .. })
+            // `r#x` is a raw identifier and, since Rust 2021, any other `ident#`,
+            // `ident'` or `ident"` is a reserved prefix
+            | (Last::Word, Last::Punct { ch: '\'' | '#', .. })
+            | (
+                Last::Literal {
+                    ends_with_dot: true,
+                },
+                Last::Punct { ch: '.', .. },
+            ) => true,
+            (
+                Last::Punct {
+                    ch: first,
+                    joint: false,
+                },
+                Last::Punct { ch: second, .. },
+            ) => GLUING_PAIRS.contains(&(first, second)),
+            _ => false,
+        }
+    }
+}
+
+/// Token texts in order, with group delimiters as their own entries and joint
+/// punctuation forming an operator merged (`&&` and `& &` differ, as they do to the
+/// parser; the spacing of other punctuation, e.g. in macro bodies, does not matter)
+fn flatten(tokens: TokenStream) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    let mut joint = false;
+    for tree in tokens {
+        let follows_joint = std::mem::take(&mut joint);
+        match tree {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                out.push(open.to_string());
+                out.extend(flatten(group.stream()));
+                out.push(close.to_string());
+            }
+            TokenTree::Punct(punct) => {
+                joint = punct.spacing() == Spacing::Joint;
+                match out.last_mut() {
+                    Some(last)
+                        if follows_joint
+                            && last.chars().next_back().is_some_and(|first| {
+                                (first, punct.as_char()) == ('>', '>')
+                                    || GLUING_PAIRS.contains(&(first, punct.as_char()))
+                            }) =>
+                    {
+                        last.push(punct.as_char());
+                    }
+                    _ => out.push(punct.as_char().to_string()),
+                }
+            }
+            other => out.push(other.to_string()),
+        }
+    }
+    out
+}
+
+/// A step of `--target-size`, which applies them in order until the bundle fits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinifyStage {
+    /// Remove doc comments
+    RemoveDocs,
+    /// Remove plain comments kept with `keep_comments`
+    RemoveComments,
+    /// Emit only the whitespace the lexer requires
+    Whitespace,
+    /// Rename local variables to short names
+    ShortenIdentifiers,
+}
+
+impl MinifyStage {
+    /// Every stage, from the least to the most intrusive
+    pub const ALL: [Self; 4] = [
+        Self::RemoveDocs,
+        Self::RemoveComments,
+        Self::Whitespace,
+        Self::ShortenIdentifiers,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::RemoveDocs => "doc removal",
+            Self::RemoveComments => "comment removal",
+            Self::Whitespace => "whitespace minification",
+            Self::ShortenIdentifiers => "identifier shortening",
+        }
+    }
+
+    /// Whether `config` already does what the stage does
+    #[must_use]
+    pub const fn is_applied(self, config: &TransformConfig) -> bool {
+        match self {
+            Self::RemoveDocs => matches!(config.effective_docs_mode(), DocsMode::Remove),
+            Self::RemoveComments => !config.keep_comments,
+            Self::Whitespace => config.aggressive_minify,
+            Self::ShortenIdentifiers => config.shorten_identifiers,
+        }
+    }
+
+    pub fn apply(self, config: &mut TransformConfig) {
+        match self {
+            Self::RemoveDocs => {
+                config.docs_mode = DocsMode::Remove;
+                #[allow(deprecated)]
+                {
+                    config.remove_docs = true;
+                }
+            }
+            Self::RemoveComments => config.keep_comments = false,
+            Self::Whitespace => {
+                config.minify = true;
+                config.aggressive_minify = true;
+            }
+            Self::ShortenIdentifiers => config.shorten_identifiers = true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(source: &str) -> String {
+        let file = syn::parse_file(source).unwrap();
+        let minified = Minifier::aggressive().minify_file(&file);
+        let reparsed = syn::parse_file(&minified).unwrap();
+        assert_eq!(
+            file.to_token_stream().to_string(),
+            reparsed.to_token_stream().to_string()
+        );
+        minified
+    }
+
+    #[test]
+    fn test_aggressive_removes_operator_spaces() {
+        let minified = roundtrip("fn main() { let x = 5 + 3; }");
+        assert_eq!(minified, "fn main(){let x=5+3;}");
+    }
+
+    #[test]
+    fn test_char_literals_and_lifetimes_are_preserved() {
+        let minified = roundtrip(
+            "fn f<'a>(s: &'a str) -> Vec<&'a str> { s.split(' ').filter(|p| *p != \" , \").collect() }",
+        );
+        assert!(minified.contains("split(' ')"));
+        assert!(minified.contains("\" , \""));
+        assert!(minified.contains("&'a str"));
+    }
+
+    #[test]
+    fn test_raw_strings_and_byte_literals_are_preserved() {
+        let minified = roundtrip(
+            r####"fn f() -> usize {
+                let a = r#"say "hi" // not a comment /* nor this */ {"#;
+                let b = r###"ends with "## but not yet"###;
+                let c = ['{', '"', '\'', '}'];
+                let d = (b"bytes \" // x", br#"raw "bytes" '"#, b'\'', b'{');
+                let e = (c"c str", cr#"raw "c""#, r"plain raw \ ");
+                a.len() + b.len() + c.len() + d.0.len() + e.2.len()
+            }"####,
+        );
+        assert!(minified.contains(r##"r#"say "hi" // not a comment /* nor this */ {"#"##));
+        assert!(minified.contains(r####"r###"ends with "## but not yet"###"####));
+        assert!(minified.contains(r#"['{','"','\'','}']"#));
+        assert!(minified.contains(r##"(b"bytes \" // x",br#"raw "bytes" '"#,b'\'',b'{')"##));
+        assert!(minified.contains("(c\"c str\",cr#\"raw \"c\"\"#,r\"plain raw \\ \")"));
+    }
+
+    #[test]
+    fn test_unary_operators_are_not_glued() {
+        let minified = roundtrip("fn f(a: i32, b: i32) -> bool { a / *&b < -b }");
+        assert!(minified.contains("a/ *&b"));
+        assert!(minified.contains("< -b"));
+    }
+
+    #[test]
+    fn test_negative_literals_are_not_glued() {
+        let bound = -1i32;
+        let tokens = quote::quote!(a < #bound && r#type >= 0);
+        assert_eq!(
+            Minifier::aggressive().minify_tokens(tokens),
+            "a< -1i32&&r#type>=0"
+        );
+    }
+
+    #[test]
+    fn test_verify_accepts_minified_output() {
+        let file =
+            syn::parse_file("fn f(a: i32, b: i32) -> bool { a < -b && a - -b > 0 }").unwrap();
+        let minified = Minifier::aggressive().minify_file_verified(&file).unwrap();
+        assert!(minified.contains("< -b"));
+    }
+
+    #[test]
+    fn test_verify_detects_glued_tokens() {
+        let file = syn::parse_file("fn f(a: &&i32) -> bool { a < -1 }").unwrap();
+        let result = Minifier::verify(&file, "fn f(a:&&i32)->bool{a<1}");
+        assert!(matches!(
+            result,
+            Err(BundlerError::MinifyVerification { message }) if message.contains("token")
+        ));
+        assert!(Minifier::verify(&file, "fn f(").is_err());
+    }
+
+    #[test]
+    fn test_basic_minify_is_single_line() {
+        let file = syn::parse_file("fn main() {\n    let x = 1;\n}\n").unwrap();
+        let minified = Minifier::new().minify_file(&file);
+        assert!(!minified.contains('\n'));
+        syn::parse_file(&minified).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+use crate::warnings::BundleWarning;
+
+/// Remove the modules among `items`, declared in the module at `path` (empty for
+/// the bundle root), that `exclude` or `only` leave out of the bundle, along with
+/// their subtrees
+///
+/// Patterns are module paths from the bundle root, optionally starting with
+/// `crate::`, whose segments may contain `*` (any part of a name) or be `**` (any
+/// number of modules): `debug`, `bin_helpers::*`, `**::fixtures`. A module matching
+/// an `exclude` pattern is removed. When `only` is not empty, a module is kept only
+/// if it, one of its ancestors or one of its descendants can match an `only`
+/// pattern. Module files are pruned before they are read. The modules removed are
+/// added to `excluded`.
+pub(super) fn prune(
+    items: &mut Vec<syn::Item>,
+    path: &[String],
+    exclude: &[String],
+    only: &[String],
+    excluded: &mut Vec<BundleWarning>,
+) {
+    if exclude.is_empty() && only.is_empty() {
+        return;
+    }
+    items.retain_mut(|item| {
+        let syn::Item::Mod(item_mod) = item else {
+            return true;
+        };
+        let mut module = path.to_vec();
+        module.push(item_mod.ident.to_string());
+        if !keep(&module, exclude, only, excluded) {
+            return false;
+        }
+        if let Some((_, content)) = &mut item_mod.content {
+            prune(content, &module, exclude, only, excluded);
+        }
+        true
+    });
+}
+
+/// Whether the module at `path` stays in the bundle, adding it to `excluded` when
+/// it does not
+pub(super) fn keep(
+    path: &[String],
+    exclude: &[String],
+    only: &[String],
+    excluded: &mut Vec<BundleWarning>,
+) -> bool {
+    let kept = is_bundled(path, exclude, only);
+    if !kept {
+        excluded.push(BundleWarning::ExcludedModule {
+            module: path.join("::"),
+        });
+    }
+    kept
+}
+
+/// Whether the module at `path` stays in the bundle (see `prune`)
+fn is_bundled(path: &[String], exclude: &[String], only: &[String]) -> bool {
+    if exclude
+        .iter()
+        .any(|pattern| matches(&segments(pattern), path))
+    {
+        return false;
+    }
+    only.is_empty()
+        || only.iter().any(|pattern| {
+            let pattern = segments(pattern);
+            (1..=path.len()).any(|len| matches(&pattern, &path[..len]))
+                || matches_below(&pattern, path)
+        })
+}
+
+fn segments(pattern: &str) -> Vec<&str> {
+    let pattern = pattern.strip_prefix("crate::").unwrap_or(pattern);
+    pattern.split("::").map(str::trim).collect()
+}
+
+/// Whether `pattern` matches the module path `path`
+fn matches(pattern: &[&str], path: &[String]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((&"**", rest)), _) => {
+            matches(rest, path)
+                || path
+                    .split_first()
+                    .is_some_and(|(_, tail)| matches(pattern, tail))
+        }
+        (Some((segment, rest)), Some((name, tail))) => glob(segment, name) && matches(rest, tail),
+        _ => false,
+    }
+}
+
+/// Whether `pattern` can match a module below `path`
+fn matches_below(pattern: &[&str], path: &[String]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (Some((&"**", _)), _) | (Some(_), None) => true,
+        (Some((segment, rest)), Some((name, tail))) => {
+            glob(segment, name) && matches_below(rest, tail)
+        }
+        (None, _) => false,
+    }
+}
+
+/// Whether `name` matches `pattern`, in which `*` stands for any characters
+fn glob(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => name.strip_prefix(prefix).is_some_and(|name| {
+            name.char_indices()
+                .map(|(index, _)| index)
+                .chain([name.len()])
+                .any(|index| glob(rest, &name[index..]))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+
+    fn path(path: &str) -> Vec<String> {
+        path.split("::").map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_module_patterns() {
+        let exclude = ["debug".to_string(), "bin_helpers::*".to_string()];
+        assert!(!is_bundled(&path("debug"), &exclude, &[]));
+        assert!(is_bundled(&path("bin_helpers"), &exclude, &[]));
+        assert!(!is_bundled(&path("bin_helpers::io"), &exclude, &[]));
+        assert!(is_bundled(&path("ai::debug"), &exclude, &[]));
+        assert!(!is_bundled(
+            &path("ai::debug"),
+            &["**::debug".to_string()],
+            &[]
+        ));
+        assert!(!is_bundled(
+            &path("debug_draw"),
+            &["debug_*".to_string()],
+            &[]
+        ));
+
+        let only = ["crate::ai::search".to_string()];
+        assert!(is_bundled(&path("ai"), &[], &only));
+        assert!(is_bundled(&path("ai::search::beam"), &[], &only));
+        assert!(!is_bundled(&path("ai::eval"), &[], &only));
+        assert!(!is_bundled(&path("debug"), &[], &only));
+    }
+
+    #[test]
+    fn test_prune_inline_modules() {
+        let mut file: syn::File = syn::parse_quote! {
+            mod debug;
+            mod ai {
+                mod search {}
+                mod fixtures {}
+            }
+            fn main() {}
+        };
+        let mut excluded = Vec::new();
+        prune(
+            &mut file.items,
+            &[],
+            &["debug".to_string(), "**::fixtures".to_string()],
+            &[],
+            &mut excluded,
+        );
+        assert_eq!(
+            file.to_token_stream().to_string(),
+            "mod ai { mod search { } } fn main () { }"
+        );
+        assert_eq!(
+            excluded,
+            [
+                BundleWarning::ExcludedModule {
+                    module: "debug".to_string()
+                },
+                BundleWarning::ExcludedModule {
+                    module: "ai::fixtures".to_string()
+                },
+            ]
+        );
+    }
+}
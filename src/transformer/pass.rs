@@ -0,0 +1,203 @@
+use crate::error::{BundlerError, Result};
+
+/// Point in the bundling pipeline at which a custom pass runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PassStage {
+    /// After module expansion and test/doc filtering, before the built-in
+    /// dead-code elimination and attribute policy
+    BeforeBuiltin,
+    /// On the final syntax tree, right before it is rendered
+    #[default]
+    AfterBuiltin,
+}
+
+/// A custom AST transformation that can be registered with `Bundler::add_pass`
+///
+/// Passes see the whole bundle as a single `syn::File`. When line markers are
+/// enabled the tree also contains `__cg_bundler_file!` placeholder items, which
+/// passes should leave in place.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use cg_bundler::transformer::TransformPass;
+/// use cg_bundler::Bundler;
+/// use syn::visit_mut::{self, VisitMut};
+///
+/// /// Replace `debug!(...)` invocations with `()`
+/// struct StripDebug;
+///
+/// impl VisitMut for StripDebug {
+///     fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+///         if let syn::Expr::Macro(mac) = expr {
+///             if mac.mac.path.is_ident("debug") {
+///                 *expr = syn::parse_quote!(());
+///                 return;
+///             }
+///         }
+///         visit_mut::visit_expr_mut(self, expr);
+///     }
+/// }
+///
+/// impl TransformPass for StripDebug {
+///     fn name(&self) -> &str {
+///         "strip-debug"
+///     }
+///
+///     fn run(&self, file: &mut syn::File) -> cg_bundler::Result<()> {
+///         StripDebug.visit_file_mut(file);
+///         Ok(())
+///     }
+/// }
+///
+/// let mut bundler = Bundler::new();
+/// bundler.add_pass(Box::new(StripDebug));
+/// let bundled = bundler.bundle("./my_project").unwrap();
+/// ```
+pub trait TransformPass {
+    /// Name used in diagnostics
+    fn name(&self) -> &str;
+
+    /// When the pass runs; defaults to after the built-in passes
+    fn stage(&self) -> PassStage {
+        PassStage::AfterBuiltin
+    }
+
+    /// Transform the bundled file in place
+    ///
+    /// # Errors
+    /// Returning an error aborts bundling
+    fn run(&self, file: &mut syn::File) -> Result<()>;
+}
+
+/// A pass built from a closure
+pub struct FnPass<F> {
+    name: String,
+    stage: PassStage,
+    function: F,
+}
+
+impl<F> FnPass<F>
+where
+    F: Fn(&mut syn::File) -> Result<()>,
+{
+    /// Create a pass running after the built-in passes
+    pub fn new(name: &str, function: F) -> Self {
+        Self {
+            name: name.to_string(),
+            stage: PassStage::default(),
+            function,
+        }
+    }
+
+    /// Run the pass at a different stage
+    #[must_use]
+    pub const fn with_stage(mut self, stage: PassStage) -> Self {
+        self.stage = stage;
+        self
+    }
+}
+
+impl<F> TransformPass for FnPass<F>
+where
+    F: Fn(&mut syn::File) -> Result<()>,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn stage(&self) -> PassStage {
+        self.stage
+    }
+
+    fn run(&self, file: &mut syn::File) -> Result<()> {
+        (self.function)(file)
+    }
+}
+
+/// Run every pass registered for `stage`, in registration order
+///
+/// Errors other than `BundlerError::TransformPass` are reported as a failure of
+/// the pass that returned them, under its name.
+pub(crate) fn run_passes(
+    passes: &[Box<dyn TransformPass + '_>],
+    stage: PassStage,
+    file: &mut syn::File,
+) -> Result<()> {
+    passes
+        .iter()
+        .filter(|pass| pass.stage() == stage)
+        .try_for_each(|pass| {
+            pass.run(file).map_err(|e| match e {
+                BundlerError::TransformPass { .. } => e,
+                e => BundlerError::TransformPass {
+                    pass: pass.name().to_string(),
+                    message: e.to_string(),
+                },
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_passes_run_in_order_for_their_stage() {
+        let log = RefCell::new(Vec::new());
+        let passes: Vec<Box<dyn TransformPass + '_>> = vec![
+            Box::new(FnPass::new("first", |_| {
+                log.borrow_mut().push("first");
+                Ok(())
+            })),
+            Box::new(
+                FnPass::new("early", |_| {
+                    log.borrow_mut().push("early");
+                    Ok(())
+                })
+                .with_stage(PassStage::BeforeBuiltin),
+            ),
+            Box::new(FnPass::new("second", |_| {
+                log.borrow_mut().push("second");
+                Ok(())
+            })),
+        ];
+        let mut file = syn::parse_file("fn main() {}").unwrap();
+
+        run_passes(&passes, PassStage::AfterBuiltin, &mut file).unwrap();
+        assert_eq!(*log.borrow(), ["first", "second"]);
+    }
+
+    #[test]
+    fn test_pass_error_aborts() {
+        let passes: Vec<Box<dyn TransformPass>> = vec![Box::new(FnPass::new("fail", |_| {
+            Err(BundlerError::TransformPass {
+                pass: "fail".to_string(),
+                message: "boom".to_string(),
+            })
+        }))];
+        let mut file = syn::parse_file("fn main() {}").unwrap();
+
+        let result = run_passes(&passes, PassStage::AfterBuiltin, &mut file);
+        assert!(matches!(result, Err(BundlerError::TransformPass { .. })));
+    }
+
+    #[test]
+    fn test_pass_errors_name_the_pass() {
+        let passes: Vec<Box<dyn TransformPass>> =
+            vec![Box::new(FnPass::new("strip-debug", |_| {
+                Err(BundlerError::Config {
+                    message: "no debug macro".to_string(),
+                    path: None,
+                })
+            }))];
+        let mut file = syn::parse_file("fn main() {}").unwrap();
+
+        let error = run_passes(&passes, PassStage::AfterBuiltin, &mut file).unwrap_err();
+        assert!(matches!(
+            &error,
+            BundlerError::TransformPass { pass, .. } if pass == "strip-debug"
+        ));
+    }
+}
@@ -0,0 +1,242 @@
+use proc_macro2::{Literal, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::spanned::Spanned;
+
+use super::comments;
+
+/// Name of the placeholder macro standing in for an item emitted with its
+/// original source text
+const PLACEHOLDER_MACRO: &str = "__cg_bundler_original";
+
+/// Source text of an item that no transform changed
+struct Original {
+    text: String,
+    /// Column of the item's first character in its source file
+    column: usize,
+    /// Lines of `text` (0-based) inside a literal spanning several lines, which
+    /// are emitted as they are
+    literal_lines: Vec<usize>,
+}
+
+/// Render a file like `prettyplease::unparse`, but emit the items that no
+/// transform changed with their original source text
+///
+/// An item is unchanged when its tokens equal the tokens of the source text its
+/// span covers. Modules and impl blocks that changed are re-printed around their
+/// unchanged items. Items built by transforms have no source text and are always
+/// re-printed. The plain comments of the source text are removed unless
+/// `keep_comments` is set, and lines holding only spaces are emptied.
+#[must_use]
+pub fn unparse_preserving(file: &syn::File, keep_comments: bool) -> String {
+    let mut file = file.clone();
+    let mut originals = Vec::new();
+    replace_items(&mut file.items, keep_comments, &mut originals);
+
+    let printed = prettyplease::unparse(&file);
+    if originals.is_empty() {
+        return printed;
+    }
+    splice(&printed, &originals)
+}
+
+fn original<T: ToTokens + Spanned>(node: &T, keep_comments: bool) -> Option<Original> {
+    let span = node.span();
+    let mut text = span.source_text()?;
+    if !keep_comments {
+        text = strip_comments(&text);
+    }
+    let reparsed: TokenStream = syn::parse_str(&text).ok()?;
+    if reparsed.to_string() != node.to_token_stream().to_string() {
+        return None;
+    }
+    let mut literal_lines = Vec::new();
+    collect_literal_lines(reparsed, &mut literal_lines);
+    Some(Original {
+        column: span.start().column,
+        literal_lines,
+        text,
+    })
+}
+
+/// `text` without its plain comments, along with the spaces before a comment
+/// ending its line and the lines holding nothing else
+fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for comment in comments::plain_comments(text) {
+        out.push_str(&text[last..comment.start]);
+        last = comment.end;
+        let rest = &text[comment.end..];
+        if rest.is_empty() || rest.starts_with('\n') {
+            out.truncate(out.trim_end_matches([' ', '\t']).len());
+            if out.ends_with('\n') && rest.starts_with('\n') {
+                last += 1;
+            }
+        }
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+/// Add the lines (0-based) after the first of each literal of `tokens` spanning
+/// several lines
+fn collect_literal_lines(tokens: TokenStream, lines: &mut Vec<usize>) {
+    for tree in tokens {
+        match tree {
+            TokenTree::Group(group) => collect_literal_lines(group.stream(), lines),
+            TokenTree::Literal(literal) => {
+                let span: Span = literal.span();
+                lines.extend(span.start().line..span.end().line);
+            }
+            TokenTree::Ident(_) | TokenTree::Punct(_) => {}
+        }
+    }
+}
+
+fn placeholder<T: syn::parse::Parse>(index: usize) -> T {
+    let name = syn::Ident::new(PLACEHOLDER_MACRO, Span::call_site());
+    let index = Literal::usize_unsuffixed(index);
+    syn::parse_quote!(#name!(#index);)
+}
+
+/// Replace unchanged items with placeholders, collecting their source text
+fn replace_items(items: &mut [syn::Item], keep_comments: bool, originals: &mut Vec<Original>) {
+    for item in items {
+        if let Some(text) = original(item, keep_comments) {
+            *item = placeholder(originals.len());
+            originals.push(text);
+            continue;
+        }
+        match item {
+            syn::Item::Mod(item_mod) => {
+                if let Some((_, content)) = &mut item_mod.content {
+                    replace_items(content, keep_comments, originals);
+                }
+            }
+            syn::Item::Impl(item_impl) => {
+                for impl_item in &mut item_impl.items {
+                    if let Some(text) = original(impl_item, keep_comments) {
+                        *impl_item = placeholder(originals.len());
+                        originals.push(text);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_placeholder(line: &str) -> Option<usize> {
+    line.strip_prefix(PLACEHOLDER_MACRO)?
+        .strip_prefix("!(")?
+        .strip_suffix(");")?
+        .parse()
+        .ok()
+}
+
+/// Substitute the placeholder lines of the printed file with the original text
+fn splice(printed: &str, originals: &[Original]) -> String {
+    let mut out = String::with_capacity(printed.len());
+    for line in printed.lines() {
+        let trimmed = line.trim_start();
+        match parse_placeholder(trimmed).and_then(|index| originals.get(index)) {
+            Some(original) => push_original(&mut out, original, line.len() - trimmed.len()),
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Append the original text with its first line at `indent`, shifting the
+/// following lines by the same amount except inside literals
+fn push_original(out: &mut String, original: &Original, indent: usize) {
+    let mut lines = original.text.lines();
+    out.push_str(&" ".repeat(indent));
+    out.push_str(lines.next().unwrap_or_default());
+
+    for (index, line) in lines.enumerate() {
+        out.push('\n');
+        if original.literal_lines.contains(&(index + 1)) {
+            out.push_str(line);
+        } else if line.trim().is_empty() {
+            // Lines of spaces are emptied
+        } else if indent >= original.column {
+            out.push_str(&" ".repeat(indent - original.column));
+            out.push_str(line);
+        } else {
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            out.push_str(&line[spaces.min(original.column - indent)..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unchanged_items_keep_their_formatting() {
+        let source = "// helpers\nfn   add(a: u32,\n        b: u32) -> u32 { a + b } // sum\n\n/// Doc\nstruct P { x: u32 }\n";
+        let mut file = syn::parse_file(source).unwrap();
+        file.items.push(syn::parse_quote!(
+            fn added() {}
+        ));
+
+        let rendered = unparse_preserving(&file, true);
+        assert!(rendered.contains("fn   add(a: u32,\n        b: u32) -> u32 { a + b }\n"));
+        assert!(rendered.contains("/// Doc\nstruct P { x: u32 }\n"));
+        assert!(rendered.contains("fn added() {}"));
+        assert!(!rendered.contains("// sum"));
+    }
+
+    #[test]
+    fn test_comments_are_removed_unless_kept() {
+        let source = "fn main() {\n    // answer\n    let x = 42; // the answer\n    \n    let s = \"a // b\n    \n  c\"; /* end */\n}\n";
+        let mut file = syn::parse_file(source).unwrap();
+        file.items.push(syn::parse_quote!(
+            fn added() {}
+        ));
+
+        let rendered = unparse_preserving(&file, false);
+        assert!(rendered.starts_with(
+            "fn main() {\n    let x = 42;\n\n    let s = \"a // b\n    \n  c\";\n}\n"
+        ));
+        let kept = unparse_preserving(&file, true);
+        assert!(kept.starts_with("fn main() {\n    // answer\n    let x = 42; // the answer\n\n"));
+    }
+
+    #[test]
+    fn test_changed_module_is_reprinted_around_unchanged_items() {
+        let source = "mod geo {\n  pub fn area() -> u32 {\n    4\n  }\n  /// Changed\n  pub fn side() -> u32 { 2 }\n}\n";
+        let mut file = syn::parse_file(source).unwrap();
+        let syn::Item::Mod(geo) = &mut file.items[0] else {
+            unreachable!()
+        };
+        let syn::Item::Fn(side) = &mut geo.content.as_mut().unwrap().1[1] else {
+            unreachable!()
+        };
+        side.attrs.clear();
+        *side.block = syn::parse_quote!({ 3 });
+
+        let rendered = unparse_preserving(&file, true);
+        assert!(rendered.starts_with("mod geo {\n"));
+        assert!(rendered.contains("    pub fn area() -> u32 {\n      4\n    }\n"));
+        assert!(rendered.contains("    pub fn side() -> u32 {\n        3\n    }\n"));
+    }
+
+    #[test]
+    fn test_multiline_literals_are_not_reindented() {
+        let source = "mod text {\n  pub const S: &str = \"a\n  b\";\n}\n";
+        let mut file = syn::parse_file(source).unwrap();
+        let syn::Item::Mod(text) = &mut file.items[0] else {
+            unreachable!()
+        };
+        text.content.as_mut().unwrap().1.push(syn::parse_quote!(
+            fn added() {}
+        ));
+
+        let rendered = unparse_preserving(&file, true);
+        assert!(rendered.contains("    pub const S: &str = \"a\n  b\";\n"));
+    }
+}
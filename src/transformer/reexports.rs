@@ -0,0 +1,777 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::{mem, slice};
+
+use proc_macro2::Span;
+use syn::visit_mut::{self, VisitMut};
+
+use super::collisions::{self, Namespaces};
+use super::line_markers;
+
+/// First segment of the paths that named the library by its crate name while it
+/// is inlined at the bundle root, until `flatten` points them at `crate`
+const LIBRARY_ROOT: &str = "__cg_bundler_library";
+
+/// How many `pub use` steps are followed to resolve a path, which also stops
+/// cycles of glob imports
+const MAX_DEPTH: usize = 16;
+
+/// Path standing for the root of a library inlined at the bundle root
+pub(super) fn library_root() -> syn::Path {
+    let root = syn::Ident::new(LIBRARY_ROOT, Span::call_site());
+    syn::parse_quote!(#root)
+}
+
+/// Point the paths of a bundle that named the library inlined at its root at
+/// `crate`, keeping the library's re-exports resolving
+///
+/// The library's root items and the binary's share the bundle root, so a root
+/// re-export such as `pub use shapes::Point as Position;` clashes with a binary
+/// item named `Position`, and a name the library re-exports with a glob is
+/// shadowed by a binary item. Such clashing re-exports are removed, and the paths
+/// that went through them, in the binary's `crate_name::...` paths and the
+/// library's own code, are rewritten to their target instead. Re-export chains,
+/// through modules, renames and globs, are followed to tell the namespaces of a
+/// re-exported item. `library` is the line marker path of the library's root file.
+pub(super) fn flatten(items: &mut Vec<syn::Item>, library: &str) {
+    let in_library: Vec<bool> = collisions::item_files(items, None)
+        .iter()
+        .map(|file| file.as_deref() == Some(library))
+        .collect();
+    let (redirects, removed) = {
+        let (root, binary): (Vec<_>, Vec<_>) = items
+            .iter()
+            .zip(&in_library)
+            .filter(|(item, _)| !line_markers::is_marker(item))
+            .partition(|(_, &in_library)| in_library);
+        let library = Library {
+            root: root.into_iter().map(|(item, _)| item).collect(),
+        };
+        library.redirects(binary.into_iter().map(|(item, _)| item))
+    };
+
+    let mut redirector = Redirector {
+        redirects: &redirects,
+        library: false,
+        depth: 0,
+    };
+    let mut index = 0;
+    items.retain_mut(|item| {
+        redirector.library = in_library[index];
+        index += 1;
+        if let (true, syn::Item::Use(item_use)) = (redirector.library, &mut *item) {
+            match remove_bindings(&item_use.tree, None, &removed) {
+                Some(tree) => item_use.tree = tree,
+                None => return false,
+            }
+        }
+        redirector.visit_item_mut(item);
+        true
+    });
+}
+
+/// Names the glob imports of the library modules bind in the library root, whose
+/// items are `items`, e.g. `Greedy` for `pub use strategies::*;`
+///
+/// Globs of globs are followed, so these are the names a path from the library
+/// root may reach without the library defining them there.
+pub(super) fn glob_names(items: &[syn::Item]) -> BTreeSet<String> {
+    let library = Library {
+        root: items.iter().collect(),
+    };
+    let mut names = BTreeSet::new();
+    library.add_glob_names(&[], 0, &mut names);
+    names
+}
+
+/// A path named by an import
+#[derive(Debug, Clone)]
+struct Target {
+    /// Whether the path is from the library root rather than an external crate
+    local: bool,
+    segments: Vec<syn::Ident>,
+}
+
+impl Target {
+    /// The path, without the `crate::` of local targets when `prefixed` by it
+    fn path(&self, prefixed: bool) -> syn::Path {
+        let segments = &self.segments;
+        if self.local && !prefixed {
+            syn::parse_quote!(crate::#(#segments)::*)
+        } else {
+            syn::parse_quote!(#(#segments)::*)
+        }
+    }
+}
+
+/// The names a module imports
+#[derive(Default)]
+struct Imports {
+    bindings: BTreeMap<String, Target>,
+    globs: Vec<Target>,
+}
+
+impl Imports {
+    /// Imports of the module at `module` among its `items`, with their paths from
+    /// the library root
+    fn of(items: &[&syn::Item], module: &[syn::Ident]) -> Self {
+        let mut local_names: BTreeSet<String> = items
+            .iter()
+            .filter_map(|item| collisions::binding(item).map(|(name, _)| name))
+            .collect();
+        let mut written = Vec::new();
+        for item in items {
+            if let syn::Item::Use(item_use) = item {
+                let mut imports = Self::default();
+                collect_imports(&item_use.tree, &mut Vec::new(), &mut imports);
+                local_names.extend(imports.bindings.keys().cloned());
+                written.push((item_use.leading_colon.is_some(), imports));
+            }
+        }
+
+        let absolute = |external: bool, target: Target| {
+            if external {
+                return target;
+            }
+            let mut segments = target.segments.into_iter().peekable();
+            let mut module = module.to_vec();
+            match segments.peek() {
+                Some(first) if first == "crate" || first == LIBRARY_ROOT => {
+                    segments.next();
+                    module.clear();
+                }
+                Some(first) if first == "self" => {
+                    segments.next();
+                }
+                Some(first) if first == "super" => {
+                    while segments.next_if(|segment| segment == "super").is_some() {
+                        module.pop();
+                    }
+                }
+                Some(first) if local_names.contains(&first.to_string()) => {}
+                _ => {
+                    return Target {
+                        local: false,
+                        segments: segments.collect(),
+                    }
+                }
+            }
+            module.extend(segments);
+            Target {
+                local: true,
+                segments: module,
+            }
+        };
+        let mut imports = Self::default();
+        for (external, written) in written {
+            imports.bindings.extend(
+                written
+                    .bindings
+                    .into_iter()
+                    .map(|(name, target)| (name, absolute(external, target))),
+            );
+            imports.globs.extend(
+                written
+                    .globs
+                    .into_iter()
+                    .map(|target| absolute(external, target)),
+            );
+        }
+        imports
+    }
+}
+
+/// Add the imports of `tree`, below the path `prefix`, to `imports`, with their
+/// paths as written
+fn collect_imports(tree: &syn::UseTree, prefix: &mut Vec<syn::Ident>, imports: &mut Imports) {
+    let target = |prefix: &[syn::Ident], ident: &syn::Ident| Target {
+        local: false,
+        segments: if ident == "self" {
+            prefix.to_vec()
+        } else {
+            [prefix, slice::from_ref(ident)].concat()
+        },
+    };
+    match tree {
+        syn::UseTree::Path(path) => {
+            prefix.push(path.ident.clone());
+            collect_imports(&path.tree, prefix, imports);
+            prefix.pop();
+        }
+        syn::UseTree::Name(name) => {
+            let bound = if name.ident == "self" {
+                prefix.last()
+            } else {
+                Some(&name.ident)
+            };
+            if let Some(bound) = bound {
+                imports
+                    .bindings
+                    .insert(bound.to_string(), target(prefix, &name.ident));
+            }
+        }
+        syn::UseTree::Rename(rename) => {
+            imports
+                .bindings
+                .insert(rename.rename.to_string(), target(prefix, &rename.ident));
+        }
+        syn::UseTree::Glob(_) => imports.globs.push(Target {
+            local: false,
+            segments: prefix.clone(),
+        }),
+        syn::UseTree::Group(group) => {
+            for tree in &group.items {
+                collect_imports(tree, prefix, imports);
+            }
+        }
+    }
+}
+
+/// `tree` without the bindings of `removed`, or `None` when nothing is left
+///
+/// The imports `removed` maps to `true` may be of traits, whose methods are still
+/// in scope through them, so they are kept as `_`. `parent` is the last path
+/// segment above `tree`, which `self` binds.
+fn remove_bindings(
+    tree: &syn::UseTree,
+    parent: Option<&syn::Ident>,
+    removed: &BTreeMap<String, bool>,
+) -> Option<syn::UseTree> {
+    let underscore = syn::Ident::new("_", Span::call_site());
+    match tree {
+        syn::UseTree::Path(path) => {
+            let mut path = path.clone();
+            *path.tree = remove_bindings(&path.tree, Some(&path.ident), removed)?;
+            Some(syn::UseTree::Path(path))
+        }
+        syn::UseTree::Name(name) => {
+            let bound = if name.ident == "self" {
+                parent
+            } else {
+                Some(&name.ident)
+            };
+            match bound.and_then(|bound| removed.get(&bound.to_string())) {
+                None => Some(tree.clone()),
+                Some(true) => {
+                    let ident = &name.ident;
+                    Some(syn::parse_quote!(#ident as #underscore))
+                }
+                Some(false) => None,
+            }
+        }
+        syn::UseTree::Rename(rename) => match removed.get(&rename.rename.to_string()) {
+            None => Some(tree.clone()),
+            Some(true) => {
+                let ident = &rename.ident;
+                Some(syn::parse_quote!(#ident as #underscore))
+            }
+            Some(false) => None,
+        },
+        syn::UseTree::Glob(_) => Some(tree.clone()),
+        syn::UseTree::Group(group) => {
+            let mut group = group.clone();
+            group.items = mem::take(&mut group.items)
+                .into_iter()
+                .filter_map(|tree| remove_bindings(&tree, parent, removed))
+                .collect();
+            (!group.items.is_empty()).then_some(syn::UseTree::Group(group))
+        }
+    }
+}
+
+/// What a path leads to in the library
+enum Binding {
+    /// A module, by its path of `mod` definitions from the library root
+    Module(Vec<syn::Ident>),
+    Trait,
+    Item(Namespaces),
+}
+
+/// The modules of the library, from its root items in the bundle
+struct Library<'a> {
+    root: Vec<&'a syn::Item>,
+}
+
+impl<'a> Library<'a> {
+    /// Items of the module at `module`, a path of `mod` definitions
+    fn items(&self, module: &[syn::Ident]) -> Option<Vec<&'a syn::Item>> {
+        let mut items = self.root.clone();
+        for name in module {
+            items = items.into_iter().find_map(|item| match item {
+                syn::Item::Mod(syn::ItemMod {
+                    ident,
+                    content: Some((_, content)),
+                    ..
+                }) if ident == name => Some(content.iter().collect()),
+                _ => None,
+            })?;
+        }
+        Some(items)
+    }
+
+    /// What `path`, from the library root, leads to
+    fn resolve(&self, path: &[syn::Ident], depth: usize) -> Option<Binding> {
+        let (name, modules) = path.split_last()?;
+        let mut module = Vec::new();
+        for segment in modules {
+            match self.lookup(&module, segment, depth)? {
+                Binding::Module(path) => module = path,
+                Binding::Trait | Binding::Item(_) => return None,
+            }
+        }
+        self.lookup(&module, name, depth)
+    }
+
+    /// What `name` is bound to in `module`, by a definition, an import or a glob
+    fn lookup(&self, module: &[syn::Ident], name: &syn::Ident, depth: usize) -> Option<Binding> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let items = self.items(module)?;
+        for item in &items {
+            match item {
+                syn::Item::Mod(item_mod) if item_mod.ident == *name => {
+                    return Some(Binding::Module([module, slice::from_ref(name)].concat()));
+                }
+                syn::Item::Trait(item_trait) if item_trait.ident == *name => {
+                    return Some(Binding::Trait);
+                }
+                _ => {
+                    if let Some((_, namespaces)) =
+                        collisions::binding(item).filter(|(bound, _)| name == bound)
+                    {
+                        return Some(Binding::Item(namespaces));
+                    }
+                }
+            }
+        }
+
+        let imports = Imports::of(&items, module);
+        if let Some(target) = imports.bindings.get(&name.to_string()) {
+            return target
+                .local
+                .then(|| self.resolve(&target.segments, depth + 1))?;
+        }
+        imports.globs.iter().find_map(|glob| {
+            if !glob.local {
+                return None;
+            }
+            match self.resolve(&glob.segments, depth + 1)? {
+                Binding::Module(module) => self.lookup(&module, name, depth + 1),
+                Binding::Trait | Binding::Item(_) => None,
+            }
+        })
+    }
+
+    /// Add the names the glob imports of `module` bind to `names`
+    fn add_glob_names(&self, module: &[syn::Ident], depth: usize, names: &mut BTreeSet<String>) {
+        if depth > MAX_DEPTH {
+            return;
+        }
+        let Some(items) = self.items(module) else {
+            return;
+        };
+        for glob in Imports::of(&items, module).globs {
+            if !glob.local {
+                continue;
+            }
+            let Some(Binding::Module(target)) = self.resolve(&glob.segments, depth + 1) else {
+                continue;
+            };
+            let Some(target_items) = self.items(&target) else {
+                continue;
+            };
+            names.extend(
+                target_items
+                    .iter()
+                    .filter_map(|item| collisions::binding(item).map(|(name, _)| name)),
+            );
+            names.extend(Imports::of(&target_items, &target).bindings.into_keys());
+            self.add_glob_names(&target, depth + 1, names);
+        }
+    }
+
+    /// Where the paths through the library's root re-exports that clash with the
+    /// definitions among the `binary` root items must go instead, and the imports
+    /// to remove, mapped to whether they must be kept as `_`
+    ///
+    /// Imports clashing with the binary's imports are left to `merge_imports`.
+    fn redirects<'b>(
+        &self,
+        binary: impl Iterator<Item = &'b syn::Item>,
+    ) -> (BTreeMap<String, Target>, BTreeMap<String, bool>) {
+        let taken: BTreeMap<String, Namespaces> = binary
+            .filter(|item| !matches!(item, syn::Item::Use(_)))
+            .filter_map(collisions::binding)
+            .collect();
+
+        let imports = Imports::of(&self.root, &[]);
+        let mut redirects = BTreeMap::new();
+        let mut removed = BTreeMap::new();
+        for (name, namespaces) in &taken {
+            let ident = syn::Ident::new(name, Span::call_site());
+            if let Some(target) = imports.bindings.get(name) {
+                let binding = target
+                    .local
+                    .then(|| self.resolve(&target.segments, 0))
+                    .flatten();
+                let imported = match binding {
+                    None => Namespaces::ALL,
+                    Some(Binding::Module(_) | Binding::Trait) => Namespaces::TYPE,
+                    Some(Binding::Item(namespaces)) => namespaces,
+                };
+                if imported.intersects(*namespaces) {
+                    redirects.insert(name.clone(), target.clone());
+                    let may_be_trait = matches!(binding, None | Some(Binding::Trait));
+                    removed.insert(name.clone(), may_be_trait);
+                }
+            } else if !self
+                .root
+                .iter()
+                .any(|item| collisions::binding(item).is_some_and(|(bound, _)| bound == *name))
+            {
+                // A name of a glob the library re-exports at its root
+                let glob = imports.globs.iter().find(|glob| {
+                    glob.local
+                        && self
+                            .resolve(
+                                &[glob.segments.as_slice(), slice::from_ref(&ident)].concat(),
+                                0,
+                            )
+                            .is_some()
+                });
+                if let Some(glob) = glob {
+                    let mut target = glob.clone();
+                    target.segments.push(ident);
+                    redirects.insert(name.clone(), target);
+                }
+            }
+        }
+
+        // Targets going through other redirected re-exports
+        let resolved = redirects.clone();
+        for target in redirects.values_mut() {
+            for _ in 0..MAX_DEPTH {
+                let Some(next) = target
+                    .segments
+                    .first()
+                    .filter(|_| target.local)
+                    .and_then(|first| resolved.get(&first.to_string()))
+                else {
+                    break;
+                };
+                let rest = target.segments.split_off(1);
+                *target = next.clone();
+                target.segments.extend(rest);
+            }
+        }
+        (redirects, removed)
+    }
+}
+
+/// Rewrites the paths reaching the library root through `redirects`
+struct Redirector<'a> {
+    redirects: &'a BTreeMap<String, Target>,
+    /// Whether the items visited are the library's, whose `crate::...` paths and
+    /// root-relative paths are the library root's
+    library: bool,
+    /// Depth of the module visited below the bundle root
+    depth: usize,
+}
+
+impl Redirector<'_> {
+    /// Number of leading segments of a path starting with `idents` that name the
+    /// library root, if it is resolved from there
+    fn root_segments<'i>(&self, mut idents: impl Iterator<Item = &'i syn::Ident>) -> Option<usize> {
+        let first = idents.next()?;
+        if first == LIBRARY_ROOT {
+            return Some(1);
+        }
+        if !self.library {
+            return None;
+        }
+        if first == "crate" {
+            return Some(1);
+        }
+        if self.depth == 0 {
+            return Some(usize::from(first == "self"));
+        }
+        let supers = 1 + idents.take_while(|ident| *ident == "super").count();
+        (first == "super" && supers == self.depth).then_some(supers)
+    }
+
+    /// Rewrite `path`, which may be a `lone` identifier only where it cannot be a
+    /// local variable
+    fn redirect_path(&self, path: &mut syn::Path, lone: bool) {
+        if path.leading_colon.is_some() {
+            return;
+        }
+        let Some(skip) = self.root_segments(path.segments.iter().map(|segment| &segment.ident))
+        else {
+            return;
+        };
+        if path.segments[0].ident == LIBRARY_ROOT {
+            path.segments[0].ident = syn::Ident::new("crate", path.segments[0].ident.span());
+        }
+        let target = path
+            .segments
+            .iter()
+            .nth(skip)
+            .filter(|_| skip > 0 || path.segments.len() > 1 || lone)
+            .and_then(|segment| self.redirects.get(&segment.ident.to_string()));
+        let Some(target) = target else {
+            return;
+        };
+
+        let mut segments = target.path(false).segments;
+        let mut rest = mem::take(&mut path.segments).into_iter().skip(skip);
+        if let (Some(redirected), Some(last)) = (rest.next(), segments.last_mut()) {
+            last.arguments = redirected.arguments;
+        }
+        segments.extend(rest);
+        path.segments = segments;
+    }
+
+    /// Rewrite an import
+    fn redirect_use(&self, tree: &mut syn::UseTree) {
+        if let syn::UseTree::Rename(rename) = tree {
+            if rename.ident == LIBRARY_ROOT {
+                rename.ident = syn::Ident::new("crate", rename.ident.span());
+            }
+            return;
+        }
+        let mut idents = Vec::new();
+        let mut below = &*tree;
+        while let syn::UseTree::Path(path) = below {
+            idents.push(&path.ident);
+            below = &path.tree;
+        }
+        let Some(skip) = self.root_segments(idents.into_iter()) else {
+            return;
+        };
+        if let syn::UseTree::Path(path) = tree {
+            if path.ident == LIBRARY_ROOT {
+                path.ident = syn::Ident::new("crate", path.ident.span());
+            }
+        }
+
+        let mut below = &mut *tree;
+        for _ in 0..skip {
+            let syn::UseTree::Path(path) = below else {
+                return;
+            };
+            below = &mut path.tree;
+        }
+        if skip > 0 && matches!(below, syn::UseTree::Group(_)) {
+            if let Some(group) = self.redirect_tree(below, true) {
+                *below = group;
+            }
+        } else if let Some(redirected) = self.redirect_tree(below, false) {
+            *tree = redirected;
+        }
+    }
+
+    /// `tree`, from the library root, through the redirects, or `None` when it is
+    /// unchanged
+    ///
+    /// A `prefixed` tree follows `crate::`, so only local targets can replace it.
+    fn redirect_tree(&self, tree: &syn::UseTree, prefixed: bool) -> Option<syn::UseTree> {
+        let target = |ident: &syn::Ident| {
+            self.redirects
+                .get(&ident.to_string())
+                .filter(|target| target.local || !prefixed)
+                .map(|target| target.path(prefixed))
+        };
+        match tree {
+            syn::UseTree::Path(path) => {
+                let target = target(&path.ident)?;
+                let subtree = &path.tree;
+                Some(syn::parse_quote!(#target::#subtree))
+            }
+            syn::UseTree::Name(name) => {
+                let target = target(&name.ident)?;
+                let alias = &name.ident;
+                Some(if target.segments.last()?.ident == *alias {
+                    syn::parse_quote!(#target)
+                } else {
+                    syn::parse_quote!(#target as #alias)
+                })
+            }
+            syn::UseTree::Rename(rename) => {
+                let target = target(&rename.ident)?;
+                let alias = &rename.rename;
+                Some(syn::parse_quote!(#target as #alias))
+            }
+            syn::UseTree::Group(group) if prefixed => {
+                let mut group = group.clone();
+                let mut changed = false;
+                for tree in &mut group.items {
+                    if let Some(redirected) = self.redirect_tree(tree, true) {
+                        *tree = redirected;
+                        changed = true;
+                    }
+                }
+                changed.then_some(syn::UseTree::Group(group))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl VisitMut for Redirector<'_> {
+    fn visit_item_mod_mut(&mut self, item: &mut syn::ItemMod) {
+        self.depth += 1;
+        visit_mut::visit_item_mod_mut(self, item);
+        self.depth -= 1;
+    }
+
+    fn visit_item_use_mut(&mut self, item: &mut syn::ItemUse) {
+        if item.leading_colon.is_none() {
+            self.redirect_use(&mut item.tree);
+        }
+    }
+
+    fn visit_type_path_mut(&mut self, type_path: &mut syn::TypePath) {
+        if type_path.qself.is_none() {
+            self.redirect_path(&mut type_path.path, true);
+        }
+        visit_mut::visit_type_path_mut(self, type_path);
+    }
+
+    fn visit_expr_call_mut(&mut self, call: &mut syn::ExprCall) {
+        if let syn::Expr::Path(function) = &mut *call.func {
+            if function.qself.is_none() {
+                self.redirect_path(&mut function.path, true);
+            }
+        }
+        visit_mut::visit_expr_call_mut(self, call);
+    }
+
+    fn visit_expr_struct_mut(&mut self, expr: &mut syn::ExprStruct) {
+        if expr.qself.is_none() {
+            self.redirect_path(&mut expr.path, true);
+        }
+        visit_mut::visit_expr_struct_mut(self, expr);
+    }
+
+    fn visit_path_mut(&mut self, path: &mut syn::Path) {
+        self.redirect_path(path, false);
+        visit_mut::visit_path_mut(self, path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+
+    /// The bundle of `library` inlined before the items of `binary`, flattened
+    fn flattened(library: syn::File, binary: syn::File) -> String {
+        let mut items = vec![line_markers::marker(Some("src/lib.rs"))];
+        items.extend(library.items);
+        items.push(line_markers::marker(None));
+        items.extend(binary.items);
+        flatten(&mut items, "src/lib.rs");
+        line_markers::strip(&mut items);
+        syn::File { items, ..binary }.to_token_stream().to_string()
+    }
+
+    #[test]
+    fn test_glob_names_followed_through_modules() {
+        let library: syn::File = syn::parse_quote! {
+            pub mod ai {
+                mod strategies {
+                    pub struct Greedy;
+                    pub use super::super::util::Helper as Tool;
+                }
+                pub use strategies::*;
+                pub use Dir::*;
+                pub enum Dir { North }
+            }
+            mod util { pub struct Helper; }
+            pub use ai::*;
+            pub use std::collections::*;
+        };
+        assert_eq!(
+            glob_names(&library.items),
+            BTreeSet::from(["Dir", "Greedy", "Tool", "strategies"].map(String::from))
+        );
+    }
+
+    #[test]
+    fn test_reexport_chain_clashing_with_binary() {
+        let bundle = flattened(
+            syn::parse_quote! {
+                mod shapes {
+                    pub mod point {
+                        pub struct Point { pub x: i32 }
+                        pub fn origin() -> Point { Point { x: 0 } }
+                    }
+                    pub use self::point::Point as Pt;
+                    pub use point::*;
+                }
+                pub mod prelude {
+                    pub use super::shapes::Pt as P;
+                }
+                pub use prelude::P as Position;
+                pub use shapes::{origin as start, Pt};
+                pub fn from_start() -> crate::Position { let start = 1; start() }
+            },
+            syn::parse_quote! {
+                struct Position(u8);
+                fn start() -> u8 { 7 }
+                fn main() {
+                    let a: __cg_bundler_library::Position = __cg_bundler_library::start();
+                    let b = Position(start());
+                }
+            },
+        );
+        assert_eq!(
+            bundle,
+            "mod shapes { pub mod point { pub struct Point { pub x : i32 } pub fn origin () -> Point { Point { x : 0 } } } pub use self :: point :: Point as Pt ; pub use point :: * ; } pub mod prelude { pub use super :: shapes :: Pt as P ; } pub use shapes :: { Pt } ; pub fn from_start () -> crate :: prelude :: P { let start = 1 ; crate :: shapes :: origin () } struct Position (u8) ; fn start () -> u8 { 7 } fn main () { let a : crate :: prelude :: P = crate :: shapes :: origin () ; let b = Position (start ()) ; }"
+        );
+    }
+
+    #[test]
+    fn test_glob_reexport_shadowed_by_binary() {
+        let bundle = flattened(
+            syn::parse_quote! {
+                mod geo {
+                    pub mod area { pub fn square(side: u32) -> u32 { side * side } }
+                    pub use area::*;
+                }
+                pub use geo::*;
+            },
+            syn::parse_quote! {
+                fn square() -> u32 { 4 }
+                mod ai {
+                    use __cg_bundler_library::{square, geo::area};
+                    pub fn run() -> u32 { square(2) + area::square(3) + super::square() }
+                }
+                fn main() {}
+            },
+        );
+        assert_eq!(
+            bundle,
+            "mod geo { pub mod area { pub fn square (side : u32) -> u32 { side * side } } pub use area :: * ; } pub use geo :: * ; fn square () -> u32 { 4 } mod ai { use crate :: { geo :: square , geo :: area } ; pub fn run () -> u32 { square (2) + area :: square (3) + super :: square () } } fn main () { }"
+        );
+    }
+
+    #[test]
+    fn test_library_paths_point_at_crate() {
+        let bundle = flattened(
+            syn::parse_quote! {
+                pub fn area() -> u32 { 1 }
+            },
+            syn::parse_quote! {
+                fn main() {
+                    use __cg_bundler_library as lib;
+                    let _ = __cg_bundler_library::area() + lib::area();
+                }
+            },
+        );
+        assert_eq!(
+            bundle,
+            "pub fn area () -> u32 { 1 } fn main () { use crate as lib ; let _ = crate :: area () + lib :: area () ; }"
+        );
+    }
+}
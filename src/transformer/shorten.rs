@@ -0,0 +1,394 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use proc_macro2::{Delimiter, Group, Ident, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::visit_mut::{self, VisitMut};
+
+use super::verbatim;
+
+/// Rename the local variables of every function to the shortest names that are
+/// not used anywhere in the bundle
+///
+/// Parameters and the bindings of `let`, closures, `match` arms and loops are
+/// renamed with their uses, the most used first, so that they get the shortest
+/// names; struct shorthands (`Point { x }`) are spelled out. A name is kept when
+/// it is also the name of an item or import, starts with `_`, or appears in a
+/// macro invocation other than as a plain expression (e.g. `{x}` in a format
+/// string or `x: 1` in a struct literal), where its meaning is unknown.
+pub(super) fn shorten_locals(file: &mut syn::File) {
+    let mut items = ItemNames::default();
+    items.visit_file_mut(file);
+    let mut taken = BTreeSet::new();
+    collect_idents(file.to_token_stream(), &mut taken);
+    Shortener {
+        items: items.0,
+        taken,
+    }
+    .visit_file_mut(file);
+}
+
+/// Names of the items, imports and enum variants of a file
+#[derive(Default)]
+struct ItemNames(BTreeSet<String>);
+
+impl VisitMut for ItemNames {
+    fn visit_item_mut(&mut self, item: &mut syn::Item) {
+        let ident = match item {
+            syn::Item::Const(item) => Some(&item.ident),
+            syn::Item::Enum(item) => Some(&item.ident),
+            syn::Item::ExternCrate(item) => Some(&item.ident),
+            syn::Item::Fn(item) => Some(&item.sig.ident),
+            syn::Item::Macro(item) => item.ident.as_ref(),
+            syn::Item::Mod(item) => Some(&item.ident),
+            syn::Item::Static(item) => Some(&item.ident),
+            syn::Item::Struct(item) => Some(&item.ident),
+            syn::Item::Trait(item) => Some(&item.ident),
+            syn::Item::Type(item) => Some(&item.ident),
+            syn::Item::Union(item) => Some(&item.ident),
+            _ => None,
+        };
+        self.0.extend(ident.map(ToString::to_string));
+        visit_mut::visit_item_mut(self, item);
+    }
+
+    fn visit_use_name_mut(&mut self, name: &mut syn::UseName) {
+        self.0.insert(name.ident.to_string());
+    }
+
+    fn visit_use_rename_mut(&mut self, rename: &mut syn::UseRename) {
+        self.0.insert(rename.rename.to_string());
+    }
+
+    fn visit_variant_mut(&mut self, variant: &mut syn::Variant) {
+        self.0.insert(variant.ident.to_string());
+        visit_mut::visit_variant_mut(self, variant);
+    }
+}
+
+struct Shortener {
+    items: BTreeSet<String>,
+    /// Every identifier of the file, which new names must not be
+    taken: BTreeSet<String>,
+}
+
+impl Shortener {
+    fn shorten(
+        &self,
+        inputs: &mut syn::punctuated::Punctuated<syn::FnArg, syn::Token![,]>,
+        block: &mut syn::Block,
+    ) {
+        let mut locals = Locals::default();
+        for input in inputs.iter_mut() {
+            if let syn::FnArg::Typed(typed) = input {
+                locals.visit_pat_mut(&mut typed.pat);
+            }
+        }
+        locals.visit_block_mut(block);
+
+        let mut candidates: Vec<(String, usize)> = locals
+            .uses
+            .into_iter()
+            .filter(|(name, _)| {
+                locals.bound.contains(name)
+                    && !locals.blocked.contains(name)
+                    && !self.items.contains(name)
+            })
+            .collect();
+        candidates.sort_by(|(a, a_uses), (b, b_uses)| b_uses.cmp(a_uses).then_with(|| a.cmp(b)));
+
+        let mut names = short_names().filter(|name| !self.taken.contains(name));
+        let renames: BTreeMap<String, String> = candidates
+            .into_iter()
+            .map_while(|(name, _)| Some((name, names.next()?)))
+            .filter(|(name, short)| short.len() < name.len())
+            .collect();
+        if renames.is_empty() {
+            return;
+        }
+
+        let mut visitor = Renamer { renames: &renames };
+        for input in inputs.iter_mut() {
+            if let syn::FnArg::Typed(typed) = input {
+                visitor.visit_pat_mut(&mut typed.pat);
+            }
+        }
+        visitor.visit_block_mut(block);
+    }
+}
+
+impl VisitMut for Shortener {
+    fn visit_item_fn_mut(&mut self, item: &mut syn::ItemFn) {
+        visit_mut::visit_item_fn_mut(self, item);
+        self.shorten(&mut item.sig.inputs, &mut item.block);
+    }
+
+    fn visit_impl_item_fn_mut(&mut self, item: &mut syn::ImplItemFn) {
+        visit_mut::visit_impl_item_fn_mut(self, item);
+        self.shorten(&mut item.sig.inputs, &mut item.block);
+    }
+
+    fn visit_trait_item_fn_mut(&mut self, item: &mut syn::TraitItemFn) {
+        visit_mut::visit_trait_item_fn_mut(self, item);
+        if let Some(block) = &mut item.default {
+            self.shorten(&mut item.sig.inputs, block);
+        }
+    }
+}
+
+/// The local variables of a function body, without those of nested items
+#[derive(Default)]
+struct Locals {
+    /// Names bound by patterns
+    bound: BTreeSet<String>,
+    /// Occurrences of each name, bindings included
+    uses: BTreeMap<String, usize>,
+    /// Names whose occurrences in macros cannot be told apart from other things
+    blocked: BTreeSet<String>,
+}
+
+impl VisitMut for Locals {
+    fn visit_item_mut(&mut self, _item: &mut syn::Item) {}
+
+    fn visit_pat_ident_mut(&mut self, pat: &mut syn::PatIdent) {
+        let name = pat.ident.to_string();
+        if name.starts_with(|c: char| c.is_ascii_lowercase()) && name != "self" {
+            self.bound.insert(name.clone());
+            *self.uses.entry(name).or_default() += 1;
+        }
+        visit_mut::visit_pat_ident_mut(self, pat);
+    }
+
+    fn visit_expr_path_mut(&mut self, path: &mut syn::ExprPath) {
+        if let Some(ident) = local_ident(path) {
+            *self.uses.entry(ident.to_string()).or_default() += 1;
+        }
+        visit_mut::visit_expr_path_mut(self, path);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        if verbatim::is_placeholder(mac) {
+            collect_idents(mac.tokens.clone(), &mut self.blocked);
+            return;
+        }
+        let braced = matches!(mac.delimiter, syn::MacroDelimiter::Brace(_));
+        self.visit_tokens(mac.tokens.clone(), braced);
+    }
+}
+
+impl Locals {
+    fn visit_tokens(&mut self, tokens: TokenStream, braced: bool) {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        for (index, token) in tokens.iter().enumerate() {
+            match token {
+                TokenTree::Ident(ident) => {
+                    let name = ident.to_string();
+                    if is_plain(&tokens, index, braced) {
+                        *self.uses.entry(name).or_default() += 1;
+                    } else {
+                        self.blocked.insert(name);
+                    }
+                }
+                // Implicit format arguments, e.g. `{x}` or `{x:>4}`
+                TokenTree::Literal(literal) => {
+                    let text = literal.to_string();
+                    let mut rest = text.as_str();
+                    while let Some(start) = rest.find('{') {
+                        rest = &rest[start + 1..];
+                        let end = rest
+                            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                            .unwrap_or(rest.len());
+                        self.blocked.insert(rest[..end].to_string());
+                    }
+                }
+                TokenTree::Group(group) => {
+                    self.visit_tokens(group.stream(), group.delimiter() == Delimiter::Brace);
+                }
+                TokenTree::Punct(_) => {}
+            }
+        }
+    }
+}
+
+/// Whether the identifier at `index` can only be a variable: it is not a field
+/// (`.x`, `x:`), a path segment (`a::x`), a named argument (`x = 1`), a lifetime
+/// or a macro, in a group delimited by braces when `braced`
+fn is_plain(tokens: &[TokenTree], index: usize, braced: bool) -> bool {
+    let punct = |index: usize| match tokens.get(index) {
+        Some(TokenTree::Punct(punct)) => Some((punct.as_char(), punct.spacing())),
+        _ => None,
+    };
+    let before = index.checked_sub(1).and_then(punct);
+    let after = punct(index + 1);
+    // Alone between commas in braces, it may be a struct shorthand (`{ x, y }`)
+    let separated =
+        |punct: Option<(char, Spacing)>, end: bool| punct.map_or(end, |(c, _)| c == ',');
+    let shorthand =
+        braced && separated(before, index == 0) && separated(after, index + 1 == tokens.len());
+    !shorthand
+        && !matches!(before, Some(('.' | ':' | '\'', _)))
+        && !matches!(after, Some((':' | '!', _)))
+        && after != Some(('=', Spacing::Alone))
+}
+
+/// The identifier of a path naming a local variable, e.g. `x`
+fn local_ident(path: &syn::ExprPath) -> Option<&syn::Ident> {
+    if path.qself.is_some() || path.path.leading_colon.is_some() {
+        return None;
+    }
+    let segment = path.path.segments.first()?;
+    (path.path.segments.len() == 1 && segment.arguments.is_none()).then_some(&segment.ident)
+}
+
+struct Renamer<'a> {
+    renames: &'a BTreeMap<String, String>,
+}
+
+impl Renamer<'_> {
+    fn rename(&self, ident: &mut syn::Ident) {
+        if let Some(short) = self.renames.get(&ident.to_string()) {
+            *ident = syn::Ident::new(short, ident.span());
+        }
+    }
+
+    fn rename_tokens(&self, tokens: TokenStream, braced: bool) -> TokenStream {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        tokens
+            .iter()
+            .enumerate()
+            .map(|(index, token)| match token {
+                TokenTree::Ident(ident) if is_plain(&tokens, index, braced) => {
+                    self.renames.get(&ident.to_string()).map_or_else(
+                        || token.clone(),
+                        |short| TokenTree::Ident(Ident::new(short, ident.span())),
+                    )
+                }
+                TokenTree::Group(group) => {
+                    let mut renamed = Group::new(
+                        group.delimiter(),
+                        self.rename_tokens(group.stream(), group.delimiter() == Delimiter::Brace),
+                    );
+                    renamed.set_span(group.span());
+                    TokenTree::Group(renamed)
+                }
+                _ => token.clone(),
+            })
+            .collect()
+    }
+}
+
+impl VisitMut for Renamer<'_> {
+    fn visit_item_mut(&mut self, _item: &mut syn::Item) {}
+
+    fn visit_pat_ident_mut(&mut self, pat: &mut syn::PatIdent) {
+        self.rename(&mut pat.ident);
+        visit_mut::visit_pat_ident_mut(self, pat);
+    }
+
+    fn visit_expr_path_mut(&mut self, path: &mut syn::ExprPath) {
+        if local_ident(path).is_some() {
+            self.rename(&mut path.path.segments[0].ident);
+        }
+    }
+
+    fn visit_field_value_mut(&mut self, field: &mut syn::FieldValue) {
+        visit_mut::visit_field_value_mut(self, field);
+        // `Point { x }` becomes `Point { x: a }`
+        if let syn::Expr::Path(path) = &field.expr {
+            spell_out(&field.member, local_ident(path), &mut field.colon_token);
+        }
+    }
+
+    fn visit_field_pat_mut(&mut self, field: &mut syn::FieldPat) {
+        visit_mut::visit_field_pat_mut(self, field);
+        if let syn::Pat::Ident(pat) = &*field.pat {
+            spell_out(&field.member, Some(&pat.ident), &mut field.colon_token);
+        }
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        let braced = matches!(mac.delimiter, syn::MacroDelimiter::Brace(_));
+        mac.tokens = self.rename_tokens(mac.tokens.clone(), braced);
+    }
+}
+
+/// Add the colon of a field shorthand whose variable no longer has the name of
+/// the field
+fn spell_out(member: &syn::Member, ident: Option<&syn::Ident>, colon: &mut Option<syn::Token![:]>) {
+    if let (syn::Member::Named(name), Some(ident)) = (member, ident) {
+        if name != ident && colon.is_none() {
+            *colon = Some(syn::Token![:](ident.span()));
+        }
+    }
+}
+
+/// `a`, ..., `z`, `aa`, `ab`, ... without keywords
+pub(super) fn short_names() -> impl Iterator<Item = String> {
+    (1..)
+        .flat_map(|len| {
+            (0..26usize.pow(len)).map(move |mut index| {
+                let mut name = vec![b'a'; len as usize];
+                for byte in name.iter_mut().rev() {
+                    *byte += u8::try_from(index % 26).unwrap_or(0);
+                    index /= 26;
+                }
+                String::from_utf8(name).unwrap_or_default()
+            })
+        })
+        .filter(|name| syn::parse_str::<syn::Ident>(name).is_ok() && name != "gen")
+}
+
+pub(super) fn collect_idents(tokens: TokenStream, idents: &mut BTreeSet<String>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            TokenTree::Group(group) => collect_idents(group.stream(), idents),
+            TokenTree::Punct(_) | TokenTree::Literal(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shortened(mut file: syn::File) -> String {
+        shorten_locals(&mut file);
+        prettyplease::unparse(&file)
+    }
+
+    #[test]
+    fn test_locals_renamed_by_use() {
+        let file: syn::File = syn::parse_quote! {
+            struct Point { left: i32, top: i32 }
+            fn distance(point: &Point, origin: Point) -> i32 {
+                let Point { left, top } = origin;
+                let delta = point.left - left;
+                let moved = Point { left: delta, top };
+                println!("{}", delta);
+                moved.top + delta * delta
+            }
+        };
+        assert_eq!(
+            shortened(file),
+            "struct Point {\n    left: i32,\n    top: i32,\n}\nfn distance(e: &Point, d: Point) -> i32 {\n    let Point { left: b, top: f } = d;\n    let a = e.left - b;\n    let c = Point { left: a, top: f };\n    println!(\"{}\", a);\n    c.top + a * a\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_names_kept() {
+        let file: syn::File = syn::parse_quote! {
+            fn count() -> usize { 3 }
+            fn report(total: usize, width: usize) {
+                let count = count();
+                println!("{total:>width$}", width = width);
+                let _unused = count;
+            }
+        };
+        let code = shortened(file);
+        assert!(code.contains("fn report(total: usize, width: usize)"));
+        assert!(code.contains("let count = count();"));
+        assert!(code.contains("let _unused = count;"));
+    }
+}
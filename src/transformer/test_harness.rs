@@ -0,0 +1,512 @@
+use std::collections::BTreeMap;
+
+use proc_macro2::{Group, Span, TokenStream, TokenTree};
+use syn::visit_mut::{self, VisitMut};
+
+use super::attributes::{classify, item_attrs_mut, AttributeClass};
+use super::lib_module::{self, is_path_separator};
+
+/// Whether an item is gated behind `#[cfg(test)]` or marked `#[test]`
+fn is_test_item(attrs: &[syn::Attribute]) -> bool {
+    attrs
+        .iter()
+        .any(|attr| classify(attr) == AttributeClass::Test)
+}
+
+/// Rewrite `crate_name::...` paths inside kept test modules to `crate::...`
+///
+/// Test code written against the library (e.g. `use my_lib::graph::Graph;`) refers to
+/// an external crate that no longer exists once the library is inlined into the
+/// bundle. Since the library items now live at the bundle root, `crate::` resolves
+/// to the same items from any nesting depth, which a bare relative path would not.
+/// When the library is embedded in a module (`TransformConfig::lib_module_name`),
+/// paths become `crate::module::...` instead.
+pub fn rewrite_crate_paths(items: &mut [syn::Item], crate_name: &str, lib_module: Option<&str>) {
+    let mut rewriter = CratePathRewriter {
+        crate_name,
+        lib_module,
+        in_test: false,
+    };
+    for item in items {
+        rewriter.visit_item_mut(item);
+    }
+}
+
+/// Rewrite `crate_name::...` paths in all of `items` to `crate::module::...`, for an
+/// external crate embedded in the bundle as `module` (see `vendor::embed`)
+pub(super) fn rewrite_extern_paths(items: &mut [syn::Item], crate_name: &str, module: &str) {
+    // Starting out "in a test" rewrites every path, not only those in test code
+    let mut rewriter = CratePathRewriter {
+        crate_name,
+        lib_module: Some(module),
+        in_test: true,
+    };
+    for item in items {
+        rewriter.visit_item_mut(item);
+    }
+}
+
+/// `tokens`, an unparsed macro body, with its `crate_name::...` paths pointed at
+/// the inlined library as `rewrite_crate_paths` does, or `None` if it has none
+pub(super) fn rewrite_macro_paths(
+    tokens: &TokenStream,
+    crate_name: &str,
+    lib_module: Option<&str>,
+) -> Option<TokenStream> {
+    fn mentions(tokens: TokenStream, name: &str) -> bool {
+        tokens.into_iter().any(|tree| match tree {
+            TokenTree::Ident(ident) => ident == name,
+            TokenTree::Group(group) => mentions(group.stream(), name),
+            _ => false,
+        })
+    }
+
+    if !mentions(tokens.clone(), crate_name) {
+        return None;
+    }
+    let rewriter = CratePathRewriter {
+        crate_name,
+        lib_module,
+        in_test: true,
+    };
+    let rewritten = rewriter.rewrite_tokens(tokens.clone());
+    (rewritten.to_string() != tokens.to_string()).then_some(rewritten)
+}
+
+struct CratePathRewriter<'a> {
+    crate_name: &'a str,
+    lib_module: Option<&'a str>,
+    in_test: bool,
+}
+
+impl CratePathRewriter<'_> {
+    fn crate_ident() -> syn::Ident {
+        syn::Ident::new("crate", Span::call_site())
+    }
+
+    /// Rewrite `crate_name ::` sequences inside an unparsed macro body
+    fn rewrite_tokens(&self, tokens: TokenStream) -> TokenStream {
+        let trees: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut rewritten = Vec::with_capacity(trees.len());
+        for (index, tree) in trees.iter().enumerate() {
+            match tree {
+                TokenTree::Group(group) => {
+                    let mut new_group =
+                        Group::new(group.delimiter(), self.rewrite_tokens(group.stream()));
+                    new_group.set_span(group.span());
+                    rewritten.push(TokenTree::Group(new_group));
+                }
+                TokenTree::Ident(ident)
+                    if ident == self.crate_name
+                        && is_path_separator(&trees, index + 1)
+                        && (index < 2 || !is_path_separator(&trees, index - 2)) =>
+                {
+                    match self.lib_module {
+                        Some(name) => rewritten.extend(lib_module::path_tokens(name, ident.span())),
+                        None => {
+                            rewritten
+                                .push(TokenTree::Ident(syn::Ident::new("crate", ident.span())));
+                        }
+                    }
+                }
+                other => rewritten.push(other.clone()),
+            }
+        }
+        rewritten.into_iter().collect()
+    }
+
+    /// Path replacing a leading `crate_name` segment
+    fn library_path(&self) -> syn::Path {
+        self.lib_module
+            .map_or_else(|| Self::crate_ident().into(), lib_module::path)
+    }
+}
+
+impl VisitMut for CratePathRewriter<'_> {
+    fn visit_item_mut(&mut self, item: &mut syn::Item) {
+        let outer = self.in_test;
+        if let Some(attrs) = item_attrs_mut(item) {
+            self.in_test |= is_test_item(attrs);
+        }
+        visit_mut::visit_item_mut(self, item);
+        self.in_test = outer;
+    }
+
+    fn visit_path_mut(&mut self, path: &mut syn::Path) {
+        if self.in_test
+            && path.leading_colon.is_none()
+            && path.segments.len() > 1
+            && path
+                .segments
+                .first()
+                .is_some_and(|first| first.ident == self.crate_name)
+        {
+            let mut segments = self.library_path().segments;
+            segments.extend(std::mem::take(&mut path.segments).into_iter().skip(1));
+            path.segments = segments;
+        }
+        visit_mut::visit_path_mut(self, path);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        if self.in_test {
+            mac.tokens = self.rewrite_tokens(std::mem::take(&mut mac.tokens));
+        }
+        visit_mut::visit_macro_mut(self, mac);
+    }
+
+    fn visit_item_use_mut(&mut self, item: &mut syn::ItemUse) {
+        if self.in_test && item.leading_colon.is_none() {
+            match &mut item.tree {
+                syn::UseTree::Path(path) if path.ident == self.crate_name => {
+                    path.ident = Self::crate_ident();
+                    if let Some(name) = self.lib_module {
+                        let module = syn::Ident::new(name, Span::call_site());
+                        let tree = &path.tree;
+                        *path.tree = syn::parse_quote!(#module::#tree);
+                    }
+                }
+                syn::UseTree::Rename(rename) if rename.ident == self.crate_name => {
+                    match self.lib_module {
+                        Some(name) => {
+                            let module = syn::Ident::new(name, Span::call_site());
+                            let alias = &rename.rename;
+                            item.tree = syn::parse_quote!(crate::#module as #alias);
+                        }
+                        None => rename.ident = Self::crate_ident(),
+                    }
+                }
+                _ => {}
+            }
+        }
+        visit_mut::visit_item_use_mut(self, item);
+    }
+}
+
+/// Give test modules unique names at the bundle root
+///
+/// Both the library and the binary commonly declare `mod tests`; once they are
+/// flattened into one file the second declaration would collide with the first.
+/// Later duplicates are renamed `tests_2`, `tests_3`, ... Returns the number of
+/// renamed modules.
+pub fn disambiguate_test_modules(items: &mut [syn::Item]) -> usize {
+    let mut seen: BTreeMap<String, usize> = items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Mod(item_mod) if !is_test_item(&item_mod.attrs) => {
+                Some((item_mod.ident.to_string(), 1))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut renamed = 0;
+    for item in items {
+        let syn::Item::Mod(item_mod) = item else {
+            continue;
+        };
+        if !is_test_item(&item_mod.attrs) {
+            continue;
+        }
+
+        let name = item_mod.ident.to_string();
+        let count = seen.entry(name.clone()).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            let mut suffix = *count;
+            let mut candidate = format!("{name}_{suffix}");
+            while seen.contains_key(&candidate) {
+                suffix += 1;
+                candidate = format!("{name}_{suffix}");
+            }
+            seen.insert(candidate.clone(), 1);
+            item_mod.ident = syn::Ident::new(&candidate, item_mod.ident.span());
+            renamed += 1;
+        }
+    }
+    renamed
+}
+
+/// Put kept test code behind `#[cfg(name)]` instead of `#[cfg(test)]`
+///
+/// Every `test` predicate of `#[cfg]`, `#[cfg_attr]` and `cfg!` becomes `name`, so
+/// `#[cfg(test)]` items and their `#[cfg(not(test))]` counterparts switch together,
+/// and `#[test]` functions outside such items get `#[cfg(name)]`. The bundle then
+/// compiles without its tests unless built with `--cfg name`, e.g. with
+/// `RUSTFLAGS="--cfg local_tests" cargo test`.
+pub fn gate_tests(items: &mut [syn::Item], name: &syn::Ident) {
+    let mut gate = TestGate { name, gated: false };
+    for item in items {
+        gate.visit_item_mut(item);
+    }
+}
+
+struct TestGate<'a> {
+    name: &'a syn::Ident,
+    /// Whether the visited item is already behind a `cfg` naming the gate
+    gated: bool,
+}
+
+impl TestGate<'_> {
+    /// Replace the `test` predicates of a `cfg` condition
+    fn gate_predicate(&self, tokens: TokenStream) -> TokenStream {
+        let trees: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut gated = Vec::with_capacity(trees.len());
+        for (index, tree) in trees.iter().enumerate() {
+            gated.push(match tree {
+                TokenTree::Group(group) => {
+                    let mut new_group =
+                        Group::new(group.delimiter(), self.gate_predicate(group.stream()));
+                    new_group.set_span(group.span());
+                    TokenTree::Group(new_group)
+                }
+                // `test`, but not the `test` of `feature = "..."`-like options
+                TokenTree::Ident(ident)
+                    if ident == "test"
+                        && !matches!(trees.get(index + 1), Some(TokenTree::Punct(punct)) if punct.as_char() == '=') =>
+                {
+                    TokenTree::Ident(syn::Ident::new(&self.name.to_string(), ident.span()))
+                }
+                tree => tree.clone(),
+            });
+        }
+        gated.into_iter().collect()
+    }
+
+    /// Replace the `test` predicates of the `cfg!` invocations in a macro body, as
+    /// in `assert!(cfg!(test))`
+    fn gate_nested_cfgs(&self, tokens: TokenStream) -> TokenStream {
+        let trees: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut gated = Vec::with_capacity(trees.len());
+        for (index, tree) in trees.iter().enumerate() {
+            let TokenTree::Group(group) = tree else {
+                gated.push(tree.clone());
+                continue;
+            };
+            let is_cfg = index >= 2
+                && matches!(&trees[index - 2], TokenTree::Ident(ident) if ident == "cfg")
+                && matches!(&trees[index - 1], TokenTree::Punct(punct) if punct.as_char() == '!');
+            let stream = if is_cfg {
+                self.gate_predicate(group.stream())
+            } else {
+                self.gate_nested_cfgs(group.stream())
+            };
+            let mut new_group = Group::new(group.delimiter(), stream);
+            new_group.set_span(group.span());
+            gated.push(TokenTree::Group(new_group));
+        }
+        gated.into_iter().collect()
+    }
+
+    /// Replace the `test` predicates of a `cfg` or `cfg_attr` attribute
+    fn gate_attribute(&self, attr: &mut syn::Attribute) {
+        let syn::Meta::List(list) = &mut attr.meta else {
+            return;
+        };
+        if list.path.is_ident("cfg") {
+            list.tokens = self.gate_predicate(std::mem::take(&mut list.tokens));
+        } else if list.path.is_ident("cfg_attr") {
+            // Only the condition, before the first comma, is a predicate
+            let mut trees = std::mem::take(&mut list.tokens).into_iter();
+            let predicate: TokenStream = trees
+                .by_ref()
+                .take_while(
+                    |tree| !matches!(tree, TokenTree::Punct(punct) if punct.as_char() == ','),
+                )
+                .collect();
+            let mut tokens = self.gate_predicate(predicate);
+            tokens.extend(quote::quote!(,));
+            tokens.extend(trees);
+            list.tokens = tokens;
+        }
+    }
+}
+
+impl VisitMut for TestGate<'_> {
+    fn visit_item_mut(&mut self, item: &mut syn::Item) {
+        let outer = self.gated;
+        if let Some(attrs) = item_attrs_mut(item) {
+            let cfg_test = attrs.iter().any(|attr| {
+                attr.path().is_ident("cfg") && is_test_item(std::slice::from_ref(attr))
+            });
+            let test = attrs.iter().any(|attr| attr.path().is_ident("test"));
+            if test && !cfg_test && !self.gated {
+                let name = self.name;
+                attrs.push(syn::parse_quote!(#[cfg(#name)]));
+            }
+            self.gated |= cfg_test || test;
+        }
+        visit_mut::visit_item_mut(self, item);
+        self.gated = outer;
+    }
+
+    fn visit_attribute_mut(&mut self, attr: &mut syn::Attribute) {
+        self.gate_attribute(attr);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        mac.tokens = if mac.path.is_ident("cfg") {
+            self.gate_predicate(std::mem::take(&mut mac.tokens))
+        } else {
+            self.gate_nested_cfgs(std::mem::take(&mut mac.tokens))
+        };
+        visit_mut::visit_macro_mut(self, mac);
+    }
+}
+
+/// Turn the `#[test]` functions at the root of an integration test file into
+/// plain functions called in order by a generated `fn main`
+///
+/// `#[ignore]` tests are not called, `#[should_panic]` ones must panic, and
+/// tests returning a `Result` must return `Ok`. A file with its own `fn main`
+/// (a test target with `harness = false`) is left alone. Returns the number of
+/// tests called.
+pub fn add_test_main(items: &mut Vec<syn::Item>) -> usize {
+    let has_main = items
+        .iter()
+        .any(|item| matches!(item, syn::Item::Fn(item_fn) if item_fn.sig.ident == "main"));
+    if has_main {
+        return 0;
+    }
+
+    let mut calls: Vec<syn::Stmt> = Vec::new();
+    for item in items.iter_mut() {
+        let syn::Item::Fn(item_fn) = item else {
+            continue;
+        };
+        if !item_fn
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("test"))
+        {
+            continue;
+        }
+        let has = |name: &str| item_fn.attrs.iter().any(|attr| attr.path().is_ident(name));
+        let (ignored, should_panic) = (has("ignore"), has("should_panic"));
+        item_fn.attrs.retain(|attr| {
+            !["test", "ignore", "should_panic"]
+                .iter()
+                .any(|name| attr.path().is_ident(name))
+        });
+        if ignored {
+            continue;
+        }
+
+        let name = &item_fn.sig.ident;
+        calls.push(if should_panic {
+            let message = format!("{name} should panic");
+            syn::parse_quote!(assert!(::std::panic::catch_unwind(#name).is_err(), #message);)
+        } else if matches!(item_fn.sig.output, syn::ReturnType::Default) {
+            syn::parse_quote!(#name();)
+        } else {
+            syn::parse_quote!(#name().unwrap();)
+        });
+    }
+    let called = calls.len();
+    items.push(syn::parse_quote! {
+        fn main() {
+            #(#calls)*
+        }
+    });
+    called
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+
+    fn render(file: &syn::File) -> String {
+        file.to_token_stream().to_string()
+    }
+
+    #[test]
+    fn test_crate_paths_rewritten_only_in_tests() {
+        let mut file = syn::parse_file(
+            "use my_lib::a; #[cfg(test)] mod tests { use my_lib::geo::dist; use my_lib as lib; #[test] fn t() { my_lib::answer(); assert_eq!(my_lib::x, a::my_lib::y); } }",
+        )
+        .unwrap();
+        let mut embedded = file.clone();
+        rewrite_crate_paths(&mut file.items, "my_lib", None);
+        let output = render(&file);
+        assert!(output.starts_with("use my_lib :: a ;"));
+        assert!(output.contains("use crate :: geo :: dist ;"));
+        assert!(output.contains("use crate as lib ;"));
+        assert!(output.contains("crate :: answer ()"));
+        assert!(output.contains("assert_eq ! (crate :: x , a :: my_lib :: y)"));
+
+        rewrite_crate_paths(&mut embedded.items, "my_lib", Some("solver"));
+        let output = render(&embedded);
+        assert!(output.contains("use crate :: solver :: geo :: dist ;"));
+        assert!(output.contains("use crate :: solver as lib ;"));
+        assert!(output.contains("crate :: solver :: answer ()"));
+        assert!(output.contains("assert_eq ! (crate :: solver :: x , a :: my_lib :: y)"));
+    }
+
+    #[test]
+    fn test_tests_gated_behind_custom_cfg() {
+        let mut file = syn::parse_file(
+            r#"#[cfg(not(test))] fn now() -> u64 { 1 }
+#[cfg(test)] fn now() -> u64 { 0 }
+#[cfg_attr(all(test, feature = "test"), derive(Debug))] struct State;
+#[test] fn top_level() { assert!(cfg!(test)); }
+#[cfg(test)] mod tests { #[test] fn works() {} }"#,
+        )
+        .unwrap();
+        let name = syn::Ident::new("local_tests", Span::call_site());
+        gate_tests(&mut file.items, &name);
+        let output = render(&file);
+        assert!(output.contains("# [cfg (not (local_tests))] fn now"));
+        assert!(output.contains("# [cfg (local_tests)] fn now"));
+        assert!(output
+            .contains("# [cfg_attr (all (local_tests , feature = \"test\") , derive (Debug))]"));
+        assert!(output.contains(
+            "# [test] # [cfg (local_tests)] fn top_level () { assert ! (cfg ! (local_tests)) ; }"
+        ));
+        assert!(output.contains("# [cfg (local_tests)] mod tests { # [test] fn works () { } }"));
+    }
+
+    #[test]
+    fn test_duplicate_test_modules_are_renamed() {
+        let mut file = syn::parse_file(
+            "#[cfg(test)] mod tests {} mod tests_2 {} #[cfg(test)] mod tests {} #[cfg(test)] mod other {}",
+        )
+        .unwrap();
+        assert_eq!(disambiguate_test_modules(&mut file.items), 1);
+        let names: Vec<String> = file
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                syn::Item::Mod(item_mod) => Some(item_mod.ident.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["tests", "tests_2", "tests_3", "other"]);
+    }
+
+    #[test]
+    fn test_main_calls_integration_tests() {
+        let mut file = syn::parse_file(
+            r#"use bot::Game;
+fn play(seed: u64) -> u32 { Game::new(seed).run() }
+#[test] fn bot_vs_bot() { assert!(play(1) > 0); }
+#[test] #[ignore] fn slow() {}
+#[test] #[should_panic(expected = "seed")] fn no_seed() { play(0); }
+#[test] fn parses() -> Result<(), String> { Ok(()) }
+#[cfg(test)] mod helpers { #[test] fn nested() {} }"#,
+        )
+        .unwrap();
+        assert_eq!(add_test_main(&mut file.items), 3);
+        let output = render(&file);
+        assert!(output.contains("fn bot_vs_bot ()"));
+        assert!(!output.contains("# [ignore]") && !output.contains("should_panic"));
+        assert!(output.contains(
+            "fn main () { bot_vs_bot () ; assert ! (:: std :: panic :: catch_unwind (no_seed) . is_err () , \"no_seed should panic\") ; parses () . unwrap () ; }"
+        ), "{output}");
+        assert!(output.contains("mod helpers { # [test] fn nested () { } }"));
+
+        let mut file = syn::parse_file("#[test] fn t() {} fn main() {}").unwrap();
+        assert_eq!(add_test_main(&mut file.items), 0);
+        assert_eq!(file.items.len(), 2);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeSet;
+
+use super::line_markers;
+use crate::warnings::BundleWarning;
+
+/// The `use crate::...` items of the modules of `file` naming nothing in it
+///
+/// A path resolves as soon as it reaches a name that is not a module, so
+/// `crate::Dir::North` only needs `Dir`. Modules with glob imports or item macros
+/// other than `macro_rules!` may define any name, so paths through them are
+/// assumed to resolve, as are paths through modules whose file was not expanded.
+pub(super) fn find(file: &syn::File) -> Vec<BundleWarning> {
+    let mut warnings = Vec::new();
+    let root = Module::new(&file.items);
+    check(&root, &root, &mut Vec::new(), &mut warnings);
+    warnings
+}
+
+/// The names a module defines
+struct Module<'a> {
+    items: &'a [syn::Item],
+    /// Inline modules by name
+    modules: Vec<(String, Option<Self>)>,
+    names: BTreeSet<String>,
+    /// Whether it may define names that cannot be listed
+    open: bool,
+}
+
+impl<'a> Module<'a> {
+    fn new(items: &'a [syn::Item]) -> Self {
+        let mut module = Self {
+            items,
+            modules: Vec::new(),
+            names: BTreeSet::new(),
+            open: false,
+        };
+        for item in items {
+            let name = match item {
+                syn::Item::Mod(item) => {
+                    let content = item.content.as_ref().map(|(_, items)| Self::new(items));
+                    module.modules.push((item.ident.to_string(), content));
+                    continue;
+                }
+                syn::Item::Use(item) => {
+                    module.add_use_names(&item.tree);
+                    continue;
+                }
+                syn::Item::Macro(item) if item.ident.is_none() => {
+                    module.open |= !item.mac.path.is_ident(line_markers::MARKER_MACRO);
+                    continue;
+                }
+                syn::Item::Macro(item) => item.ident.as_ref(),
+                syn::Item::Const(item) => Some(&item.ident),
+                syn::Item::Enum(item) => Some(&item.ident),
+                syn::Item::ExternCrate(item) => Some(
+                    item.rename
+                        .as_ref()
+                        .map_or(&item.ident, |(_, rename)| rename),
+                ),
+                syn::Item::Fn(item) => Some(&item.sig.ident),
+                syn::Item::Static(item) => Some(&item.ident),
+                syn::Item::Struct(item) => Some(&item.ident),
+                syn::Item::Trait(item) => Some(&item.ident),
+                syn::Item::TraitAlias(item) => Some(&item.ident),
+                syn::Item::Type(item) => Some(&item.ident),
+                syn::Item::Union(item) => Some(&item.ident),
+                _ => None,
+            };
+            if let Some(name) = name {
+                module.names.insert(name.to_string());
+            }
+        }
+        module
+    }
+
+    fn add_use_names(&mut self, tree: &syn::UseTree) {
+        match tree {
+            syn::UseTree::Path(path) => self.add_use_names(&path.tree),
+            syn::UseTree::Name(name) => {
+                self.names.insert(name.ident.to_string());
+            }
+            syn::UseTree::Rename(rename) => {
+                self.names.insert(rename.rename.to_string());
+            }
+            syn::UseTree::Glob(_) => self.open = true,
+            syn::UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.add_use_names(tree);
+                }
+            }
+        }
+    }
+
+    /// Whether `path`, relative to this module, names something
+    fn resolves(&self, path: &[String]) -> bool {
+        let Some((first, rest)) = path.split_first() else {
+            return true;
+        };
+        match self.modules.iter().find(|(name, _)| name == first) {
+            Some((_, Some(module))) => module.resolves(rest),
+            // Not expanded
+            Some((_, None)) => true,
+            None => self.open || self.names.contains(first) || first == "self",
+        }
+    }
+}
+
+/// Check the `use crate::...` items of `module`, at `module_path`, and of its
+/// submodules against `root`
+fn check(
+    root: &Module,
+    module: &Module,
+    module_path: &mut Vec<String>,
+    warnings: &mut Vec<BundleWarning>,
+) {
+    for item in module.items {
+        if let syn::Item::Use(item) = item {
+            let mut paths = Vec::new();
+            use_paths(&item.tree, &mut Vec::new(), &mut paths);
+            for path in paths {
+                if path.first().map(String::as_str) == Some("crate") && !root.resolves(&path[1..]) {
+                    warnings.push(BundleWarning::UnresolvedUse {
+                        module: module_path.join("::"),
+                        path: path.join("::"),
+                    });
+                }
+            }
+        }
+    }
+    for (name, submodule) in &module.modules {
+        if let Some(submodule) = submodule {
+            module_path.push(name.clone());
+            check(root, submodule, module_path, warnings);
+            module_path.pop();
+        }
+    }
+}
+
+/// The paths imported by `tree`, below `prefix`; a glob imports its module
+fn use_paths(tree: &syn::UseTree, prefix: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+    match tree {
+        syn::UseTree::Path(path) => {
+            prefix.push(path.ident.to_string());
+            use_paths(&path.tree, prefix, paths);
+            prefix.pop();
+        }
+        syn::UseTree::Name(syn::UseName { ident })
+        | syn::UseTree::Rename(syn::UseRename { ident, .. }) => {
+            let mut path = prefix.clone();
+            if ident != "self" {
+                path.push(ident.to_string());
+            }
+            paths.push(path);
+        }
+        syn::UseTree::Glob(_) => paths.push(prefix.clone()),
+        syn::UseTree::Group(group) => {
+            for tree in &group.items {
+                use_paths(tree, prefix, paths);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unresolved_uses_found() {
+        let file = syn::parse_file(
+            r"
+use crate::geo::{self, Point, Dir::North, missing};
+use crate::geo::shapes::Circle;
+use crate::gone::Thing;
+mod geo {
+    pub struct Point;
+    pub enum Dir { North }
+    pub mod shapes { pub struct Circle; }
+    use crate::prelude::anything;
+}
+mod prelude {
+    pub use std::collections::*;
+    use crate::geo::shapes::*;
+}
+mod external;
+use crate::external::Item;
+",
+        )
+        .unwrap();
+        assert_eq!(
+            find(&file)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "unresolved import `crate::geo::missing` at the bundle root",
+                "unresolved import `crate::gone::Thing` at the bundle root",
+            ]
+        );
+
+        let file = syn::parse_file("mod a { use crate::b::c; }\nmod b { }").unwrap();
+        assert_eq!(
+            find(&file),
+            [BundleWarning::UnresolvedUse {
+                module: "a".to_string(),
+                path: "crate::b::c".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use quote::ToTokens;
+
+use super::{features, lib_module, test_harness, CodeTransformer, TransformConfig};
+use crate::diagnostics::Span;
+use crate::error::{BundlerError, Result};
+use crate::file_manager::FileManager;
+
+/// An external crate inlined into the bundle as `pub mod <name>`
+#[derive(Debug, Clone)]
+pub struct VendoredCrate {
+    /// Name of the crate's library, as in `use name::...`
+    pub name: String,
+    /// Items of the crate's root, with its modules expanded
+    pub items: Vec<syn::Item>,
+}
+
+impl VendoredCrate {
+    /// Read the crate whose library root is `lib_path`
+    ///
+    /// Its modules are expanded and its tests removed as for the bundled package.
+    /// `cfg(feature = "...")` conditions are resolved with the enabled `features`;
+    /// items behind disabled features are removed.
+    ///
+    /// # Errors
+    /// Returns an error if a source file of the crate cannot be read or parsed
+    pub fn load(
+        name: &str,
+        lib_path: &Path,
+        features: &BTreeSet<String>,
+        config: &TransformConfig,
+    ) -> Result<Self> {
+        let code = FileManager::read_file(lib_path)?;
+        let mut file = syn::parse_file(&code).map_err(|e| BundlerError::SyntaxError {
+            message: format!("Failed to parse vendored crate {name}: {e}"),
+            file_path: Some(lib_path.to_path_buf()),
+            span: Span::of_syn_error(&e),
+        })?;
+
+        let config = TransformConfig {
+            remove_tests: true,
+            strip_unused: false,
+            line_markers: false,
+            extract_demos: false,
+            crate_name: None,
+            lib_module_name: None,
+            ..config.clone()
+        };
+        let base_path = lib_path.parent().unwrap_or_else(|| Path::new(""));
+        CodeTransformer::new(base_path, name, config).transform_file(&mut file)?;
+
+        // Features of vendored crates are those of the dependency, not warned about
+        features::resolve(&mut file.items, features);
+        Ok(Self {
+            name: name.to_string(),
+            items: file.items,
+        })
+    }
+
+    /// Features enabled by default in the `Cargo.toml` of the crate whose library
+    /// root is `lib_path`, for crates vendored from a path rather than a dependency
+    ///
+    /// Empty if no manifest is found next to `lib_path` or in its parent directory.
+    #[must_use]
+    pub fn default_features(lib_path: &Path) -> BTreeSet<String> {
+        let Some(manifest) = lib_path
+            .ancestors()
+            .skip(1)
+            .take(2)
+            .find_map(|dir| FileManager::read_file(dir.join("Cargo.toml")).ok())
+        else {
+            return BTreeSet::new();
+        };
+        let Ok(manifest) = manifest.parse::<toml::Table>() else {
+            return BTreeSet::new();
+        };
+        let Some(table) = manifest.get("features").and_then(toml::Value::as_table) else {
+            return BTreeSet::new();
+        };
+
+        let mut enabled = BTreeSet::new();
+        let mut pending: Vec<String> = table
+            .contains_key("default")
+            .then(|| "default".to_string())
+            .into_iter()
+            .collect();
+        while let Some(feature) = pending.pop() {
+            // `dep:name` and `name/feature` are about dependencies, not our cfgs
+            if feature.contains([':', '/']) || !enabled.insert(feature.clone()) {
+                continue;
+            }
+            let implied = table.get(&feature).and_then(toml::Value::as_array);
+            pending.extend(
+                implied
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|value| value.as_str().map(ToString::to_string)),
+            );
+        }
+        enabled
+    }
+
+    /// Library root of a crate given as its `lib.rs`, its source directory, or the
+    /// directory of its `Cargo.toml`
+    ///
+    /// # Errors
+    /// Returns an error if no `lib.rs` is found there
+    pub fn library_root(source: &Path) -> Result<PathBuf> {
+        if source.is_file() {
+            return Ok(source.to_path_buf());
+        }
+        [source.join("lib.rs"), source.join("src/lib.rs")]
+            .into_iter()
+            .find(|path| path.is_file())
+            .ok_or_else(|| BundlerError::ProjectStructure {
+                message: format!(
+                    "No lib.rs in '{}' or its src directory to vendor",
+                    source.display()
+                ),
+            })
+    }
+}
+
+/// Embed vendored crates in `file` as modules, pointing paths into them there
+///
+/// `use name::a::b;` and `name::f()` become `use crate::name::a::b;` and
+/// `crate::name::f()` anywhere in the bundle, including in other vendored crates.
+/// `extern crate name;` and `use name;` are removed. The crates' own `extern crate`
+/// declarations (`alloc`, `std`) move to the bundle root, where they must be to
+/// be reachable from every module.
+pub fn embed(file: &mut syn::File, crates: &[VendoredCrate]) {
+    if crates.is_empty() {
+        return;
+    }
+    let names: BTreeSet<String> = crates.iter().map(|krate| krate.name.clone()).collect();
+    file.items.retain(|item| !imports_crate(item, &names));
+
+    let mut extern_crates: Vec<syn::Item> = Vec::new();
+    let mut modules = Vec::new();
+    for krate in crates {
+        let mut items = krate.items.clone();
+        items.retain(|item| {
+            let syn::Item::ExternCrate(extern_crate) = item else {
+                return true;
+            };
+            let declared = |item: &syn::Item| {
+                item.to_token_stream().to_string() == extern_crate.to_token_stream().to_string()
+            };
+            if !names.contains(&extern_crate.ident.to_string())
+                && !file.items.iter().any(declared)
+                && !extern_crates.iter().any(declared)
+            {
+                extern_crates.push(item.clone());
+            }
+            false
+        });
+        modules.push(lib_module::wrap(items, &krate.name));
+    }
+    file.items
+        .splice(0..0, extern_crates.into_iter().chain(modules));
+
+    for name in &names {
+        test_harness::rewrite_extern_paths(&mut file.items, name, name);
+    }
+}
+
+/// Whether `item` is `extern crate name;` or `use name;` for one of `names`
+fn imports_crate(item: &syn::Item, names: &BTreeSet<String>) -> bool {
+    match item {
+        syn::Item::ExternCrate(extern_crate) => names.contains(&extern_crate.ident.to_string()),
+        syn::Item::Use(syn::ItemUse {
+            tree: syn::UseTree::Name(name),
+            ..
+        }) => names.contains(&name.ident.to_string()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_features_resolved_in_vendored_crate() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let lib_path = dir.path().join("lib.rs");
+        std::fs::write(
+            &lib_path,
+            r#"#![no_std]
+#[cfg(feature = "use_alloc")]
+extern crate alloc;
+mod adaptors;
+pub use adaptors::Pairs;
+#[cfg(all(feature = "use_std", not(feature = "use_alloc")))]
+pub fn std_only() {}
+#[cfg_attr(feature = "use_alloc", derive(Debug))]
+pub struct Counter {
+    #[cfg(feature = "use_alloc")]
+    pub items: alloc::vec::Vec<u8>,
+    #[cfg(unix)]
+    pub fd: i32,
+}
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("adaptors.rs"),
+            "pub struct Pairs;\nimpl Pairs {\n    #[cfg(not(feature = \"use_alloc\"))]\n    pub fn without_alloc() {}\n    pub fn new() -> crate::adaptors::Pairs { Pairs }\n}\n",
+        )
+        .unwrap();
+
+        let features = BTreeSet::from(["use_alloc".to_string()]);
+        let krate = VendoredCrate::load("tools", &lib_path, &features, &TransformConfig::default())
+            .unwrap();
+        let mut file = syn::parse_file(
+            "use tools::Pairs;\nfn main() { let _ = tools::Counter { items: Vec::new() }; }",
+        )
+        .unwrap();
+        embed(&mut file, &[krate]);
+
+        let output = prettyplease::unparse(&file);
+        assert!(output.starts_with("extern crate alloc;\npub mod tools {"));
+        assert!(output.contains("#[derive(Debug)]\n    pub struct Counter"));
+        assert!(output.contains("#[cfg(unix)]"));
+        assert!(!output.contains("feature"));
+        assert!(!output.contains("std_only"));
+        assert!(!output.contains("without_alloc"));
+        assert!(output.contains("pub fn new() -> crate::tools::adaptors::Pairs"));
+        assert!(output.contains("use crate::tools::Pairs;"));
+        assert!(output.contains("crate::tools::Counter {"));
+    }
+}
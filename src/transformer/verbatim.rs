@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::ops::Range;
+
+use proc_macro2::Span;
+
+use super::lexer::{block_comment_len, literal_len};
+
+/// Name of the placeholder macro carrying a verbatim region through the pipeline
+const VERBATIM_MACRO: &str = "__cg_bundler_verbatim";
+
+/// Line comment opening a region emitted exactly as written, after `cg-bundler:`
+const START: &str = "verbatim-start";
+/// Line comment closing that region
+const END: &str = "verbatim-end";
+
+/// A marker comment alone on its line
+struct Marker {
+    start: bool,
+    /// The whole line, with its line break
+    line: Range<usize>,
+    /// Line number, from 1
+    number: usize,
+}
+
+/// `code` with each verbatim region replaced by a placeholder macro call
+///
+/// A region goes from a `// cg-bundler: verbatim-start` line to a
+/// `// cg-bundler: verbatim-end` line. The placeholder carries its text, marker
+/// lines included, which `restore` writes back as is.
+///
+/// The placeholder also carries the code of the region, so that the analyses of
+/// the bundle, such as dead code elimination, see what it uses. It stands where
+/// an item or a statement can, so a region must enclose whole items or
+/// statements. Lines keep their numbers.
+///
+/// # Errors
+/// Returns an error if a marker has no matching marker
+pub fn mark(code: &str) -> syn::Result<Cow<'_, str>> {
+    let markers = markers(code);
+    if markers.is_empty() {
+        return Ok(Cow::Borrowed(code));
+    }
+    let mut marked = String::with_capacity(code.len() * 2);
+    let mut cursor = 0;
+    let mut markers = markers.into_iter();
+    while let Some(open) = markers.next() {
+        if !open.start {
+            return Err(marker_error(
+                &open,
+                &format!("`{END}` without a `{START}` above it"),
+            ));
+        }
+        let close = match markers.next() {
+            Some(close) if !close.start => close,
+            _ => {
+                return Err(marker_error(
+                    &open,
+                    &format!("`{START}` without a `{END}` below it"),
+                ))
+            }
+        };
+        let mut text = code[open.line.start..close.line.end].to_string();
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        let line_break = if code[..close.line.end].ends_with('\n') {
+            "\n"
+        } else {
+            ""
+        };
+        marked.push_str(&code[cursor..open.line.start]);
+        let _ = write!(
+            marked,
+            "{VERBATIM_MACRO}!({text:?},\n{});{line_break}",
+            &code[open.line.end..close.line.start]
+        );
+        cursor = close.line.end;
+    }
+    marked.push_str(&code[cursor..]);
+    Ok(Cow::Owned(marked))
+}
+
+/// Byte ranges of the regions of `code`, from the start of their first marker
+/// line to the end of their last, ignoring unmatched markers
+#[must_use]
+pub fn regions(code: &str) -> Vec<Range<usize>> {
+    let mut regions = Vec::new();
+    let mut markers = markers(code).into_iter().peekable();
+    while let Some(open) = markers.next() {
+        if let Some(close) = markers.next_if(|close| open.start && !close.start) {
+            regions.push(open.line.start..close.line.end);
+        }
+    }
+    regions
+}
+
+/// Error for a parse `error` of code whose verbatim regions `mark` replaced,
+/// when the code parses as written: a region encloses part of an item or a
+/// statement
+#[must_use]
+pub fn misplaced(error: &syn::Error) -> syn::Error {
+    syn::Error::new(
+        error.span(),
+        "a verbatim region must enclose whole items or statements",
+    )
+}
+
+/// Whether `mac` is the placeholder of a verbatim region, whose names must not be
+/// renamed: the region is emitted as written
+#[must_use]
+pub fn is_placeholder(mac: &syn::Macro) -> bool {
+    mac.path.is_ident(VERBATIM_MACRO)
+}
+
+/// Turn the placeholders of rendered code back into the text of their regions
+///
+/// A placeholder alone on its line is replaced along with the line; one after
+/// other code, as in minified output, is followed by the region on the same
+/// line, which ends with a line break.
+#[must_use]
+pub fn restore(code: &str) -> String {
+    if !code.contains(VERBATIM_MACRO) {
+        return code.to_string();
+    }
+    let mut restored = String::with_capacity(code.len());
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        if let Some((len, text)) = placeholder(rest) {
+            let line_start = restored.rfind('\n').map_or(0, |index| index + 1);
+            if restored[line_start..].trim().is_empty() {
+                restored.truncate(line_start);
+            }
+            restored.push_str(&text);
+            let after = &rest[len..];
+            let line_end = after.find('\n').map_or(after.len(), |index| index + 1);
+            rest = if after[..line_end].trim().is_empty() {
+                &after[line_end..]
+            } else {
+                after.trim_start_matches([' ', '\t'])
+            };
+            continue;
+        }
+        let len = token_len(rest).unwrap_or_else(|| c.len_utf8());
+        restored.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    restored
+}
+
+/// The marker comments of `code` alone on their lines, in order
+fn markers(code: &str) -> Vec<Marker> {
+    let mut markers = Vec::new();
+    let mut offset = 0;
+    while let Some(c) = code[offset..].chars().next() {
+        let rest = &code[offset..];
+        if rest.starts_with("//") {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            let line_start = code[..offset].rfind('\n').map_or(0, |index| index + 1);
+            let start = match marker_name(&rest[..len]) {
+                Some(START) => Some(true),
+                Some(END) => Some(false),
+                _ => None,
+            };
+            if let Some(start) = start.filter(|_| code[line_start..offset].trim().is_empty()) {
+                markers.push(Marker {
+                    start,
+                    line: line_start..(offset + len + usize::from(len < rest.len())),
+                    number: code[..offset].matches('\n').count() + 1,
+                });
+            }
+        }
+        offset += token_len(rest).unwrap_or_else(|| c.len_utf8());
+    }
+    markers
+}
+
+/// What follows `cg-bundler:` in a line comment
+fn marker_name(comment: &str) -> Option<&str> {
+    let body = comment.strip_prefix("//")?.trim();
+    Some(body.strip_prefix("cg-bundler:")?.trim())
+}
+
+fn marker_error(marker: &Marker, message: &str) -> syn::Error {
+    syn::Error::new(
+        Span::call_site(),
+        format!("line {}: {message}", marker.number),
+    )
+}
+
+/// Length of the comment, literal or identifier at the start of `rest`
+fn token_len(rest: &str) -> Option<usize> {
+    if rest.starts_with("//") {
+        Some(rest.find('\n').unwrap_or(rest.len()))
+    } else if rest.starts_with("/*") {
+        Some(block_comment_len(rest))
+    } else {
+        literal_len(rest)
+    }
+}
+
+/// Length and region text of the placeholder at the start of `rest`, printed as
+/// `name!("text", tokens);` with any spacing
+fn placeholder(rest: &str) -> Option<(usize, String)> {
+    let after_name = rest.strip_prefix(VERBATIM_MACRO)?;
+    let after_bang = after_name.trim_start().strip_prefix('!')?;
+    let args = after_bang.trim_start().strip_prefix('(')?.trim_start();
+    let literal_len = literal_len(args).filter(|_| args.starts_with('"'))?;
+    let text = syn::parse_str::<syn::LitStr>(&args[..literal_len])
+        .ok()?
+        .value();
+
+    // The code of the region, up to the closing parenthesis
+    let mut tail = &args[literal_len..];
+    let mut depth = 0_usize;
+    loop {
+        let c = tail.chars().next()?;
+        let len = match c {
+            '(' | '[' | '{' => {
+                depth += 1;
+                1
+            }
+            ')' if depth == 0 => break,
+            ')' | ']' | '}' => {
+                depth = depth.checked_sub(1)?;
+                1
+            }
+            _ => token_len(tail).unwrap_or_else(|| c.len_utf8()),
+        };
+        tail = &tail[len..];
+    }
+    let tail = tail[1..].trim_start_matches([' ', '\t']);
+    let tail = tail.strip_prefix(';').unwrap_or(tail);
+    Some((rest.len() - tail.len(), text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+fn main() {
+    let x = 1;
+    // cg-bundler: verbatim-start
+    let grid = [
+        [1, 0],   // row 0
+        [0, 1],   // row 1
+    ];
+    // cg-bundler: verbatim-end
+    println!(\"{x} {grid:?}\");
+}
+";
+
+    #[test]
+    fn test_regions_survive_reprinting() {
+        let marked = mark(SOURCE).unwrap();
+        assert_eq!(marked.lines().count(), SOURCE.lines().count());
+        let file = syn::parse_file(&marked).unwrap();
+
+        let printed = prettyplease::unparse(&file);
+        assert!(printed.contains(VERBATIM_MACRO), "{printed}");
+        assert_eq!(restore(&printed), SOURCE);
+    }
+
+    #[test]
+    fn test_restore_after_code_on_the_same_line() {
+        let marked = mark(SOURCE).unwrap();
+        let minified = format!(
+            "fn main(){{let x=1;{}println!(\"{{x}}\");}}",
+            &marked[marked.find(VERBATIM_MACRO).unwrap()..marked.find(");\n").unwrap() + 2]
+        );
+        assert_eq!(
+            restore(&minified),
+            "fn main(){let x=1;    // cg-bundler: verbatim-start\n    let grid = [\n        [1, 0],   // row 0\n        [0, 1],   // row 1\n    ];\n    // cg-bundler: verbatim-end\nprintln!(\"{x}\");}"
+        );
+    }
+
+    #[test]
+    fn test_unmatched_markers() {
+        let error = mark("// cg-bundler: verbatim-start\nfn f() {}\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 1: `verbatim-start` without a `verbatim-end` below it"
+        );
+        let error = mark("fn f() {}\n//cg-bundler:verbatim-end").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 2: `verbatim-end` without a `verbatim-start` above it"
+        );
+
+        let code = "const S: &str = \"// cg-bundler: verbatim-start\";\nfn f() {} // cg-bundler: verbatim-end\n";
+        assert!(matches!(mark(code), Ok(Cow::Borrowed(_))));
+    }
+}
@@ -0,0 +1,168 @@
+use syn::visit_mut::{self, VisitMut};
+
+/// Point the restricted visibilities of a bundle at modules of its tree
+///
+/// Bundling can move a module away from the path a `pub(in path)` names: the
+/// library is wrapped in a module with `--lib-module-name`, and a test module of
+/// the binary is renamed `tests_2` next to the library's. rustc rejects a
+/// visibility restricted to a module that is not an ancestor of its item, so such
+/// a path is pointed at the ancestor whose path ends with it, or else widened to
+/// `pub(crate)`, which keeps every use compiling. A `pub(super)` at the bundle
+/// root becomes `pub(crate)` too. Returns the number of visibilities changed.
+pub(super) fn fix_restricted(items: &mut [syn::Item]) -> usize {
+    let mut fixer = Restrictions::default();
+    for item in items {
+        fixer.visit_item_mut(item);
+    }
+    fixer.fixed
+}
+
+#[derive(Default)]
+struct Restrictions {
+    /// Path of the module being visited, from the bundle root
+    module: Vec<String>,
+    fixed: usize,
+}
+
+impl Restrictions {
+    /// Module `path` stands for in the current module, `None` if it goes above the
+    /// bundle root
+    fn resolve(&self, path: &syn::Path) -> Option<Vec<String>> {
+        let mut segments = path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string());
+        let mut target = match segments.next()?.as_str() {
+            "crate" => Vec::new(),
+            "self" => self.module.clone(),
+            _ => {
+                let mut parent = self.module.clone();
+                parent.pop()?;
+                parent
+            }
+        };
+        for segment in segments {
+            if segment == "super" {
+                target.pop()?;
+            } else {
+                target.push(segment);
+            }
+        }
+        Some(target)
+    }
+
+    /// The visibility replacing `pub(in path)` in the current module, if `path` is
+    /// not one of its ancestors
+    fn fix(&self, path: &syn::Path) -> Option<syn::Visibility> {
+        let target = self.resolve(path);
+        if let Some(target) = &target {
+            if self.module.starts_with(target) {
+                return None;
+            }
+        }
+        let depth = target.and_then(|target| {
+            (1..=self.module.len())
+                .rev()
+                .find(|&depth| !target.is_empty() && self.module[..depth].ends_with(&target))
+        });
+        Some(match depth {
+            Some(depth) => {
+                let path = format!("crate::{}", self.module[..depth].join("::"));
+                let path: syn::Path = syn::parse_str(&path).ok()?;
+                syn::parse_quote!(pub(in #path))
+            }
+            None => syn::parse_quote!(pub(crate)),
+        })
+    }
+}
+
+impl VisitMut for Restrictions {
+    fn visit_item_mod_mut(&mut self, item_mod: &mut syn::ItemMod) {
+        self.visit_visibility_mut(&mut item_mod.vis);
+        let Some((_, items)) = &mut item_mod.content else {
+            return;
+        };
+        self.module.push(item_mod.ident.to_string());
+        for item in items {
+            self.visit_item_mut(item);
+        }
+        self.module.pop();
+    }
+
+    fn visit_visibility_mut(&mut self, vis: &mut syn::Visibility) {
+        let syn::Visibility::Restricted(restricted) = vis else {
+            return;
+        };
+        // Other paths are those of the 2015 edition, relative to the crate root
+        let relative = restricted.path.leading_colon.is_none()
+            && restricted.path.segments.first().is_some_and(|first| {
+                ["crate", "self", "super"]
+                    .iter()
+                    .any(|name| first.ident == name)
+            });
+        if let Some(fixed) = relative.then(|| self.fix(&restricted.path)).flatten() {
+            *vis = fixed;
+            self.fixed += 1;
+        }
+        visit_mut::visit_visibility_mut(self, vis);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+
+    fn fix(source: &str) -> (String, usize) {
+        let mut file = syn::parse_file(source).unwrap();
+        let fixed = fix_restricted(&mut file.items);
+        (file.to_token_stream().to_string(), fixed)
+    }
+
+    #[test]
+    fn test_ancestor_paths_are_kept() {
+        let source = r"
+            pub mod ai {
+                pub(in crate::ai) fn helper() {}
+                pub mod search {
+                    pub(super) fn up() {}
+                    pub(in super::super) fn root() {}
+                    pub(in crate::ai::search) struct Node { pub(self) depth: u8 }
+                    pub(crate) fn all() {}
+                }
+            }
+            fn main() { pub(in crate) fn local() {} }
+        ";
+        let (output, fixed) = fix(source);
+        assert_eq!(fixed, 0);
+        assert_eq!(
+            output,
+            syn::parse_file(source)
+                .unwrap()
+                .to_token_stream()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_moved_modules() {
+        let (output, fixed) = fix(r"
+            pub mod solver {
+                pub mod ai {
+                    pub(in crate::ai) fn helper() {}
+                    pub mod search { pub(in crate::ai) fn deep() {} }
+                }
+            }
+            mod tests {}
+            mod tests_2 {
+                mod fixtures { pub(in crate::tests) fn answer() {} }
+            }
+            pub(super) fn at_root() {}
+            ");
+        assert_eq!(fixed, 4);
+        assert!(output.contains("pub (in crate :: solver :: ai) fn helper"));
+        assert!(output.contains("pub (in crate :: solver :: ai) fn deep"));
+        assert!(output.contains("pub (crate) fn answer"));
+        assert!(output.contains("pub (crate) fn at_root"));
+    }
+}
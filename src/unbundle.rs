@@ -0,0 +1,255 @@
+use proc_macro2::{LineColumn, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::diagnostics::Span;
+use crate::error::{BundlerError, Result};
+use crate::transformer::attributes::{classify, AttributeClass};
+
+/// A source file recovered from a bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnbundledFile {
+    /// Path relative to the `src` directory, e.g. `ai/search.rs`
+    pub path: PathBuf,
+    pub code: String,
+}
+
+/// Split a bundle back into a module tree, inverting module expansion
+///
+/// The crate root (`main.rs`, or `lib.rs` when the bundle has no top-level
+/// `fn main`) keeps the root items; every inline `mod name { ... }` becomes
+/// `mod name;` with its items in `name.rs` next to its parent's file, or in
+/// `parent/name.rs` below the root. `#[cfg(test)]` modules stay inline, as they
+/// usually are in the sources. The text of the bundle is kept as is, comments and
+/// formatting included, with each module's indentation removed.
+///
+/// The library inlined into the bundle root cannot be told apart from the binary's
+/// own items, so it stays in the crate root.
+///
+/// # Errors
+/// Returns an error if `code` is not valid Rust
+pub fn unbundle(code: &str) -> Result<Vec<UnbundledFile>> {
+    let file = syn::parse_file(code).map_err(|e| BundlerError::SyntaxError {
+        message: format!("Failed to parse the bundle: {e}"),
+        file_path: None,
+        span: Span::of_syn_error(&e),
+    })?;
+    let has_main = file
+        .items
+        .iter()
+        .any(|item| matches!(item, syn::Item::Fn(function) if function.sig.ident == "main"));
+    let root = if has_main { "main.rs" } else { "lib.rs" };
+
+    let source = Source::new(code, &file);
+    let mut files = Vec::new();
+    source.split(
+        0..code.len(),
+        &file.items,
+        PathBuf::from(root),
+        Path::new(""),
+        &mut files,
+    );
+    // Parents before their modules
+    files.reverse();
+    Ok(files)
+}
+
+/// A bundle's text, indexed by line
+struct Source<'a> {
+    code: &'a str,
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+    /// Lines inside multi-line literals, which must not be re-indented
+    literal_lines: BTreeSet<usize>,
+}
+
+impl<'a> Source<'a> {
+    fn new(code: &'a str, file: &syn::File) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(code.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        let mut literal_lines = BTreeSet::new();
+        collect_literal_lines(file.to_token_stream(), &mut literal_lines);
+        Self {
+            code,
+            line_starts,
+            literal_lines,
+        }
+    }
+
+    /// Byte offset of a span position
+    fn offset(&self, position: LineColumn) -> usize {
+        let start = self.line_starts[position.line - 1];
+        self.code[start..]
+            .char_indices()
+            .nth(position.column)
+            .map_or(self.code.len(), |(index, _)| start + index)
+    }
+
+    /// Line (1-based) of a byte offset
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+
+    /// Add to `files` the file at `path` holding `range` of the bundle, which
+    /// contains `items`, and the files of its modules, in `dir`
+    fn split(
+        &self,
+        range: Range<usize>,
+        items: &[syn::Item],
+        path: PathBuf,
+        dir: &Path,
+        files: &mut Vec<UnbundledFile>,
+    ) {
+        let mut code = String::new();
+        let mut cursor = range.start;
+        for item in items {
+            let syn::Item::Mod(item_mod) = item else {
+                continue;
+            };
+            let Some((brace, content)) = &item_mod.content else {
+                continue;
+            };
+            let is_test = item_mod
+                .attrs
+                .iter()
+                .any(|attr| classify(attr) == AttributeClass::Test);
+            if is_test {
+                continue;
+            }
+
+            let open = self.offset(brace.span.open().start());
+            let close = self.offset(brace.span.close().start());
+            code.push_str(self.code[cursor..open].trim_end());
+            code.push(';');
+            cursor = close + 1;
+
+            let name = item_mod.ident.to_string();
+            let module_dir = dir.join(&name);
+            self.split(
+                open + 1..close,
+                content,
+                dir.join(format!("{name}.rs")),
+                &module_dir,
+                files,
+            );
+        }
+        code.push_str(&self.code[cursor..range.end]);
+
+        let first_line = self.line(range.start);
+        files.push(UnbundledFile {
+            path,
+            code: self.dedent(&code, first_line),
+        });
+    }
+
+    /// Remove the indentation common to the lines of `code`, which starts on
+    /// `first_line` of the bundle, and surrounding blank lines
+    fn dedent(&self, code: &str, first_line: usize) -> String {
+        let lines: Vec<(usize, &str)> = code
+            .split('\n')
+            .enumerate()
+            .map(|(index, line)| (first_line + index, line.trim_end()))
+            .collect();
+        let movable = |line: &usize| !self.literal_lines.contains(line);
+        let indent = lines
+            .iter()
+            .filter(|(number, line)| movable(number) && !line.trim().is_empty())
+            .map(|(_, line)| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+
+        let mut dedented: Vec<&str> = lines
+            .iter()
+            .map(|(number, line)| {
+                if movable(number) {
+                    line.get(indent..).unwrap_or_else(|| line.trim_start())
+                } else {
+                    line
+                }
+            })
+            .collect();
+        while dedented.last().is_some_and(|line| line.is_empty()) {
+            dedented.pop();
+        }
+        let start = dedented
+            .iter()
+            .position(|line| !line.is_empty())
+            .unwrap_or(dedented.len());
+        let mut code = dedented[start..].join("\n");
+        code.push('\n');
+        code
+    }
+}
+
+/// Lines after the first of every literal spanning several lines
+fn collect_literal_lines(tokens: TokenStream, lines: &mut BTreeSet<usize>) {
+    for tree in tokens {
+        match tree {
+            TokenTree::Literal(literal) => {
+                let span = literal.span();
+                lines.extend(span.start().line + 1..=span.end().line);
+            }
+            TokenTree::Group(group) => collect_literal_lines(group.stream(), lines),
+            TokenTree::Ident(_) | TokenTree::Punct(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unbundle_module_tree() {
+        let bundle = "\
+// Bundled bot
+mod ai {
+    pub mod search {
+        /// Search depth
+        pub fn depth() -> u32 {
+            let banner = \"two
+    lines\";
+            banner.len() as u32
+        }
+    }
+
+    pub use search::depth;
+}
+fn main() {
+    println!(\"{}\", ai::depth());
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn it_works() {}
+}
+";
+        let files = unbundle(bundle).unwrap();
+        let paths: Vec<&Path> = files.iter().map(|file| file.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("main.rs"),
+                Path::new("ai.rs"),
+                Path::new("ai/search.rs")
+            ]
+        );
+        assert_eq!(
+            files[0].code,
+            "// Bundled bot\nmod ai;\nfn main() {\n    println!(\"{}\", ai::depth());\n}\n\n#[cfg(test)]\nmod tests {\n    #[test]\n    fn it_works() {}\n}\n"
+        );
+        assert_eq!(files[1].code, "pub mod search;\n\npub use search::depth;\n");
+        assert_eq!(
+            files[2].code,
+            "/// Search depth\npub fn depth() -> u32 {\n    let banner = \"two\n    lines\";\n    banner.len() as u32\n}\n"
+        );
+
+        let library = unbundle("pub mod geo {\n    pub fn area() {}\n}\n").unwrap();
+        assert_eq!(library[0].path, Path::new("lib.rs"));
+        assert_eq!(library[0].code, "pub mod geo;\n");
+    }
+}
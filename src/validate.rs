@@ -0,0 +1,397 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::bundler::Bundler;
+use crate::cargo_project::{CargoProject, EntryTarget};
+use crate::config::{BundlerConfig, CONFIG_FILE_NAME};
+use crate::diagnostics::{code_frame, Span};
+use crate::file_manager::FileManager;
+use crate::plan::BundlePlan;
+use crate::transformer::{SourceMap, TransformConfig};
+
+/// Validation stage that reported a problem, in the order the stages run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProblemCategory {
+    /// Project layout, `Cargo.toml` syntax and bundler configuration, checked
+    /// without running cargo
+    Preflight,
+    /// `cargo metadata` and target selection
+    Metadata,
+    /// Rust syntax of the source files
+    Parse,
+    /// Module declarations without a source file
+    Structure,
+    /// Bundling and re-parsing the bundled output
+    Bundle,
+}
+
+impl ProblemCategory {
+    /// Every category, in stage order
+    pub const ALL: [Self; 5] = [
+        Self::Preflight,
+        Self::Metadata,
+        Self::Parse,
+        Self::Structure,
+        Self::Bundle,
+    ];
+
+    /// Lowercase name used in reports
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Preflight => "preflight",
+            Self::Metadata => "metadata",
+            Self::Parse => "parse",
+            Self::Structure => "structure",
+            Self::Bundle => "bundle",
+        }
+    }
+}
+
+/// A single problem found while validating a project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Problem {
+    pub category: ProblemCategory,
+    /// File the problem is located in, if any
+    pub path: Option<PathBuf>,
+    pub message: String,
+    /// Lines of code the problem is in, with the problem underlined
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.message),
+            None => write!(f, "{}", self.message),
+        }?;
+        self.excerpt
+            .as_ref()
+            .map_or(Ok(()), |excerpt| write!(f, "\n{}", excerpt.trim_end()))
+    }
+}
+
+/// Every problem found by `validate_project`, grouped by stage
+///
+/// Stages that depend on an earlier one that failed are skipped rather than
+/// reported as passing, e.g. no bundling is attempted when a file does not parse.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub problems: Vec<Problem>,
+    /// Stages that ran
+    pub checked: BTreeSet<ProblemCategory>,
+    /// The analyzed project, when `cargo metadata` succeeded
+    pub project: Option<CargoProject>,
+}
+
+impl ValidationReport {
+    /// Whether every stage ran without problems
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty() && self.checked.len() == ProblemCategory::ALL.len()
+    }
+
+    /// Number of problems reported by a stage
+    #[must_use]
+    pub fn count(&self, category: ProblemCategory) -> usize {
+        self.problems
+            .iter()
+            .filter(|problem| problem.category == category)
+            .count()
+    }
+
+    /// Table of problem counts per stage, with `skipped` for stages that did not run
+    #[must_use]
+    pub fn summary_table(&self) -> String {
+        let mut table = format!("{:<10} {:>8}\n", "Stage", "Problems");
+        for category in ProblemCategory::ALL {
+            let count = if self.checked.contains(&category) {
+                self.count(category).to_string()
+            } else {
+                "skipped".to_string()
+            };
+            let _ = writeln!(table, "{:<10} {count:>8}", category.name());
+        }
+        let _ = writeln!(table, "{:<10} {:>8}", "total", self.problems.len());
+        table
+    }
+
+    fn push(&mut self, category: ProblemCategory, path: Option<&Path>, message: String) {
+        self.problems.push(Problem {
+            category,
+            path: path.map(Path::to_path_buf),
+            message,
+            excerpt: None,
+        });
+    }
+
+    fn has_problems(&self, category: ProblemCategory) -> bool {
+        self.count(category) > 0
+    }
+}
+
+/// Check a project in stages and collect every problem instead of stopping at the
+/// first one
+///
+/// Source files are parsed even when the manifest is broken, so a single run
+/// reports manifest, metadata, syntax and module problems together. `target`
+/// selects the bundled binary or example, like `CargoProject::with_target`; files of
+/// the other test, bench and example targets are not checked.
+#[must_use]
+pub fn validate_project(
+    package_path: &Path,
+    target: &EntryTarget,
+    config: &TransformConfig,
+) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    let manifest_ok = preflight(&mut report, package_path);
+    if manifest_ok {
+        report.checked.insert(ProblemCategory::Metadata);
+        match CargoProject::with_target(package_path, target) {
+            Ok(project) => report.project = Some(project),
+            Err(e) => report.push(ProblemCategory::Metadata, None, e.to_string()),
+        }
+    }
+
+    let src_dir = report.project.as_ref().map_or_else(
+        || package_path.join("src"),
+        |project| project.base_path().to_path_buf(),
+    );
+    if src_dir.is_dir() {
+        let project = report.project.take();
+        parse_sources(&mut report, &src_dir, project.as_ref());
+        report.project = project;
+    }
+
+    let Some(project) = report.project.take() else {
+        return report;
+    };
+
+    report.checked.insert(ProblemCategory::Structure);
+    match BundlePlan::new(&project, config) {
+        Ok(plan) => {
+            for module in plan.missing_modules() {
+                report.push(
+                    ProblemCategory::Structure,
+                    None,
+                    format!(
+                        "Module '{}' is declared but has no source file",
+                        module.path
+                    ),
+                );
+            }
+        }
+        Err(e) => report.push(ProblemCategory::Structure, None, e.to_string()),
+    }
+
+    if !report.has_problems(ProblemCategory::Parse)
+        && !report.has_problems(ProblemCategory::Structure)
+        && report.checked.contains(&ProblemCategory::Parse)
+    {
+        report.checked.insert(ProblemCategory::Bundle);
+        match Bundler::with_config(config.clone()).bundle_project(&project) {
+            Ok(code) => {
+                if let Err(e) = syn::parse_file(&code) {
+                    // Line markers tell which source file the faulty lines came from
+                    let marked = (!config.line_markers)
+                        .then(|| {
+                            let config = TransformConfig {
+                                line_markers: true,
+                                ..config.clone()
+                            };
+                            Bundler::with_config(config).bundle_project(&project).ok()
+                        })
+                        .flatten();
+                    report
+                        .problems
+                        .push(invalid_bundle(&code, &e, marked.as_deref()));
+                }
+            }
+            Err(e) => report.push(ProblemCategory::Bundle, None, e.to_string()),
+        }
+    }
+
+    report.project = Some(project);
+    report
+}
+
+/// Problem for a bundle that does not parse, with the lines at `error` and the
+/// source file they came from
+///
+/// The file is found with the line markers of `marked`, the same bundle rendered
+/// with them, or of `code` itself when it has them.
+fn invalid_bundle(code: &str, error: &syn::Error, marked: Option<&str>) -> Problem {
+    let span = Span::of_syn_error(error);
+    let marked_span = marked.map_or(Some((code, span)), |marked| {
+        syn::parse_file(marked)
+            .err()
+            .map(|error| (marked, Span::of_syn_error(&error)))
+    });
+    let origin = marked_span
+        .and_then(|(marked, span)| {
+            SourceMap::from_output(marked)
+                .file_at_position(span.line, span.column)
+                .map(|file| format!(", in code from {file}"))
+        })
+        .unwrap_or_default();
+    Problem {
+        category: ProblemCategory::Bundle,
+        path: None,
+        message: format!(
+            "Generated code is not valid Rust at bundle line {}:{}{origin}: {error}",
+            span.line, span.column
+        ),
+        excerpt: Some(code_frame(code, span)),
+    }
+}
+
+/// Check the project layout and manifest syntax; returns whether `Cargo.toml` is usable
+fn preflight(report: &mut ValidationReport, package_path: &Path) -> bool {
+    report.checked.insert(ProblemCategory::Preflight);
+    if !package_path.is_dir() {
+        report.push(
+            ProblemCategory::Preflight,
+            Some(package_path),
+            "Project directory does not exist".to_string(),
+        );
+        return false;
+    }
+
+    let src_dir = package_path.join("src");
+    if !src_dir.is_dir() {
+        report.push(
+            ProblemCategory::Preflight,
+            Some(&src_dir),
+            "Source directory does not exist".to_string(),
+        );
+    }
+
+    let manifest_path = package_path.join("Cargo.toml");
+    let manifest = FileManager::try_read_file(&manifest_path);
+    let manifest_error = match manifest.map(|manifest| manifest.parse::<toml::Table>()) {
+        None => Some("Cargo.toml not found".to_string()),
+        Some(Err(e)) => Some(format!("Invalid manifest: {}", e.message())),
+        Some(Ok(_)) => None,
+    };
+    let manifest_ok = manifest_error.is_none();
+    if let Some(message) = manifest_error {
+        report.push(ProblemCategory::Preflight, Some(&manifest_path), message);
+    }
+
+    // An unparsable manifest was reported above; only check the bundler
+    // configuration it would have contained when it parses
+    if manifest_ok || FileManager::file_exists(package_path.join(CONFIG_FILE_NAME)) {
+        if let Err(e) = BundlerConfig::load(package_path) {
+            report.push(ProblemCategory::Preflight, None, e.to_string());
+        }
+    }
+    manifest_ok
+}
+
+/// Parse every `.rs` file under `src_dir`, reporting each syntax error with its location
+/// Parse every Rust file under `src_dir`, except those of the test, bench and
+/// example targets of `project`, which are not part of the bundle
+fn parse_sources(report: &mut ValidationReport, src_dir: &Path, project: Option<&CargoProject>) {
+    report.checked.insert(ProblemCategory::Parse);
+    let mut files: Vec<PathBuf> = WalkDir::new(src_dir)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .map(walkdir::DirEntry::into_path)
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        .filter(|path| !project.is_some_and(|project| project.is_auxiliary_source(path)))
+        .collect();
+    files.sort();
+
+    for file in files {
+        let parsed = FileManager::read_file(&file).map(|code| syn::parse_file(&code));
+        match parsed {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                let start = e.span().start();
+                report.push(
+                    ProblemCategory::Parse,
+                    Some(&file),
+                    format!("{}:{}: {e}", start.line, start.column + 1),
+                );
+            }
+            Err(e) => report.push(ProblemCategory::Parse, Some(&file), e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reports_manifest_and_parse_errors_together() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        fs::create_dir(project.join("src")).unwrap();
+        fs::write(project.join("Cargo.toml"), "[package\nname = \"broken\"\n").unwrap();
+        fs::write(project.join("src/main.rs"), "mod geo;\nfn main() {}\n").unwrap();
+        fs::write(project.join("src/geo.rs"), "pub fn area( -> u32 { 4 }\n").unwrap();
+
+        let report = validate_project(project, &EntryTarget::Default, &TransformConfig::default());
+        assert!(!report.is_ok());
+        assert_eq!(report.count(ProblemCategory::Preflight), 1);
+        assert_eq!(report.count(ProblemCategory::Parse), 1);
+        assert!(!report.checked.contains(&ProblemCategory::Metadata));
+        assert!(report.problems[1].message.starts_with("1:"));
+
+        let table = report.summary_table();
+        assert!(table.contains("metadata    skipped"));
+        assert!(table.contains("total             2"));
+    }
+
+    #[test]
+    fn test_reports_missing_modules_and_skips_bundling() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        fs::create_dir(project.join("src")).unwrap();
+        fs::write(
+            project.join("Cargo.toml"),
+            "[package]\nname = \"missing\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join("src/main.rs"),
+            "mod geo;\nmod ai;\nfn main() {}\n",
+        )
+        .unwrap();
+        fs::write(project.join("src/geo.rs"), "pub fn area() -> u32 { 4 }\n").unwrap();
+
+        let report = validate_project(project, &EntryTarget::Default, &TransformConfig::default());
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].category, ProblemCategory::Structure);
+        assert!(report.problems[0].message.contains("'ai'"));
+        assert!(!report.checked.contains(&ProblemCategory::Bundle));
+        assert!(report.project.is_some());
+    }
+
+    #[test]
+    fn test_invalid_bundle_located_in_source_file() {
+        let code = "mod geo {\n    pub fn area() -> u32 {\n        4 4\n    }\n}\nfn main() {}\n";
+        let marked = "// file: src/main.rs\n// file: src/geo.rs\nmod geo {\n    pub fn area() -> u32 {\n        4 4\n    }\n}\n// file: src/main.rs\nfn main() {}\n";
+        let error = syn::parse_file(code).unwrap_err();
+
+        let problem = invalid_bundle(code, &error, Some(marked));
+        assert_eq!(
+            problem.to_string(),
+            "Generated code is not valid Rust at bundle line 3:11, in code from src/geo.rs: \
+             unexpected token, expected `;`\n  |\n2 |     pub fn area() -> u32 {\n3 |         4 4\n  |           ^"
+        );
+        let unmarked = invalid_bundle(code, &error, None);
+        assert!(unmarked
+            .message
+            .ends_with("line 3:11: unexpected token, expected `;`"));
+    }
+}
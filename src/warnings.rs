@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Something the bundler left out of a bundle or could not make sense of, which
+/// may make the bundle behave differently from the project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BundleWarning {
+    /// A `mod name;` whose file could not be found, read or parsed, left as it is
+    SkippedModule {
+        /// Path of the module from the bundle root, e.g. `ai::search`
+        module: String,
+        reason: String,
+    },
+    /// A `use crate::...` naming nothing in the bundle
+    UnresolvedUse {
+        /// Module of the `use`, empty at the bundle root
+        module: String,
+        path: String,
+    },
+    /// An item removed because its `#[cfg(...)]` condition is false for the
+    /// features enabled with `TransformConfig::enabled_modules`
+    CfgStripped {
+        /// The item, e.g. `debug::fn dump`
+        item: String,
+        condition: String,
+    },
+    /// An `include!`, `include_str!` or `include_bytes!` whose file could not be
+    /// resolved, read or parsed, left as it is
+    UnresolvedInclude {
+        /// The macro, e.g. `include_str!`
+        invocation: String,
+        reason: String,
+    },
+    /// A define of `TransformConfig::defines` matching no const of the bundle
+    UnknownDefine { name: String },
+    /// A module left out by `TransformConfig::excluded_modules` or `only_modules`
+    ExcludedModule {
+        /// Path of the module from the bundle root
+        module: String,
+    },
+    /// An item renamed with `TransformConfig::rename_collisions` because another
+    /// item of its module binds the same name
+    RenamedCollision { collision: String },
+    /// Items of a file left unexpanded by `CodeTransformer::visit_file_mut`
+    ExpansionFailed { reason: String },
+    /// The build script failed with `Bundler::with_build_script_run`, so its
+    /// output was reproduced instead
+    BuildScriptFailed { reason: String },
+}
+
+impl fmt::Display for BundleWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SkippedModule { module, reason } => {
+                write!(f, "module `{module}` skipped: {reason}")
+            }
+            Self::UnresolvedUse { module, path } if module.is_empty() => {
+                write!(f, "unresolved import `{path}` at the bundle root")
+            }
+            Self::UnresolvedUse { module, path } => {
+                write!(f, "unresolved import `{path}` in module `{module}`")
+            }
+            Self::CfgStripped { item, condition } => {
+                write!(f, "{item} removed by #[cfg({condition})]")
+            }
+            Self::UnresolvedInclude { invocation, reason } => {
+                write!(f, "{invocation} left unchanged: {reason}")
+            }
+            Self::UnknownDefine { name } => write!(f, "no const named {name} to define"),
+            Self::ExcludedModule { module } => write!(f, "module `{module}` excluded"),
+            Self::RenamedCollision { collision } => {
+                write!(f, "renamed colliding item {collision}")
+            }
+            Self::ExpansionFailed { reason } => write!(f, "items left unexpanded: {reason}"),
+            Self::BuildScriptFailed { reason } => {
+                write!(f, "{reason}; reproducing the build script instead")
+            }
+        }
+    }
+}
+
+/// What `Bundler::bundle_with_report` noticed while bundling
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BundleReport {
+    /// In the order they were found
+    pub warnings: Vec<BundleWarning>,
+    /// Files inlined by `include!`, `include_str!` and `include_bytes!`, in the
+    /// order they were first read
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub included_files: Vec<PathBuf>,
+}
+
+impl BundleReport {
+    /// Whether bundling went without warnings
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
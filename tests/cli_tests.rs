@@ -173,6 +173,43 @@ fn test_function() {
             .stdout(predicate::str::contains("test_function"));
     }
 
+    #[test]
+    fn test_cli_restricted_visibility_of_renamed_test_module() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let project_path = temp_dir.path();
+        create_test_project(
+            project_path,
+            "restricted",
+            r"use restricted::ai;
+fn main() { ai::run(); }
+#[cfg(test)]
+mod tests {
+    mod fixtures {
+        pub(in crate::tests) fn answer() -> u32 { 2 }
+    }
+    #[test]
+    fn run() { assert_eq!(fixtures::answer(), 2); }
+}
+",
+        );
+        fs::write(
+            project_path.join("src/lib.rs"),
+            "pub mod ai {\n    pub(in crate::ai) fn helper() {}\n    pub fn run() { helper() }\n}\n#[cfg(test)]\nmod tests {}\n",
+        )
+        .expect("Failed to write lib.rs");
+
+        // The binary's `tests` becomes `tests_2`, which `crate::tests` does not name
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project_path)
+            .arg("--keep-tests")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("mod tests_2 {"))
+            .stdout(predicate::str::contains("pub(crate) fn answer() -> u32"))
+            .stdout(predicate::str::contains("pub(in crate::ai) fn helper() {}"));
+    }
+
     #[test]
     fn test_cli_with_keep_docs_flag() {
         let temp_dir = TempDir::new().expect("Failed to create temp directory");
@@ -531,402 +568,3347 @@ fn main() {
                     .not(),
             );
     }
-}
-
-/// Tests for watch mode functionality
-mod watch_mode_tests {
-    use super::*;
-    use std::time::Duration;
 
     #[test]
-    fn test_cli_watch_flag_exists() {
+    fn test_cli_with_verify_minify_flag() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "verify_minify_test",
+            "fn main() { let a = 1; let b = 2; println!(\"{}\", a < -b && a - -b > 0); }",
+        );
+
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
 
-        cmd.arg("--help")
+        cmd.current_dir(temp_dir.path())
+            .args(["--m2", "--verify-minify"])
             .assert()
             .success()
-            .stdout(predicate::str::contains("--watch"))
-            .stdout(predicate::str::contains("Watch for file changes"));
+            .stdout(predicate::str::contains("a< -b"));
     }
 
     #[test]
-    fn test_cli_watch_short_flag() {
-        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+    fn test_cli_lib_module_name() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "use bot::strategy::pick;\nmod strategy { pub fn local() {} }\nfn main() { strategy::local(); println!(\"{}\", pick()); }",
+        );
+        fs::write(temp_dir.path().join("src/lib.rs"), "pub mod strategy;\n")
+            .expect("Failed to write lib.rs");
+        fs::write(
+            temp_dir.path().join("src/strategy.rs"),
+            "pub fn pick() -> u32 { 7 }\n",
+        )
+        .expect("Failed to write strategy.rs");
 
-        cmd.arg("--help")
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--lib-module-name", "bot-lib"])
             .assert()
             .success()
-            .stdout(predicate::str::contains("-w, --watch"));
-    }
+            .stdout(predicate::str::contains("pub mod bot_lib {"))
+            .stdout(predicate::str::contains(
+                "use crate::bot_lib::strategy::pick;",
+            ));
 
-    #[test]
-    fn test_cli_src_dir_flag() {
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
-
-        cmd.arg("--help")
+        cmd.current_dir(temp_dir.path())
+            .args(["--lib-module-name", "not valid"])
             .assert()
-            .success()
-            .stdout(predicate::str::contains("--src-dir"))
-            .stdout(predicate::str::contains("Source directory to watch"));
+            .failure();
     }
 
     #[test]
-    fn test_cli_debounce_flag() {
-        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+    fn test_cli_vendor_dep_from_path() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "use grid_utils::Grid;\nfn main() { let grid = Grid::new(3); println!(\"{}\", grid_utils::area(&grid)); }",
+        );
+        let vendor_dir = temp_dir.path().join("vendor/grid-utils");
+        fs::create_dir_all(vendor_dir.join("src")).expect("Failed to create vendor dir");
+        fs::write(
+            vendor_dir.join("Cargo.toml"),
+            "[package]\nname = \"grid-utils\"\nversion = \"0.1.0\"\n\n[features]\ndefault = [\"square\"]\nsquare = []\nhuge = []\n",
+        )
+        .expect("Failed to write Cargo.toml");
+        fs::write(
+            vendor_dir.join("src/lib.rs"),
+            "#![no_std]\nmod grid;\npub use grid::Grid;\n#[cfg(feature = \"square\")]\npub fn area(grid: &Grid) -> usize { grid.side * grid.side }\n#[cfg(feature = \"huge\")]\npub fn huge() {}\n",
+        )
+        .expect("Failed to write lib.rs");
+        fs::write(
+            vendor_dir.join("src/grid.rs"),
+            "pub struct Grid { pub side: usize }\nimpl Grid { pub fn new(side: usize) -> Self { Self { side } } }\n",
+        )
+        .expect("Failed to write grid.rs");
 
-        cmd.arg("--help")
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--vendor-dep", "grid-utils=vendor/grid-utils"])
             .assert()
             .success()
-            .stdout(predicate::str::contains("--debounce"))
-            .stdout(predicate::str::contains("Debounce delay in milliseconds"));
-    }
+            .stdout(predicate::str::contains("pub mod grid_utils {"))
+            .stdout(predicate::str::contains("use crate::grid_utils::Grid;"))
+            .stdout(predicate::str::contains("::grid_utils::area("))
+            .stdout(predicate::str::contains("pub fn area"))
+            .stdout(predicate::str::contains("huge").not());
 
-    #[test]
-    fn test_watch_mode_with_invalid_project() {
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
-
-        cmd.arg("--watch")
-            .arg("nonexistent_project")
-            .timeout(Duration::from_secs(5))
+        cmd.current_dir(temp_dir.path())
+            .args(["--vendor-dep", "grid_utils"])
             .assert()
             .failure()
-            .stderr(predicate::str::contains("Error:"));
+            .stderr(predicate::str::contains("not a dependency"));
     }
 
     #[test]
-    fn test_watch_mode_shows_startup_messages() {
+    fn test_cli_plain_docs() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
-        create_test_project(temp_dir.path(), "watch_test", "fn main() {}");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "//! The bot\n\n/// Entry point\nfn main() {\n    // Say hello\n    println!(\"/// {}\", 1);\n}\n",
+        );
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .arg("--plain-docs")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("// The bot"))
+            .stdout(predicate::str::contains("// Entry point\nfn main()"))
+            .stdout(predicate::str::contains("println!(\"/// {}\", 1);"))
+            .stdout(predicate::str::contains("//!").not());
 
-        // Use timeout to avoid hanging - watch mode will run indefinitely
+        fs::write(
+            temp_dir.path().join("cg-bundler.toml"),
+            "docs-mode = \"convert-to-plain\"\n",
+        )
+        .expect("Failed to write cg-bundler.toml");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
         cmd.current_dir(temp_dir.path())
-            .arg("--watch")
-            .arg("-o")
-            .arg("output.rs")
-            .timeout(Duration::from_secs(2))
-            .assert();
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("// Entry point"));
 
-        // We expect this to timeout since watch mode runs forever
-        // The test passes if we don't get an immediate error
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .arg("--minify")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Entry point").not());
     }
 
     #[test]
-    fn test_watch_mode_with_custom_src_dir() {
+    fn test_cli_max_line_len() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
-        let project_path = temp_dir.path().join("custom_project");
-        fs::create_dir_all(&project_path).expect("Failed to create project dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};\nfn score(first_value: usize, second_value: usize, third_value: usize, label: &str) -> usize { first_value + second_value + third_value + label.len() }\nfn main() { let _ = (BTreeMap::<u8, u8>::new(), BTreeSet::<u8>::new(), BinaryHeap::<u8>::new(), HashMap::<u8, u8>::new(), HashSet::<u8>::new(), LinkedList::<u8>::new(), VecDeque::<u8>::new()); println!(\"{}\", score(1, 2, 3, \"a, very, long, label\")); }\n",
+        );
 
-        // Create a custom source directory
-        fs::create_dir_all(project_path.join("custom_src")).expect("Failed to create custom_src");
+        for style in ["--pretty", "--minify"] {
+            let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+            let output = cmd
+                .current_dir(temp_dir.path())
+                .args([style, "--max-line-len", "60"])
+                .output()
+                .expect("Failed to run cg-bundler");
+            assert!(output.status.success());
+            let code = String::from_utf8(output.stdout).expect("Output should be UTF-8");
+            assert!(code.contains("\"a, very, long, label\""));
+            assert!(
+                code.lines().all(|line| line.chars().count() <= 60),
+                "{style} output has long lines:\n{code}"
+            );
+            assert!(
+                syn::parse_file(&code).is_ok(),
+                "{style} output should parse"
+            );
+        }
+    }
 
-        fs::write(
-            project_path.join("Cargo.toml"),
-            r#"
-[package]
-name = "custom_project"
-version = "0.1.0"
-edition = "2021"
+    #[test]
+    fn test_cli_format_json() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "fn main() {\n    println!(\"hi\");\n}\n",
+        );
 
-[[bin]]
-name = "custom_project"
-path = "custom_src/main.rs"
-"#,
-        )
-        .expect("Failed to write Cargo.toml");
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args(["--format", "json"])
+            .output()
+            .expect("Failed to run cg-bundler");
+        assert!(output.status.success());
+        let result: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout should be JSON");
+        assert_eq!(result["success"], true);
+        assert!(result["code"]
+            .as_str()
+            .unwrap()
+            .contains("println!(\"hi\")"));
+        assert!(result["output"].is_null());
+        assert_eq!(result["warnings"], serde_json::json!([]));
+        assert_eq!(result["size"]["limit"], 100_000);
+        assert!(result["size"]["characters"].as_u64().unwrap() > 0);
+        assert!(result["timing"]["duration_ms"].is_u64());
+
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args(["--format", "json", "--info"])
+            .output()
+            .expect("Failed to run cg-bundler");
+        let info: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout should be JSON");
+        assert_eq!(info["name"], "bot");
+        assert_eq!(info["binary"]["name"], "bot");
+
+        fs::write(temp_dir.path().join("src/main.rs"), "fn main( {}\n").unwrap();
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args(["--format", "json", "--validate"])
+            .output()
+            .expect("Failed to run cg-bundler");
+        assert!(!output.status.success());
+        let report: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout should be a single JSON object");
+        assert_eq!(report["success"], false);
+        assert_eq!(report["problems"][0]["category"], "parse");
+        assert_eq!(report["skipped"], serde_json::json!(["bundle"]));
+    }
 
+    #[test]
+    fn test_cli_keep_comments() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
+        create_test_project(
+            project,
+            "comments_test",
+            "mod ai;\nfn main() {\n    // TODO tune this constant\n    let depth = ai::DEPTH; // plies\n    println!(\"{depth}\");\n}\n",
+        );
         fs::write(
-            project_path.join("custom_src/main.rs"),
-            "fn main() { println!(\"Custom source!\"); }",
+            project.join("src/ai.rs"),
+            "/* Search settings */\npub const DEPTH: u32 = 3;\n",
         )
-        .expect("Failed to write main.rs");
+        .expect("Failed to write ai.rs");
+
+        for args in [&["--keep-comments"][..], &["--keep-comments", "--pretty"]] {
+            let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+            cmd.arg(project)
+                .args(args)
+                .assert()
+                .success()
+                .stdout(predicate::str::contains("// TODO tune this constant"))
+                .stdout(predicate::str::contains("// plies"))
+                .stdout(predicate::str::contains("/* Search settings */"))
+                .stdout(predicate::str::contains("__cg_bundler_comment").not());
+        }
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(project)
+            .arg("--pretty")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("TODO").not());
+    }
 
-        // Test that watch mode accepts custom src dir
-        cmd.current_dir(&project_path)
-            .arg("--watch")
-            .arg("--src-dir")
-            .arg("custom_src")
-            .arg("-o")
-            .arg("output.rs")
-            .timeout(Duration::from_secs(2))
-            .assert();
-
-        // We expect this to timeout since watch mode runs forever
+    #[test]
+    fn test_cli_verbatim_region() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
+        create_test_project(
+            project,
+            "verbatim_test",
+            "mod table;\nfn main() {\n    let base = 1;\n    // cg-bundler: verbatim-start\n    let total = base  +  table::lookup(0);\n    // cg-bundler: verbatim-end\n    println!(\"{total}\");\n}\n",
+        );
+        let region = "// cg-bundler: verbatim-start\npub fn lookup(i: u32) -> u32 {\n    match i {\n        0 => 10,   1 => 11,   // aligned\n        _ => fallback(),\n    }\n}\n// cg-bundler: verbatim-end\n";
+        fs::write(
+            project.join("src/table.rs"),
+            format!("fn fallback() -> u32 {{ 7 }}\n\n{region}"),
+        )
+        .expect("Failed to write table.rs");
+
+        // Names the regions use are not renamed either
+        for args in [
+            &[][..],
+            &["--pretty"],
+            &["--minify", "--strip-unused"],
+            &["--mangle"],
+        ] {
+            let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+            cmd.arg(project)
+                .args(args)
+                .assert()
+                .success()
+                .stdout(predicate::str::contains(region))
+                .stdout(predicate::str::contains(
+                    "let total = base  +  table::lookup(0);\n",
+                ))
+                .stdout(predicate::str::contains("fn fallback"))
+                .stdout(predicate::str::contains("let base"))
+                .stdout(predicate::str::contains("__cg_bundler_verbatim").not());
+        }
     }
 
     #[test]
-    fn test_watch_mode_with_custom_debounce() {
+    fn test_cli_parse_error_diagnostic() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
-        create_test_project(temp_dir.path(), "debounce_test", "fn main() {}");
+        create_test_project(temp_dir.path(), "bot", "fn main() {\n    let x = 1\n}\n");
+
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args(["--format", "json"])
+            .output()
+            .expect("Failed to run cg-bundler");
+        assert!(!output.status.success());
+        let result: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("stdout should be JSON");
+        let diagnostic = &result["diagnostic"];
+        assert_eq!(diagnostic["code"], "CG0003");
+        assert_eq!(diagnostic["severity"], "error");
+        assert!(diagnostic["file"].as_str().unwrap().ends_with("main.rs"));
+        assert_eq!(diagnostic["span"]["line"], 3);
+        assert_eq!(diagnostic["span"]["column"], 1);
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
-
-        // Test that watch mode accepts custom debounce
         cmd.current_dir(temp_dir.path())
-            .arg("--watch")
-            .arg("--debounce")
-            .arg("1000")
-            .arg("-o")
-            .arg("output.rs")
-            .timeout(Duration::from_secs(2))
-            .assert();
-
-        // We expect this to timeout since watch mode runs forever
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("at line 3, column 1"))
+            .stderr(predicate::str::contains("main.rs:3:1"));
     }
-}
-
-/// Tests for additional CLI edge cases and functionality
-mod advanced_cli_tests {
-    use super::*;
 
     #[test]
-    fn test_cli_with_all_flags_combined() {
+    fn test_cli_define_removes_debug_logging() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
         create_test_project(
-            temp_dir.path(),
-            "all_flags_test",
-            "fn main() { println!(\"All flags!\"); }",
+            project,
+            "define_test",
+            "const DEBUG: bool = true;\nfn main() {\n    if DEBUG {\n        eprintln!(\"debug\");\n    }\n    println!(\"move\");\n}\n",
         );
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(project)
+            .args(["--define", "DEBUG=false"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("const DEBUG: bool = false;"))
+            .stdout(predicate::str::contains("eprintln!").not())
+            .stdout(predicate::str::contains("println!(\"move\")"));
 
-        cmd.current_dir(temp_dir.path())
+        fs::write(
+            project.join("cg-bundler.toml"),
+            "[defines]\nDEBUG = false\nVERBOSE = true\n",
+        )
+        .expect("Failed to write config");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(project)
             .arg("--verbose")
-            .arg("--keep-tests")
-            .arg("--keep-docs")
-            .arg("--minify")
-            .arg("-o")
-            .arg("all_flags_output.rs")
             .assert()
-            .success();
-
-        // Check that output file was created
-        assert!(temp_dir.path().join("all_flags_output.rs").exists());
-    }
-
-    #[test]
-    fn test_cli_info_command_shows_detailed_information() {
-        let temp_dir = TempDir::new().expect("Failed to create temp dir");
-        create_test_project(temp_dir.path(), "info_test", "fn main() {}");
+            .success()
+            .stdout(predicate::str::contains("eprintln!").not())
+            .stderr(predicate::str::contains("no const named VERBOSE"));
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
-
-        cmd.current_dir(temp_dir.path())
-            .arg("--info")
+        cmd.arg(project)
+            .args(["--define", "DEBUG"])
             .assert()
-            .success()
-            .stdout(predicate::str::contains("Project Information"))
-            .stdout(predicate::str::contains("Name: info_test"))
-            .stdout(predicate::str::contains("Version: 0.1.0"))
-            .stdout(predicate::str::contains("Targets"))
-            .stdout(predicate::str::contains("Binary"));
+            .failure()
+            .stderr(predicate::str::contains("expected NAME=VALUE"));
     }
 
     #[test]
-    fn test_cli_validate_command() {
+    fn test_cli_verbose_lists_bundle_warnings() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
         create_test_project(
             temp_dir.path(),
-            "validate_test",
-            "fn main() { println!(\"Valid!\"); }",
+            "warned",
+            "mod helper;\nuse crate::helper::{run, stop};\nfn main() { run(); }",
         );
+        fs::write(temp_dir.path().join("src/helper.rs"), "pub fn run() {}")
+            .expect("Failed to write helper.rs");
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
-
-        cmd.current_dir(temp_dir.path())
-            .arg("--validate")
+        cmd.arg(temp_dir.path())
             .assert()
             .success()
-            .stdout(predicate::str::contains("Project validation successful"));
-    }
-
-    #[test]
-    fn test_cli_validate_verbose() {
-        let temp_dir = TempDir::new().expect("Failed to create temp dir");
-        create_test_project(temp_dir.path(), "validate_verbose_test", "fn main() {}");
+            .stderr(predicate::str::contains(
+                "1 issue(s) found while bundling, run with --verbose to list them",
+            ));
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
-
-        cmd.current_dir(temp_dir.path())
-            .arg("--validate")
+        cmd.arg(temp_dir.path())
             .arg("--verbose")
             .assert()
             .success()
-            .stderr(predicate::str::contains("Validating project:"))
-            .stderr(predicate::str::contains("✓ Project structure is valid"))
-            .stderr(predicate::str::contains(
-                "✓ Project can be bundled successfully",
-            ))
             .stderr(predicate::str::contains(
-                "✓ Generated code is syntactically valid",
+                "unresolved import `crate::helper::stop` at the bundle root",
             ));
     }
 
     #[test]
-    fn test_cli_pretty_flag() {
+    fn test_cli_tree() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
         create_test_project(
             temp_dir.path(),
-            "pretty_test",
-            "fn main(){let x=5;println!(\"x is {}\",x);}",
+            "tree_test",
+            "mod helper;\nfn main() { helper::run(); }",
         );
+        fs::write(
+            temp_dir.path().join("src/helper.rs"),
+            "pub fn run() {}\nmod inner { pub struct Unit; }\n",
+        )
+        .expect("Failed to write helper.rs");
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
-
         cmd.current_dir(temp_dir.path())
-            .arg("--pretty")
-            .arg("-o")
-            .arg("pretty_output.rs")
+            .arg("--tree")
             .assert()
-            .success();
+            .success()
+            .stdout(predicate::str::starts_with("crate (src/main.rs, "))
+            .stdout(predicate::str::contains("└── helper (src/helper.rs, "))
+            .stdout(predicate::str::contains("    └── inner (src/helper.rs, "))
+            .stdout(predicate::str::contains("1 struct"))
+            .stdout(predicate::str::contains("2 source file(s)"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        let output = cmd
+            .current_dir(temp_dir.path())
+            .args(["--tree", "--format", "json"])
+            .output()
+            .expect("Failed to run");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+        assert_eq!(json["root"]["modules"][0]["path"], "helper");
+        assert_eq!(json["files"][1], "src/helper.rs");
+    }
+
+    #[test]
+    fn test_cli_emit_graph() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "graph_test",
+            "mod board;\nmod bot;\nfn main() { bot::play(); }",
+        );
+        fs::write(temp_dir.path().join("src/board.rs"), "pub struct Board;\n")
+            .expect("Failed to write board.rs");
+        fs::write(
+            temp_dir.path().join("src/bot.rs"),
+            "use crate::board::Board;\npub fn play() { let _ = Board; }\n",
+        )
+        .expect("Failed to write bot.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--emit-graph", "dot"])
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with("digraph modules {"))
+            .stdout(predicate::str::contains(
+                "m1 [label=\"board\\nsrc/board.rs\\n",
+            ))
+            .stdout(predicate::str::contains("    m0 -> m1;\n"))
+            .stdout(predicate::str::contains(
+                "    m0 -> m2 [style=dashed, label=\"play\"];\n",
+            ))
+            .stdout(predicate::str::contains(
+                "    m2 -> m1 [style=dashed, label=\"Board\"];\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--emit-graph", "mermaid"])
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with("flowchart TD\n"))
+            .stdout(predicate::str::contains("    m2 -.->|\"Board\"| m1\n"));
+    }
+
+    #[test]
+    fn test_cli_init_creates_project_ready_to_bundle() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["init", "my-bot"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Created bot project 'my-bot'"))
+            .stdout(predicate::str::contains("src/strategy.rs"));
+        let project = temp_dir.path().join("my-bot");
+        assert!(project.join(".vscode/tasks.json").is_file());
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(&project).arg("--check").assert().success();
+        let bundle = fs::read_to_string(project.join("bundle.rs")).expect("bundle.rs written");
+        assert!(bundle.contains("pub mod strategy {"));
+        assert!(!bundle.contains("my_bot::"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["init", "my-bot", "--template", "puzzle"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("not overwriting it"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["init", "other", "--name", "2fast"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("not a valid package name"));
+    }
+
+    #[test]
+    fn test_cli_analyze_duplicates() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "duplicates_test",
+            "mod a;\nmod b;\nfn main() { println!(\"{} {}\", a::score(&[1]), b::rate(&[2])); }",
+        );
+        fs::write(
+            temp_dir.path().join("src/a.rs"),
+            "pub fn score(cells: &[u32]) -> u32 {\n    let mut total = 0;\n    for cell in cells { if *cell > 3 { total += cell * 2; } }\n    total\n}\n",
+        )
+        .expect("Failed to write a.rs");
+        fs::write(
+            temp_dir.path().join("src/b.rs"),
+            "pub fn rate(values: &[u32]) -> u32 {\n    let mut sum = 0;\n    for value in values { if *value > 4 { sum += value * 2; } }\n    sum\n}\n",
+        )
+        .expect("Failed to write b.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .arg("--analyze-duplicates")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "1 group(s) of duplicated functions",
+            ))
+            .stdout(predicate::str::contains(
+                "Similar, but for names and literals: 2 x",
+            ))
+            .stdout(predicate::str::contains("  a::score (src/a.rs)"))
+            .stdout(predicate::str::contains("  b::rate (src/b.rs)"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        let output = cmd
+            .current_dir(temp_dir.path())
+            .args(["--analyze-duplicates", "--format", "json"])
+            .output()
+            .expect("Failed to run");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+        assert_eq!(json["groups"][0]["kind"], "similar");
+        assert!(json["recoverable"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_cli_hook_install() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let git_init = std::process::Command::new("git")
+            .arg("init")
+            .arg("-q")
+            .current_dir(temp_dir.path())
+            .status();
+        if !git_init.is_ok_and(|status| status.success()) {
+            return;
+        }
+        create_test_project(&temp_dir.path().join("bot"), "hook_test", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["hook", "install", "bot", "--output-path", "out/bundle.rs"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Installed the pre-commit hook"));
+        let hook_path = temp_dir.path().join(".git/hooks/pre-commit");
+        let hook = fs::read_to_string(&hook_path).expect("hook written");
+        assert!(hook.contains("cd \"$(git rev-parse --show-toplevel)\"/bot || exit 1"));
+        assert!(hook.contains("cg-bundler --output out/bundle.rs || exit 1"));
+        assert!(hook.contains("git add -- out/bundle.rs"));
+
+        fs::write(&hook_path, "#!/bin/sh\ncargo fmt --check\n").expect("Failed to write hook");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path().join("bot"))
+            .args(["hook", "install", "--check"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("use --append"));
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path().join("bot"))
+            .args(["hook", "install", "--check", "--append"])
+            .assert()
+            .success();
+        let hook = fs::read_to_string(&hook_path).expect("hook written");
+        assert!(hook.starts_with("#!/bin/sh\ncargo fmt --check\n"));
+        assert!(hook.contains("cg-bundler --check-output bundle.rs || exit 1"));
+    }
+
+    #[test]
+    fn test_cli_verbose_prints_phase_timings() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "timed",
+            "mod helper;\nfn main() { helper::run(); }",
+        );
+        fs::write(
+            temp_dir.path().join("src/helper.rs"),
+            "pub fn run() {\n    println!(\"run\");\n}\n",
+        )
+        .expect("Failed to write helper.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(temp_dir.path())
+            .arg("--verbose")
+            .assert()
+            .success()
+            .stderr(predicate::str::is_match(
+                r"Timings: cargo metadata: \d+ ms, parse: \d+ ms, transform: \d+ ms, emit: \d+ ms, format: \d+ ms \(total \d+ ms\), 2 files, 5 lines",
+            ).expect("valid regex"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(temp_dir.path())
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("Timings:").not());
+    }
+}
+
+/// Tests for watch mode functionality
+mod watch_mode_tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cli_watch_flag_exists() {
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.arg("--help")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("--watch"))
+            .stdout(predicate::str::contains("Watch for file changes"));
+    }
+
+    #[test]
+    fn test_cli_watch_short_flag() {
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.arg("--help")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("-w, --watch"));
+    }
+
+    #[test]
+    fn test_cli_src_dir_flag() {
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.arg("--help")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("--src-dir"))
+            .stdout(predicate::str::contains("Source directory to watch"));
+    }
+
+    #[test]
+    fn test_cli_debounce_flag() {
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.arg("--help")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("--debounce"))
+            .stdout(predicate::str::contains("Debounce delay in milliseconds"));
+    }
+
+    #[test]
+    fn test_watch_mode_with_invalid_project() {
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.arg("--watch")
+            .arg("nonexistent_project")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Error:"));
+    }
+
+    #[test]
+    fn test_watch_mode_shows_startup_messages() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "watch_test", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        // Use timeout to avoid hanging - watch mode will run indefinitely
+        cmd.current_dir(temp_dir.path())
+            .arg("--watch")
+            .arg("-o")
+            .arg("output.rs")
+            .timeout(Duration::from_secs(2))
+            .assert();
+
+        // We expect this to timeout since watch mode runs forever
+        // The test passes if we don't get an immediate error
+    }
+
+    #[test]
+    fn test_watch_mode_with_custom_src_dir() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project_path = temp_dir.path().join("custom_project");
+        fs::create_dir_all(&project_path).expect("Failed to create project dir");
+
+        // Create a custom source directory
+        fs::create_dir_all(project_path.join("custom_src")).expect("Failed to create custom_src");
+
+        fs::write(
+            project_path.join("Cargo.toml"),
+            r#"
+[package]
+name = "custom_project"
+version = "0.1.0"
+edition = "2021"
+
+[[bin]]
+name = "custom_project"
+path = "custom_src/main.rs"
+"#,
+        )
+        .expect("Failed to write Cargo.toml");
+
+        fs::write(
+            project_path.join("custom_src/main.rs"),
+            "fn main() { println!(\"Custom source!\"); }",
+        )
+        .expect("Failed to write main.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        // Test that watch mode accepts custom src dir
+        cmd.current_dir(&project_path)
+            .arg("--watch")
+            .arg("--src-dir")
+            .arg("custom_src")
+            .arg("-o")
+            .arg("output.rs")
+            .timeout(Duration::from_secs(2))
+            .assert();
+
+        // We expect this to timeout since watch mode runs forever
+    }
+
+    #[test]
+    fn test_watch_mode_with_custom_debounce() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "debounce_test", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        // Test that watch mode accepts custom debounce
+        cmd.current_dir(temp_dir.path())
+            .arg("--watch")
+            .arg("--debounce")
+            .arg("1000")
+            .arg("-o")
+            .arg("output.rs")
+            .timeout(Duration::from_secs(2))
+            .assert();
+
+        // We expect this to timeout since watch mode runs forever
+    }
+
+    #[test]
+    fn test_cli_notify_port_requires_watch() {
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg("--help")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("--notify-port <PORT>"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.args(["--notify-port", "9000"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--watch"));
+    }
+
+    #[test]
+    fn test_cli_tui_needs_watch_and_output() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "tui_test", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg("--tui")
+            .arg(temp_dir.path())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--watch"));
+
+        // Without an output file, or without the `tui` feature
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.args(["--watch", "--tui"])
+            .arg(temp_dir.path())
+            .timeout(Duration::from_secs(10))
+            .assert()
+            .failure()
+            .stderr(
+                predicate::str::contains("--tui needs an output file")
+                    .or(predicate::str::contains("enable the `tui` feature")),
+            );
+    }
+
+    #[test]
+    fn test_cli_submit_on_success_needs_watch() {
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.args(["--submit-on-success", "onboarding"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--watch"));
+    }
+}
+
+/// Tests for additional CLI edge cases and functionality
+mod advanced_cli_tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_with_all_flags_combined() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "all_flags_test",
+            "fn main() { println!(\"All flags!\"); }",
+        );
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--verbose")
+            .arg("--keep-tests")
+            .arg("--keep-docs")
+            .arg("--minify")
+            .arg("-o")
+            .arg("all_flags_output.rs")
+            .assert()
+            .success();
+
+        // Check that output file was created
+        assert!(temp_dir.path().join("all_flags_output.rs").exists());
+    }
+
+    #[test]
+    fn test_cli_info_command_shows_detailed_information() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "info_test", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--info")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Project Information"))
+            .stdout(predicate::str::contains("Name: info_test"))
+            .stdout(predicate::str::contains("Version: 0.1.0"))
+            .stdout(predicate::str::contains("Targets"))
+            .stdout(predicate::str::contains("Binary"));
+    }
+
+    #[test]
+    fn test_cli_validate_command() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "validate_test",
+            "fn main() { println!(\"Valid!\"); }",
+        );
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--validate")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Project validation successful"));
+    }
+
+    #[test]
+    fn test_cli_validate_verbose() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "validate_verbose_test", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--validate")
+            .arg("--verbose")
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("Validating project:"))
+            .stderr(predicate::str::contains("✓ Project structure is valid"))
+            .stderr(predicate::str::contains(
+                "✓ Project can be bundled successfully",
+            ))
+            .stderr(predicate::str::contains(
+                "✓ Generated code is syntactically valid",
+            ));
+    }
+
+    #[test]
+    fn test_cli_pretty_flag() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "pretty_test",
+            "fn main(){let x=5;println!(\"x is {}\",x);}",
+        );
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--pretty")
+            .arg("-o")
+            .arg("pretty_output.rs")
+            .assert()
+            .success();
 
         // Read the output file and check it's properly formatted
         let output_content = fs::read_to_string(temp_dir.path().join("pretty_output.rs"))
             .expect("Failed to read output file");
 
-        // Should have proper formatting (if rustfmt is available)
-        assert!(output_content.contains("fn main()"));
+        // Should have proper formatting (if rustfmt is available)
+        assert!(output_content.contains("fn main()"));
+    }
+
+    #[test]
+    fn test_cli_invalid_flag_combination() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "invalid_combo_test", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        // Test that minify and pretty can't be used together effectively
+        // (both should work, but pretty should be ignored when minify is used)
+        cmd.current_dir(temp_dir.path())
+            .arg("--minify")
+            .arg("--pretty")
+            .arg("-o")
+            .arg("combo_output.rs")
+            .assert()
+            .success();
+
+        // Check that output file was created
+        assert!(temp_dir.path().join("combo_output.rs").exists());
+    }
+
+    #[test]
+    fn test_cli_output_to_stdout() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "stdout_test",
+            "fn main() { println!(\"Hello\"); }",
+        );
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("fn main()"))
+            .stdout(predicate::str::contains("println!"));
+    }
+
+    #[test]
+    fn test_cli_with_project_path_and_output() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project_name = "path_and_output_test";
+        let project_path = temp_dir.path().join(project_name);
+
+        // Create the project directory first
+        fs::create_dir_all(&project_path).expect("Failed to create project directory");
+        create_test_project(&project_path, project_name, "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        let output_path = temp_dir.path().join("bundled_output.rs");
+
+        cmd.arg(&project_path)
+            .arg("-o")
+            .arg(&output_path)
+            .assert()
+            .success();
+
+        // Check that output file was created in the correct location
+        assert!(output_path.exists());
+    }
+
+    #[test]
+    fn test_cli_m2_aggressive_minify() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "m2_test",
+            "fn main() { let x = 5 + 3; println!(\"Result: {}\", x); }",
+        );
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--m2")
+            .arg("-o")
+            .arg("m2_output.rs")
+            .assert()
+            .success();
+
+        // Read the output and verify aggressive minification
+        let output_content = fs::read_to_string(temp_dir.path().join("m2_output.rs"))
+            .expect("Failed to read output file");
+
+        // Should have aggressive minification (no spaces around operators)
+        assert!(output_content.contains("5+3"));
+    }
+
+    #[test]
+    fn test_cli_no_expand_modules() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project_path = temp_dir.path().join("no_expand_test");
+        fs::create_dir_all(&project_path).expect("Failed to create project dir");
+
+        // Create a project with modules
+        fs::write(
+            project_path.join("Cargo.toml"),
+            r#"
+[package]
+name = "no_expand_test"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .expect("Failed to write Cargo.toml");
+
+        fs::create_dir_all(project_path.join("src")).expect("Failed to create src");
+
+        fs::write(
+            project_path.join("src/main.rs"),
+            "mod utils;\nfn main() { utils::hello(); }",
+        )
+        .expect("Failed to write main.rs");
+
+        fs::write(
+            project_path.join("src/utils.rs"),
+            "pub fn hello() { println!(\"Hello from utils!\"); }",
+        )
+        .expect("Failed to write utils.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(&project_path)
+            .arg("--no-expand-modules")
+            .arg("-o")
+            .arg("no_expand_output.rs")
+            .assert()
+            .success();
+
+        // Read the output and verify modules were not expanded
+        let output_content = fs::read_to_string(project_path.join("no_expand_output.rs"))
+            .expect("Failed to read output file");
+
+        // Should still contain module declaration
+        assert!(output_content.contains("mod utils"));
+    }
+
+    #[test]
+    fn test_cli_strip_unused() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "strip_test",
+            "fn used() -> i32 { 1 }\nfn unused_helper() -> i32 { 2 }\nfn main() { println!(\"{}\", used()); }",
+        );
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--strip-unused")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("fn used"))
+            .stdout(predicate::str::contains("unused_helper").not());
+    }
+
+    #[test]
+    fn test_cli_reads_config_file() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "config_test",
+            "fn used() -> i32 { 1 }\nfn unused_helper() -> i32 { 2 }\nfn main() { println!(\"{}\", used()); }",
+        );
+        fs::write(
+            temp_dir.path().join("cg-bundler.toml"),
+            "output = \"bundle.rs\"\nstrip-unused = true\n",
+        )
+        .expect("Failed to write config");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path()).assert().success();
+
+        let output = fs::read_to_string(temp_dir.path().join("bundle.rs"))
+            .expect("Output file should exist");
+        assert!(output.contains("fn used"));
+        assert!(!output.contains("unused_helper"));
+    }
+
+    #[test]
+    fn test_cli_default_output_has_no_source_comments() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "comments_test",
+            "fn main() {\n    // secret note\n    let x = 1; /* inline */\n    \n    println!(\"{x}\");\n}\n",
+        );
+
+        let bundle = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+            let output = cmd
+                .current_dir(temp_dir.path())
+                .args(args)
+                .output()
+                .expect("Failed to run");
+            assert!(output.status.success());
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+
+        for args in [&[][..], &["--preserve-formatting"]] {
+            let stdout = bundle(args);
+            assert!(!stdout.contains("secret note"), "{stdout}");
+            assert!(!stdout.contains("inline"), "{stdout}");
+            assert!(
+                stdout.lines().all(|line| line.trim_end() == line),
+                "{stdout}"
+            );
+        }
+        let stdout = bundle(&["--preserve-formatting", "--keep-comments"]);
+        assert!(stdout.contains("// secret note"), "{stdout}");
+    }
+
+    #[test]
+    fn test_cli_flags_override_config_file() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "config_override_test",
+            "fn used() -> i32 { 1 }\nfn unused_helper() -> i32 { 2 }\nfn main() { println!(\"{}\", used()); }",
+        );
+        fs::write(
+            temp_dir.path().join("cg-bundler.toml"),
+            "minify = true\nstrip-unused = true\n",
+        )
+        .expect("Failed to write config");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        let output = cmd
+            .current_dir(temp_dir.path())
+            .arg("--no-minify")
+            .arg("--no-strip-unused")
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("unused_helper"));
+        assert!(stdout.trim().lines().count() > 1);
+
+        // The last of a flag and its negation wins
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        let output = cmd
+            .current_dir(temp_dir.path())
+            .arg("--no-minify")
+            .arg("--minify")
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .lines()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_cli_explain_cache() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "cache_test",
+            "fn helper() -> i32 { 1 }\nfn main() { assert_eq!(helper(), 1); }",
+        );
+        let bundle = || {
+            let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+            let output = cmd
+                .current_dir(temp_dir.path())
+                .arg("--explain-cache")
+                .output()
+                .expect("Failed to run");
+            assert!(output.status.success());
+            (
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            )
+        };
+
+        let (first, stderr) = bundle();
+        assert!(stderr.contains("Cache: rebuilt, no cached bundle"));
+        let (second, stderr) = bundle();
+        assert!(stderr.contains("Cache: reused"));
+        assert_eq!(first, second);
+
+        // Changing the configuration alone invalidates the cached bundle
+        fs::write(temp_dir.path().join("cg-bundler.toml"), "minify = true\n")
+            .expect("Failed to write config");
+        let (minified, stderr) = bundle();
+        assert!(stderr.contains("Cache: rebuilt, the configuration fingerprint changed"));
+        assert_eq!(minified.trim().lines().count(), 1);
+
+        fs::write(
+            temp_dir.path().join("src/main.rs"),
+            "fn main() { assert_eq!(2, 2); }",
+        )
+        .expect("Failed to write source");
+        let (_, stderr) = bundle();
+        assert!(stderr.contains("Cache: rebuilt, changed inputs: src/main.rs"));
+        let (_, stderr) = bundle();
+        assert!(stderr.contains("Cache: reused"));
+    }
+
+    #[test]
+    fn test_cli_cache_tracks_included_files() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "cache_include_test",
+            "const MAP: &str = include_str!(\"map.txt\");\nfn main() { print!(\"{MAP}\"); }",
+        );
+        fs::write(temp_dir.path().join("src/map.txt"), "#.#").expect("Failed to write map.txt");
+        let bundle = || {
+            let output = Command::cargo_bin("cg-bundler")
+                .expect("Binary should exist")
+                .current_dir(temp_dir.path())
+                .arg("--explain-cache")
+                .output()
+                .expect("Failed to run");
+            assert!(output.status.success());
+            (
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            )
+        };
+
+        let (first, _) = bundle();
+        assert!(first.contains("\"#.#\""));
+        let (_, stderr) = bundle();
+        assert!(stderr.contains("Cache: reused"));
+
+        fs::write(temp_dir.path().join("src/map.txt"), "..#").expect("Failed to write map.txt");
+        let (rebuilt, stderr) = bundle();
+        assert!(stderr.contains("Cache: rebuilt, changed inputs: src/map.txt"));
+        assert!(rebuilt.contains("\"..#\""));
+    }
+
+    #[test]
+    fn test_cli_rejects_invalid_config_file() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "bad_config_test", "fn main() {}");
+        fs::write(temp_dir.path().join("cg-bundler.toml"), "unknown-key = 1\n")
+            .expect("Failed to write config");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Configuration error"));
+    }
+
+    #[test]
+    fn test_cli_dry_run() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "dry_run_test",
+            "mod helper;\nmod missing;\nfn main() { helper::run(); }",
+        );
+        fs::write(temp_dir.path().join("src/helper.rs"), "pub fn run() {}")
+            .expect("Failed to write helper.rs");
+        let output_path = temp_dir.path().join("bundle.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .args(["--dry-run", "--m2", "-o"])
+            .arg(&output_path)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("helper"))
+            .stdout(predicate::str::contains("missing (file not found)"))
+            .stdout(predicate::str::contains("aggressive minify"))
+            .stdout(predicate::str::contains("bundle.rs"))
+            .stdout(predicate::str::contains("fn main").not());
+
+        assert!(!output_path.exists(), "Dry run must not write the output");
+    }
+
+    #[test]
+    fn test_cli_line_markers_and_source_map() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "markers_test",
+            "mod helper;\nfn main() { helper::run(); }",
+        );
+        fs::write(temp_dir.path().join("src/helper.rs"), "pub fn run() {}")
+            .expect("Failed to write helper.rs");
+        let map_path = temp_dir.path().join("bundle.map.json");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--source-map")
+            .arg(&map_path)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("// file: src/main.rs"))
+            .stdout(predicate::str::contains("// file: src/helper.rs"))
+            .stdout(predicate::str::contains("__cg_bundler_file").not());
+
+        let map = fs::read_to_string(&map_path).expect("Source map should exist");
+        assert!(map.contains("\"file\": \"src/helper.rs\""));
+        assert!(map.contains("\"start_line\": 1"));
+    }
+
+    #[test]
+    fn test_cli_all_bins() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
+        fs::create_dir_all(project.join("src/bin")).expect("Failed to create src/bin");
+        fs::write(
+            project.join("Cargo.toml"),
+            r#"[package]
+name = "contest"
+version = "0.1.0"
+edition = "2021"
+
+[[bin]]
+name = "referee"
+path = "src/bin/referee.rs"
+
+[[bin]]
+name = "bot"
+path = "src/bin/bot.rs"
+"#,
+        )
+        .expect("Failed to write Cargo.toml");
+        fs::write(project.join("src/lib.rs"), "pub mod board;\n").expect("Failed to write lib.rs");
+        fs::write(project.join("src/board.rs"), "pub fn size() -> u32 { 9 }\n")
+            .expect("Failed to write board.rs");
+        fs::write(
+            project.join("src/bin/referee.rs"),
+            "use contest::board;\nfn main() { println!(\"referee {}\", board::size()); }\n",
+        )
+        .expect("Failed to write referee.rs");
+        fs::write(
+            project.join("src/bin/bot.rs"),
+            "use contest::board::size;\nfn main() { println!(\"bot {}\", size()); }\n",
+        )
+        .expect("Failed to write bot.rs");
+        let out_dir = project.join("out");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(project)
+            .args(["--all-bins", "--out-dir"])
+            .arg(&out_dir)
+            .assert()
+            .success();
+
+        for (name, expected) in [("referee", "referee {}"), ("bot", "bot {}")] {
+            let code = fs::read_to_string(out_dir.join(format!("{name}.rs")))
+                .expect("Bundle should be written for every binary");
+            assert!(code.contains(expected));
+            assert!(code.contains("pub fn size"));
+            assert!(!code.contains("mod board;"));
+        }
+
+        // The library shared by the binaries is checked like the one of a single bundle
+        fs::create_dir_all(project.join("src/board")).expect("Failed to create src/board");
+        fs::write(
+            project.join("src/board/mod.rs"),
+            "pub fn size() -> u32 { 9 }\n",
+        )
+        .expect("Failed to write board/mod.rs");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(project)
+            .args(["--all-bins", "--out-dir"])
+            .arg(&out_dir)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "File for module 'board' found at both",
+            ));
+    }
+
+    #[test]
+    fn test_cli_all_bins_conflicts_with_output() {
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.args(["--all-bins", "-o", "bundle.rs"])
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_cli_determinism_check() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "determinism_test",
+            "mod helper;\nfn main() { helper::run(); }",
+        );
+        fs::write(temp_dir.path().join("src/helper.rs"), "pub fn run() {}")
+            .expect("Failed to write helper.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .args(["--determinism-check", "3", "--strip-unused"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("identical across 3 runs"));
+    }
+
+    #[test]
+    fn test_cli_extract_demos() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "demos_test",
+            "mod shapes;\nfn main() { println!(\"{}\", shapes::area()); }",
+        );
+        fs::write(
+            temp_dir.path().join("src/shapes.rs"),
+            "pub fn area() -> u32 { 4 }\n#[cfg(test)]\nfn demo() { println!(\"demo {}\", area()); }\n",
+        )
+        .expect("Failed to write shapes.rs");
+        let demo_dir = temp_dir.path().join("examples");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--extract-demos")
+            .arg(&demo_dir)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("pub fn area"))
+            .stdout(predicate::str::contains("fn demo").not());
+
+        let example = fs::read_to_string(demo_dir.join("shapes_demo.rs"))
+            .expect("Demo example should be written");
+        assert!(example.contains("fn demo()"));
+        assert!(example.contains("crate::shapes::demo();"));
+        assert!(!example.contains("cfg(test)"));
+    }
+
+    #[test]
+    fn test_cli_crate_name_override() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "vendored_bot",
+            "use bot::*;\nfn main() { println!(\"{}\", strategy::pick()); }",
+        );
+        fs::write(temp_dir.path().join("src/lib.rs"), "pub mod strategy;\n")
+            .expect("Failed to write lib.rs");
+        fs::write(
+            temp_dir.path().join("src/strategy.rs"),
+            "pub fn pick() -> u32 { 7 }\n",
+        )
+        .expect("Failed to write strategy.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("pub fn pick").not());
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--crate-name", "bot"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("pub fn pick"))
+            .stdout(predicate::str::contains("use bot").not());
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--crate-name", "not valid"])
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_cli_bin_selects_binary_target() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
+        fs::create_dir_all(project.join("src/bin")).expect("Failed to create src/bin");
+        fs::write(
+            project.join("Cargo.toml"),
+            r#"[package]
+name = "contest"
+version = "0.1.0"
+edition = "2021"
+
+[[bin]]
+name = "referee"
+path = "src/bin/referee.rs"
+
+[[bin]]
+name = "bot"
+path = "src/bin/bot.rs"
+"#,
+        )
+        .expect("Failed to write Cargo.toml");
+        fs::write(
+            project.join("src/bin/referee.rs"),
+            "fn main() { println!(\"referee\"); }\n",
+        )
+        .expect("Failed to write referee.rs");
+        fs::write(
+            project.join("src/bin/bot.rs"),
+            "fn main() { println!(\"bot\"); }\n",
+        )
+        .expect("Failed to write bot.rs");
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--bin NAME"));
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .args(["--bin", "bot"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("println!(\"bot\")"))
+            .stdout(predicate::str::contains("referee").not());
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .args(["--bin", "player"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("No binary target named 'player'"));
+    }
+
+    #[test]
+    fn test_cli_attest_writes_provenance() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "attest_test",
+            "mod helper;\nfn main() { helper::run(); }",
+        );
+        fs::write(temp_dir.path().join("src/helper.rs"), "pub fn run() {}")
+            .expect("Failed to write helper.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs", "--attest", "bundle.intoto.json"])
+            .assert()
+            .success();
+
+        let bundle = fs::read(temp_dir.path().join("bundle.rs")).expect("Bundle should be written");
+        let attestation: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(temp_dir.path().join("bundle.intoto.json"))
+                .expect("Attestation should be written"),
+        )
+        .expect("Attestation should be valid JSON");
+
+        assert_eq!(
+            attestation["predicateType"],
+            "https://slsa.dev/provenance/v1"
+        );
+        assert_eq!(
+            attestation["subject"][0]["digest"]["sha256"],
+            cg_bundler::digest::sha256_hex(&bundle)
+        );
+        let inputs: Vec<&str> = attestation["predicate"]["buildDefinition"]["resolvedDependencies"]
+            .as_array()
+            .expect("Inputs should be listed")
+            .iter()
+            .filter_map(|input| input["uri"].as_str())
+            .collect();
+        assert_eq!(inputs, ["Cargo.toml", "src/main.rs", "src/helper.rs"]);
+        assert!(
+            attestation["predicate"]["runDetails"]["metadata"]["startedOn"]
+                .as_str()
+                .is_some_and(|time| time.ends_with('Z'))
+        );
+    }
+
+    #[test]
+    fn test_cli_attest_lists_included_files_and_config() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "attest_include_test",
+            "const MAP: &str = include_str!(\"map.txt\");\nfn main() { print!(\"{MAP}\"); }",
+        );
+        fs::write(temp_dir.path().join("src/map.txt"), "#.#\n").expect("Failed to write map.txt");
+        fs::write(
+            temp_dir.path().join("cg-bundler.toml"),
+            "keep-docs = true\n",
+        )
+        .expect("Failed to write cg-bundler.toml");
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs", "--attest", "bundle.intoto.json"])
+            .assert()
+            .success();
+
+        let attestation: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(temp_dir.path().join("bundle.intoto.json"))
+                .expect("Attestation should be written"),
+        )
+        .expect("Attestation should be valid JSON");
+        let inputs: Vec<&str> = attestation["predicate"]["buildDefinition"]["resolvedDependencies"]
+            .as_array()
+            .expect("Inputs should be listed")
+            .iter()
+            .filter_map(|input| input["uri"].as_str())
+            .collect();
+        assert_eq!(
+            inputs,
+            [
+                "Cargo.toml",
+                "src/main.rs",
+                "cg-bundler.toml",
+                "src/map.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cli_size_report_lists_source_files() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "size_report_test",
+            "mod helper;\nfn main() { helper::run(); }",
+        );
+        fs::write(
+            temp_dir.path().join("src/helper.rs"),
+            "pub fn run() { println!(\"a fairly long line of output from the helper\"); }",
+        )
+        .expect("Failed to write helper.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--size-report")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Bundle size:"))
+            .stdout(predicate::str::contains("of the CodinGame limit of 100000"))
+            .stdout(
+                predicate::str::is_match(
+                    r"\d+\s+\d+\.\d%\s+src/helper\.rs\n\s+\d+\s+\d+\.\d%\s+src/main\.rs",
+                )
+                .unwrap(),
+            );
+    }
+
+    #[test]
+    fn test_cli_max_size_fails_oversized_bundle() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "max_size_test", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs", "--max-size", "5"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("over the maximum of 5"));
+        assert!(!temp_dir.path().join("bundle.rs").exists());
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .args(["--max-size", "1000"])
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn test_cli_split_output() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "split_test",
+            "mod board;\nmod bot;\nfn main() { bot::play(board::Board::default()); }",
+        );
+        fs::write(
+            temp_dir.path().join("src/board.rs"),
+            "#[derive(Default)]\npub struct Board {\n    pub cells: Vec<u8>,\n}\n",
+        )
+        .expect("Failed to write board.rs");
+        fs::write(
+            temp_dir.path().join("src/bot.rs"),
+            "use crate::board::Board;\npub fn play(board: Board) {\n    println!(\"{}\", board.cells.len());\n}\n",
+        )
+        .expect("Failed to write bot.rs");
+
+        fs::create_dir(temp_dir.path().join("out")).expect("Failed to create out");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "out/main.rs", "--split-output", "150", "--check"])
+            .assert()
+            .success();
+        let root = fs::read_to_string(temp_dir.path().join("out/main.rs")).unwrap();
+        assert!(root.contains("include!(\"part1.rs\");"), "{root}");
+        assert!(!root.contains("mod board"), "{root}");
+        let parts: Vec<String> = ["part1.rs", "part2.rs"]
+            .iter()
+            .map(|part| fs::read_to_string(temp_dir.path().join("out").join(part)).unwrap())
+            .collect();
+        assert!(parts[0].starts_with("mod board {"), "{}", parts[0]);
+        assert!(parts[1].starts_with("mod bot {"), "{}", parts[1]);
+        assert!(parts.iter().all(|part| part.len() <= 150));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--split-output", "150"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("set it with --output"));
+    }
+
+    #[test]
+    fn test_cli_update_in_place_keeps_header() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "update_test",
+            "fn main() { first(); }\nfn first() {}",
+        );
+        let bundle_path = temp_dir.path().join("bundle.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs", "--update-in-place"])
+            .assert()
+            .success();
+        let created = fs::read_to_string(&bundle_path).expect("Bundle should be written");
+        let sentinel = cg_bundler::file_manager::GENERATED_SENTINEL;
+        assert!(created.starts_with(sentinel));
+
+        fs::write(&bundle_path, format!("// Strategy notes\n{created}"))
+            .expect("Failed to edit header");
+        fs::write(
+            temp_dir.path().join("src/main.rs"),
+            "fn main() { second(); }\nfn second() {}",
+        )
+        .expect("Failed to update main.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs", "--update-in-place"])
+            .assert()
+            .success();
+        let updated = fs::read_to_string(&bundle_path).expect("Bundle should be written");
+        assert!(updated.starts_with(&format!("// Strategy notes\n{sentinel}\n")));
+        assert!(updated.contains("fn second()"));
+        assert!(!updated.contains("fn first()"));
+    }
+
+    #[test]
+    fn test_cli_update_in_place_requires_sentinel() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "sentinel_test", "fn main() {}");
+        fs::write(temp_dir.path().join("bundle.rs"), "// notes\nfn old() {}\n")
+            .expect("Failed to write bundle.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs", "--update-in-place"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("no generated-code sentinel line"));
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("bundle.rs")).expect("File should remain"),
+            "// notes\nfn old() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_cli_check_reports_compile_errors_by_module() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "check_test",
+            "mod helper;\nfn main() { helper::run(); }",
+        );
+        fs::write(
+            temp_dir.path().join("src/helper.rs"),
+            "pub fn run() { undefined_function(); }",
+        )
+        .expect("Failed to write helper.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs", "--check"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("error[E0425]"))
+            .stderr(predicate::str::contains("--> src/helper.rs (bundle line"))
+            .stderr(predicate::str::contains(
+                "Bundle does not compile: 1 error(s)",
+            ));
+
+        fs::write(temp_dir.path().join("src/helper.rs"), "pub fn run() {}")
+            .expect("Failed to write helper.rs");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs", "--check", "-m"])
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn test_cli_output_template_and_latest_symlink() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "template_test", "fn main() {}");
+
+        for flag in ["--m2", "--pretty"] {
+            let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+            cmd.current_dir(temp_dir.path())
+                .args([
+                    flag,
+                    "--output-template",
+                    "bundles/{crate}-{profile}-{hash}.rs",
+                    "--latest-symlink",
+                ])
+                .assert()
+                .success();
+        }
+
+        let bundles = temp_dir.path().join("bundles");
+        assert!(bundles.join("template_test-m2-unknown.rs").is_file());
+        let newest = bundles.join("template_test-pretty-unknown.rs");
+        assert!(newest.is_file());
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(bundles.join("latest.rs")).expect("Link should exist"),
+            Path::new("template_test-pretty-unknown.rs")
+        );
+        assert_eq!(
+            fs::read_to_string(bundles.join("latest.rs")).expect("Link should resolve"),
+            fs::read_to_string(newest).expect("Bundle should be readable")
+        );
+    }
+
+    #[test]
+    fn test_cli_output_template_rejects_unknown_variable() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "bad_template_test", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--output-template", "{crate}-{version}.rs"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("unknown variable '{version}'"));
+    }
+
+    #[test]
+    fn test_cli_inlines_build_script_output() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "build_script_test",
+            "include!(concat!(env!(\"OUT_DIR\"), \"/table.rs\"));\nfn main() { assert_eq!(TABLE.len(), 3); }",
+        );
+        fs::write(
+            temp_dir.path().join("build.rs"),
+            r#"
+use std::{env, fs, path::Path};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("table.rs"), "const TABLE: [u32; 3] = [2, 3, 5];").unwrap();
+}
+"#,
+        )
+        .expect("Failed to write build.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "const TABLE: [u32; 3] = [2, 3, 5];",
+            ))
+            .stdout(predicate::str::contains("include!").not());
+    }
+
+    #[test]
+    fn test_cli_run_build_script_for_complex_scripts() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "run_build_script_test",
+            "include!(concat!(env!(\"OUT_DIR\"), \"/squares.rs\"));\nfn main() { assert_eq!(SQUARES[2], 9); }",
+        );
+        fs::write(
+            temp_dir.path().join("build.rs"),
+            r#"
+fn main() {
+    let squares: Vec<String> = (1..=3).map(|i| (i * i).to_string()).collect();
+    let code = format!("const SQUARES: [u32; 3] = [{}];", squares.join(", "));
+    std::fs::write(format!("{}/squares.rs", std::env::var("OUT_DIR").unwrap()), code).unwrap();
+}
+"#,
+        )
+        .expect("Failed to write build.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .arg("--verbose")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "include!(concat!(env!(\"OUT_DIR\")",
+            ))
+            .stderr(predicate::str::contains("use --run-build-script"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .arg("--run-build-script")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "const SQUARES: [u32; 3] = [1, 4, 9];",
+            ));
+    }
+
+    #[test]
+    fn test_cli_out_dir_hint_resolves_previous_build() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path().join("parser");
+        create_test_project(
+            &project,
+            "parser",
+            "include!(concat!(env!(\"OUT_DIR\"), \"/tables.rs\"));\nfn main() { println!(\"{}\", STATES); }",
+        );
+        let out_dir = temp_dir
+            .path()
+            .join("target/debug/build/parser-0f1e2d3c/out");
+        fs::create_dir_all(&out_dir).expect("Failed to create OUT_DIR");
+        fs::write(out_dir.join("tables.rs"), "const STATES: usize = 12;")
+            .expect("Failed to write tables.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(&project)
+            .arg("--out-dir-hint")
+            .arg(temp_dir.path().join("target"))
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("const STATES: usize = 12;"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(&project)
+            .arg("--out-dir-hint")
+            .arg(temp_dir.path().join("nowhere"))
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("is not a directory"));
+    }
+
+    #[test]
+    fn test_cli_footer_from_flag_and_config() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "footer_bot", "fn main() {}");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--footer", "// {crate} ({profile})"])
+            .assert()
+            .success()
+            .stdout(predicate::str::ends_with("}\n// footer_bot (default)\n"));
+
+        fs::write(
+            temp_dir.path().join("cg-bundler.toml"),
+            "footer-file = \"FOOTER.txt\"\n\n[footers]\nm2 = \"// minified {crate}\"\n",
+        )
+        .expect("Failed to write config");
+        fs::write(
+            temp_dir.path().join("FOOTER.txt"),
+            "// Strategy changelog\n// - v2: {bin}\n",
+        )
+        .expect("Failed to write footer");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::ends_with(
+                "// Strategy changelog\n// - v2: footer_bot\n",
+            ));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .arg("--m2")
+            .assert()
+            .success()
+            .stdout(predicate::str::ends_with("\n// minified footer_bot\n"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--footer", "// {version}"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("unknown variable '{version}'"));
+    }
+
+    #[test]
+    fn test_cargo_subcommand_bundles_enclosing_package() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "subcommand_bot",
+            "fn main() { println!(\"subcommand\"); }",
+        );
+
+        let mut cmd = Command::cargo_bin("cargo-cg-bundle").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path().join("src"))
+            .env_remove("CARGO_MANIFEST_DIR")
+            .args(["cg-bundle", "--m2"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("println!(\"subcommand\")"));
+
+        let mut cmd = Command::cargo_bin("cargo-cg-bundle").expect("Binary should exist");
+        cmd.args(["cg-bundle", "--help"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Usage: cargo cg-bundle"));
+    }
+
+    #[test]
+    fn test_cli_no_manifest_from_stdin() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        fs::write(
+            temp_dir.path().join("geo.rs"),
+            "pub fn area() -> u32 { 6 }\n",
+        )
+        .expect("Failed to write geo.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--no-manifest", "-"])
+            .write_stdin("mod geo;\nfn main() {\n    println!(\"{}\", geo::area());\n}\n")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("mod geo {"))
+            .stdout(predicate::str::contains("pub fn area() -> u32"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--no-manifest", "--info"])
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_cli_enable_module() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "use bot::ai::Strategy;\nfn main() {\n    println!(\"{}\", Strategy::NAME);\n}\n",
+        );
+        fs::create_dir_all(temp_dir.path().join("src/ai")).expect("Failed to create ai");
+        fs::write(temp_dir.path().join("src/lib.rs"), "pub mod ai;\n")
+            .expect("Failed to write lib.rs");
+        fs::write(
+            temp_dir.path().join("src/ai.rs"),
+            "#[cfg(feature = \"splash\")]\npub mod splash;\n#[cfg(feature = \"cover\")]\npub mod cover;\n#[cfg(feature = \"cover\")]\npub use cover::Cover as Strategy;\n#[cfg(not(feature = \"cover\"))]\npub use splash::Splash as Strategy;\n",
+        )
+        .expect("Failed to write ai.rs");
+        fs::write(
+            temp_dir.path().join("src/ai/splash.rs"),
+            "pub struct Splash;\nimpl Splash { pub const NAME: &str = \"splash\"; }\n",
+        )
+        .expect("Failed to write splash.rs");
+        fs::write(
+            temp_dir.path().join("src/ai/cover.rs"),
+            "pub struct Cover;\nimpl Cover { pub const NAME: &str = \"cover\"; }\n",
+        )
+        .expect("Failed to write cover.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--enable-module", "ai::cover=cover"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "pub use cover::Cover as Strategy;",
+            ))
+            .stdout(predicate::str::contains("Splash").not())
+            .stdout(predicate::str::contains("feature").not());
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--enable-module", "ai::cover=splash"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "Module 'ai::cover' is not in the bundle with feature 'splash' enabled",
+            ));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--enable-module", "ai::cover"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("expected PATH=FEATURE"));
+    }
+
+    #[test]
+    fn test_cli_enable_module_keeps_default_features() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "mod ai;\nfn main() {\n    ai::log();\n    println!(\"{}\", ai::NAME);\n}\n",
+        );
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"bot\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[features]\ndefault = [\"splash\", \"logging\"]\nsplash = []\ncover = []\nlogging = []\n",
+        )
+        .expect("Failed to write Cargo.toml");
+        fs::write(
+            temp_dir.path().join("src/ai.rs"),
+            "#[cfg(feature = \"logging\")]\npub fn log() {}\n#[cfg(feature = \"cover\")]\npub mod cover {\n    pub const NAME: &str = \"cover\";\n}\n#[cfg(feature = \"cover\")]\npub use cover::NAME;\n",
+        )
+        .expect("Failed to write ai.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--enable-module", "ai::cover=cover"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("pub fn log()"))
+            .stdout(predicate::str::contains("pub use cover::NAME;"));
+    }
+
+    #[test]
+    fn test_cli_verify_output() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "mod geo;\nfn main() {\n    println!(\"{}\", geo::area());\n}\n",
+        );
+        fs::write(
+            temp_dir.path().join("src/geo.rs"),
+            "pub fn area() -> u32 { 4 }\npub fn perimeter() -> u32 { 8 }\n",
+        )
+        .expect("Failed to write geo.rs");
+        let bundle_path = temp_dir.path().join("bundle.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--pretty", "-o", "bundle.rs"])
+            .assert()
+            .success();
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .arg("--pretty")
+            .arg("verify-output")
+            .arg(&bundle_path)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("matches the current sources"));
+
+        fs::write(
+            temp_dir.path().join("src/geo.rs"),
+            "pub fn area() -> u32 { 5 }\n",
+        )
+        .expect("Failed to write geo.rs");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .arg("--pretty")
+            .arg("verify-output")
+            .arg(&bundle_path)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("✗ src/geo.rs"))
+            .stderr(predicate::str::contains("✗ geo::fn perimeter"))
+            .stderr(predicate::str::contains("src/main.rs").not())
+            .stderr(predicate::str::contains(
+                "does not match the current sources",
+            ));
+    }
+
+    #[test]
+    fn test_cli_verify_output_rejects_other_operations() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "bot", "fn main() {}\n");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--watch", "verify-output", "bundle.rs"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "the argument '--watch' cannot be used with a subcommand",
+            ));
+    }
+
+    #[test]
+    fn test_cli_merge_imports() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "use std::collections::HashMap;\nuse std::fmt::Write;\nuse bot::counts;\nfn main() {\n    let mut out = String::new();\n    let map: HashMap<u8, u8> = counts(&[1, 1]);\n    write!(out, \"{}\", map[&1]).unwrap();\n    println!(\"{out}\");\n}\n",
+        );
+        fs::write(
+            temp_dir.path().join("src/lib.rs"),
+            "use std::collections::{self, HashMap};\nuse std::io::Write;\npub fn counts(values: &[u8]) -> HashMap<u8, u8> {\n    let mut map = collections::HashMap::new();\n    for v in values { *map.entry(*v).or_insert(0) += 1; }\n    let _ = std::io::sink().write(&[]);\n    map\n}\n",
+        )
+        .expect("Failed to write lib.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        let output = cmd
+            .current_dir(temp_dir.path())
+            .arg("--merge-imports")
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        let code = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(code.matches("use std").count(), 1, "{code}");
+        assert_eq!(code.matches("HashMap,").count(), 1, "{code}");
+        assert!(code.contains("Write as _"), "{code}");
+    }
+
+    #[test]
+    fn test_cli_bench() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bench_test",
+            "mod helper;\nfn main() { helper::run(); }",
+        );
+        fs::write(temp_dir.path().join("src/helper.rs"), "pub fn run() {}")
+            .expect("Failed to write helper.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["bench", "2"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Benchmark (2 runs)"))
+            .stdout(predicate::str::contains("total"))
+            .stdout(predicate::str::contains("metadata"))
+            .stdout(predicate::str::contains("transform"))
+            .stdout(predicate::str::contains("peak RSS"))
+            .stdout(predicate::str::contains("fn main").not());
+    }
+
+    #[test]
+    fn test_cli_stats() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "stats_test",
+            "mod helper;\nfn main() { helper::run(); }",
+        );
+        fs::write(
+            temp_dir.path().join("src/helper.rs"),
+            "pub fn run() {}\npub fn never_called() { println!(\"a long unused function body\"); }\n",
+        )
+        .expect("Failed to write helper.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--stats", "--max-size", "200"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Health: "))
+            .stdout(predicate::str::contains("Dependencies  std only"))
+            .stdout(predicate::str::contains("Recommendations:"))
+            .stdout(predicate::str::contains("not reachable from fn main"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        let output = cmd
+            .current_dir(temp_dir.path())
+            .args(["--stats", "--format", "json"])
+            .output()
+            .expect("Failed to run");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+        assert_eq!(json["budget"], 100_000);
+        assert!(json["dead_characters"].as_u64().unwrap() > 0);
+        assert!(json["grade"].is_string());
+    }
+
+    #[test]
+    fn test_cli_ignores_tests_and_bundles_examples() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::create_dir_all(project.join("examples")).unwrap();
+        fs::write(
+            project.join("Cargo.toml"),
+            "[package]\nname = \"flat\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[[test]]\nname = \"integration\"\npath = \"src/integration.rs\"\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join("src/lib.rs"),
+            "pub fn answer() -> u32 { 42 }\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join("src/main.rs"),
+            "fn main() { println!(\"{}\", flat::answer()); }\n",
+        )
+        .unwrap();
+        fs::write(project.join("src/integration.rs"), "fn broken( {\n").unwrap();
+        fs::write(
+            project.join("examples/replay.rs"),
+            "use flat::answer;\nfn main() { println!(\"replay {}\", answer()); }\n",
+        )
+        .unwrap();
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .arg("--validate")
+            .assert()
+            .success();
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .args(["--include-examples", "replay"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("replay {}"))
+            .stdout(predicate::str::contains("fn answer()"));
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .args(["--include-examples", "missing"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Available examples: replay"));
+    }
+
+    #[test]
+    fn test_cli_name_collisions() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "use bot::solve;\n\nfn helper() -> u32 {\n    1\n}\n\nfn main() {\n    println!(\"{}\", solve() + helper());\n}\n",
+        );
+        fs::write(
+            temp_dir.path().join("src/lib.rs"),
+            "pub fn solve() -> u32 {\n    helper()\n}\n\nfn helper() -> u32 {\n    41\n}\n",
+        )
+        .expect("Failed to write lib.rs");
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "`helper` in crate: src/lib.rs:5, src/main.rs:3",
+            ));
+
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .arg("--rename-collisions")
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        let code = String::from_utf8_lossy(&output.stdout);
+        assert!(code.contains("fn helper_2() -> u32"), "{code}");
+        assert!(code.contains("solve() + helper_2()"), "{code}");
+        assert!(
+            code.contains("pub fn solve() -> u32 {\n    helper()\n}"),
+            "{code}"
+        );
+    }
+
+    #[test]
+    fn test_cli_tests_under_cfg() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "gated",
+            "fn double(x: u32) -> u32 {\n    x * 2\n}\n\nfn main() {\n    println!(\"{}\", double(21));\n}\n\n#[cfg(test)]\nmod tests {\n    use super::*;\n\n    #[test]\n    fn doubles() {\n        assert_eq!(double(2), 4);\n    }\n}\n",
+        );
+
+        for (args, gate) in [
+            (vec!["--tests-under-cfg"], "#[cfg(local_tests)]"),
+            (
+                vec!["--tests-under-cfg", "debug_tests"],
+                "#[cfg(debug_tests)]",
+            ),
+        ] {
+            let output = Command::cargo_bin("cg-bundler")
+                .expect("Binary should exist")
+                .current_dir(temp_dir.path())
+                .args(&args)
+                .output()
+                .expect("Failed to run");
+            assert!(output.status.success());
+            let code = String::from_utf8_lossy(&output.stdout);
+            assert!(code.contains(&format!("{gate}\nmod tests {{")), "{code}");
+            assert!(code.contains("fn doubles()"), "{code}");
+            assert!(!code.contains("cfg(test)"), "{code}");
+        }
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args(["--tests-under-cfg", "not a name"])
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_cli_expand_macros_bundles_generated_modules() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "strategies",
+            "#[macro_use]\nmod macros;\n\ndecl_strategies! { alpha, beta }\n\nfn main() {\n    println!(\"{}\", alpha::score() + beta::score());\n}\n",
+        );
+        fs::write(
+            temp_dir.path().join("src/macros.rs"),
+            "macro_rules! decl_strategies {\n    ($($name:ident),*) => {\n        $(mod $name;)*\n    };\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            temp_dir.path().join("src/alpha.rs"),
+            "pub fn score() -> u32 {\n    1\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            temp_dir.path().join("src/beta.rs"),
+            "pub fn score() -> u32 {\n    2\n}\n",
+        )
+        .unwrap();
+
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .arg("--expand-macros")
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        let code = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            code.contains("mod alpha {\n    pub fn score() -> u32"),
+            "{code}"
+        );
+        assert!(
+            code.contains("mod beta {\n    pub fn score() -> u32"),
+            "{code}"
+        );
+        assert!(!code.contains("decl_strategies! {"), "{code}");
+
+        // Without the flag the invocation is left as is
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run");
+        let code = String::from_utf8_lossy(&output.stdout);
+        assert!(code.contains("decl_strategies! {"), "{code}");
+    }
+
+    #[test]
+    fn test_cli_header_from_flag_template_and_config() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "header_bot", "fn main() {}");
+
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .arg("--header")
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        let code = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = code.lines().collect();
+        assert!(lines[0].starts_with("// header_bot 0.1.0 ("), "{code}");
+        assert!(lines[1].starts_with("// Built 20"), "{code}");
+        assert!(
+            lines[1].ends_with(&format!("Z with cg-bundler {}", env!("CARGO_PKG_VERSION"))),
+            "{code}"
+        );
+        assert_eq!(lines[2], "fn main() {}");
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args([
+                "--m2",
+                "--header-template",
+                "{crate} v{crate_version}\n{profile}",
+            ])
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with(
+                "// header_bot v0.1.0\n// m2\nfn main(){}",
+            ));
+
+        fs::write(
+            temp_dir.path().join("cg-bundler.toml"),
+            "header-template = \"// {bin}\"\n",
+        )
+        .expect("Failed to write config");
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with("// header_bot\nfn main() {}"));
+    }
+
+    #[test]
+    fn test_cli_exclude_and_only_modules() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "pruned",
+            "mod ai;\nmod debug;\nmod bin_helpers;\n\nfn main() {\n    println!(\"{}\", ai::search::depth());\n}\n",
+        );
+        let src = temp_dir.path().join("src");
+        fs::create_dir_all(src.join("ai")).unwrap();
+        fs::create_dir_all(src.join("bin_helpers")).unwrap();
+        fs::write(src.join("ai.rs"), "pub mod search;\npub mod eval;\n").unwrap();
+        fs::write(
+            src.join("ai/search.rs"),
+            "pub fn depth() -> u32 {\n    3\n}\n",
+        )
+        .unwrap();
+        fs::write(src.join("ai/eval.rs"), "pub fn eval() {}\n").unwrap();
+        // Excluded files are never read, so they need not even parse
+        fs::write(src.join("debug.rs"), "this is not rust").unwrap();
+        fs::write(
+            src.join("bin_helpers.rs"),
+            "pub mod io;\npub fn help() {}\n",
+        )
+        .unwrap();
+        fs::write(src.join("bin_helpers/io.rs"), "not rust either").unwrap();
+
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args([
+                "--verbose",
+                "--exclude-module",
+                "debug",
+                "--exclude-module",
+                "bin_helpers::*",
+            ])
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        let code = String::from_utf8_lossy(&output.stdout);
+        assert!(!code.contains("mod debug"), "{code}");
+        assert!(
+            code.contains("mod bin_helpers {\n    pub fn help() {}\n}"),
+            "{code}"
+        );
+        assert!(code.contains("pub mod eval"), "{code}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("module `bin_helpers::io` excluded"),
+            "{stderr}"
+        );
+
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args(["--only-module", "ai::search"])
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        let code = String::from_utf8_lossy(&output.stdout);
+        assert!(code.contains("pub mod search {"), "{code}");
+        assert!(!code.contains("eval"), "{code}");
+        assert!(!code.contains("bin_helpers"), "{code}");
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .args(["--exclude-module", "ai/search"])
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_cli_unbundle_writes_module_files() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let bundle_path = temp_dir.path().join("bundle.rs");
+        fs::write(
+            &bundle_path,
+            "mod ai {\n    pub mod search {\n        pub fn depth() -> u32 {\n            3\n        }\n    }\n}\nfn main() {\n    println!(\"{}\", ai::search::depth());\n}\n",
+        )
+        .expect("Failed to write bundle");
+        let project = temp_dir.path().join("project");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Failed to find binary");
+        cmd.arg("unbundle").arg(&bundle_path).arg(&project);
+        cmd.assert()
+            .success()
+            .stderr(predicate::str::contains("Wrote"));
+
+        let main = fs::read_to_string(project.join("src/main.rs")).expect("main.rs");
+        assert!(main.starts_with("mod ai;\nfn main()"));
+        let ai = fs::read_to_string(project.join("src/ai.rs")).expect("ai.rs");
+        assert_eq!(ai, "pub mod search;\n");
+        let search = fs::read_to_string(project.join("src/ai/search.rs")).expect("search.rs");
+        assert_eq!(search, "pub fn depth() -> u32 {\n    3\n}\n");
+
+        // Existing files are left alone
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Failed to find binary");
+        cmd.arg("unbundle").arg(&bundle_path).arg(&project);
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("Refusing to overwrite"));
+    }
+
+    #[test]
+    fn test_cli_binary_modules_use_library_and_crate_paths() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
+        create_test_project(
+            project,
+            "contest",
+            "use contest::Game;\nuse crate::helpers::x;\nmod helpers;\nfn main() { println!(\"{} {}\", Game::new().0, x()); }\n",
+        );
+        fs::write(
+            project.join("src/lib.rs"),
+            "pub struct Game(pub u32);\nimpl Game { pub fn new() -> Self { Game(1) } }\n",
+        )
+        .expect("Failed to write lib.rs");
+        fs::write(
+            project.join("src/helpers.rs"),
+            "use contest::Game;\npub fn x() -> u32 { Game::new().0 + crate::helpers::y() }\npub fn y() -> u32 { contest::Game::new().0 }\n",
+        )
+        .expect("Failed to write helpers.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        let output = cmd.arg(project).output().expect("Failed to run bundler");
+        assert!(output.status.success());
+        let code = String::from_utf8(output.stdout).expect("Bundle should be UTF-8");
+        assert_eq!(code.matches("pub struct Game").count(), 1);
+        assert!(code.contains("use crate::Game;"));
+        assert!(code.contains("crate::helpers::y()"));
+        assert!(code.contains("crate::Game::new().0"));
+        assert!(!code.contains("contest::"));
+    }
+
+    #[test]
+    fn test_cli_jobs_bundle_matches_sequential() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
+        create_test_project(
+            project,
+            "jobs_test",
+            "mod a;\nmod b;\nfn main() { a::run(); b::run(); }\n",
+        );
+        fs::create_dir_all(project.join("src/a")).expect("Failed to create a/");
+        fs::write(
+            project.join("src/a.rs"),
+            "mod inner;\npub fn run() { inner::go(); }\n",
+        )
+        .expect("Failed to write a.rs");
+        fs::write(project.join("src/a/inner.rs"), "pub fn go() {}\n")
+            .expect("Failed to write inner.rs");
+        fs::write(project.join("src/b.rs"), "pub fn run() {}\n").expect("Failed to write b.rs");
+
+        let bundle = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+            let output = cmd.arg(project).args(args).output().expect("Failed to run");
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).expect("Bundle should be UTF-8")
+        };
+        let sequential = bundle(&[]);
+        assert!(sequential.contains("pub fn go"));
+        assert_eq!(bundle(&["--jobs", "4"]), sequential);
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(project)
+            .args(["--jobs", "0"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("at least 1 job"));
+    }
+
+    #[test]
+    fn test_cli_reexport_chains() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "geom",
+            "struct Position(u8);\n\nfn start() -> u8 {\n    7\n}\n\nfn main() {\n    let origin: geom::Position = geom::start();\n    println!(\"{} {}\", origin.x, Position(start()).0);\n}\n",
+        );
+        fs::write(
+            temp_dir.path().join("src/lib.rs"),
+            "mod shapes {\n    pub mod point {\n        pub struct Point {\n            pub x: i32,\n        }\n\n        pub fn origin() -> Point {\n            Point { x: 0 }\n        }\n    }\n\n    pub use point::*;\n}\n\npub mod prelude {\n    pub use super::shapes::Point as P;\n}\n\npub use prelude::P as Position;\npub use shapes::origin as start;\n",
+        )
+        .expect("Failed to write lib.rs");
+
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        let code = String::from_utf8_lossy(&output.stdout);
+        assert!(!code.contains("pub use prelude::P as Position;"), "{code}");
+        assert!(!code.contains("as start"), "{code}");
+        assert!(
+            code.contains("let origin: crate::prelude::P = crate::shapes::origin();"),
+            "{code}"
+        );
+        assert!(code.contains("Position(start()).0"), "{code}");
+    }
+
+    #[test]
+    fn test_cli_library_target() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
+        fs::create_dir_all(project.join("src/geo")).expect("Failed to create src");
+        fs::write(
+            project.join("Cargo.toml"),
+            "[package]\nname = \"geom\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .expect("Failed to write Cargo.toml");
+        fs::write(
+            project.join("src/lib.rs"),
+            "pub mod geo;\n\npub use crate::geo::area;\n\nfn helper() -> u32 {\n    2\n}\n\npub fn double(x: u32) -> u32 {\n    x * helper()\n}\n",
+        )
+        .expect("Failed to write lib.rs");
+        fs::write(
+            project.join("src/geo.rs"),
+            "pub mod shapes;\n\npub fn area(square: &shapes::Square) -> u32 {\n    square.0 * square.0\n}\n",
+        )
+        .expect("Failed to write geo.rs");
+        fs::write(
+            project.join("src/geo/shapes.rs"),
+            "pub struct Square(pub u32);\n",
+        )
+        .expect("Failed to write shapes.rs");
+
+        let output = Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .args(["--lib", "--strip-unused"])
+            .output()
+            .expect("Failed to run");
+        assert!(output.status.success());
+        let code = String::from_utf8_lossy(&output.stdout);
+        assert!(code.contains("pub mod shapes {"), "{code}");
+        assert!(code.contains("pub use crate::geo::area;"), "{code}");
+        assert!(code.contains("fn helper() -> u32"), "{code}");
+        assert!(!code.contains("fn main"), "{code}");
+    }
+
+    #[test]
+    fn test_cli_library_target_missing() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path(), "geom", "fn main() {}\n");
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(temp_dir.path())
+            .arg("--lib")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("No library target"));
+    }
+
+    #[test]
+    fn test_cli_run_build_script_resolves_rustc_env() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "rustc_env_test",
+            "include!(concat!(env!(\"OUT_DIR\"), \"/\", env!(\"TABLES_FILE\")));\nfn main() { println!(\"{}\", TABLES.len()); }",
+        );
+        fs::write(
+            temp_dir.path().join("build.rs"),
+            r#"
+fn main() {
+    let name = format!("tables_v{}.rs", 1 + 1);
+    println!("cargo:rustc-env=TABLES_FILE={name}");
+    let path = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join(name);
+    std::fs::write(path, "const TABLES: [u8; 2] = [4, 2];").unwrap();
+}
+"#,
+        )
+        .expect("Failed to write build.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .arg("--run-build-script")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("const TABLES: [u8; 2] = [4, 2];"))
+            .stdout(predicate::str::contains("include!").not());
+    }
+
+    #[test]
+    fn test_cli_run_build_script_falls_back_when_cargo_fails() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "failing_check_test",
+            "include!(concat!(env!(\"OUT_DIR\"), \"/n.rs\"));\nfn main() { let s: String = N; println!(\"{s}\"); }",
+        );
+        fs::write(
+            temp_dir.path().join("build.rs"),
+            r#"
+fn main() {
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(format!("{out_dir}/n.rs"), "const N: u32 = 7;").unwrap();
+}
+"#,
+        )
+        .expect("Failed to write build.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--run-build-script", "--verbose"])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("Warning: Build script error"))
+            .stdout(predicate::str::contains("const N: u32 = 7;"));
+    }
+
+    #[test]
+    fn test_cli_lint_codingame() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "lint_test",
+            "mod debug;\nfn main() { debug::dump(\"start\"); std::process::exit(0); }",
+        );
+        fs::write(
+            temp_dir.path().join("src/debug.rs"),
+            "pub fn dump(state: &str) {\n    std::fs::write(\"state.txt\", state).ok();\n}\n",
+        )
+        .expect("Failed to write debug.rs");
+        let output = temp_dir.path().join("bundle.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--lint", "codingame", "-o"])
+            .arg(&output)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "forbidden: `std::fs::write` (file system access) in debug::fn dump",
+            ))
+            .stderr(predicate::str::contains("--> src/debug.rs"))
+            .stderr(predicate::str::contains(
+                "Bundle uses 1 API(s) forbidden on codingame",
+            ));
+        assert!(!output.exists());
+
+        fs::write(
+            temp_dir.path().join("src/debug.rs"),
+            "pub fn dump(state: &str) {\n    eprintln!(\"{state}\");\n}\n",
+        )
+        .expect("Failed to write debug.rs");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--lint", "codingame"])
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn test_cli_target_size() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "target_size_test",
+            "/// Sums the scores\nfn total(scores: &[u32]) -> u32 {\n    let mut running_total = 0;\n    for score in scores {\n        running_total += score;\n    }\n    running_total\n}\n\nfn main() {\n    println!(\"{}\", total(&[1, 2, 3]));\n}\n",
+        );
+        let output = temp_dir.path().join("bundle.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--target-size", "100000", "-o"])
+            .arg(&output)
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("Minification stages: none needed"));
+        assert!(fs::read_to_string(&output)
+            .expect("Failed to read bundle")
+            .contains("running_total"));
+
+        // The smallest bundle reachable has every stage applied
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        let failure = cmd
+            .current_dir(temp_dir.path())
+            .args(["--target-size", "1", "-o"])
+            .arg(&output)
+            .assert()
+            .failure()
+            .get_output()
+            .stderr
+            .clone();
+        let failure = String::from_utf8_lossy(&failure);
+        let smallest = failure
+            .split("Bundle has ")
+            .nth(1)
+            .and_then(|rest| rest.split(' ').next())
+            .expect("Failure should give the bundle size");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--target-size", smallest, "-o"])
+            .arg(&output)
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(
+                "Minification stages: whitespace minification, identifier shortening",
+            ));
+        let bundle = fs::read_to_string(&output).expect("Failed to read bundle");
+        assert!(!bundle.contains("running_total"));
+        assert!(bundle.contains("fn total("));
+    }
+
+    #[test]
+    fn test_cli_mangle() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "mangle_test",
+            "struct Grid {\n    cell_width: u32,\n}\n\nfn area_of(grid: &Grid, cell_count: u32) -> u32 {\n    grid.cell_width * cell_count\n}\n\nfn main() {\n    let grid = Grid { cell_width: 3 };\n    println!(\"{}\", area_of(&grid, 4));\n}\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        let output = cmd
+            .current_dir(temp_dir.path())
+            .arg("--mangle")
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let bundle = String::from_utf8_lossy(&output);
+        for name in ["area_of", "cell_width", "cell_count", "grid"] {
+            assert!(
+                !bundle.contains(name),
+                "{name} should be renamed:\n{bundle}"
+            );
+        }
+        assert!(bundle.contains("struct Grid"));
+        assert!(bundle.contains("fn main()"));
+    }
+
+    #[test]
+    fn test_cli_check_output() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "check_output_test",
+            "fn main() { first(); }\nfn first() {}",
+        );
+        let bundle_path = temp_dir.path().join("bundle.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs"])
+            .assert()
+            .success();
+        let committed = fs::read_to_string(&bundle_path).expect("Bundle should be written");
+        let modified = fs::metadata(&bundle_path)
+            .and_then(|metadata| metadata.modified())
+            .expect("Bundle should have a modification time");
+
+        // Rebundling the same sources leaves the file untouched
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs"])
+            .assert()
+            .success();
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--check-output", "bundle.rs"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("bundle.rs is up to date"));
+        assert_eq!(
+            fs::metadata(&bundle_path)
+                .and_then(|metadata| metadata.modified())
+                .expect("Bundle should have a modification time"),
+            modified
+        );
+
+        fs::write(
+            temp_dir.path().join("src/main.rs"),
+            "fn main() { second(); }\nfn second() {}",
+        )
+        .expect("Failed to update main.rs");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--check-output", "bundle.rs"])
+            .assert()
+            .failure()
+            .stdout(predicate::str::contains(
+                "--- bundle.rs\n+++ bundle.rs (current sources)\n@@",
+            ))
+            .stdout(predicate::str::contains("-fn first() {}"))
+            .stdout(predicate::str::contains("+fn second() {}"))
+            .stderr(predicate::str::contains(
+                "'bundle.rs' does not match the current sources",
+            ));
+        assert_eq!(
+            fs::read_to_string(&bundle_path).expect("Bundle should remain"),
+            committed
+        );
     }
 
     #[test]
-    fn test_cli_invalid_flag_combination() {
+    fn test_cli_example_in_its_own_directory() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
-        create_test_project(temp_dir.path(), "invalid_combo_test", "fn main() {}");
+        let project = temp_dir.path();
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::create_dir_all(project.join("examples/strategy_x")).unwrap();
+        fs::write(
+            project.join("Cargo.toml"),
+            "[package]\nname = \"arena\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::write(project.join("src/lib.rs"), "pub mod geo;\n").unwrap();
+        fs::write(
+            project.join("src/geo.rs"),
+            "pub fn origin() -> (i32, i32) { (0, 0) }\n",
+        )
+        .unwrap();
+        fs::write(project.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(
+            project.join("examples/strategy_x/main.rs"),
+            "mod tactics;\nuse arena::geo::origin;\nfn main() { assert_eq!(origin(), (0, 0)); assert_eq!(tactics::pick(), 3); }\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join("examples/strategy_x/tactics.rs"),
+            "pub fn pick() -> u8 { 3 }\n",
+        )
+        .unwrap();
 
-        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .args(["--example", "strategy_x"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("mod tactics"))
+            .stdout(predicate::str::contains("pub fn pick() -> u8"))
+            .stdout(predicate::str::contains("pub fn origin() -> (i32, i32)"));
+    }
 
-        // Test that minify and pretty can't be used together effectively
-        // (both should work, but pretty should be ignored when minify is used)
-        cmd.current_dir(temp_dir.path())
-            .arg("--minify")
-            .arg("--pretty")
-            .arg("-o")
-            .arg("combo_output.rs")
+    #[test]
+    fn test_cli_test_target_with_generated_main() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project = temp_dir.path();
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::create_dir_all(project.join("tests")).unwrap();
+        fs::write(
+            project.join("Cargo.toml"),
+            "[package]\nname = \"arena\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join("src/lib.rs"),
+            "pub fn play(seed: u64) -> u64 { seed * 2 }\n",
+        )
+        .unwrap();
+        fs::write(project.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(
+            project.join("tests/bot_vs_bot.rs"),
+            "use arena::play;\n#[test]\nfn first_wins() { assert_eq!(play(2), 4); }\n#[test]\n#[ignore]\nfn slow() {}\n",
+        )
+        .unwrap();
+
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .args(["--test", "bot_vs_bot", "--check"])
             .assert()
-            .success();
+            .success()
+            .stdout(predicate::str::contains("pub fn play(seed: u64) -> u64"))
+            .stdout(predicate::str::contains(
+                "fn main() {\n    first_wins();\n}",
+            ))
+            .stdout(predicate::str::contains("#[test]").not());
 
-        // Check that output file was created
-        assert!(temp_dir.path().join("combo_output.rs").exists());
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project)
+            .args(["--test", "arena_vs_arena"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "No test target named 'arena_vs_arena'. Available tests: bot_vs_bot",
+            ));
     }
 
     #[test]
-    fn test_cli_output_to_stdout() {
+    fn test_cli_profiles_write_each_output() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
         create_test_project(
             temp_dir.path(),
-            "stdout_test",
-            "fn main() { println!(\"Hello\"); }",
+            "profiles_test",
+            "/// Entry point\nfn main() {\n    println!(\"Profiles!\");\n}\n",
         );
+        fs::write(
+            temp_dir.path().join("cg-bundler.toml"),
+            "[profiles.debug]\nkeep-docs = true\noutput = \"bundle.debug.rs\"\n\n[profiles.submit]\nm2 = true\noutput = \"bundle.submit.rs\"\n",
+        )
+        .expect("Failed to write cg-bundler.toml");
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--profile", "debug", "--profile", "submit"])
+            .args(["--profile", "tiny:minify,output=bundle.tiny.rs"])
+            .assert()
+            .success();
+
+        let debug = fs::read_to_string(temp_dir.path().join("bundle.debug.rs"))
+            .expect("Debug bundle should be written");
+        assert!(debug.contains("/// Entry point"));
+        let submit = fs::read_to_string(temp_dir.path().join("bundle.submit.rs"))
+            .expect("Submit bundle should be written");
+        assert!(submit.starts_with("fn main(){println!(\"Profiles!\");}"));
+        assert!(temp_dir.path().join("bundle.tiny.rs").exists());
 
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
         cmd.current_dir(temp_dir.path())
+            .args(["--profile", "release"])
             .assert()
-            .success()
-            .stdout(predicate::str::contains("fn main()"))
-            .stdout(predicate::str::contains("println!"));
+            .failure()
+            .stderr(predicate::str::contains("unknown profile 'release'"));
     }
 
     #[test]
-    fn test_cli_with_project_path_and_output() {
+    fn test_cli_profiles_name_their_outputs() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
-        let project_name = "path_and_output_test";
-        let project_path = temp_dir.path().join(project_name);
-
-        // Create the project directory first
-        fs::create_dir_all(&project_path).expect("Failed to create project directory");
-        create_test_project(&project_path, project_name, "fn main() {}");
+        create_test_project(
+            temp_dir.path(),
+            "profile_names_test",
+            "fn main() {\n    println!(\"Profiles!\");\n}\n",
+        );
+        fs::write(
+            temp_dir.path().join("cg-bundler.toml"),
+            "output-template = \"out/{crate}-{profile}.rs\"\n\n[profiles.debug]\npretty = true\n\n[profiles.submit]\nm2 = true\n",
+        )
+        .expect("Failed to write cg-bundler.toml");
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
-
-        let output_path = temp_dir.path().join("bundled_output.rs");
-
-        cmd.arg(&project_path)
-            .arg("-o")
-            .arg(&output_path)
+        cmd.current_dir(temp_dir.path())
+            .args(["--profile", "debug", "--profile", "submit"])
             .assert()
             .success();
+        let out = temp_dir.path().join("out");
+        assert!(out.join("profile_names_test-debug.rs").exists());
+        assert!(out.join("profile_names_test-submit.rs").exists());
 
-        // Check that output file was created in the correct location
-        assert!(output_path.exists());
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--profile", "first:output=same.rs"])
+            .args(["--profile", "second:m2,output=same.rs"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "profiles 'first' and 'second' both write",
+            ));
+        assert!(!temp_dir.path().join("same.rs").exists());
     }
 
     #[test]
-    fn test_cli_m2_aggressive_minify() {
+    fn test_cli_edition_from_package_or_flag() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
         create_test_project(
             temp_dir.path(),
-            "m2_test",
-            "fn main() { let x = 5 + 3; println!(\"Result: {}\", x); }",
+            "edition_test",
+            // Paths of `use` start at the crate root only in the 2015 edition
+            "mod a {\n    use b::X;\n    pub fn run() -> X { X }\n}\nmod b {\n    pub struct X;\n}\nfn main() { a::run(); }\n",
         );
+        let manifest = temp_dir.path().join("Cargo.toml");
+        let content = fs::read_to_string(&manifest).expect("Failed to read Cargo.toml");
+        fs::write(&manifest, content.replace("2021", "2015")).expect("Failed to write Cargo.toml");
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
-
         cmd.current_dir(temp_dir.path())
-            .arg("--m2")
-            .arg("-o")
-            .arg("m2_output.rs")
+            .args(["-o", "bundle.rs", "--check"])
             .assert()
             .success();
 
-        // Read the output and verify aggressive minification
-        let output_content = fs::read_to_string(temp_dir.path().join("m2_output.rs"))
-            .expect("Failed to read output file");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["-o", "bundle.rs", "--check", "--edition", "2021"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("error[E0432]"));
 
-        // Should have aggressive minification (no spaces around operators)
-        assert!(output_content.contains("5+3"));
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--edition", "2019"])
+            .assert()
+            .failure();
     }
 
     #[test]
-    fn test_cli_no_expand_modules() {
+    fn test_cli_filter() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
-        let project_path = temp_dir.path().join("no_expand_test");
-        fs::create_dir_all(&project_path).expect("Failed to create project dir");
-
-        // Create a project with modules
+        // The configuration file of the current directory is not read
         fs::write(
-            project_path.join("Cargo.toml"),
-            r#"
-[package]
-name = "no_expand_test"
-version = "0.1.0"
-edition = "2021"
-"#,
+            temp_dir.path().join("cg-bundler.toml"),
+            "output = \"bundle.rs\"\n",
         )
-        .expect("Failed to write Cargo.toml");
+        .expect("Failed to write cg-bundler.toml");
 
-        fs::create_dir_all(project_path.join("src")).expect("Failed to create src");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--filter", "--mangle"])
+            .write_stdin(
+                "/// Entry point\nfn main() {\n    let total_score = 1;\n    println!(\"{}\", total_score);\n}\n",
+            )
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("fn main()"))
+            .stdout(predicate::str::contains("total_score").not())
+            .stdout(predicate::str::contains("Entry point").not());
+        assert!(!temp_dir.path().join("bundle.rs").exists());
 
-        fs::write(
-            project_path.join("src/main.rs"),
-            "mod utils;\nfn main() { utils::hello(); }",
-        )
-        .expect("Failed to write main.rs");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.args(["--filter", "--tree"]).assert().failure();
+    }
 
-        fs::write(
-            project_path.join("src/utils.rs"),
-            "pub fn hello() { println!(\"Hello from utils!\"); }",
-        )
-        .expect("Failed to write utils.rs");
+    #[test]
+    fn test_cli_features() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "features",
+            "#[cfg(feature = \"debug-vis\")]\nfn draw() {}\nfn main() {\n    #[cfg(feature = \"advanced-ai\")]\n    println!(\"search\");\n    #[cfg(feature = \"greedy\")]\n    println!(\"greedy\");\n}\n",
+        );
+        let manifest = temp_dir.path().join("Cargo.toml");
+        let mut cargo_toml = fs::read_to_string(&manifest).expect("Failed to read Cargo.toml");
+        cargo_toml.push_str(
+            "\n[features]\ndefault = [\"greedy\"]\ngreedy = []\nadvanced-ai = []\ndebug-vis = []\n",
+        );
+        fs::write(&manifest, cargo_toml).expect("Failed to write Cargo.toml");
 
         let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(temp_dir.path())
+            .args([
+                "--features",
+                "advanced-ai,debug-vis",
+                "--no-default-features",
+            ])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("fn draw()"))
+            .stdout(predicate::str::contains("println!(\"search\")"))
+            .stdout(predicate::str::contains("greedy").not())
+            .stdout(predicate::str::contains("cfg").not());
 
-        cmd.current_dir(&project_path)
-            .arg("--no-expand-modules")
-            .arg("-o")
-            .arg("no_expand_output.rs")
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(temp_dir.path())
+            .arg("--all-features")
             .assert()
-            .success();
+            .success()
+            .stdout(predicate::str::contains("println!(\"greedy\")"))
+            .stdout(predicate::str::contains("println!(\"search\")"));
 
-        // Read the output and verify modules were not expanded
-        let output_content = fs::read_to_string(project_path.join("no_expand_output.rs"))
-            .expect("Failed to read output file");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(temp_dir.path())
+            .args(["-F", "turbo"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("no feature 'turbo'"));
+    }
 
-        // Should still contain module declaration
-        assert!(output_content.contains("mod utils"));
+    #[test]
+    fn test_cli_submit_without_credentials() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let bundle = temp_dir.path().join("bundle.rs");
+        fs::write(&bundle, "fn main() {}").expect("Failed to write bundle");
+
+        // Without the `codingame-api` feature, or without an account to upload with
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.args(["submit", "--puzzle", "onboarding", "--file"])
+            .arg(&bundle)
+            .env_remove("CODINGAME_TOKEN")
+            .env_remove("CODINGAME_USER_ID")
+            .assert()
+            .failure()
+            .stderr(
+                predicate::str::contains("enable the `codingame-api` feature")
+                    .or(predicate::str::contains("set CODINGAME_USER_ID")),
+            );
     }
 }
 
@@ -985,6 +3967,52 @@ mod error_condition_tests {
             .stderr(predicate::str::contains("Error:"));
     }
 
+    #[test]
+    fn test_cli_with_custom_layout() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let project_path = temp_dir.path();
+        fs::create_dir_all(project_path.join("bot")).expect("Failed to create bot");
+        fs::write(project_path.join("bot/main.rs"), "fn main() {}")
+            .expect("Failed to write main.rs");
+        fs::write(
+            project_path.join("Cargo.toml"),
+            "[package]\nname = \"layout\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .expect("Failed to write Cargo.toml");
+
+        // No src directory: Cargo finds no target, the file defining main is suggested
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project_path)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "No source file found for a binary target",
+            ))
+            .stderr(predicate::str::contains("found: bot/main.rs"))
+            .stderr(predicate::str::contains(
+                "help: add a [[bin]] section with name = \"layout\" and `path = \"bot/main.rs\"`",
+            ));
+
+        // A [[bin]] path naming a missing file
+        fs::write(
+            project_path.join("Cargo.toml"),
+            "[package]\nname = \"layout\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[[bin]]\nname = \"layout\"\npath = \"bots/main.rs\"\n",
+        )
+        .expect("Failed to write Cargo.toml");
+        Command::cargo_bin("cg-bundler")
+            .expect("Binary should exist")
+            .current_dir(project_path)
+            .args(["--format", "json"])
+            .assert()
+            .failure()
+            .stdout(predicate::str::contains("\"code\": \"CG0028\""))
+            .stdout(predicate::str::contains("looked for: bots/main.rs"))
+            .stdout(predicate::str::contains(
+                "help: set `path = \\\"bot/main.rs\\\"` in the [[bin]] section named 'layout' of Cargo.toml",
+            ));
+    }
+
     #[test]
     fn test_cli_with_malformed_cargo_toml() {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
@@ -1020,4 +4048,109 @@ mod error_condition_tests {
             .failure()
             .stderr(predicate::str::contains("Error:"));
     }
+
+    #[test]
+    fn test_cli_validate_reports_every_problem() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "multi_error_test",
+            "mod geo;\nfn main() {}",
+        );
+        fs::write(temp_dir.path().join("Cargo.toml"), "[package\nname = 1\n")
+            .expect("Failed to write Cargo.toml");
+        fs::write(temp_dir.path().join("src/geo.rs"), "pub fn area( {}\n")
+            .expect("Failed to write geo.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.current_dir(temp_dir.path())
+            .arg("--validate")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("[preflight]"))
+            .stderr(predicate::str::contains("Cargo.toml"))
+            .stderr(predicate::str::contains("[parse]"))
+            .stderr(predicate::str::contains("geo.rs"))
+            .stderr(predicate::str::contains("metadata    skipped"))
+            .stderr(predicate::str::contains("Validation found 2 problem(s)"));
+    }
+
+    #[test]
+    fn test_cli_error_links_prefilled_issue() {
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+
+        cmd.arg("/nonexistent/project/path")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "https://github.com/MathieuSoysal/CG-Bundler/issues/new?title=Error%3A%20",
+            ));
+    }
+
+    #[test]
+    fn test_cli_reports_duplicate_main() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "bot",
+            "use bot::pick;\n\nfn main() { println!(\"{}\", pick()); }",
+        );
+        fs::write(
+            temp_dir.path().join("src/lib.rs"),
+            "pub fn pick() -> u32 { 7 }\nfn main() {}\n",
+        )
+        .expect("Failed to write lib.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "defined by the binary at src/main.rs:3 and by the inlined library at src/lib.rs:2",
+            ))
+            .stderr(predicate::str::contains("--lib-module-name"));
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.current_dir(temp_dir.path())
+            .args(["--lib-module-name", "lib"])
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn test_module_with_both_file_layouts() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        create_test_project(
+            temp_dir.path(),
+            "layouts",
+            "mod ai;\nmod geo;\nfn main() { ai::run(); geo::shapes::draw(); }",
+        );
+        let src = temp_dir.path().join("src");
+        fs::create_dir_all(src.join("ai")).expect("Failed to create ai");
+        fs::create_dir_all(src.join("geo")).expect("Failed to create geo");
+        fs::write(src.join("geo.rs"), "pub mod shapes;").expect("Failed to write geo.rs");
+        fs::write(src.join("geo/shapes.rs"), "pub fn draw() {}")
+            .expect("Failed to write shapes.rs");
+        fs::write(src.join("ai/mod.rs"), "pub fn run() {}").expect("Failed to write ai/mod.rs");
+
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(temp_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("pub fn draw()"));
+
+        fs::write(src.join("ai.rs"), "pub fn run() {}").expect("Failed to write ai.rs");
+        let mut cmd = Command::cargo_bin("cg-bundler").expect("Binary should exist");
+        cmd.arg(temp_dir.path())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "File for module 'ai' found at both",
+            ))
+            .stderr(predicate::str::contains("ai.rs' and"))
+            .stderr(predicate::str::contains(
+                "mod.rs'; delete or rename one of them",
+            ));
+    }
 }
@@ -1,7 +1,16 @@
+// The tests of `TransformConfig` also cover its deprecated `remove_docs` field
+#![allow(deprecated)]
+
 use cg_bundler::bundle;
-use cg_bundler::{Bundler, CargoProject, TransformConfig}; // Added imports for new tests
+use cg_bundler::transformer::{FnPass, PassStage};
+use cg_bundler::warnings::BundleWarning;
+use cg_bundler::{
+    Bundler, BundlerError, CargoProject, DocsMode, FeatureSelection, TransformConfig,
+}; // Added imports for new tests
+use quote::ToTokens;
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tempfile::TempDir;
 
 /// Test the basic functionality of the bundle function
@@ -444,6 +453,7 @@ fn test_bundler_with_keep_tests_config() {
         expand_modules: true,
         minify: false,
         aggressive_minify: false,
+        ..TransformConfig::default()
     };
 
     let bundler = Bundler::with_config(config);
@@ -458,6 +468,496 @@ fn test_bundler_with_keep_tests_config() {
     assert!(!bundled_code.is_empty(), "Bundle should not be empty");
 }
 
+/// Kept tests from the library and the binary must compile together under `rustc --test`
+#[test]
+fn test_keep_tests_output_compiles_as_test_harness() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"harness\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        project.join("src/lib.rs"),
+        r#"pub mod geo;
+fn secret() -> i32 { 41 }
+pub fn answer() -> i32 { secret() + 1 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harness::geo::dist;
+    #[test]
+    fn lib_test() { assert_eq!(answer(), 42); assert_eq!(dist(1, 2), 1); }
+}
+"#,
+    )
+    .unwrap();
+    fs::write(
+        project.join("src/geo.rs"),
+        r#"pub fn dist(a: i32, b: i32) -> i32 { (a - b).abs() }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn geo_test() { assert_eq!(dist(3, 1), 2); assert_eq!(harness::answer(), 42); }
+}
+"#,
+    )
+    .unwrap();
+    fs::write(
+        project.join("src/main.rs"),
+        r#"use harness::*;
+fn main() { println!("{}", answer()); }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn main_test() { assert_eq!(geo::dist(1, 5), 4); }
+}
+"#,
+    )
+    .unwrap();
+
+    let config = TransformConfig {
+        remove_tests: false,
+        ..TransformConfig::default()
+    };
+    let bundled_code = Bundler::with_config(config)
+        .bundle(project)
+        .expect("Bundle should succeed");
+    assert!(bundled_code.contains("mod tests_2"));
+    assert!(bundled_code.contains("use crate::geo::dist;"));
+    assert!(!bundled_code.contains("harness::"));
+
+    let bundled_file = temp_dir.path().join("bundled.rs");
+    fs::write(&bundled_file, &bundled_code).expect("Failed to write bundled code");
+    let output = std::process::Command::new("rustc")
+        .args(["--edition", "2021", "--test"])
+        .arg(&bundled_file)
+        .arg("-o")
+        .arg(temp_dir.path().join("bundled_tests"))
+        .output();
+
+    match output {
+        Ok(result) => assert!(
+            result.status.success(),
+            "Bundled tests failed to compile:\n{}",
+            String::from_utf8_lossy(&result.stderr)
+        ),
+        Err(e) => eprintln!("Warning: rustc not available for compilation test: {}", e),
+    }
+}
+
+/// Custom passes run around the built-in ones in registration order
+#[test]
+fn test_bundler_custom_transform_passes() {
+    let mut bundler = Bundler::with_config(TransformConfig {
+        strip_unused: true,
+        ..TransformConfig::default()
+    });
+    // Runs before dead-code elimination, so the unused function is stripped again
+    bundler.add_pass(Box::new(
+        FnPass::new("add-before", |file: &mut syn::File| {
+            file.items.push(syn::parse_quote!(
+                fn added_before_builtin() {}
+            ));
+            Ok(())
+        })
+        .with_stage(PassStage::BeforeBuiltin),
+    ));
+    bundler.add_pass(Box::new(FnPass::new(
+        "add-after",
+        |file: &mut syn::File| {
+            file.items.push(syn::parse_quote!(
+                fn added_after_builtin() {}
+            ));
+            Ok(())
+        },
+    )));
+
+    let bundled_code = bundler
+        .bundle(Path::new("test_project"))
+        .expect("Bundle should succeed");
+    assert!(!bundled_code.contains("fn added_before_builtin()"));
+    assert!(bundled_code.contains("fn added_after_builtin()"));
+}
+
+/// A failing custom pass aborts bundling with its error
+#[test]
+fn test_bundler_custom_pass_error() {
+    let mut bundler = Bundler::new();
+    bundler.add_pass(Box::new(FnPass::new("fail", |_: &mut syn::File| {
+        Err(BundlerError::TransformPass {
+            pass: "fail".to_string(),
+            message: "rejected".to_string(),
+        })
+    })));
+
+    let error = bundler
+        .bundle(Path::new("test_project"))
+        .expect_err("Bundle should fail");
+    assert_eq!(error.to_string(), "Transform pass 'fail' failed: rejected");
+}
+
+/// The built-in pipeline produces identical output across repeated runs, while a
+/// pass emitting items in hash order is caught
+#[test]
+fn test_bundler_determinism_check() {
+    let bundler = Bundler::new();
+    let bundled_code = bundler
+        .check_determinism(Path::new("test_project"), 3)
+        .expect("Built-in passes should be deterministic");
+    assert!(bundled_code.contains("fn main"));
+
+    let mut bundler = Bundler::new();
+    bundler.add_pass(Box::new(FnPass::new(
+        "hash-order",
+        |file: &mut syn::File| {
+            let names: std::collections::HashSet<String> =
+                (0..32).map(|i| format!("generated_{i}")).collect();
+            for name in names {
+                let ident = syn::Ident::new(&name, proc_macro2::Span::call_site());
+                file.items.push(syn::parse_quote!(
+                    fn #ident() {}
+                ));
+            }
+            Ok(())
+        },
+    )));
+
+    let error = bundler
+        .check_determinism(Path::new("test_project"), 10)
+        .expect_err("Hash-ordered output should be detected");
+    assert!(matches!(error, BundlerError::Nondeterministic { .. }));
+}
+
+/// Modules are expanded in declaration order, so projects whose files were created
+/// (and are listed by the file system) in different orders bundle byte-identically
+#[test]
+fn test_module_expansion_follows_declaration_order() {
+    let files = [
+        (
+            "Cargo.toml",
+            "[package]\nname = \"order_test\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        ),
+        (
+            "src/main.rs",
+            "mod zeta;\nmod alpha;\nmod mid;\nfn main() { zeta::z(); alpha::a(); mid::m(); }\n",
+        ),
+        ("src/zeta.rs", "pub fn z() {}\n"),
+        (
+            "src/alpha/mod.rs",
+            "mod second;\nmod first;\npub fn a() { second::s(); first::f(); }\n",
+        ),
+        ("src/alpha/first.rs", "pub fn f() {}\n"),
+        ("src/alpha/second.rs", "pub fn s() {}\n"),
+        ("src/mid.rs", "pub fn m() {}\n"),
+    ];
+
+    let bundle_in_order = |files: &mut dyn Iterator<Item = &(&str, &str)>| {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        for (path, content) in files {
+            let path = temp_dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).expect("Failed to create directory");
+            fs::write(path, content).expect("Failed to write file");
+        }
+        Bundler::new()
+            .check_determinism(temp_dir.path(), 3)
+            .expect("Bundling should be deterministic")
+    };
+
+    let forward = bundle_in_order(&mut files.iter());
+    let reverse = bundle_in_order(&mut files.iter().rev());
+    assert_eq!(forward, reverse);
+
+    let positions: Vec<usize> = [
+        "mod zeta",
+        "mod alpha",
+        "mod second",
+        "mod first",
+        "mod mid",
+    ]
+    .iter()
+    .map(|module| forward.find(module).expect("Module should be expanded"))
+    .collect();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+/// Inner attributes of the library and of module files end up where they are legal
+#[test]
+fn test_crate_level_attributes_are_hoisted() {
+    let modules = std::collections::HashMap::from([
+        (
+            std::path::PathBuf::from("lib.rs"),
+            "//! Solver library\n#![allow(clippy::all)]\n#![cfg_attr(nightly, feature(test))]\n#![deny(missing_docs)]\npub mod grid;\n".to_string(),
+        ),
+        (
+            std::path::PathBuf::from("grid.rs"),
+            "#![allow(dead_code)]\n#![feature(let_chains)]\npub struct Grid;\n".to_string(),
+        ),
+    ]);
+    let entry = "#![allow(clippy::all)]\nuse solver::grid::Grid;\nfn main() { let _ = Grid; }\n";
+
+    let bundled_code = Bundler::new()
+        .bundle_sources("solver", entry, &modules)
+        .expect("In-memory bundle should succeed");
+
+    let file = syn::parse_file(&bundled_code).expect("Bundled code should parse");
+    let root_attrs: Vec<String> = file
+        .attrs
+        .iter()
+        .map(|attr| quote::ToTokens::to_token_stream(attr).to_string())
+        .collect();
+    assert_eq!(
+        root_attrs,
+        [
+            "# ! [allow (clippy :: all)]",
+            "# ! [cfg_attr (nightly , feature (test))]",
+            "# ! [feature (let_chains)]",
+        ]
+    );
+    assert!(bundled_code.contains("pub mod grid {\n    #![allow(dead_code)]"));
+    assert!(!bundled_code.contains("missing_docs"));
+    assert!(!bundled_code.contains("Solver library"));
+}
+
+/// `lib_module_name` embeds the library in a module so it cannot clash with the binary's own
+#[test]
+fn test_library_embedded_in_named_module() {
+    let modules = std::collections::HashMap::from([(
+        std::path::PathBuf::from("lib.rs"),
+        "pub mod geo { pub struct Point(pub i32); pub fn origin() -> crate::geo::Point { Point(0) } }\n#[macro_export]\nmacro_rules! point { ($x:expr) => { $crate::geo::Point($x) }; }\npub fn double(x: i32) -> i32 { x * 2 }\n".to_string(),
+    )]);
+    let entry = "use solver::geo::{self as shapes, origin};\nuse solver::point;\nmod geo { pub fn area() -> i32 { 4 } }\nfn main() { let d = solver::double(geo::area()); let p = point!(d); let _ = (p.0, origin(), shapes::Point(1)); }\n";
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let bundled_code = Bundler::with_config(TransformConfig {
+        lib_module_name: Some("lib".to_string()),
+        ..TransformConfig::default()
+    })
+    .bundle_sources("solver", entry, &modules)
+    .expect("In-memory bundle should succeed");
+    assert!(bundled_code.contains("pub mod lib {"));
+    assert!(bundled_code.contains("use crate::lib::geo::{self as shapes, origin};"));
+    assert!(bundled_code.contains("crate::lib::double(geo::area())"));
+
+    let bundled_file = temp_dir.path().join("bundled.rs");
+    fs::write(&bundled_file, &bundled_code).expect("Failed to write bundled code");
+    let output = std::process::Command::new("rustc")
+        .args(["--edition", "2021", "--emit=metadata", "--out-dir"])
+        .arg(temp_dir.path())
+        .arg(&bundled_file)
+        .output();
+
+    match output {
+        Ok(result) => assert!(
+            result.status.success(),
+            "Bundled code failed to compile:\n{}\n{bundled_code}",
+            String::from_utf8_lossy(&result.stderr)
+        ),
+        Err(e) => eprintln!("Warning: rustc not available for compilation test: {}", e),
+    }
+}
+
+/// In-memory sources are bundled without a Cargo project on disk
+#[test]
+fn test_bundler_bundle_sources_in_memory() {
+    let modules = std::collections::HashMap::from([
+        (
+            std::path::PathBuf::from("lib.rs"),
+            "pub mod geometry;\npub fn double(x: i32) -> i32 { x * 2 }\n".to_string(),
+        ),
+        (
+            std::path::PathBuf::from("geometry/mod.rs"),
+            "pub mod shapes;\n".to_string(),
+        ),
+        (
+            std::path::PathBuf::from("geometry/shapes.rs"),
+            "pub struct Square(pub i32);\n#[cfg(test)]\nmod tests {}\n".to_string(),
+        ),
+        (
+            std::path::PathBuf::from("helper.rs"),
+            "pub fn greet() {}\n".to_string(),
+        ),
+    ]);
+    let entry = "use generated::*;\nmod helper;\nfn main() { helper::greet(); let _ = generated::double(geometry::shapes::Square(2).0); }\n";
+
+    let bundled_code = Bundler::new()
+        .bundle_sources("generated", entry, &modules)
+        .expect("In-memory bundle should succeed");
+
+    assert!(bundled_code.contains("pub struct Square"));
+    assert!(bundled_code.contains("pub fn greet"));
+    assert!(bundled_code.contains("fn double"));
+    assert!(!bundled_code.contains("mod tests"));
+    assert!(!bundled_code.contains("generated::"));
+    syn::parse_file(&bundled_code).expect("Bundled code should parse");
+
+    let missing = Bundler::new().bundle_sources("generated", "mod absent;\nfn main() {}", &modules);
+    assert!(
+        missing.is_ok(),
+        "Unresolved modules are left as declarations"
+    );
+}
+
+/// A formatter set with `with_formatter` formats the bundle unless it is minified
+#[test]
+fn test_bundler_with_formatter() {
+    use cg_bundler::formatter::{Formatter, PrettyPlease};
+
+    /// Records the edition it was asked for
+    struct Edition;
+
+    impl Formatter for Edition {
+        fn format(&self, code: &str, edition: &str) -> cg_bundler::Result<String> {
+            Ok(format!("// edition {edition}\n{code}"))
+        }
+    }
+
+    let modules = std::collections::HashMap::new();
+    let entry = "fn main() {\n        // answer\n  let x = 42;\n}\n";
+    let config = TransformConfig {
+        preserve_formatting: true,
+        keep_comments: true,
+        ..TransformConfig::default()
+    };
+
+    let formatted = Bundler::with_config(config.clone())
+        .with_formatter(Box::new(PrettyPlease))
+        .bundle_sources("formatted", entry, &modules)
+        .expect("Formatted bundle should succeed");
+    assert_eq!(
+        formatted,
+        "fn main() {\n    // answer\n    let x = 42;\n}\n"
+    );
+
+    let edition = Bundler::new()
+        .with_formatter(Box::new(Edition))
+        .bundle_sources("formatted", entry, &modules)
+        .expect("Formatted bundle should succeed");
+    assert!(edition.starts_with("// edition 2021\n"));
+
+    let minified = Bundler::with_config(TransformConfig {
+        minify: true,
+        ..config
+    })
+    .with_formatter(Box::new(Edition))
+    .bundle_sources("formatted", entry, &modules)
+    .expect("Minified bundle should succeed");
+    assert!(!minified.contains("edition"));
+}
+
+/// `use crate_name::path::Item` imports are pointed at the inlined library and the
+/// bundle compiles
+#[test]
+fn test_bundle_rewrites_library_imports() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path().join("imports");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"imports\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        project.join("src/lib.rs"),
+        "pub mod geo;\npub mod util;\npub use util::clamp;\n",
+    )
+    .unwrap();
+    fs::write(
+        project.join("src/geo.rs"),
+        "pub fn area(w: u32, h: u32) -> u32 { w * h }\n",
+    )
+    .unwrap();
+    fs::write(
+        project.join("src/util.rs"),
+        "pub fn clamp(x: u32) -> u32 { x.min(10) }\n",
+    )
+    .unwrap();
+    fs::write(
+        project.join("src/main.rs"),
+        "use imports::geo::area;\nuse imports::{clamp, util};\nfn main() { assert_eq!(clamp(area(4, 5)), util::clamp(10)); }\n",
+    )
+    .unwrap();
+
+    let bundled_code = Bundler::new()
+        .bundle(&project)
+        .expect("Bundle should succeed");
+    assert!(bundled_code.contains("use crate::geo::area;"));
+    assert!(!bundled_code.contains("imports::"));
+
+    let bundled_file = temp_dir.path().join("bundled.rs");
+    fs::write(&bundled_file, &bundled_code).expect("Failed to write bundled code");
+    let output = std::process::Command::new("rustc")
+        .args(["--edition", "2021", "--crate-type", "bin", "-o"])
+        .arg(temp_dir.path().join("bundled"))
+        .arg(&bundled_file)
+        .output()
+        .expect("rustc should run");
+    assert!(
+        output.status.success(),
+        "Bundle should compile: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// `include!`, `include_str!` and `include_bytes!` are resolved relative to the
+/// file containing them and inlined into a bundle that compiles
+#[test]
+fn test_bundle_inlines_include_macros() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path().join("includes");
+    fs::create_dir_all(project.join("src/gen")).unwrap();
+    fs::create_dir_all(project.join("data")).unwrap();
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"includes\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(project.join("data/map.txt"), "#.#\n").unwrap();
+    fs::write(
+        project.join("src/gen/tables.rs"),
+        "pub const WIDTH: usize = 3;\n",
+    )
+    .unwrap();
+    fs::write(
+        project.join("src/grid.rs"),
+        "include!(\"gen/tables.rs\");\npub const MAP: &str = include_str!(\"../data/map.txt\");\n",
+    )
+    .unwrap();
+    fs::write(
+        project.join("src/main.rs"),
+        "mod grid;\nconst RAW: &[u8; 4] = include_bytes!(\"../data/map.txt\");\nfn main() { assert_eq!(grid::MAP.len(), grid::WIDTH + 1); assert_eq!(RAW, b\"#.#\\n\"); }\n",
+    )
+    .unwrap();
+
+    let bundled_code = Bundler::new()
+        .bundle(&project)
+        .expect("Bundle should succeed");
+    assert!(!bundled_code.contains("include"));
+    assert!(bundled_code.contains("pub const WIDTH: usize = 3;"));
+
+    let bundled_file = temp_dir.path().join("bundled.rs");
+    fs::write(&bundled_file, &bundled_code).expect("Failed to write bundled code");
+    let output = std::process::Command::new("rustc")
+        .args(["--edition", "2021", "--crate-type", "bin", "-o"])
+        .arg(temp_dir.path().join("bundled"))
+        .arg(&bundled_file)
+        .output()
+        .expect("rustc should run");
+    assert!(
+        output.status.success(),
+        "Bundle should compile: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let run = std::process::Command::new(temp_dir.path().join("bundled"))
+        .output()
+        .expect("bundle should run");
+    assert!(run.status.success());
+}
+
 /// Test Bundler with custom TransformConfig - keep docs
 #[test]
 fn test_bundler_with_keep_docs_config() {
@@ -467,6 +967,7 @@ fn test_bundler_with_keep_docs_config() {
         expand_modules: true,
         minify: false,
         aggressive_minify: false,
+        ..TransformConfig::default()
     };
 
     let bundler = Bundler::with_config(config);
@@ -489,6 +990,7 @@ fn test_bundler_disable_module_expansion() {
         expand_modules: false,
         minify: false,
         aggressive_minify: false,
+        ..TransformConfig::default()
     };
 
     let bundler = Bundler::with_config(config);
@@ -1156,6 +1658,7 @@ fn test_transform_config_custom() {
         expand_modules: false,
         minify: true,
         aggressive_minify: true,
+        ..TransformConfig::default()
     };
 
     assert!(
@@ -1195,6 +1698,7 @@ fn test_bundler_config_updates() {
         expand_modules: false,
         minify: true,
         aggressive_minify: false,
+        ..TransformConfig::default()
     };
 
     bundler.set_config(new_config.clone());
@@ -2471,3 +2975,627 @@ fn main() {
     let parsed = syn::parse_file(&result);
     assert!(parsed.is_ok(), "Bundled code should be valid Rust syntax");
 }
+
+/// `bundle_file` bundles a crate root and its sibling modules without a `Cargo.toml`
+#[test]
+fn test_bundle_file_without_manifest() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let crate_dir = temp_dir.path().join("scratch-bot");
+    fs::create_dir_all(crate_dir.join("geo")).expect("Failed to create crate dir");
+    fs::write(
+        crate_dir.join("main.rs"),
+        "mod geo;\nuse scratch_bot::double;\nfn main() { println!(\"{}\", double(geo::area())); }\n",
+    )
+    .expect("Failed to write main.rs");
+    fs::write(
+        crate_dir.join("geo.rs"),
+        "mod shapes;\npub fn area() -> u32 { shapes::SIDE }\n",
+    )
+    .expect("Failed to write geo.rs");
+    fs::write(
+        crate_dir.join("geo/shapes.rs"),
+        "pub const SIDE: u32 = 3;\n",
+    )
+    .expect("Failed to write shapes.rs");
+    fs::write(
+        crate_dir.join("lib.rs"),
+        "pub fn double(x: u32) -> u32 { x * 2 }\n",
+    )
+    .expect("Failed to write lib.rs");
+
+    let bundled_code = Bundler::new()
+        .bundle_file(&crate_dir)
+        .expect("Bundling without a manifest should succeed");
+    assert!(bundled_code.contains("pub const SIDE: u32 = 3;"));
+    assert!(bundled_code.contains("pub fn double(x: u32) -> u32"));
+    assert!(!bundled_code.contains("use scratch_bot"));
+    assert_eq!(
+        Bundler::new()
+            .bundle_file(crate_dir.join("main.rs"))
+            .expect("Bundling the crate root file should succeed"),
+        bundled_code
+    );
+
+    assert!(matches!(
+        Bundler::new().bundle_file(temp_dir.path()),
+        Err(BundlerError::ProjectStructure { .. })
+    ));
+}
+
+/// Modules are looked up next to the `src_path` of their target, so a binary
+/// outside `src/` keeps its own modules apart from the library's
+#[test]
+fn test_binary_outside_src_directory() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src/geo")).expect("Failed to create src/geo");
+    fs::create_dir_all(project.join("bot")).expect("Failed to create bot");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"arena\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[lib]\npath = \"src/lib.rs\"\n\n[[bin]]\nname = \"bot\"\npath = \"bot/main.rs\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(project.join("src/lib.rs"), "pub mod geo;\n").expect("Failed to write lib.rs");
+    fs::write(
+        project.join("src/geo.rs"),
+        "pub fn origin() -> (i32, i32) { (0, 0) }\n",
+    )
+    .expect("Failed to write geo.rs");
+    fs::write(
+        project.join("bot/main.rs"),
+        "mod strategy;\nuse arena::geo::origin;\nfn main() { assert_eq!(origin(), (0, 0)); assert_eq!(strategy::pick(), 7); }\n",
+    )
+    .expect("Failed to write main.rs");
+    fs::write(
+        project.join("bot/strategy.rs"),
+        "pub fn pick() -> u8 { 7 }\n",
+    )
+    .expect("Failed to write strategy.rs");
+
+    let bundled_code = bundle(project).expect("Bundle should succeed");
+    assert!(bundled_code.contains("pub fn pick() -> u8"));
+    assert!(bundled_code.contains("pub fn origin() -> (i32, i32)"));
+
+    let plan = Bundler::new().plan(project).expect("Plan should succeed");
+    assert!(plan
+        .input_files()
+        .iter()
+        .any(|file| file.ends_with("bot/strategy.rs")));
+}
+
+/// Sources are read through the `FileProvider` given to the bundler, so nothing
+/// but the manifest has to be on disk
+#[test]
+fn test_bundle_with_file_provider() {
+    let root = Path::new("/virtual/bot");
+    let files: HashMap<PathBuf, String> = [
+        (
+            "main.rs",
+            "mod geo;\nfn main() { println!(\"{}\", geo::shapes::area()); }\n",
+        ),
+        ("geo.rs", "pub mod shapes;\n"),
+        ("geo/shapes/mod.rs", "pub fn area() -> u32 { 42 }\n"),
+    ]
+    .into_iter()
+    .map(|(path, code)| (root.join(path), code.to_string()))
+    .collect();
+
+    let bundled_code = Bundler::new()
+        .with_file_provider(Box::new(files))
+        .bundle_file(root.join("main.rs"))
+        .expect("Bundle should succeed");
+    assert!(bundled_code.contains("mod shapes"));
+    assert!(bundled_code.contains("pub fn area() -> u32"));
+
+    // A provider may serve other sources than those of the project on disk
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src")).expect("Failed to create src");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"provided\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(project.join("src/main.rs"), "fn main() { on_disk(); }\n")
+        .expect("Failed to write main.rs");
+    let main_path = fs::canonicalize(project)
+        .expect("Project path should resolve")
+        .join("src/main.rs");
+    let files = HashMap::from([(main_path, "fn main() { provided(); }\n".to_string())]);
+
+    let bundled_code = Bundler::new()
+        .with_file_provider(Box::new(files))
+        .bundle(project)
+        .expect("Bundle should succeed");
+    assert!(bundled_code.contains("provided()"));
+    assert!(!bundled_code.contains("on_disk"));
+}
+
+/// Modules that cannot be expanded and imports naming nothing are reported
+/// instead of being left in the bundle silently
+#[test]
+fn test_bundle_with_report() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src")).expect("Failed to create src");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"reported\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(
+        project.join("src/lib.rs"),
+        "pub mod geo;\npub mod missing;\n",
+    )
+    .expect("Failed to write lib.rs");
+    fs::write(project.join("src/geo.rs"), "pub fn origin() {}\n").expect("Failed to write geo.rs");
+    fs::write(
+        project.join("src/main.rs"),
+        "use reported::geo::{origin, area};\nfn main() { origin(); }\n",
+    )
+    .expect("Failed to write main.rs");
+
+    let (bundled_code, report) = Bundler::new()
+        .bundle_with_report(project)
+        .expect("Bundle should succeed");
+    assert!(bundled_code.contains("pub fn origin()"));
+    assert_eq!(
+        report.warnings,
+        [
+            BundleWarning::SkippedModule {
+                module: "missing".to_string(),
+                reason: "Project structure error: Module 'missing' not found in expected locations"
+                    .to_string(),
+            },
+            BundleWarning::UnresolvedUse {
+                module: String::new(),
+                path: "crate::geo::area".to_string(),
+            },
+        ]
+    );
+
+    let (_, report) = Bundler::new()
+        .bundle_with_report(Path::new("test_project"))
+        .expect("Bundle should succeed");
+    assert!(report.is_clean());
+}
+
+/// `extern crate` declarations of dependencies are kept with their attributes,
+/// once each, and those importing macros are declared at the bundle root
+#[test]
+fn test_extern_crate_of_dependencies_kept() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src")).expect("Failed to create src");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"old\"\nversion = \"0.1.0\"\nedition = \"2015\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(
+        project.join("src/lib.rs"),
+        "#[macro_use]\nextern crate lazy_static;\npub mod cache;\n",
+    )
+    .expect("Failed to write lib.rs");
+    fs::write(
+        project.join("src/cache.rs"),
+        "#[macro_use]\nextern crate lazy_static;\nlazy_static! { pub static ref SIZE: usize = 64; }\n",
+    )
+    .expect("Failed to write cache.rs");
+    fs::write(
+        project.join("src/main.rs"),
+        "#[macro_use]\nextern crate lazy_static;\nextern crate old;\nuse old::cache::SIZE;\nfn main() { println!(\"{}\", *SIZE); }\n",
+    )
+    .expect("Failed to write main.rs");
+
+    let bundled_code = bundle(project).expect("Bundle should succeed");
+    let file = syn::parse_file(&bundled_code).expect("Bundle should parse");
+    let root_declarations: Vec<String> = file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::ExternCrate(item) => Some(item.to_token_stream().to_string()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        root_declarations,
+        ["# [macro_use] extern crate lazy_static ;"]
+    );
+    assert!(!bundled_code.contains("extern crate old"));
+    assert!(bundled_code.contains("pub mod cache {\n    extern crate lazy_static;"));
+}
+
+/// Every variant bundles the package with its own configuration
+#[test]
+fn test_bundle_variants() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src")).expect("Failed to create src");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"variants\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(
+        project.join("src/main.rs"),
+        "mod geo;\n// Prints the area\nfn main() { println!(\"{}\", geo::area()); }\n",
+    )
+    .expect("Failed to write main.rs");
+    fs::write(
+        project.join("src/geo.rs"),
+        "/// Area\npub fn area() -> u32 { 6 }\n",
+    )
+    .expect("Failed to write geo.rs");
+
+    let variants = [
+        Bundler::with_config(TransformConfig {
+            docs_mode: DocsMode::Keep,
+            keep_comments: true,
+            ..TransformConfig::default()
+        }),
+        Bundler::with_config(TransformConfig {
+            minify: true,
+            aggressive_minify: true,
+            ..TransformConfig::default()
+        }),
+    ];
+    let bundles = Bundler::new()
+        .bundle_variants(project, &variants)
+        .expect("Bundle should succeed");
+
+    assert_eq!(bundles.len(), 2);
+    assert!(bundles[0].contains("/// Area"));
+    assert!(bundles[0].contains("// Prints the area"));
+    assert_eq!(bundles[0], variants[0].bundle(project).unwrap());
+    assert_eq!(bundles[1], variants[1].bundle(project).unwrap());
+}
+
+/// The graph has a node per module with the file it comes from, nested as in
+/// the bundle
+#[test]
+fn test_analyze() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src")).expect("Failed to create src");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"graph\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(
+        project.join("src/main.rs"),
+        "mod geo;\nfn main() { println!(\"{}\", geo::shapes::area()); }\n",
+    )
+    .expect("Failed to write main.rs");
+    fs::write(
+        project.join("src/geo.rs"),
+        "pub struct Point;\npub mod shapes { pub fn area() -> u32 { 6 } }\n",
+    )
+    .expect("Failed to write geo.rs");
+
+    let graph = Bundler::new()
+        .analyze(project)
+        .expect("Analysis should succeed");
+
+    assert_eq!(graph.files, ["src/main.rs", "src/geo.rs"]);
+    assert_eq!(graph.root.items.get("fn"), Some(&1));
+    let geo = &graph.root.modules[0];
+    assert_eq!(geo.file.as_deref(), Some("src/geo.rs"));
+    assert_eq!(geo.items.get("struct"), Some(&1));
+    assert_eq!(geo.modules[0].path, "geo::shapes");
+    assert!(geo.characters > geo.modules[0].characters);
+    assert!(graph.root.characters > geo.characters);
+}
+
+/// Names the library re-exports with globs, through several modules, resolve from
+/// the binary whether the library is at the bundle root or in a module
+#[test]
+fn test_glob_reexports_of_library() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src/ai")).expect("Failed to create src/ai");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"bot\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(project.join("src/lib.rs"), "pub mod ai;\npub use ai::*;\n")
+        .expect("Failed to write lib.rs");
+    fs::write(
+        project.join("src/ai.rs"),
+        "mod strategies;\npub use strategies::*;\n",
+    )
+    .expect("Failed to write ai.rs");
+    fs::write(
+        project.join("src/ai/strategies.rs"),
+        "pub struct Greedy;\npub fn pick() -> u32 { 2 }\n",
+    )
+    .expect("Failed to write strategies.rs");
+    fs::write(
+        project.join("src/main.rs"),
+        "use crate::Greedy;\nuse crate::{ai::pick};\nfn main() { let _ = (Greedy, pick()); }\n",
+    )
+    .expect("Failed to write main.rs");
+
+    for lib_module_name in [None, Some("lib".to_string())] {
+        let bundled_code = Bundler::with_config(TransformConfig {
+            lib_module_name: lib_module_name.clone(),
+            ..TransformConfig::default()
+        })
+        .bundle(project)
+        .expect("Bundle should succeed");
+        if lib_module_name.is_some() {
+            assert!(bundled_code.contains("use crate::lib::Greedy;"));
+        }
+
+        let bundled_file = temp_dir.path().join("bundled.rs");
+        fs::write(&bundled_file, &bundled_code).expect("Failed to write bundled code");
+        let output = std::process::Command::new("rustc")
+            .args(["--edition", "2021", "--emit=metadata", "--out-dir"])
+            .arg(temp_dir.path())
+            .arg(&bundled_file)
+            .output();
+        match output {
+            Ok(result) => assert!(
+                result.status.success(),
+                "Bundled code failed to compile:\n{}\n{bundled_code}",
+                String::from_utf8_lossy(&result.stderr)
+            ),
+            Err(e) => eprintln!("Warning: rustc not available for compilation test: {}", e),
+        }
+    }
+}
+
+/// A library renamed with `[lib] name` is inlined when the binary names it only in
+/// macro input, whose paths are pointed at the bundle
+#[test]
+fn test_renamed_library_in_macro_input() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src")).expect("Failed to create src");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"my-bot\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[lib]\nname = \"bot_core\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(
+        project.join("src/lib.rs"),
+        "pub mod ai { pub fn think() -> u32 { 2 } }\n",
+    )
+    .expect("Failed to write lib.rs");
+    fs::write(
+        project.join("src/main.rs"),
+        "fn main() { println!(\"{}\", bot_core::ai::think()); }\n",
+    )
+    .expect("Failed to write main.rs");
+
+    let bundled_code = bundle(project).expect("Bundle should succeed");
+    assert!(bundled_code.contains("pub fn think()"));
+    assert!(!bundled_code.contains("bot_core"));
+
+    let bundled_code = Bundler::with_config(TransformConfig {
+        lib_module_name: Some("engine".to_string()),
+        ..TransformConfig::default()
+    })
+    .bundle(project)
+    .expect("Bundle should succeed");
+    assert!(bundled_code.contains("pub mod engine {"));
+    assert!(bundled_code.contains("::engine::ai::think()"));
+}
+
+/// `transform_source` applies the single-file transforms to a bundle, leaving its
+/// modules and library paths alone
+#[test]
+fn test_transform_source() {
+    let bundle = r#"
+/// Geometry helpers
+mod geo {
+    pub fn area(width: u32, height: u32) -> u32 {
+        width * height
+    }
+    #[cfg(test)]
+    mod tests {
+        #[test]
+        fn test_area() {
+            assert_eq!(super::area(2, 3), 6);
+        }
+    }
+}
+mod missing;
+fn main() {
+    println!("{}", geo::area(2, 3));
+}
+"#;
+    let transformed = Bundler::with_config(TransformConfig {
+        minify: true,
+        ..TransformConfig::default()
+    })
+    .transform_source(bundle)
+    .expect("Bundled source should transform");
+
+    assert!(!transformed.contains("Geometry helpers"));
+    assert!(!transformed.contains("test_area"));
+    assert!(transformed.contains("mod missing"));
+    assert!(!transformed.contains('\n'), "{transformed}");
+    syn::parse_file(&transformed).expect("Transformed code should parse");
+
+    assert!(Bundler::new().transform_source("fn main() {").is_err());
+}
+
+/// Raw strings with any number of hashes, char literals such as `'{'` and byte
+/// strings come out of aggressive minification unchanged, with comments kept or not
+#[test]
+fn test_aggressive_minify_keeps_literals() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src")).expect("Failed to create src");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"literals\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    let main = r####"// Prints "every" literal
+fn main() {
+    let raw = r#"say "hi" // not a comment /* nor this */ {"#;
+    let hashes = r###"ends with "## not yet"###;
+    let chars = ['{', '"', '\'', '}'];
+    /* block with "quote */
+    let bytes = (b"bytes \" // x", br#"raw "bytes" '"#, b'\'');
+    println!("{raw}|{hashes}|{chars:?}|{bytes:?}");
+}
+"####;
+    fs::write(project.join("src/main.rs"), main).expect("Failed to write main.rs");
+    let expected = r####"say "hi" // not a comment /* nor this */ {|ends with "## not yet|['{', '"', '\'', '}']|([98, 121, 116, 101, 115, 32, 34, 32, 47, 47, 32, 120], [114, 97, 119, 32, 34, 98, 121, 116, 101, 115, 34, 32, 39], 39)"####;
+
+    for keep_comments in [false, true] {
+        let bundled_code = Bundler::with_config(TransformConfig {
+            minify: true,
+            aggressive_minify: true,
+            keep_comments,
+            verify_minify: true,
+            ..TransformConfig::default()
+        })
+        .bundle(project)
+        .expect("Bundle should succeed");
+        // A kept line comment ends its own line
+        let lines = if keep_comments { 2 } else { 1 };
+        assert_eq!(bundled_code.lines().count(), lines, "{bundled_code}");
+        assert_eq!(bundled_code.contains("block with"), keep_comments);
+
+        let bundled_file = temp_dir.path().join("bundled.rs");
+        let binary = temp_dir.path().join("bundled");
+        fs::write(&bundled_file, &bundled_code).expect("Failed to write bundled code");
+        let output = std::process::Command::new("rustc")
+            .args(["--edition", "2021", "-o"])
+            .arg(&binary)
+            .arg(&bundled_file)
+            .output();
+        match output {
+            Ok(result) => {
+                assert!(
+                    result.status.success(),
+                    "Bundled code failed to compile:\n{}\n{bundled_code}",
+                    String::from_utf8_lossy(&result.stderr)
+                );
+                let run = std::process::Command::new(&binary)
+                    .output()
+                    .expect("Bundled binary should run");
+                assert_eq!(String::from_utf8_lossy(&run.stdout).trim_end(), expected);
+            }
+            Err(e) => eprintln!("Warning: rustc not available for compilation test: {}", e),
+        }
+    }
+}
+
+/// `TransformConfig::features` resolves `cfg(feature)` conditions against the
+/// package's `[features]`, defaults and implied features included
+#[test]
+fn test_bundle_with_features() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let project = temp_dir.path();
+    fs::create_dir_all(project.join("src")).expect("Failed to create src");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"features\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+         [features]\ndefault = [\"greedy\"]\ngreedy = []\nadvanced-ai = [\"search\"]\nsearch = []\ndebug-vis = []\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(
+        project.join("src/main.rs"),
+        r#"#[cfg(feature = "search")]
+mod search;
+#[cfg(feature = "debug-vis")]
+fn draw() {}
+fn main() {
+    #[cfg(feature = "advanced-ai")]
+    println!("{}", search::depth());
+    #[cfg(feature = "greedy")]
+    println!("greedy");
+}
+"#,
+    )
+    .expect("Failed to write main.rs");
+    fs::write(
+        project.join("src/search.rs"),
+        "pub fn depth() -> u32 { 3 }\n",
+    )
+    .expect("Failed to write search.rs");
+
+    let bundle = |features: &[&str], no_default_features| {
+        Bundler::with_config(TransformConfig {
+            features: Some(FeatureSelection {
+                features: features.iter().map(ToString::to_string).collect(),
+                no_default_features,
+                all_features: false,
+            }),
+            ..TransformConfig::default()
+        })
+        .bundle(project)
+    };
+
+    let bundled_code = bundle(&["advanced-ai"], true).expect("Bundle should succeed");
+    assert!(bundled_code.contains("pub fn depth() -> u32"));
+    assert!(bundled_code.contains("search::depth()"));
+    assert!(!bundled_code.contains("greedy"));
+    assert!(!bundled_code.contains("draw"));
+    assert!(!bundled_code.contains("feature"), "{bundled_code}");
+
+    let bundled_code = bundle(&[], false).expect("Bundle should succeed");
+    assert!(bundled_code.contains("println!(\"greedy\")"));
+    assert!(!bundled_code.contains("mod search"));
+
+    assert!(matches!(
+        bundle(&["fast"], false),
+        Err(BundlerError::Config { message, .. }) if message.contains("'fast'")
+    ));
+}
+
+/// `testing::Snapshots` writes a missing snapshot, matches it on the next run
+/// despite a new build time in the header, and fails with a diff once the code
+/// changes
+#[test]
+fn test_snapshots_of_fixture() {
+    use cg_bundler::template::{Template, DEFAULT_HEADER};
+    use cg_bundler::testing::{Normalizer, SnapshotStatus, Snapshots};
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let fixture = temp_dir.path().join("fixture");
+    fs::create_dir_all(fixture.join("src")).expect("Failed to create src");
+    fs::write(
+        fixture.join("Cargo.toml"),
+        "[package]\nname = \"fixture\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    fs::write(fixture.join("src/main.rs"), "fn main() {}\n").expect("Failed to write main.rs");
+
+    let snapshots = || {
+        let header = Template::parse(DEFAULT_HEADER).expect("Default header should parse");
+        Snapshots::new(temp_dir.path().join("snapshots"))
+            .with_bundler(Bundler::new().with_header(header, "default"))
+            .with_normalizer(Normalizer::new().strip_hashes().strip_timestamps())
+            .with_update(false)
+    };
+    assert_eq!(
+        snapshots().check_fixture("fixture", &fixture).unwrap(),
+        SnapshotStatus::Created
+    );
+    let snapshot = fs::read_to_string(snapshots().snapshot_path("fixture"))
+        .expect("Snapshot should be written");
+    assert!(snapshot.contains("Built [timestamp]"), "{snapshot}");
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    snapshots().assert_fixture("fixture", &fixture);
+
+    fs::write(
+        fixture.join("src/main.rs"),
+        "fn main() { println!(\"1\"); }\n",
+    )
+    .expect("Failed to write main.rs");
+    let error = snapshots()
+        .check_fixture("fixture", &fixture)
+        .expect_err("Changed code should not match the snapshot");
+    assert!(
+        matches!(error, BundlerError::SnapshotMismatch { ref diff, .. } if diff.contains("+fn main()")),
+        "{error}"
+    );
+}
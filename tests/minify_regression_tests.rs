@@ -0,0 +1,98 @@
+//! Regression suite for the token-based minifier: snippets from real bot code that
+//! mix generics, turbofish, shifts, ranges, closures and lifetimes, where a wrong
+//! space changes or breaks the program.
+
+use cg_bundler::check::check_bundle;
+use cg_bundler::Minifier;
+
+/// Tricky snippets, each a complete item
+const SNIPPETS: &[&str] = &[
+    // Nested generics closing with `>>` and `>>>`
+    "fn grid(w: usize, h: usize) -> Vec<Vec<i32>> { let g: Vec<Vec<i32>> = vec![vec![0; w]; h]; g }",
+    "type Callback = Option<Box<dyn Fn(i32) -> Option<Vec<i32>>>>;",
+    "fn first(v: &[Vec<Vec<u8>>]) -> Option<&Vec<Vec<u8>>> { v.first() }",
+    // Turbofish
+    "fn counts() -> std::collections::HashMap<u32, Vec<(i32, i32)>> { std::collections::HashMap::<u32, Vec<(i32, i32)>>::new() }",
+    "fn parse(line: &str) -> Vec<i64> { line.split_whitespace().map(|s| s.parse::<i64>().unwrap()).collect::<Vec<_>>() }",
+    "fn total(v: &[u64]) -> u64 { v.iter().copied().sum::<u64>() }",
+    // Shifts next to comparisons and qualified paths
+    "fn bits(x: u64, s: u32) -> u64 { let m = !0u64 >> 1; let mut y = (1u64 << 63) >> s; y >>= 1; y <<= 2; x & m | y }",
+    "fn below_max(a: u64) -> bool { a < <u64 as Default>::default() || a >= <u64>::MAX >> 1 }",
+    "fn ordered(a: i32, b: i32) -> bool { a < -b && a - -b > 0 && a > -1 }",
+    // Higher-ranked bounds and lifetimes
+    "fn apply<F>(f: F) -> usize where F: for<'a> Fn(&'a str) -> &'a str { f(\"x\").len() }",
+    "struct Iter<'a> { data: &'a [u8], pos: usize }",
+    "impl<'a> Iterator for Iter<'a> { type Item = &'a u8; fn next(&mut self) -> Option<&'a u8> { self.pos += 1; self.data.get(self.pos - 1) } }",
+    "fn search(g: &[Vec<u8>]) -> Option<(usize, usize)> { 'outer: for (y, row) in g.iter().enumerate() { for (x, &c) in row.iter().enumerate() { if c == b'#' { break 'outer; } if c == b'E' { return Some((x, y)); } } } None }",
+    // Ranges, including half-open range patterns
+    "fn bucket(d: u32) -> u8 { match d { 0..=9 => 0, 10.. => 1 } }",
+    "fn ranges(n: usize) -> (usize, f64) { let r = 0..=n; let f = 1. ..2.; (r.count(), f.start) }",
+    // References, closures and raw identifiers
+    "fn refs(a: &&i32, b: i32) -> bool { let c = **a & b; c != 0 && *&b > 0 || !(c == b) }",
+    "fn sort(v: &mut Vec<(i32, i32)>) { v.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0))); let _ = || -1; }",
+    "fn raw() -> usize { let r#type = 1usize; let s = r#\"a\"b\"#; let t = b'x'; let u = br\"bytes\"; r#type + s.len() + t as usize + u.len() }",
+    "fn pairs(t: ((i32, i32), i32)) -> i32 { t.0.1 + t.1 }",
+    // macro_rules with repetitions
+    "macro_rules! maxm { ($x:expr) => { $x }; ($x:expr, $($rest:expr),+) => { std::cmp::max($x, maxm!($($rest),+)) }; }",
+];
+
+fn program() -> String {
+    let mut code = String::from("#![allow(dead_code, unused)]\n");
+    for snippet in SNIPPETS {
+        code.push_str(snippet);
+        code.push('\n');
+    }
+    code.push_str("fn main() { println!(\"{}\", maxm!(1, 2, 3)); }\n");
+    code
+}
+
+#[test]
+fn test_snippets_round_trip_through_both_minifiers() {
+    for snippet in SNIPPETS {
+        let file = syn::parse_file(snippet).expect("snippet should parse");
+        for minifier in [Minifier::new(), Minifier::aggressive()] {
+            if let Err(e) = minifier.minify_file_verified(&file) {
+                panic!("{snippet}\n{}\n{e}", minifier.minify_file(&file));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_aggressive_output_keeps_required_spaces_only() {
+    let minify = |source: &str| {
+        let file = syn::parse_file(source).expect("snippet should parse");
+        Minifier::aggressive().minify_file(&file)
+    };
+
+    assert_eq!(
+        minify("type T = Option<Vec<Vec<i32>>>;"),
+        "type T=Option<Vec<Vec<i32>>>;"
+    );
+    assert_eq!(
+        minify("fn f() { let g: Vec<Vec<u8>> = Vec::<Vec<u8>>::new(); }"),
+        "fn f(){let g:Vec<Vec<u8>> =Vec::<Vec<u8>>::new();}"
+    );
+    assert_eq!(
+        minify("fn f(a: u64) -> bool { a < <u64>::MAX >> 1 }"),
+        "fn f(a:u64)->bool{a< <u64>::MAX>>1}"
+    );
+    assert_eq!(
+        minify("fn f(d: u32) -> u8 { match d { 10.. => 1, _ => 0 } }"),
+        "fn f(d:u32)->u8{match d{10.. =>1,_=>0}}"
+    );
+    assert_eq!(
+        minify("fn f<F>() where F: for<'a> Fn(&'a str) -> &'a str {}"),
+        "fn f<F>()where F:for<'a>Fn(&'a str)->&'a str{}"
+    );
+}
+
+#[test]
+fn test_minified_snippets_compile() {
+    let file = syn::parse_file(&program()).expect("program should parse");
+    for minifier in [Minifier::new(), Minifier::aggressive()] {
+        let minified = minifier.minify_file(&file);
+        let report = check_bundle(&minified, "2021").expect("rustc should run");
+        assert!(report.is_ok(), "{minified}\n{:#?}", report.diagnostics);
+    }
+}
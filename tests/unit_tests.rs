@@ -1,3 +1,6 @@
+// The tests of `TransformConfig` also cover its deprecated `remove_docs` field
+#![allow(deprecated)]
+
 use cg_bundler::error::BundlerError;
 use cg_bundler::file_manager::FileManager;
 use cg_bundler::transformer::{CodeTransformer, TransformConfig};
@@ -29,6 +32,7 @@ mod transform_config_tests {
             expand_modules: false,
             minify: true,
             aggressive_minify: true,
+            ..TransformConfig::default()
         };
 
         assert!(!config.remove_tests);
@@ -46,6 +50,7 @@ mod transform_config_tests {
             expand_modules: true,
             minify: false,
             aggressive_minify: true,
+            ..TransformConfig::default()
         };
 
         let config2 = config1.clone();
@@ -451,6 +456,7 @@ mod edge_case_tests {
             expand_modules: false,
             minify: false,
             aggressive_minify: false,
+            ..TransformConfig::default()
         };
 
         assert!(!config.remove_tests);
@@ -469,6 +475,7 @@ mod edge_case_tests {
             expand_modules: true,
             minify: true,
             aggressive_minify: true,
+            ..TransformConfig::default()
         };
 
         assert!(config.remove_tests);
@@ -607,6 +614,7 @@ mod transformer_internals_tests {
                 expand_modules: true,
                 minify: false,
                 aggressive_minify: false,
+                ..TransformConfig::default()
             },
             TransformConfig {
                 remove_tests: false,
@@ -614,6 +622,7 @@ mod transformer_internals_tests {
                 expand_modules: false,
                 minify: true,
                 aggressive_minify: true,
+                ..TransformConfig::default()
             },
         ];
 
@@ -645,6 +654,7 @@ mod transformer_internals_tests {
                 expand_modules: false,
                 minify: true,
                 aggressive_minify: false,
+                ..TransformConfig::default()
             },
         ];
 
@@ -1017,17 +1027,10 @@ mod filesystem_edge_cases {
             current_path = current_path.join(format!("level_{}", i));
             fs::create_dir_all(&current_path).expect("Failed to create deep directory");
 
-            // Create both mod.rs and a sibling module
+            // Each level is a mod.rs declaring the next one: a sibling
+            // level_N.rs as well would make the module ambiguous
             let mod_content = format!("pub mod level_{};", i + 1);
             fs::write(current_path.join("mod.rs"), &mod_content).expect("Failed to write mod.rs");
-
-            if i < depth - 1 {
-                let next_module = format!("level_{}.rs", i + 1);
-                let module_content =
-                    format!("// Level {} module\npub fn level_{}() {{}}", i + 1, i + 1);
-                fs::write(current_path.join(&next_module), &module_content)
-                    .expect("Failed to write module");
-            }
         }
 
         // Test finding modules at various depths